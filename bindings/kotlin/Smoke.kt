@@ -0,0 +1,26 @@
+// Run by .github/workflows/bindings.yml against the generated bindings: a
+// round trip through each format, and a wrong password.
+
+import io.github.pswitchy.fileencryptor.ChunkStream
+import io.github.pswitchy.fileencryptor.FileEncryptorException
+import io.github.pswitchy.fileencryptor.decrypt
+import io.github.pswitchy.fileencryptor.encrypt
+
+const val PASSWORD = "correct horse battery staple"
+
+fun main() {
+    val plaintext = "hello from Kotlin".toByteArray()
+    val file = encrypt(PASSWORD, plaintext)
+    check(decrypt(PASSWORD, file).contentEquals(plaintext))
+    try {
+        decrypt("wrong password", file)
+        error("a wrong password decrypted the file")
+    } catch (e: FileEncryptorException.WrongKey) {
+        println("refused: ${e.message}")
+    }
+
+    val chunked = ChunkStream.encrypting(PASSWORD).use { it.update(plaintext) + it.finish() }
+    val decrypted = ChunkStream.decrypting(PASSWORD).use { it.update(chunked) + it.finish() }
+    check(decrypted.contentEquals(plaintext))
+    println("ok")
+}
@@ -0,0 +1,23 @@
+// Run by .github/workflows/bindings.yml against the generated bindings: a
+// round trip through each format, and a wrong password.
+
+import Foundation
+
+let password = "correct horse battery staple"
+let plaintext = Data("hello from Swift".utf8)
+let file = try encrypt(password: password, plaintext: plaintext)
+let opened = try decrypt(password: password, file: file)
+precondition(opened == plaintext)
+do {
+    _ = try decrypt(password: "wrong password", file: file)
+    fatalError("a wrong password decrypted the file")
+} catch let FileEncryptorError.WrongKey(message) {
+    print("refused: \(message)")
+}
+
+let encrypting = ChunkStream.encrypting(password: password)
+let chunked = try encrypting.update(input: plaintext) + encrypting.finish()
+let decrypting = ChunkStream.decrypting(password: password)
+let decrypted = try decrypting.update(input: chunked) + decrypting.finish()
+precondition(decrypted == plaintext)
+print("ok")
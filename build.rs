@@ -0,0 +1,27 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC service is compiled with protox so building it doesn't need protoc.
+    // The client is only used by the service's tests.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/file_encryptor.proto");
+        let descriptors = protox::compile(["proto/file_encryptor.proto"], ["proto"])?;
+        tonic_build::configure()
+            .build_client(true)
+            .compile_fds(descriptors)?;
+    }
+    // The C header is written from the exported functions, so it can't fall
+    // behind them.
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR")?;
+        let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))?;
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .generate()?
+            .write_to_file(format!("{crate_dir}/include/file_encryptor.h"));
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+#ifndef FILE_ENCRYPTOR_H
+#define FILE_ENCRYPTOR_H
+
+/* Generated from src/ffi.rs by cbindgen (see build.rs); don't edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define FE_OK 0
+
+// Any failure without a code of its own.
+#define FE_ERROR -1
+
+// A null pointer, or a password that isn't UTF-8.
+#define FE_INVALID_ARGUMENT -2
+
+// The password isn't the file's.
+#define FE_WRONG_KEY -3
+
+// The file was damaged after it was encrypted.
+#define FE_CORRUPTED -4
+
+// An encryption or decryption in progress.
+typedef struct FeStream FeStream;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message of the last failure on this thread, or null if there was
+// none. It stays valid until the next call on the thread.
+const char *fe_last_error(void);
+
+// Encrypts `input_len` bytes at `input` with `password` into a new buffer,
+// stored with its length in `*output` and `*output_len`.
+//
+// # Safety
+//
+// `password` must be a NUL-terminated string, `input` must point to
+// `input_len` readable bytes (or be null if that is 0), and `output` and
+// `output_len` must be writable.
+int fe_encrypt_buffer(const char *password,
+                      const uint8_t *input,
+                      size_t input_len,
+                      uint8_t **output,
+                      size_t *output_len);
+
+// Decrypts the file of `input_len` bytes at `input`, in either format, with
+// `password` into a new buffer, stored with its length in `*output` and
+// `*output_len`.
+//
+// # Safety
+//
+// As for [`fe_encrypt_buffer`].
+int fe_decrypt_buffer(const char *password,
+                      const uint8_t *input,
+                      size_t input_len,
+                      uint8_t **output,
+                      size_t *output_len);
+
+// Frees a buffer returned by this library. Null is ignored.
+//
+// # Safety
+//
+// `buffer` and `len` must be a buffer and length this library returned,
+// not yet freed.
+void fe_buffer_free(uint8_t *buffer, size_t len);
+
+// Starts encrypting to the chunked format with `password`, returning the
+// context, or null on failure.
+//
+// # Safety
+//
+// `password` must be a NUL-terminated string.
+FeStream *fe_stream_encrypt_new(const char *password);
+
+// Starts decrypting a file of the chunked format with `password`, returning
+// the context, or null on failure. Single-message files go to
+// [`fe_decrypt_buffer`].
+//
+// # Safety
+//
+// `password` must be a NUL-terminated string.
+FeStream *fe_stream_decrypt_new(const char *password);
+
+// Pushes the next `input_len` bytes at `input` into `stream`, storing the
+// output that is ready in a new buffer in `*output` and `*output_len`. On
+// failure the context can only be freed.
+//
+// # Safety
+//
+// `stream` must be a live context, and the rest as for
+// [`fe_encrypt_buffer`].
+int fe_stream_update(FeStream *stream,
+                     const uint8_t *input,
+                     size_t input_len,
+                     uint8_t **output,
+                     size_t *output_len);
+
+// Ends the input of `stream`, storing the rest of the output in a new buffer
+// in `*output` and `*output_len`, and frees the context. Decryption fails
+// here if the file was cut short or doesn't match its checksums.
+//
+// # Safety
+//
+// `stream` must be a live context, which is gone afterwards whatever the
+// result, and `output` and `output_len` must be writable.
+int fe_stream_finish(FeStream *stream, uint8_t **output, size_t *output_len);
+
+// Abandons `stream`, discarding its output. Null is ignored.
+//
+// # Safety
+//
+// `stream` must be null or a live context, which is gone afterwards.
+void fe_stream_free(FeStream *stream);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FILE_ENCRYPTOR_H */
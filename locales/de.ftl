@@ -0,0 +1,762 @@
+# Deutsche Meldungen. Fehlende Einträge werden aus en.ftl übernommen.
+
+## Passwortabfrage
+
+password-prompt = Passwort:{" "}
+password-confirm-prompt = Passwort bestätigen:{" "}
+new-password-prompt = Neues Passwort:{" "}
+password-unavailable = Kein Passwort angegeben, und vom Terminal konnte keines gelesen werden
+password-empty = Das Passwort darf nicht leer sein
+password-mismatch = Die Passwörter stimmen nicht überein
+identity-passphrase-prompt = Passphrase für { $path }:{" "}
+identity-new-passphrase-prompt = Passphrase für den neuen geheimen Schlüssel:{" "}
+identity-unlock-failed = Die Schlüsseldatei { $path } konnte nicht entsperrt werden
+
+## Priorität
+
+background-failed = Warnung: Die Priorität konnte nicht gesenkt werden: { $error }
+
+## Passwortrichtlinie
+
+policy-too-short = das Passwort ist kürzer als die { $min } Zeichen, die die Passwortrichtlinie verlangt
+policy-too-simple = das Passwort hat geschätzt { $bits } Bit Entropie, die Passwortrichtlinie verlangt { $min }
+policy-denylisted = das Passwort steht auf der Liste verbotener Passwörter der Passwortrichtlinie
+policy-refused = Verschlüsselung abgelehnt: { $reason }. Wählen Sie ein anderes Passwort oder setzen Sie die Richtlinie mit --force-weak-password außer Kraft
+policy-forced = WARNUNG: Verschlüsselung mit einem Passwort, das gegen die Passwortrichtlinie verstößt ({ $reason }). Diese Ausnahme wurde protokolliert.
+strength-word = das Passwort besteht großteils aus einem verbreiteten Passwort oder Wort
+strength-sequence = das Passwort besteht großteils aus einer Folge wie abc oder 9876
+strength-repeat = das Passwort besteht großteils aus einer Wiederholung wie aaa oder abcabc
+strength-keyboard = das Passwort besteht großteils aus nebeneinanderliegenden Tasten wie qwertz
+strength-date = das Passwort besteht großteils aus einem Datum oder Jahr
+strength-short = das Passwort ist zu kurz
+strength-refused = Verschlüsselung abgelehnt: { $reason }, es wäre also mit nur etwa 10^{ $guesses } Versuchen zu erraten. Wählen Sie ein längeres, etwa aus einigen zusammenhanglosen Wörtern, oder verwenden Sie es mit --allow-weak-password trotzdem
+strength-allowed = WARNUNG: Verschlüsselung mit einem leicht zu erratenden Passwort ({ $reason }).
+policy-denylist-unreadable = Die Passwort-Sperrliste { $path } konnte nicht gelesen werden
+policy-unknown-setting = { $path }:{ $line }: unbekannte Einstellung "{ $key }"
+policy-unreadable = Die Passwortrichtlinie { $path } konnte nicht gelesen werden
+
+## Pfade
+
+output-exists = { $path } existiert bereits; mit --force wird sie überschrieben
+in-place-no-room = Nicht genug Platz, um { $path } an Ort und Stelle zu ersetzen: daneben werden etwa { $needed } gebraucht, frei sind { $free }
+output-underivable = Aus { $path } lässt sich kein Ausgabename ableiten; geben Sie OUTPUT oder -o an
+report-stdout-taken = --output-format gibt die Einträge auf der Standardausgabe aus, daher kann die Ausgabe nicht - sein
+in-place-not-file = --in-place ersetzt eine INPUT-Datei
+output-inside-input = Die Ausgabe { $output } liegt im Eingabeverzeichnis { $input }
+output-is-input = { $path } ist die Eingabedatei selbst; mit --in-place wird eine Datei ersetzt
+already-encrypted = { $path } ist bereits verschlüsselt; mit --allow-double-encrypt wird sie erneut verschlüsselt
+
+## Grenzen
+
+limit-file-size = { $path } ist { $size } groß und überschreitet die Grenze von { $limit } (--max-file-size)
+limit-total-bytes = Die Eingaben sind zusammen { $size } groß und überschreiten die Grenze von { $limit } (--max-total-bytes)
+limit-files = Der Lauf umfasst { $count } Dateien und überschreitet die Grenze von { $limit } (--max-files)
+
+## Ver- und Entschlüsselung
+
+encrypt-failed = Fehler beim Verschlüsseln
+decrypt-failed = Fehler beim Entschlüsseln
+decrypt-wrong-key = Falsches Passwort, falsche Schlüsseldatei oder Identität
+decrypt-corrupted = Die Datei ist beschädigt oder wurde manipuliert: sie lässt sich mit dem richtigen Schlüssel nicht authentifizieren
+decrypt-failed-headerless = Fehler beim Entschlüsseln: falsches Passwort oder keine file-encryptor-Datei (sie hat keinen file-encryptor-Dateikopf, stammt also von einer früheren Version oder ist etwas ganz anderes)
+decompress-failed = Die entschlüsselten Daten lassen sich nicht entpacken ({ $error }); die Datei wurde von einem fehlerhaften Programm geschrieben
+encrypt-done = Verschlüsselung abgeschlossen: { $path }
+sealed-box-done = Sealed Box geschrieben: { $path }
+openpgp-done = OpenPGP-Nachricht geschrieben: { $path }
+saltpack-done = Saltpack-Nachricht geschrieben: { $path }
+age-done = age-Datei geschrieben: { $path }
+decrypt-done = Entschlüsselung abgeschlossen, entschlüsselte Datei gespeichert unter: { $path }
+metadata-none = Für { $path } gibt es nichts wiederherzustellen: die Datei wurde ohne --keep-metadata verschlüsselt
+rekey-done = Passwort geändert: { $path }
+rekey-converted = Passwort geändert: { $path } (einmalig für Empfänger neu verschlüsselt, spätere Änderungen schreiben nur den Header neu)
+rekey-reencrypted = Passwort geändert, neu verschlüsselt: { $path }
+rotate-would-rewrap = Sein Passwort-Slot würde neu verpackt: { $path }
+rotate-would-reencrypt = Würde neu verschlüsselt: { $path }
+rotate-done = { $count ->
+    [one] 1 Datei
+   *[other] { $count } Dateien
+} auf das neue Passwort umgestellt
+rotate-checked = Probelauf: { $count ->
+    [one] 1 Datei öffnet
+   *[other] { $count } Dateien öffnen
+} sich mit dem alten Passwort; nichts wurde geändert
+rotate-wrong-password = Diese Dateien öffnen sich nicht mit dem alten Passwort:
+rotate-errors = Diese Dateien sind fehlgeschlagen:
+rotate-failed = { $failed } von { $count } Dateien konnten nicht geändert werden
+rotate-empty = Keine .enc-Dateien unter { $path }
+man-written = { $count ->
+    [one] 1 Handbuchseite
+   *[other] { $count } Handbuchseiten
+} nach { $path } geschrieben
+watch-started = { $input } wird überwacht; verschlüsselte Dateien kommen nach { $output }. Mit Strg-C beenden.
+watch-list-failed = { $path } konnte nicht gelesen werden, neuer Versuch: { $error }
+watch-failed = { $path } konnte nicht verschlüsselt werden: { $error }
+split-key-written = Schlüssel in { $count } Anteile geteilt, von denen je { $threshold } { $path } entschlüsseln. Geben Sie jeden an eine andere Person oder einen anderen Ort:
+recovery-key-created = Wiederherstellungsschlüssel für { $path }, mit dem `decrypt --recovery-key` die Datei entschlüsselt, falls das Passwort verloren geht. Schreiben Sie ihn auf und bewahren Sie ihn getrennt von der Datei auf; er wird nicht noch einmal angezeigt:
+walk-skipped-symlink = Die symbolische Verknüpfung { $path } wurde übersprungen; mit --symlinks follow wird ihr Ziel verwendet
+walk-skipped-special = { $path } wurde übersprungen, da es keine reguläre Datei, kein Verzeichnis und keine Verknüpfung ist
+walk-skipped-broken = Die defekte symbolische Verknüpfung { $path } wurde übersprungen
+walk-skipped-loop = { $path } wurde übersprungen, da es auf ein Verzeichnis verweist, in dem es liegt
+token-invalid = { $token } ist kein Hardware-Token: Geben Sie eine PKCS#11-URI an, die den Schlüssel über id oder object benennt, etwa pkcs11:token=NAME;id=%01, oder yubikey:SLOT, etwa yubikey:9d
+token-failed = Das Hardware-Token { $token } konnte nicht verwendet werden
+token-pin-prompt = PIN für { $token }:{" "}
+token-too-long = Die Kennung eines Hardware-Tokens darf höchstens { $max } Bytes lang sein
+token-not-p256 = Der Schlüssel auf dem Hardware-Token { $token } ist kein P-256-Schlüssel
+token-not-listed = Die Datei wurde nicht für ein Hardware-Token verschlüsselt
+shred-done = Überschrieben und gelöscht: { $path }
+keyring-failed = Der Schlüsselbund-Eintrag { $name } konnte nicht verwendet werden
+keyring-stored = Passwort im Schlüsselbund als { $name } gespeichert
+keyring-missing = Im Schlüsselbund ist kein Passwort als { $name } gespeichert; zuerst mit --use-keyring { $name } verschlüsseln
+extract-done = Entpacken abgeschlossen, { $count ->
+    [one] 1 Datei
+   *[other] { $count } Dateien
+} wiederhergestellt unter: { $path }
+manifest-done = Manifest abgeschlossen, { $count ->
+    [one] 1 Datei
+   *[other] { $count } Dateien
+} verarbeitet aus: { $path }
+resume-continuing = { $path } wird ab dem Sicherungspunkt fortgesetzt
+resume-stale = { $path } hat sich seit dem Sicherungspunkt geändert; es wird neu begonnen
+resume-unsupported = --resume braucht eine unkomprimierte Datei im --stream-Format
+resume-truncated = Die Datei ist kürzer, als ihr Sicherungspunkt angibt
+resume-failed = Fortsetzen ab { $path } nicht möglich; zum Neubeginn die Datei löschen
+split-done = Geschrieben als { $count ->
+    [one] 1 Teil
+   *[other] { $count } Teile
+}: { $first } bis { $last }
+split-first-part = { $path } ist Teil einer aufgeteilten Datei; ihren ersten Teil NAME.001 entschlüsseln
+split-missing = Teil { $path } der aufgeteilten Datei fehlt
+split-mismatch = { $path } ist nicht der nächste Teil dieser aufgeteilten Datei
+split-index-invalid = { $path } ist nicht der Index dieser aufgeteilten Datei
+split-index-forged = Der Index der aufgeteilten Datei wurde nicht mit dem Schlüssel dieser Datei geschrieben
+split-part-damaged = Teil { $path } der aufgeteilten Datei ist beschädigt: er passt nicht zum Index
+aad-required = Diese Datei ist an zugehörige Daten gebunden; mit demselben --aad entschlüsseln
+aad-unexpected = Diese Datei ist an keine zugehörigen Daten gebunden; ohne --aad entschlüsseln
+aad-mismatch = Das angegebene --aad ist nicht das, an das diese Datei gebunden ist
+timestamp-done = Zeitstempel vom { $time }: { $path }
+decrypt-waiting = Warte { $seconds } s nach { $count ->
+        [one] { $count } fehlgeschlagenen Versuch
+       *[other] { $count } fehlgeschlagenen Versuchen
+    } mit dieser Datei
+decrypt-state-unreadable = Warte { $seconds } s wie nach vielen fehlgeschlagenen Versuchen: Die Aufzeichnung fehlgeschlagener Entschlüsselungen, { $path }, konnte nicht gelesen werden. Der richtige Schlüssel setzt sie zurück.
+progress-left = noch { $time }
+encrypt-to-stdout-failed = Fehler beim Verschlüsseln von { $path } auf die Standardausgabe
+encrypt-to-failed = Fehler beim Verschlüsseln von { $path } nach { $output }
+saltpack-not-armored = { $path } ist keine Saltpack-Nachricht in ASCII-Armor
+identity-unreadable = Die Identitätsdatei { $path } konnte nicht gelesen werden
+identity-not-key-file = { $path } ist keine Schlüsseldatei
+key-not-encoded = { $kind ->
+    [secret] Der geheime Schlüssel
+   *[public] Der öffentliche Schlüssel
+} muss hex- oder base64-kodiert sein
+key-wrong-length = { $kind ->
+    [secret] Der geheime Schlüssel
+   *[public] Der öffentliche Schlüssel
+} muss { $expected } Bytes lang sein, nicht { $length }
+mime-not-message = { $path } ist keine MIME-Nachricht
+armor-not-message = { $path } ist keine Nachricht in ASCII-Armor
+best-effort-single-message = --best-effort stellt die Blöcke einer --stream-Datei wieder her; diese ist eine einzelne Nachricht
+extract-needs-output-dir = --extract braucht ein Ausgabeverzeichnis, nicht die Standardausgabe
+extract-not-archive = Diese Datei ist kein --archive; entschlüsseln Sie sie ohne --extract
+extract-not-dir-name = { $path } ist kein Verzeichnisname
+extract-replace-failed = { $path } konnte nicht ersetzt werden
+extract-move-failed = Der entpackte Baum konnte nicht nach { $path } verschoben werden
+chunk-length-invalid = Die Blocklänge muss zwischen 1 und { $max } Bytes liegen
+
+## Überprüfung
+
+verify-mismatch = { $path } wird anders zurückgelesen, als es geschrieben wurde; der Speicher ist möglicherweise defekt
+verify-failed = Der Geheimtext für { $path } lässt sich nicht authentifizieren; es wurde nichts geschrieben
+verify-ok = { $path } ist unversehrt und lässt sich mit dem angegebenen Schlüssel entschlüsseln
+verify-damaged = { $path } ist beschädigt: die Prüfsumme stimmt nicht, daher entschlüsselt sie kein Schlüssel
+verify-wrong-key = { $path } ist unversehrt, aber das angegebene Passwort, die Schlüsseldatei oder die Identität öffnet sie nicht
+verify-undecided = { $path } lässt sich nicht entschlüsseln: der angegebene Schlüssel ist falsch oder die Datei beschädigt (ohne Prüfsummen-Fußzeile lässt sich das nicht unterscheiden)
+verify-chunks-damaged = { $path } ist beschädigt: { $count } von { $total } Blöcken lassen sich nicht authentifizieren, die übrigen sind intakt
+verify-chunk-damaged = Block { $chunk } lässt sich nicht authentifizieren: Bytes { $start } bis { $end } der Datei, { $plaintext_start } bis { $plaintext_end } des Klartexts
+verify-chunk-damaged-compressed = Block { $chunk } lässt sich nicht authentifizieren: Bytes { $start } bis { $end } der Datei
+verify-chunk-missing = Block { $chunk } fehlt: die Datei endet bei Byte { $start }
+best-effort-recovered = { $path } wurde wiederhergestellt, ist aber unvollständig: { $count } von { $total } Blöcken waren beschädigt und sind darin Nullen, oder fehlen, wenn die Datei komprimiert war
+best-effort-footer = Alle Blöcke von { $path } ließen sich authentifizieren, aber die Prüfsumme der Datei stimmt nicht: der Schaden liegt außerhalb der Daten
+
+## Signierte Manifeste
+
+manifest-signed = Signiertes Manifest geschrieben: { $path }
+manifest-needs-output = --sign-manifest listet die Dateien einer Verzeichnis-EINGABE auf, die im eigenen Format in eine lokale AUSGABE verschlüsselt wird
+manifest-unlistable = { $path } kann nicht in ein Manifest: Der Name ist kein UTF-8 oder enthält einen Zeilenumbruch
+manifest-unreadable = { $path } konnte nicht gelesen werden
+manifest-invalid = { $path } ist kein signiertes Manifest oder ist beschädigt
+manifest-bad-signature = Die Signatur von { $path } passt nicht: Das Manifest wurde nach dem Signieren verändert
+manifest-wrong-signer = { $path } wurde mit { $signer } signiert, nicht mit dem unter --signer angegebenen Schlüssel
+manifest-unverified-signer = { $path } ist mit { $signer } signiert; prüfen Sie, ob das der Schlüssel des Absenders ist, oder geben Sie ihn mit --signer an
+manifest-missing = Fehlt: { $path }
+manifest-changed = Verändert: { $path }
+manifest-extra = Nicht aufgeführt: { $path }
+manifest-incomplete = Die Dateien passen nicht zu { $path }: { $count ->
+        [one] 1 fehlt, ist verändert oder nicht aufgeführt
+       *[other] { $count } fehlen, sind verändert oder nicht aufgeführt
+    }
+manifest-ok = { $count ->
+        [one] Die in { $path } aufgeführte Datei ist
+       *[other] Alle { $count } in { $path } aufgeführten Dateien sind
+    } vorhanden und unverändert, signiert mit { $signer }
+
+## Dateikopf
+
+header-invalid = Der Dateikopf ist beschädigt oder abgeschnitten
+header-unsupported-version = Die Datei verwendet Formatversion { $version }, die diese Version von file-encryptor nicht lesen kann; zum Entschlüsseln bitte aktualisieren
+header-unknown-cipher = Die Datei wurde mit einem dieser Version unbekannten Verfahren verschlüsselt (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-unknown-key-source = Die Schlüsselquelle der Datei ist dieser Version unbekannt (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-unknown-kdf = Die Schlüsselableitung der Datei ist dieser Version unbekannt (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-unknown-compression = Die Datei wurde mit einem dieser Version unbekannten Verfahren komprimiert (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-unknown-slot = Die Datei nennt einen Empfänger einer dieser Version unbekannten Art (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-unknown-token-mechanism = Der Schlüssel der Datei ist für ein Hardware-Token auf eine dieser Version unbekannte Art verpackt (ID { $id }); zum Entschlüsseln bitte aktualisieren
+header-kdf-limits = Die Datei verlangt Parameter für die Schlüsselableitung jenseits der sicheren Grenzen; der Schlüssel wird nicht abgeleitet
+header-metadata-too-long = Der Dateiname ist zu lang, um ihn im Dateikopf festzuhalten
+header-too-long = Die Empfänger passen nicht in den Dateikopf, der höchstens 65535 Bytes fasst; verwenden Sie weniger Empfänger oder kürzere Token-Namen
+kdf-invalid = Ungültige Parameter für die Schlüsselableitung: { $error }
+kdf-calibrated = Schlüsselableitung auf diesen Rechner abgestimmt: { $kdf }
+
+## Schlüsseldateien
+
+key-source-password = Die Datei wurde nur mit einem Passwort verschlüsselt, nicht mit einer Schlüsseldatei
+key-source-key-file = Die Datei wurde mit einer Schlüsseldatei verschlüsselt, nicht mit einem Passwort; zum Entschlüsseln --key-file angeben
+key-source-password-and-key-file = Die Datei wurde mit einem Passwort und einer Schlüsseldatei verschlüsselt; zum Entschlüsseln --key-file und das Passwort angeben
+key-file-invalid = Keine Schlüsseldatei: erwartet werden { $length } Bytes als Hex-Zeile
+key-file-exists = { $path } existiert bereits; ein neuer Schlüssel würde den Zugriff auf alles verlieren, was mit dem alten verschlüsselt wurde
+keygen-done = Schlüsseldatei gespeichert unter: { $path }. Bewahren Sie eine Kopie sicher auf: ohne sie lässt sich nichts entschlüsseln, was damit verschlüsselt wurde
+key-source-recipients = Die Datei wurde für Empfänger verschlüsselt; zum Entschlüsseln --identity mit einem passenden geheimen Schlüssel, --token oder eines ihrer Passwörter angeben
+recipient-not-listed = Die Datei wurde nicht für den öffentlichen Schlüssel dieser Identität verschlüsselt
+recipients-too-many = Eine Datei kann für höchstens { $max } Empfänger verschlüsselt werden
+keygen-identity-done = Geheimer Schlüssel gespeichert unter: { $path }. Halten Sie ihn geheim; Absender erhalten den öffentlichen Schlüssel darunter
+keygen-signing-done = Signaturschlüssel gespeichert unter: { $path }. Halten Sie ihn geheim; Empfänger erhalten den öffentlichen Schlüssel darunter
+share-invalid = Kein Schlüsselanteil, oder er wurde falsch abgetippt: erwartet wird eine feshare1-…-Zeile
+share-threshold = Ein Schlüssel muss in mindestens so viele Anteile geteilt werden, wie zum Entschlüsseln nötig sind, und es müssen mindestens 2 nötig sein
+share-too-few = Zum Entschlüsseln sind { $needed } Anteile nötig, angegeben wurden nur { $given }
+share-mismatch = Diese Anteile stammen aus verschiedenen Teilungen eines Schlüssels
+share-duplicate = Derselbe Anteil wurde mehrfach angegeben
+recovery-key-invalid = Kein Wiederherstellungsschlüssel, oder er wurde falsch eingegeben: erwartet werden acht Vierergruppen aus Buchstaben und Ziffern
+key-expired = Der Schlüssel in { $path } ist am { $date } abgelaufen. Er funktioniert weiterhin, sollte aber ersetzt werden: Erzeugen Sie mit keygen einen neuen und übertragen Sie die Dateien mit rekey darauf
+key-exported = Schlüssel exportiert nach: { $path }
+key-import-unreadable = Der Schlüssel unter { $path } konnte nicht gelesen werden
+key-import-invalid = { $path } ist weder ein geheimer PKCS#8-Schlüssel noch ein öffentlicher Schlüssel in PEM oder DER
+key-import-label = Ein PEM-Block „{ $label }“ ist kein importierbarer Schlüssel; erwartet wird PRIVATE KEY oder PUBLIC KEY
+key-import-not-x25519 = Der Schlüssel gehört zum Algorithmus { $oid }, nicht zu X25519 (1.3.101.110)
+key-import-length = Ein X25519-Schlüssel muss 32 Bytes lang sein
+key-import-needs-output = Ein geheimer Schlüssel wird in eine Identitätsdatei importiert: geben Sie OUTPUT an
+token-pin-incorrect = die PIN ist falsch
+token-pin-locked = die PIN ist gesperrt
+token-not-present = der Token ist nicht angeschlossen
+token-no-ecdh = der Schlüssel beherrscht kein ECDH
+token-call-failed = { $call } ist fehlgeschlagen: { $reason }
+token-module-unloadable = das PKCS#11-Modul { $path } konnte nicht geladen werden
+token-not-module = { $path } ist kein PKCS#11-Modul
+token-none-matches = kein angeschlossener Token passt dazu
+token-no-such-key = der Token enthält keinen solchen Schlüssel
+token-attribute-unavailable = das Attribut des Schlüssels ist nicht verfügbar
+token-secret-length = der Token hat ein Geheimnis der falschen Länge zurückgegeben
+token-unsupported = PKCS#11-Token werden auf dieser Plattform noch nicht unterstützt
+
+## Transportprüfsumme
+
+checksum-ok = Transportprüfsumme in Ordnung: { $path }
+checksum-missing = { $path } hat keine Transportprüfsumme (die Datei stammt von einer älteren Version)
+checksum-mismatch = Transportprüfsumme stimmt nicht: Die Datei wurde nach dem Verschlüsseln beschädigt
+content-mismatch = Der entschlüsselte Inhalt stimmt nicht mit der beim Verschlüsseln gespeicherten Prüfsumme überein
+input-changed = Die Eingabe hat sich während des Verschlüsselns geändert; verschlüsseln Sie sie erneut
+deterministic-recipients = Deterministische Verschlüsselung nimmt ein Passwort, eine Schlüsseldatei oder beides, keine öffentlichen Schlüssel oder mehrere Passwörter
+deterministic-unknown-content = Deterministische Verschlüsselung eines Datenstroms braucht seinen Inhalt vorab, den die Standardeingabe oder eine URL nicht liefern kann
+
+## Notizen und Tresor
+
+container-unlock-failed = { $path } konnte nicht entsperrt werden
+container-created = Container { $path } wurde angelegt
+container-rolled-back = { $path } ist auf Generation { $generation }, es wurde aber schon Generation { $seen } gesehen; die Datei wurde möglicherweise durch eine ältere Kopie ersetzt. Geben Sie --accept-rollback an, wenn diese Kopie absichtlich wiederhergestellt wurde
+container-rollback-accepted = Warnung: { $path } wird wie mit --accept-rollback angegeben auf Generation { $generation } geöffnet, älter als eine schon gesehene Kopie
+note-body-prompt = Geben Sie die Notiz ein und drücken Sie zum Speichern Strg-D (unter Windows Strg-Z):
+note-added = Notiz { $number } hinzugefügt: { $title }
+note-missing = Keine Notiz { $note }
+vault-value-prompt = Wert für { $key }:{" "}
+vault-value-missing = Kein Wert für { $key } angegeben
+text-prompt = Geben Sie den Text ein oder fügen Sie ihn ein, dann drücken Sie Strg-D (unter Windows Strg-Z und Eingabe):
+vault-stored = { $key } gespeichert
+vault-updated = { $key } aktualisiert
+vault-entry-missing = Kein Tresoreintrag { $key }
+vault-removed = { $key } entfernt
+vault-created = Tresor { $path } angelegt
+vault-exists = { $path } ist bereits ein Tresor
+vault-not-found = { $path } ist kein Tresor; legen Sie ihn mit vault init an
+vault-file-added = { $name } hinzugefügt ({ $size })
+vault-file-replaced = { $name } ersetzt ({ $size })
+container-no-data-dir = Das Datenverzeichnis des Benutzers ist unbekannt; geben Sie --container an
+container-invalid = { $path } ist kein gültiger Container
+
+## Zufallsquelle
+
+entropy-unavailable = der Zufallszahlengenerator des Betriebssystems ist ausgefallen: { $error }
+entropy-constant = der Zufallszahlengenerator des Betriebssystems lieferte immer wieder dasselbe Byte
+entropy-repeated = der Zufallszahlengenerator des Betriebssystems wiederholte seine vorige Ausgabe
+entropy-no-source = kein Zufallszahlengenerator: dieser Build hat keinen des Betriebssystems, und es wurde keiner eingerichtet
+
+## age-Dateien
+
+age-no-recipients = age braucht mindestens einen Empfänger oder ein Passwort
+age-not-age = Keine age-Datei
+age-not-for-identity = Die age-Datei ist nicht für diese Identität verschlüsselt
+age-not-password = Die age-Datei ist nicht mit einem Passwort verschlüsselt
+age-wrong-password = Falsches Passwort für die age-Datei
+age-header-malformed = Fehlerhafter age-Dateikopf
+age-header-tampered = Der age-Dateikopf wurde manipuliert
+age-x25519-malformed = Fehlerhafter age-X25519-Eintrag
+age-scrypt-malformed = Fehlerhafter age-scrypt-Eintrag
+age-scrypt-not-alone = Ein age-scrypt-Eintrag muss der einzige sein
+age-scrypt-work-factor = Der age-scrypt-Aufwandsfaktor { $log_n } liegt über der Grenze von { $limit }
+age-payload-truncated = Der Inhalt der age-Datei ist abgeschnitten
+age-payload-damaged = Der Inhalt der age-Datei ist beschädigt oder abgeschnitten
+age-recipient-length = Ein age-Empfänger enthält einen 32-Byte-Schlüssel
+age-recipient-invalid = { $text } ist kein gültiger age-Empfänger
+age-identity-length = Eine age-Identität enthält einen 32-Byte-Schlüssel
+age-identity-invalid = Die AGE-SECRET-KEY-1-Zeile ist keine gültige age-Identität
+
+## libsodium-Secretstream
+
+secretstream-key-file = --format secretstream ver- und entschlüsselt allein mit dem Rohschlüssel einer --key-file
+secretstream-one-file = --format secretstream verarbeitet eine Datei
+secretstream-truncated = Der Secretstream ist abgeschnitten: Er endet vor seiner letzten Nachricht
+secretstream-unopened = Der Secretstream lässt sich nicht öffnen: Der Schlüssel ist falsch, die Datei beschädigt, oder ihre Blöcke sind keine Zweierpotenz zwischen 256 Byte und 16 MiB lang
+secretstream-trailing = Der Secretstream enthält Daten nach seiner letzten Nachricht
+
+## Dienste
+
+service-name-invalid = Ein Dienstname darf nur Buchstaben, Ziffern, - und _ enthalten, { $name } enthält anderes
+service-password-argument = Die Befehlszeile eines Dienstes können andere Benutzer lesen: Verwenden Sie --password-file, --key-file oder --recipient statt --password
+service-needs-key = Für einen Dienst gibt niemand ein Passwort ein: Geben Sie --password-file, --key-file oder --recipient an
+service-unquotable = { $argument } kann nicht in der Befehlszeile eines Dienstes stehen
+service-no-directory = Das Verzeichnis, aus dem der Dienst installiert wird, wurde nicht gefunden
+service-no-program = Der Pfad dieses Programms für den Dienst wurde nicht gefunden
+service-exists = Ein Dienst namens { $name } ist bereits installiert ({ $path }); deinstallieren Sie ihn zuerst
+service-not-installed = Es ist kein Dienst namens { $name } installiert
+service-not-running = Der Dienst { $name } läuft nicht
+service-command-missing = { $command } konnte nicht ausgeführt werden
+service-command-failed = { $command } ist fehlgeschlagen ({ $status })
+service-installed = Der Dienst { $name } wurde installiert und gestartet: { $path }
+service-uninstalled = Der Dienst { $name } wurde beendet und entfernt
+service-unsupported = Auf dieser Plattform gibt es keine Dienstverwaltung, in die installiert werden kann
+
+## Zeitpläne
+
+schedule-needs-job-root = Die Konfigurationsdatei enthält Zeitpläne, die serve --job-root brauchen
+schedule-next = Zeitplan { $name } läuft als Nächstes um { $time }
+schedule-started = Zeitplan { $name } gestartet
+schedule-succeeded = Zeitplan { $name } beendet: { $count ->
+    [one] 1 Datei
+   *[other] { $count } Dateien
+} verarbeitet
+schedule-failed = Zeitplan { $name } ist fehlgeschlagen: { $error }
+schedule-notify-failed = Der on_failure-Befehl von Zeitplan { $name } ist fehlgeschlagen: { $error }
+
+## Dateien
+
+file-open-failed = Die Datei { $path } konnte nicht geöffnet werden
+file-read-failed = Fehler beim Lesen der Datei: { $path }
+file-write-failed = Fehler beim Schreiben der Datei: { $path }
+file-sync-failed = Fehler beim Schreiben der Datei auf den Datenträger: { $path }
+file-create-failed = Fehler beim Anlegen der Datei { $path }
+file-open-direct-failed = Die Datei { $path } konnte nicht für direkte E/A geöffnet werden
+file-create-direct-failed = Fehler beim Anlegen der Datei { $path } für direkte E/A
+dir-create-failed = Das Verzeichnis { $path } konnte nicht angelegt werden
+stdout-write-failed = Fehler beim Schreiben auf die Standardausgabe
+file-unreadable = { $path } konnte nicht gelesen werden
+dir-list-failed = Das Verzeichnis { $path } konnte nicht aufgelistet werden
+path-not-utf8 = { $path } ist kein gültiger UTF-8-Pfad
+file-failed = Fehler bei der Verarbeitung von { $path }
+batch-failed = { $failed } von { $total } Dateien sind fehlgeschlagen:
+batch-committed = Alle { $total } Ausgaben sind an ihrem Platz
+batch-rolled-back = Die Ausgaben aller { $total } Dateien wurden zurückgenommen, da nicht jede gelang
+staged-unmoved = { $path } konnte nicht an seinen Platz verschoben werden
+password-env-failed = Die Umgebungsvariable { $name } konnte nicht gelesen werden
+password-fd-failed = Der Dateideskriptor { $fd } konnte nicht gelesen werden
+password-file-failed = Die Passwortdatei { $path } konnte nicht gelesen werden
+key-file-unreadable = Die Schlüsseldatei { $path } konnte nicht gelesen werden
+key-file-unusable = Die Schlüsseldatei { $path } konnte nicht verwendet werden
+share-file-unreadable = Die Anteilsdatei { $path } konnte nicht gelesen werden
+share-file-unusable = Die Anteilsdatei { $path } konnte nicht verwendet werden
+file-reserve-failed = Für { $path } konnten keine { $size } Bytes reserviert werden
+symlink-unresolved = Der symbolische Link { $path } konnte nicht aufgelöst werden
+permissions-copy-failed = Die Berechtigungen von { $path } konnten nicht übernommen werden
+permissions-private-failed = { $path } konnte nicht auf seinen Eigentümer beschränkt werden
+dir-sync-failed = Fehler beim Schreiben des Verzeichnisses auf den Datenträger: { $path }
+file-unwritable = { $path } konnte nicht geschrieben werden
+rekey-key-file-only = { $path } ist nur mit einer Schlüsseldatei verschlüsselt, die kein Passwort zum Ändern hat
+rekey-not-file = rekey ändert eine Datei an Ort und Stelle; { $path } ist keine
+shred-not-file = shred überschreibt reguläre Dateien; { $path } ist keine
+verify-not-file = verify prüft eine verschlüsselte Datei; { $path } ist keine
+split-size-too-small = --split-size muss mehr als { $min } Bytes sein
+split-too-many-parts = --split-size ist für diese Ausgabe zu klein
+output-sync-failed = Fehler beim Schreiben der Ausgabe auf die Festplatte
+metadata-name-not-utf8 = { $path } hat keinen gültigen UTF-8-Dateinamen, der sich aufzeichnen ließe
+armor-not-text = Die Nachricht in ASCII-Armor ist kein Text
+file-delete-failed = { $path } konnte nicht gelöscht werden
+file-encrypt-failed = Fehler beim Verschlüsseln von { $path }
+file-decrypt-failed = Fehler beim Entschlüsseln von { $path }
+log-open-failed = Die Protokolldatei { $path } konnte nicht geöffnet werden
+checkpoint-save-failed = Der Prüfpunkt { $path } konnte nicht gespeichert werden
+checkpoint-delete-failed = Der Prüfpunkt { $path } konnte nicht gelöscht werden
+
+## Optionen
+
+dir-needs-output-dir = Ein Verzeichnis als INPUT braucht ein Ausgabeverzeichnis, nicht die Standardausgabe
+symlinks-preserve-needs-archive = --symlinks preserve bewahrt Links nur in einer --archive-Datei
+password-fd-unsupported = --password-fd braucht Unix; verwenden Sie --password-file oder --password-env
+split-key-invalid = "{ $value }" ist nicht THRESHOLD/COUNT, etwa 3/5
+direct-io-stdio = --direct-io braucht Dateien, nicht - für die Standardein- oder -ausgabe
+verify-after-stdout = --verify-after braucht eine Ausgabedatei zum Zurücklesen
+resume-stdio = --resume braucht Dateien, nicht - für die Standardein- oder -ausgabe
+direct-io-remote = --direct-io und --resume brauchen lokale Dateien, keine URLs
+verify-after-remote = --verify-after braucht eine lokale Ausgabedatei zum Zurücklesen
+argon2-needs-kdf = Die Optionen --argon2-* setzen --kdf argon2id voraus
+argon2-memory-too-large = --argon2-memory ist zu groß
+duration-invalid = "{ $value }" ist keine Dauer wie 500ms oder 2s
+expiry-invalid = "{ $value }" ist keine Dauer wie 90d, 12w oder 1y
+size-unit-unknown = unbekannte Einheit "{ $unit }"; verwenden Sie K, M, G oder T
+size-invalid = "{ $value }" ist keine Größe wie 4096, 500M oder 20G
+remove-original-stdio = --remove-original braucht INPUT- und OUTPUT-Dateien, nicht -
+armor-native-only = --armor verpackt das eigene Format
+line-length-needs-wrapping = --line-length setzt --format mime oder --armor voraus
+stream-native-only = --stream schreibt das eigene Format
+resume-needs-stream = --resume setzt eine --stream-Verschlüsselung einer Datei fort
+timestamp-needs-output = --timestamp-url speichert den Zeitstempel neben einer Ausgabedatei
+dir-needs-password = Ein Verzeichnis wird mit einem Passwort, --key-file oder --recipient verschlüsselt
+age-one-file = --format age verschlüsselt eine einzelne Datei
+dir-remote-needs-archive = Ein Verzeichnis geht nur als eine --archive-Datei an eine URL
+archive-needs-dir = --archive packt ein Verzeichnis als INPUT in das eigene Format
+split-size-one-file = --split-size teilt eine einzelne Ausgabedatei im eigenen Format
+compress-native-only = --compress gilt für das eigene Format
+aad-native-only = --aad gilt für das eigene Format
+deterministic-native-only = --deterministic gilt für das eigene Format
+recovery-key-native-only = --recovery-key gilt für das eigene Format
+keep-metadata-needs-file = --keep-metadata hält eine INPUT-Datei im eigenen Format fest
+encrypt-names-needs-dir = --encrypt-names benennt die Dateien eines Verzeichnisses als INPUT
+manifest-input-needs-password = Ein --manifest wird mit einem Passwort, --key-file oder --recipient verschlüsselt
+split-key-native-only = --split-key gilt für das eigene Format
+print-shares-stdout = --print-shares braucht die Standardausgabe für sich
+saltpack-needs-recipients = --format saltpack verschlüsselt an --recipient-Schlüssel
+age-needs-recipients = --format age verschlüsselt an --recipient-Schlüssel oder mit einem Passwort
+key-file-one-password = --key-file geht nur mit einem einzelnen Passwort
+mime-password-only = --format mime verpackt das passwortbasierte Format
+job-encrypt-options = cipher, compress und keep_metadata gelten nur beim Verschlüsseln
+extract-one-archive = --extract stellt eine einzelne --archive-Datei im eigenen Format wieder her
+best-effort-one-file = --best-effort stellt eine einzelne Datei im eigenen Format in eine Ausgabedatei wieder her
+manifest-input-native-only = Ein --manifest listet Dateien im eigenen Format
+age-decrypts-one-file = --format age entschlüsselt eine einzelne Datei
+restore-metadata-needs-file = --restore-metadata braucht eine Ausgabedatei, nicht -
+decrypt-remote-output = decrypt schreibt in eine lokale Datei oder nach -, nicht an eine URL
+saltpack-needs-identity = --format saltpack entschlüsselt mit --identity
+age-needs-identity = Eine age-Datei wird mit --identity oder einem Passwort entschlüsselt
+split-key-needs-output = --split-key schreibt Anteilsdateien neben eine einzelne lokale OUTPUT-Datei; verwenden Sie --print-shares
+filter-needs-dir = --include, --exclude und --symlinks gelten für ein Verzeichnis als INPUT
+batch-needs-files = --all-or-nothing und --keep-going gelten für ein Verzeichnis als INPUT oder ein --manifest
+all-or-nothing-remote = --all-or-nothing legt Ausgaben unter vorläufigen Namen an, was bei { $path } nicht geht
+rate-zero = die Rate muss größer als 0 sein
+direct-io-unsupported = direkte E/A wird auf dieser Plattform nicht unterstützt
+generate-man-subcommand = --generate-man kann nicht mit einem Unterbefehl verwendet werden
+subcommand-required = ein Unterbefehl ist erforderlich
+
+## Überwachung
+
+rules-unreadable = Die Regeldatei { $path } konnte nicht gelesen werden
+rules-invalid = { $path } Zeile { $line }: erwartet wird `include PATTERN` oder `exclude PATTERN`
+watch-not-dir = { $path } ist kein Verzeichnis, das überwacht werden kann
+watch-same-dir = --output-dir muss ein anderes Verzeichnis als --input-dir sein
+
+## Zeitstempel
+
+timestamp-request-failed = Die Zeitstempelanfrage an { $url } ist fehlgeschlagen
+timestamp-response-unreadable = Fehler beim Lesen der Zeitstempelantwort
+timestamp-response-unusable = Der Zeitstempeldienst hat eine unbrauchbare Antwort geliefert
+timestamp-rejected = Anfrage mit PKIStatus { $status } abgelehnt
+timestamp-no-tstinfo = der Zeitstempel enthält kein TSTInfo
+timestamp-wrong-digest = der Zeitstempel gilt für einen anderen Hashwert als den angefragten
+timestamp-wrong-nonce = die Nonce des Zeitstempels passt nicht zur Anfrage
+timestamp-no-nonce = dem Zeitstempel fehlt die Nonce der Anfrage
+der-truncated = abgeschnittenes DER-Element
+der-unexpected-tag = unerwartetes DER-Tag { $tag }, erwartet { $expected }
+
+## Entfernter Speicher
+
+s3-url-invalid = { $url } ist keine URL der Form s3://BUCKET/KEY
+s3-no-credentials = s3://-URLs brauchen AWS_ACCESS_KEY_ID und AWS_SECRET_ACCESS_KEY
+s3-endpoint-invalid = AWS_ENDPOINT_URL { $endpoint } ist keine URL
+remote-open-failed = { $url } konnte nicht geöffnet werden
+s3-error-status = S3 antwortete mit { $status }: { $error }
+s3-no-details = keine Fehlerangaben
+s3-no-upload-id = S3 hat einen Upload ohne UploadId begonnen
+s3-no-etag = S3 hat Teil { $part } ohne ETag angenommen
+s3-error = S3 antwortete: { $error }
+remote-write-failed-because = { $url } konnte nicht geschrieben werden: { $error }
+remote-write-failed = { $url } konnte nicht geschrieben werden
+url-not-utf8 = { $path } ist keine gültige UTF-8-URL
+
+## Archive
+
+archive-file-shrank = { $path } ist beim Archivieren kürzer geworden
+source-date-epoch-invalid = SOURCE_DATE_EPOCH muss eine ganze Zahl von Sekunden sein
+symlink-unreadable = Der symbolische Link { $path } konnte nicht gelesen werden
+archive-damaged = Das Archiv ist beschädigt
+archive-truncated = Das Archiv endet mitten in { $path }
+archive-bad-copy = { $path } kopiert { $of }, das keine Datei davor ist
+archive-inside-symlink = { $path } liegt im Archiv innerhalb eines symbolischen Links
+symlink-create-failed = Der symbolische Link { $path } konnte nicht angelegt werden
+archive-unsafe-path = Der unsichere Pfad { $path }, der aus dem Ziel herausführen würde, wird abgelehnt
+permissions-restore-failed = Die Berechtigungen von { $path } konnten nicht wiederhergestellt werden
+time-restore-failed = Die Zeit von { $path } konnte nicht wiederhergestellt werden
+
+## Selbsttest
+
+self-test-failed = Einige Selbsttests sind fehlgeschlagen; verwenden Sie diesen Build nicht
+self-test-sealed = die Verschlüsselung ergab { $actual }
+self-test-unopened = der Geheimtext des Testvektors ließ sich nicht öffnen
+self-test-wrong-opening = das Öffnen ergab den falschen Klartext
+self-test-tag-accepted = ein geändertes Tag wurde angenommen
+self-test-wrong-plaintext = die Entschlüsselung ergab den falschen Klartext
+self-test-file-accepted = eine Datei mit einem geänderten Byte wurde entschlüsselt
+self-test-stream-accepted = ein Stream mit einem geänderten Byte wurde entschlüsselt
+
+## Saltpack
+
+saltpack-no-recipients = saltpack braucht mindestens einen Empfänger
+saltpack-header-not-binary = das saltpack-Kopfpaket ist keine Bytefolge
+saltpack-not-message = keine saltpack-Nachricht
+saltpack-version = nicht unterstützte saltpack-Version (nur Version 2 wird unterstützt)
+saltpack-not-encryption = die saltpack-Nachricht ist nicht im Verschlüsselungsmodus
+saltpack-ephemeral-length = der kurzlebige saltpack-Schlüssel hat die falsche Länge
+saltpack-not-for-identity = diese saltpack-Nachricht ist nicht an die angegebene Identität gerichtet
+saltpack-sender-corrupt = die saltpack-Absender-Secretbox ist beschädigt
+saltpack-truncated = die saltpack-Nachricht ist abgeschnitten (kein letztes Paket)
+saltpack-packet-malformed = fehlerhaftes saltpack-Nutzdatenpaket
+saltpack-no-authenticator = dem saltpack-Nutzdatenpaket fehlt unser Authentifikator
+saltpack-packet-unauthenticated = das saltpack-Nutzdatenpaket { $packet } ließ sich nicht authentifizieren
+saltpack-packet-corrupt = das saltpack-Nutzdatenpaket { $packet } ist beschädigt
+saltpack-trailing = unerwartete Daten nach dem letzten saltpack-Paket
+saltpack-malformed = fehlerhafte saltpack-Nachricht
+saltpack-not-list = { $what ->
+    [header] der saltpack-Kopf
+    [version] die saltpack-Version
+    [recipients] die saltpack-Empfängerliste
+   *[packet] das saltpack-Nutzdatenpaket
+} ist keine Liste
+saltpack-not-binary = { $what ->
+    [ephemeral-key] der kurzlebige saltpack-Schlüssel
+   *[sender-secretbox] die saltpack-Absender-Secretbox
+} ist keine Bytefolge
+saltpack-not-armored-text = keine saltpack-Nachricht in ASCII-Armor
+saltpack-not-armored-encrypted = keine verschlüsselte saltpack-Nachricht in ASCII-Armor
+saltpack-armor-block-length = die saltpack-Armor hat eine ungültige Blocklänge
+saltpack-armor-character = ungültiges Zeichen { $character } in der saltpack-Armor
+saltpack-armor-overflow = ein saltpack-Armor-Block läuft über
+
+## Server
+
+server-needs-certificate = ein Client-Zertifikat ist erforderlich
+server-no-common-name = das Client-Zertifikat hat keinen Common Name im Subject
+server-jobs-disabled = Aufträge sind abgeschaltet; starten Sie den Server mit --job-root
+server-uploads-disabled = Uploads sind abgeschaltet; starten Sie den Server mit --job-root
+server-wrong-password = falsches Passwort
+server-corrupted = beschädigter Geheimtext
+server-wrong-password-or-corrupted = falsches Passwort oder beschädigter Geheimtext
+server-locked-out = zu viele fehlgeschlagene Entschlüsselungen; versuchen Sie es in { $seconds } s erneut
+server-rate-limited = Grenze von { $limit } Anfragen pro Minute überschritten
+server-job-needs-password = ein Auftrag braucht ein Passwort
+server-empty-stream = leerer Anfragestrom
+server-first-needs-password = die erste Anfragenachricht muss das Passwort enthalten
+server-input-too-large = die Eingabe überschreitet { $limit } Bytes
+server-tls-invalid = Ungültige TLS-Konfiguration
+server-needs-tls = serve braucht --tls-cert, --tls-key und --client-ca (oder --allow-unauthenticated)
+server-needs-client-ca = serve braucht --client-ca, um Aufrufer zu authentifizieren (oder --allow-unauthenticated)
+server-runtime-failed = Die asynchrone Laufzeitumgebung konnte nicht gestartet werden
+server-metrics-listen-failed = Auf { $address } konnte nicht für Metriken gelauscht werden
+server-serving = gRPC wird auf { $address } bereitgestellt
+server-failed = Der gRPC-Server auf { $address } ist fehlgeschlagen
+server-job-root-uncreated = Die Auftragswurzel konnte nicht angelegt werden: { $error }
+server-job-root-unresolved = Die Auftragswurzel konnte nicht aufgelöst werden: { $error }
+server-upload-outside-root = Upload-Pfade müssen innerhalb der Auftragswurzel bleiben
+server-output-exists = { $name } existiert bereits
+server-output-dir-uncreated = das Verzeichnis der Ausgabe konnte nicht angelegt werden
+server-too-many-uploads = zu viele laufende Uploads
+server-no-upload = kein solcher Upload
+server-upload-finished = der Upload ist abgeschlossen
+server-upload-busy = ein anderer Aufruf hängt gerade an den Upload an
+server-upload-offset = der Upload steht bei Offset { $offset }, nicht { $given }
+server-output-unmoved = die Ausgabe konnte nicht an ihren Platz verschoben werden
+server-segment-cut = das Segment endete vor dem Upload
+server-job-operation-unknown = unbekannte Auftragsart
+server-job-modes-conflict = all_or_nothing und keep_going lassen sich nicht kombinieren
+server-too-many-jobs = zu viele laufende Aufträge
+server-job-files-failed = { $failed } von { $total } Dateien sind fehlgeschlagen
+server-no-job = kein solcher Auftrag
+server-path-not-relative = { $path } muss ein relativer Pfad ohne '..' sein
+server-input-missing = { $path } existiert nicht
+server-job-outside-root = Auftragspfade müssen innerhalb der Auftragswurzel bleiben
+server-input-not-file-or-dir = { $path } ist weder eine Datei noch ein Verzeichnis
+server-input-unlisted = { $path } konnte nicht aufgelistet werden: { $error }
+server-file-unread = die Datei konnte nicht gelesen werden
+server-file-dir-uncreated = ihr Verzeichnis konnte nicht angelegt werden
+server-output-unwritten = die Ausgabe konnte nicht geschrieben werden
+server-metrics-serving = /metrics und /healthz werden auf { $address } bereitgestellt
+
+## ASCII-Armor und MIME
+
+armor-no-begin = Keine Zeile { $line } gefunden
+armor-no-end = Der Nachricht in ASCII-Armor fehlt die Zeile { $line }; sie wurde womöglich abgeschnitten
+armor-not-base64 = Die Nachricht in ASCII-Armor ist kein gültiges Base64: { $error }
+mime-no-part = Kein Teil vom Typ { $content_type } gefunden
+mime-not-base64 = Der Teil vom Typ { $content_type } ist kein gültiges Base64: { $error }
+
+## OpenPGP
+
+openpgp-key-invalid = Der öffentliche OpenPGP-Schlüssel konnte nicht gelesen werden
+openpgp-key-bindings = Der öffentliche OpenPGP-Schlüssel hat ungültige Bindungssignaturen
+openpgp-key-cannot-encrypt = Der öffentliche OpenPGP-Schlüssel hat keinen Schlüssel, der verschlüsseln kann
+openpgp-wrap-failed = Fehler beim Verpacken des Sitzungsschlüssels für den OpenPGP-Empfänger
+openpgp-encrypt-failed = Fehler bei der OpenPGP-Verschlüsselung
+
+## Konfigurationsdateien
+
+manifest-unknown-table = [[file]] erwartet, nicht "{ $table }"
+config-expected-key-value = `Schlüssel = Wert` erwartet
+manifest-key-outside-table = { $key } außerhalb einer [[file]]-Tabelle
+config-not-bool = "{ $value }" ist weder true noch false
+config-unknown-key = unbekannter Schlüssel "{ $key }"
+manifest-empty = Das Manifest { $path } enthält kein [[file]]
+manifest-entry-no-input = ein [[file]] braucht eine Eingabe (input)
+manifest-entry-stdio = Manifest-Einträge sind Dateien, nicht -
+manifest-output-twice = { $path } ist die Ausgabe zweier Einträge
+manifest-input-not-file = { $path } im Manifest ist keine Datei
+config-unknown-schedule-key = unbekannter Schlüssel für einen Zeitplan
+config-unknown-table = die einzigen Tabellen sind [schedule.NAME]
+config-schedule-twice = der Zeitplan { $name } ist doppelt definiert
+config-kdf-time-and-iterations = { $path }: kdf_time und argon2_iterations können nicht beide gesetzt sein
+config-unterminated-string = nicht abgeschlossene Zeichenkette
+config-escapes = Escape-Sequenzen werden nicht unterstützt; verwenden Sie einen 'literal string'
+config-trailing = unerwartetes "{ $rest }" nach dem Wert
+config-not-number = "{ $value }" ist keine gültige Zahl
+cron-not-five-fields = "{ $expression }" besteht nicht aus fünf Feldern: Minute, Stunde, Tag, Monat und Wochentag
+cron-never = "{ $expression }" trifft auf keinen Tag zu
+cron-invalid-field = "{ $field }" ist kein cron-Feld mit Werten von { $min } bis { $max }
+config-unreadable = Die Konfigurationsdatei { $path } konnte nicht gelesen werden
+manifest-file-unreadable = Das Manifest { $path } konnte nicht gelesen werden
+
+## Untersuchen
+
+inspect-foreign-format = Dies ist eine Saltpack- oder OpenPGP-Nachricht in ASCII-Armor; inspect liest das eigene Format
+inspect-too-short = Die Datei ist zu kurz für eine verschlüsselte Datei
+inspect-intact = Die Datei ist unversehrt. Lässt sie sich nicht entschlüsseln, ist das Passwort oder die Schlüsseldatei falsch.
+inspect-damaged = Die Datei wurde nach dem Verschlüsseln beschädigt; kein Passwort wird sie entschlüsseln.
+inspect-wrapping = Verpackung
+inspect-format = Format
+inspect-created = Erstellt
+inspect-expires = Läuft ab
+inspect-layout = Aufbau
+inspect-cipher = Chiffre
+inspect-compression = Kompression
+inspect-key-source = Schlüsselquelle
+inspect-kdf = KDF
+inspect-salt = Salt
+inspect-nonce = Nonce
+inspect-nonce-prefix = Nonce-Präfix
+inspect-key-check = Prüfwert
+inspect-aad = AAD
+inspect-metadata = Metadaten
+inspect-content-sha256 = Inhalt SHA-256
+inspect-header = Header
+inspect-compressed = Komprimiert
+inspect-plaintext = Klartext
+inspect-checksum = Prüfsumme
+inspect-mime = MIME-Anhang (--format mime)
+inspect-age-identity = age-Identität
+inspect-raw-key-file = Schlüsseldatei, 32 Rohbytes
+inspect-key-file = Schlüsseldatei oder X25519-Identität
+inspect-not-recorded = nicht aufgezeichnet
+inspect-never = nie
+inspect-expired = { $date } (abgelaufen)
+inspect-expires-in = { $date } (in { $days } Tagen)
+inspect-version = Version { $version }
+inspect-legacy-chunked = in Blöcken, aus der Zeit vor dem versionierten Header
+inspect-legacy = kein Header: aus der Zeit vor dem versionierten Header, oder gar nicht von diesem Werkzeug
+inspect-single-message = eine einzige Nachricht
+inspect-chunked = in Blöcken von { $size }
+inspect-none = keine
+inspect-public-key = öffentlicher Schlüssel
+inspect-password-slot = Passwort, { $kdf }
+inspect-token-slot = Hardware-Token { $token }, ECDH P-256
+inspect-recipient = Empfänger { $number }
+inspect-none-legacy = keine (aus der Zeit vor dem Header)
+inspect-aad-bound = an zugehörige Daten gebunden; das Entschlüsseln braucht dasselbe --aad
+inspect-checksum-hidden = aufgezeichnet (verschlüsselt; --with-password zeigt sie an)
+inspect-bytes = { $length } Bytes
+inspect-compressed-length = { $size } ({ $length } Bytes) vor dem Dekomprimieren, aus der Länge der Datei
+inspect-plaintext-length = { $size } ({ $length } Bytes), aus der Länge der Datei
+inspect-plaintext-unknown = unbekannt: die Datei ist zu kurz für ihren Aufbau
+inspect-mismatch = ABWEICHUNG
+inspect-checksum-none = keine (aus der Zeit vor dem Prüfsummen-Footer, daher sieht eine Beschädigung wie ein falsches Passwort aus)
+inspect-source-password = Passwort
+inspect-source-key-file = Schlüsseldatei
+inspect-source-password-and-key-file = Passwort und Schlüsseldatei
+inspect-source-recipients = Empfänger (öffentliche Schlüssel und Passwörter)
+inspect-pbkdf2 = PBKDF2-HMAC-SHA256, { $iterations } Iterationen
+inspect-argon2id = Argon2id, { $memory } Speicher, { $iterations } Iterationen, Parallelität { $parallelism }
+inspect-metadata-recorded = Name, Berechtigungen und Änderungszeit (verschlüsselt, { $length } Bytes)
+
+## Diagnose
+
+doctor-data-dir = Datenverzeichnis
+doctor-state-dir = Zustandsverzeichnis
+doctor-fix = Abhilfe: { $fix }
+doctor-failed = Einige Prüfungen sind fehlgeschlagen
+doctor-entropy = Zufallsquelle
+doctor-entropy-ok = der Zufallszahlengenerator des Betriebssystems ist verfügbar
+doctor-entropy-broken-fix = verschlüsseln Sie auf diesem Rechner nicht, bis sein Zufallszahlengenerator repariert ist
+doctor-entropy-missing-fix = prüfen Sie, ob /dev/urandom (oder getrandom) verfügbar ist, etwa in Containern
+doctor-temp-dir = Temporäres Verzeichnis
+doctor-dir-unwritable = { $path } ist nicht beschreibbar: { $error }
+doctor-temp-dir-fix = setzen Sie TMPDIR (unter Windows TEMP) auf ein beschreibbares Verzeichnis
+doctor-dir-low-space = { $path } ist beschreibbar, aber nur { $free } frei
+doctor-low-space-fix = schaffen Sie Platz oder richten Sie TMPDIR auf ein größeres Dateisystem
+doctor-dir-space = { $path } ist beschreibbar, { $free } frei
+doctor-dir-writable = { $path } ist beschreibbar
+doctor-dir-unknown = konnte nicht bestimmt werden
+doctor-dir-unknown-fix = setzen Sie HOME (oder XDG_DATA_HOME / XDG_STATE_HOME), oder geben Sie note und vault --container mit
+doctor-permissions-fix = korrigieren Sie die Berechtigungen von { $path }
+doctor-terminal-ok = interaktiv; Passwortabfragen funktionieren
+doctor-terminal = Terminal
+doctor-terminal-missing = kein steuerndes Terminal, daher schlagen Passwortabfragen fehl
+doctor-terminal-fix = geben Sie -p an oder führen Sie den Befehl in einem interaktiven Terminal aus
+doctor-terminal-redirected = Passwortabfragen nutzen das Terminal (die Standardeingabe ist umgeleitet)
+doctor-locale-windows = unter Windows nicht zutreffend
+doctor-locale = Gebietsschema
+doctor-locale-not-utf8 = { $locale } ist nicht UTF-8
+doctor-locale-fix = setzen Sie LANG=C.UTF-8 (oder das UTF-8-Gebietsschema Ihrer Sprache), damit Passwörter mit Nicht-ASCII-Zeichen so eingegeben werden wie anderswo
+algorithms-for-encryption = Verschlüsselung
+algorithms-for-kdf = Schlüsselableitung
+algorithms-for-checksum = Transportprüfsumme
+algorithms-hardware = Hardware ({ $features })
+algorithms-software-missing = Software (kein { $features })
+algorithms-software-unbuilt = Software (ohne --cfg { $cfg } gebaut)
+algorithms-software-sha2 = Software (dieser Build nutzt die SHA2-Befehle der CPU nicht)
+algorithms-software = Software
+algorithms-algorithm = Algorithmus
+algorithms-used-for = Verwendet für
+algorithms-backend = Backend
+algorithms-default-cipher = Standard-Chiffre: { $cipher }
+bench-size-zero = --size muss mehr als 0 Bytes sein
+bench-chunk-size-range = --chunk-size muss zwischen 1 Byte und { $max } liegen
+bench-debug-build = Dies ist ein Debug-Build; bauen Sie mit --release, um aussagekräftige Werte zu erhalten.
+bench-kdf = Schlüsselableitung, je Schlüssel
+bench-cipher = Chiffre, { $size } in Stücken von { $chunk }
+bench-stream = Verschlüsseln mit --stream, { $cipher }, { $size }
+bench-picked = {" "}(gewählt)
+
+## C-Bibliothek
+
+ffi-stream-finished = der Datenstrom ist bereits beendet
+ffi-stream-panicked = der Thread des Datenstroms ist abgestürzt
+ffi-panicked = die Bibliothek ist abgestürzt
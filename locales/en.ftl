@@ -0,0 +1,763 @@
+# Messages shown to the user. Keep the ids in sync with the other catalogs;
+# a message missing there falls back to the English text here.
+
+## Password prompts
+
+password-prompt = Password:{" "}
+password-confirm-prompt = Confirm password:{" "}
+new-password-prompt = New password:{" "}
+password-unavailable = No password given, and none could be read from the terminal
+password-empty = The password must not be empty
+password-mismatch = Passwords do not match
+identity-passphrase-prompt = Passphrase for { $path }:{" "}
+identity-new-passphrase-prompt = Passphrase for the new secret key:{" "}
+identity-unlock-failed = Could not unlock the identity file { $path }
+
+## Priority
+
+background-failed = Warning: could not lower the priority: { $error }
+
+## Password policy
+
+policy-too-short = the password is shorter than the { $min } characters the password policy requires
+policy-too-simple = the password has an estimated { $bits } bits of entropy, and the password policy requires { $min }
+policy-denylisted = the password is on the password policy's list of forbidden passwords
+policy-refused = Refusing to encrypt: { $reason }. Choose another password, or pass --force-weak-password to override the policy
+policy-forced = WARNING: encrypting with a password that breaks the password policy ({ $reason }). This override has been logged.
+strength-word = the password is mostly a common password or word
+strength-sequence = the password is mostly a run such as abc or 9876
+strength-repeat = the password is mostly a repeat such as aaa or abcabc
+strength-keyboard = the password is mostly a row of adjacent keys such as qwerty
+strength-date = the password is mostly a date or year
+strength-short = the password is too short
+strength-refused = Refusing to encrypt: { $reason }, so it would take only about 10^{ $guesses } guesses. Choose a longer one, such as a few unrelated words, or pass --allow-weak-password to use it anyway
+strength-allowed = WARNING: encrypting with a password that is easy to guess ({ $reason }).
+policy-denylist-unreadable = Could not read the password denylist { $path }
+policy-unknown-setting = { $path }:{ $line }: unknown setting "{ $key }"
+policy-unreadable = Could not read the password policy { $path }
+
+## Paths
+
+output-exists = { $path } already exists; pass --force to overwrite it
+in-place-no-room = Not enough space to replace { $path } in place: it needs about { $needed } free beside it, and { $free } is free
+output-underivable = Cannot derive an output name from { $path }; give OUTPUT or -o
+report-stdout-taken = --output-format prints the records to standard output, so the output can't be -
+in-place-not-file = --in-place replaces an INPUT file
+output-inside-input = The output { $output } is inside the input directory { $input }
+output-is-input = { $path } is the input file itself; use --in-place to replace a file
+already-encrypted = { $path } is already encrypted; pass --allow-double-encrypt to encrypt it again
+
+## Limits
+
+limit-file-size = { $path } is { $size }, over the limit of { $limit } (--max-file-size)
+limit-total-bytes = The inputs add up to { $size }, over the limit of { $limit } (--max-total-bytes)
+limit-files = The run covers { $count } files, over the limit of { $limit } (--max-files)
+
+## Encryption and decryption
+
+encrypt-failed = Error during encryption
+decrypt-failed = Error during decryption
+decrypt-wrong-key = Incorrect password, key file or identity
+decrypt-corrupted = The file is damaged or was tampered with: it doesn't authenticate under the right key
+decrypt-failed-headerless = Error during decryption: wrong password, or not a file-encryptor file (it has no file-encryptor header, so it is either from an earlier version or something else entirely)
+decompress-failed = The decrypted data doesn't decompress ({ $error }); the file was written by a faulty program
+encrypt-done = Encryption complete: { $path }
+sealed-box-done = Sealed box written: { $path }
+openpgp-done = OpenPGP message written: { $path }
+saltpack-done = Saltpack message written: { $path }
+age-done = age file written: { $path }
+decrypt-done = Decryption complete, decrypted file saved at: { $path }
+metadata-none = Nothing to restore for { $path }: the file was encrypted without --keep-metadata
+rekey-done = Password changed: { $path }
+rekey-converted = Password changed: { $path } (re-encrypted once for recipients, so later changes only rewrite its header)
+rekey-reencrypted = Password changed, re-encrypted: { $path }
+rotate-would-rewrap = Would rewrap its password slot: { $path }
+rotate-would-reencrypt = Would re-encrypt: { $path }
+rotate-done = { $count ->
+    [one] 1 file changed
+   *[other] { $count } files changed
+} to the new password
+rotate-checked = Dry run: { $count ->
+    [one] 1 file opens
+   *[other] { $count } files open
+} with the old password; nothing was changed
+rotate-wrong-password = These files don't open with the old password:
+rotate-errors = These files failed:
+rotate-failed = { $failed } of { $count } files could not be changed
+rotate-empty = No .enc files under { $path }
+man-written = { $count ->
+    [one] 1 manual page
+   *[other] { $count } manual pages
+} written to { $path }
+watch-started = Watching { $input }; encrypted files go to { $output }. Press Ctrl-C to stop.
+watch-list-failed = Could not list { $path }, trying again: { $error }
+watch-failed = Could not encrypt { $path }: { $error }
+split-key-written = Key split into { $count } shares, any { $threshold } of which decrypt { $path }. Give each to a different person or place:
+recovery-key-created = Recovery key for { $path }, which decrypts it with `decrypt --recovery-key` if the password is lost. Write it down and keep it apart from the file; it is not shown again:
+walk-skipped-symlink = Skipped the symbolic link { $path }; --symlinks follow takes what it points to
+walk-skipped-special = Skipped { $path }, which is not a regular file, directory or link
+walk-skipped-broken = Skipped the broken symbolic link { $path }
+walk-skipped-loop = Skipped { $path }, a link back to a directory it is in
+token-invalid = { $token } is not a hardware token: give a PKCS#11 URI naming the key by id or object, such as pkcs11:token=NAME;id=%01, or yubikey:SLOT, such as yubikey:9d
+token-failed = Could not use the hardware token { $token }
+token-pin-prompt = PIN for { $token }:{" "}
+token-too-long = A hardware token identifier can be at most { $max } bytes long
+token-not-p256 = The key on the hardware token { $token } is not a P-256 key
+token-not-listed = The file was not encrypted to a hardware token
+shred-done = Overwritten and deleted: { $path }
+keyring-failed = Could not use the keychain entry { $name }
+keyring-stored = Password saved to the keychain as { $name }
+keyring-missing = No password is saved in the keychain as { $name }; encrypt with --use-keyring { $name } first
+extract-done = Extraction complete, { $count ->
+    [one] 1 file
+   *[other] { $count } files
+} restored to: { $path }
+manifest-done = Manifest complete, { $count ->
+    [one] 1 file
+   *[other] { $count } files
+} processed from: { $path }
+resume-continuing = Resuming { $path } from its checkpoint
+resume-stale = { $path } has changed since its checkpoint; starting over
+resume-unsupported = --resume needs an uncompressed file in the --stream format
+resume-truncated = The file is shorter than its checkpoint says
+resume-failed = Could not resume from { $path }; delete it to start over
+split-done = Written as { $count ->
+    [one] 1 part
+   *[other] { $count } parts
+}: { $first } to { $last }
+split-first-part = { $path } is part of a split file; decrypt its first part, NAME.001
+split-missing = Part { $path } of the split file is missing
+split-mismatch = { $path } is not the next part of this split file
+split-index-invalid = { $path } is not the index of this split file
+split-index-forged = The index of the split file was not written with this file's key
+split-part-damaged = Part { $path } of the split file is damaged: it doesn't match the index
+aad-required = This file is bound to associated data; decrypt it with the same --aad
+aad-unexpected = This file is not bound to associated data; decrypt it without --aad
+aad-mismatch = The --aad given is not the one this file is bound to
+timestamp-done = Timestamped at { $time }: { $path }
+decrypt-waiting = Waiting { $seconds }s after { $count ->
+        [one] { $count } failed attempt
+       *[other] { $count } failed attempts
+    } on this file
+decrypt-state-unreadable = Waiting { $seconds }s, as after many failed attempts: the record of failed decryptions, { $path }, can't be read. The right key resets it.
+progress-left = { $time } left
+encrypt-to-stdout-failed = Error encrypting { $path } to standard output
+encrypt-to-failed = Error encrypting { $path } to { $output }
+saltpack-not-armored = { $path } is not an armored saltpack message
+identity-unreadable = Could not read identity file { $path }
+identity-not-key-file = { $path } is not a key file
+key-not-encoded = { $kind ->
+    [secret] Secret key
+   *[public] Public key
+} must be hex or base64 encoded
+key-wrong-length = { $kind ->
+    [secret] Secret key
+   *[public] Public key
+} must be { $expected } bytes, got { $length }
+mime-not-message = { $path } is not a MIME message
+armor-not-message = { $path } is not an armored message
+best-effort-single-message = --best-effort recovers the chunks of a --stream file; this one is a single message
+extract-needs-output-dir = --extract needs an output directory, not standard output
+extract-not-archive = This file is not an --archive; decrypt it without --extract
+extract-not-dir-name = { $path } does not name a directory
+extract-replace-failed = Could not replace { $path }
+extract-move-failed = Could not move the extracted tree to { $path }
+chunk-length-invalid = The chunk length must be from 1 to { $max } bytes
+
+## Verification
+
+verify-mismatch = { $path } reads back differently from what was written; the storage may be faulty
+verify-failed = The ciphertext for { $path } does not authenticate; nothing was written
+verify-ok = { $path } is intact, and it decrypts with the key given
+verify-damaged = { $path } is damaged: its checksum doesn't match, so no key will decrypt it
+verify-wrong-key = { $path } is intact, but the password, key file or identity given doesn't open it
+verify-undecided = { $path } doesn't decrypt: the key given is wrong, or the file is damaged (it has no checksum footer to tell which)
+verify-chunks-damaged = { $path } is damaged: { $count } of { $total } chunks fail to authenticate, and the others are intact
+verify-chunk-damaged = Chunk { $chunk } fails to authenticate: bytes { $start } to { $end } of the file, { $plaintext_start } to { $plaintext_end } of the plaintext
+verify-chunk-damaged-compressed = Chunk { $chunk } fails to authenticate: bytes { $start } to { $end } of the file
+verify-chunk-missing = Chunk { $chunk } is missing: the file ends at byte { $start }
+best-effort-recovered = { $path } was recovered but is incomplete: { $count } of { $total } chunks were damaged and are zeros in it, or left out if the file was compressed
+best-effort-footer = Every chunk of { $path } authenticated, but the file's checksum doesn't match: the damage is outside the data
+
+## Signed manifests
+
+manifest-signed = Signed manifest written: { $path }
+manifest-needs-output = --sign-manifest lists the files of a directory INPUT encrypted in the native format to a local OUTPUT
+manifest-unlistable = { $path } can't go in a manifest: its name isn't UTF-8 or has a line break
+manifest-unreadable = Could not read { $path }
+manifest-invalid = { $path } is not a signed manifest, or it is damaged
+manifest-bad-signature = The signature on { $path } doesn't match it: the manifest was changed after it was signed
+manifest-wrong-signer = { $path } was signed by { $signer }, not by the key given with --signer
+manifest-unverified-signer = { $path } is signed by { $signer }; check that this is the sender's key, or give it with --signer
+manifest-missing = Missing: { $path }
+manifest-changed = Changed: { $path }
+manifest-extra = Not listed: { $path }
+manifest-incomplete = The files don't match { $path }: { $count ->
+        [one] 1 is
+       *[other] { $count } are
+    } missing, changed or not listed
+manifest-ok = { $count ->
+        [one] The file listed in { $path } is
+       *[other] All { $count } files listed in { $path } are
+    } present and unchanged, signed by { $signer }
+
+## File header
+
+header-invalid = The file header is damaged or truncated
+header-unsupported-version = The file uses format version { $version }, which this version of file-encryptor can't read; upgrade to decrypt it
+header-unknown-cipher = The file was encrypted with a cipher this version doesn't know (id { $id }); upgrade to decrypt it
+header-unknown-key-source = The file's key source is unknown to this version (id { $id }); upgrade to decrypt it
+header-unknown-kdf = The file's key derivation function is unknown to this version (id { $id }); upgrade to decrypt it
+header-unknown-compression = The file was compressed with a method this version doesn't know (id { $id }); upgrade to decrypt it
+header-unknown-slot = The file lists a recipient of a kind this version doesn't know (id { $id }); upgrade to decrypt it
+header-unknown-token-mechanism = The file's key is wrapped for a hardware token in a way this version doesn't know (id { $id }); upgrade to decrypt it
+header-kdf-limits = The file asks for key derivation parameters beyond the safe limits; refusing to derive the key
+header-metadata-too-long = The file name is too long to record in the header
+header-too-long = The recipients don't fit in the file header, which holds at most 65535 bytes; use fewer recipients or shorter token names
+kdf-invalid = Invalid key derivation parameters: { $error }
+kdf-calibrated = Key derivation tuned for this machine: { $kdf }
+
+## Key files
+
+key-source-password = The file was encrypted with a password only, not a key file
+key-source-key-file = The file was encrypted with a key file, not a password; decrypt it with --key-file
+key-source-password-and-key-file = The file was encrypted with a password and a key file; decrypt it with --key-file and the password
+key-file-invalid = Not a key file: expected { $length } bytes as a line of hex
+key-file-exists = { $path } already exists; a new key would lose access to everything encrypted with the old one
+keygen-done = Key file saved at: { $path }. Keep a copy somewhere safe: nothing it encrypts can be decrypted without it
+key-source-recipients = The file was encrypted for recipients; decrypt it with --identity and a matching secret key, with --token, or with one of its passwords
+recipient-not-listed = The file was not encrypted to this identity's public key
+recipients-too-many = A file can be encrypted to at most { $max } recipients
+keygen-identity-done = Secret key saved at: { $path }. Keep it private, and give senders the public key below
+keygen-signing-done = Signing key saved at: { $path }. Keep it private, and give recipients the public key below
+share-invalid = Not a key share, or it was mistyped: expected a feshare1-… line
+share-threshold = A key must be split into at least as many shares as it takes to decrypt, and it must take at least 2
+share-too-few = { $needed } shares are needed to decrypt, but only { $given } were given
+share-mismatch = These shares come from different splits of a key
+share-duplicate = The same share was given more than once
+recovery-key-invalid = Not a recovery key, or it was mistyped: expected eight groups of four letters and digits
+key-expired = The key in { $path } expired on { $date }. It still works, but it is due to be replaced: generate a new one with keygen and move files to it with rekey
+key-exported = Key exported to: { $path }
+key-import-unreadable = Could not read the key at { $path }
+key-import-invalid = { $path } is not a PKCS#8 secret key or a public key in PEM or DER
+key-import-label = A "{ $label }" PEM block is not a key this can import; expected PRIVATE KEY or PUBLIC KEY
+key-import-not-x25519 = The key is for the algorithm { $oid }, not X25519 (1.3.101.110)
+key-import-length = An X25519 key must be 32 bytes
+key-import-needs-output = A secret key is imported into an identity file: give it an OUTPUT
+token-pin-incorrect = the PIN is incorrect
+token-pin-locked = the PIN is locked
+token-not-present = the token is not present
+token-no-ecdh = the key does not do ECDH
+token-call-failed = { $call } failed: { $reason }
+token-module-unloadable = could not load the PKCS#11 module { $path }
+token-not-module = { $path } is not a PKCS#11 module
+token-none-matches = no token present matches it
+token-no-such-key = the token holds no such key
+token-attribute-unavailable = the key's attribute is unavailable
+token-secret-length = the token returned a secret of the wrong length
+token-unsupported = PKCS#11 tokens are not supported on this platform yet
+
+## Transport checksum
+
+checksum-ok = Transport checksum OK: { $path }
+checksum-missing = { $path } has no transport checksum (it was written by an older version)
+checksum-mismatch = Transport checksum mismatch: the file was damaged after it was encrypted
+content-mismatch = The decrypted content does not match the checksum recorded when it was encrypted
+input-changed = The input changed while it was being encrypted; encrypt it again
+deterministic-recipients = Deterministic encryption takes one password, a key file or both, not public keys or several passwords
+deterministic-unknown-content = Deterministic encryption of a stream needs its content up front, which standard input or a URL can't give
+
+## Notes and vault
+
+container-unlock-failed = Could not unlock { $path }
+container-created = Created the container { $path }
+container-rolled-back = { $path } is at generation { $generation }, but generation { $seen } was seen before, so it may have been rolled back to an older copy; pass --accept-rollback if that copy was restored on purpose
+container-rollback-accepted = Warning: opening { $path } at generation { $generation }, older than a copy seen before, as --accept-rollback says
+note-body-prompt = Enter the note, then press Ctrl-D (Ctrl-Z on Windows) to save:
+note-added = Added note { $number }: { $title }
+note-missing = No note { $note }
+vault-value-prompt = Value for { $key }:{" "}
+vault-value-missing = No value given for { $key }
+text-prompt = Type or paste the text, then press Ctrl-D (Ctrl-Z and Enter on Windows):
+vault-stored = Stored { $key }
+vault-updated = Updated { $key }
+vault-entry-missing = No vault entry { $key }
+vault-removed = Removed { $key }
+vault-created = Created vault { $path }
+vault-exists = { $path } is already a vault
+vault-not-found = { $path } is not a vault; create it with vault init
+vault-file-added = Added { $name } ({ $size })
+vault-file-replaced = Replaced { $name } ({ $size })
+container-no-data-dir = Could not determine the user data directory; pass --container
+container-invalid = { $path } is not a valid container
+
+## Entropy source
+
+entropy-unavailable = the OS random number generator failed: { $error }
+entropy-constant = the OS random number generator returned the same byte over and over
+entropy-repeated = the OS random number generator repeated its previous output
+entropy-no-source = no random number generator: this build has no OS generator and none was installed
+
+## age files
+
+age-no-recipients = age needs at least one recipient or a password
+age-not-age = Not an age file
+age-not-for-identity = The age file is not encrypted to this identity
+age-not-password = The age file is not encrypted with a password
+age-wrong-password = Wrong password for the age file
+age-header-malformed = Malformed age header
+age-header-tampered = The age header has been tampered with
+age-x25519-malformed = Malformed age X25519 stanza
+age-scrypt-malformed = Malformed age scrypt stanza
+age-scrypt-not-alone = An age scrypt stanza must be the only one
+age-scrypt-work-factor = The age scrypt work factor { $log_n } is above the limit of { $limit }
+age-payload-truncated = The age payload is truncated
+age-payload-damaged = The age payload is damaged or truncated
+age-recipient-length = An age recipient holds a 32-byte key
+age-recipient-invalid = { $text } is not a valid age recipient
+age-identity-length = An age identity holds a 32-byte key
+age-identity-invalid = The AGE-SECRET-KEY-1 line is not a valid age identity
+
+## libsodium secretstream
+
+secretstream-key-file = --format secretstream encrypts and decrypts with the raw key of a --key-file alone
+secretstream-one-file = --format secretstream handles one file
+secretstream-truncated = The secretstream is cut short: it ends before its final message
+secretstream-unopened = The secretstream doesn't open: the key is wrong, the file is damaged, or its chunks aren't a power of two from 256 bytes to 16 MiB long
+secretstream-trailing = The secretstream has data after its final message
+
+## Services
+
+service-name-invalid = A service name may only hold letters, digits, - and _, which { $name } does not
+service-password-argument = A service's command line can be read by other users: use --password-file, --key-file or --recipient rather than --password
+service-needs-key = A service has nobody to type a password: give --password-file, --key-file or --recipient
+service-unquotable = { $argument } can't go on a service's command line
+service-no-directory = Could not find the directory to install the service from
+service-no-program = Could not find this program's path for the service to run
+service-exists = A service named { $name } is installed already ({ $path }); uninstall it first
+service-not-installed = No service named { $name } is installed
+service-not-running = The service { $name } is not running
+service-command-missing = Could not run { $command }
+service-command-failed = { $command } failed ({ $status })
+service-installed = Installed and started the service { $name }: { $path }
+service-uninstalled = Stopped and removed the service { $name }
+service-unsupported = There is no service manager to install into on this platform
+
+## Schedules
+
+schedule-needs-job-root = The config file has schedules, which need serve --job-root
+schedule-next = Schedule { $name } runs next at { $time }
+schedule-started = Schedule { $name } started
+schedule-succeeded = Schedule { $name } finished: { $count ->
+    [one] 1 file
+   *[other] { $count } files
+} done
+schedule-failed = Schedule { $name } failed: { $error }
+schedule-notify-failed = The on_failure command of schedule { $name } failed: { $error }
+
+## Files
+
+file-open-failed = Could not open file { $path }
+file-read-failed = Error reading file at path: { $path }
+file-write-failed = Error writing to file at path: { $path }
+file-sync-failed = Error flushing file to disk: { $path }
+file-create-failed = Error creating file { $path }
+file-open-direct-failed = Could not open file { $path } for direct I/O
+file-create-direct-failed = Error creating file { $path } for direct I/O
+dir-create-failed = Could not create directory { $path }
+stdout-write-failed = Error writing to standard output
+file-unreadable = Could not read { $path }
+dir-list-failed = Could not list directory { $path }
+path-not-utf8 = { $path } is not a valid UTF-8 path
+file-failed = Error processing { $path }
+batch-failed = { $failed } of { $total } files failed:
+batch-committed = All { $total } outputs are in place
+batch-rolled-back = Rolled back the outputs of all { $total } files, as not every one succeeded
+staged-unmoved = Could not move { $path } into place
+password-env-failed = Could not read the environment variable { $name }
+password-fd-failed = Could not read file descriptor { $fd }
+password-file-failed = Could not read password file { $path }
+key-file-unreadable = Could not read key file { $path }
+key-file-unusable = Could not use key file { $path }
+share-file-unreadable = Could not read share file { $path }
+share-file-unusable = Could not use share file { $path }
+file-reserve-failed = Could not reserve { $size } bytes for { $path }
+symlink-unresolved = Could not resolve the symlink { $path }
+permissions-copy-failed = Could not copy the permissions of { $path }
+permissions-private-failed = Could not make { $path } private to its owner
+dir-sync-failed = Error flushing directory to disk: { $path }
+file-unwritable = Could not write { $path }
+rekey-key-file-only = { $path } is encrypted with a key file alone, which has no password to change
+rekey-not-file = rekey changes a file in place; { $path } is not one
+shred-not-file = shred overwrites regular files; { $path } is not one
+verify-not-file = verify checks one encrypted file; { $path } is not one
+split-size-too-small = --split-size must be more than { $min } bytes
+split-too-many-parts = --split-size is too small for this output
+output-sync-failed = Error flushing the output to disk
+metadata-name-not-utf8 = { $path } has no valid UTF-8 file name to record
+armor-not-text = The armored message is not text
+file-delete-failed = Could not delete { $path }
+file-encrypt-failed = Error encrypting { $path }
+file-decrypt-failed = Error decrypting { $path }
+log-open-failed = Could not open log file { $path }
+checkpoint-save-failed = Could not save the checkpoint { $path }
+checkpoint-delete-failed = Could not delete the checkpoint { $path }
+
+## Options
+
+dir-needs-output-dir = A directory INPUT needs an output directory, not standard output
+symlinks-preserve-needs-archive = --symlinks preserve keeps links in one --archive file
+password-fd-unsupported = --password-fd needs Unix; use --password-file or --password-env
+split-key-invalid = "{ $value }" is not THRESHOLD/COUNT, such as 3/5
+direct-io-stdio = --direct-io needs files, not - for standard input or output
+verify-after-stdout = --verify-after needs an output file to read back
+resume-stdio = --resume needs files, not - for standard input or output
+direct-io-remote = --direct-io and --resume need local files, not URLs
+verify-after-remote = --verify-after needs a local output file to read back
+argon2-needs-kdf = --argon2-* options require --kdf argon2id
+argon2-memory-too-large = --argon2-memory is too large
+duration-invalid = "{ $value }" is not a duration such as 500ms or 2s
+expiry-invalid = "{ $value }" is not a duration such as 90d, 12w or 1y
+size-unit-unknown = unknown unit "{ $unit }"; use K, M, G or T
+size-invalid = "{ $value }" is not a size such as 4096, 500M or 20G
+remove-original-stdio = --remove-original needs INPUT and OUTPUT files, not -
+armor-native-only = --armor wraps the native format
+line-length-needs-wrapping = --line-length requires --format mime or --armor
+stream-native-only = --stream writes the native format
+resume-needs-stream = --resume picks up a --stream encryption of a file
+timestamp-needs-output = --timestamp-url saves the token next to an output file
+dir-needs-password = A directory is encrypted with a password, --key-file or --recipient
+age-one-file = --format age encrypts one file
+dir-remote-needs-archive = A directory goes to a URL as one --archive file
+archive-needs-dir = --archive packs a directory INPUT in the native format
+split-size-one-file = --split-size splits one output file in the native format
+compress-native-only = --compress applies to the native format
+aad-native-only = --aad applies to the native format
+deterministic-native-only = --deterministic applies to the native format
+recovery-key-native-only = --recovery-key applies to the native format
+keep-metadata-needs-file = --keep-metadata records an INPUT file in the native format
+encrypt-names-needs-dir = --encrypt-names names the files of a directory INPUT
+manifest-input-needs-password = A --manifest is encrypted with a password, --key-file or --recipient
+split-key-native-only = --split-key applies to the native format
+print-shares-stdout = --print-shares needs standard output to itself
+saltpack-needs-recipients = --format saltpack encrypts to --recipient keys
+age-needs-recipients = --format age encrypts to --recipient keys or with one password
+key-file-one-password = --key-file goes with a single password
+mime-password-only = --format mime wraps the password-based format
+job-encrypt-options = cipher, compress and keep_metadata apply to encrypting
+extract-one-archive = --extract restores one --archive file in the native format
+best-effort-one-file = --best-effort recovers one file in the native format to an output file
+manifest-input-native-only = A --manifest lists files in the native format
+age-decrypts-one-file = --format age decrypts one file
+restore-metadata-needs-file = --restore-metadata needs an output file, not -
+decrypt-remote-output = decrypt writes to a local file or -, not a URL
+saltpack-needs-identity = --format saltpack decrypts with --identity
+age-needs-identity = An age file decrypts with --identity or a password
+split-key-needs-output = --split-key writes share files next to one local OUTPUT; use --print-shares
+filter-needs-dir = --include, --exclude and --symlinks apply to a directory INPUT
+batch-needs-files = --all-or-nothing and --keep-going apply to a directory INPUT or a --manifest
+all-or-nothing-remote = --all-or-nothing stages outputs under temporary names, which { $path } can't be
+rate-zero = the rate must be more than 0
+direct-io-unsupported = direct I/O is not supported on this platform
+generate-man-subcommand = --generate-man can't be used with a subcommand
+subcommand-required = a subcommand is required
+
+## Watching
+
+rules-unreadable = Could not read rules file { $path }
+rules-invalid = { $path } line { $line }: expected `include PATTERN` or `exclude PATTERN`
+watch-not-dir = { $path } is not a directory to watch
+watch-same-dir = --output-dir must be another directory than --input-dir
+
+## Timestamps
+
+timestamp-request-failed = Timestamp request to { $url } failed
+timestamp-response-unreadable = Error reading timestamp response
+timestamp-response-unusable = Timestamp authority returned an unusable response
+timestamp-rejected = request rejected with PKIStatus { $status }
+timestamp-no-tstinfo = token does not contain TSTInfo
+timestamp-wrong-digest = token covers a different digest than the one requested
+timestamp-wrong-nonce = token nonce does not match the request
+timestamp-no-nonce = token is missing the request nonce
+der-truncated = truncated DER element
+der-unexpected-tag = unexpected DER tag { $tag }, expected { $expected }
+
+## Remote storage
+
+s3-url-invalid = { $url } is not an s3://BUCKET/KEY URL
+s3-no-credentials = s3:// URLs need AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+s3-endpoint-invalid = AWS_ENDPOINT_URL { $endpoint } is not a URL
+remote-open-failed = Could not open { $url }
+s3-error-status = S3 answered { $status }: { $error }
+s3-no-details = no error details
+s3-no-upload-id = S3 started an upload without an UploadId
+s3-no-etag = S3 took part { $part } without an ETag
+s3-error = S3 answered: { $error }
+remote-write-failed-because = Could not write { $url }: { $error }
+remote-write-failed = Could not write { $url }
+url-not-utf8 = { $path } is not a valid UTF-8 URL
+
+## Archives
+
+archive-file-shrank = { $path } got shorter while it was being archived
+source-date-epoch-invalid = SOURCE_DATE_EPOCH must be a whole number of seconds
+symlink-unreadable = Could not read the symlink { $path }
+archive-damaged = The archive is damaged
+archive-truncated = The archive ends in the middle of { $path }
+archive-bad-copy = { $path } copies { $of }, which is not a file before it
+archive-inside-symlink = { $path } lies inside a symlink in the archive
+symlink-create-failed = Could not create the symlink { $path }
+archive-unsafe-path = Refusing the unsafe path { $path }, which would lead out of the destination
+permissions-restore-failed = Could not restore the permissions of { $path }
+time-restore-failed = Could not restore the time of { $path }
+
+## Self-test
+
+self-test-failed = Some self-tests failed; do not use this build
+self-test-sealed = sealing gave { $actual }
+self-test-unopened = the vector's ciphertext did not open
+self-test-wrong-opening = opening gave the wrong plaintext
+self-test-tag-accepted = a changed tag was accepted
+self-test-wrong-plaintext = decrypting gave the wrong plaintext
+self-test-file-accepted = a file with a changed byte was decrypted
+self-test-stream-accepted = a stream with a changed byte was decrypted
+
+## Saltpack
+
+saltpack-no-recipients = saltpack needs at least one recipient
+saltpack-header-not-binary = saltpack header packet is not a byte string
+saltpack-not-message = not a saltpack message
+saltpack-version = unsupported saltpack version (only version 2 is supported)
+saltpack-not-encryption = saltpack message is not in encryption mode
+saltpack-ephemeral-length = saltpack ephemeral key has the wrong length
+saltpack-not-for-identity = this saltpack message is not addressed to the given identity
+saltpack-sender-corrupt = saltpack sender secretbox is corrupt
+saltpack-truncated = saltpack message is truncated (no final packet)
+saltpack-packet-malformed = malformed saltpack payload packet
+saltpack-no-authenticator = saltpack payload packet is missing our authenticator
+saltpack-packet-unauthenticated = saltpack payload packet { $packet } failed authentication
+saltpack-packet-corrupt = saltpack payload packet { $packet } is corrupt
+saltpack-trailing = unexpected data after the final saltpack packet
+saltpack-malformed = malformed saltpack message
+saltpack-not-list = saltpack { $what ->
+    [header] header
+    [version] version
+    [recipients] recipients list
+   *[packet] payload packet
+} is not a list
+saltpack-not-binary = saltpack { $what ->
+    [ephemeral-key] ephemeral key
+   *[sender-secretbox] sender secretbox
+} is not a byte string
+saltpack-not-armored-text = not an armored saltpack message
+saltpack-not-armored-encrypted = not an armored saltpack encrypted message
+saltpack-armor-block-length = saltpack armor has an invalid block length
+saltpack-armor-character = invalid character { $character } in saltpack armor
+saltpack-armor-overflow = saltpack armor block overflows
+
+## Server
+
+server-needs-certificate = a client certificate is required
+server-no-common-name = client certificate has no subject common name
+server-jobs-disabled = jobs are disabled; start the server with --job-root
+server-uploads-disabled = uploads are disabled; start the server with --job-root
+server-wrong-password = wrong password
+server-corrupted = corrupted ciphertext
+server-wrong-password-or-corrupted = wrong password or corrupted ciphertext
+server-locked-out = too many failed decryptions; retry in { $seconds }s
+server-rate-limited = rate limit of { $limit } requests per minute exceeded
+server-job-needs-password = a job needs a password
+server-empty-stream = empty request stream
+server-first-needs-password = the first request message must carry the password
+server-input-too-large = input exceeds { $limit } bytes
+server-tls-invalid = Invalid TLS configuration
+server-needs-tls = serve needs --tls-cert, --tls-key and --client-ca (or --allow-unauthenticated)
+server-needs-client-ca = serve needs --client-ca to authenticate callers (or --allow-unauthenticated)
+server-runtime-failed = Could not start the async runtime
+server-metrics-listen-failed = Could not listen for metrics on { $address }
+server-serving = Serving gRPC on { $address }
+server-failed = gRPC server on { $address } failed
+server-job-root-uncreated = Could not create the job root: { $error }
+server-job-root-unresolved = Could not resolve the job root: { $error }
+server-upload-outside-root = upload paths must stay inside the job root
+server-output-exists = { $name } already exists
+server-output-dir-uncreated = could not create the output's directory
+server-too-many-uploads = too many uploads in progress
+server-no-upload = no such upload
+server-upload-finished = the upload is finished
+server-upload-busy = another call is appending to the upload
+server-upload-offset = the upload is at offset { $offset }, not { $given }
+server-output-unmoved = could not move the output into place
+server-segment-cut = the segment ended before the upload did
+server-job-operation-unknown = unknown job operation
+server-job-modes-conflict = all_or_nothing and keep_going cannot be combined
+server-too-many-jobs = too many jobs in progress
+server-job-files-failed = { $failed } of { $total } files failed
+server-no-job = no such job
+server-path-not-relative = { $path } must be a relative path without '..'
+server-input-missing = { $path } does not exist
+server-job-outside-root = job paths must stay inside the job root
+server-input-not-file-or-dir = { $path } is not a file or directory
+server-input-unlisted = Could not list { $path }: { $error }
+server-file-unread = could not read the file
+server-file-dir-uncreated = could not create its directory
+server-output-unwritten = could not write the output
+server-metrics-serving = Serving /metrics and /healthz on { $address }
+
+## Armor
+
+armor-no-begin = No { $line } line found
+armor-no-end = The armored message has no { $line } line; it may have been cut short
+armor-not-base64 = The armored message is not valid base64: { $error }
+mime-no-part = No { $content_type } part found
+mime-not-base64 = The { $content_type } part is not valid base64: { $error }
+
+## OpenPGP
+
+openpgp-key-invalid = Could not parse OpenPGP public key
+openpgp-key-bindings = OpenPGP public key has invalid binding signatures
+openpgp-key-cannot-encrypt = OpenPGP public key has no encryption-capable key
+openpgp-wrap-failed = Error wrapping the session key for the OpenPGP recipient
+openpgp-encrypt-failed = Error during OpenPGP encryption
+
+## Config files
+
+manifest-unknown-table = expected [[file]], not "{ $table }"
+config-expected-key-value = expected `key = value`
+manifest-key-outside-table = { $key } outside a [[file]] table
+config-not-bool = "{ $value }" is not true or false
+config-unknown-key = unknown key "{ $key }"
+manifest-empty = The manifest { $path } lists no [[file]]
+manifest-entry-no-input = a [[file]] needs an input
+manifest-entry-stdio = manifest entries are files, not -
+manifest-output-twice = { $path } is the output of two entries
+manifest-input-not-file = { $path } in the manifest is not a file
+config-unknown-schedule-key = unknown schedule key
+config-unknown-table = the only tables are [schedule.NAME]
+config-schedule-twice = schedule { $name } is defined twice
+config-kdf-time-and-iterations = { $path }: kdf_time and argon2_iterations can't both be set
+config-unterminated-string = unterminated string
+config-escapes = escapes are not supported; use a 'literal string'
+config-trailing = unexpected "{ $rest }" after the value
+config-not-number = "{ $value }" is not a valid number
+cron-not-five-fields = "{ $expression }" is not five fields: minute, hour, day, month and weekday
+cron-never = "{ $expression }" never matches any day
+cron-invalid-field = "{ $field }" is not a cron field of values { $min } to { $max }
+config-unreadable = Could not read the config file { $path }
+manifest-file-unreadable = Could not read the manifest { $path }
+
+## Inspect
+
+inspect-foreign-format = This is an armored saltpack or OpenPGP message; inspect reads the native format
+inspect-too-short = The file is too short to be an encrypted file
+inspect-intact = The file is intact. If it won't decrypt, the password or key file is wrong.
+inspect-damaged = The file was damaged after it was encrypted; no password will decrypt it.
+inspect-wrapping = Wrapping
+inspect-format = Format
+inspect-created = Created
+inspect-expires = Expires
+inspect-layout = Layout
+inspect-cipher = Cipher
+inspect-compression = Compression
+inspect-key-source = Key source
+inspect-kdf = KDF
+inspect-salt = Salt
+inspect-nonce = Nonce
+inspect-nonce-prefix = Nonce prefix
+inspect-key-check = Key check
+inspect-aad = AAD
+inspect-metadata = Metadata
+inspect-content-sha256 = Content SHA-256
+inspect-header = Header
+inspect-compressed = Compressed
+inspect-plaintext = Plaintext
+inspect-checksum = Checksum
+inspect-mime = MIME attachment (--format mime)
+inspect-age-identity = age identity
+inspect-raw-key-file = key file, 32 raw bytes
+inspect-key-file = key file or X25519 identity
+inspect-not-recorded = not recorded
+inspect-never = never
+inspect-expired = { $date } (expired)
+inspect-expires-in = { $date } (in { $days } days)
+inspect-version = version { $version }
+inspect-legacy-chunked = chunked, from before the versioned header
+inspect-legacy = no header: from before the versioned header, or not this tool's at all
+inspect-single-message = single message
+inspect-chunked = chunked, { $size } chunks
+inspect-none = none
+inspect-public-key = public key
+inspect-password-slot = password, { $kdf }
+inspect-token-slot = hardware token { $token }, ECDH P-256
+inspect-recipient = Recipient { $number }
+inspect-none-legacy = none (from before the header)
+inspect-aad-bound = bound to associated data; decrypting needs the same --aad
+inspect-checksum-hidden = recorded (encrypted; --with-password shows it)
+inspect-bytes = { $length } bytes
+inspect-compressed-length = { $size } ({ $length } bytes) before decompressing, from the file's length
+inspect-plaintext-length = { $size } ({ $length } bytes), from the file's length
+inspect-plaintext-unknown = unknown: the file is too short for its layout
+inspect-mismatch = MISMATCH
+inspect-checksum-none = none (from before the checksum footer, so damage looks like a wrong password)
+inspect-source-password = password
+inspect-source-key-file = key file
+inspect-source-password-and-key-file = password and key file
+inspect-source-recipients = recipients (public keys and passwords)
+inspect-pbkdf2 = PBKDF2-HMAC-SHA256, { $iterations } iterations
+inspect-argon2id = Argon2id, { $memory } memory, { $iterations } iterations, parallelism { $parallelism }
+inspect-metadata-recorded = name, permissions and modification time (encrypted, { $length } bytes)
+
+## Diagnostics
+
+doctor-data-dir = Data directory
+doctor-state-dir = State directory
+doctor-fix = fix: { $fix }
+doctor-failed = Some checks failed
+doctor-entropy = Entropy source
+doctor-entropy-ok = the OS random number generator is available
+doctor-entropy-broken-fix = do not encrypt on this machine until its RNG is fixed
+doctor-entropy-missing-fix = check that /dev/urandom (or getrandom) is available, e.g. inside containers
+doctor-temp-dir = Temp directory
+doctor-dir-unwritable = { $path } is not writable: { $error }
+doctor-temp-dir-fix = set TMPDIR (TEMP on Windows) to a writable directory
+doctor-dir-low-space = { $path } is writable, but only { $free } free
+doctor-low-space-fix = free up space or point TMPDIR at a larger filesystem
+doctor-dir-space = { $path } is writable, { $free } free
+doctor-dir-writable = { $path } is writable
+doctor-dir-unknown = could not be determined
+doctor-dir-unknown-fix = set HOME (or XDG_DATA_HOME / XDG_STATE_HOME), or pass --container to note and vault
+doctor-permissions-fix = fix the permissions on { $path }
+doctor-terminal-ok = interactive; password prompts will work
+doctor-terminal = Terminal
+doctor-terminal-missing = no controlling terminal, so password prompts will fail
+doctor-terminal-fix = pass -p or run the command from an interactive terminal
+doctor-terminal-redirected = password prompts use the terminal (standard input is redirected)
+doctor-locale-windows = not applicable on Windows
+doctor-locale = Locale
+doctor-locale-not-utf8 = { $locale } is not UTF-8
+doctor-locale-fix = set LANG=C.UTF-8 (or your language's UTF-8 locale) so non-ASCII passwords are typed the same way they are elsewhere
+algorithms-for-encryption = native format encryption
+algorithms-for-kdf = native format key derivation
+algorithms-for-checksum = transport checksum
+algorithms-hardware = hardware ({ $features })
+algorithms-software-missing = software (no { $features })
+algorithms-software-unbuilt = software (built without --cfg { $cfg })
+algorithms-software-sha2 = software (this build does not use the CPU's SHA2 instructions)
+algorithms-software = software
+algorithms-algorithm = Algorithm
+algorithms-used-for = Used for
+algorithms-backend = Backend
+algorithms-default-cipher = Default cipher: { $cipher }
+bench-size-zero = --size must be more than 0 bytes
+bench-chunk-size-range = --chunk-size must be from 1 byte to { $max }
+bench-debug-build = This is a debug build; build with --release for figures that mean anything.
+bench-kdf = Key derivation, per key
+bench-cipher = Cipher, { $size } in { $chunk } pieces
+bench-stream = Encrypt --stream, { $cipher }, { $size }
+bench-picked = {" "}(picked)
+
+## C library
+
+ffi-stream-finished = the stream has already finished
+ffi-stream-panicked = the stream's thread panicked
+ffi-panicked = the library panicked
@@ -0,0 +1,643 @@
+//! The age file format (age-encryption.org/v1), so files can be exchanged
+//! with age and rage.
+//!
+//! A file is for X25519 recipients, the `age1...` public keys whose secret
+//! keys are `AGE-SECRET-KEY-1...` identities, or for one password through
+//! scrypt, which age only allows alone. Stanzas of other kinds, such as
+//! those of SSH keys or plugins, are passed over when decrypting. The file
+//! key opens a header MAC and then the payload, in 64 KiB ChaCha20-Poly1305
+//! chunks.
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305,
+};
+use crypto_box::{PublicKey, SecretKey};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use file_encryptor::{i18n::t, DecryptError};
+
+use crate::entropy;
+
+/// The first line of every age file.
+pub const INTRO: &[u8] = b"age-encryption.org/v1\n";
+const X25519_LABEL: &[u8] = b"age-encryption.org/v1/X25519";
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+const RECIPIENT_PREFIX: &str = "age";
+const IDENTITY_PREFIX: &str = "age-secret-key-";
+
+const FILE_KEY_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LENGTH: usize = 16;
+/// Stanza bodies are wrapped at this many base64 characters.
+const COLUMNS: usize = 64;
+/// The scrypt work factor of files we write, age's own.
+const SCRYPT_LOG_N: u8 = 18;
+/// The largest work factor read, as age; 2^22 takes 4 GiB of memory.
+const MAX_SCRYPT_LOG_N: u8 = 22;
+const SCRYPT_R: usize = 8;
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// What an age file is decrypted with.
+pub enum Key<'a> {
+    Identity(&'a SecretKey),
+    Password(&'a str),
+}
+
+/// One recipient's wrapping of the file key: its type and arguments, then
+/// its body.
+struct Stanza {
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+/// Encrypts `plaintext` to every key in `recipients`.
+pub fn encrypt(recipients: &[PublicKey], plaintext: &[u8]) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        bail!(t!("age-no-recipients"));
+    }
+    let file_key = Zeroizing::new(entropy::bytes::<FILE_KEY_LENGTH>()?);
+    let stanzas = recipients
+        .iter()
+        .map(|recipient| wrap_x25519(recipient, &file_key))
+        .collect::<Result<Vec<_>>>()?;
+    seal(&stanzas, &file_key, plaintext)
+}
+
+/// Encrypts `plaintext` with `password`.
+pub fn encrypt_with_password(password: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let file_key = Zeroizing::new(entropy::bytes::<FILE_KEY_LENGTH>()?);
+    let salt: [u8; 16] = entropy::bytes()?;
+    let key = scrypt(
+        password.as_bytes(),
+        &[SCRYPT_LABEL, &salt].concat(),
+        SCRYPT_LOG_N,
+    );
+    let stanza = Stanza {
+        args: vec![
+            "scrypt".to_owned(),
+            STANDARD_NO_PAD.encode(salt),
+            SCRYPT_LOG_N.to_string(),
+        ],
+        body: wrap(&key, &file_key[..])?,
+    };
+    seal(&[stanza], &file_key, plaintext)
+}
+
+/// Decrypts an age file with `key`.
+pub fn decrypt(key: &Key, file: &[u8]) -> Result<Vec<u8>> {
+    let Parsed {
+        stanzas,
+        header,
+        mac,
+        payload,
+    } = parse(file)?;
+    let file_key = match key {
+        Key::Identity(identity) => stanzas
+            .iter()
+            .filter(|stanza| stanza.args[0] == "X25519")
+            .find_map(|stanza| unwrap_x25519(identity, stanza).transpose())
+            .transpose()?
+            .ok_or_else(|| anyhow!(t!("age-not-for-identity")).context(DecryptError::WrongKey))?,
+        Key::Password(password) => unwrap_scrypt(password, &stanzas)?,
+    };
+    let mac_key = hkdf(&[], &file_key[..], b"header");
+    if !bool::from(hmac(&mac_key[..], &[header])[..].ct_eq(&mac)) {
+        return Err(anyhow!(t!("age-header-tampered")).context(DecryptError::Corrupted));
+    }
+    open_payload(&file_key, payload)
+}
+
+/// The X25519 public key of an `age1...` recipient.
+pub fn parse_recipient(text: &str) -> Result<PublicKey> {
+    match bech32_decode(text.trim()) {
+        Some((prefix, key)) if prefix == RECIPIENT_PREFIX => <[u8; 32]>::try_from(key)
+            .map(PublicKey::from)
+            .map_err(|_| anyhow!(t!("age-recipient-length"))),
+        _ => Err(anyhow!(t!(
+            "age-recipient-invalid",
+            text = format!("{text:?}")
+        ))),
+    }
+}
+
+/// The secret key on the `AGE-SECRET-KEY-1...` line of an age identity
+/// file, or `None` if `text` has no such line.
+pub fn parse_identity(text: &str) -> Result<Option<SecretKey>> {
+    let Some(line) = text
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("AGE-SECRET-KEY-1"))
+    else {
+        return Ok(None);
+    };
+    match bech32_decode(line) {
+        Some((prefix, key)) if prefix == IDENTITY_PREFIX => {
+            let key = Zeroizing::new(key);
+            let bytes: [u8; 32] = key
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!(t!("age-identity-length")))?;
+            Ok(Some(SecretKey::from(bytes)))
+        }
+        _ => Err(anyhow!(t!("age-identity-invalid"))),
+    }
+}
+
+/// Writes the header of `stanzas` and the payload under `file_key`.
+fn seal(stanzas: &[Stanza], file_key: &[u8; FILE_KEY_LENGTH], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut file = INTRO.to_vec();
+    for stanza in stanzas {
+        file.extend_from_slice(b"-> ");
+        file.extend_from_slice(stanza.args.join(" ").as_bytes());
+        file.push(b'\n');
+        let body = STANDARD_NO_PAD.encode(&stanza.body);
+        for line in body.as_bytes().chunks(COLUMNS) {
+            file.extend_from_slice(line);
+            file.push(b'\n');
+        }
+        // The last line is always short, if need be empty.
+        if body.len() % COLUMNS == 0 {
+            file.push(b'\n');
+        }
+    }
+    file.extend_from_slice(b"---");
+    let mac = hmac(&hkdf(&[], file_key, b"header")[..], &[&file]);
+    file.push(b' ');
+    file.extend_from_slice(STANDARD_NO_PAD.encode(&mac[..]).as_bytes());
+    file.push(b'\n');
+
+    let nonce: [u8; NONCE_LENGTH] = entropy::bytes()?;
+    file.extend_from_slice(&nonce);
+    let cipher = ChaCha20Poly1305::new((&*hkdf(&nonce, file_key, b"payload")).into());
+    // An empty plaintext is one empty last chunk.
+    let chunks: Vec<&[u8]> = match plaintext.is_empty() {
+        true => vec![&[]],
+        false => plaintext.chunks(CHUNK_SIZE).collect(),
+    };
+    for (counter, chunk) in chunks.iter().enumerate() {
+        let nonce = chunk_nonce(counter as u64, counter + 1 == chunks.len());
+        let sealed = cipher
+            .encrypt(&nonce.into(), *chunk)
+            .map_err(|e| anyhow!(e))?;
+        file.extend_from_slice(&sealed);
+    }
+    Ok(file)
+}
+
+/// An age file as read.
+struct Parsed<'a> {
+    stanzas: Vec<Stanza>,
+    /// The header as far as its MAC covers it, up to and including `---`.
+    header: &'a [u8],
+    mac: Vec<u8>,
+    payload: &'a [u8],
+}
+
+fn parse(file: &[u8]) -> Result<Parsed<'_>> {
+    let malformed = || header_error(t!("age-header-malformed"));
+    if !file.starts_with(INTRO) {
+        bail!(t!("age-not-age"));
+    }
+    let mut at = INTRO.len();
+    let mut stanzas = Vec::new();
+    loop {
+        let line_start = at;
+        let line = next_line(file, &mut at)?;
+        if let Some(mac) = line.strip_prefix(b"--- ") {
+            let mac = STANDARD_NO_PAD.decode(mac).map_err(|_| malformed())?;
+            if mac.len() != 32 {
+                return Err(malformed());
+            }
+            return Ok(Parsed {
+                stanzas,
+                header: &file[..line_start + b"---".len()],
+                mac,
+                payload: &file[at..],
+            });
+        }
+        let args = line.strip_prefix(b"-> ").ok_or_else(malformed)?;
+        let args: Vec<String> = std::str::from_utf8(args)
+            .map_err(|_| malformed())?
+            .split(' ')
+            .map(str::to_owned)
+            .collect();
+        if args
+            .iter()
+            .any(|arg| arg.is_empty() || !arg.bytes().all(|b| b.is_ascii_graphic()))
+        {
+            return Err(malformed());
+        }
+        let mut body = Vec::new();
+        loop {
+            let line = next_line(file, &mut at)?;
+            if line.len() > COLUMNS {
+                return Err(malformed());
+            }
+            body.extend_from_slice(line);
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+        let body = STANDARD_NO_PAD.decode(&body).map_err(|_| malformed())?;
+        stanzas.push(Stanza { args, body });
+    }
+}
+
+/// `message`, about a header that doesn't parse, as a
+/// [`DecryptError::HeaderInvalid`].
+fn header_error(message: String) -> anyhow::Error {
+    anyhow!(message).context(DecryptError::HeaderInvalid)
+}
+
+/// The line of `file` at `at`, without its newline, moving `at` past it.
+fn next_line<'a>(file: &'a [u8], at: &mut usize) -> Result<&'a [u8]> {
+    let end = file[*at..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| header_error(t!("age-header-malformed")))?;
+    let line = &file[*at..*at + end];
+    *at += end + 1;
+    Ok(line)
+}
+
+fn wrap_x25519(recipient: &PublicKey, file_key: &[u8; FILE_KEY_LENGTH]) -> Result<Stanza> {
+    let ephemeral = x25519_dalek::StaticSecret::from(entropy::bytes::<32>()?);
+    let share = x25519_dalek::PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&x25519_dalek::PublicKey::from(*recipient.as_bytes()));
+    let salt = [share.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let wrap_key = hkdf(&salt, shared.as_bytes(), X25519_LABEL);
+    Ok(Stanza {
+        args: vec![
+            "X25519".to_owned(),
+            STANDARD_NO_PAD.encode(share.as_bytes()),
+        ],
+        body: wrap(&wrap_key, file_key)?,
+    })
+}
+
+/// The file key in an X25519 stanza, or `None` if it isn't for `identity`.
+fn unwrap_x25519(
+    identity: &SecretKey,
+    stanza: &Stanza,
+) -> Result<Option<Zeroizing<[u8; FILE_KEY_LENGTH]>>> {
+    let malformed = || header_error(t!("age-x25519-malformed"));
+    let [_, share] = &stanza.args[..] else {
+        return Err(malformed());
+    };
+    let share: [u8; 32] = STANDARD_NO_PAD
+        .decode(share)
+        .ok()
+        .and_then(|share| share.try_into().ok())
+        .ok_or_else(malformed)?;
+    let secret = x25519_dalek::StaticSecret::from(identity.to_bytes());
+    let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(share));
+    if !shared.was_contributory() {
+        return Err(malformed());
+    }
+    let public = x25519_dalek::PublicKey::from(&secret);
+    let salt = [share.as_slice(), public.as_bytes()].concat();
+    let wrap_key = hkdf(&salt, shared.as_bytes(), X25519_LABEL);
+    Ok(unwrap(&wrap_key, &stanza.body))
+}
+
+fn unwrap_scrypt(password: &str, stanzas: &[Stanza]) -> Result<Zeroizing<[u8; FILE_KEY_LENGTH]>> {
+    let Some(stanza) = stanzas.iter().find(|stanza| stanza.args[0] == "scrypt") else {
+        bail!(t!("age-not-password"));
+    };
+    if stanzas.len() != 1 {
+        return Err(header_error(t!("age-scrypt-not-alone")));
+    }
+    let malformed = || header_error(t!("age-scrypt-malformed"));
+    let [_, salt, log_n] = &stanza.args[..] else {
+        return Err(malformed());
+    };
+    let salt = STANDARD_NO_PAD
+        .decode(salt)
+        .ok()
+        .filter(|salt| salt.len() == 16)
+        .ok_or_else(malformed)?;
+    // Decimal without leading zeros, as age writes it.
+    let log_n: u8 = log_n
+        .parse()
+        .ok()
+        .filter(|n: &u8| *n > 0 && log_n == &n.to_string())
+        .ok_or_else(malformed)?;
+    if log_n > MAX_SCRYPT_LOG_N {
+        bail!(DecryptError::Unsupported(t!(
+            "age-scrypt-work-factor",
+            log_n = log_n,
+            limit = MAX_SCRYPT_LOG_N
+        )));
+    }
+    let key = scrypt(password.as_bytes(), &[SCRYPT_LABEL, &salt].concat(), log_n);
+    unwrap(&key, &stanza.body)
+        .ok_or_else(|| anyhow!(t!("age-wrong-password")).context(DecryptError::WrongKey))
+}
+
+/// The file key under ChaCha20-Poly1305 with a zero nonce; each wrapping
+/// key is used once.
+fn wrap(key: &[u8; 32], file_key: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(&[0; 12].into(), file_key)
+        .map_err(|e| anyhow!(e))
+}
+
+fn unwrap(key: &[u8; 32], body: &[u8]) -> Option<Zeroizing<[u8; FILE_KEY_LENGTH]>> {
+    if body.len() != FILE_KEY_LENGTH + TAG_LENGTH {
+        return None;
+    }
+    let file_key = Zeroizing::new(
+        ChaCha20Poly1305::new(key.into())
+            .decrypt(&[0; 12].into(), body)
+            .ok()?,
+    );
+    file_key.as_slice().try_into().ok().map(Zeroizing::new)
+}
+
+fn open_payload(file_key: &[u8; FILE_KEY_LENGTH], payload: &[u8]) -> Result<Vec<u8>> {
+    let truncated = || anyhow!(t!("age-payload-truncated")).context(DecryptError::Corrupted);
+    let nonce = payload.get(..NONCE_LENGTH).ok_or_else(truncated)?;
+    let chunks: Vec<&[u8]> = payload[NONCE_LENGTH..]
+        .chunks(CHUNK_SIZE + TAG_LENGTH)
+        .collect();
+    if chunks.is_empty() {
+        return Err(truncated());
+    }
+    let cipher = ChaCha20Poly1305::new((&*hkdf(nonce, file_key, b"payload")).into());
+    let mut plaintext = Vec::with_capacity(payload.len());
+    for (counter, chunk) in chunks.iter().enumerate() {
+        let last = counter + 1 == chunks.len();
+        let opened = cipher
+            .decrypt(&chunk_nonce(counter as u64, last).into(), *chunk)
+            .map_err(|_| anyhow!(t!("age-payload-damaged")).context(DecryptError::Corrupted))?;
+        // Only an empty file ends in an empty chunk.
+        if last && opened.is_empty() && counter > 0 {
+            return Err(truncated());
+        }
+        plaintext.extend_from_slice(&opened);
+    }
+    Ok(plaintext)
+}
+
+/// The STREAM nonce of chunk `counter`: the counter, big-endian in eleven
+/// bytes, and a last-chunk flag.
+fn chunk_nonce(counter: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = u8::from(last);
+    nonce
+}
+
+/// HKDF-SHA256 (RFC 5869) of one 32-byte output.
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0; 32]);
+    Hkdf::<sha2_v10::Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut key[..])
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> Zeroizing<[u8; 32]> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+/// scrypt (RFC 7914) with r = 8 and p = 1, as age uses it.
+fn scrypt(password: &[u8], salt: &[u8], log_n: u8) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0; 32]);
+    scrypt_into(password, salt, log_n, SCRYPT_R, &mut key[..]);
+    key
+}
+
+/// scrypt with N = 2^`log_n`, block size `r` and p = 1, filling `output`.
+pub fn scrypt_into(password: &[u8], salt: &[u8], log_n: u8, r: usize, output: &mut [u8]) {
+    let words = 32 * r;
+    let n = 1usize << log_n;
+    let mut block = Zeroizing::new(vec![0u8; 4 * words]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, 1, &mut block[..]);
+
+    let mut x = Zeroizing::new(vec![0u32; words]);
+    for (word, bytes) in x.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().expect("four bytes"));
+    }
+    let mut y = Zeroizing::new(vec![0u32; words]);
+    let mut v = Zeroizing::new(vec![0u32; words * n]);
+    for i in 0..n {
+        v[i * words..(i + 1) * words].copy_from_slice(&x);
+        block_mix(&x, &mut y);
+        std::mem::swap(&mut x, &mut y);
+    }
+    for _ in 0..n {
+        let j = x[words - 16] as usize & (n - 1);
+        for (word, other) in x.iter_mut().zip(&v[j * words..(j + 1) * words]) {
+            *word ^= other;
+        }
+        block_mix(&x, &mut y);
+        std::mem::swap(&mut x, &mut y);
+    }
+
+    for (bytes, word) in block.chunks_exact_mut(4).zip(x.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, &block[..], 1, output);
+}
+
+/// scryptBlockMix: Salsa20/8 over the 2r 64-byte blocks of `input`, chained,
+/// with the even results first and the odd ones after.
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let r = input.len() / 32;
+    let mut x: [u32; 16] = input[input.len() - 16..].try_into().expect("a block");
+    for (i, block) in input.chunks_exact(16).enumerate() {
+        for (word, other) in x.iter_mut().zip(block) {
+            *word ^= other;
+        }
+        salsa20_8(&mut x);
+        let to = match i % 2 {
+            0 => i / 2,
+            _ => r + i / 2,
+        };
+        output[to * 16..(to + 1) * 16].copy_from_slice(&x);
+    }
+}
+
+fn salsa20_8(block: &mut [u32; 16]) {
+    let mut x = *block;
+    let mut quarter = |a: usize, b: usize, c: usize, d: usize| {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    };
+    for _ in 0..4 {
+        quarter(0, 4, 8, 12);
+        quarter(5, 9, 13, 1);
+        quarter(10, 14, 2, 6);
+        quarter(15, 3, 7, 11);
+        quarter(0, 1, 2, 3);
+        quarter(5, 6, 7, 4);
+        quarter(10, 11, 8, 9);
+        quarter(15, 12, 13, 14);
+    }
+    for (word, mixed) in block.iter_mut().zip(x) {
+        *word = word.wrapping_add(mixed);
+    }
+}
+
+/// The prefix and data of a bech32 (BIP 173) string, in either case but not
+/// both.
+fn bech32_decode(text: &str) -> Option<(String, Vec<u8>)> {
+    if text.bytes().any(|b| b.is_ascii_lowercase()) && text.bytes().any(|b| b.is_ascii_uppercase())
+    {
+        return None;
+    }
+    let text = text.to_ascii_lowercase();
+    let (prefix, data) = text.rsplit_once('1')?;
+    if prefix.is_empty() || data.len() < 6 {
+        return None;
+    }
+    let values = data
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|&x| x == c).map(|v| v as u8))
+        .collect::<Option<Vec<_>>>()?;
+    if bech32_polymod(&[&bech32_expand(prefix), &values[..]].concat()) != 1 {
+        return None;
+    }
+    // Back from five bits to eight, with no more than four zero bits over.
+    let (mut bytes, mut accumulator, mut bits) = (Vec::new(), 0u32, 0);
+    for &value in &values[..values.len() - 6] {
+        accumulator = (accumulator << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+    if bits >= 5 || accumulator & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    Some((prefix.to_owned(), bytes))
+}
+
+fn bech32_expand(prefix: &str) -> Vec<u8> {
+    let high = prefix.bytes().map(|b| b >> 5);
+    let low = prefix.bytes().map(|b| b & 31);
+    high.chain([0]).chain(low).collect()
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for &value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ u32::from(value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The payload of both files below: `PLAINTEXT`, under file key 00..0f
+    /// and payload nonce 10..1f.
+    const PAYLOAD: &str = "101112131415161718191a1b1c1d1e1fe4de5afe54b77995bcf0684019855002be4f2f08481c\
+                           1f4520e591764d520a30519d8282a9d3f46b8c99828afc1334d8d781e5b2ceeb711622bc2c85";
+    const PLAINTEXT: &[u8] = b"age file from an independent implementation\n";
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX";
+    const RECIPIENT: &str = "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj";
+    const X25519_HEADER: &str = "age-encryption.org/v1
+-> X25519 8T/vPvqVmKKiP8dWv2iP6LvX9s+VKLuu87REJojwqzE
+I1/7/AD/ct24elK7NcMR/rrhVPTcOnZCXCIS0C+H/Wk
+--- B83RJidorcpe46tuAHZt5NQ6X6GFdJSYGbmw1nk41wE
+";
+    const SCRYPT_HEADER: &str = "age-encryption.org/v1
+-> scrypt ICEiIyQlJicoKSorLC0uLw 10
+ZkFGhF3JS2Nzii0Utem5QkZbVTW5zjMJ5NeGySTjWNs
+--- RggWT+bpxExosMadawXHnPQUqwpRI3W7V7xrty58jsY
+";
+
+    fn file(header: &str) -> Vec<u8> {
+        [header.as_bytes(), &hex::decode(PAYLOAD).unwrap()].concat()
+    }
+
+    #[test]
+    fn scrypt_matches_rfc_7914() {
+        let mut key = [0; 64];
+        scrypt_into(b"", b"", 4, 1, &mut key);
+        assert_eq!(
+            hex::encode(key),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        scrypt_into(b"pleaseletmein", b"SodiumChloride", 14, 8, &mut key);
+        assert_eq!(
+            hex::encode(key),
+            "7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2\
+             d5432955613f0fcf62d49705242a9af9e61e85dc0d651e40dfcf017b45575887"
+        );
+    }
+
+    #[test]
+    fn decrypts_a_file_for_an_identity() {
+        let identity = parse_identity(IDENTITY).unwrap().unwrap();
+        assert_eq!(
+            identity.public_key().as_bytes(),
+            parse_recipient(RECIPIENT).unwrap().as_bytes()
+        );
+        let file = file(X25519_HEADER);
+        assert_eq!(
+            decrypt(&Key::Identity(&identity), &file).unwrap(),
+            PLAINTEXT
+        );
+
+        let other = SecretKey::from([7; 32]);
+        let e = decrypt(&Key::Identity(&other), &file).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&DecryptError::WrongKey));
+    }
+
+    #[test]
+    fn decrypts_a_file_for_a_password() {
+        let file = file(SCRYPT_HEADER);
+        let key = Key::Password("correct horse");
+        assert_eq!(decrypt(&key, &file).unwrap(), PLAINTEXT);
+        let e = decrypt(&Key::Password("wrong horse"), &file).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&DecryptError::WrongKey));
+
+        let mut tampered = file.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let e = decrypt(&key, &tampered).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&DecryptError::Corrupted));
+        tampered = file.clone();
+        tampered[SCRYPT_HEADER.find("--- ").unwrap() + 4] ^= 1;
+        let e = decrypt(&key, &tampered).unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&DecryptError::Corrupted));
+    }
+
+    #[test]
+    fn round_trips_to_a_recipient() {
+        let identity = SecretKey::from([9; 32]);
+        let sealed = encrypt(&[identity.public_key()], PLAINTEXT).unwrap();
+        assert_eq!(
+            decrypt(&Key::Identity(&identity), &sealed).unwrap(),
+            PLAINTEXT
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! `algorithms`: which backend each primitive runs on here, so large speed
+//! differences between machines can be explained.
+//!
+//! The report follows what the crates we build on actually do. On x86 they
+//! pick hardware instructions at runtime. On aarch64 the AES and GHASH
+//! backends are only compiled in with `--cfg aes_armv8` and
+//! `--cfg polyval_armv8`, ChaCha20's with `--cfg chacha20_force_neon`, and
+//! sha2 0.9 stays in software without its `asm` feature.
+
+use file_encryptor::i18n::t;
+
+struct Primitive {
+    name: &'static str,
+    used_for: String,
+    backend: String,
+}
+
+/// Names the hardware path when every feature it needs is present.
+fn backend(features: &[(&str, bool)]) -> String {
+    if features.iter().all(|&(_, present)| present) {
+        let names: Vec<_> = features.iter().map(|&(name, _)| name).collect();
+        t!("algorithms-hardware", features = names.join(", "))
+    } else {
+        let missing: Vec<_> = features
+            .iter()
+            .filter(|&&(_, present)| !present)
+            .map(|&(name, _)| name)
+            .collect();
+        t!("algorithms-software-missing", features = missing.join(", "))
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn primitives() -> Vec<Primitive> {
+    use std::arch::is_x86_feature_detected as has;
+
+    let blake3 = [
+        ("AVX-512", has!("avx512f") && has!("avx512vl")),
+        ("AVX2", has!("avx2")),
+        ("SSE4.1", has!("sse4.1")),
+    ]
+    .iter()
+    .find(|&&(_, present)| present)
+    .map_or_else(
+        || t!("algorithms-software"),
+        |&(name, _)| t!("algorithms-hardware", features = name),
+    );
+    vec![
+        Primitive {
+            name: "AES-256-GCM",
+            used_for: t!("algorithms-for-encryption"),
+            backend: backend(&[("AES-NI", has!("aes")), ("PCLMULQDQ", has!("pclmulqdq"))]),
+        },
+        Primitive {
+            name: "ChaCha20-Poly1305",
+            used_for: "--cipher (x)chacha20poly1305".to_owned(),
+            // SSE2 is the fallback, and every x86_64 CPU has it.
+            backend: if has!("avx2") {
+                t!("algorithms-hardware", features = "AVX2")
+            } else {
+                backend(&[("SSE2", has!("sse2"))])
+            },
+        },
+        Primitive {
+            name: "PBKDF2-HMAC-SHA256",
+            used_for: t!("algorithms-for-kdf"),
+            backend: backend(&[("SHA-NI", has!("sha") && has!("sse4.1"))]),
+        },
+        Primitive {
+            name: "BLAKE3",
+            used_for: t!("algorithms-for-checksum"),
+            backend: blake3,
+        },
+    ]
+}
+
+#[cfg(target_arch = "aarch64")]
+fn primitives() -> Vec<Primitive> {
+    use std::arch::is_aarch64_feature_detected as has;
+
+    let aes = if cfg!(all(aes_armv8, polyval_armv8)) {
+        backend(&[("AES", has!("aes")), ("PMULL", has!("pmull"))])
+    } else {
+        t!(
+            "algorithms-software-unbuilt",
+            cfg = "aes_armv8 / polyval_armv8"
+        )
+    };
+    let sha = if has!("sha2") {
+        t!("algorithms-software-sha2")
+    } else {
+        t!("algorithms-software-missing", features = "SHA2")
+    };
+    vec![
+        Primitive {
+            name: "AES-256-GCM",
+            used_for: t!("algorithms-for-encryption"),
+            backend: aes,
+        },
+        Primitive {
+            name: "ChaCha20-Poly1305",
+            used_for: "--cipher (x)chacha20poly1305".to_owned(),
+            backend: if cfg!(chacha20_force_neon) {
+                t!("algorithms-hardware", features = "NEON")
+            } else {
+                t!("algorithms-software-unbuilt", cfg = "chacha20_force_neon")
+            },
+        },
+        Primitive {
+            name: "PBKDF2-HMAC-SHA256",
+            used_for: t!("algorithms-for-kdf"),
+            backend: sha,
+        },
+        Primitive {
+            name: "BLAKE3",
+            used_for: t!("algorithms-for-checksum"),
+            backend: if cfg!(target_endian = "little") {
+                t!("algorithms-hardware", features = "NEON")
+            } else {
+                t!("algorithms-software")
+            },
+        },
+    ]
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn primitives() -> Vec<Primitive> {
+    [
+        ("AES-256-GCM", t!("algorithms-for-encryption")),
+        (
+            "ChaCha20-Poly1305",
+            "--cipher (x)chacha20poly1305".to_owned(),
+        ),
+        ("PBKDF2-HMAC-SHA256", t!("algorithms-for-kdf")),
+        ("BLAKE3", t!("algorithms-for-checksum")),
+    ]
+    .into_iter()
+    .map(|(name, used_for)| Primitive {
+        name,
+        used_for,
+        backend: t!("algorithms-software"),
+    })
+    .collect()
+}
+
+/// Whether AES-256-GCM runs on the CPU's AES and carry-less multiply
+/// instructions here, as the report says; without them `encrypt` defaults
+/// to ChaCha20-Poly1305.
+pub fn aes_accelerated() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        use std::arch::is_x86_feature_detected as has;
+        has!("aes") && has!("pclmulqdq")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        use std::arch::is_aarch64_feature_detected as has;
+        cfg!(all(aes_armv8, polyval_armv8)) && has!("aes") && has!("pmull")
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+pub fn print_report() {
+    println!(
+        "{:<20} {:<30} {}",
+        t!("algorithms-algorithm"),
+        t!("algorithms-used-for"),
+        t!("algorithms-backend")
+    );
+    for primitive in primitives() {
+        println!(
+            "{:<20} {:<30} {}",
+            primitive.name, primitive.used_for, primitive.backend
+        );
+    }
+    let default = match aes_accelerated() {
+        true => "AES-256-GCM",
+        false => "ChaCha20-Poly1305",
+    };
+    println!();
+    println!("{}", t!("algorithms-default-cipher", cipher = default));
+}
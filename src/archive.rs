@@ -0,0 +1,659 @@
+//! `encrypt --archive` and `decrypt --extract`: a directory tree packed into
+//! one stream, which is then encrypted in the chunked format like any other
+//! input. The stream is
+//!
+//! ```text
+//! "FEARCHV2" | entry | ... | end
+//! ```
+//!
+//! where each entry is a bincode [`Entry`] (path, kind, permissions and
+//! modification time), followed for a regular file by its contents, and the
+//! end is a bincode `None`. Paths are relative, `/`-separated and in path
+//! order, each directory before what it contains.
+//!
+//! A file whose contents are byte for byte those of a file before it is
+//! stored once: its entry is a copy that names the earlier file and has no
+//! contents of its own. Files are only hashed, with BLAKE3, when another file
+//! has the same length, so a tree without duplicates is read through once.
+//! Version 1 archives, from before copies, extract as they always did.
+//!
+//! A reproducible archive leaves out what differs between two copies of the
+//! same tree: every time is `SOURCE_DATE_EPOCH`, or the epoch itself, and
+//! permissions come down to 755 for directories and executable files, 644
+//! for other files and 777 for symlinks. Owners are never recorded. Entries
+//! are in path order either way, by the bytes of each name.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map, HashMap, HashSet},
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    i18n::t, log, throttle,
+    walk::{Filter, Skipped, Symlinks},
+};
+
+const MAGIC: &[u8; 8] = b"FEARCHV2";
+/// The magic of archives from before copies, which are read the same way.
+const MAGIC_V1: &[u8; 8] = b"FEARCHV1";
+/// Entries claiming to be larger than this are refused rather than
+/// allocated for.
+const MAX_ENTRY_LENGTH: u64 = 64 << 10;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    kind: Kind,
+    /// Unix permission bits; elsewhere only the read-only flag survives.
+    mode: u32,
+    /// Seconds and nanoseconds since the Unix epoch.
+    modified: (u64, u32),
+}
+
+#[derive(Serialize, Deserialize)]
+enum Kind {
+    Directory,
+    File { length: u64 },
+    Symlink { target: String },
+    /// A file with the same contents as the earlier file at `of`.
+    Copy { of: String },
+}
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(MAX_ENTRY_LENGTH)
+}
+
+/// Reads as the archive of a directory tree, one file at a time.
+pub struct Reader {
+    root: PathBuf,
+    entries: std::vec::IntoIter<Entry>,
+    /// Serialized, and not yet read.
+    pending: Vec<u8>,
+    offset: usize,
+    /// The contents of the file whose entry was just read.
+    file: Option<(io::Take<fs::File>, PathBuf)>,
+    finished: bool,
+    length: u64,
+}
+
+impl Reader {
+    /// Lists the tree under `root`, or what of it `filter` takes, with its
+    /// symbolic links as `symlinks` says, and the times and permissions
+    /// normalized if `reproducible`. Sockets, FIFOs and devices are skipped
+    /// with a warning.
+    pub fn new(
+        root: &Path,
+        filter: &Filter,
+        symlinks: Symlinks,
+        reproducible: bool,
+    ) -> Result<Reader> {
+        let mut entries = Vec::new();
+        let mut listing = Listing {
+            root,
+            filter,
+            symlinks,
+            ancestors: Vec::new(),
+        };
+        if symlinks == Symlinks::Follow {
+            listing.ancestors.push(
+                fs::canonicalize(root)
+                    .with_context(|| t!("dir-list-failed", path = root.display().to_string()))?,
+            );
+        }
+        listing.list(Path::new(""), &mut entries)?;
+        deduplicate(root, &mut entries)?;
+        if reproducible {
+            normalize(&mut entries)?;
+        }
+        let mut length = MAGIC.len() as u64 + options().serialized_size(&None::<Entry>)?;
+        for entry in &entries {
+            length += options().serialized_size(&Some(entry))?;
+            if let Kind::File {
+                length: file_length,
+            } = entry.kind
+            {
+                length += file_length;
+            }
+        }
+        Ok(Reader {
+            root: root.to_owned(),
+            entries: entries.into_iter(),
+            pending: MAGIC.to_vec(),
+            offset: 0,
+            file: None,
+            finished: false,
+            length,
+        })
+    }
+
+    /// The length of the whole archive, as long as no file changes size
+    /// while it is read.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Queues the next entry, returning `false` after the end.
+    fn advance(&mut self) -> io::Result<bool> {
+        let entry = self.entries.next();
+        if entry.is_none() {
+            if self.finished {
+                return Ok(false);
+            }
+            self.finished = true;
+        }
+        self.pending = options().serialize(&entry).map_err(io::Error::other)?;
+        self.offset = 0;
+        if let Some(Entry {
+            path,
+            kind: Kind::File { length },
+            ..
+        }) = &entry
+        {
+            let path = self.root.join(path);
+            let file = fs::File::open(&path)?;
+            self.file = Some((file.take(*length), path));
+        }
+        Ok(true)
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.offset < self.pending.len() {
+                let n = buf.len().min(self.pending.len() - self.offset);
+                buf[..n].copy_from_slice(&self.pending[self.offset..self.offset + n]);
+                self.offset += n;
+                return Ok(n);
+            }
+            if let Some((file, path)) = &mut self.file {
+                let n = file.read(buf)?;
+                throttle::charge(n);
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+                if file.limit() > 0 {
+                    return Err(io::Error::other(t!(
+                        "archive-file-shrank",
+                        path = path.display().to_string()
+                    )));
+                }
+                self.file = None;
+            }
+            if !self.advance()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Turns each file in `entries` whose contents match an earlier one's into a
+/// copy of it. Only files sharing a length with another are hashed.
+fn deduplicate(root: &Path, entries: &mut [Entry]) -> Result<()> {
+    let mut lengths: HashMap<u64, usize> = HashMap::new();
+    for entry in entries.iter() {
+        if let Kind::File { length } = entry.kind {
+            *lengths.entry(length).or_default() += 1;
+        }
+    }
+    let mut seen: HashMap<(u64, [u8; 32]), String> = HashMap::new();
+    let mut copies = 0;
+    for entry in entries {
+        let Kind::File { length } = entry.kind else {
+            continue;
+        };
+        if length == 0 || lengths[&length] < 2 {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        let mut file = fs::File::open(&path)
+            .map(throttle::Reader)
+            .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut file, &mut hasher)
+            .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+        match seen.entry((length, hasher.finalize().into())) {
+            hash_map::Entry::Occupied(original) => {
+                entry.kind = Kind::Copy {
+                    of: original.get().clone(),
+                };
+                copies += 1;
+            }
+            hash_map::Entry::Vacant(slot) => {
+                slot.insert(entry.path.clone());
+            }
+        }
+    }
+    if copies > 0 {
+        log::debug(&format!("{copies} files are stored as copies of others"));
+    }
+    Ok(())
+}
+
+/// Sets the times and permissions of `entries` to those of a reproducible
+/// archive.
+fn normalize(entries: &mut [Entry]) -> Result<()> {
+    let epoch = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!(t!("source-date-epoch-invalid")))?,
+        Err(_) => 0,
+    };
+    for entry in entries {
+        entry.modified = (epoch, 0);
+        entry.mode = match entry.kind {
+            Kind::Directory => 0o755,
+            Kind::Symlink { .. } => 0o777,
+            Kind::File { .. } | Kind::Copy { .. } if entry.mode & 0o111 != 0 => 0o755,
+            Kind::File { .. } | Kind::Copy { .. } => 0o644,
+        };
+    }
+    Ok(())
+}
+
+/// What [`Reader::new`] is listing.
+struct Listing<'a> {
+    root: &'a Path,
+    filter: &'a Filter,
+    symlinks: Symlinks,
+    /// When following links, the real paths of the directory being listed
+    /// and of those it is in.
+    ancestors: Vec<PathBuf>,
+}
+
+impl Listing<'_> {
+    /// Adds what is under `root.join(relative)` to `entries`.
+    fn list(&mut self, relative: &Path, entries: &mut Vec<Entry>) -> Result<()> {
+        let dir = self.root.join(relative);
+        let mut children = fs::read_dir(&dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .with_context(|| t!("dir-list-failed", path = dir.display().to_string()))?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let relative = relative.join(child.file_name());
+            let path = self.root.join(&relative);
+            let mut metadata = fs::symlink_metadata(&path)
+                .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+            let skip = |why: Skipped| log::warn(&why.warning(&path));
+            if metadata.file_type().is_symlink() {
+                match self.symlinks {
+                    Symlinks::Skip => {
+                        if self.filter.takes(&relative) {
+                            skip(Skipped::Symlink);
+                        }
+                        continue;
+                    }
+                    Symlinks::Follow => match fs::metadata(&path) {
+                        Ok(target) => metadata = target,
+                        Err(_) => {
+                            skip(Skipped::Broken);
+                            continue;
+                        }
+                    },
+                    Symlinks::Preserve => {}
+                }
+            }
+            let mut real = None;
+            let kind = if metadata.is_dir() {
+                if self.filter.prunes(&relative) {
+                    continue;
+                }
+                if self.symlinks == Symlinks::Follow {
+                    let path = fs::canonicalize(&path)
+                        .with_context(|| t!("dir-list-failed", path = path.display().to_string()))?;
+                    if self.ancestors.contains(&path) {
+                        skip(Skipped::Loop);
+                        continue;
+                    }
+                    real = Some(path);
+                }
+                Kind::Directory
+            } else if !self.filter.takes(&relative) {
+                continue;
+            } else if metadata.is_file() {
+                Kind::File {
+                    length: metadata.len(),
+                }
+            } else if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&path)
+                    .with_context(|| t!("symlink-unreadable", path = path.display().to_string()))?;
+                Kind::Symlink {
+                    target: utf8(&target)?.to_owned(),
+                }
+            } else {
+                skip(Skipped::Special);
+                continue;
+            };
+            let is_dir = matches!(kind, Kind::Directory);
+            entries.push(Entry {
+                path: utf8(&relative)?.replace(std::path::MAIN_SEPARATOR, "/"),
+                kind,
+                mode: mode(&metadata),
+                modified: modified_since_epoch(&metadata),
+            });
+            if is_dir {
+                self.ancestors.extend(real);
+                self.list(&relative, entries)?;
+                if self.symlinks == Symlinks::Follow {
+                    self.ancestors.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn utf8(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!(t!("path-not-utf8", path = path.display().to_string())))
+}
+
+/// When the file `metadata` describes was last modified, in seconds and
+/// nanoseconds since the Unix epoch; 0 where the platform doesn't say.
+pub fn modified_since_epoch(metadata: &fs::Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or((0, 0), |since| (since.as_secs(), since.subsec_nanos()))
+}
+
+/// Reverses [`modified_since_epoch`].
+pub fn from_epoch((secs, nanos): (u64, u32)) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::new(secs, nanos)
+}
+
+#[cfg(unix)]
+pub fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+pub fn mode(metadata: &fs::Metadata) -> u32 {
+    match metadata.permissions().readonly() {
+        true => 0o444,
+        false => 0o644,
+    }
+}
+
+/// Restores the tree an archive holds into `dest`, which must not exist yet,
+/// returning the number of files. Entries that would land outside `dest` are
+/// refused. Symlinks are created last, so no entry is written through one,
+/// and permissions and directory times are set once everything is in, so a
+/// copy can still read the file it copies.
+/// With `sync`, each file is flushed to disk as it is finished.
+pub fn extract(mut input: impl Read, dest: &Path, sync: bool) -> Result<u64> {
+    let mut magic = [0; MAGIC.len()];
+    input
+        .read_exact(&mut magic)
+        .ok()
+        .filter(|()| &magic == MAGIC || &magic == MAGIC_V1)
+        .ok_or_else(|| anyhow!(t!("extract-not-archive")))?;
+    fs::create_dir(dest)
+        .with_context(|| t!("dir-create-failed", path = dest.display().to_string()))?;
+
+    let mut files = 0;
+    let mut dirs = Vec::new();
+    let mut written = Vec::new();
+    let mut extracted = HashSet::new();
+    let mut symlinks = Vec::new();
+    loop {
+        let entry: Option<Entry> = options()
+            .deserialize_from(&mut input)
+            .with_context(|| t!("archive-damaged"))?;
+        let Some(entry) = entry else { break };
+        let path = dest.join(checked_path(&entry.path)?);
+        match &entry.kind {
+            Kind::Directory => {
+                fs::create_dir(&path)
+                    .with_context(|| t!("dir-create-failed", path = path.display().to_string()))?;
+                dirs.push((path, entry));
+            }
+            Kind::File { length } => {
+                let mut file = fs::File::create(&path)
+                    .with_context(|| t!("file-create-failed", path = path.display().to_string()))?;
+                let copied = io::copy(&mut (&mut input).take(*length), &mut file)
+                    .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+                if copied < *length {
+                    bail!(t!("archive-truncated", path = entry.path.as_str()));
+                }
+                if sync {
+                    file.sync_all().with_context(|| {
+                        t!("file-sync-failed", path = path.display().to_string())
+                    })?;
+                }
+                file.set_modified(modified(&entry))
+                    .with_context(|| t!("time-restore-failed", path = path.display().to_string()))?;
+                extracted.insert(entry.path.clone());
+                written.push((path, entry));
+                files += 1;
+            }
+            Kind::Copy { of } => {
+                if !extracted.contains(of) {
+                    bail!(t!("archive-bad-copy", path = entry.path.as_str(), of = of.as_str()));
+                }
+                let original = dest.join(checked_path(of)?);
+                let mut file = fs::File::create(&path)
+                    .with_context(|| t!("file-create-failed", path = path.display().to_string()))?;
+                fs::File::open(&original)
+                    .and_then(|mut original| io::copy(&mut original, &mut file))
+                    .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+                if sync {
+                    file.sync_all().with_context(|| {
+                        t!("file-sync-failed", path = path.display().to_string())
+                    })?;
+                }
+                file.set_modified(modified(&entry))
+                    .with_context(|| t!("time-restore-failed", path = path.display().to_string()))?;
+                written.push((path, entry));
+                files += 1;
+            }
+            Kind::Symlink { .. } => symlinks.push((path, entry)),
+        }
+    }
+
+    for (path, entry) in &written {
+        restore_permissions(path, entry)?;
+    }
+    for (path, entry) in symlinks {
+        let Kind::Symlink { target } = &entry.kind else {
+            unreachable!("only symlinks are deferred here")
+        };
+        // An earlier symlink could otherwise redirect this one out of `dest`.
+        let mut parent = path.parent();
+        while let Some(dir) = parent.filter(|dir| *dir != dest) {
+            if fs::symlink_metadata(dir).is_ok_and(|m| m.file_type().is_symlink()) {
+                bail!(t!("archive-inside-symlink", path = entry.path.as_str()));
+            }
+            parent = dir.parent();
+        }
+        symlink(target, &path)
+            .with_context(|| t!("symlink-create-failed", path = path.display().to_string()))?;
+    }
+    // Innermost first, as restoring a directory's time is undone by changing
+    // what it contains.
+    for (path, entry) in dirs.iter().rev() {
+        #[cfg(unix)]
+        fs::File::open(path)
+            .and_then(|dir| dir.set_modified(modified(entry)))
+            .with_context(|| t!("time-restore-failed", path = path.display().to_string()))?;
+        restore_permissions(path, entry)?;
+    }
+    Ok(files)
+}
+
+/// `path` from an archive, or a name a file records, as a relative path with
+/// no `..`, so it stays within the destination.
+pub fn checked_path(path: &str) -> Result<PathBuf> {
+    let relative: PathBuf = path.split('/').collect();
+    let normal = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if path.is_empty() || !normal {
+        bail!(t!("archive-unsafe-path", path = path));
+    }
+    Ok(relative)
+}
+
+fn modified(entry: &Entry) -> SystemTime {
+    from_epoch(entry.modified)
+}
+
+fn restore_permissions(path: &Path, entry: &Entry) -> Result<()> {
+    fs::set_permissions(path, permissions(path, entry.mode)?)
+        .with_context(|| t!("permissions-restore-failed", path = path.display().to_string()))
+}
+
+#[cfg(unix)]
+pub fn permissions(_path: &Path, mode: u32) -> Result<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn permissions(path: &Path, mode: u32) -> Result<fs::Permissions> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    Ok(permissions)
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_encryptor::{DecryptError, Encryptor, Kdf};
+
+    const KDF: Kdf = Kdf::Pbkdf2 { iterations: 1 };
+
+    /// `a.txt`, its copy `sub/b.txt`, `sub/c.txt`, and on Unix a link to it.
+    fn tree(root: &Path) {
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "same").unwrap();
+        fs::write(root.join("sub/b.txt"), "same").unwrap();
+        fs::write(root.join("sub/c.txt"), "other").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("c.txt", root.join("sub/link")).unwrap();
+    }
+
+    fn archive(root: &Path, reproducible: bool) -> Vec<u8> {
+        let mut reader =
+            Reader::new(root, &Filter::default(), Symlinks::Preserve, reproducible).unwrap();
+        let length = reader.length();
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes.len() as u64, length);
+        bytes
+    }
+
+    #[test]
+    fn encrypted_trees_extract_as_they_were() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("tree");
+        tree(&root);
+        let mut encrypted = Vec::new();
+        let reader = Reader::new(&root, &Filter::default(), Symlinks::Preserve, false).unwrap();
+        let mut keys = Encryptor::with_kdf("pw", KDF);
+        keys.encrypt_reader_to_writer(reader, &mut encrypted).unwrap();
+
+        let wrong = Encryptor::new("wrong").decrypt(&encrypted).unwrap_err();
+        assert_eq!(wrong.downcast_ref(), Some(&DecryptError::WrongKey));
+        let mut altered = encrypted.clone();
+        let middle = altered.len() / 2;
+        altered[middle] ^= 1;
+        assert!(Encryptor::new("pw").decrypt(&altered).is_err());
+
+        let plaintext = Encryptor::new("pw").decrypt(&encrypted).unwrap();
+        let out = dir.path().join("out");
+        assert_eq!(extract(plaintext.as_slice(), &out, false).unwrap(), 3);
+        for name in ["a.txt", "sub/b.txt", "sub/c.txt"] {
+            assert_eq!(fs::read(out.join(name)).unwrap(), fs::read(root.join(name)).unwrap());
+            let [original, extracted] = [&root, &out].map(|dir| {
+                fs::metadata(dir.join(name)).unwrap().modified().unwrap()
+            });
+            assert_eq!(original, extracted);
+        }
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(out.join("sub/link")).unwrap(), Path::new("c.txt"));
+        // The copy is stored once.
+        assert_eq!(plaintext.windows(4).filter(|w| w == b"same").count(), 1);
+        // Extracting never overwrites.
+        assert!(extract(plaintext.as_slice(), &out, false).is_err());
+    }
+
+    #[test]
+    fn reproducible_archives_are_equal_for_equal_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let [first, second] = ["first", "second"].map(|name| dir.path().join(name));
+        tree(&first);
+        tree(&second);
+        fs::File::options()
+            .write(true)
+            .open(second.join("a.txt"))
+            .and_then(|file| file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(7)))
+            .unwrap();
+        assert_eq!(archive(&first, true), archive(&second, true));
+        assert_ne!(archive(&first, false), archive(&second, false));
+    }
+
+    /// The layout from before copies, which extracts as it always did.
+    #[test]
+    fn version_1_archives_still_extract() {
+        let mut bytes = MAGIC_V1.to_vec();
+        let entry = Entry {
+            path: "a.txt".to_owned(),
+            kind: Kind::File { length: 5 },
+            mode: 0o644,
+            modified: (7, 0),
+        };
+        bytes.extend(options().serialize(&Some(&entry)).unwrap());
+        bytes.extend_from_slice(b"hello");
+        bytes.extend(options().serialize(&None::<Entry>).unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        assert_eq!(extract(bytes.as_slice(), &out, false).unwrap(), 1);
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn entries_outside_the_destination_or_damaged_are_refused() {
+        for path in ["../a.txt", "sub/../../a.txt", "", "."] {
+            assert!(checked_path(path).is_err(), "{path}");
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes = MAGIC.to_vec();
+        let copy = Entry {
+            path: "b.txt".to_owned(),
+            kind: Kind::Copy {
+                of: "a.txt".to_owned(),
+            },
+            mode: 0o644,
+            modified: (7, 0),
+        };
+        bytes.extend(options().serialize(&Some(&copy)).unwrap());
+        assert!(extract(bytes.as_slice(), &dir.path().join("copy"), false).is_err());
+        let root = dir.path().join("tree");
+        tree(&root);
+        let cut = archive(&root, false);
+        assert!(extract(&cut[..cut.len() - 3], &dir.path().join("cut"), false).is_err());
+        assert!(extract(&b"FEARCHV9"[..], &dir.path().join("other"), false).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! ASCII armor for the native format: the file's bytes in base64, wrapped in
+//! lines of [`LINE_LENGTH`] characters unless asked otherwise, between
+//! marker lines, so it survives being pasted into a chat, an email, a ticket
+//! or a YAML file:
+//!
+//! ```text
+//! -----BEGIN FILE-ENCRYPTOR MESSAGE-----
+//! RkVOQwkAAV...
+//! -----END FILE-ENCRYPTOR MESSAGE-----
+//! ```
+//!
+//! The armored bytes are the whole file, header included, so the cipher and
+//! KDF parameters travel with it as they do in binary.
+
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+
+use file_encryptor::i18n::t;
+
+pub const BEGIN: &str = "-----BEGIN FILE-ENCRYPTOR MESSAGE-----";
+pub const END: &str = "-----END FILE-ENCRYPTOR MESSAGE-----";
+pub const LINE_LENGTH: usize = 64;
+
+/// Whether `file_bytes` are text with an armored message somewhere in it,
+/// as [`dearmor`] looks for one. Native files are binary, so never text.
+pub fn is_armored(file_bytes: &[u8]) -> bool {
+    std::str::from_utf8(file_bytes).is_ok_and(|text| text.lines().any(|line| line.trim() == BEGIN))
+}
+
+/// `data` armored in base64 lines of `line_length` characters, ending with a
+/// newline.
+pub fn armor(data: &[u8], line_length: usize) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let line_length = line_length.max(1);
+    let lines = encoded.len().div_ceil(line_length);
+    let mut out = String::with_capacity(encoded.len() + lines + BEGIN.len() + END.len() + 2);
+    out.push_str(BEGIN);
+    out.push('\n');
+    // base64 output is ASCII, so byte chunks are character chunks.
+    for line in encoded.as_bytes().chunks(line_length) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push('\n');
+    }
+    out.push_str(END);
+    out.push('\n');
+    out
+}
+
+/// The bytes of the first armored message in `text`. Text around it, such as
+/// the rest of an email, is ignored, as is the way its lines were rewrapped.
+pub fn dearmor(text: &str) -> Result<Vec<u8>> {
+    let mut lines = text.lines().map(str::trim);
+    lines
+        .by_ref()
+        .find(|line| *line == BEGIN)
+        .ok_or_else(|| anyhow!(t!("armor-no-begin", line = BEGIN)))?;
+    let mut encoded = String::new();
+    let mut ended = false;
+    for line in lines {
+        if line == END {
+            ended = true;
+            break;
+        }
+        encoded.extend(line.chars().filter(|c| !c.is_whitespace()));
+    }
+    if !ended {
+        bail!(t!("armor-no-end", line = END));
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| anyhow!(t!("armor-not-base64", error = e.to_string())))
+}
@@ -0,0 +1,175 @@
+//! Increasing delays between failed decryptions of the same file, to slow
+//! down someone guessing passwords at the keyboard.
+//!
+//! Failures are recorded by ciphertext digest in the user's state directory,
+//! so renaming or copying a file does not reset its count. Whoever can delete
+//! that state can reset it, so this only raises the cost of interactive
+//! guessing; it does nothing against an offline attack on a copy.
+//!
+//! The state is replaced by renaming a new copy over it, under an advisory
+//! lock, so concurrent runs don't lose each other's failures and a crash
+//! can't leave it half written. State that still can't be read is treated as
+//! owing the longest delay, rather than as a clean slate.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{i18n::t, log, report::ErrorKind, state_file};
+
+const STATE_FILE: &str = "decrypt-failures";
+const MAX_DELAY_SECS: u64 = 300;
+/// Records are dropped this long after their last failure.
+const FORGET_AFTER_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Failures {
+    files: HashMap<String, Record>,
+}
+
+/// The state file as read: unreadable or undecodable state is `Corrupt`.
+enum State {
+    Read(Failures),
+    Corrupt,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    count: u32,
+    last_failure: u64,
+}
+
+/// A pending decryption attempt; report its outcome with [`Attempt::failed`]
+/// or [`Attempt::succeeded`].
+pub struct Attempt {
+    digest: String,
+}
+
+/// Waits out any delay owed from earlier failures on `ciphertext`.
+pub fn begin(ciphertext: &[u8]) -> Attempt {
+    let digest = blake3::hash(ciphertext).to_hex().to_string();
+    let Some(path) = state_file(STATE_FILE) else {
+        return Attempt { digest };
+    };
+    match load(&path) {
+        State::Read(failures) => {
+            if let Some(record) = failures.files.get(&digest) {
+                let ready_at = record.last_failure + delay_secs(record.count);
+                let now = now();
+                if ready_at > now {
+                    log::warn(&t!(
+                        "decrypt-waiting",
+                        seconds = ready_at - now,
+                        count = record.count
+                    ));
+                    thread::sleep(Duration::from_secs(ready_at - now));
+                }
+            }
+        }
+        State::Corrupt => {
+            log::warn(&t!(
+                "decrypt-state-unreadable",
+                path = path.display().to_string(),
+                seconds = MAX_DELAY_SECS
+            ));
+            thread::sleep(Duration::from_secs(MAX_DELAY_SECS));
+        }
+    }
+    Attempt { digest }
+}
+
+impl Attempt {
+    /// Counts `error` against the file if the key was wrong. A damaged file
+    /// says nothing about anyone guessing, so it isn't counted.
+    pub fn failed(self, error: &anyhow::Error) {
+        if !matches!(ErrorKind::of(error), ErrorKind::WrongKey) {
+            return;
+        }
+        update(|state| {
+            // Unreadable state stays as it is, owing the longest delay.
+            let State::Read(mut failures) = state else {
+                return None;
+            };
+            let record = failures.files.entry(self.digest).or_insert(Record {
+                count: 0,
+                last_failure: 0,
+            });
+            record.count = record.count.saturating_add(1);
+            record.last_failure = now();
+            Some(failures)
+        });
+    }
+
+    /// Clears the file's failures. The right key also replaces unreadable
+    /// state, which would otherwise delay every attempt.
+    pub fn succeeded(self) {
+        update(|state| match state {
+            State::Read(mut failures) => failures.files.remove(&self.digest).map(|_| failures),
+            State::Corrupt => Some(Failures::default()),
+        });
+    }
+}
+
+/// 1s after the first failure, doubling up to [`MAX_DELAY_SECS`].
+fn delay_secs(count: u32) -> u64 {
+    match count {
+        0 => 0,
+        n => (1u64 << (n - 1).min(16)).min(MAX_DELAY_SECS),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// Writing the state is best effort: a state file that can't be written must
+// not stop a legitimate decryption.
+fn load(path: &Path) -> State {
+    match fs::read(path) {
+        Ok(bytes) => bincode::deserialize(&bytes).map_or(State::Corrupt, State::Read),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => State::Read(Failures::default()),
+        Err(_) => State::Corrupt,
+    }
+}
+
+/// Replaces the state with what `change` makes of it, if anything, holding
+/// the lock from reading it to renaming the new copy into place.
+fn update(change: impl FnOnce(State) -> Option<Failures>) {
+    let Some(path) = state_file(STATE_FILE) else {
+        return;
+    };
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    let lock = fs::create_dir_all(dir).and_then(|()| {
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.with_extension("lock"))
+    });
+    let Ok(lock) = lock else {
+        return;
+    };
+    if lock.lock().is_err() {
+        return;
+    }
+    let Some(mut failures) = change(load(&path)) else {
+        return;
+    };
+    let now = now();
+    failures
+        .files
+        .retain(|_, record| now.saturating_sub(record.last_failure) < FORGET_AFTER_SECS);
+    if let Ok(bytes) = bincode::serialize(&failures) {
+        let temp = path.with_extension("tmp");
+        let _ = fs::write(&temp, bytes).and_then(|()| fs::rename(&temp, &path));
+    }
+}
@@ -0,0 +1,166 @@
+//! `bench`: how fast this machine derives keys, runs each cipher and
+//! encrypts end to end, to choose `--cipher` and `--kdf` settings by, and to
+//! tune the chunk length of `encrypt --stream` with.
+//!
+//! Everything runs in memory on data of `--size` bytes, so the disk's speed
+//! doesn't count. The ciphers are timed on their own with a fixed key and
+//! nonce, which is only safe because nothing here is kept; the end-to-end
+//! runs go through `encrypt --stream`, checksum included, once the key is
+//! derived.
+
+use aes_gcm::{
+    aead::{AeadInPlace, Key, KeyInit, Nonce},
+    Aes256Gcm,
+};
+use anyhow::{anyhow, Result};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use clap::Args;
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use file_encryptor::{entropy, i18n::t, stream, Cipher, Encryptor, Kdf};
+
+use crate::{config, human_size, inspect, log, parse_size, CipherName, KdfOptions};
+
+/// The chunk lengths timed when no --chunk-size is given.
+const CHUNK_LENGTHS: [u32; 5] = [16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20];
+
+#[derive(Args)]
+pub struct BenchOptions {
+    /// Data to encrypt in each measurement (e.g. 64M, 1G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "64M")]
+    size: u64,
+    /// Cipher of the end-to-end runs [default: the config file's, or as for encrypt]
+    #[arg(long, value_enum)]
+    cipher: Option<CipherName>,
+    /// Chunk length of an end-to-end run; repeat it to compare several [default: 16K to 4M]
+    #[arg(long = "chunk-size", value_name = "SIZE", value_parser = parse_size)]
+    chunk_sizes: Vec<u64>,
+    #[command(flatten)]
+    kdf: KdfOptions,
+}
+
+pub fn run(options: &BenchOptions) -> Result<()> {
+    let size = usize::try_from(options.size)
+        .ok()
+        .filter(|&size| size > 0)
+        .ok_or_else(|| anyhow!(t!("bench-size-zero")))?;
+    let chunk_lengths = match options.chunk_sizes.as_slice() {
+        [] => CHUNK_LENGTHS.to_vec(),
+        sizes => sizes
+            .iter()
+            .map(|&size| {
+                u32::try_from(size)
+                    .ok()
+                    .filter(|length| (1..=stream::MAX_CHUNK_LENGTH).contains(length))
+                    .ok_or_else(|| {
+                        anyhow!(t!(
+                            "bench-chunk-size-range",
+                            max = human_size(stream::MAX_CHUNK_LENGTH.into())
+                        ))
+                    })
+            })
+            .collect::<Result<_>>()?,
+    };
+    if cfg!(debug_assertions) {
+        log::warn(&t!("bench-debug-build"));
+    }
+
+    println!("{}", t!("bench-kdf"));
+    let mut kdfs = vec![Kdf::default(), Kdf::ARGON2ID];
+    if !options.kdf.is_empty() {
+        let kdf = options.kdf.kdf()?;
+        if !kdfs.contains(&kdf) {
+            kdfs.push(kdf);
+        }
+    }
+    for kdf in kdfs {
+        let elapsed = kdf.time()?;
+        println!(
+            "  {:<58} {:>8.0} ms",
+            inspect::kdf_description(kdf),
+            elapsed.as_secs_f64() * 1e3
+        );
+    }
+
+    let mut data = vec![0; size];
+    entropy::fill(&mut data)?;
+    println!();
+    println!(
+        "{}",
+        t!(
+            "bench-cipher",
+            size = human_size(options.size),
+            chunk = human_size(stream::CHUNK_LENGTH.into())
+        )
+    );
+    for (name, elapsed) in [
+        ("AES-256-GCM", seal_all::<Aes256Gcm>(&mut data)?),
+        (
+            "ChaCha20-Poly1305",
+            seal_all::<ChaCha20Poly1305>(&mut data)?,
+        ),
+        (
+            "XChaCha20-Poly1305",
+            seal_all::<XChaCha20Poly1305>(&mut data)?,
+        ),
+    ] {
+        println!("  {name:<20} {}", rate(options.size, elapsed));
+    }
+
+    let cipher: Cipher = options
+        .cipher
+        .or(config::get().cipher)
+        .unwrap_or_else(CipherName::for_this_cpu)
+        .into();
+    // One iteration: the key is derived once, before the timing starts.
+    let mut keys =
+        Encryptor::with_kdf("benchmark", Kdf::Pbkdf2 { iterations: 1 }).with_cipher(cipher);
+    stream::encrypt(&mut keys, io::empty(), io::sink())?;
+    println!();
+    println!(
+        "{}",
+        t!(
+            "bench-stream",
+            cipher = inspect::cipher_name(cipher),
+            size = human_size(options.size)
+        )
+    );
+    for chunk_length in chunk_lengths {
+        let started = Instant::now();
+        stream::encrypt_chunked(&mut keys, chunk_length, &data[..], io::sink())?;
+        let elapsed = started.elapsed();
+        let default = match chunk_length == stream::chunk_length_for(Some(options.size)) {
+            true => t!("bench-picked"),
+            false => String::new(),
+        };
+        println!(
+            "  {:<20} {}",
+            format!("{}{default}", human_size(chunk_length.into())),
+            rate(options.size, elapsed)
+        );
+    }
+    Ok(())
+}
+
+/// Seals `data` in place, a chunk at a time, as `encrypt --stream` would.
+fn seal_all<A: AeadInPlace + KeyInit>(data: &mut [u8]) -> Result<Duration> {
+    let cipher = A::new(&Key::<A>::default());
+    let nonce = Nonce::<A>::default();
+    let started = Instant::now();
+    for chunk in data.chunks_mut(stream::CHUNK_LENGTH as usize) {
+        cipher
+            .encrypt_in_place_detached(&nonce, b"", chunk)
+            .map_err(|e| anyhow!(e))?;
+    }
+    Ok(started.elapsed())
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> String {
+    format!(
+        "{:>10.1} MiB/s",
+        bytes as f64 / elapsed.as_secs_f64().max(1e-9) / f64::from(1 << 20)
+    )
+}
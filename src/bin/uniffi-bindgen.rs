@@ -0,0 +1,6 @@
+//! Generates the Kotlin and Swift bindings of the `uniffi` feature from the
+//! built library, as `bindings/README.md` describes.
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,104 @@
+//! `completions SHELL`: a completion script for bash, zsh, fish, elvish or
+//! PowerShell, generated by `clap_complete` from the command line definition
+//! itself so it never falls behind the options.
+//!
+//! Every script completes the subcommands and their aliases, the options of
+//! the subcommand being typed, the values of options that take one of a list
+//! (such as `--format`) and file names for the rest.
+
+use clap::Command;
+pub use clap_complete::Shell;
+
+/// Prints the completion script for `shell` on standard output.
+pub fn print(shell: Shell, command: &mut Command) {
+    print!("{}", script(shell, command));
+}
+
+fn script(shell: Shell, command: &mut Command) -> String {
+    let name = command.get_name().to_owned();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, command, &name, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    match shell {
+        // The bash script's case labels spell the `-` in the command's own
+        // name differently from the paths it matches them against, so no
+        // subcommand option would ever complete.
+        Shell::Bash => script.replace(
+            &format!("{}__subcmd__", name.replace('-', "__subcmd__")),
+            &format!("{}__subcmd__", name.replace('-', "__")),
+        ),
+        _ => script,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use std::{
+        io::{self, Write},
+        process::{Command as Process, Stdio},
+    };
+
+    /// Runs `program` with `args` on `script` as standard input, or `None`
+    /// when the shell isn't installed.
+    fn run(program: &str, args: &[&str], script: &str) -> Option<std::process::Output> {
+        let child = Process::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            child => child.unwrap(),
+        };
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        Some(child.wait_with_output().unwrap())
+    }
+
+    #[test]
+    fn scripts_parse_in_their_shells() {
+        for (shell, program, args) in [
+            (Shell::Bash, "bash", &["-n"][..]),
+            (Shell::Zsh, "zsh", &["-n"][..]),
+            (Shell::Fish, "fish", &["--no-execute"][..]),
+        ] {
+            if let Some(output) = run(program, args, &script(shell, &mut crate::Cli::command())) {
+                assert!(
+                    output.status.success(),
+                    "{program}: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bash_completes_subcommands_options_and_values() {
+        let script = script(Shell::Bash, &mut crate::Cli::command());
+        let complete = |words: &str| {
+            let test = format!(
+                "{script}\nCOMP_WORDS=({words}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))\n\
+                 _file__encryptor file-encryptor \"${{COMP_WORDS[COMP_CWORD]}}\" \
+                 \"${{COMP_WORDS[COMP_CWORD-1]}}\"; printf '%s\\n' \"${{COMPREPLY[@]}}\"\n"
+            );
+            let output = run("bash", &[], &test)?;
+            assert!(output.status.success());
+            Some(String::from_utf8(output.stdout).unwrap())
+        };
+        let Some(subcommands) = complete("file-encryptor enc") else {
+            return;
+        };
+        assert_eq!(subcommands, "encrypt\nencrypt-text\n");
+        let options = complete("file-encryptor encrypt --allow-d").unwrap();
+        assert_eq!(options, "--allow-double-encrypt\n");
+        let formats = complete("file-encryptor encrypt --format ag").unwrap();
+        assert_eq!(formats, "age\n");
+    }
+}
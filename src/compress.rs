@@ -0,0 +1,228 @@
+//! Compression of the plaintext before it is encrypted, in one piece for the
+//! single-message format and as it streams for the chunked one.
+//!
+//! `new` only fails if the zstd library can't set up its state, as when out
+//! of memory.
+
+use anyhow::{anyhow, Result};
+use flate2::{read::GzEncoder, write::GzDecoder};
+use std::io::{self, Read, Write};
+
+use crate::{header::Compression, i18n::t};
+
+/// zstd's own default, which compresses about as well as gzip's at several
+/// times the speed.
+const ZSTD_LEVEL: i32 = 3;
+
+pub(crate) fn compress(compression: Compression, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    Reader::new(compression, plaintext)?.read_to_end(&mut compressed)?;
+    Ok(compressed)
+}
+
+pub(crate) fn decompress(compression: Compression, payload: Vec<u8>) -> Result<Vec<u8>> {
+    let mut inflater = Inflater::new(compression)?;
+    let mut plaintext = inflater.push(payload)?;
+    plaintext.extend(inflater.finish()?);
+    Ok(plaintext)
+}
+
+/// Yields what `input` yields, compressed.
+pub(crate) enum Reader<R> {
+    None(R),
+    /// Boxed, as the compressor's state is far larger than most readers.
+    Gzip(Box<GzEncoder<R>>),
+    Zstd(Box<zstd::stream::read::Encoder<'static, io::BufReader<R>>>),
+}
+
+impl<R: Read> Reader<R> {
+    pub(crate) fn new(compression: Compression, input: R) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Reader::None(input),
+            Compression::Gzip => Reader::Gzip(Box::new(GzEncoder::new(
+                input,
+                flate2::Compression::default(),
+            ))),
+            Compression::Zstd => Reader::Zstd(Box::new(zstd::stream::read::Encoder::new(
+                input, ZSTD_LEVEL,
+            )?)),
+        })
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Reader::None(input) => input.read(buf),
+            Reader::Gzip(input) => input.read(buf),
+            Reader::Zstd(input) => input.read(buf),
+        }
+    }
+}
+
+/// Compresses a plaintext handed over a piece at a time, for the async
+/// encryptor, which can't lend a [`Reader`] its input.
+#[cfg(feature = "tokio")]
+pub(crate) enum Deflater {
+    None,
+    Gzip(Box<flate2::write::GzEncoder<Vec<u8>>>),
+    Zstd(Box<zstd::stream::write::Encoder<'static, Vec<u8>>>),
+}
+
+#[cfg(feature = "tokio")]
+impl Deflater {
+    pub(crate) fn new(compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Deflater::None,
+            Compression::Gzip => Deflater::Gzip(Box::new(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            ))),
+            Compression::Zstd => Deflater::Zstd(Box::new(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                ZSTD_LEVEL,
+            )?)),
+        })
+    }
+
+    /// Appends what `piece` compresses to, which may be nothing yet, to
+    /// `output`.
+    pub(crate) fn push(&mut self, piece: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Deflater::None => output.extend_from_slice(piece),
+            Deflater::Gzip(encoder) => {
+                encoder.write_all(piece)?;
+                output.append(encoder.get_mut());
+            }
+            Deflater::Zstd(encoder) => {
+                encoder.write_all(piece)?;
+                output.append(encoder.get_mut());
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the rest of the compressed plaintext to `output`, once all of it
+    /// has been pushed.
+    pub(crate) fn finish(&mut self, output: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Deflater::None => {}
+            Deflater::Gzip(encoder) => {
+                encoder.try_finish()?;
+                output.append(encoder.get_mut());
+            }
+            Deflater::Zstd(encoder) => {
+                encoder.do_finish()?;
+                output.append(encoder.get_mut());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses a payload handed over a piece at a time.
+pub(crate) enum Inflater {
+    None,
+    Gzip(Box<GzDecoder<Vec<u8>>>),
+    Zstd(Box<zstd::stream::write::Decoder<'static, Vec<u8>>>),
+}
+
+impl Inflater {
+    pub(crate) fn new(compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Inflater::None,
+            Compression::Gzip => Inflater::Gzip(Box::new(GzDecoder::new(Vec::new()))),
+            Compression::Zstd => {
+                Inflater::Zstd(Box::new(zstd::stream::write::Decoder::new(Vec::new())?))
+            }
+        })
+    }
+
+    /// The plaintext `piece` completes, which may be none yet.
+    pub(crate) fn push(&mut self, piece: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Inflater::None => Ok(piece),
+            Inflater::Gzip(decoder) => {
+                decoder.write_all(&piece).map_err(corrupt)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Inflater::Zstd(decoder) => {
+                decoder.write_all(&piece).map_err(corrupt)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+
+    /// The rest of the plaintext, once the whole payload has been pushed.
+    pub(crate) fn finish(&mut self) -> Result<Vec<u8>> {
+        match self {
+            Inflater::None => Ok(Vec::new()),
+            Inflater::Gzip(decoder) => {
+                decoder.try_finish().map_err(corrupt)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Inflater::Zstd(decoder) => {
+                decoder.flush().map_err(corrupt)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+}
+
+/// Only a faulty writer produces authenticated data that doesn't decompress.
+fn corrupt(e: io::Error) -> anyhow::Error {
+    anyhow!(t!("decompress-failed", error = e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encryptor;
+
+    const ALL: [Compression; 3] = [Compression::None, Compression::Gzip, Compression::Zstd];
+
+    /// Compressible, and long enough for several chunks once compressed.
+    fn plaintext() -> Vec<u8> {
+        (0..1 << 20)
+            .map(|i: u32| (i % 17 + i / 4096 % 5) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn every_compression_round_trips() {
+        let plaintext = plaintext();
+        for compression in ALL {
+            let keys = || Encryptor::from_key_file([7; 32]).with_compression(compression);
+            let file = keys().encrypt(&plaintext).unwrap();
+            assert_eq!(keys().decrypt(&file).unwrap(), plaintext, "{compression:?}");
+            if compression != Compression::None {
+                assert!(file.len() < plaintext.len() / 4, "{compression:?}");
+            }
+
+            let mut chunked = Vec::new();
+            keys()
+                .encrypt_reader_to_writer(&plaintext[..], &mut chunked)
+                .unwrap();
+            let mut decrypted = Vec::new();
+            keys()
+                .decrypt_reader_to_writer(&chunked[..], &mut decrypted)
+                .unwrap();
+            assert_eq!(decrypted, plaintext, "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn pieces_inflate_to_the_whole() {
+        let plaintext = plaintext();
+        for compression in ALL {
+            let compressed = compress(compression, &plaintext).unwrap();
+            let mut inflater = Inflater::new(compression).unwrap();
+            let mut inflated = Vec::new();
+            for piece in compressed.chunks(1000) {
+                inflated.extend(inflater.push(piece.to_vec()).unwrap());
+            }
+            inflated.extend(inflater.finish().unwrap());
+            assert_eq!(inflated, plaintext, "{compression:?}");
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! The user's defaults for `encrypt`, `decrypt` and `rekey`, read from
+//! `~/.config/file-encryptor/config.toml` (see [`config_path`]). The file
+//! is a flat list of TOML `key = value` lines:
+//!
+//! ```toml
+//! cipher = "xchacha20poly1305"
+//! compress = "gzip"
+//! kdf = "argon2id"
+//! argon2_memory = "256M"
+//! argon2_iterations = 4
+//! output_dir = "~/encrypted"
+//! jobs = 4
+//! ```
+//!
+//! The keys are the long options, with `_` for `-`, plus `output_dir` for
+//! where `encrypt` writes when given no OUTPUT; their values are checked like
+//! the options'. Any option on the command line wins over its key. The KDF
+//! keys (`kdf`, `argon2_*` and `kdf_time`) are one setting: a command line
+//! with any KDF option ignores all of them. A file that can't be read or
+//! parsed is an error rather than no defaults, so a typo can't go unnoticed.
+//!
+//! After the defaults, `[schedule.NAME]` tables define jobs that
+//! `serve --grpc --job-root DIR` runs at the times of a [`Cron`] expression,
+//! with paths relative to DIR as for `SubmitJob`:
+//!
+//! ```toml
+//! [schedule.nightly]
+//! cron = "30 2 * * *"
+//! input = "documents"
+//! output = "backup/documents"
+//! password_file = "/etc/file-encryptor/backup.pass"
+//! keep_going = true
+//! on_failure = "mail -s 'backup failed' root < /dev/null"
+//! ```
+//!
+//! `operation` is `encrypt` (the default) or `decrypt`, and `all_or_nothing`
+//! and `keep_going` are as for `SubmitJob`. `on_failure` is a shell command
+//! run when a job fails, with the schedule's name and the error in
+//! `FILE_ENCRYPTOR_SCHEDULE` and `FILE_ENCRYPTOR_ERROR`.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use file_encryptor::i18n::t;
+
+use crate::{
+    cron::Cron, parse_duration, parse_size, CipherName, CompressionName, KdfName, KdfOptions,
+};
+
+const CONFIG_ENV: &str = "FILE_ENCRYPTOR_CONFIG";
+
+#[derive(Default)]
+pub struct Config {
+    pub cipher: Option<CipherName>,
+    pub compress: Option<CompressionName>,
+    pub kdf: KdfOptions,
+    pub output_dir: Option<PathBuf>,
+    pub jobs: Option<NonZeroUsize>,
+    pub schedules: Vec<Schedule>,
+}
+
+/// A job of a `[schedule.NAME]` table.
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct Schedule {
+    pub name: String,
+    pub cron: Cron,
+    pub operation: ScheduleOperation,
+    /// Relative to the job root.
+    pub input: String,
+    pub output: String,
+    pub password_file: PathBuf,
+    pub all_or_nothing: bool,
+    pub keep_going: bool,
+    pub on_failure: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub enum ScheduleOperation {
+    Encrypt,
+    Decrypt,
+}
+
+/// A `[schedule.NAME]` table being read, with the line it starts on.
+struct Table {
+    line: usize,
+    name: String,
+    cron: Option<Cron>,
+    operation: ScheduleOperation,
+    input: Option<String>,
+    output: Option<String>,
+    password_file: Option<PathBuf>,
+    all_or_nothing: bool,
+    keep_going: bool,
+    on_failure: Option<String>,
+}
+
+impl Table {
+    fn new(line: usize, name: &str) -> Table {
+        Table {
+            line,
+            name: name.to_owned(),
+            cron: None,
+            operation: ScheduleOperation::Encrypt,
+            input: None,
+            output: None,
+            password_file: None,
+            all_or_nothing: false,
+            keep_going: false,
+            on_failure: None,
+        }
+    }
+
+    fn set(
+        &mut self,
+        key: &str,
+        value: &str,
+        invalid: impl Fn(String) -> anyhow::Error,
+    ) -> Result<()> {
+        let flag = |value: &str| {
+            value
+                .parse()
+                .map_err(|_| invalid(t!("config-not-bool", value = value)))
+        };
+        match key {
+            "cron" => self.cron = Some(Cron::parse(value).map_err(&invalid)?),
+            "operation" => {
+                self.operation = ScheduleOperation::from_str(value, true).map_err(&invalid)?
+            }
+            "input" => self.input = Some(value.to_owned()),
+            "output" => self.output = Some(value.to_owned()),
+            "password_file" => self.password_file = Some(expand_home(value)),
+            "all_or_nothing" => self.all_or_nothing = flag(value)?,
+            "keep_going" => self.keep_going = flag(value)?,
+            "on_failure" => self.on_failure = Some(value.to_owned()),
+            _ => return Err(invalid(t!("config-unknown-schedule-key"))),
+        }
+        Ok(())
+    }
+
+    fn finish(self, path: &Path) -> Result<Schedule> {
+        let at = |message: &str| {
+            anyhow!(
+                "{}:{}: schedule {}: {message}",
+                path.display(),
+                self.line,
+                self.name
+            )
+        };
+        if self.all_or_nothing && self.keep_going {
+            return Err(at("all_or_nothing and keep_going can't both be set"));
+        }
+        let (Some(cron), Some(input), Some(output), Some(password_file)) =
+            (self.cron, self.input, self.output, self.password_file)
+        else {
+            return Err(at("cron, input, output and password_file must all be set"));
+        };
+        Ok(Schedule {
+            name: self.name,
+            cron,
+            operation: self.operation,
+            input,
+            output,
+            password_file,
+            all_or_nothing: self.all_or_nothing,
+            keep_going: self.keep_going,
+            on_failure: self.on_failure,
+        })
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// `FILE_ENCRYPTOR_CONFIG` if set, else `config.toml` in the `file-encryptor`
+/// directory of the user's configuration directory (`~/.config` on Linux).
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        return Some(path.into());
+    }
+    dirs::config_dir().map(|dir| dir.join("file-encryptor").join("config.toml"))
+}
+
+/// Reads the configuration file for [`get`]; without one every default is
+/// the built-in one.
+pub fn load() -> Result<()> {
+    let config = match config_path() {
+        Some(path) => read(&path)?,
+        None => Config::default(),
+    };
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+fn read(path: &Path) -> Result<Config> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| t!("config-unreadable", path = path.display().to_string()))
+        }
+    };
+    let mut config = Config::default();
+    let mut table: Option<Table> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = |message: String| anyhow!("{}:{}: {message}", path.display(), number + 1);
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .and_then(|header| header.trim().strip_prefix("schedule."))
+                .filter(|name| {
+                    !name.is_empty()
+                        && name
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                })
+                .ok_or_else(|| at(t!("config-unknown-table")))?;
+            if config.schedules.iter().any(|s| s.name == name)
+                || table.as_ref().is_some_and(|table| table.name == name)
+            {
+                return Err(at(t!("config-schedule-twice", name = name)));
+            }
+            if let Some(table) = table.replace(Table::new(number + 1, name)) {
+                config.schedules.push(table.finish(path)?);
+            }
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at(t!("config-expected-key-value")))?;
+        let key = key.trim();
+        let value = value_of(value.trim()).map_err(at)?;
+        let invalid = |e: String| at(format!("{key}: {e}"));
+        if let Some(table) = &mut table {
+            table.set(key, value, invalid)?;
+            continue;
+        }
+        match key {
+            "cipher" => config.cipher = Some(CipherName::from_str(value, true).map_err(invalid)?),
+            "compress" => {
+                config.compress = Some(CompressionName::from_str(value, true).map_err(invalid)?)
+            }
+            "kdf" => config.kdf.name = Some(KdfName::from_str(value, true).map_err(invalid)?),
+            "argon2_memory" => config.kdf.argon2_memory = Some(parse_size(value).map_err(invalid)?),
+            "argon2_iterations" => config.kdf.argon2_iterations = Some(parse(value, invalid)?),
+            "argon2_parallelism" => config.kdf.argon2_parallelism = Some(parse(value, invalid)?),
+            "kdf_time" => config.kdf.kdf_time = Some(parse_duration(value).map_err(invalid)?),
+            "output_dir" => config.output_dir = Some(expand_home(value)),
+            "jobs" => config.jobs = Some(parse(value, invalid)?),
+            _ => return Err(at(t!("config-unknown-key", key = key))),
+        }
+    }
+    if let Some(table) = table {
+        config.schedules.push(table.finish(path)?);
+    }
+    if config.kdf.kdf_time.is_some() && config.kdf.argon2_iterations.is_some() {
+        return Err(anyhow!(t!(
+            "config-kdf-time-and-iterations",
+            path = path.display().to_string()
+        )));
+    }
+    Ok(config)
+}
+
+/// The text of a TOML value: a basic string without escapes, a literal
+/// string (`'C:\Backups'`) or a bare word such as a number, followed by an
+/// optional comment.
+pub fn value_of(value: &str) -> Result<&str, String> {
+    let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+        return Ok(value
+            .split_once('#')
+            .map_or(value, |(value, _)| value.trim_end()));
+    };
+    let (value, rest) = value[1..]
+        .split_once(quote)
+        .ok_or_else(|| t!("config-unterminated-string"))?;
+    if quote == '"' && value.contains('\\') {
+        return Err(t!("config-escapes"));
+    }
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(t!("config-trailing", rest = rest));
+    }
+    Ok(value)
+}
+
+fn parse<T: std::str::FromStr>(
+    value: &str,
+    invalid: impl Fn(String) -> anyhow::Error,
+) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(t!("config-not-number", value = value)))
+}
+
+/// `~/` at the start of `value` is the home directory.
+fn expand_home(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(value),
+    }
+}
@@ -0,0 +1,269 @@
+//! Password-protected containers of structured records (notes, vault entries)
+//! kept in the user's data directory. They use the same on-disk format as
+//! encrypted files, so `decrypt` and `check --transport` work on them too.
+//!
+//! Inside the encryption, each container carries a random id and a generation
+//! number that every save increments. The highest generation seen for each id
+//! is remembered in the user's state directory. A container that comes back
+//! older than that (say, an earlier copy restored by a sync service) is
+//! refused as a possible rollback, unless `--accept-rollback` is given.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::{deserialize, serialize};
+use clap::Args;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    attempts, decrypt_bytes, encrypt_bytes, entropy, i18n::t, log, policy, prompt_password,
+    read_file_bytes, state_file, write_file_bytes, IoOptions, PasswordSource,
+};
+
+/// Starts the plaintext of containers that carry a generation; older ones
+/// hold the bare contents.
+const MAGIC: &[u8; 8] = b"FECONT01";
+const GENERATIONS_FILE: &str = "container-generations";
+
+#[derive(Serialize, Deserialize)]
+struct Stored<T> {
+    id: [u8; 16],
+    generation: u64,
+    contents: T,
+}
+
+/// Where a loaded container stands in its update history; pass it back to
+/// [`save`].
+pub struct Generation {
+    id: [u8; 16],
+    number: u64,
+    /// The container didn't exist yet, so saving creates it.
+    new: bool,
+}
+
+#[derive(Args)]
+pub struct ContainerArgs {
+    #[arg(short, long, value_name = "PASSWORD", conflicts_with = "password_source")]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// Create the container even if the password breaks the administrator's password policy;
+    /// the override is logged
+    #[arg(long)]
+    force_weak_password: bool,
+    /// Create the container even if the password is easy to guess, with a warning
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// Open the container even if it is older than a copy seen before, as when an earlier
+    /// backup was restored on purpose
+    #[arg(long)]
+    accept_rollback: bool,
+    /// Container file to use instead of the default one in the user's data directory
+    #[arg(long, value_name = "FILE")]
+    pub container: Option<PathBuf>,
+}
+
+impl ContainerArgs {
+    /// The password of the container at `path`, from --password or where the
+    /// password options say, or else prompted for as `encrypt` does: twice if
+    /// the container doesn't exist yet, since a typo would lock it for good.
+    pub fn password(&self, path: &Path) -> Result<Zeroizing<String>> {
+        match self.password_source.or(&self.password)? {
+            Some(password) => Ok(password),
+            None => prompt_password(!path.exists()),
+        }
+    }
+
+    /// The container file, defaulting to `file_name` in our data directory.
+    pub fn path(&self, file_name: &str) -> Result<PathBuf> {
+        match &self.container {
+            Some(path) => Ok(path.clone()),
+            None => dirs::data_dir()
+                .map(|dir| dir.join("file-encryptor").join(file_name))
+                .ok_or_else(|| anyhow!(t!("container-no-data-dir"))),
+        }
+    }
+}
+
+/// Reads and decrypts a container, or returns empty contents if it doesn't
+/// exist yet.
+pub fn load<T: DeserializeOwned + Default>(
+    path: &Path,
+    args: &ContainerArgs,
+    password: &str,
+) -> Result<T> {
+    open(path, args, password).map(|(contents, _)| contents)
+}
+
+/// Like [`load`], also returning the generation to [`save`] the update with.
+pub fn open<T: DeserializeOwned + Default>(
+    path: &Path,
+    args: &ContainerArgs,
+    password: &str,
+) -> Result<(T, Generation)> {
+    let generations = state_file(GENERATIONS_FILE);
+    open_tracked(path, password, args.accept_rollback, generations.as_deref())
+}
+
+/// [`open`], with the generations seen kept in `generations`.
+fn open_tracked<T: DeserializeOwned + Default>(
+    path: &Path,
+    password: &str,
+    accept_rollback: bool,
+    generations: Option<&Path>,
+) -> Result<(T, Generation)> {
+    let id = entropy::bytes()?;
+    if !path.exists() {
+        return Ok((T::default(), Generation { id, number: 0, new: true }));
+    }
+    let file_bytes = read_file_bytes(path, IoOptions::default())?;
+    let attempt = attempts::begin(&file_bytes);
+    let plain_bytes = match decrypt_bytes(password, &file_bytes) {
+        Ok(plain_bytes) => {
+            attempt.succeeded();
+            Zeroizing::new(plain_bytes)
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            return Err(e.context(t!(
+                "container-unlock-failed",
+                path = path.display().to_string()
+            )));
+        }
+    };
+    let invalid = || t!("container-invalid", path = path.display().to_string());
+    let Some(stored) = plain_bytes.strip_prefix(MAGIC) else {
+        let contents = deserialize(&plain_bytes).with_context(invalid)?;
+        return Ok((contents, Generation { id, number: 0, new: false }));
+    };
+    let stored: Stored<T> = deserialize(stored).with_context(invalid)?;
+
+    let seen = observe(generations, stored.id, stored.generation);
+    if stored.generation < seen {
+        let (path, generation) = (path.display().to_string(), stored.generation);
+        if !accept_rollback {
+            bail!(t!("container-rolled-back", path = path, generation = generation, seen = seen));
+        }
+        let accepted = t!("container-rollback-accepted", path = path, generation = generation);
+        log::warn(&accepted);
+    }
+    Ok((
+        stored.contents,
+        Generation {
+            id: stored.id,
+            // Saving an accepted rollback moves it past what was seen, so it
+            // isn't refused again.
+            number: stored.generation.max(seen),
+            new: false,
+        },
+    ))
+}
+
+/// Encrypts `contents` to `path`. Creating the container holds its password
+/// to the password policy, as `encrypt` does, with the overrides in `args`.
+pub fn save<T: Serialize>(
+    path: &Path,
+    args: &ContainerArgs,
+    password: &str,
+    contents: &T,
+    generation: Generation,
+) -> Result<()> {
+    let generations = state_file(GENERATIONS_FILE);
+    save_tracked(path, args, password, contents, generation, generations.as_deref())
+}
+
+/// [`save`], with the generations seen kept in `generations`.
+fn save_tracked<T: Serialize>(
+    path: &Path,
+    args: &ContainerArgs,
+    password: &str,
+    contents: &T,
+    generation: Generation,
+    generations: Option<&Path>,
+) -> Result<()> {
+    if generation.new {
+        let (force, allow_weak) = (args.force_weak_password, args.allow_weak_password);
+        policy::Policy::load()?.enforce(password, force, allow_weak)?;
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| t!("dir-create-failed", path = dir.display().to_string()))?;
+    }
+    let stored = Stored {
+        id: generation.id,
+        generation: generation.number + 1,
+        contents,
+    };
+    let mut plain_bytes = Zeroizing::new(MAGIC.to_vec());
+    plain_bytes.extend_from_slice(&Zeroizing::new(serialize(&stored)?));
+    let encrypted = encrypt_bytes(password, &plain_bytes)?;
+    write_file_bytes(path, &encrypted, IoOptions::default())?;
+    observe(generations, stored.id, stored.generation);
+    if generation.new {
+        log::info(&t!("container-created", path = path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Records `generation` for container `id` in the file `generations`,
+/// returning the highest generation seen for it before. Best effort: without
+/// a state directory nothing is tracked.
+fn observe(generations: Option<&Path>, id: [u8; 16], generation: u64) -> u64 {
+    let Some(state) = generations else {
+        return 0;
+    };
+    let mut seen: HashMap<[u8; 16], u64> = fs::read(state)
+        .ok()
+        .and_then(|bytes| deserialize(&bytes).ok())
+        .unwrap_or_default();
+    let previous = seen.get(&id).copied().unwrap_or(0);
+    if generation > previous {
+        seen.insert(id, generation);
+        if let (Some(dir), Ok(bytes)) = (state.parent(), serialize(&seen)) {
+            let _ = fs::create_dir_all(dir).and_then(|()| fs::write(state, bytes));
+        }
+    }
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        container: ContainerArgs,
+    }
+
+    #[test]
+    fn restored_older_copy_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, old_copy) = (dir.path().join("notes.enc"), dir.path().join("old.enc"));
+        let generations = dir.path().join(GENERATIONS_FILE);
+        let generations = Some(generations.as_path());
+        let args = Cli::parse_from(["file-encryptor", "--allow-weak-password"]).container;
+        let update = |note: &str, accept_rollback| -> Result<Vec<String>> {
+            let (mut notes, generation): (Vec<String>, _) =
+                open_tracked(&path, "pw", accept_rollback, generations)?;
+            notes.push(note.to_owned());
+            save_tracked(&path, &args, "pw", &notes, generation, generations)?;
+            Ok(notes)
+        };
+
+        update("first", false).unwrap();
+        fs::copy(&path, &old_copy).unwrap();
+        update("second", false).unwrap();
+        fs::copy(&old_copy, &path).unwrap();
+        let refused = update("third", false).unwrap_err();
+        assert!(refused.to_string().contains("--accept-rollback"));
+        assert_eq!(update("third", true).unwrap(), ["first", "third"]);
+        // Saved past the generation seen, the accepted copy opens again.
+        assert_eq!(update("fourth", false).unwrap(), ["first", "third", "fourth"]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Cron expressions for the schedules of the config file: the five fields
+//! minute, hour, day of the month, month and day of the week (0 or 7 for
+//! Sunday), each `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a
+//! list of these, as in `30 2 * * 1-5`. `@hourly`, `@daily`, `@weekly`,
+//! `@monthly` and `@yearly` stand for the usual expressions. As in cron, a day
+//! matches if either day field does when both are restricted. Times are UTC,
+//! like the rest of the tool's dates.
+
+use std::time::{Duration, SystemTime};
+
+use file_encryptor::{date, i18n::t};
+
+/// How far ahead [`Cron::next_after`] looks, past the longest gap between
+/// two matching days (29 February on a Monday, 28 years).
+const HORIZON: Duration = Duration::from_secs(30 * 366 * 86_400);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+    /// One bit per matching value of each field.
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day fields are other than `*`, for the either-day rule.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Cron, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(t!("cron-not-five-fields", expression = expression));
+        };
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        // 7 is Sunday too.
+        if weekday_bits & 1 << 7 != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+        let cron = Cron {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        };
+        if cron.next_after(SystemTime::UNIX_EPOCH).is_none() {
+            return Err(t!("cron-never", expression = expression));
+        }
+        Ok(cron)
+    }
+
+    /// The first whole minute after `time` that matches.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let since = time.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+        let end = since + HORIZON.as_secs();
+        let mut minute = since / 60 + 1;
+        while minute * 60 <= end {
+            let at = SystemTime::UNIX_EPOCH + Duration::from_secs(minute * 60);
+            let [_, month, day, hour, minute_of_hour, _] = date::utc(at);
+            if !self.matches_day(month, day, minute / 1440) {
+                minute = (minute / 1440 + 1) * 1440;
+            } else if self.hours & 1 << hour == 0 {
+                minute = (minute / 60 + 1) * 60;
+            } else if self.minutes & 1 << minute_of_hour == 0 {
+                minute += 1;
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, month: u64, day: u64, days_since_epoch: u64) -> bool {
+        // 1 January 1970 was a Thursday.
+        let weekday = (days_since_epoch + 4) % 7;
+        let day_matches = self.days & 1 << day != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+        self.months & 1 << month != 0
+            && match (self.days_restricted, self.weekdays_restricted) {
+                (true, true) => day_matches || weekday_matches,
+                _ => day_matches && weekday_matches,
+            }
+    }
+}
+
+/// The bits of the values `min..=max` that one field matches.
+fn field(text: &str, min: u64, max: u64) -> Result<u64, String> {
+    let invalid = || t!("cron-invalid-field", field = text, min = min, max = max);
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((first, last)) => (
+                    first.parse().map_err(|_| invalid())?,
+                    last.parse().map_err(|_| invalid())?,
+                ),
+                // `a/n` runs from `a` to the end.
+                None if part.contains('/') => (range.parse().map_err(|_| invalid())?, max),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    (value, value)
+                }
+            },
+        };
+        if step == 0 || first < min || last > max || first > last {
+            return Err(invalid());
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> SystemTime {
+        date::parse(text).unwrap()
+    }
+
+    #[test]
+    fn next_runs_follow_the_fields() {
+        let next = |expression: &str, after: &str| {
+            date::format(
+                Cron::parse(expression)
+                    .unwrap()
+                    .next_after(at(after))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            next("30 2 * * *", "2026-10-14T02:30:00Z"),
+            "2026-10-15T02:30:00Z"
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2026-10-14T09:31:10Z"),
+            "2026-10-14T09:45:00Z"
+        );
+        // 14 October 2026 is a Wednesday.
+        assert_eq!(
+            next("0 9 * * 1-5", "2026-10-16T10:00:00Z"),
+            "2026-10-19T09:00:00Z"
+        );
+        assert_eq!(
+            next("0 0 1,15 * 7", "2026-10-14T00:00:00Z"),
+            "2026-10-15T00:00:00Z"
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2026-10-14T00:00:00Z"),
+            "2028-02-29T00:00:00Z"
+        );
+        assert_eq!(
+            next("@monthly", "2026-12-31T23:59:00Z"),
+            "2027-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn bad_expressions_are_refused() {
+        for expression in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "0 0 31 2 *",
+            "@often",
+        ] {
+            assert!(Cron::parse(expression).is_err(), "{expression}");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Times as RFC 3339 dates in UTC, such as `2026-10-14T09:30:00Z`, with the
+//! civil calendar worked out by hand (after Howard Hinnant's algorithms)
+//! rather than with a calendar crate.
+
+use std::time::{Duration, SystemTime};
+
+/// The year, month, day, hour, minute and second of `time` in UTC.
+pub fn utc(time: SystemTime) -> [u64; 6] {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Days since 1970-01-01 to a civil date.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    [year, month, day, time / 3600, time / 60 % 60, time % 60]
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format(time: SystemTime) -> String {
+    let [year, month, day, hour, minute, second] = utc(time);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// Reverses [`format`], also taking a bare `YYYY-MM-DD` as its midnight.
+/// Dates before 1970 and offsets other than `Z` aren't taken.
+pub fn parse(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z')?)),
+        None => (text, None),
+    };
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<u64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let seconds = match time {
+        None => 0,
+        Some(time) => {
+            let mut time = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+            let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+    };
+    // A civil date to days since 1970-01-01.
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+    let parsed = SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86_400 + seconds);
+    // Refuses days a month doesn't have, such as the 31st of April.
+    (utc(parsed)[..3] == [year + u64::from(month <= 2), month, day]).then_some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_round_trip() {
+        for seconds in [0, 951_782_400, 1_709_164_800, 1_791_979_200, 4_107_542_399] {
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(parse(&format(time)), Some(time), "{}", format(time));
+        }
+        assert_eq!(
+            format(SystemTime::UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+        assert_eq!(parse("2000-02-29"), parse("2000-02-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn impossible_dates_are_refused() {
+        for text in [
+            "2026-04-31",
+            "2025-02-29",
+            "2026-13-01",
+            "1969-12-31",
+            "2026-10-14T24:00:00Z",
+            "2026-10-14T10:00:00+02:00",
+            "yesterday",
+        ] {
+            assert_eq!(parse(text), None, "{text}");
+        }
+    }
+}
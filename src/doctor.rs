@@ -0,0 +1,248 @@
+//! `doctor`: checks the parts of the environment the tool depends on and
+//! suggests a fix for each problem found.
+
+use anyhow::{bail, Result};
+use std::{
+    fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use file_encryptor::i18n::t;
+
+use crate::{
+    entropy::{self, EntropyError},
+    human_size, state_file,
+};
+
+/// Below this much free space in the temp directory, large runs may fail.
+const LOW_SPACE: u64 = 1 << 30;
+
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    status: Status,
+    name: String,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: String, detail: String) -> Self {
+        Check {
+            status: Status::Ok,
+            name,
+            detail,
+            fix: None,
+        }
+    }
+
+    fn problem(status: Status, name: String, detail: String, fix: String) -> Self {
+        Check {
+            status,
+            name,
+            detail,
+            fix: Some(fix),
+        }
+    }
+}
+
+pub fn run() -> Result<()> {
+    let mut checks = vec![entropy(), temp_dir()];
+    checks.extend(
+        [
+            (
+                t!("doctor-data-dir"),
+                dirs::data_dir().map(|dir| dir.join("file-encryptor")),
+            ),
+            (
+                t!("doctor-state-dir"),
+                state_file("probe").and_then(|file| file.parent().map(Path::to_path_buf)),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, dir)| app_dir(name, dir)),
+    );
+    checks.push(terminal());
+    checks.push(locale());
+
+    let mut failed = false;
+    for check in &checks {
+        let label = match check.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => {
+                failed = true;
+                "FAIL"
+            }
+        };
+        println!("[{label:>4}] {}: {}", check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            println!("       {}", t!("doctor-fix", fix = fix.as_str()));
+        }
+    }
+    if failed {
+        bail!(t!("doctor-failed"));
+    }
+    Ok(())
+}
+
+fn entropy() -> Check {
+    match entropy::check() {
+        Ok(()) => Check::ok(t!("doctor-entropy"), t!("doctor-entropy-ok")),
+        Err(e @ (EntropyError::Constant | EntropyError::Repeated)) => Check::problem(
+            Status::Fail,
+            t!("doctor-entropy"),
+            e.to_string(),
+            t!("doctor-entropy-broken-fix"),
+        ),
+        Err(e) => Check::problem(
+            Status::Fail,
+            t!("doctor-entropy"),
+            e.to_string(),
+            t!("doctor-entropy-missing-fix"),
+        ),
+    }
+}
+
+fn temp_dir() -> Check {
+    let dir = std::env::temp_dir();
+    if let Err(e) = probe_write(&dir) {
+        return Check::problem(
+            Status::Fail,
+            t!("doctor-temp-dir"),
+            t!(
+                "doctor-dir-unwritable",
+                path = dir.display().to_string(),
+                error = e.to_string()
+            ),
+            t!("doctor-temp-dir-fix"),
+        );
+    }
+    match free_space(&dir) {
+        Some(free) if free < LOW_SPACE => Check::problem(
+            Status::Warn,
+            t!("doctor-temp-dir"),
+            t!(
+                "doctor-dir-low-space",
+                path = dir.display().to_string(),
+                free = human_size(free)
+            ),
+            t!("doctor-low-space-fix"),
+        ),
+        Some(free) => Check::ok(
+            t!("doctor-temp-dir"),
+            t!(
+                "doctor-dir-space",
+                path = dir.display().to_string(),
+                free = human_size(free)
+            ),
+        ),
+        None => Check::ok(
+            t!("doctor-temp-dir"),
+            t!("doctor-dir-writable", path = dir.display().to_string()),
+        ),
+    }
+}
+
+/// Where notes, vault and bookkeeping state are kept.
+fn app_dir(name: String, dir: Option<PathBuf>) -> Check {
+    let Some(dir) = dir else {
+        return Check::problem(
+            Status::Warn,
+            name,
+            t!("doctor-dir-unknown"),
+            t!("doctor-dir-unknown-fix"),
+        );
+    };
+    match fs::create_dir_all(&dir).and_then(|()| probe_write(&dir)) {
+        Ok(()) => Check::ok(
+            name,
+            t!("doctor-dir-writable", path = dir.display().to_string()),
+        ),
+        Err(e) => Check::problem(
+            Status::Warn,
+            name,
+            t!(
+                "doctor-dir-unwritable",
+                path = dir.display().to_string(),
+                error = e.to_string()
+            ),
+            t!("doctor-permissions-fix", path = dir.display().to_string()),
+        ),
+    }
+}
+
+fn terminal() -> Check {
+    if cfg!(unix)
+        && fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .is_err()
+    {
+        return Check::problem(
+            Status::Warn,
+            t!("doctor-terminal"),
+            t!("doctor-terminal-missing"),
+            t!("doctor-terminal-fix"),
+        );
+    }
+    if !std::io::stdin().is_terminal() {
+        return Check::ok(t!("doctor-terminal"), t!("doctor-terminal-redirected"));
+    }
+    Check::ok(t!("doctor-terminal"), t!("doctor-terminal-ok"))
+}
+
+fn locale() -> Check {
+    if cfg!(windows) {
+        return Check::ok(t!("doctor-locale"), t!("doctor-locale-windows"));
+    }
+    let value = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    match value {
+        Some(value) if value.to_lowercase().replace('-', "").contains("utf8") => {
+            Check::ok(t!("doctor-locale"), format!("{value} (UTF-8)"))
+        }
+        value => Check::problem(
+            Status::Warn,
+            t!("doctor-locale"),
+            t!(
+                "doctor-locale-not-utf8",
+                locale = value.as_deref().unwrap_or("C")
+            ),
+            t!("doctor-locale-fix"),
+        ),
+    }
+}
+
+fn probe_write(dir: &Path) -> std::io::Result<()> {
+    let name: [u8; 8] = entropy::bytes().map_err(std::io::Error::other)?;
+    let probe = dir.join(format!(".file-encryptor-doctor-{}", hex::encode(name)));
+    fs::write(&probe, b"probe")?;
+    fs::remove_file(&probe)
+}
+
+/// The space free to an unprivileged user on the file system of `dir`.
+#[cfg(unix)]
+pub fn free_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)] // field widths differ between platforms
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,137 @@
+//! Randomness from the OS generator, with basic health checks. Salts, nonces
+//! and keys are only as good as this source, and containers and embedded
+//! boards sometimes come up with a broken one, so a failure stops the run
+//! with an [`EntropyError`] instead of panicking or carrying on.
+//!
+//! The checks are those of a continuous RNG test: an output of one repeated
+//! byte, or one equal to the previous output, means the generator is stuck.
+//! They catch a dead source, not a subtly biased one.
+//!
+//! Without the `os-rng` feature, as on `wasm32-unknown-unknown`, there is no
+//! OS generator to fall back on: embedders install theirs with
+//! [`set_source`], and until then everything that needs randomness fails
+//! with [`EntropyError::NoSource`].
+
+use rand::{CryptoRng, RngCore};
+use std::{
+    fmt,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::i18n::t;
+
+/// Outputs shorter than this are too likely to trip the checks by chance.
+const MIN_CHECKED_LENGTH: usize = 8;
+
+/// The previous checked output.
+static PREVIOUS: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static SOURCE: OnceLock<Source> = OnceLock::new();
+
+/// Fills its buffer with random bytes from a cryptographically secure
+/// generator, or fails.
+pub type Source = fn(&mut [u8]) -> Result<(), rand::Error>;
+
+#[derive(Debug)]
+pub enum EntropyError {
+    Unavailable(rand::Error),
+    Constant,
+    Repeated,
+    /// Built without `os-rng`, and no [`set_source`] yet.
+    NoSource,
+}
+
+impl fmt::Display for EntropyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            EntropyError::Unavailable(e) => t!("entropy-unavailable", error = e.to_string()),
+            EntropyError::Constant => t!("entropy-constant"),
+            EntropyError::Repeated => t!("entropy-repeated"),
+            EntropyError::NoSource => t!("entropy-no-source"),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for EntropyError {}
+
+/// Draws randomness from `source` instead of the OS generator, for the whole
+/// process; only the first call counts, and it returns whether it was first.
+pub fn set_source(source: Source) -> bool {
+    SOURCE.set(source).is_ok()
+}
+
+/// Fills `buf` from the source, unchecked.
+fn draw(buf: &mut [u8]) -> Result<(), EntropyError> {
+    if let Some(source) = SOURCE.get() {
+        return source(buf).map_err(EntropyError::Unavailable);
+    }
+    #[cfg(feature = "os-rng")]
+    return rand::rngs::OsRng
+        .try_fill_bytes(buf)
+        .map_err(EntropyError::Unavailable);
+    #[cfg(not(feature = "os-rng"))]
+    Err(EntropyError::NoSource)
+}
+
+/// Fills `buf` from the source and checks the result.
+pub fn fill(buf: &mut [u8]) -> Result<(), EntropyError> {
+    draw(buf)?;
+    if buf.len() < MIN_CHECKED_LENGTH {
+        return Ok(());
+    }
+    if buf.iter().all(|&b| b == buf[0]) {
+        return Err(EntropyError::Constant);
+    }
+    let mut previous = PREVIOUS.lock().unwrap_or_else(|e| e.into_inner());
+    if previous.as_slice() == buf {
+        return Err(EntropyError::Repeated);
+    }
+    previous.clear();
+    previous.extend_from_slice(buf);
+    Ok(())
+}
+
+pub fn bytes<const N: usize>() -> Result<[u8; N], EntropyError> {
+    let mut bytes = [0u8; N];
+    fill(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Runs the checks on a fresh sample, for callers that hand [`Rng`] to
+/// other crates, which panic if it fails.
+pub fn check() -> Result<(), EntropyError> {
+    bytes::<32>().map(drop)
+}
+
+/// The source as a generator, for crates that take one.
+pub struct Rng;
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest)
+            .unwrap_or_else(|e| panic!("no randomness: {e}"));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        draw(dest).map_err(|e| match e {
+            EntropyError::Unavailable(e) => e,
+            _ => std::num::NonZeroU32::new(rand::Error::CUSTOM_START)
+                .expect("nonzero")
+                .into(),
+        })
+    }
+}
+
+impl CryptoRng for Rng {}
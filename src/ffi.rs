@@ -0,0 +1,528 @@
+//! A C interface to the formats, for programs in C, C++ or anything with a
+//! C foreign function interface, such as Python's `ctypes`. The functions
+//! write exactly what the command line does: `fe_encrypt_buffer` a
+//! single-message file like `encrypt`, and a streaming context the chunked
+//! format of `encrypt --stream`. The declarations are in
+//! `include/file_encryptor.h`, which the build writes from this file with
+//! cbindgen.
+//!
+//! Every function returns [`FE_OK`] or a negative code, the message of the
+//! last failure on the calling thread being [`fe_last_error`]. Buffers the
+//! library hands out are freed with [`fe_buffer_free`]; a streaming context
+//! lives until `fe_stream_finish` or `fe_stream_free`.
+//!
+//! A streaming context runs the chunked encryptor or decryptor on a thread of
+//! its own, fed the input as it is pushed. Output comes out as chunks are
+//! done, so `fe_stream_update` may return less than it was given, or nothing,
+//! and `fe_stream_finish` returns the rest.
+
+use anyhow::{anyhow, Result};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    io::{self, Read, Write},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use zeroize::Zeroizing;
+
+use crate::{i18n::t, DecryptError, Encryptor};
+
+pub const FE_OK: c_int = 0;
+/// Any failure without a code of its own.
+pub const FE_ERROR: c_int = -1;
+/// A null pointer, or a password that isn't UTF-8.
+pub const FE_INVALID_ARGUMENT: c_int = -2;
+/// The password isn't the file's.
+pub const FE_WRONG_KEY: c_int = -3;
+/// The file was damaged after it was encrypted.
+pub const FE_CORRUPTED: c_int = -4;
+
+/// Pieces of input a streaming context holds before `fe_stream_update`
+/// waits for its thread to catch up.
+const QUEUED_PIECES: usize = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An encryption or decryption in progress.
+pub struct FeStream {
+    input: Option<SyncSender<Vec<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    worker: Option<JoinHandle<Result<()>>>,
+}
+
+/// The message of the last failure on this thread, or null if there was
+/// none. It stays valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn fe_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Encrypts `input_len` bytes at `input` with `password` into a new buffer,
+/// stored with its length in `*output` and `*output_len`.
+///
+/// # Safety
+///
+/// `password` must be a NUL-terminated string, `input` must point to
+/// `input_len` readable bytes (or be null if that is 0), and `output` and
+/// `output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fe_encrypt_buffer(
+    password: *const c_char,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    call(|| {
+        let mut keys = Encryptor::new(password_arg(password)?);
+        let sealed = keys.encrypt(bytes_arg(input, input_len)?)?;
+        hand_out(sealed, output, output_len)
+    })
+}
+
+/// Decrypts the file of `input_len` bytes at `input`, in either format, with
+/// `password` into a new buffer, stored with its length in `*output` and
+/// `*output_len`.
+///
+/// # Safety
+///
+/// As for [`fe_encrypt_buffer`].
+#[no_mangle]
+pub unsafe extern "C" fn fe_decrypt_buffer(
+    password: *const c_char,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    call(|| {
+        let mut keys = Encryptor::new(password_arg(password)?);
+        let plaintext = keys.decrypt(bytes_arg(input, input_len)?)?;
+        hand_out(plaintext, output, output_len)
+    })
+}
+
+/// Frees a buffer returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `buffer` and `len` must be a buffer and length this library returned,
+/// not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn fe_buffer_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)));
+    }
+}
+
+/// Starts encrypting to the chunked format with `password`, returning the
+/// context, or null on failure.
+///
+/// # Safety
+///
+/// `password` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fe_stream_encrypt_new(password: *const c_char) -> *mut FeStream {
+    stream_new(password, FeStream::encrypting)
+}
+
+/// Starts decrypting a file of the chunked format with `password`, returning
+/// the context, or null on failure. Single-message files go to
+/// [`fe_decrypt_buffer`].
+///
+/// # Safety
+///
+/// `password` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fe_stream_decrypt_new(password: *const c_char) -> *mut FeStream {
+    stream_new(password, FeStream::decrypting)
+}
+
+/// Pushes the next `input_len` bytes at `input` into `stream`, storing the
+/// output that is ready in a new buffer in `*output` and `*output_len`. On
+/// failure the context can only be freed.
+///
+/// # Safety
+///
+/// `stream` must be a live context, and the rest as for
+/// [`fe_encrypt_buffer`].
+#[no_mangle]
+pub unsafe extern "C" fn fe_stream_update(
+    stream: *mut FeStream,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    call(|| {
+        let stream = stream
+            .as_mut()
+            .ok_or_else(|| invalid("the stream is null"))?;
+        let ready = stream.push(bytes_arg(input, input_len)?.to_vec())?;
+        hand_out(ready, output, output_len)
+    })
+}
+
+/// Ends the input of `stream`, storing the rest of the output in a new buffer
+/// in `*output` and `*output_len`, and frees the context. Decryption fails
+/// here if the file was cut short or doesn't match its checksums.
+///
+/// # Safety
+///
+/// `stream` must be a live context, which is gone afterwards whatever the
+/// result, and `output` and `output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fe_stream_finish(
+    stream: *mut FeStream,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    call(|| {
+        if stream.is_null() {
+            return Err(invalid("the stream is null"));
+        }
+        let rest = Box::from_raw(stream).end()?;
+        hand_out(rest, output, output_len)
+    })
+}
+
+/// Abandons `stream`, discarding its output. Null is ignored.
+///
+/// # Safety
+///
+/// `stream` must be null or a live context, which is gone afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fe_stream_free(stream: *mut FeStream) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+impl FeStream {
+    /// Starts encrypting to the chunked format with `password`.
+    pub(crate) fn encrypting(password: &str) -> FeStream {
+        FeStream::start(password, |keys, input, output| {
+            keys.encrypt_reader_to_writer(input, output).map(drop)
+        })
+    }
+
+    /// Starts decrypting a file of the chunked format with `password`.
+    pub(crate) fn decrypting(password: &str) -> FeStream {
+        FeStream::start(password, |keys, input, output| {
+            keys.decrypt_reader_to_writer(input, output)
+        })
+    }
+
+    /// Runs `run` on a thread of its own, with a copy of `password` so the
+    /// caller's can go as soon as this returns.
+    fn start(password: &str, run: fn(&mut Encryptor, Pieces, Shared) -> Result<()>) -> FeStream {
+        let password = Zeroizing::new(password.to_owned());
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_PIECES);
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let shared = Shared(output.clone());
+        let worker = thread::spawn(move || {
+            run(
+                &mut Encryptor::new(&password),
+                Pieces::new(receiver),
+                shared,
+            )
+        });
+        FeStream {
+            input: Some(sender),
+            output,
+            worker: Some(worker),
+        }
+    }
+
+    /// Pushes the next `piece` of input, returning the output that is ready.
+    pub(crate) fn push(&mut self, piece: Vec<u8>) -> Result<Vec<u8>> {
+        let sent = match &self.input {
+            Some(sender) => sender.send(piece).is_ok(),
+            None => false,
+        };
+        if !sent {
+            // The thread stopped, with the error `finish` returns.
+            self.finish()?;
+            return Err(anyhow!(t!("ffi-stream-finished")));
+        }
+        Ok(self.take_output())
+    }
+
+    /// Ends the input, returning the rest of the output.
+    pub(crate) fn end(&mut self) -> Result<Vec<u8>> {
+        self.finish()?;
+        Ok(self.take_output())
+    }
+
+    fn take_output(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.lock().expect("not poisoned"))
+    }
+
+    /// Ends the input and waits for the thread.
+    fn finish(&mut self) -> Result<()> {
+        self.input = None;
+        match self.worker.take() {
+            Some(worker) => worker
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!(t!("ffi-stream-panicked")))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for FeStream {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+unsafe fn stream_new(password: *const c_char, start: fn(&str) -> FeStream) -> *mut FeStream {
+    guarded(|| Ok(Box::into_raw(Box::new(start(password_arg(password)?)))))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Runs `f` as [`guarded`] does, returning its result as a code.
+fn call(f: impl FnOnce() -> Result<()>) -> c_int {
+    let Err(e) = guarded(f) else {
+        return FE_OK;
+    };
+    if e.is::<Invalid>() {
+        return FE_INVALID_ARGUMENT;
+    }
+    match e.downcast_ref::<DecryptError>() {
+        Some(DecryptError::WrongKey) => FE_WRONG_KEY,
+        Some(e) if e.is_damage() => FE_CORRUPTED,
+        _ => FE_ERROR,
+    }
+}
+
+/// Runs `f`, recording its error, or a panic, for [`fe_last_error`].
+fn guarded<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    LAST_ERROR.with(|last| last.borrow_mut().take());
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(anyhow!(t!("ffi-panicked"))));
+    if let Err(e) = &result {
+        let message = format!("{e:#}").replace('\0', " ");
+        LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    }
+    result
+}
+
+/// An argument the caller got wrong.
+#[derive(Debug)]
+struct Invalid(&'static str);
+
+impl std::fmt::Display for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+fn invalid(message: &'static str) -> anyhow::Error {
+    Invalid(message).into()
+}
+
+unsafe fn password_arg<'a>(password: *const c_char) -> Result<&'a str> {
+    if password.is_null() {
+        return Err(invalid("the password is null"));
+    }
+    CStr::from_ptr(password)
+        .to_str()
+        .map_err(|_| invalid("the password isn't UTF-8"))
+}
+
+unsafe fn bytes_arg<'a>(input: *const u8, input_len: usize) -> Result<&'a [u8]> {
+    match (input.is_null(), input_len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(invalid("the input is null")),
+        (false, _) => Ok(slice::from_raw_parts(input, input_len)),
+    }
+}
+
+/// Hands `bytes` to the caller, to free with [`fe_buffer_free`].
+unsafe fn hand_out(bytes: Vec<u8>, output: *mut *mut u8, output_len: *mut usize) -> Result<()> {
+    if output.is_null() || output_len.is_null() {
+        return Err(invalid("the output is null"));
+    }
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+    *output_len = bytes.len();
+    *output = bytes.cast();
+    Ok(())
+}
+
+/// The pieces pushed into a streaming context, as a reader that ends once
+/// the context's input is closed.
+struct Pieces {
+    receiver: Receiver<Vec<u8>>,
+    piece: Vec<u8>,
+    read: usize,
+}
+
+impl Pieces {
+    fn new(receiver: Receiver<Vec<u8>>) -> Self {
+        Pieces {
+            receiver,
+            piece: Vec::new(),
+            read: 0,
+        }
+    }
+}
+
+impl Read for Pieces {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.piece.len() {
+            match self.receiver.recv() {
+                Ok(piece) => (self.piece, self.read) = (piece, 0),
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.piece.len() - self.read);
+        buf[..n].copy_from_slice(&self.piece[self.read..self.read + n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+/// The output of a streaming context, collected until the caller takes it.
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().expect("not poisoned").extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls `f` with an output buffer, returning its code and the bytes
+    /// handed out, freed again.
+    fn collect(f: impl FnOnce(*mut *mut u8, *mut usize) -> c_int) -> (c_int, Vec<u8>) {
+        let (mut output, mut output_len) = (ptr::null_mut(), 0);
+        let code = f(&mut output, &mut output_len);
+        let mut bytes = Vec::new();
+        if !output.is_null() {
+            unsafe {
+                bytes.extend_from_slice(slice::from_raw_parts(output, output_len));
+                fe_buffer_free(output, output_len);
+            }
+        }
+        (code, bytes)
+    }
+
+    fn decrypt_buffer(password: &CStr, file: &[u8]) -> (c_int, Vec<u8>) {
+        collect(|output, output_len| unsafe {
+            fe_decrypt_buffer(
+                password.as_ptr(),
+                file.as_ptr(),
+                file.len(),
+                output,
+                output_len,
+            )
+        })
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(fe_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn buffers_round_trip_and_report_why_they_fail() {
+        let (code, file) = collect(|output, output_len| unsafe {
+            fe_encrypt_buffer(c"pw".as_ptr(), b"hello".as_ptr(), 5, output, output_len)
+        });
+        assert_eq!(code, FE_OK);
+        assert!(fe_last_error().is_null());
+        assert_eq!(decrypt_buffer(c"pw", &file), (FE_OK, b"hello".to_vec()));
+
+        assert_eq!(decrypt_buffer(c"wrong", &file).0, FE_WRONG_KEY);
+        assert_eq!(last_error(), DecryptError::WrongKey.to_string());
+        let mut damaged = file.clone();
+        damaged[file.len() / 2] ^= 1;
+        assert_eq!(decrypt_buffer(c"pw", &damaged).0, FE_CORRUPTED);
+
+        let code = unsafe {
+            fe_decrypt_buffer(
+                ptr::null(),
+                file.as_ptr(),
+                file.len(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(code, FE_INVALID_ARGUMENT);
+        let (code, _) = collect(|output, output_len| unsafe {
+            fe_encrypt_buffer(c"pw".as_ptr(), ptr::null(), 1, output, output_len)
+        });
+        assert_eq!(code, FE_INVALID_ARGUMENT);
+    }
+
+    /// Pushes `pieces` through a new context from `start`, returning what
+    /// came out, or the code that ended it.
+    fn run(start: *mut FeStream, pieces: &[&[u8]]) -> Result<Vec<u8>, c_int> {
+        assert!(!start.is_null());
+        let mut out = Vec::new();
+        for piece in pieces {
+            let (code, ready) = collect(|output, output_len| unsafe {
+                fe_stream_update(start, piece.as_ptr(), piece.len(), output, output_len)
+            });
+            if code != FE_OK {
+                unsafe { fe_stream_free(start) };
+                return Err(code);
+            }
+            out.extend(ready);
+        }
+        let (code, rest) =
+            collect(|output, output_len| unsafe { fe_stream_finish(start, output, output_len) });
+        out.extend(rest);
+        (code == FE_OK).then_some(out).ok_or(code)
+    }
+
+    #[test]
+    fn streams_round_trip_in_pieces_and_refuse_truncation() {
+        let password = c"pw".as_ptr();
+        let file = run(
+            unsafe { fe_stream_encrypt_new(password) },
+            &[b"hel", b"", b"lo"],
+        )
+        .unwrap();
+        let (first, second) = file.split_at(file.len() / 3);
+        let decrypted = run(unsafe { fe_stream_decrypt_new(password) }, &[first, second]);
+        assert_eq!(decrypted.unwrap(), b"hello");
+
+        let wrong = run(
+            unsafe { fe_stream_decrypt_new(c"wrong".as_ptr()) },
+            &[&file],
+        );
+        assert_eq!(wrong, Err(FE_WRONG_KEY));
+        let cut = run(
+            unsafe { fe_stream_decrypt_new(password) },
+            &[&file[..file.len() - 1]],
+        );
+        assert!(cut.is_err());
+        assert!(unsafe { fe_stream_encrypt_new(ptr::null()) }.is_null());
+    }
+}
@@ -0,0 +1,775 @@
+//! `serve --grpc`: the Encrypt/Decrypt streaming service from
+//! `proto/file_encryptor.proto`.
+//!
+//! The file format authenticates the ciphertext as a whole, so each call
+//! buffers its input (up to [`MAX_INPUT_LENGTH`]) before streaming the result
+//! back in [`RESPONSE_CHUNK_LENGTH`] pieces.
+//!
+//! With `--client-ca`, callers must present a certificate signed by that CA,
+//! and each call's password is scoped to the certificate's subject common
+//! name, so one client cannot use the service to decrypt another's output.
+//!
+//! Every client (by certificate name, or by address without client auth) is
+//! rate limited, and failed decryptions lock it out for exponentially longer
+//! periods, so the service cannot be used to guess passwords at KDF speed.
+//!
+//! `SubmitJob`/`GetJob` run encryptions over server-side files in the
+//! background; see [`jobs`]. `CreateUpload`/`AppendUpload`/`GetUpload` take
+//! plaintexts too large for one call in resumable segments; see [`uploads`].
+//! The config file's schedules run jobs at set times; see [`schedules`].
+
+// `tonic::Status` is large, but it is the error type every handler returns.
+#![allow(clippy::result_large_err)]
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{
+    transport::{Certificate, Identity, Server, ServerTlsConfig},
+    Request, Response, Status, Streaming,
+};
+use x509_cert::der::{oid::db::rfc4519::CN, Decode, Tag, Tagged};
+
+use file_encryptor::{i18n::t, DecryptError};
+
+use crate::{decrypt_bytes, encrypt_bytes, log, policy::Policy, Limits};
+
+mod jobs;
+pub mod metrics;
+mod schedules;
+mod uploads;
+
+mod proto {
+    tonic::include_proto!("file_encryptor.v1");
+}
+
+use jobs::JobQueue;
+use metrics::{Operation, METRICS};
+use proto::{
+    file_encryptor_server::{FileEncryptor, FileEncryptorServer},
+    Chunk, CryptRequest, Job, JobOperation, JobQuery, JobRequest, Upload, UploadQuery,
+    UploadRequest, UploadSegment,
+};
+use uploads::Uploads;
+
+const MAX_INPUT_LENGTH: usize = 1 << 30;
+const RESPONSE_CHUNK_LENGTH: usize = 1 << 20;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<Chunk, Status>> + Send>>;
+
+#[derive(Args)]
+pub struct ServeOptions {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:50051")]
+    pub listen: SocketAddr,
+    /// PEM certificate chain the server presents
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// PEM CA certificates that client certificates must chain to
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub client_ca: Option<PathBuf>,
+    /// Accept callers without a client certificate (any local process can then
+    /// use the service)
+    #[arg(long, conflicts_with = "client_ca")]
+    pub allow_unauthenticated: bool,
+    /// Requests each client may make per minute (0 for no limit)
+    #[arg(long, value_name = "N", default_value_t = 60)]
+    pub rate_limit: u32,
+    /// Lockout after a client's first failed decryption, doubling with each
+    /// further failure up to 15 minutes
+    #[arg(long, value_name = "SECONDS", default_value_t = 1)]
+    pub failure_backoff: u64,
+    /// Also serve /metrics and /healthz over plain HTTP on this address
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+    /// Directory SubmitJob may read and write, uploads are written to and the config file's
+    /// schedules work in; all are disabled without it
+    #[arg(long, value_name = "DIR")]
+    pub job_root: Option<PathBuf>,
+    /// Jobs that may run at the same time; the rest wait queued
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub max_jobs: usize,
+    /// Limits on each call and job; streamed input is also capped at 1 GiB
+    #[command(flatten)]
+    pub limits: Limits,
+}
+
+struct Service {
+    namespaced: bool,
+    /// Most bytes one Encrypt or Decrypt call may stream in.
+    max_input: usize,
+    /// The administrator's password policy, applied to passwords used to
+    /// encrypt.
+    policy: Policy,
+    limiter: Arc<Limiter>,
+    jobs: Option<JobQueue>,
+    uploads: Option<Uploads>,
+}
+
+impl Service {
+    /// The caller's key namespace: the subject common name of its client
+    /// certificate, or `None` when the server runs without client auth.
+    fn namespace<T>(&self, request: &Request<T>) -> Result<Option<String>, Status> {
+        if !self.namespaced {
+            return Ok(None);
+        }
+        let certs = request
+            .peer_certs()
+            .ok_or_else(|| Status::unauthenticated(t!("server-needs-certificate")))?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| Status::unauthenticated(t!("server-needs-certificate")))?;
+        common_name(leaf)
+            .map(Some)
+            .ok_or_else(|| Status::permission_denied(t!("server-no-common-name")))
+    }
+
+    /// Checks a client's password (before scoping) against the policy;
+    /// there is no override on the server.
+    fn check_policy(&self, password: &str) -> Result<(), Status> {
+        match self.policy.violation(password) {
+            Some(reason) => Err(Status::failed_precondition(reason)),
+            None => Ok(()),
+        }
+    }
+
+    fn jobs(&self) -> Result<&JobQueue, Status> {
+        self.jobs
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented(t!("server-jobs-disabled")))
+    }
+
+    fn uploads(&self) -> Result<&Uploads, Status> {
+        self.uploads
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented(t!("server-uploads-disabled")))
+    }
+
+    /// Handles one call. The outer error rejects the request before any
+    /// cryptographic work; the inner one reports a failed operation.
+    async fn run(
+        &self,
+        operation: Operation,
+        request: Request<Streaming<CryptRequest>>,
+    ) -> Result<Result<Vec<u8>, Status>, Status> {
+        let namespace = self.namespace(&request)?;
+        let client = client_id(&request, &namespace);
+        self.limiter.admit(&client)?;
+        let (password, data) = collect_input(request.into_inner(), self.max_input).await?;
+        if let Operation::Encrypt = operation {
+            self.check_policy(&password)?;
+        }
+        METRICS.add_input(operation, data.len());
+        let password = scoped_password(namespace, password);
+
+        let output = tokio::task::spawn_blocking(move || match operation {
+            Operation::Encrypt => encrypt_bytes(&password, &data),
+            Operation::Decrypt => decrypt_bytes(&password, &data),
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(match (operation, output) {
+            (Operation::Encrypt, output) => output.map_err(|e| Status::internal(format!("{e:#}"))),
+            (Operation::Decrypt, Ok(output)) => {
+                self.limiter.record_success(&client);
+                Ok(output)
+            }
+            (Operation::Decrypt, Err(e)) => {
+                self.limiter.record_failure(&client);
+                Err(match e.downcast_ref::<DecryptError>() {
+                    Some(DecryptError::WrongKey) => {
+                        Status::invalid_argument(t!("server-wrong-password"))
+                    }
+                    Some(e) if e.is_damage() => Status::data_loss(t!("server-corrupted")),
+                    _ => Status::invalid_argument(t!("server-wrong-password-or-corrupted")),
+                })
+            }
+        })
+    }
+}
+
+/// Which client a request counts against: its namespace, or its address when
+/// the server runs without client auth.
+fn client_id<T>(request: &Request<T>, namespace: &Option<String>) -> String {
+    match (namespace, request.remote_addr()) {
+        (Some(namespace), _) => namespace.clone(),
+        (None, Some(addr)) => addr.ip().to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+#[derive(Default)]
+struct ClientState {
+    window_start: Option<Instant>,
+    requests: u32,
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+struct Limiter {
+    per_minute: u32,
+    backoff: Duration,
+    clients: Mutex<HashMap<String, ClientState>>,
+}
+
+impl Limiter {
+    /// Counts a request from `client`, refusing it while the client is over
+    /// its rate limit or locked out after failed decryptions.
+    fn admit(&self, client: &str) -> Result<(), Status> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, state| {
+                state.locked_until.is_some_and(|until| until > now)
+                    || state
+                        .window_start
+                        .is_some_and(|start| now - start < RATE_WINDOW)
+            });
+        }
+        let state = clients.entry(client.to_owned()).or_default();
+
+        if let Some(until) = state.locked_until.filter(|&until| until > now) {
+            return Err(Status::resource_exhausted(t!(
+                "server-locked-out",
+                seconds = (until - now).as_secs() + 1
+            )));
+        }
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        if state
+            .window_start
+            .is_none_or(|start| now - start >= RATE_WINDOW)
+        {
+            state.window_start = Some(now);
+            state.requests = 0;
+        }
+        if state.requests >= self.per_minute {
+            return Err(Status::resource_exhausted(t!(
+                "server-rate-limited",
+                limit = self.per_minute
+            )));
+        }
+        state.requests += 1;
+        Ok(())
+    }
+
+    fn record_failure(&self, client: &str) {
+        let mut clients = self.clients.lock().unwrap();
+        let state = clients.entry(client.to_owned()).or_default();
+        state.failures += 1;
+        let delay = self
+            .backoff
+            .saturating_mul(1 << (state.failures - 1).min(20))
+            .min(MAX_BACKOFF);
+        state.locked_until = Some(Instant::now() + delay);
+    }
+
+    fn record_success(&self, client: &str) {
+        if let Some(state) = self.clients.lock().unwrap().get_mut(client) {
+            state.failures = 0;
+            state.locked_until = None;
+        }
+    }
+}
+
+/// The password the key is actually derived from for authenticated clients:
+/// `<length>:<namespace>:<password>`, with the namespace's length in bytes so
+/// that no other namespace and password make the same string.
+fn scoped_password(namespace: Option<String>, password: String) -> String {
+    match namespace {
+        Some(namespace) => format!("{}:{namespace}:{password}", namespace.len()),
+        None => password,
+    }
+}
+
+fn common_name(der: &[u8]) -> Option<String> {
+    let cert = x509_cert::Certificate::from_der(der).ok()?;
+    let subject = cert.tbs_certificate.subject;
+    subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|attribute| attribute.oid == CN)
+        .filter(|attribute| {
+            matches!(
+                attribute.value.tag(),
+                Tag::Utf8String | Tag::PrintableString | Tag::Ia5String
+            )
+        })
+        .and_then(|attribute| String::from_utf8(attribute.value.value().to_vec()).ok())
+}
+
+#[tonic::async_trait]
+impl FileEncryptor for Service {
+    type EncryptStream = ChunkStream;
+    type DecryptStream = ChunkStream;
+
+    async fn encrypt(
+        &self,
+        request: Request<Streaming<CryptRequest>>,
+    ) -> Result<Response<ChunkStream>, Status> {
+        let outcome = self.run(Operation::Encrypt, request).await;
+        METRICS.record(Operation::Encrypt, &outcome);
+        Ok(Response::new(chunked(outcome??)))
+    }
+
+    async fn decrypt(
+        &self,
+        request: Request<Streaming<CryptRequest>>,
+    ) -> Result<Response<ChunkStream>, Status> {
+        let outcome = self.run(Operation::Decrypt, request).await;
+        METRICS.record(Operation::Decrypt, &outcome);
+        Ok(Response::new(chunked(outcome??)))
+    }
+
+    async fn submit_job(&self, request: Request<JobRequest>) -> Result<Response<Job>, Status> {
+        let namespace = self.namespace(&request)?;
+        let client = client_id(&request, &namespace);
+        self.limiter.admit(&client)?;
+        let jobs = self.jobs()?;
+        let mut job_request = request.into_inner();
+        if job_request.password.is_empty() {
+            return Err(Status::invalid_argument(t!("server-job-needs-password")));
+        }
+        if job_request.operation == i32::from(JobOperation::Encrypt) {
+            self.check_policy(&job_request.password)?;
+        }
+        job_request.password = scoped_password(namespace.clone(), job_request.password);
+        let job = jobs.submit(namespace, job_request, self.limiter.clone(), client)?;
+        Ok(Response::new(job))
+    }
+
+    async fn get_job(&self, request: Request<JobQuery>) -> Result<Response<Job>, Status> {
+        let namespace = self.namespace(&request)?;
+        let job = self.jobs()?.get(&namespace, &request.get_ref().id)?;
+        Ok(Response::new(job))
+    }
+
+    async fn create_upload(
+        &self,
+        request: Request<UploadRequest>,
+    ) -> Result<Response<Upload>, Status> {
+        let namespace = self.namespace(&request)?;
+        let client = client_id(&request, &namespace);
+        self.limiter.admit(&client)?;
+        let upload = self.uploads()?.create(namespace, request.get_ref())?;
+        Ok(Response::new(upload))
+    }
+
+    async fn append_upload(
+        &self,
+        request: Request<Streaming<UploadSegment>>,
+    ) -> Result<Response<Upload>, Status> {
+        let namespace = self.namespace(&request)?;
+        let client = client_id(&request, &namespace);
+        self.limiter.admit(&client)?;
+        let uploads = self.uploads()?;
+        let mut segments = request.into_inner();
+        let first = segments
+            .next()
+            .await
+            .ok_or_else(|| Status::invalid_argument(t!("server-empty-stream")))??;
+        if first.password.is_empty() {
+            return Err(Status::invalid_argument(t!("server-first-needs-password")));
+        }
+        // Only the call that starts the file picks its password.
+        if first.offset == 0 {
+            self.check_policy(&first.password)?;
+        }
+        let password = scoped_password(namespace.clone(), first.password.clone());
+        let upload = uploads
+            .append(
+                &namespace,
+                first,
+                segments,
+                password,
+                &self.limiter,
+                &client,
+            )
+            .await?;
+        Ok(Response::new(upload))
+    }
+
+    async fn get_upload(&self, request: Request<UploadQuery>) -> Result<Response<Upload>, Status> {
+        let namespace = self.namespace(&request)?;
+        let upload = self.uploads()?.get(&namespace, &request.get_ref().id)?;
+        Ok(Response::new(upload))
+    }
+}
+
+/// Reads the whole request stream, taking the password from the first message.
+async fn collect_input(
+    mut stream: Streaming<CryptRequest>,
+    max_input: usize,
+) -> Result<(String, Vec<u8>), Status> {
+    let first = stream
+        .next()
+        .await
+        .ok_or_else(|| Status::invalid_argument(t!("server-empty-stream")))??;
+    if first.password.is_empty() {
+        return Err(Status::invalid_argument(t!("server-first-needs-password")));
+    }
+    let mut data = first.data;
+    while let Some(message) = stream.next().await {
+        data.extend_from_slice(&message?.data);
+        if data.len() > max_input {
+            return Err(Status::resource_exhausted(t!(
+                "server-input-too-large",
+                limit = max_input
+            )));
+        }
+    }
+    Ok((first.password, data))
+}
+
+fn chunked(data: Vec<u8>) -> ChunkStream {
+    let chunks: Vec<_> = data
+        .chunks(RESPONSE_CHUNK_LENGTH)
+        .map(|chunk| {
+            Ok(Chunk {
+                data: chunk.to_vec(),
+            })
+        })
+        .collect();
+    Box::pin(tokio_stream::iter(chunks))
+}
+
+pub fn serve(options: &ServeOptions) -> Result<()> {
+    let mut builder = Server::builder();
+    match (&options.tls_cert, &options.tls_key) {
+        (Some(cert), Some(key)) => {
+            let identity = Identity::from_pem(read_pem(cert)?, read_pem(key)?);
+            let mut tls = ServerTlsConfig::new().identity(identity);
+            if let Some(ca) = &options.client_ca {
+                tls = tls.client_ca_root(Certificate::from_pem(read_pem(ca)?));
+            }
+            builder = builder
+                .tls_config(tls)
+                .with_context(|| t!("server-tls-invalid"))?;
+        }
+        _ if !options.allow_unauthenticated => {
+            bail!(t!("server-needs-tls"))
+        }
+        _ => {}
+    }
+    if options.client_ca.is_none() && !options.allow_unauthenticated {
+        bail!(t!("server-needs-client-ca"));
+    }
+    let schedules = &crate::config::get().schedules;
+    if !schedules.is_empty() && options.job_root.is_none() {
+        bail!(t!("schedule-needs-job-root"));
+    }
+    let limits = options.limits;
+    let max_input = [limits.max_file_size, limits.max_total_bytes]
+        .into_iter()
+        .flatten()
+        .fold(MAX_INPUT_LENGTH, |max, limit| {
+            max.min(usize::try_from(limit).unwrap_or(usize::MAX))
+        });
+    let service = Service {
+        namespaced: options.client_ca.is_some(),
+        max_input,
+        policy: Policy::load()?,
+        limiter: Arc::new(Limiter {
+            per_minute: options.rate_limit,
+            backoff: Duration::from_secs(options.failure_backoff),
+            clients: Mutex::new(HashMap::new()),
+        }),
+        jobs: options
+            .job_root
+            .clone()
+            .map(|root| JobQueue::new(root, options.max_jobs, limits)),
+        uploads: options
+            .job_root
+            .clone()
+            .map(|root| Uploads::new(root, limits)),
+    };
+
+    let scheduled = service.jobs.clone().filter(|_| !schedules.is_empty());
+    let limiter = service.limiter.clone();
+    file_encryptor::observe_key_derivation(|elapsed| METRICS.observe_kdf(elapsed));
+
+    let listen = options.listen;
+    let runtime = tokio::runtime::Runtime::new().with_context(|| t!("server-runtime-failed"))?;
+    runtime.block_on(async {
+        if let Some(metrics_listen) = options.metrics_listen {
+            let metrics = tokio::net::TcpListener::bind(metrics_listen)
+                .await
+                .with_context(|| {
+                    t!(
+                        "server-metrics-listen-failed",
+                        address = metrics_listen.to_string()
+                    )
+                })?;
+            tokio::spawn(metrics::serve(metrics));
+        }
+        if let Some(jobs) = &scheduled {
+            schedules::start(schedules, jobs, &limiter);
+        }
+        log::notice(&t!("server-serving", address = listen.to_string()));
+        builder
+            .add_service(FileEncryptorServer::new(service))
+            .serve(listen)
+            .await
+            .with_context(|| t!("server-failed", address = listen.to_string()))
+    })
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| t!("file-unreadable", path = path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::file_encryptor_client::FileEncryptorClient;
+    use tonic::transport::{server::TcpIncoming, Channel, ClientTlsConfig};
+    use x509_cert::der::{DecodePem, Encode};
+
+    /// A service without client auth, limits or jobs.
+    fn service() -> Service {
+        Service {
+            namespaced: false,
+            max_input: MAX_INPUT_LENGTH,
+            policy: Policy::default(),
+            limiter: Arc::new(Limiter {
+                per_minute: 0,
+                backoff: Duration::ZERO,
+                clients: Mutex::new(HashMap::new()),
+            }),
+            jobs: None,
+            uploads: None,
+        }
+    }
+
+    /// Serves `service` on a local port, returning the address.
+    async fn listen(service: Service, mut builder: Server) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            builder
+                .add_service(FileEncryptorServer::new(service))
+                .serve_with_incoming(incoming),
+        );
+        addr
+    }
+
+    async fn connect(service: Service) -> FileEncryptorClient<Channel> {
+        let addr = listen(service, Server::builder()).await;
+        FileEncryptorClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap()
+    }
+
+    /// Makes an Encrypt or Decrypt call sending `data` in `pieces` messages,
+    /// the first with `password`.
+    async fn call(
+        client: &mut FileEncryptorClient<Channel>,
+        operation: Operation,
+        password: &str,
+        data: &[u8],
+        pieces: usize,
+    ) -> Result<Vec<u8>, Status> {
+        let requests: Vec<_> = data
+            .chunks(data.len().div_ceil(pieces).max(1))
+            .enumerate()
+            .map(|(i, data)| CryptRequest {
+                password: if i == 0 { password.to_owned() } else { String::new() },
+                data: data.to_vec(),
+            })
+            .collect();
+        let requests = tokio_stream::iter(requests);
+        let mut response = match operation {
+            Operation::Encrypt => client.encrypt(requests).await?,
+            Operation::Decrypt => client.decrypt(requests).await?,
+        }
+        .into_inner();
+        let mut output = Vec::new();
+        while let Some(chunk) = response.next().await {
+            output.extend(chunk?.data);
+        }
+        Ok(output)
+    }
+
+    #[tokio::test]
+    async fn calls_round_trip_and_report_why_they_fail() {
+        let mut client = connect(service()).await;
+        let plaintext = b"streamed through the service".repeat(100);
+        let file = call(&mut client, Operation::Encrypt, "pw", &plaintext, 7)
+            .await
+            .unwrap();
+        assert_eq!(file_encryptor::decrypt_bytes("pw", &file).unwrap(), plaintext);
+        let decrypted = call(&mut client, Operation::Decrypt, "pw", &file, 3).await;
+        assert_eq!(decrypted.unwrap(), plaintext);
+
+        let wrong = call(&mut client, Operation::Decrypt, "wrong", &file, 1).await;
+        assert_eq!(wrong.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let mut damaged = file.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 1;
+        let damaged = call(&mut client, Operation::Decrypt, "pw", &damaged, 1).await;
+        assert_eq!(damaged.unwrap_err().code(), tonic::Code::DataLoss);
+        let unkeyed = call(&mut client, Operation::Encrypt, "", b"data", 1).await;
+        assert_eq!(unkeyed.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let mut client = connect(Service {
+            max_input: 10,
+            ..service()
+        })
+        .await;
+        let large = call(&mut client, Operation::Encrypt, "pw", &[0; 11], 2).await;
+        assert_eq!(large.unwrap_err().code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[test]
+    fn scoped_passwords_of_different_clients_differ() {
+        let scoped = |namespace: &str, password: &str| {
+            scoped_password(Some(namespace.to_owned()), password.to_owned())
+        };
+        assert_eq!(scoped("alice", "pw"), "5:alice:pw");
+        assert_ne!(scoped("a", "b:c"), scoped("a:b", "c"));
+        assert_eq!(scoped_password(None, "pw".to_owned()), "pw");
+    }
+
+    fn limiter(per_minute: u32, backoff: Duration) -> Limiter {
+        Limiter {
+            per_minute,
+            backoff,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn clients_are_held_to_their_own_rate() {
+        let limited = limiter(2, Duration::ZERO);
+        assert!(limited.admit("a").is_ok());
+        assert!(limited.admit("a").is_ok());
+        let refused = limited.admit("a").unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::ResourceExhausted);
+        assert!(limited.admit("b").is_ok());
+        let unlimited = limiter(0, Duration::ZERO);
+        assert!((0..100).all(|_| unlimited.admit("a").is_ok()));
+    }
+
+    #[test]
+    fn failures_lock_clients_out_for_longer_each_time() {
+        let limiter = limiter(0, Duration::from_secs(60));
+        let lockout = |limiter: &Limiter| {
+            let clients = limiter.clients.lock().unwrap();
+            clients["a"].locked_until.unwrap() - Instant::now()
+        };
+        limiter.record_failure("a");
+        assert_eq!(limiter.admit("a").unwrap_err().code(), tonic::Code::ResourceExhausted);
+        assert!(limiter.admit("b").is_ok());
+        assert!(lockout(&limiter) <= Duration::from_secs(60));
+        limiter.record_failure("a");
+        assert!(lockout(&limiter) > Duration::from_secs(60));
+        for _ in 0..30 {
+            limiter.record_failure("a");
+        }
+        assert!(lockout(&limiter) <= MAX_BACKOFF);
+        limiter.record_success("a");
+        assert!(limiter.admit("a").is_ok());
+        limiter.record_failure("a");
+        assert!(lockout(&limiter) <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn a_wrong_password_locks_the_caller_out() {
+        let mut client = connect(Service {
+            limiter: Arc::new(limiter(0, Duration::from_secs(60))),
+            ..service()
+        })
+        .await;
+        let file = call(&mut client, Operation::Encrypt, "pw", b"data", 1)
+            .await
+            .unwrap();
+        let wrong = call(&mut client, Operation::Decrypt, "wrong", &file, 1).await;
+        assert_eq!(wrong.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let locked = call(&mut client, Operation::Decrypt, "pw", &file, 1).await;
+        assert_eq!(locked.unwrap_err().code(), tonic::Code::ResourceExhausted);
+    }
+
+    /// A fixture from `src/grpc/certs`, all issued by `ca.pem` and valid until
+    /// 2126: `server` for `localhost`, client certificates for `alice` and
+    /// `bob`, and `anonymous`, one without a common name.
+    fn pem(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/grpc/certs").join(name);
+        std::fs::read(path).unwrap()
+    }
+
+    /// Connects over TLS as the client with certificate `name`.
+    async fn connect_as(addr: SocketAddr, name: &str) -> FileEncryptorClient<Channel> {
+        let tls = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(pem("ca.pem")))
+            .domain_name("localhost")
+            .identity(Identity::from_pem(pem(&format!("{name}.pem")), pem(&format!("{name}.key"))));
+        let channel = Channel::from_shared(format!("https://{addr}"))
+            .unwrap()
+            .tls_config(tls)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        FileEncryptorClient::new(channel)
+    }
+
+    #[test]
+    fn common_names_are_read_from_certificates() {
+        let der = |name: &str| {
+            let pem = pem(&format!("{name}.pem"));
+            x509_cert::Certificate::from_pem(pem).unwrap().to_der().unwrap()
+        };
+        assert_eq!(common_name(&der("alice")).as_deref(), Some("alice"));
+        assert_eq!(common_name(&der("anonymous")), None);
+        assert_eq!(common_name(b"not a certificate"), None);
+    }
+
+    #[tokio::test]
+    async fn each_client_certificate_has_its_own_keys() {
+        let tls = ServerTlsConfig::new()
+            .identity(Identity::from_pem(pem("server.pem"), pem("server.key")))
+            .client_ca_root(Certificate::from_pem(pem("ca.pem")));
+        let namespaced = || Service {
+            namespaced: true,
+            ..service()
+        };
+        let addr = listen(namespaced(), Server::builder().tls_config(tls).unwrap()).await;
+        let mut alice = connect_as(addr, "alice").await;
+        let mut bob = connect_as(addr, "bob").await;
+
+        let file = call(&mut alice, Operation::Encrypt, "pw", b"alice's", 1)
+            .await
+            .unwrap();
+        let decrypted = call(&mut alice, Operation::Decrypt, "pw", &file, 1).await;
+        assert_eq!(decrypted.unwrap(), b"alice's");
+        let by_bob = call(&mut bob, Operation::Decrypt, "pw", &file, 1).await;
+        assert_eq!(by_bob.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let scoped = scoped_password(Some("alice".to_owned()), "pw".to_owned());
+        assert_eq!(file_encryptor::decrypt_bytes(&scoped, &file).unwrap(), b"alice's");
+
+        let mut anonymous = connect_as(addr, "anonymous").await;
+        let unnamed = call(&mut anonymous, Operation::Encrypt, "pw", b"data", 1).await;
+        assert_eq!(unnamed.unwrap_err().code(), tonic::Code::PermissionDenied);
+        // Without TLS there is no certificate to take a namespace from.
+        let mut plain = connect(namespaced()).await;
+        let unauthenticated = call(&mut plain, Operation::Encrypt, "pw", b"data", 1).await;
+        assert_eq!(unauthenticated.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+}
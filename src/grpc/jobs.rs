@@ -0,0 +1,578 @@
+//! Asynchronous server-side jobs (`SubmitJob`/`GetJob`): encrypt or decrypt a
+//! file or directory tree under `--job-root` without the client holding a
+//! connection open.
+//!
+//! Each client works under `<job-root>/<namespace>` (the root itself without
+//! client auth) and only sees its own jobs. At most `--max-jobs` run at once;
+//! the rest wait queued. Job status lives in memory, so it is lost when the
+//! server restarts.
+//!
+//! With `all_or_nothing`, outputs are staged (see [`crate::staging`]) and renamed
+//! into place only after every file succeeded. A failed job
+//! removes what it staged, and a job cut short by a crash leaves only staged
+//! names behind, never a partly processed tree under the final names. With
+//! `keep_going`, a failed file is listed in the job's `failures` and the job
+//! moves on; the job still ends as failed if any file did.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Semaphore;
+use tonic::Status;
+
+use file_encryptor::i18n::t;
+
+use super::{
+    proto::{FileFailure, Job, JobOperation, JobRequest, JobState},
+    Limiter,
+};
+use crate::{
+    entropy, read_file_bytes,
+    staging::Staging,
+    walk::walk,
+    write_file_bytes, Encryptor, IoOptions, Limits,
+};
+
+const MAX_RETAINED_JOBS: usize = 10_000;
+
+struct Entry {
+    owner: Option<String>,
+    job: Job,
+}
+
+/// One file of a job, with the path relative to the client's root for
+/// status messages (so errors don't reveal the server's layout).
+struct Task {
+    name: PathBuf,
+    input: PathBuf,
+    output: PathBuf,
+    size: u64,
+}
+
+enum Failure {
+    Decrypt(String),
+    Other(String),
+    /// Files that failed in a `keep_going` job, and whether any of them was a
+    /// failed decryption.
+    Files(Vec<FileFailure>, bool),
+}
+
+impl Failure {
+    /// Names the file the failure is about.
+    fn of(self, task: &Task) -> Failure {
+        let named = |error| format!("{:?}: {error}", task.name);
+        match self {
+            Failure::Decrypt(error) => Failure::Decrypt(named(error)),
+            Failure::Other(error) => Failure::Other(named(error)),
+            files => files,
+        }
+    }
+}
+
+/// What a job does when a file fails.
+#[derive(Clone, Copy)]
+enum Mode {
+    Stop,
+    AllOrNothing,
+    KeepGoing,
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    root: PathBuf,
+    limits: Limits,
+    slots: Arc<Semaphore>,
+    jobs: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl JobQueue {
+    pub fn new(root: PathBuf, max_jobs: usize, limits: Limits) -> Self {
+        JobQueue {
+            root,
+            limits,
+            slots: Arc::new(Semaphore::new(max_jobs.max(1))),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Validates and queues a job, returning its initial status.
+    pub fn submit(
+        &self,
+        namespace: Option<String>,
+        request: JobRequest,
+        limiter: Arc<Limiter>,
+        client: String,
+    ) -> Result<Job, Status> {
+        let operation = JobOperation::try_from(request.operation)
+            .ok()
+            .filter(|&operation| operation != JobOperation::Unspecified)
+            .ok_or_else(|| Status::invalid_argument(t!("server-job-operation-unknown")))?;
+        let mode = match (request.all_or_nothing, request.keep_going) {
+            (false, false) => Mode::Stop,
+            (true, false) => Mode::AllOrNothing,
+            (false, true) => Mode::KeepGoing,
+            (true, true) => return Err(Status::invalid_argument(t!("server-job-modes-conflict"))),
+        };
+        let base = match &namespace {
+            Some(namespace) => self.root.join(relative(namespace)?),
+            None => self.root.clone(),
+        };
+        let tasks = plan(operation, &base, &request.input, &request.output)?;
+        let sizes: Vec<_> = tasks
+            .iter()
+            .map(|task| (task.name.display().to_string(), task.size))
+            .collect();
+        self.limits
+            .check(&sizes)
+            .map_err(|e| Status::resource_exhausted(e.to_string()))?;
+
+        let id: [u8; 16] =
+            entropy::bytes().map_err(|e| Status::unavailable(e.to_string()))?;
+        let id = hex::encode(id);
+        let job = Job {
+            id: id.clone(),
+            state: JobState::Queued.into(),
+            files_done: 0,
+            files_total: tasks.len() as u64,
+            error: String::new(),
+            failures: Vec::new(),
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.len() >= MAX_RETAINED_JOBS {
+                jobs.retain(|_, entry| !finished(&entry.job));
+            }
+            if jobs.len() >= MAX_RETAINED_JOBS {
+                return Err(Status::resource_exhausted(t!("server-too-many-jobs")));
+            }
+            jobs.insert(
+                id.clone(),
+                Entry {
+                    owner: namespace,
+                    job: job.clone(),
+                },
+            );
+        }
+
+        let (slots, jobs, password) = (self.slots.clone(), self.jobs.clone(), request.password);
+        tokio::spawn(async move {
+            let _permit = slots.acquire_owned().await;
+            update(&jobs, &id, |job| job.state = JobState::Running.into());
+            let progress = {
+                let (jobs, id) = (jobs.clone(), id.clone());
+                move |done| update(&jobs, &id, |job| job.files_done = done)
+            };
+            let result = tokio::task::spawn_blocking(move || {
+                run(operation, &password, &tasks, mode, progress)
+            })
+            .await
+            .unwrap_or_else(|e| Err(Failure::Other(e.to_string())));
+            update(&jobs, &id, |job| match result {
+                Ok(()) => job.state = JobState::Succeeded.into(),
+                Err(Failure::Decrypt(error)) => {
+                    limiter.record_failure(&client);
+                    job.state = JobState::Failed.into();
+                    job.error = error;
+                }
+                Err(Failure::Other(error)) => {
+                    job.state = JobState::Failed.into();
+                    job.error = error;
+                }
+                Err(Failure::Files(failures, wrong_password)) => {
+                    if wrong_password {
+                        limiter.record_failure(&client);
+                    }
+                    job.state = JobState::Failed.into();
+                    job.error = t!(
+                        "server-job-files-failed",
+                        failed = failures.len(),
+                        total = job.files_total
+                    );
+                    job.failures = failures;
+                }
+            });
+        });
+        Ok(job)
+    }
+
+    pub fn get(&self, namespace: &Option<String>, id: &str) -> Result<Job, Status> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|entry| entry.owner == *namespace)
+            .map(|entry| entry.job.clone())
+            .ok_or_else(|| Status::not_found(t!("server-no-job")))
+    }
+}
+
+pub(super) fn finished(job: &Job) -> bool {
+    job.state == i32::from(JobState::Succeeded) || job.state == i32::from(JobState::Failed)
+}
+
+fn update(jobs: &Mutex<HashMap<String, Entry>>, id: &str, change: impl FnOnce(&mut Job)) {
+    if let Some(entry) = jobs.lock().unwrap().get_mut(id) {
+        change(&mut entry.job);
+    }
+}
+
+/// Accepts only plain relative paths, so a job cannot name anything outside
+/// the client's root.
+pub(super) fn relative(path: &str) -> Result<&Path, Status> {
+    let path = Path::new(path);
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(Status::invalid_argument(t!(
+            "server-path-not-relative",
+            path = path.display().to_string()
+        )));
+    }
+    Ok(path)
+}
+
+/// Lists the files a job will process. Symbolic links are skipped, and both
+/// ends must resolve inside `base`.
+fn plan(
+    operation: JobOperation,
+    base: &Path,
+    input: &str,
+    output: &str,
+) -> Result<Vec<Task>, Status> {
+    let (input_name, output_name) = (relative(input)?, relative(output)?);
+    let (input, output) = (base.join(input_name), base.join(output_name));
+    let not_found = || {
+        Status::not_found(t!(
+            "server-input-missing",
+            path = input_name.display().to_string()
+        ))
+    };
+    let base = base.canonicalize().map_err(|_| not_found())?;
+    let metadata = fs::symlink_metadata(&input).map_err(|_| not_found())?;
+    if !input
+        .canonicalize()
+        .is_ok_and(|path| path.starts_with(&base))
+        || !confined(&output, &base)
+    {
+        return Err(Status::permission_denied(t!("server-job-outside-root")));
+    }
+
+    if metadata.is_file() {
+        return Ok(vec![Task {
+            name: input_name.to_path_buf(),
+            input,
+            output,
+            size: metadata.len(),
+        }]);
+    }
+    if !metadata.is_dir() {
+        return Err(Status::invalid_argument(t!(
+            "server-input-not-file-or-dir",
+            path = input_name.display().to_string()
+        )));
+    }
+    let files = walk(&input).map_err(|e| {
+        Status::internal(t!(
+            "server-input-unlisted",
+            path = input_name.display().to_string(),
+            error = e.to_string()
+        ))
+    })?;
+    Ok(files
+        .into_iter()
+        .map(|(file, size)| {
+            let target = output.join(&file);
+            let target = match operation {
+                JobOperation::Decrypt if file.extension().is_some_and(|ext| ext == "enc") => {
+                    target.with_extension("")
+                }
+                JobOperation::Decrypt => with_suffix(target, ".dec"),
+                _ => with_suffix(target, ".enc"),
+            };
+            Task {
+                name: input_name.join(&file),
+                input: input.join(&file),
+                output: target,
+                size,
+            }
+        })
+        .collect())
+}
+
+pub(super) fn with_suffix(path: PathBuf, suffix: &str) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(suffix);
+    path.into()
+}
+
+/// Whether `path`, or its nearest existing ancestor, resolves inside `base`.
+pub(super) fn confined(path: &Path, base: &Path) -> bool {
+    path.ancestors()
+        .find_map(|ancestor| ancestor.canonicalize().ok())
+        .is_some_and(|resolved| resolved.starts_with(base))
+}
+
+fn run(
+    operation: JobOperation,
+    password: &str,
+    tasks: &[Task],
+    mode: Mode,
+    progress: impl Fn(u64),
+) -> Result<(), Failure> {
+    // One KDF run for the whole job rather than one per file.
+    let mut keys = Encryptor::new(password);
+    match mode {
+        Mode::Stop => {
+            for (done, task) in tasks.iter().enumerate() {
+                process(&mut keys, operation, task, &task.output)
+                    .map_err(|failure| failure.of(task))?;
+                progress(done as u64 + 1);
+            }
+            Ok(())
+        }
+        Mode::AllOrNothing => {
+            let staging = Staging::default();
+            for (done, task) in tasks.iter().enumerate() {
+                let staged = staging.stage(&task.output);
+                process(&mut keys, operation, task, &staged).map_err(|failure| failure.of(task))?;
+                progress(done as u64 + 1);
+            }
+            staging.commit().map_err(|output| {
+                let task = tasks.iter().find(|task| task.output == output);
+                let name = task.map_or(output.as_path(), |task| &task.name);
+                Failure::Other(format!("{}: {}", name.display(), t!("server-output-unmoved")))
+            })
+        }
+        Mode::KeepGoing => {
+            let (mut failures, mut wrong_password) = (Vec::new(), false);
+            for (done, task) in tasks.iter().enumerate() {
+                let error = match process(&mut keys, operation, task, &task.output) {
+                    Ok(()) => None,
+                    Err(Failure::Decrypt(error)) => {
+                        wrong_password = true;
+                        Some(error)
+                    }
+                    Err(Failure::Other(error)) => Some(error),
+                    Err(Failure::Files(..)) => unreachable!("process fails one file"),
+                };
+                if let Some(error) = error {
+                    failures.push(FileFailure {
+                        path: task.name.to_string_lossy().into_owned(),
+                        error,
+                    });
+                }
+                progress(done as u64 + 1);
+            }
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                Err(Failure::Files(failures, wrong_password))
+            }
+        }
+    }
+}
+
+/// Processes one file; errors don't name it, the caller does.
+fn process(
+    keys: &mut Encryptor,
+    operation: JobOperation,
+    task: &Task,
+    output_path: &Path,
+) -> Result<(), Failure> {
+    let data = read_file_bytes(&task.input, IoOptions::default())
+        .map_err(|_| Failure::Other(t!("server-file-unread")))?;
+    let output = match operation {
+        JobOperation::Decrypt => keys
+            .decrypt(&data)
+            .map_err(|_| Failure::Decrypt(t!("server-wrong-password-or-corrupted")))?,
+        _ => keys
+            .encrypt(&data)
+            .map_err(|e| Failure::Other(format!("{e:#}")))?,
+    };
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|_| Failure::Other(t!("server-file-dir-uncreated")))?;
+    }
+    write_file_bytes(output_path, &output, IoOptions::default())
+        .map_err(|_| Failure::Other(t!("server-output-unwritten")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter() -> Arc<Limiter> {
+        Arc::new(Limiter {
+            per_minute: 0,
+            backoff: Duration::ZERO,
+            clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn request(operation: JobOperation, input: &str, output: &str) -> JobRequest {
+        JobRequest {
+            password: "pw".to_owned(),
+            operation: operation.into(),
+            input: input.to_owned(),
+            output: output.to_owned(),
+            all_or_nothing: false,
+            keep_going: false,
+        }
+    }
+
+    fn submit(
+        queue: &JobQueue,
+        namespace: Option<&str>,
+        request: JobRequest,
+    ) -> Result<Job, Status> {
+        queue.submit(namespace.map(str::to_owned), request, limiter(), String::new())
+    }
+
+    /// Polls the job until it has finished.
+    async fn wait(queue: &JobQueue, namespace: Option<&str>, id: &str) -> Job {
+        loop {
+            let job = queue.get(&namespace.map(str::to_owned), id).unwrap();
+            if finished(&job) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    fn write(path: &Path, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[tokio::test]
+    async fn jobs_encrypt_and_decrypt_trees_under_the_clients_root() {
+        let root = tempfile::tempdir().unwrap();
+        let queue = JobQueue::new(root.path().to_owned(), 2, Limits::default());
+        let base = root.path().join("alice");
+        write(&base.join("in/a.txt"), b"a");
+        write(&base.join("in/sub/b.txt"), b"b");
+
+        let encrypt = request(JobOperation::Encrypt, "in", "out");
+        let job = submit(&queue, Some("alice"), encrypt).unwrap();
+        assert_eq!(job.state, i32::from(JobState::Queued));
+        assert_eq!(job.files_total, 2);
+        assert!(queue.get(&None, &job.id).is_err());
+        assert!(queue.get(&Some("bob".to_owned()), &job.id).is_err());
+        let job = wait(&queue, Some("alice"), &job.id).await;
+        assert_eq!(job.state, i32::from(JobState::Succeeded), "{}", job.error);
+        assert_eq!(job.files_done, 2);
+        let encrypted = fs::read(base.join("out/sub/b.txt.enc")).unwrap();
+        assert_eq!(Encryptor::new("pw").decrypt(&encrypted).unwrap(), b"b");
+
+        let decrypt = request(JobOperation::Decrypt, "out", "back");
+        let job = submit(&queue, Some("alice"), decrypt).unwrap();
+        let job = wait(&queue, Some("alice"), &job.id).await;
+        assert_eq!(job.state, i32::from(JobState::Succeeded), "{}", job.error);
+        assert_eq!(fs::read(base.join("back/a.txt")).unwrap(), b"a");
+        assert_eq!(fs::read(base.join("back/sub/b.txt")).unwrap(), b"b");
+    }
+
+    #[test]
+    fn jobs_outside_the_root_or_without_an_operation_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        write(&root.path().join("in.txt"), b"data");
+        let queue = JobQueue::new(root.path().to_owned(), 1, Limits::default());
+        let code = |request| submit(&queue, None, request).unwrap_err().code();
+
+        for (input, output) in [
+            ("../in.txt", "out"),
+            ("/in.txt", "out"),
+            ("in.txt", "a/../b"),
+            ("", "out"),
+        ] {
+            let request = request(JobOperation::Encrypt, input, output);
+            assert_eq!(code(request), tonic::Code::InvalidArgument, "{input} to {output}");
+        }
+        let missing = request(JobOperation::Encrypt, "missing", "out");
+        assert_eq!(code(missing), tonic::Code::NotFound);
+        let unspecified = request(JobOperation::Unspecified, "in.txt", "out");
+        assert_eq!(code(unspecified), tonic::Code::InvalidArgument);
+        let both = JobRequest {
+            all_or_nothing: true,
+            keep_going: true,
+            ..request(JobOperation::Encrypt, "in.txt", "out")
+        };
+        assert_eq!(code(both), tonic::Code::InvalidArgument);
+        let limited = JobQueue::new(
+            root.path().to_owned(),
+            1,
+            Limits {
+                max_file_size: Some(1),
+                ..Limits::default()
+            },
+        );
+        let large = submit(&limited, None, request(JobOperation::Encrypt, "in.txt", "out"));
+        assert_eq!(large.unwrap_err().code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_out_of_the_root_are_refused() {
+        let (root, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write(&outside.path().join("secret.txt"), b"secret");
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+        let queue = JobQueue::new(root.path().to_owned(), 1, Limits::default());
+        let escaping = request(JobOperation::Encrypt, "link/secret.txt", "out");
+        let escaping = submit(&queue, None, escaping);
+        assert_eq!(escaping.unwrap_err().code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn failed_files_stop_roll_back_or_are_listed() {
+        let root = tempfile::tempdir().unwrap();
+        let queue = JobQueue::new(root.path().to_owned(), 2, Limits::default());
+        write(&root.path().join("in/a.enc"), &Encryptor::new("pw").encrypt(b"a").unwrap());
+        write(&root.path().join("in/b.enc"), b"not an encrypted file");
+
+        let all_or_nothing = JobRequest {
+            all_or_nothing: true,
+            ..request(JobOperation::Decrypt, "in", "staged")
+        };
+        let job = submit(&queue, None, all_or_nothing).unwrap();
+        let job = wait(&queue, None, &job.id).await;
+        assert_eq!(job.state, i32::from(JobState::Failed));
+        assert!(job.error.contains("b.enc"), "{}", job.error);
+        assert!(!root.path().join("staged").exists());
+
+        let keep_going = JobRequest {
+            keep_going: true,
+            ..request(JobOperation::Decrypt, "in", "kept")
+        };
+        let job = submit(&queue, None, keep_going).unwrap();
+        let job = wait(&queue, None, &job.id).await;
+        assert_eq!(job.state, i32::from(JobState::Failed));
+        assert_eq!(job.files_done, 2);
+        let failed: Vec<_> = job.failures.iter().map(|failure| failure.path.as_str()).collect();
+        assert_eq!(failed, [Path::new("in").join("b.enc").to_str().unwrap()]);
+        assert_eq!(fs::read(root.path().join("kept/a")).unwrap(), b"a");
+    }
+
+    #[tokio::test]
+    async fn jobs_beyond_the_limit_wait_queued() {
+        let root = tempfile::tempdir().unwrap();
+        write(&root.path().join("in.txt"), b"data");
+        let queue = JobQueue::new(root.path().to_owned(), 1, Limits::default());
+        // Take the one slot, as a running job would.
+        let slot = queue.slots.clone().acquire_owned().await.unwrap();
+
+        let encrypt = request(JobOperation::Encrypt, "in.txt", "out.enc");
+        let job = submit(&queue, None, encrypt).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let queued = queue.get(&None, &job.id).unwrap();
+        assert_eq!(queued.state, i32::from(JobState::Queued));
+        assert!(!root.path().join("out.enc").exists());
+        drop(slot);
+        let done = wait(&queue, None, &job.id).await;
+        assert_eq!(done.state, i32::from(JobState::Succeeded), "{}", done.error);
+    }
+}
@@ -0,0 +1,266 @@
+//! `/metrics` and `/healthz` for `serve --grpc`, on a separate plain-HTTP
+//! listener (`--metrics-listen`) so scrapers need no client certificate.
+//!
+//! The counters are process-wide statics: the exposition format is simple
+//! enough to render by hand, and key derivation records its latency here
+//! without having to know which server asked for it.
+
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tonic::Status;
+
+use file_encryptor::i18n::t;
+
+use crate::log;
+
+/// Upper bounds, in seconds, of the KDF latency histogram buckets.
+const KDF_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const MAX_REQUEST_HEAD: usize = 8192;
+
+pub static METRICS: Metrics = Metrics::new();
+
+#[derive(Clone, Copy)]
+pub enum Operation {
+    Encrypt,
+    Decrypt,
+}
+
+impl Operation {
+    const ALL: [Operation; 2] = [Operation::Encrypt, Operation::Decrypt];
+
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Encrypt => "encrypt",
+            Operation::Decrypt => "decrypt",
+        }
+    }
+}
+
+/// How a call ended: `rejected` before any cryptographic work (auth, rate
+/// limits, malformed requests), `failed` in the operation itself (for
+/// decryption, usually a wrong password).
+const OUTCOMES: [&str; 3] = ["ok", "failed", "rejected"];
+
+pub struct Metrics {
+    operations: [[AtomicU64; OUTCOMES.len()]; Operation::ALL.len()],
+    input_bytes: [AtomicU64; Operation::ALL.len()],
+    output_bytes: [AtomicU64; Operation::ALL.len()],
+    kdf_buckets: [AtomicU64; KDF_BUCKETS.len()],
+    kdf_micros: AtomicU64,
+    kdf_count: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            operations: [const { [const { AtomicU64::new(0) }; OUTCOMES.len()] };
+                Operation::ALL.len()],
+            input_bytes: [const { AtomicU64::new(0) }; Operation::ALL.len()],
+            output_bytes: [const { AtomicU64::new(0) }; Operation::ALL.len()],
+            kdf_buckets: [const { AtomicU64::new(0) }; KDF_BUCKETS.len()],
+            kdf_micros: AtomicU64::new(0),
+            kdf_count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add_input(&self, operation: Operation, len: usize) {
+        self.input_bytes[operation as usize].fetch_add(len as u64, Relaxed);
+    }
+
+    /// Counts a finished call; the outer error means it was rejected, the
+    /// inner one that the operation failed.
+    pub fn record(&self, operation: Operation, outcome: &Result<Result<Vec<u8>, Status>, Status>) {
+        let index = match outcome {
+            Ok(Ok(output)) => {
+                self.output_bytes[operation as usize].fetch_add(output.len() as u64, Relaxed);
+                0
+            }
+            Ok(Err(_)) => 1,
+            Err(_) => 2,
+        };
+        self.operations[operation as usize][index].fetch_add(1, Relaxed);
+    }
+
+    pub fn observe_kdf(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, &bound) in self.kdf_buckets.iter().zip(&KDF_BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Relaxed);
+            }
+        }
+        self.kdf_micros
+            .fetch_add(elapsed.as_micros() as u64, Relaxed);
+        self.kdf_count.fetch_add(1, Relaxed);
+    }
+
+    /// Renders the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP file_encryptor_operations_total Encrypt and decrypt calls by outcome.\n",
+        );
+        out.push_str("# TYPE file_encryptor_operations_total counter\n");
+        for operation in Operation::ALL {
+            for (outcome, count) in OUTCOMES.iter().zip(&self.operations[operation as usize]) {
+                let _ = writeln!(
+                    out,
+                    "file_encryptor_operations_total{{operation=\"{}\",outcome=\"{outcome}\"}} {}",
+                    operation.name(),
+                    count.load(Relaxed)
+                );
+            }
+        }
+        for (name, help, counters) in [
+            ("input", "Bytes received", &self.input_bytes),
+            ("output", "Bytes returned", &self.output_bytes),
+        ] {
+            let _ = writeln!(out, "# HELP file_encryptor_{name}_bytes_total {help}.");
+            let _ = writeln!(out, "# TYPE file_encryptor_{name}_bytes_total counter");
+            for operation in Operation::ALL {
+                let _ = writeln!(
+                    out,
+                    "file_encryptor_{name}_bytes_total{{operation=\"{}\"}} {}",
+                    operation.name(),
+                    counters[operation as usize].load(Relaxed)
+                );
+            }
+        }
+        out.push_str("# HELP file_encryptor_kdf_duration_seconds Key derivation latency.\n");
+        out.push_str("# TYPE file_encryptor_kdf_duration_seconds histogram\n");
+        for (bound, bucket) in KDF_BUCKETS.iter().zip(&self.kdf_buckets) {
+            let _ = writeln!(
+                out,
+                "file_encryptor_kdf_duration_seconds_bucket{{le=\"{bound}\"}} {}",
+                bucket.load(Relaxed)
+            );
+        }
+        let count = self.kdf_count.load(Relaxed);
+        let _ = writeln!(
+            out,
+            "file_encryptor_kdf_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "file_encryptor_kdf_duration_seconds_sum {}",
+            self.kdf_micros.load(Relaxed) as f64 / 1e6
+        );
+        let _ = writeln!(out, "file_encryptor_kdf_duration_seconds_count {count}");
+        out
+    }
+}
+
+/// Answers scrapes on `listener` until the process exits.
+pub async fn serve(listener: TcpListener) {
+    if let Ok(addr) = listener.local_addr() {
+        log::notice(&t!("server-metrics-serving", address = addr.to_string()));
+    }
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        tokio::spawn(async move {
+            let _ = respond(stream).await;
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let request_line = head.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    let (method, path) = (parts.next(), parts.next());
+
+    let (status, content_type, body) = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            ("200 OK", "text/plain; version=0.0.4", METRICS.render())
+        }
+        (Some(b"GET"), Some(b"/healthz")) => ("200 OK", "text/plain", "ok\n".to_owned()),
+        (Some(b"GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_owned(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_and_key_derivations_are_counted() {
+        let metrics = Metrics::new();
+        metrics.add_input(Operation::Encrypt, 10);
+        metrics.record(Operation::Encrypt, &Ok(Ok(vec![0; 90])));
+        metrics.record(Operation::Decrypt, &Ok(Err(Status::invalid_argument(""))));
+        metrics.record(Operation::Decrypt, &Err(Status::resource_exhausted("")));
+        metrics.observe_kdf(Duration::from_millis(40));
+        metrics.observe_kdf(Duration::from_secs(20));
+
+        let rendered = metrics.render();
+        for line in [
+            "file_encryptor_operations_total{operation=\"encrypt\",outcome=\"ok\"} 1",
+            "file_encryptor_operations_total{operation=\"encrypt\",outcome=\"failed\"} 0",
+            "file_encryptor_operations_total{operation=\"decrypt\",outcome=\"failed\"} 1",
+            "file_encryptor_operations_total{operation=\"decrypt\",outcome=\"rejected\"} 1",
+            "file_encryptor_input_bytes_total{operation=\"encrypt\"} 10",
+            "file_encryptor_output_bytes_total{operation=\"encrypt\"} 90",
+            "file_encryptor_output_bytes_total{operation=\"decrypt\"} 0",
+            "file_encryptor_kdf_duration_seconds_bucket{le=\"0.025\"} 0",
+            "file_encryptor_kdf_duration_seconds_bucket{le=\"0.05\"} 1",
+            "file_encryptor_kdf_duration_seconds_bucket{le=\"10\"} 1",
+            "file_encryptor_kdf_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "file_encryptor_kdf_duration_seconds_sum 20.04",
+            "file_encryptor_kdf_duration_seconds_count 2",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{line} in\n{rendered}");
+        }
+    }
+
+    #[tokio::test]
+    async fn scrapes_and_health_checks_are_answered() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+        let get = |request: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let health = get("GET /healthz HTTP/1.1\r\nHost: x\r\n\r\n").await;
+        assert!(health.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(health.ends_with("\r\n\r\nok\n"));
+        let metrics = get("GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(metrics.contains("\r\n\r\n# HELP file_encryptor_operations_total "));
+        let missing = get("GET /other HTTP/1.1\r\n\r\n").await;
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let posted = get("POST /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(posted.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
@@ -0,0 +1,144 @@
+//! The `[schedule.NAME]` jobs of the config file, which the server submits to
+//! its [`JobQueue`] at the times of their cron expressions, as if a client
+//! without a namespace had sent them with `SubmitJob`. Each run, and how it
+//! ended, is printed and logged with `--log-file`; a failed run also starts
+//! the schedule's `on_failure` command. A run that is still going when the
+//! next is due is not started twice: the next run is the first due after it
+//! ends.
+
+use std::{
+    process::Command,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use file_encryptor::{date, i18n::t};
+
+use super::{
+    jobs::{finished, JobQueue},
+    proto::{JobOperation, JobRequest, JobState},
+    Limiter,
+};
+use crate::{
+    config::{Schedule, ScheduleOperation},
+    log, PasswordSource,
+};
+
+/// How often a running job is looked at.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The longest sleep before the clock is read again, so a clock that jumps
+/// or a machine that sleeps doesn't delay a run by long.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Starts the runs of every schedule on the current runtime.
+pub fn start(schedules: &'static [Schedule], jobs: &JobQueue, limiter: &Arc<Limiter>) {
+    for schedule in schedules {
+        tokio::spawn(run(schedule, jobs.clone(), limiter.clone()));
+    }
+}
+
+async fn run(schedule: &'static Schedule, jobs: JobQueue, limiter: Arc<Limiter>) {
+    loop {
+        let Some(next) = schedule.cron.next_after(SystemTime::now()) else {
+            return;
+        };
+        log::info(&t!(
+            "schedule-next",
+            name = schedule.name.as_str(),
+            time = date::format(next)
+        ));
+        while let Ok(wait) = next.duration_since(SystemTime::now()) {
+            tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+        }
+        log::notice(&t!("schedule-started", name = schedule.name.as_str()));
+        match job(schedule, &jobs, &limiter).await {
+            Ok(files) => log::notice(&t!(
+                "schedule-succeeded",
+                name = schedule.name.as_str(),
+                count = files
+            )),
+            Err(error) => {
+                log::failure(&t!(
+                    "schedule-failed",
+                    name = schedule.name.as_str(),
+                    error = error.as_str()
+                ));
+                if let Some(command) = &schedule.on_failure {
+                    notify(schedule, command, &error).await;
+                }
+            }
+        }
+    }
+}
+
+/// Submits one run of `schedule` and waits for it, returning the number of
+/// files it processed.
+async fn job(schedule: &Schedule, jobs: &JobQueue, limiter: &Arc<Limiter>) -> Result<u64, String> {
+    let source = PasswordSource {
+        password_env: None,
+        password_fd: None,
+        password_file: Some(schedule.password_file.display().to_string()),
+    };
+    let password = source
+        .read()
+        .map_err(|e| format!("{e:#}"))?
+        .ok_or_else(|| t!("password-empty"))?;
+    let request = JobRequest {
+        password: password.to_string(),
+        operation: match schedule.operation {
+            ScheduleOperation::Encrypt => JobOperation::Encrypt,
+            ScheduleOperation::Decrypt => JobOperation::Decrypt,
+        }
+        .into(),
+        input: schedule.input.clone(),
+        output: schedule.output.clone(),
+        all_or_nothing: schedule.all_or_nothing,
+        keep_going: schedule.keep_going,
+    };
+    let client = format!("schedule {}", schedule.name);
+    let mut job = jobs
+        .submit(None, request, limiter.clone(), client)
+        .map_err(|status| status.message().to_owned())?;
+    while !finished(&job) {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        job = jobs
+            .get(&None, &job.id)
+            .map_err(|status| status.message().to_owned())?;
+    }
+    match job.state() {
+        JobState::Succeeded => Ok(job.files_done),
+        _ => Err(job.error),
+    }
+}
+
+/// Runs the `on_failure` command of `schedule` through the shell.
+async fn notify(schedule: &Schedule, command: &str, error: &str) {
+    let mut shell = match cfg!(windows) {
+        true => {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        }
+        false => {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        }
+    };
+    shell
+        .arg(command)
+        .env("FILE_ENCRYPTOR_SCHEDULE", &schedule.name)
+        .env("FILE_ENCRYPTOR_ERROR", error);
+    let status = tokio::task::spawn_blocking(move || shell.status()).await;
+    let failure = match status {
+        Ok(Ok(status)) if status.success() => return,
+        Ok(Ok(status)) => status.to_string(),
+        Ok(Err(e)) => e.to_string(),
+        Err(e) => e.to_string(),
+    };
+    log::warn(&t!(
+        "schedule-notify-failed",
+        name = schedule.name.as_str(),
+        error = failure
+    ));
+}
@@ -0,0 +1,374 @@
+//! Resumable uploads (`CreateUpload`/`AppendUpload`/`GetUpload`), after the
+//! tus protocol: a client pushes a plaintext too large for one call in
+//! segments, over as many calls as its connection needs, into one `--stream`
+//! file under its job root.
+//!
+//! Each `AppendUpload` call encrypts its segment as it arrives, appending
+//! sealed chunks to the `<output>`[`PARTIAL_SUFFIX`] file, and commits only
+//! whole chunks: what a call leaves of a chunk when it ends or breaks off is
+//! dropped, and the upload's offset is the plaintext its committed chunks
+//! hold. The client resends from that offset, asking `GetUpload` for it if
+//! it lost the reply. The segment marked `last` ends the plaintext, and the
+//! finished file takes the output's name. Every call carries the password,
+//! and a resumed call derives the key again from the file's header, so no
+//! key outlives a call. As with jobs, uploads are tracked in memory and lost
+//! when the server restarts.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tonic::{Status, Streaming};
+
+use file_encryptor::{i18n::t, stream, DecryptError};
+
+use super::{
+    jobs::{confined, relative, with_suffix},
+    proto::{Upload, UploadRequest, UploadSegment},
+    Limiter,
+};
+use crate::{entropy, Encryptor, Limits};
+
+const MAX_UPLOADS: usize = 10_000;
+const PARTIAL_SUFFIX: &str = ".fe-upload";
+/// Messages a call hands the encrypting thread before it waits for it.
+const QUEUED_MESSAGES: usize = 4;
+
+struct Entry {
+    owner: Option<String>,
+    /// The output, relative to the client's root for limit messages.
+    name: String,
+    output: PathBuf,
+    /// Chunks sealed and written to the partial file.
+    chunks: u64,
+    /// Plaintext committed, which for an unfinished upload is what the
+    /// chunks hold.
+    offset: u64,
+    finished: bool,
+    /// Whether an `AppendUpload` call is working on it.
+    busy: bool,
+}
+
+impl Entry {
+    fn status(&self, id: &str) -> Upload {
+        Upload {
+            id: id.to_owned(),
+            offset: self.offset,
+            finished: self.finished,
+        }
+    }
+}
+
+pub struct Uploads {
+    root: PathBuf,
+    limits: Limits,
+    uploads: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+/// How an `AppendUpload` call's encryption ended.
+enum Sealed {
+    /// The last segment came, and the file is complete.
+    Finished(u64),
+    /// The call ended before the last segment.
+    Interrupted,
+}
+
+/// Clears an upload's `busy` flag when the call working on it ends, however
+/// it ends.
+struct Claim<'a> {
+    uploads: &'a Mutex<HashMap<String, Entry>>,
+    id: String,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.uploads.lock().unwrap().get_mut(&self.id) {
+            entry.busy = false;
+        }
+    }
+}
+
+impl Uploads {
+    pub fn new(root: PathBuf, limits: Limits) -> Self {
+        Uploads {
+            root,
+            limits,
+            uploads: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn create(
+        &self,
+        namespace: Option<String>,
+        request: &UploadRequest,
+    ) -> Result<Upload, Status> {
+        let base = match &namespace {
+            Some(namespace) => self.root.join(relative(namespace)?),
+            None => self.root.clone(),
+        };
+        let name = relative(&request.output)?;
+        let output = base.join(name);
+        fs::create_dir_all(&base).map_err(|e| {
+            Status::internal(t!("server-job-root-uncreated", error = e.to_string()))
+        })?;
+        let base = base.canonicalize().map_err(|e| {
+            Status::internal(t!("server-job-root-unresolved", error = e.to_string()))
+        })?;
+        if !confined(&output, &base) {
+            return Err(Status::permission_denied(t!("server-upload-outside-root")));
+        }
+        if output.exists() {
+            return Err(Status::already_exists(t!(
+                "server-output-exists",
+                name = name.display().to_string()
+            )));
+        }
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|_| Status::internal(t!("server-output-dir-uncreated")))?;
+        }
+
+        let id: [u8; 16] = entropy::bytes().map_err(|e| Status::unavailable(e.to_string()))?;
+        let id = hex::encode(id);
+        let entry = Entry {
+            owner: namespace,
+            name: name.display().to_string(),
+            output,
+            chunks: 0,
+            offset: 0,
+            finished: false,
+            busy: false,
+        };
+        let upload = entry.status(&id);
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.len() >= MAX_UPLOADS {
+            uploads.retain(|_, entry| !entry.finished);
+        }
+        if uploads.len() >= MAX_UPLOADS {
+            return Err(Status::resource_exhausted(t!("server-too-many-uploads")));
+        }
+        uploads.insert(id, entry);
+        Ok(upload)
+    }
+
+    pub fn get(&self, namespace: &Option<String>, id: &str) -> Result<Upload, Status> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|entry| entry.owner == *namespace)
+            .map(|entry| entry.status(id))
+            .ok_or_else(|| Status::not_found(t!("server-no-upload")))
+    }
+
+    /// Carries on the upload `first` names with its segment, under the
+    /// (scoped) `password`, returning where it got to.
+    pub async fn append(
+        &self,
+        namespace: &Option<String>,
+        first: UploadSegment,
+        mut segments: Streaming<UploadSegment>,
+        password: String,
+        limiter: &Limiter,
+        client: &str,
+    ) -> Result<Upload, Status> {
+        let (name, output, chunks, offset) = {
+            let mut uploads = self.uploads.lock().unwrap();
+            let entry = uploads
+                .get_mut(&first.id)
+                .filter(|entry| entry.owner == *namespace)
+                .ok_or_else(|| Status::not_found(t!("server-no-upload")))?;
+            if entry.finished {
+                return Err(Status::failed_precondition(t!("server-upload-finished")));
+            }
+            if entry.busy {
+                return Err(Status::aborted(t!("server-upload-busy")));
+            }
+            if first.offset != entry.offset {
+                return Err(Status::failed_precondition(t!(
+                    "server-upload-offset",
+                    offset = entry.offset,
+                    given = first.offset
+                )));
+            }
+            entry.busy = true;
+            (
+                entry.name.clone(),
+                entry.output.clone(),
+                entry.chunks,
+                entry.offset,
+            )
+        };
+        let id = first.id.clone();
+        let _claim = Claim {
+            uploads: &self.uploads,
+            id: id.clone(),
+        };
+
+        let partial = with_suffix(output.clone(), PARTIAL_SUFFIX);
+        let committed = Arc::new(AtomicU64::new(chunks));
+        let (sender, pieces) = mpsc::channel(QUEUED_MESSAGES);
+        let worker = {
+            let (partial, committed) = (partial.clone(), committed.clone());
+            tokio::task::spawn_blocking(move || {
+                seal(&password, &partial, chunks, pieces, &committed)
+            })
+        };
+        let mut refused = None;
+        let mut received = offset;
+        let mut message = Some(first);
+        while let Some(segment) = message.take() {
+            received += segment.data.len() as u64;
+            if let Err(e) = self.limits.check(&[(name.clone(), received)]) {
+                refused = Some(Status::resource_exhausted(e.to_string()));
+                break;
+            }
+            let last = segment.last;
+            if sender.send((segment.data, last)).await.is_err() || last {
+                break;
+            }
+            // A broken connection ends the segment like a finished call.
+            message = segments.next().await.and_then(Result::ok);
+        }
+        drop(sender);
+        let sealed = worker
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!(e.to_string())));
+
+        let mut uploads = self.uploads.lock().unwrap();
+        let entry = uploads
+            .get_mut(&id)
+            .ok_or_else(|| Status::not_found(t!("server-no-upload")))?;
+        entry.chunks = committed.load(Ordering::Acquire);
+        entry.offset = entry.chunks * u64::from(stream::CHUNK_LENGTH);
+        match sealed {
+            Ok(Sealed::Finished(length)) => {
+                if entry.output.exists() {
+                    return Err(Status::already_exists(t!(
+                        "server-output-exists",
+                        name = entry.name.as_str()
+                    )));
+                }
+                fs::rename(&partial, &entry.output)
+                    .map_err(|_| Status::internal(t!("server-output-unmoved")))?;
+                entry.finished = true;
+                entry.offset = length;
+            }
+            Ok(Sealed::Interrupted) => {}
+            Err(e) => {
+                return Err(match e.downcast_ref::<DecryptError>() {
+                    Some(DecryptError::WrongKey) => {
+                        limiter.record_failure(client);
+                        Status::invalid_argument(t!("server-wrong-password"))
+                    }
+                    _ => Status::internal(format!("{e:#}")),
+                })
+            }
+        }
+        match refused {
+            Some(status) => Err(status),
+            None => Ok(entry.status(&id)),
+        }
+    }
+}
+
+/// One call's segment, as the messages of the call hand it over. It reads
+/// to the end of the plaintext after the `last` message, and fails when the
+/// call ends before one, which stops the encryption at the last whole chunk.
+struct Segment {
+    pieces: mpsc::Receiver<(Vec<u8>, bool)>,
+    data: Vec<u8>,
+    at: usize,
+    last: bool,
+    cut: bool,
+    length: u64,
+}
+
+impl Read for Segment {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.at == self.data.len() {
+            if self.last {
+                return Ok(0);
+            }
+            match self.pieces.blocking_recv() {
+                Some((data, last)) => {
+                    (self.data, self.at, self.last) = (data, 0, last);
+                }
+                None => {
+                    self.cut = true;
+                    return Err(io::Error::other(t!("server-segment-cut")));
+                }
+            }
+        }
+        let n = buf.len().min(self.data.len() - self.at);
+        buf[..n].copy_from_slice(&self.data[self.at..self.at + n]);
+        self.at += n;
+        self.length += n as u64;
+        Ok(n)
+    }
+}
+
+/// Encrypts `pieces` onto the partial file after its first `chunks`
+/// chunks, counting the chunks written in `committed` as they are flushed.
+fn seal(
+    password: &str,
+    partial: &Path,
+    chunks: u64,
+    pieces: mpsc::Receiver<(Vec<u8>, bool)>,
+    committed: &AtomicU64,
+) -> anyhow::Result<Sealed> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(partial)?;
+    let mut keys = Encryptor::new(password).with_chunk_length(stream::CHUNK_LENGTH);
+    // Not even the header is kept of a file without a chunk, so its first
+    // call may give the password it likes.
+    let resumed = match chunks {
+        0 => None,
+        chunks => Some(stream::Resumed::read(io::BufReader::new(&file), chunks)?),
+    };
+    let output_offset = resumed.as_ref().map_or(0, stream::Resumed::output_offset);
+    file.set_len(output_offset)?;
+    file.seek(SeekFrom::Start(output_offset))?;
+    let mut segment = Segment {
+        pieces,
+        data: Vec::new(),
+        at: 0,
+        last: false,
+        cut: false,
+        length: 0,
+    };
+    let mut writer = io::BufWriter::new(file);
+    let sealed = stream::encrypt_from(
+        &mut keys,
+        resumed,
+        &mut segment,
+        &mut writer,
+        |writer, chunks| {
+            writer.flush()?;
+            committed.store(chunks, Ordering::Release);
+            Ok(())
+        },
+    );
+    match sealed {
+        Ok(_) => {
+            writer.into_inner()?.sync_all()?;
+            Ok(Sealed::Finished(
+                chunks * u64::from(stream::CHUNK_LENGTH) + segment.length,
+            ))
+        }
+        Err(_) if segment.cut => Ok(Sealed::Interrupted),
+        Err(e) => Err(e),
+    }
+}
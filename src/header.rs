@@ -0,0 +1,973 @@
+//! The versioned header every new file starts with. Each field is tagged or
+//! length-prefixed, so a reader knows exactly where the header ends, can tell
+//! it apart from the headerless files of earlier releases, and refuses
+//! versions and algorithms it doesn't know by name:
+//!
+//! ```text
+//! "FENCRYPT" | version (u8) | header length (u16 LE, whole header)
+//!     | cipher (u8) | key source (u8) | compression (u8) | KDF (u8)
+//!     | KDF parameter length (u8) | KDF parameters | salt length (u8) | salt
+//!     | nonce length (u8) | nonce | chunk length (u32 LE)
+//!     | key check length (u8) | key check
+//!     | metadata length (u16 LE) | metadata
+//!     | AAD check length (u8) | AAD check
+//!     | content checksum length (u8) | content checksum [| slot count (u8) | slots]
+//! ```
+//!
+//! The key check is a digest of the key that tells a wrong password or key
+//! file from a damaged file before anything is decrypted. The metadata is the
+//! original file's [`Metadata`] sealed under the file's key, empty when the
+//! file records none. The AAD check is a digest under the file's key of the
+//! associated data the caller bound the file to (`--aad`), empty when it is
+//! bound to nothing. The content checksum is the SHA-256 of the whole
+//! plaintext sealed under the file's key, which decryption compares with what
+//! it restored, empty when the writer couldn't know it up front, as for a
+//! stream from standard input. A key file alone records no KDF (id 0, no
+//! parameters), and neither does a file for
+//! [`recipient`](crate::recipient)s, whose header ends with one slot per
+//! recipient instead:
+//!
+//! ```text
+//! public key: 1 (u8) | sealed file key length (u8) | sealed file key
+//! password:   2 (u8) | KDF (u8) | KDF parameter length (u8) | KDF parameters
+//!     | salt length (u8) | salt | nonce length (u8) | nonce
+//!     | wrapped file key length (u8) | wrapped file key
+//! token:      3 (u8) | mechanism (u8) | token length (u8) | token
+//!     | ephemeral key length (u8) | ephemeral key | nonce length (u8) | nonce
+//!     | wrapped file key length (u8) | wrapped file key
+//! ```
+//!
+//! A token slot's mechanism is 1 for ECDH over P-256, and its token the
+//! identifier of the hardware token holding the key, such as a PKCS#11 URI.
+//!
+//! A password alone makes a file for recipients too, with just that
+//! password, so that every file has a random key of its own. The password's
+//! key is then derived once for many files, and wraps each one's key under a
+//! nonce of its own.
+//!
+//! A chunk length of 0 means the payload is a single AEAD message under the
+//! nonce. Otherwise it is in the chunked [`stream`](crate::stream) layout and
+//! the nonce is the STREAM prefix. The header, followed by the caller's
+//! associated data if any, is authenticated as associated data of every
+//! message, except that a file for recipients is authenticated with an
+//! empty slot list instead of its slots: a slot only
+//! opens the file if it holds the file key, and leaving them out lets
+//! `rekey` replace one without touching the payload. A compressed payload is
+//! compressed before it is encrypted, as ciphertext doesn't compress.
+
+use aes_gcm::{Aes256Gcm, Key};
+use anyhow::{anyhow, bail, Context, Result};
+use hmac::Hmac;
+use pbkdf2::pbkdf2;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::{
+    i18n::t,
+    recipient::{Slot, TokenMechanism},
+    DecryptError, KEY_LENGTH, PBKDF2_ITERATIONS, SALT_LENGTH,
+};
+
+pub const MAGIC: &[u8; 8] = b"FENCRYPT";
+pub const VERSION: u8 = 1;
+/// The magic, version and header length, which say how much more to read.
+pub const PREFIX_LENGTH: usize = MAGIC.len() + 1 + 2;
+
+const CIPHER_AES_256_GCM: u8 = 1;
+const CIPHER_CHACHA20_POLY1305: u8 = 2;
+const CIPHER_XCHACHA20_POLY1305: u8 = 3;
+const KEY_SOURCE_PASSWORD: u8 = 1;
+const KEY_SOURCE_KEY_FILE: u8 = 2;
+const KEY_SOURCE_PASSWORD_AND_KEY_FILE: u8 = 3;
+const KEY_SOURCE_RECIPIENTS: u8 = 4;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZSTD: u8 = 2;
+const KDF_NONE: u8 = 0;
+const KDF_PBKDF2_SHA256: u8 = 1;
+/// The authentication tag of every cipher, once per message or chunk.
+pub(crate) const TAG_LENGTH: usize = 16;
+/// STREAM spends this much of each nonce on the chunk counter and last-chunk
+/// flag, leaving the rest as the prefix the header stores.
+const STREAM_NONCE_OVERHEAD: usize = 5;
+const KDF_ARGON2ID: u8 = 2;
+const SLOT_PUBLIC_KEY: u8 = 1;
+const SLOT_PASSWORD: u8 = 2;
+const SLOT_TOKEN: u8 = 3;
+const TOKEN_ECDH_P256: u8 = 1;
+pub const KEY_CHECK_LENGTH: usize = 16;
+pub const AAD_CHECK_LENGTH: usize = 16;
+/// A SHA-256 digest.
+pub const CONTENT_CHECKSUM_LENGTH: usize = 32;
+/// Serialized metadata longer than this is refused, which leaves room for a
+/// file name of any length a filesystem allows.
+const MAX_METADATA_LENGTH: usize = 4 << 10;
+
+/// Headers asking for more work than this are refused, so a crafted file
+/// can't make decryption exhaust the machine.
+const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 << 20;
+const MAX_ARGON2_ITERATIONS: u32 = 1_000;
+const MAX_ARGON2_PARALLELISM: u32 = 64;
+/// Calibration times PBKDF2 over enough iterations to take at least this
+/// long, so that timer resolution and noise don't skew the estimate.
+const CALIBRATION_PROBE: Duration = Duration::from_millis(50);
+
+/// The AEAD the payload is sealed with. All take a 256-bit key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Cipher {
+    #[default]
+    Aes256Gcm,
+    /// Fast in software, for CPUs without AES instructions.
+    ChaCha20Poly1305,
+    /// ChaCha20-Poly1305 with a 192-bit nonce.
+    XChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn nonce_length(&self) -> usize {
+        match self {
+            Cipher::Aes256Gcm | Cipher::ChaCha20Poly1305 => 12,
+            Cipher::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    /// The random part of each chunk's nonce in the chunked format.
+    pub fn nonce_prefix_length(&self) -> usize {
+        self.nonce_length() - STREAM_NONCE_OVERHEAD
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => CIPHER_AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => CIPHER_CHACHA20_POLY1305,
+            Cipher::XChaCha20Poly1305 => CIPHER_XCHACHA20_POLY1305,
+        }
+    }
+}
+
+/// What the plaintext is compressed with before it is encrypted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn id(&self) -> u8 {
+        match self {
+            Compression::None => COMPRESSION_NONE,
+            Compression::Gzip => COMPRESSION_GZIP,
+            Compression::Zstd => COMPRESSION_ZSTD,
+        }
+    }
+}
+
+/// What the key is made from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeySource {
+    #[default]
+    Password,
+    KeyFile,
+    /// The password's key mixed with the key file, so both are needed.
+    PasswordAndKeyFile,
+    /// A random file key wrapped for each of several X25519 public keys and
+    /// passwords, any one of which opens the file.
+    Recipients,
+}
+
+impl KeySource {
+    pub fn needs_password(&self) -> bool {
+        matches!(self, KeySource::Password | KeySource::PasswordAndKeyFile)
+    }
+
+    pub fn needs_key_file(&self) -> bool {
+        matches!(self, KeySource::KeyFile | KeySource::PasswordAndKeyFile)
+    }
+
+    /// The error for unlocking a file of this key source with another.
+    pub fn mismatch(&self) -> anyhow::Error {
+        anyhow!(match self {
+            KeySource::Password => t!("key-source-password"),
+            KeySource::KeyFile => t!("key-source-key-file"),
+            KeySource::PasswordAndKeyFile => t!("key-source-password-and-key-file"),
+            KeySource::Recipients => t!("key-source-recipients"),
+        })
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            KeySource::Password => KEY_SOURCE_PASSWORD,
+            KeySource::KeyFile => KEY_SOURCE_KEY_FILE,
+            KeySource::PasswordAndKeyFile => KEY_SOURCE_PASSWORD_AND_KEY_FILE,
+            KeySource::Recipients => KEY_SOURCE_RECIPIENTS,
+        }
+    }
+}
+
+/// How a password becomes a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Default for Kdf {
+    /// PBKDF2-HMAC-SHA256, the only KDF of files from before the header.
+    fn default() -> Self {
+        Kdf::Pbkdf2 {
+            iterations: PBKDF2_ITERATIONS,
+        }
+    }
+}
+
+impl Kdf {
+    /// 64 MiB, 3 passes, 1 lane, so every guess costs an attacker 64 MiB of
+    /// memory as well as time.
+    pub const ARGON2ID: Kdf = Kdf::Argon2id {
+        memory_kib: 64 << 10,
+        iterations: 3,
+        parallelism: 1,
+    };
+
+    pub(crate) fn derive(&self, password: &str, salt: &[u8]) -> Result<Zeroizing<Key<Aes256Gcm>>> {
+        let mut key = Zeroizing::new(Key::<Aes256Gcm>::default());
+        let key_bytes = key.as_mut_slice();
+        match *self {
+            Kdf::Pbkdf2 { iterations } => {
+                pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, key_bytes)
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    argon2::Params::new(memory_kib, iterations, parallelism, Some(KEY_LENGTH))
+                        .map_err(|e| anyhow!(t!("kdf-invalid", error = e.to_string())))?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(password.as_bytes(), salt, key_bytes)
+                    .map_err(|e| anyhow!(t!("kdf-invalid", error = e.to_string())))?;
+            }
+        }
+        Ok(key)
+    }
+
+    /// How long deriving one key takes on this machine. This reads the clock,
+    /// which `wasm32-unknown-unknown` doesn't have.
+    pub fn time(self) -> Result<Duration> {
+        let started = Instant::now();
+        self.derive("benchmark", &[0; SALT_LENGTH])?;
+        Ok(started.elapsed())
+    }
+
+    /// This KDF with its cost scaled so that deriving a key takes about
+    /// `target` on this machine: PBKDF2's iterations, never fewer than the
+    /// default, or the passes Argon2id makes over the memory it already has.
+    pub fn calibrated(self, target: Duration) -> Result<Kdf> {
+        let (password, salt) = ("calibration", [0; SALT_LENGTH]);
+        let kdf = match self {
+            Kdf::Pbkdf2 { .. } => {
+                let mut probe = 10_000;
+                let elapsed = loop {
+                    let started = Instant::now();
+                    Kdf::Pbkdf2 { iterations: probe }.derive(password, &salt)?;
+                    let elapsed = started.elapsed();
+                    if elapsed >= CALIBRATION_PROBE || probe >= MAX_PBKDF2_ITERATIONS / 4 {
+                        break elapsed;
+                    }
+                    probe *= 4;
+                };
+                Kdf::Pbkdf2 {
+                    iterations: scale(probe, target, elapsed)
+                        .clamp(PBKDF2_ITERATIONS, MAX_PBKDF2_ITERATIONS),
+                }
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                parallelism,
+                ..
+            } => {
+                let started = Instant::now();
+                Kdf::Argon2id {
+                    memory_kib,
+                    iterations: 1,
+                    parallelism,
+                }
+                .derive(password, &salt)?;
+                Kdf::Argon2id {
+                    memory_kib,
+                    iterations: scale(1, target, started.elapsed()).clamp(1, MAX_ARGON2_ITERATIONS),
+                    parallelism,
+                }
+            }
+        };
+        Ok(kdf)
+    }
+
+    /// Refuses parameters beyond what any sane writer uses.
+    fn check_limits(&self) -> Result<()> {
+        let within = match *self {
+            Kdf::Pbkdf2 { iterations } => (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                memory_kib <= MAX_ARGON2_MEMORY_KIB
+                    && (1..=MAX_ARGON2_ITERATIONS).contains(&iterations)
+                    && (1..=MAX_ARGON2_PARALLELISM).contains(&parallelism)
+            }
+        };
+        if !within {
+            bail!(DecryptError::Unsupported(t!("header-kdf-limits")));
+        }
+        Ok(())
+    }
+}
+
+pub struct Header {
+    pub cipher: Cipher,
+    pub key_source: KeySource,
+    pub compression: Compression,
+    /// `None` exactly when no password is involved.
+    pub kdf: Option<Kdf>,
+    pub salt: [u8; SALT_LENGTH],
+    pub nonce: Vec<u8>,
+    /// 0 for a single message.
+    pub chunk_length: u32,
+    /// The file key wrapped for each recipient; empty for other key sources.
+    pub slots: Vec<Slot>,
+    /// A digest of the file's key, which tells a wrong key from a damaged
+    /// file; `None` in headers not yet sealed and for files from before the
+    /// header.
+    pub key_check: Option<[u8; KEY_CHECK_LENGTH]>,
+    /// The sealed [`Metadata`], nonce first; empty when the file records
+    /// none.
+    pub metadata: Vec<u8>,
+    /// A digest, under the file's key, of the associated data the payload is
+    /// bound to besides the header, which decryption has to be given again;
+    /// `None` when there is none or the header is not yet sealed.
+    pub aad_check: Option<[u8; AAD_CHECK_LENGTH]>,
+    /// The SHA-256 of the plaintext, before any compression, sealed like the
+    /// metadata, nonce first; empty when the file records none.
+    pub content_checksum: Vec<u8>,
+}
+
+/// What a file can record about the plaintext it was encrypted from, sealed
+/// in its header so that only its key reveals it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The file's name, without its directory.
+    pub name: String,
+    /// Unix permission bits.
+    pub mode: u32,
+    /// Seconds and nanoseconds since the Unix epoch.
+    pub modified: (u64, u32),
+}
+
+impl Metadata {
+    /// Seals the metadata under `key`, the key of the file whose header it
+    /// goes in, with a random nonce: files encrypted with one key file share
+    /// their key. A `deterministic` nonce is a keyed hash of the metadata.
+    pub(crate) fn seal(
+        &self,
+        cipher: Cipher,
+        key: &Key<Aes256Gcm>,
+        deterministic: bool,
+    ) -> Result<Vec<u8>> {
+        let plaintext = Zeroizing::new(bincode::serialize(self)?);
+        if plaintext.len() > MAX_METADATA_LENGTH {
+            bail!(t!("header-metadata-too-long"));
+        }
+        let mut sealed = match deterministic {
+            true => crate::synthetic_nonce(key, &plaintext, cipher.nonce_length()),
+            false => crate::generate_nonce(cipher.nonce_length())?,
+        };
+        let ciphertext = crate::encrypt(cipher, &metadata_key(key), &sealed, &[], &plaintext)
+            .with_context(|| t!("encrypt-failed"))?;
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// How long [`Metadata::seal`] makes it.
+    pub(crate) fn sealed_length(&self, cipher: Cipher) -> usize {
+        let length = bincode::serialized_size(self).expect("metadata serializes");
+        cipher.nonce_length() + length as usize + TAG_LENGTH
+    }
+
+    /// Opens what [`Metadata::seal`] sealed.
+    pub(crate) fn open(sealed: &[u8], cipher: Cipher, key: &Key<Aes256Gcm>) -> Result<Metadata> {
+        if sealed.len() < cipher.nonce_length() {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        let (nonce, ciphertext) = sealed.split_at(cipher.nonce_length());
+        let plaintext = Zeroizing::new(
+            crate::decrypt(cipher, &metadata_key(key), nonce, &[], ciphertext)
+                .context(DecryptError::Corrupted)?,
+        );
+        bincode::deserialize(&plaintext).context(DecryptError::Corrupted)
+    }
+}
+
+/// The key metadata is sealed under, apart from the payload's.
+fn metadata_key(key: &Key<Aes256Gcm>) -> Zeroizing<Key<Aes256Gcm>> {
+    let key = Zeroizing::new(blake3::derive_key(
+        "file-encryptor 2026 header metadata",
+        key,
+    ));
+    Zeroizing::new((*key).into())
+}
+
+/// Seals `digest`, the SHA-256 of a file's plaintext, under `key`, the
+/// file's key, with a random nonce, or a keyed hash of it if
+/// `deterministic`. Sealed, it doesn't confirm a guess at the plaintext.
+pub(crate) fn seal_content_checksum(
+    cipher: Cipher,
+    key: &Key<Aes256Gcm>,
+    digest: &[u8; CONTENT_CHECKSUM_LENGTH],
+    deterministic: bool,
+) -> Result<Vec<u8>> {
+    let mut sealed = match deterministic {
+        true => crate::synthetic_nonce(key, digest, cipher.nonce_length()),
+        false => crate::generate_nonce(cipher.nonce_length())?,
+    };
+    let ciphertext = crate::encrypt(cipher, &content_checksum_key(key), &sealed, &[], digest)
+        .with_context(|| t!("encrypt-failed"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// How long [`seal_content_checksum`] makes it.
+pub(crate) fn sealed_content_checksum_length(cipher: Cipher) -> usize {
+    cipher.nonce_length() + CONTENT_CHECKSUM_LENGTH + TAG_LENGTH
+}
+
+/// Opens what [`seal_content_checksum`] sealed.
+pub(crate) fn open_content_checksum(
+    sealed: &[u8],
+    cipher: Cipher,
+    key: &Key<Aes256Gcm>,
+) -> Result<[u8; CONTENT_CHECKSUM_LENGTH]> {
+    if sealed.len() < cipher.nonce_length() {
+        bail!(DecryptError::HeaderInvalid);
+    }
+    let (nonce, ciphertext) = sealed.split_at(cipher.nonce_length());
+    let digest = crate::decrypt(cipher, &content_checksum_key(key), nonce, &[], ciphertext)
+        .context(DecryptError::Corrupted)?;
+    digest
+        .try_into()
+        .map_err(|_| anyhow!(DecryptError::HeaderInvalid))
+}
+
+/// The key the content checksum is sealed under, apart from the others.
+fn content_checksum_key(key: &Key<Aes256Gcm>) -> Zeroizing<Key<Aes256Gcm>> {
+    let key = Zeroizing::new(blake3::derive_key(
+        "file-encryptor 2026 header content checksum",
+        key,
+    ));
+    Zeroizing::new((*key).into())
+}
+
+impl Header {
+    /// What a file from before the header implies: uncompressed AES-256-GCM
+    /// under the default PBKDF2.
+    pub fn legacy(salt: [u8; SALT_LENGTH], nonce: Vec<u8>) -> Header {
+        Header {
+            cipher: Cipher::Aes256Gcm,
+            key_source: KeySource::Password,
+            compression: Compression::None,
+            kdf: Some(Kdf::default()),
+            salt,
+            nonce,
+            chunk_length: 0,
+            slots: Vec::new(),
+            key_check: None,
+            metadata: Vec::new(),
+            aad_check: None,
+            content_checksum: Vec::new(),
+        }
+    }
+
+    /// The header as written; too many or too long slots don't fit its
+    /// 16-bit length, and are an error.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.write(&self.slots)
+    }
+
+    /// What a new file with this header authenticates its payload with.
+    pub fn associated_data(&self) -> Result<Vec<u8>> {
+        match self.key_source {
+            KeySource::Recipients => self.write(&[]),
+            _ => self.to_bytes(),
+        }
+    }
+
+    fn write(&self, slots: &[Slot]) -> Result<Vec<u8>> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&[0, 0]); // Filled in below.
+        out.push(self.cipher.id());
+        out.push(self.key_source.id());
+        out.push(self.compression.id());
+        write_kdf(&mut out, self.kdf);
+        for field in [&self.salt, self.nonce.as_slice()] {
+            write_prefixed(&mut out, field);
+        }
+        out.extend_from_slice(&self.chunk_length.to_le_bytes());
+        write_prefixed(
+            &mut out,
+            self.key_check
+                .as_ref()
+                .map_or(&[], |check| check.as_slice()),
+        );
+        out.extend_from_slice(&(self.metadata.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.metadata);
+        write_prefixed(
+            &mut out,
+            self.aad_check
+                .as_ref()
+                .map_or(&[], |check| check.as_slice()),
+        );
+        write_prefixed(&mut out, &self.content_checksum);
+        if self.key_source == KeySource::Recipients {
+            write_slots(&mut out, slots);
+        }
+        write_length(&mut out)?;
+        Ok(out)
+    }
+
+    /// How much plaintext a payload of `sealed_length` bytes (everything
+    /// between header and footer) holds, or `None` if no plaintext seals to
+    /// that length, as when the file was cut short.
+    pub fn plaintext_length(&self, sealed_length: u64) -> Option<u64> {
+        let tag = TAG_LENGTH as u64;
+        if self.chunk_length == 0 {
+            return sealed_length.checked_sub(tag);
+        }
+        // Every chunk is full but the last, which may be empty.
+        let sealed_chunk = u64::from(self.chunk_length) + tag;
+        let full_chunks = sealed_length / sealed_chunk;
+        let last = (sealed_length % sealed_chunk).checked_sub(tag)?;
+        Some(full_chunks * u64::from(self.chunk_length) + last)
+    }
+
+    /// The length of the whole header, from its first [`PREFIX_LENGTH`]
+    /// bytes.
+    pub fn length(prefix: &[u8]) -> Result<usize> {
+        if prefix.len() < PREFIX_LENGTH || !prefix.starts_with(MAGIC) {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        let version = prefix[MAGIC.len()];
+        if version != VERSION {
+            bail!(DecryptError::Unsupported(t!(
+                "header-unsupported-version",
+                version = version
+            )));
+        }
+        let length = u16::from_le_bytes([prefix[MAGIC.len() + 1], prefix[MAGIC.len() + 2]]);
+        Ok(usize::from(length).max(PREFIX_LENGTH))
+    }
+
+    /// Parses a whole header, as measured by [`Header::length`].
+    pub fn parse(bytes: &[u8]) -> Result<Header> {
+        Ok(Header::parse_at(bytes)?.0)
+    }
+
+    /// The header, and where in `bytes` its slot count is or would be.
+    fn parse_at(bytes: &[u8]) -> Result<(Header, usize)> {
+        let length = Header::length(bytes)?;
+        let mut fields = Fields(bytes.get(PREFIX_LENGTH..length).unwrap_or_default());
+        let cipher = match fields.byte()? {
+            CIPHER_AES_256_GCM => Cipher::Aes256Gcm,
+            CIPHER_CHACHA20_POLY1305 => Cipher::ChaCha20Poly1305,
+            CIPHER_XCHACHA20_POLY1305 => Cipher::XChaCha20Poly1305,
+            id => bail!(DecryptError::Unsupported(t!(
+                "header-unknown-cipher",
+                id = id
+            ))),
+        };
+        let key_source = match fields.byte()? {
+            KEY_SOURCE_PASSWORD => KeySource::Password,
+            KEY_SOURCE_KEY_FILE => KeySource::KeyFile,
+            KEY_SOURCE_PASSWORD_AND_KEY_FILE => KeySource::PasswordAndKeyFile,
+            KEY_SOURCE_RECIPIENTS => KeySource::Recipients,
+            id => bail!(DecryptError::Unsupported(t!(
+                "header-unknown-key-source",
+                id = id
+            ))),
+        };
+        let compression = match fields.byte()? {
+            COMPRESSION_NONE => Compression::None,
+            COMPRESSION_GZIP => Compression::Gzip,
+            COMPRESSION_ZSTD => Compression::Zstd,
+            id => bail!(DecryptError::Unsupported(t!(
+                "header-unknown-compression",
+                id = id
+            ))),
+        };
+        let kdf = fields.kdf()?;
+        if kdf.is_some() != key_source.needs_password() {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        let salt = fields.salt()?;
+        let nonce = fields.prefixed()?.to_vec();
+        let chunk_length = fields.u32()?;
+        let nonce_length = match chunk_length {
+            0 => cipher.nonce_length(),
+            _ => cipher.nonce_prefix_length(),
+        };
+        if nonce.len() != nonce_length {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        let key_check = match fields.prefixed()? {
+            [] => None,
+            check => Some(
+                check
+                    .try_into()
+                    .map_err(|_| anyhow!(DecryptError::HeaderInvalid))?,
+            ),
+        };
+        let metadata_length = fields.u16()?;
+        let metadata = fields.take(usize::from(metadata_length))?.to_vec();
+        let aad_check = match fields.prefixed()? {
+            [] => None,
+            check => Some(
+                check
+                    .try_into()
+                    .map_err(|_| anyhow!(DecryptError::HeaderInvalid))?,
+            ),
+        };
+        let content_checksum = fields.prefixed()?.to_vec();
+        let slots_at = length - fields.0.len();
+        let mut slots = Vec::new();
+        if key_source == KeySource::Recipients {
+            for _ in 0..fields.byte()? {
+                slots.push(fields.slot()?);
+            }
+            if slots.is_empty() {
+                bail!(DecryptError::HeaderInvalid);
+            }
+        }
+        let header = Header {
+            cipher,
+            key_source,
+            compression,
+            kdf,
+            salt,
+            nonce,
+            chunk_length,
+            slots,
+            key_check,
+            metadata,
+            aad_check,
+            content_checksum,
+        };
+        Ok((header, slots_at))
+    }
+}
+
+/// `count`, which took `elapsed`, scaled to take `target`.
+fn scale(count: u32, target: Duration, elapsed: Duration) -> u32 {
+    let scaled = f64::from(count) * target.as_secs_f64() / elapsed.as_secs_f64().max(1e-9);
+    scaled.round().min(f64::from(u32::MAX)) as u32
+}
+
+/// A digest of `key` for the header, from which the key can't be recovered.
+pub(crate) fn key_check(key: &Key<Aes256Gcm>) -> [u8; KEY_CHECK_LENGTH] {
+    let digest = blake3::derive_key("file-encryptor 2026 header key check", key);
+    digest[..KEY_CHECK_LENGTH]
+        .try_into()
+        .expect("a digest is longer")
+}
+
+/// A digest of `aad` for the header of a file under `key`. It is keyed, so
+/// the header doesn't confirm a guess at what the file is bound to.
+pub(crate) fn aad_check(key: &Key<Aes256Gcm>, aad: &[u8]) -> [u8; AAD_CHECK_LENGTH] {
+    let key = Zeroizing::new(blake3::derive_key("file-encryptor 2026 header aad check", key));
+    blake3::keyed_hash(&key, aad).as_bytes()[..AAD_CHECK_LENGTH]
+        .try_into()
+        .expect("a digest is longer")
+}
+
+/// The header of a file for recipients, `bytes`, with `slots` in place of its
+/// own.
+pub fn replace_slots(bytes: &[u8], slots: &[Slot]) -> Result<Vec<u8>> {
+    let (header, slots_at) = Header::parse_at(bytes)?;
+    if header.key_source != KeySource::Recipients {
+        bail!(DecryptError::HeaderInvalid);
+    }
+    let mut out = bytes[..slots_at].to_vec();
+    write_slots(&mut out, slots);
+    write_length(&mut out)?;
+    Ok(out)
+}
+
+/// What the payload after the header `bytes` is authenticated with.
+pub(crate) fn associated_data(bytes: &[u8]) -> Result<Vec<u8>> {
+    match slots_detached(bytes) {
+        true => replace_slots(bytes, &[]),
+        false => Ok(bytes.to_vec()),
+    }
+}
+
+/// Whether the file starting with `bytes` is for recipients and leaves its
+/// slots out of the associated data, so they can be replaced in place.
+pub fn slots_detached(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+        && key_source(bytes).is_ok_and(|source| source == KeySource::Recipients)
+}
+
+/// The key source `file_bytes` need: whatever the header records, or a
+/// password for files from before the header.
+pub fn key_source(file_bytes: &[u8]) -> Result<KeySource> {
+    if !file_bytes.starts_with(MAGIC) {
+        return Ok(KeySource::Password);
+    }
+    Ok(Header::parse(file_bytes)?.key_source)
+}
+
+/// Fills in the length of the header `out`, refusing one too long to record.
+fn write_length(out: &mut [u8]) -> Result<()> {
+    let length = u16::try_from(out.len()).map_err(|_| anyhow!(t!("header-too-long")))?;
+    out[MAGIC.len() + 1..PREFIX_LENGTH].copy_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+fn write_slots(out: &mut Vec<u8>, slots: &[Slot]) {
+    out.push(slots.len() as u8);
+    for slot in slots {
+        match slot {
+            Slot::PublicKey(sealed) => {
+                out.push(SLOT_PUBLIC_KEY);
+                write_prefixed(out, sealed);
+            }
+            Slot::Password {
+                kdf,
+                salt,
+                nonce,
+                wrapped,
+            } => {
+                out.push(SLOT_PASSWORD);
+                write_kdf(out, Some(*kdf));
+                write_prefixed(out, salt);
+                write_prefixed(out, nonce);
+                write_prefixed(out, wrapped);
+            }
+            Slot::Token {
+                mechanism,
+                token,
+                ephemeral,
+                nonce,
+                wrapped,
+            } => {
+                out.push(SLOT_TOKEN);
+                out.push(match mechanism {
+                    TokenMechanism::EcdhP256 => TOKEN_ECDH_P256,
+                });
+                write_prefixed(out, token.as_bytes());
+                write_prefixed(out, ephemeral);
+                write_prefixed(out, nonce);
+                write_prefixed(out, wrapped);
+            }
+        }
+    }
+}
+
+fn write_prefixed(out: &mut Vec<u8>, field: &[u8]) {
+    out.push(field.len() as u8);
+    out.extend_from_slice(field);
+}
+
+/// The KDF id and its length-prefixed parameters.
+fn write_kdf(out: &mut Vec<u8>, kdf: Option<Kdf>) {
+    let params: Vec<u8> = match kdf {
+        None => {
+            out.push(KDF_NONE);
+            Vec::new()
+        }
+        Some(Kdf::Pbkdf2 { iterations }) => {
+            out.push(KDF_PBKDF2_SHA256);
+            iterations.to_le_bytes().to_vec()
+        }
+        Some(Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        }) => {
+            out.push(KDF_ARGON2ID);
+            [memory_kib, iterations, parallelism]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect()
+        }
+    };
+    write_prefixed(out, &params);
+}
+
+/// The rest of a header being parsed.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.0.len() < length {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        let (field, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// A field preceded by its length.
+    fn prefixed(&mut self) -> Result<&'a [u8]> {
+        let length = self.byte()?;
+        self.take(usize::from(length))
+    }
+
+    fn salt(&mut self) -> Result<[u8; SALT_LENGTH]> {
+        self.prefixed()?
+            .try_into()
+            .map_err(|_| anyhow!(DecryptError::HeaderInvalid))
+    }
+
+    /// As [`write_kdf`] wrote it, within the safe limits.
+    fn kdf(&mut self) -> Result<Option<Kdf>> {
+        let id = self.byte()?;
+        let mut params = Fields(self.prefixed()?);
+        let kdf = match id {
+            KDF_NONE => return Ok(None),
+            KDF_PBKDF2_SHA256 => Kdf::Pbkdf2 {
+                iterations: params.u32()?,
+            },
+            KDF_ARGON2ID => Kdf::Argon2id {
+                memory_kib: params.u32()?,
+                iterations: params.u32()?,
+                parallelism: params.u32()?,
+            },
+            id => bail!(DecryptError::Unsupported(t!("header-unknown-kdf", id = id))),
+        };
+        kdf.check_limits()?;
+        Ok(Some(kdf))
+    }
+
+    fn slot(&mut self) -> Result<Slot> {
+        let slot = match self.byte()? {
+            SLOT_PUBLIC_KEY => Slot::PublicKey(self.prefixed()?.to_vec()),
+            SLOT_PASSWORD => Slot::Password {
+                kdf: self
+                    .kdf()?
+                    .ok_or_else(|| anyhow!(DecryptError::HeaderInvalid))?,
+                salt: self.salt()?,
+                nonce: self
+                    .prefixed()?
+                    .try_into()
+                    .map_err(|_| anyhow!(DecryptError::HeaderInvalid))?,
+                wrapped: self.prefixed()?.to_vec(),
+            },
+            SLOT_TOKEN => Slot::Token {
+                mechanism: match self.byte()? {
+                    TOKEN_ECDH_P256 => TokenMechanism::EcdhP256,
+                    id => bail!(DecryptError::Unsupported(t!(
+                        "header-unknown-token-mechanism",
+                        id = id
+                    ))),
+                },
+                token: String::from_utf8(self.prefixed()?.to_vec())
+                    .map_err(|_| anyhow!(DecryptError::HeaderInvalid))?,
+                ephemeral: self.prefixed()?.to_vec(),
+                nonce: self
+                    .prefixed()?
+                    .try_into()
+                    .map_err(|_| anyhow!(DecryptError::HeaderInvalid))?,
+                wrapped: self.prefixed()?.to_vec(),
+            },
+            id => bail!(DecryptError::Unsupported(t!(
+                "header-unknown-slot",
+                id = id
+            ))),
+        };
+        if !slot.has_valid_length() {
+            bail!(DecryptError::HeaderInvalid);
+        }
+        Ok(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipient::{MAX_RECIPIENTS, MAX_TOKEN_LENGTH};
+
+    fn for_tokens(count: usize) -> Header {
+        let slot = Slot::Token {
+            mechanism: TokenMechanism::EcdhP256,
+            token: "t".repeat(MAX_TOKEN_LENGTH),
+            ephemeral: vec![4; 65],
+            nonce: [0; 12],
+            wrapped: vec![0; KEY_LENGTH + TAG_LENGTH],
+        };
+        Header {
+            key_source: KeySource::Recipients,
+            kdf: None,
+            slots: vec![slot; count],
+            ..Header::legacy([0; SALT_LENGTH], vec![0; 12])
+        }
+    }
+
+    #[test]
+    fn slots_past_the_length_field_are_refused() {
+        let header = for_tokens(MAX_RECIPIENTS);
+        assert!(header.to_bytes().is_err());
+        let bytes = for_tokens(1).to_bytes().unwrap();
+        assert!(replace_slots(&bytes, &header.slots).is_err());
+
+        let header = for_tokens(100);
+        let bytes = header.to_bytes().unwrap();
+        assert_eq!(Header::parse(&bytes).unwrap().slots, header.slots);
+    }
+
+    #[test]
+    fn other_versions_are_refused_by_name() {
+        let mut bytes = for_tokens(1).to_bytes().unwrap();
+        bytes[MAGIC.len()] = VERSION + 1;
+        let Err(e) = Header::parse(&bytes) else {
+            panic!("version {} parsed", VERSION + 1);
+        };
+        assert!(matches!(e.downcast_ref(), Some(DecryptError::Unsupported(_))));
+    }
+
+    /// The layout of the first release, the only one before the header: the
+    /// nonce and salt, then AES-256-GCM under PBKDF2 of the password.
+    #[test]
+    fn files_from_before_the_header_still_decrypt() {
+        let (nonce, salt) = ([1; 12], [2; SALT_LENGTH]);
+        let mut key = [0; KEY_LENGTH];
+        pbkdf2::<Hmac<Sha256>>(b"pw", &salt, PBKDF2_ITERATIONS, &mut key);
+        let sealed = crate::encrypt(Cipher::Aes256Gcm, &key.into(), &nonce, &[], b"hello");
+        let file = [&nonce[..], &salt, &sealed.unwrap()].concat();
+        assert_eq!(crate::Encryptor::new("pw").decrypt(&file).unwrap(), b"hello");
+        assert!(crate::Encryptor::new("wrong").decrypt(&file).is_err());
+    }
+}
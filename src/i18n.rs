@@ -0,0 +1,92 @@
+//! Translated prompts, progress messages and common errors. The catalogs are
+//! Fluent files under `locales/`, compiled into the crate.
+//!
+//! The language is taken from `FILE_ENCRYPTOR_LANG`, then `LC_ALL`,
+//! `LC_MESSAGES` and `LANG`; a language without a catalog, or a message
+//! missing from one, falls back to English. Command-line help stays English.
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+pub use fluent_bundle::FluentValue;
+
+const ENGLISH: &str = "en";
+const CATALOGS: [(&str, &str); 2] = [
+    (ENGLISH, include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The user's language first, then English.
+static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// Looks up `id` in the user's language, filling in `args`.
+pub fn message(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let bundles = BUNDLES.get_or_init(load);
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    for bundle in bundles {
+        if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+    }
+    id.to_owned()
+}
+
+/// `t!("id")` or `t!("id", name = value, ...)`: [`message`] with named
+/// arguments. Values are anything Fluent converts from, such as strings and
+/// integers.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! t {
+    ($id:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message(
+            $id,
+            &[$((stringify!($name), $crate::i18n::FluentValue::from($value))),*],
+        )
+    };
+}
+#[doc(hidden)]
+pub use crate::t;
+
+fn load() -> Vec<FluentBundle<FluentResource>> {
+    let language = requested_language();
+    let requested = CATALOGS
+        .iter()
+        .filter(|(code, _)| *code == language && *code != ENGLISH);
+    let english = CATALOGS.iter().filter(|(code, _)| *code == ENGLISH);
+    requested
+        .chain(english)
+        .map(|(code, source)| {
+            let id: LanguageIdentifier = code.parse().expect("catalog codes are valid");
+            let mut bundle = FluentBundle::new_concurrent(vec![id]);
+            // Unicode isolation marks show up as stray characters in terminals.
+            bundle.set_use_isolating(false);
+            let resource = FluentResource::try_new(source.to_string())
+                .unwrap_or_else(|(resource, _)| resource);
+            let _ = bundle.add_resource(resource);
+            bundle
+        })
+        .collect()
+}
+
+/// The language part of the first locale variable set, e.g. `de` for
+/// `de_DE.UTF-8`.
+fn requested_language() -> String {
+    ["FILE_ENCRYPTOR_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .map(|value| {
+            value
+                .split(['_', '-', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+        .unwrap_or_default()
+}
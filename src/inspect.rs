@@ -0,0 +1,299 @@
+//! `inspect`: prints what an encrypted file's header records, and whether its
+//! checksum footer still matches, without the password. A file that checks out
+//! here but won't decrypt has the wrong password or key file; one that doesn't
+//! was damaged. With `--with-password` it also opens the SHA-256 of the
+//! plaintext the header records, to compare with a manifest made elsewhere.
+//! Given a key file or identity instead, it prints when the key was made and
+//! when it expires.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+    time::SystemTime,
+};
+use zeroize::Zeroizing;
+
+use file_encryptor::{
+    date,
+    header::{self, Header},
+    i18n::t,
+    key_file::{self, Validity},
+    recipient::Slot,
+    stream, Cipher, Compression, Kdf, KeySource, FOOTER_LENGTH, FOOTER_MAGIC, SALT_LENGTH,
+};
+
+use crate::{human_size, log, mime};
+
+/// Enough for any header.
+const HEADER_PEEK: u64 = 64 << 10;
+/// Nonce then salt, in files from before the header.
+const LEGACY_NONCE_LENGTH: usize = 12;
+
+pub fn run(path: &str, with_password: bool) -> Result<()> {
+    let path = Path::new(path);
+    let mut input = io::BufReader::new(
+        fs::File::open(path)
+            .with_context(|| t!("file-open-failed", path = path.display().to_string()))?,
+    );
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(HEADER_PEEK)
+        .read_to_end(&mut head)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    if let Some(kind) = key_kind(&head) {
+        inspect_key(path, &kind, &head);
+        return Ok(());
+    }
+    if head.starts_with(b"MIME-Version:") {
+        input
+            .read_to_end(&mut head)
+            .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+        let text = String::from_utf8(head)
+            .map_err(|_| anyhow!(t!("mime-not-message", path = path.display().to_string())))?;
+        let file_bytes = mime::unwrap(&text)?;
+        row(&t!("inspect-wrapping"), t!("inspect-mime"));
+        return inspect(file_bytes.len() as u64, file_bytes.as_slice(), with_password);
+    }
+    let length = fs::metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?
+        .len();
+    inspect(length, io::Cursor::new(head).chain(input), with_password)
+}
+
+/// What kind of key `head` holds, if it is a key or identity file.
+fn key_kind(head: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(head).ok();
+    if text.is_some_and(|text| {
+        text.lines()
+            .any(|line| line.trim().starts_with("AGE-SECRET-KEY-1"))
+    }) {
+        return Some(t!("inspect-age-identity"));
+    }
+    match key_file::parse(head) {
+        Ok(_) if text.is_none() => Some(t!("inspect-raw-key-file")),
+        Ok(_) => Some(t!("inspect-key-file")),
+        Err(_) => None,
+    }
+}
+
+/// Reports when the key in `contents` was made and when it expires, warning
+/// if it has.
+fn inspect_key(path: &Path, kind: &str, contents: &[u8]) {
+    row(&t!("inspect-format"), kind);
+    let validity = Validity::read(contents);
+    let now = SystemTime::now();
+    row(
+        &t!("inspect-created"),
+        validity.created.map_or(t!("inspect-not-recorded"), date::format),
+    );
+    let expires = match validity.expires {
+        None => t!("inspect-never"),
+        Some(expires) if validity.is_expired(now) => {
+            t!("inspect-expired", date = date::format(expires))
+        }
+        Some(expires) => {
+            let left = expires.duration_since(now).unwrap_or_default().as_secs();
+            t!("inspect-expires-in", date = date::format(expires), days = left.div_ceil(86_400))
+        }
+    };
+    row(&t!("inspect-expires"), expires);
+    if let Some(expires) = validity.expires.filter(|_| validity.is_expired(now)) {
+        log::warn(&t!(
+            "key-expired",
+            path = path.display().to_string(),
+            date = date::format(expires)
+        ));
+    }
+}
+
+/// Reports on a native file of `length` bytes, prompting for its password
+/// to open its content checksum if `with_password`.
+fn inspect(length: u64, mut input: impl Read, with_password: bool) -> Result<()> {
+    let mut head = Vec::new();
+    input.by_ref().take(HEADER_PEEK).read_to_end(&mut head)?;
+    if head.starts_with(b"BEGIN") || head.starts_with(b"-----BEGIN PGP") {
+        return Err(anyhow!(t!("inspect-foreign-format")));
+    }
+
+    let (header, header_length) = if head.starts_with(header::MAGIC) {
+        row(&t!("inspect-format"), t!("inspect-version", version = head[header::MAGIC.len()]));
+        stream::header(head.as_slice())?
+    } else if head.starts_with(stream::MAGIC) {
+        row(&t!("inspect-format"), t!("inspect-legacy-chunked"));
+        stream::header(head.as_slice())?
+    } else if head.len() >= LEGACY_NONCE_LENGTH + SALT_LENGTH {
+        row(&t!("inspect-format"), t!("inspect-legacy"));
+        let (nonce, rest) = head.split_at(LEGACY_NONCE_LENGTH);
+        let salt = rest[..SALT_LENGTH].try_into()?;
+        (
+            Header::legacy(salt, nonce.to_vec()),
+            LEGACY_NONCE_LENGTH + SALT_LENGTH,
+        )
+    } else {
+        return Err(anyhow!(t!("inspect-too-short")));
+    };
+
+    match header.chunk_length {
+        0 => row(&t!("inspect-layout"), t!("inspect-single-message")),
+        chunk_length => row(
+            &t!("inspect-layout"),
+            t!("inspect-chunked", size = human_size(chunk_length.into())),
+        ),
+    }
+    row(&t!("inspect-cipher"), cipher_name(header.cipher));
+    row(
+        &t!("inspect-compression"),
+        match header.compression {
+            Compression::None => t!("inspect-none"),
+            Compression::Gzip => "gzip".to_owned(),
+            Compression::Zstd => "zstd".to_owned(),
+        },
+    );
+    row(&t!("inspect-key-source"), key_source_name(header.key_source));
+    for (i, slot) in header.slots.iter().enumerate() {
+        let recipient = match slot {
+            Slot::PublicKey(_) => t!("inspect-public-key"),
+            Slot::Password { kdf, .. } => t!("inspect-password-slot", kdf = kdf_description(*kdf)),
+            Slot::Token { token, .. } => t!("inspect-token-slot", token = token.to_string()),
+        };
+        row(&t!("inspect-recipient", number = i + 1), recipient);
+    }
+    row(&t!("inspect-kdf"), header.kdf.map_or(t!("inspect-none"), kdf_description));
+    row(&t!("inspect-salt"), hex::encode(header.salt));
+    match header.chunk_length {
+        0 => row(&t!("inspect-nonce"), hex::encode(&header.nonce)),
+        _ => row(&t!("inspect-nonce-prefix"), hex::encode(&header.nonce)),
+    }
+    row(
+        &t!("inspect-key-check"),
+        header
+            .key_check
+            .map_or(t!("inspect-none"), hex::encode),
+    );
+    row(
+        &t!("inspect-aad"),
+        match header.aad_check {
+            Some(_) => t!("inspect-aad-bound"),
+            None => t!("inspect-none"),
+        },
+    );
+    row(
+        &t!("inspect-metadata"),
+        match header.metadata.len() {
+            0 => t!("inspect-none"),
+            length => t!("inspect-metadata-recorded", length = length),
+        },
+    );
+    let content_checksum = match header.content_checksum.is_empty() {
+        true if head.starts_with(header::MAGIC) => t!("inspect-none"),
+        true => t!("inspect-none-legacy"),
+        false if with_password => {
+            let mut prompted = Zeroizing::new(String::new());
+            let mut keys = crate::unlock(&head, None, None, &mut prompted)?;
+            let digest = keys.content_checksum(&head)?;
+            digest.map_or(t!("inspect-none"), hex::encode)
+        }
+        false => t!("inspect-checksum-hidden"),
+    };
+    row(&t!("inspect-content-sha256"), content_checksum);
+    row(&t!("inspect-header"), t!("inspect-bytes", length = header_length));
+
+    let checksum = checksum(io::Cursor::new(head).chain(input), length)?;
+    let footer_length = checksum.map_or(0, |_| FOOTER_LENGTH as u64);
+    let plaintext_length = length
+        .checked_sub(header_length as u64 + footer_length)
+        .and_then(|sealed_length| header.plaintext_length(sealed_length));
+    match plaintext_length {
+        // The length says nothing about what compressed data expands to.
+        Some(plaintext_length) if header.compression != Compression::None => row(
+            &t!("inspect-compressed"),
+            t!(
+                "inspect-compressed-length",
+                size = human_size(plaintext_length),
+                length = plaintext_length
+            ),
+        ),
+        Some(plaintext_length) => row(
+            &t!("inspect-plaintext"),
+            t!(
+                "inspect-plaintext-length",
+                size = human_size(plaintext_length),
+                length = plaintext_length
+            ),
+        ),
+        None => row(&t!("inspect-plaintext"), t!("inspect-plaintext-unknown")),
+    }
+    match checksum {
+        Some(true) => {
+            row(&t!("inspect-checksum"), "ok");
+            println!("\n{}", t!("inspect-intact"));
+        }
+        Some(false) => {
+            row(&t!("inspect-checksum"), t!("inspect-mismatch"));
+            println!("\n{}", t!("inspect-damaged"));
+        }
+        None => row(
+            &t!("inspect-checksum"),
+            t!("inspect-checksum-none"),
+        ),
+    }
+    Ok(())
+}
+
+fn row(label: &str, value: impl std::fmt::Display) {
+    println!("{:<17}{value}", format!("{label}:"));
+}
+
+/// Whether the checksum footer of the `length` bytes of `input` matches, or
+/// `None` without one.
+pub fn checksum(mut input: impl Read, length: u64) -> Result<Option<bool>> {
+    let Some(body_length) = length.checked_sub(FOOTER_LENGTH as u64) else {
+        return Ok(None);
+    };
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(input.by_ref().take(body_length))?;
+    let mut footer = [0u8; FOOTER_LENGTH];
+    input.read_exact(&mut footer)?;
+    if !footer.ends_with(FOOTER_MAGIC) {
+        return Ok(None);
+    }
+    Ok(Some(
+        hasher.finalize().as_bytes() == &footer[..blake3::OUT_LEN],
+    ))
+}
+
+pub fn cipher_name(cipher: Cipher) -> &'static str {
+    match cipher {
+        Cipher::Aes256Gcm => "AES-256-GCM",
+        Cipher::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+        Cipher::XChaCha20Poly1305 => "XChaCha20-Poly1305",
+    }
+}
+
+fn key_source_name(key_source: KeySource) -> String {
+    match key_source {
+        KeySource::Password => t!("inspect-source-password"),
+        KeySource::KeyFile => t!("inspect-source-key-file"),
+        KeySource::PasswordAndKeyFile => t!("inspect-source-password-and-key-file"),
+        KeySource::Recipients => t!("inspect-source-recipients"),
+    }
+}
+
+pub fn kdf_description(kdf: Kdf) -> String {
+    match kdf {
+        Kdf::Pbkdf2 { iterations } => t!("inspect-pbkdf2", iterations = iterations),
+        Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => t!(
+            "inspect-argon2id",
+            memory = human_size(u64::from(memory_kib) << 10),
+            iterations = iterations,
+            parallelism = parallelism
+        ),
+    }
+}
@@ -0,0 +1,141 @@
+//! Key files: a random 256-bit key, stored as one line of hex, that stands in
+//! for a password or is required alongside one (see
+//! [`Encryptor::from_key_file`](crate::Encryptor::from_key_file)). Anyone
+//! holding the file can decrypt what it protects, so it belongs somewhere
+//! other than the files themselves.
+//!
+//! Lines starting with `#` are comments. `# created:` and `# expires:` lines
+//! with RFC 3339 dates give the key's [`Validity`], which identity files
+//! carry the same way, so keys can be rotated on a schedule. An expired key
+//! still works, with a warning, as the files it encrypted still need it.
+
+use anyhow::{anyhow, Result};
+use std::time::{Duration, SystemTime};
+use zeroize::Zeroizing;
+
+use crate::{date, entropy, i18n::t, KEY_LENGTH};
+
+pub const LENGTH: usize = KEY_LENGTH;
+
+/// When a key was made, and until when it is meant to be used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Validity {
+    pub created: Option<SystemTime>,
+    pub expires: Option<SystemTime>,
+}
+
+impl Validity {
+    /// A key made now, expiring `expires_in` from now if given.
+    pub fn starting_now(expires_in: Option<Duration>) -> Validity {
+        let now = SystemTime::now();
+        Validity {
+            created: Some(now),
+            expires: expires_in.and_then(|expires_in| now.checked_add(expires_in)),
+        }
+    }
+
+    /// The dates in the comments of a key or identity file's contents;
+    /// those that are missing or unreadable are `None`.
+    pub fn read(contents: &[u8]) -> Validity {
+        let mut validity = Validity::default();
+        let Ok(text) = std::str::from_utf8(contents) else {
+            return validity;
+        };
+        for line in text.lines() {
+            let Some((name, value)) = line
+                .trim()
+                .strip_prefix('#')
+                .and_then(|comment| comment.split_once(':'))
+            else {
+                continue;
+            };
+            match name.trim() {
+                "created" => validity.created = date::parse(value),
+                "expires" => validity.expires = date::parse(value),
+                _ => {}
+            }
+        }
+        validity
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// The comment lines recording these dates.
+    pub fn comments(&self) -> String {
+        let mut comments = String::new();
+        for (name, time) in [("created", self.created), ("expires", self.expires)] {
+            if let Some(time) = time {
+                comments.push_str(&format!("# {name}: {}\n", date::format(time)));
+            }
+        }
+        comments
+    }
+}
+
+/// The contents of a new key file, valid as `validity` says.
+pub fn generate(validity: &Validity) -> Result<String> {
+    let key = Zeroizing::new(entropy::bytes::<LENGTH>()?);
+    Ok(format!(
+        "{}{}\n",
+        validity.comments(),
+        hex::encode(key.as_slice())
+    ))
+}
+
+/// `text` without its comment lines, leaving the key.
+pub fn without_comments(text: &str) -> Zeroizing<String> {
+    let mut key = Zeroizing::new(String::new());
+    for line in text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+    {
+        key.push_str(line);
+        key.push('\n');
+    }
+    key
+}
+
+/// The key in a key file's contents. Besides our hex line, a file of exactly
+/// [`LENGTH`] raw bytes is taken as the key itself.
+pub fn parse(contents: &[u8]) -> Result<[u8; LENGTH]> {
+    if let Ok(key) = contents.try_into() {
+        return Ok(key);
+    }
+    std::str::from_utf8(contents)
+        .ok()
+        .and_then(|text| hex::decode(without_comments(text).trim()).ok())
+        .map(Zeroizing::new)
+        .and_then(|key| key.as_slice().try_into().ok())
+        .ok_or_else(|| anyhow!(t!("key-file-invalid", length = LENGTH)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_comments_around_the_key() {
+        let validity = Validity::starting_now(Some(Duration::from_secs(90 * 86_400)));
+        let contents = generate(&validity).unwrap();
+        assert!(contents.starts_with("# created: "));
+        let read = Validity::read(contents.as_bytes());
+        let seconds = |time: Option<SystemTime>| {
+            time.map(|time| {
+                time.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            })
+        };
+        assert_eq!(seconds(read.created), seconds(validity.created));
+        assert_eq!(seconds(read.expires), seconds(validity.expires));
+        assert!(!read.is_expired(SystemTime::now()));
+        assert!(read.is_expired(validity.expires.unwrap()));
+        assert!(parse(contents.as_bytes()).is_ok());
+        assert_eq!(
+            Validity::read(b"# expires: someday\n00"),
+            Validity::default()
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! `--use-keyring NAME`: keeps a password in the platform's keychain instead
+//! of on the command line, so a scheduled backup needs no password in its
+//! crontab. Entries belong to the service `file-encryptor`, with NAME as the
+//! account: in the Secret Service (GNOME Keyring, KWallet) on Linux and other
+//! Unixes, in the login keychain on macOS, and as a generic credential
+//! `file-encryptor:NAME` in the Windows Credential Manager. The `keyring`
+//! crate reaches each store.
+
+use anyhow::{anyhow, Context, Result};
+use zeroize::Zeroizing;
+
+use file_encryptor::i18n::t;
+
+use crate::log;
+
+const SERVICE: &str = "file-encryptor";
+
+/// The password stored under `name`, if there is one.
+pub fn get(name: &str) -> Result<Option<Zeroizing<String>>> {
+    let password = match entry(name).and_then(|entry| entry.get_password()) {
+        Ok(password) => Some(Zeroizing::new(password)),
+        Err(::keyring::Error::NoEntry) => None,
+        Err(e) => return Err(e).with_context(|| t!("keyring-failed", name = name)),
+    };
+    match password {
+        Some(password) if password.is_empty() => Err(anyhow!(t!("password-empty"))),
+        password => Ok(password),
+    }
+}
+
+/// Stores `password` under `name`.
+pub fn set(name: &str, password: &str) -> Result<()> {
+    entry(name)
+        .and_then(|entry| entry.set_password(password))
+        .with_context(|| t!("keyring-failed", name = name))?;
+    log::notice(&t!("keyring-stored", name = name));
+    Ok(())
+}
+
+/// The entry for `name`. On Windows its target is `file-encryptor:NAME`
+/// rather than the crate's `NAME.file-encryptor`, so the Credential Manager
+/// lists it under the tool's name.
+fn entry(name: &str) -> ::keyring::Result<::keyring::Entry> {
+    if cfg!(windows) {
+        ::keyring::Entry::new_with_target(&format!("{SERVICE}:{name}"), SERVICE, name)
+    } else {
+        ::keyring::Entry::new(SERVICE, name)
+    }
+}
@@ -0,0 +1,1318 @@
+//! The `file-encryptor` formats, for programs that embed encryption instead
+//! of running the binary.
+//!
+//! [`Encryptor`] holds one password, [`key_file`] or both, or the public keys,
+//! hardware tokens and passwords of several [`recipient`]s, and caches the
+//! keys derived from them. It
+//! writes either a single AEAD message (versioned [`header`], ciphertext,
+//! transport checksum footer) or, for input of any size, the chunked
+//! [`stream`] format; decryption accepts both, as well as the headerless
+//! files of earlier versions. The cipher is AES-256-GCM unless
+//! [`Encryptor::with_cipher`] picks ChaCha20-Poly1305 or XChaCha20-Poly1305,
+//! and [`Encryptor::with_compression`] compresses the plaintext first.
+//! [`Encryptor::with_metadata`] records the original file's name,
+//! permissions and modification time, encrypted, in the header, as does
+//! every single message and every stream whose plaintext is known up front
+//! ([`Encryptor::with_content_checksum`]) with its SHA-256, which decryption
+//! checks what it restored against. [`Encryptor::with_deterministic`] makes
+//! equal plaintexts encrypt to equal files, for deduplication.
+//!
+//! Everything here works on byte slices, readers and writers, never on paths,
+//! so with the default `cli` feature turned off the crate builds for wasm32.
+//! Randomness comes from the operating system with the `os-rng` feature, or
+//! from whatever [`entropy::set_source`] installs.
+//!
+//! ```no_run
+//! use file_encryptor::Encryptor;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut encryptor = Encryptor::new("correct horse battery staple");
+//! let sealed = encryptor.encrypt(b"attack at dawn")?;
+//! assert_eq!(encryptor.decrypt(&sealed)?, b"attack at dawn");
+//!
+//! let input = std::fs::File::open("disk.img")?;
+//! let output = std::fs::File::create("disk.img.enc")?;
+//! encryptor.encrypt_reader_to_writer(input, output)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Errors are [`anyhow::Error`]s with messages in the user's language (see
+//! [`i18n`]); a broken random number generator surfaces as an
+//! [`EntropyError`] inside them, and a file that doesn't decrypt as a
+//! [`DecryptError`] wherever a wrong key can be told from a damaged file.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key,
+};
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Read, Write},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+mod compress;
+pub mod date;
+pub mod entropy;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod header;
+pub mod i18n;
+pub mod key_file;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod recipient;
+pub mod recovery;
+pub mod shamir;
+pub mod stream;
+
+pub use entropy::EntropyError;
+pub use header::{Cipher, Compression, Kdf, KeySource, Metadata};
+use header::{Header, CONTENT_CHECKSUM_LENGTH};
+pub use recipient::TokenAgreement;
+use recipient::{Recipient, Slot};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+const KEY_LENGTH: usize = 32; // 256 bits for every cipher
+pub const SALT_LENGTH: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000; // Adjust as needed
+const NONCE_LENGTH: usize = 12;
+// Trailer holding an unkeyed BLAKE3 digest of everything before it, so transfer
+// damage can be detected without the password.
+pub const FOOTER_MAGIC: &[u8; 8] = b"FENCSUM1";
+pub const FOOTER_LENGTH: usize = blake3::OUT_LEN + FOOTER_MAGIC.len();
+
+/// Files from before the versioned header start with the nonce and salt and
+/// nothing else, and are always PBKDF2 with the default iterations.
+const LEGACY_METADATA_LENGTH: usize = NONCE_LENGTH + SALT_LENGTH;
+
+/// Called with the duration of every key derivation, once set.
+static KDF_OBSERVER: OnceLock<fn(Duration)> = OnceLock::new();
+
+/// Has `observer` called with the time each key derivation took, for
+/// metrics. Only the first observer registered is kept.
+pub fn observe_key_derivation(observer: fn(Duration)) {
+    let _ = KDF_OBSERVER.set(observer);
+}
+
+/// A key, wiped from memory when dropped, as is every key and key file the
+/// crate holds.
+type SecretKey = Zeroizing<Key<Aes256Gcm>>;
+/// A key file or random file key, wiped when dropped.
+type FileKey = Zeroizing<[u8; KEY_LENGTH]>;
+
+/// Why a file didn't decrypt, for callers that act on the cause; find it with
+/// [`anyhow::Error::downcast_ref`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecryptError {
+    /// The password, key file or identity isn't the file's: its key check
+    /// doesn't match, no slot opens, or an intact file doesn't authenticate.
+    WrongKey,
+    /// The checksum footer doesn't match: the file was damaged after it was
+    /// encrypted.
+    ChecksumMismatch,
+    /// The key is right, but the ciphertext doesn't authenticate under it.
+    Corrupted,
+    /// The header is cut short or doesn't parse.
+    HeaderInvalid,
+    /// The file is from a newer version, or uses an algorithm or parameters
+    /// this one doesn't accept, as the message says.
+    Unsupported(String),
+    /// The file is bound to other associated data than the caller gave, or
+    /// to none, as the message says.
+    WrongAad(String),
+    /// Everything authenticates, but the plaintext isn't what the header's
+    /// content checksum says was encrypted.
+    ContentMismatch,
+}
+
+impl DecryptError {
+    /// Whether the file, rather than the key, is at fault.
+    pub fn is_damage(&self) -> bool {
+        matches!(
+            self,
+            DecryptError::ChecksumMismatch
+                | DecryptError::Corrupted
+                | DecryptError::HeaderInvalid
+                | DecryptError::ContentMismatch
+        )
+    }
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            DecryptError::WrongKey => t!("decrypt-wrong-key"),
+            DecryptError::ChecksumMismatch => t!("checksum-mismatch"),
+            DecryptError::Corrupted => t!("decrypt-corrupted"),
+            DecryptError::HeaderInvalid => t!("header-invalid"),
+            DecryptError::ContentMismatch => t!("content-mismatch"),
+            DecryptError::Unsupported(message) | DecryptError::WrongAad(message) => {
+                message.clone()
+            }
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// `e`, a payload that failed to authenticate, as a [`DecryptError`] when
+/// the cause is known: with the key `checked` against the header the file is
+/// damaged, and with the ciphertext `intact` by its footer the key is wrong.
+fn authentication_failure(e: anyhow::Error, checked: bool, intact: bool) -> anyhow::Error {
+    match (checked, intact) {
+        (true, _) => e.context(DecryptError::Corrupted),
+        (false, true) => e.context(DecryptError::WrongKey),
+        (false, false) => e.context(t!("decrypt-failed")),
+    }
+}
+
+fn derive_key(password: &str, kdf: Kdf, salt: &[u8]) -> Result<SecretKey> {
+    // Only timed for an observer: wasm32-unknown-unknown has no clock.
+    let Some(observer) = KDF_OBSERVER.get() else {
+        return kdf.derive(password, salt);
+    };
+    let started = Instant::now();
+    let key = kdf.derive(password, salt)?;
+    observer(started.elapsed());
+    Ok(key)
+}
+
+/// A key file's contribution: HMAC-SHA256 keyed with the key file, over the
+/// password's key when there is one and over the salt when there isn't.
+fn mix_key_file(key_file: &[u8; KEY_LENGTH], message: &[u8]) -> SecretKey {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key_file).expect("HMAC accepts any key length");
+    mac.update(message);
+    Zeroizing::new(mac.finalize().into_bytes())
+}
+
+fn generate_salt() -> Result<[u8; SALT_LENGTH]> {
+    Ok(entropy::bytes()?)
+}
+
+fn generate_nonce(length: usize) -> Result<Vec<u8>> {
+    let mut nonce = vec![0; length];
+    entropy::fill(&mut nonce)?;
+    Ok(nonce)
+}
+
+/// A nonce of `length` bytes that is a keyed hash of `input`, which must
+/// hold everything the nonce seals under `key`: only equal messages then
+/// share a nonce, and their ciphertexts are equal rather than broken.
+fn synthetic_nonce(key: &Key<Aes256Gcm>, input: &[u8], length: usize) -> Vec<u8> {
+    let nonce_key = Zeroizing::new(blake3::derive_key(
+        "file-encryptor 2026 synthetic nonce",
+        key,
+    ));
+    let mut nonce = vec![0; length];
+    blake3::Hasher::new_keyed(&nonce_key)
+        .update(input)
+        .finalize_xof()
+        .fill(&mut nonce);
+    nonce
+}
+
+// `nonce` must be `cipher.nonce_length()` bytes; every cipher takes the same key type.
+fn encrypt(cipher: Cipher, key: &Key<Aes256Gcm>, nonce: &[u8], aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let payload = Payload { msg: data, aad };
+    match cipher {
+        Cipher::Aes256Gcm => Aes256Gcm::new(key).encrypt(nonce.into(), payload),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).encrypt(nonce.into(), payload),
+        Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new(key).encrypt(nonce.into(), payload),
+    }
+    .map_err(|e| anyhow!(e))  // Use anyhow!
+}
+
+fn decrypt(cipher: Cipher, key: &Key<Aes256Gcm>, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let payload = Payload { msg: ciphertext, aad };
+    match cipher {
+        Cipher::Aes256Gcm => Aes256Gcm::new(key).decrypt(nonce.into(), payload),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).decrypt(nonce.into(), payload),
+        Cipher::XChaCha20Poly1305 => XChaCha20Poly1305::new(key).decrypt(nonce.into(), payload),
+    }
+    .map_err(|e| anyhow!(e))
+}
+
+
+/// Appends the transport checksum footer to a finished ciphertext.
+fn append_footer(data: &mut Vec<u8>) {
+    let digest = blake3::hash(data);
+    data.extend_from_slice(digest.as_bytes());
+    data.extend_from_slice(FOOTER_MAGIC);
+}
+
+/// Splits off and validates the transport checksum footer, which needs no
+/// password. Returns `None` for files written before the footer existed.
+pub fn strip_footer(data: &[u8]) -> Result<Option<&[u8]>> {
+    if data.len() < FOOTER_LENGTH || !data.ends_with(FOOTER_MAGIC) {
+        return Ok(None);
+    }
+    let (body, footer) = data.split_at(data.len() - FOOTER_LENGTH);
+    let expected = blake3::Hash::from_bytes(footer[..blake3::OUT_LEN].try_into()?);
+    if blake3::hash(body) != expected {
+        return Err(DecryptError::ChecksumMismatch.into());
+    }
+    Ok(Some(body))
+}
+
+/// Encrypts `plaintext` under `password` into the on-disk format: header,
+/// ciphertext, then the transport checksum footer.
+pub fn encrypt_bytes(password: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    Encryptor::new(password).encrypt(plaintext)
+}
+
+/// Reverses [`encrypt_bytes`].
+pub fn decrypt_bytes(password: &str, file_bytes: &[u8]) -> Result<Vec<u8>> {
+    Encryptor::new(password).decrypt(file_bytes)
+}
+
+/// Password keys by the KDF and salt they were derived with, each in a cell
+/// of its own, so that the map is locked only to find the cell, and deriving
+/// one key holds up only the clones after that same key.
+type KeyCache = HashMap<(Kdf, [u8; SALT_LENGTH]), Arc<Mutex<Option<SecretKey>>>>;
+
+/// Keys derived from one password over a batch of files. Every file the
+/// session encrypts shares one salt, so the KDF runs once per batch instead of
+/// once per file; each file is still encrypted under a random key of its own,
+/// which the password's key wraps. Decryption derives each distinct salt and
+/// KDF setting only once.
+///
+/// A [`key_file`] can stand in for the password or be added to it. Each file
+/// records which it took, and decrypting with anything else is refused with a
+/// message saying what the file needs. Files for [`recipient`]s are made with
+/// [`Encryptor::to_recipients`] and opened with [`Encryptor::from_identity`],
+/// [`Encryptor::from_token`] or any one of their passwords.
+///
+/// Clones share every key derived, before or after cloning, so workers
+/// decrypting a batch in parallel derive each key once between them. They
+/// share the salt once it is drawn, so workers encrypting a batch can each
+/// take a clone after [`Encryptor::derive`].
+///
+/// Derived keys, key files and file keys are wiped from memory when the last
+/// session holding them is dropped. The password is only borrowed, so wiping
+/// it is up to the caller.
+#[derive(Clone)]
+pub struct Encryptor<'a> {
+    password: Option<&'a str>,
+    /// The key file, or for recipients the key of the file last encrypted.
+    key_file: Option<FileKey>,
+    /// The file key wrapped for each recipient, for the headers of new files
+    /// that all share that key.
+    slots: Vec<Slot>,
+    /// Who the random key of every new file is wrapped for.
+    recipients: Vec<Recipient>,
+    /// Our secret key, to open the file key sealed to us.
+    identity: Option<crypto_box::SecretKey>,
+    /// The hardware tokens we reach, to open the file key wrapped for one.
+    token: Option<TokenAgreement>,
+    /// What new files are encrypted with.
+    kdf: Kdf,
+    cipher: Cipher,
+    compression: Compression,
+    /// What the headers of new files record about their plaintext.
+    metadata: Option<Metadata>,
+    /// The SHA-256 of the plaintext of the next stream, known up front.
+    content_checksum: Option<[u8; CONTENT_CHECKSUM_LENGTH]>,
+    /// Whether new files derive their salt and nonces instead of drawing them.
+    deterministic: bool,
+    /// The associated data files are bound to, on top of their header.
+    aad: Option<Vec<u8>>,
+    /// Drawn on the first encryption, so decrypting needs no randomness.
+    salt: Option<[u8; SALT_LENGTH]>,
+    /// How many threads seal or open the chunks of a stream.
+    threads: usize,
+    /// How long the chunks of a new stream are.
+    chunk_length: u32,
+    keys: Arc<Mutex<KeyCache>>,
+}
+
+impl<'a> Encryptor<'a> {
+    pub fn new(password: &'a str) -> Self {
+        Encryptor::with_kdf(password, Kdf::default())
+    }
+
+    /// Encrypts with `kdf` instead of the default. Decryption always uses
+    /// whatever each file records.
+    pub fn with_kdf(password: &'a str, kdf: Kdf) -> Self {
+        Encryptor {
+            password: Some(password),
+            ..Encryptor::unkeyed(kdf)
+        }
+    }
+
+    /// Uses the key file alone, with no password and so no KDF.
+    pub fn from_key_file(key_file: [u8; KEY_LENGTH]) -> Self {
+        Encryptor {
+            key_file: Some(Zeroizing::new(key_file)),
+            ..Encryptor::unkeyed(Kdf::default())
+        }
+    }
+
+    /// Encrypts so that any of `public_keys` and `passwords`, of which there
+    /// is at least one, opens the file. Each password's key is derived up
+    /// front, with its own salt for `kdf`, and every file the session
+    /// encrypts gets a random file key wrapped for each recipient.
+    pub fn to_recipients(
+        public_keys: &[crypto_box::PublicKey],
+        passwords: &[&str],
+        kdf: Kdf,
+    ) -> Result<Self> {
+        recipient::check_count(public_keys.len() + passwords.len())?;
+        let mut recipients: Vec<_> = public_keys
+            .iter()
+            .map(|public_key| Recipient::PublicKey(public_key.clone()))
+            .collect();
+        for password in passwords {
+            let salt = generate_salt()?;
+            let key = derive_key(password, kdf, &salt)?;
+            recipients.push(Recipient::Password { kdf, salt, key });
+        }
+        Ok(Encryptor {
+            recipients,
+            ..Encryptor::unkeyed(kdf)
+        })
+    }
+
+    /// Decrypts files encrypted to the public key of `identity`.
+    pub fn from_identity(identity: crypto_box::SecretKey) -> Self {
+        Encryptor {
+            identity: Some(identity),
+            ..Encryptor::unkeyed(Kdf::default())
+        }
+    }
+
+    /// Decrypts files encrypted to a hardware token, which `agree` asks to do
+    /// the key agreement of its slot.
+    pub fn from_token(agree: TokenAgreement) -> Self {
+        Encryptor {
+            token: Some(agree),
+            ..Encryptor::unkeyed(Kdf::default())
+        }
+    }
+
+    /// A session with no key yet and every setting at its default, which
+    /// each constructor fills in.
+    fn unkeyed(kdf: Kdf) -> Self {
+        Encryptor {
+            password: None,
+            key_file: None,
+            slots: Vec::new(),
+            recipients: Vec::new(),
+            identity: None,
+            token: None,
+            kdf,
+            cipher: Cipher::default(),
+            compression: Compression::default(),
+            metadata: None,
+            content_checksum: None,
+            deterministic: false,
+            aad: None,
+            salt: None,
+            threads: 1,
+            chunk_length: stream::CHUNK_LENGTH,
+            keys: Arc::default(),
+        }
+    }
+
+    /// Also wraps the key of every file for the hardware token `token`, by
+    /// the SEC1 encoding of its P-256 `public_key`, on a session from
+    /// [`Encryptor::to_recipients`]. `token` is recorded in the header, to
+    /// say which token opens the file.
+    pub fn with_token(mut self, token: &str, public_key: &[u8]) -> Result<Self> {
+        recipient::check_count(self.recipients.len() + 1)?;
+        self.recipients.push(recipient::token(token, public_key)?);
+        Ok(self)
+    }
+
+    /// Requires `key_file` as well as the password.
+    pub fn with_key_file(mut self, key_file: [u8; KEY_LENGTH]) -> Self {
+        self.key_file = Some(Zeroizing::new(key_file));
+        self
+    }
+
+    /// Encrypts with `cipher` instead of AES-256-GCM. As with the KDF,
+    /// decryption follows each file's header.
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Compresses the plaintext of new files with `compression`, which a
+    /// file records so decryption decompresses it.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Records `metadata` in the header of new files, sealed under each
+    /// file's key; `None` records nothing.
+    pub fn with_metadata(mut self, metadata: Option<Metadata>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Records `digest`, the SHA-256 of the plaintext the next
+    /// [`Encryptor::encrypt_reader_to_writer`] encrypts, in its header, as a
+    /// stream's header is written before its plaintext is read; the stream
+    /// fails if what it reads doesn't match. [`Encryptor::encrypt`] records
+    /// the checksum of its plaintext anyway. `None` records nothing.
+    pub fn with_content_checksum(
+        mut self,
+        digest: Option<[u8; CONTENT_CHECKSUM_LENGTH]>,
+    ) -> Self {
+        self.content_checksum = digest;
+        self
+    }
+
+    /// Makes new files a function of their plaintext and the session's
+    /// password or key file, so that encrypting the same plaintext twice
+    /// gives the same file and backups of it deduplicate. The salt is then
+    /// derived from the password and key file rather than drawn, so one
+    /// password's key can be precomputed for every file, and anyone who sees
+    /// two files can tell whether they hold the same plaintext. Files have no
+    /// random file key, and so no slots: public keys and several passwords are
+    /// refused, and a stream needs [`Encryptor::with_content_checksum`].
+    /// Decryption needs nothing of this.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Binds new files to `aad`, associated data from outside the file such
+    /// as what it is for, and opens files bound to it; a file bound to other
+    /// associated data, or to none, is refused. `None` binds nothing.
+    pub fn with_aad(mut self, aad: Option<Vec<u8>>) -> Self {
+        self.aad = aad;
+        self
+    }
+
+    /// Seals, and opens, the chunks of a stream on up to `threads` threads
+    /// at once, while the calling thread reads the input and writes the
+    /// output in order, so one large file isn't held to one core. The file
+    /// comes out the same whatever the number; 1, the default, goes through
+    /// the chunks in turn on the calling thread, as does a target without
+    /// threads.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Writes streams in chunks of `chunk_length` bytes, held to 1 to
+    /// [`stream::MAX_CHUNK_LENGTH`], instead of [`stream::CHUNK_LENGTH`]; see
+    /// [`stream::chunk_length_for`]. The header records it, so decrypting
+    /// needs nothing of this.
+    pub fn with_chunk_length(mut self, chunk_length: u32) -> Self {
+        self.chunk_length = chunk_length.clamp(1, stream::MAX_CHUNK_LENGTH);
+        self
+    }
+
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// The file key wrapped for each recipient, empty for other key sources.
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    /// What the session holds. Files it encrypts with a password alone are
+    /// for recipients, with just the password.
+    pub fn key_source(&self) -> KeySource {
+        if self.identity.is_some()
+            || self.token.is_some()
+            || !self.slots.is_empty()
+            || !self.recipients.is_empty()
+        {
+            return KeySource::Recipients;
+        }
+        match (self.password, &self.key_file) {
+            (_, None) => KeySource::Password,
+            (None, Some(_)) => KeySource::KeyFile,
+            (Some(_), Some(_)) => KeySource::PasswordAndKeyFile,
+        }
+    }
+
+    /// The salt the session encrypts under.
+    pub(crate) fn salt(&mut self) -> Result<[u8; SALT_LENGTH]> {
+        match self.salt {
+            Some(salt) => Ok(salt),
+            None if self.deterministic => Ok(*self.salt.insert(self.deterministic_salt())),
+            None => Ok(*self.salt.insert(generate_salt()?)),
+        }
+    }
+
+    /// The salt of [`Encryptor::with_deterministic`], from what the session
+    /// holds.
+    fn deterministic_salt(&self) -> [u8; SALT_LENGTH] {
+        let mut hasher = blake3::Hasher::new_derive_key("file-encryptor 2026 deterministic salt");
+        if let Some(password) = self.password {
+            hasher.update(&(password.len() as u64).to_le_bytes());
+            hasher.update(password.as_bytes());
+        }
+        if let Some(key_file) = &self.key_file {
+            hasher.update(key_file.as_slice());
+        }
+        let mut salt = [0; SALT_LENGTH];
+        hasher.finalize_xof().fill(&mut salt);
+        salt
+    }
+
+    /// Draws the salt and derives the key new files are encrypted with, or
+    /// whose keys are wrapped with, which otherwise happens on the first
+    /// encryption.
+    pub fn derive(&mut self) -> Result<()> {
+        if self.wraps_file_keys() {
+            return self.wrapping().map(drop);
+        }
+        let salt = self.salt()?;
+        let header = self.header(salt, Vec::new(), 0);
+        self.key(&header).map(drop)
+    }
+
+    /// Whether every new file gets a random key of its own, wrapped for
+    /// [`Encryptor::wrapping`].
+    fn wraps_file_keys(&self) -> bool {
+        !self.recipients.is_empty()
+            || (self.key_source() == KeySource::Password && !self.deterministic)
+    }
+
+    /// Who the key of a new file is wrapped for: the session's recipients,
+    /// or for a password alone that password.
+    fn wrapping(&mut self) -> Result<Vec<Recipient>> {
+        let Some(password) = self.password.filter(|_| self.recipients.is_empty()) else {
+            return Ok(self.recipients.clone());
+        };
+        let salt = self.salt()?;
+        let key = self.password_key(password, self.kdf, salt)?;
+        Ok(vec![Recipient::Password {
+            kdf: self.kdf,
+            salt,
+            key,
+        }])
+    }
+
+    /// The header of a new file under `nonce`, with the key check of the key
+    /// it is encrypted with and the sealed `content_checksum` if there is
+    /// one, and that key.
+    pub(crate) fn sealing_header(
+        &mut self,
+        nonce: Vec<u8>,
+        chunk_length: u32,
+        content_checksum: Option<&[u8; CONTENT_CHECKSUM_LENGTH]>,
+    ) -> Result<(Header, SecretKey)> {
+        if self.deterministic {
+            if self.key_source() == KeySource::Recipients {
+                return Err(anyhow!(t!("deterministic-recipients")));
+            }
+            if content_checksum.is_none() {
+                return Err(anyhow!(t!("deterministic-unknown-content")));
+            }
+        }
+        let salt = self.salt()?;
+        let mut header = self.header(salt, nonce, chunk_length);
+        let key = match self.wraps_file_keys() {
+            true => {
+                let file_key = Zeroizing::new(entropy::bytes()?);
+                header.slots = self
+                    .wrapping()?
+                    .iter()
+                    .map(|recipient| recipient.slot(&file_key))
+                    .collect::<Result<_>>()?;
+                let key = mix_key_file(&file_key, &header.salt);
+                // So that the session can check what it wrote.
+                if !self.recipients.is_empty() {
+                    self.key_file = Some(file_key);
+                }
+                key
+            }
+            false => self.key(&header)?,
+        };
+        header.key_check = Some(header::key_check(&key));
+        header.aad_check = self.aad.as_ref().map(|aad| header::aad_check(&key, aad));
+        let deterministic = self.deterministic;
+        if let Some(metadata) = &self.metadata {
+            header.metadata = metadata.seal(self.cipher, &key, deterministic)?;
+        }
+        if let Some(digest) = content_checksum {
+            header.content_checksum =
+                header::seal_content_checksum(self.cipher, &key, digest, deterministic)?;
+        }
+        if let (true, Some(digest)) = (deterministic, content_checksum) {
+            // Everything the payload is sealed with: the rest of the header,
+            // the associated data and, by its digest, the plaintext.
+            let length = header.nonce.len();
+            header.nonce = vec![0; length];
+            let mut input = header.to_bytes()?;
+            if let Some(aad) = &self.aad {
+                input.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+                input.extend_from_slice(aad);
+            }
+            input.extend_from_slice(digest);
+            header.nonce = synthetic_nonce(&key, &input, length);
+        }
+        Ok((header, key))
+    }
+
+    /// A header for a new file under this session's key source and KDF. The
+    /// slots of a file whose key is its own are placeholders until it is
+    /// sealed.
+    pub(crate) fn header(
+        &self,
+        salt: [u8; SALT_LENGTH],
+        nonce: Vec<u8>,
+        chunk_length: u32,
+    ) -> Header {
+        let (key_source, slots) = match self.wraps_file_keys() {
+            true if self.recipients.is_empty() => (
+                KeySource::Recipients,
+                vec![recipient::placeholder(self.kdf, salt)],
+            ),
+            true => (
+                KeySource::Recipients,
+                self.recipients.iter().map(Recipient::placeholder).collect(),
+            ),
+            false => (self.key_source(), self.slots.clone()),
+        };
+        Header {
+            cipher: self.cipher,
+            key_source,
+            compression: self.compression,
+            kdf: key_source.needs_password().then_some(self.kdf),
+            salt,
+            nonce,
+            chunk_length,
+            slots,
+            key_check: None,
+            metadata: Vec::new(),
+            aad_check: None,
+            content_checksum: Vec::new(),
+        }
+    }
+
+    /// `header_aad`, what the file with `header` under `key` authenticates
+    /// its payload with for its header, followed by the associated data it is
+    /// bound to, which must be this session's.
+    pub(crate) fn bind_aad(
+        &self,
+        header: &Header,
+        key: &SecretKey,
+        mut header_aad: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        match (&self.aad, header.aad_check) {
+            (None, None) => {}
+            (None, Some(_)) => return Err(DecryptError::WrongAad(t!("aad-required")).into()),
+            (Some(_), None) => return Err(DecryptError::WrongAad(t!("aad-unexpected")).into()),
+            (Some(aad), Some(check)) => {
+                if !bool::from(header::aad_check(key, aad).ct_eq(&check)) {
+                    return Err(DecryptError::WrongAad(t!("aad-mismatch")).into());
+                }
+                // The header says where it ends, so the two can't run together.
+                header_aad.extend_from_slice(aad);
+            }
+        }
+        Ok(header_aad)
+    }
+
+    /// Whether this session can open files of key `source`: the one it
+    /// encrypts with, or for a password alone also files for recipients,
+    /// which may list it.
+    pub fn accepts(&self, source: KeySource) -> bool {
+        let own = self.key_source();
+        source == own || (source == KeySource::Recipients && own == KeySource::Password)
+    }
+
+    /// The key for a file with `header`, if this session holds what it
+    /// records as its key source and it matches the header's key check.
+    pub(crate) fn key(&mut self, header: &Header) -> Result<SecretKey> {
+        if !self.accepts(header.key_source) {
+            return Err(header.key_source.mismatch());
+        }
+        let key = if header.key_source == KeySource::Recipients {
+            let file_key = self.file_key(&header.slots)?;
+            mix_key_file(&file_key, &header.salt)
+        } else {
+            let password_key = match (self.password, header.kdf) {
+                (Some(password), Some(kdf)) => {
+                    Some(self.password_key(password, kdf, header.salt)?)
+                }
+                _ => None,
+            };
+            match (password_key, &self.key_file) {
+                (Some(key), None) => key,
+                (Some(key), Some(key_file)) => mix_key_file(key_file, &key),
+                (None, Some(key_file)) => mix_key_file(key_file, &header.salt),
+                (None, None) => unreachable!("an Encryptor always holds a password or a key file"),
+            }
+        };
+        if let Some(check) = header.key_check {
+            if !bool::from(header::key_check(&key).ct_eq(&check)) {
+                return Err(DecryptError::WrongKey.into());
+            }
+        }
+        Ok(key)
+    }
+
+    /// The metadata recorded by the file that starts with `file_bytes`, in
+    /// either layout, if it has any.
+    pub fn metadata(&mut self, file_bytes: &[u8]) -> Result<Option<Metadata>> {
+        if !file_bytes.starts_with(header::MAGIC) {
+            return Ok(None);
+        }
+        let header = Header::parse(file_bytes)?;
+        if header.metadata.is_empty() {
+            return Ok(None);
+        }
+        let key = self.key(&header)?;
+        Metadata::open(&header.metadata, header.cipher, &key).map(Some)
+    }
+
+    /// A key for authenticating what travels with the file that starts with
+    /// `file_bytes`, such as the index of its split parts: `context`'s key
+    /// derived from the file's, which gives nothing of it away. The session
+    /// that encrypted the file gets the same key as one that decrypts it.
+    pub fn binding_key(
+        &mut self,
+        file_bytes: &[u8],
+        context: &str,
+    ) -> Result<Zeroizing<[u8; 32]>> {
+        let header = Header::parse(file_bytes)?;
+        let key = self.key(&header)?;
+        Ok(Zeroizing::new(blake3::derive_key(context, &key)))
+    }
+
+    /// The SHA-256 of the plaintext recorded by the file that starts with
+    /// `file_bytes`, in either layout, if it has one.
+    pub fn content_checksum(
+        &mut self,
+        file_bytes: &[u8],
+    ) -> Result<Option<[u8; CONTENT_CHECKSUM_LENGTH]>> {
+        if !file_bytes.starts_with(header::MAGIC) {
+            return Ok(None);
+        }
+        let header = Header::parse(file_bytes)?;
+        if header.content_checksum.is_empty() {
+            return Ok(None);
+        }
+        let key = self.key(&header)?;
+        header::open_content_checksum(&header.content_checksum, header.cipher, &key).map(Some)
+    }
+
+    fn password_key(
+        &self,
+        password: &str,
+        kdf: Kdf,
+        salt: [u8; SALT_LENGTH],
+    ) -> Result<SecretKey> {
+        let cell = {
+            let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+            keys.entry((kdf, salt)).or_default().clone()
+        };
+        // Held while deriving, so clones racing for the same key wait for it
+        // instead of deriving it again; a failed derivation leaves the cell
+        // empty for the next to try.
+        let mut cell = cell.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(key) = &*cell {
+            return Ok(key.clone());
+        }
+        let key = derive_key(password, kdf, &salt)?;
+        *cell = Some(key.clone());
+        Ok(key)
+    }
+
+    /// The file key of a file for recipients, from whichever of its `slots`
+    /// this session opens.
+    fn file_key(&self, slots: &[Slot]) -> Result<FileKey> {
+        if let Some(identity) = &self.identity {
+            return recipient::open(identity, slots).context(DecryptError::WrongKey);
+        }
+        if let Some(agree) = &self.token {
+            return recipient::open_token(agree, slots);
+        }
+        let Some(password) = self.password else {
+            // Encrypting for recipients, and so holding the file key.
+            return self.key_file.clone().ok_or_else(|| KeySource::Recipients.mismatch());
+        };
+        let (_, file_key) = self.password_slot(password, slots)?;
+        Ok(file_key)
+    }
+
+    /// Which of `slots` `password` opens, and the file key in it.
+    fn password_slot(&self, password: &str, slots: &[Slot]) -> Result<(usize, FileKey)> {
+        for (index, slot) in slots.iter().enumerate() {
+            if let Slot::Password {
+                kdf,
+                salt,
+                nonce,
+                wrapped,
+            } = slot
+            {
+                let key = self.password_key(password, *kdf, *salt)?;
+                if let Some(file_key) = recipient::unwrap(&key, *nonce, wrapped) {
+                    return Ok((index, file_key));
+                }
+            }
+        }
+        Err(DecryptError::WrongKey.into())
+    }
+
+    /// For the file for recipients with `header`, which this session's
+    /// password opens: a session that encrypts with the same file key, cipher,
+    /// compression and recipients, except that the password's slot is
+    /// rewrapped for the password of `new`, a session for a password alone,
+    /// which derives its key once however many files it rekeys. A file whose
+    /// slots are [`detached`](header::slots_detached) only needs its slots
+    /// replaced with the new session's [`Encryptor::slots`]; any other is
+    /// re-encrypted.
+    pub fn rekeyed(&self, header: &Header, new: &mut Encryptor) -> Result<Encryptor<'static>> {
+        let Some(password) = self.password.filter(|_| header.key_source == KeySource::Recipients)
+        else {
+            return Err(header.key_source.mismatch());
+        };
+        if new.key_source() != KeySource::Password {
+            return Err(new.key_source().mismatch());
+        }
+        let (index, file_key) = self.password_slot(password, &header.slots)?;
+        let mut slots = header.slots.clone();
+        slots[index] = new.wrapping()?[0].slot(&file_key)?;
+        Ok(Encryptor {
+            slots,
+            cipher: header.cipher,
+            compression: header.compression,
+            aad: self.aad.clone(),
+            ..Encryptor::from_key_file(*file_key)
+        })
+    }
+
+    /// Encrypts `plaintext` as one message; see [`encrypt_bytes`].
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        // 1. Build the header, which is authenticated along with the data,
+        // and get the session key
+        let nonce = generate_nonce(self.cipher.nonce_length())?;
+        let digest: [u8; CONTENT_CHECKSUM_LENGTH] = Sha256::digest(plaintext).into();
+        let (header, key) = self.sealing_header(nonce, 0, Some(&digest))?;
+        let header_bytes = header.to_bytes()?;
+
+        // 2. Encrypt data
+        let compressed;
+        let plaintext = match self.compression {
+            Compression::None => plaintext,
+            compression => {
+                compressed = compress::compress(compression, plaintext)?;
+                &compressed
+            }
+        };
+        let aad = self.bind_aad(&header, &key, header.associated_data()?)?;
+        let encrypted_data = encrypt(self.cipher, &key, &header.nonce, &aad, plaintext)
+            .with_context(|| t!("encrypt-failed"))?;
+
+        // 3. Combine header and encrypted data
+        let mut full_encrypted_data = header_bytes;
+        full_encrypted_data.extend_from_slice(&encrypted_data);
+        append_footer(&mut full_encrypted_data);
+        Ok(full_encrypted_data)
+    }
+
+    /// Decrypts a whole file held in memory, in any of the formats.
+    pub fn decrypt(&mut self, file_bytes: &[u8]) -> Result<Vec<u8>> {
+        if stream::is_stream(file_bytes)? {
+            let mut plaintext = Vec::new();
+            stream::decrypt(self, file_bytes, &mut plaintext)?;
+            return Ok(plaintext);
+        }
+        let stripped = strip_footer(file_bytes)?;
+        let intact = stripped.is_some();
+        let encrypted_data = stripped.unwrap_or(file_bytes);
+
+        if encrypted_data.starts_with(header::MAGIC) {
+            let header = Header::parse(encrypted_data)?;
+            let (header_bytes, encrypted_data) =
+                encrypted_data.split_at(Header::length(encrypted_data)?);
+            let key = self.key(&header)?;
+            let aad = self.bind_aad(&header, &key, header::associated_data(header_bytes)?)?;
+            let payload = decrypt(header.cipher, &key, &header.nonce, &aad, encrypted_data)
+                .map_err(|e| authentication_failure(e, header.key_check.is_some(), intact))?;
+            let plaintext = compress::decompress(header.compression, payload)?;
+            if !header.content_checksum.is_empty() {
+                let digest =
+                    header::open_content_checksum(&header.content_checksum, header.cipher, &key)?;
+                if !bool::from(Sha256::digest(&plaintext).ct_eq(&digest)) {
+                    return Err(DecryptError::ContentMismatch.into());
+                }
+            }
+            return Ok(plaintext);
+        }
+
+        // No header: a file from an earlier version, or not one of ours at all.
+        if encrypted_data.len() < LEGACY_METADATA_LENGTH {
+            return Err(anyhow!(t!("decrypt-failed-headerless")));
+        }
+        let (nonce, rest) = encrypted_data.split_at(NONCE_LENGTH);
+        let (salt, encrypted_data) = rest.split_at(SALT_LENGTH);
+        let header = Header::legacy(salt.try_into()?, nonce.to_vec());
+        let key = self.key(&header)?;
+        self.bind_aad(&header, &key, Vec::new())?;
+
+        decrypt(Cipher::Aes256Gcm, &key, nonce, &[], encrypted_data).map_err(|e| match intact {
+            true => e.context(DecryptError::WrongKey),
+            false => e.context(t!("decrypt-failed-headerless")),
+        })
+    }
+
+    /// Encrypts everything `reader` yields to `writer` in the chunked format,
+    /// with bounded memory, returning the number of bytes written.
+    pub fn encrypt_reader_to_writer(
+        &mut self,
+        reader: impl Read,
+        writer: impl Write,
+    ) -> Result<u64> {
+        stream::encrypt(self, reader, writer)
+    }
+
+    /// Reverses [`Encryptor::encrypt_reader_to_writer`], authenticating each
+    /// chunk before writing it. Use [`stream::Decryptor`] to see the first
+    /// chunk before committing to an output.
+    pub fn decrypt_reader_to_writer(
+        &mut self,
+        reader: impl Read,
+        writer: impl Write,
+    ) -> Result<()> {
+        stream::decrypt(self, reader, writer)
+    }
+
+    /// [`Encryptor::encrypt_reader_to_writer`] over tokio's async reader and
+    /// writer.
+    #[cfg(feature = "tokio")]
+    pub async fn encrypt_async_reader_to_writer(
+        &mut self,
+        reader: impl tokio::io::AsyncRead + Unpin,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> Result<u64> {
+        stream::encrypt_async(self, reader, writer).await
+    }
+
+    /// [`Encryptor::decrypt_reader_to_writer`] over tokio's async reader and
+    /// writer.
+    #[cfg(feature = "tokio")]
+    pub async fn decrypt_async_reader_to_writer(
+        &mut self,
+        reader: impl tokio::io::AsyncRead + Unpin,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> Result<()> {
+        stream::decrypt_async(self, reader, writer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough to derive in every test; files record their KDF, so
+    /// nothing else changes.
+    const KDF: Kdf = Kdf::Pbkdf2 { iterations: 1 };
+
+    fn session(password: &str) -> Encryptor<'_> {
+        Encryptor::with_kdf(password, KDF)
+    }
+
+    fn cause(e: anyhow::Error) -> Option<DecryptError> {
+        e.downcast_ref::<DecryptError>().cloned()
+    }
+
+    /// `file`, a single message or a stream, with its header given `slots`
+    /// and the byte `flip` places after the header, if any, changed, under a
+    /// new footer so the change gets past the transport checksum.
+    fn altered(file: &[u8], slots: Option<&[Slot]>, flip: Option<usize>) -> Vec<u8> {
+        let body = strip_footer(file).unwrap().unwrap();
+        let (header, payload) = body.split_at(Header::length(body).unwrap());
+        let mut out = match slots {
+            Some(slots) => header::replace_slots(header, slots).unwrap(),
+            None => header.to_vec(),
+        };
+        let at = out.len();
+        out.extend_from_slice(payload);
+        if let Some(flip) = flip {
+            out[at + flip] ^= 1;
+        }
+        append_footer(&mut out);
+        out
+    }
+
+    #[test]
+    fn password_files_wrap_a_random_file_key() {
+        let mut encryptor = session("pw");
+        let file = encryptor.encrypt(b"hello").unwrap();
+        let header = Header::parse(&file).unwrap();
+        assert_eq!(header.key_source, KeySource::Recipients);
+        assert!(matches!(header.slots[..], [Slot::Password { .. }]));
+        // Under one salt, so one derivation, but a file key of its own.
+        let again = Header::parse(&encryptor.encrypt(b"hello").unwrap()).unwrap();
+        assert_eq!(again.salt, header.salt);
+        assert_ne!(again.slots, header.slots);
+
+        assert_eq!(session("pw").decrypt(&file).unwrap(), b"hello");
+        let wrong = session("wrong").decrypt(&file).unwrap_err();
+        assert_eq!(cause(wrong), Some(DecryptError::WrongKey));
+
+        let Slot::Password { kdf, salt, nonce, mut wrapped } = header.slots[0].clone() else {
+            unreachable!();
+        };
+        wrapped[0] ^= 1;
+        let slot = Slot::Password { kdf, salt, nonce, wrapped };
+        let rewrapped = altered(&file, Some(&[slot]), None);
+        assert_eq!(
+            cause(session("pw").decrypt(&rewrapped).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&file, None, Some(0));
+        assert_eq!(
+            cause(session("pw").decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+        let mut cut = file.clone();
+        cut.truncate(file.len() - 1);
+        assert!(session("pw").decrypt(&cut).is_err());
+    }
+
+    /// A file of the layout that password files had before the file key:
+    /// the payload sealed under the password's key itself.
+    #[test]
+    fn files_sealed_under_the_password_key_still_decrypt() {
+        let salt = [2; SALT_LENGTH];
+        let key = derive_key("pw", KDF, &salt).unwrap();
+        let header = Header {
+            key_source: KeySource::Password,
+            kdf: Some(KDF),
+            slots: Vec::new(),
+            key_check: Some(header::key_check(&key)),
+            ..session("pw").header(salt, vec![1; NONCE_LENGTH], 0)
+        };
+        let mut file = header.to_bytes().unwrap();
+        let aad = header.associated_data().unwrap();
+        file.extend(encrypt(Cipher::Aes256Gcm, &key, &header.nonce, &aad, b"hello").unwrap());
+        append_footer(&mut file);
+
+        assert_eq!(session("pw").decrypt(&file).unwrap(), b"hello");
+        assert_eq!(
+            cause(session("wrong").decrypt(&file).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&file, None, Some(3));
+        assert_eq!(
+            cause(session("pw").decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+    }
+
+    #[test]
+    fn rekeying_rewraps_only_the_password_slot() {
+        let file = session("old").encrypt(b"hello").unwrap();
+        let header = Header::parse(&file).unwrap();
+        let mut new = session("new");
+        let wrong = session("wrong").rekeyed(&header, &mut new).err().unwrap();
+        assert_eq!(cause(wrong), Some(DecryptError::WrongKey));
+        let rekeyed = session("old").rekeyed(&header, &mut new).unwrap();
+        let rewritten = altered(&file, Some(rekeyed.slots()), None);
+        // The payload, and so everything after the header, is untouched.
+        let length = Header::length(&file).unwrap();
+        let new_length = Header::length(&rewritten).unwrap();
+        assert_eq!(
+            file[length..file.len() - FOOTER_LENGTH],
+            rewritten[new_length..rewritten.len() - FOOTER_LENGTH]
+        );
+
+        assert_eq!(session("new").decrypt(&rewritten).unwrap(), b"hello");
+        assert_eq!(
+            cause(session("old").decrypt(&rewritten).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&rewritten, None, Some(0));
+        assert_eq!(
+            cause(session("new").decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+
+        // A stream's slots are replaced the same way.
+        let mut stream = Vec::new();
+        session("old").encrypt_reader_to_writer(&b"hello"[..], &mut stream).unwrap();
+        let (header, _) = stream::header(stream.as_slice()).unwrap();
+        let rekeyed = session("old").rekeyed(&header, &mut new).unwrap();
+        let rewritten = altered(&stream, Some(rekeyed.slots()), None);
+        assert_eq!(session("new").decrypt(&rewritten).unwrap(), b"hello");
+    }
+
+    /// Files without slots have nothing to rewrap, and are re-encrypted.
+    #[test]
+    fn files_without_slots_are_not_rekeyed_in_place() {
+        let mut deterministic = session("old").with_deterministic(true);
+        let file = deterministic.encrypt(b"hello").unwrap();
+        let header = Header::parse(&file).unwrap();
+        assert_eq!(header.key_source, KeySource::Password);
+        assert!(session("old").rekeyed(&header, &mut session("new")).is_err());
+    }
+
+    fn identity(seed: u8) -> crypto_box::SecretKey {
+        crypto_box::SecretKey::from([seed; crypto_box::KEY_SIZE])
+    }
+
+    #[test]
+    fn public_key_files_open_only_with_their_identity() {
+        let public_key = identity(1).public_key();
+        let mut encryptor = Encryptor::to_recipients(&[public_key], &[], KDF).unwrap();
+        let file = encryptor.encrypt(b"hello").unwrap();
+        let header = Header::parse(&file).unwrap();
+        assert!(matches!(header.slots[..], [Slot::PublicKey(_)]));
+        // The sender needs no secret to check what it wrote.
+        assert_eq!(encryptor.decrypt(&file).unwrap(), b"hello");
+
+        assert_eq!(Encryptor::from_identity(identity(1)).decrypt(&file).unwrap(), b"hello");
+        assert_eq!(
+            cause(Encryptor::from_identity(identity(2)).decrypt(&file).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        assert!(session("pw").decrypt(&file).is_err());
+
+        let Slot::PublicKey(mut sealed) = header.slots[0].clone() else {
+            unreachable!();
+        };
+        sealed[crypto_box::SEALBYTES] ^= 1;
+        let resealed = altered(&file, Some(&[Slot::PublicKey(sealed)]), None);
+        assert_eq!(
+            cause(Encryptor::from_identity(identity(1)).decrypt(&resealed).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&file, None, Some(0));
+        assert_eq!(
+            cause(Encryptor::from_identity(identity(1)).decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+    }
+
+    #[test]
+    fn any_one_of_several_recipients_opens_the_file() {
+        let public_keys = [identity(1).public_key(), identity(2).public_key()];
+        let mut encryptor = Encryptor::to_recipients(&public_keys, &["a", "b"], KDF).unwrap();
+        let file = encryptor.encrypt(b"hello").unwrap();
+        assert_eq!(Header::parse(&file).unwrap().slots.len(), 4);
+        for mut opener in [
+            Encryptor::from_identity(identity(1)),
+            Encryptor::from_identity(identity(2)),
+            session("a"),
+            session("b"),
+        ] {
+            assert_eq!(opener.decrypt(&file).unwrap(), b"hello");
+        }
+        assert_eq!(
+            cause(session("c").decrypt(&file).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        // Each password's slot has its own salt.
+        let salts: Vec<_> = Header::parse(&file)
+            .unwrap()
+            .slots
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Password { salt, .. } => Some(*salt),
+                _ => None,
+            })
+            .collect();
+        assert_ne!(salts[0], salts[1]);
+
+        let damaged = altered(&file, None, Some(0));
+        assert_eq!(
+            cause(session("b").decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+        let too_many = vec!["p"; recipient::MAX_RECIPIENTS + 1];
+        assert!(Encryptor::to_recipients(&[], &too_many, KDF).is_err());
+    }
+
+    #[test]
+    fn files_open_only_with_the_associated_data_they_are_bound_to() {
+        let bound = |aad: Option<&[u8]>| session("pw").with_aad(aad.map(<[u8]>::to_vec));
+        let file = bound(Some(b"invoice 7")).encrypt(b"hello").unwrap();
+        assert_eq!(bound(Some(b"invoice 7")).decrypt(&file).unwrap(), b"hello");
+        for aad in [None, Some(&b"invoice 8"[..]), Some(b"")] {
+            let refused = bound(aad).decrypt(&file).unwrap_err();
+            assert!(matches!(cause(refused), Some(DecryptError::WrongAad(_))));
+        }
+        let unbound = session("pw").encrypt(b"hello").unwrap();
+        let refused = bound(Some(b"invoice 7")).decrypt(&unbound).unwrap_err();
+        assert!(matches!(cause(refused), Some(DecryptError::WrongAad(_))));
+
+        let mut wrong_key = session("wrong").with_aad(Some(b"invoice 7".to_vec()));
+        assert_eq!(
+            cause(wrong_key.decrypt(&file).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&file, None, Some(0));
+        assert_eq!(
+            cause(bound(Some(b"invoice 7")).decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+
+        let mut stream = Vec::new();
+        bound(Some(b"invoice 7"))
+            .encrypt_reader_to_writer(&b"hello"[..], &mut stream)
+            .unwrap();
+        assert_eq!(bound(Some(b"invoice 7")).decrypt(&stream).unwrap(), b"hello");
+        assert!(bound(Some(b"invoice 8")).decrypt(&stream).is_err());
+    }
+
+    #[test]
+    fn deterministic_files_are_equal_for_equal_plaintexts() {
+        let deterministic = |password| session(password).with_deterministic(true);
+        let file = deterministic("pw").encrypt(b"hello").unwrap();
+        assert_eq!(deterministic("pw").encrypt(b"hello").unwrap(), file);
+        assert_ne!(deterministic("pw").encrypt(b"hellp").unwrap(), file);
+        assert_ne!(deterministic("pv").encrypt(b"hello").unwrap(), file);
+        let bound = deterministic("pw").with_aad(Some(b"a".to_vec())).encrypt(b"hello");
+        assert_ne!(bound.unwrap(), file);
+
+        assert_eq!(session("pw").decrypt(&file).unwrap(), b"hello");
+        assert_eq!(
+            cause(session("wrong").decrypt(&file).unwrap_err()),
+            Some(DecryptError::WrongKey)
+        );
+        let damaged = altered(&file, None, Some(0));
+        assert_eq!(
+            cause(session("pw").decrypt(&damaged).unwrap_err()),
+            Some(DecryptError::Corrupted)
+        );
+
+        // A stream needs its checksum up front, which then fixes its nonce.
+        let stream = |digest| {
+            let mut stream = Vec::new();
+            deterministic("pw")
+                .with_content_checksum(digest)
+                .encrypt_reader_to_writer(&b"hello"[..], &mut stream)
+                .map(|_| stream)
+        };
+        assert!(stream(None).is_err());
+        let digest = Some(Sha256::digest(b"hello").into());
+        assert_eq!(stream(digest).unwrap(), stream(digest).unwrap());
+        assert_eq!(session("pw").decrypt(&stream(digest).unwrap()).unwrap(), b"hello");
+
+        let recipients = Encryptor::to_recipients(&[identity(1).public_key()], &[], KDF);
+        assert!(recipients.unwrap().with_deterministic(true).encrypt(b"hello").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! A trail of what a run did. `-v` prints a line on standard error as each
+//! file is started and finished, with its sizes and how long it took, and one
+//! for the run; `-vv` adds the steps in between, such as the temporary file
+//! an output is written to and its rename. `--log-file FILE` appends the same
+//! lines, at least those of `-v`, with a UTC timestamp, which makes an audit
+//! trail of what `encrypt` on a directory or `watch` encrypted when:
+//!
+//! ```text
+//! 2026-10-14T15:08:00Z INFO  encrypt started
+//! 2026-10-14T15:08:00Z INFO  started: a.txt -> a.txt.enc
+//! 2026-10-14T15:08:00Z WARN  Skipped link: a symbolic link
+//! 2026-10-14T15:08:00Z INFO  finished: a.txt -> a.txt.enc, 12 B -> 121 B in 104 ms
+//! 2026-10-14T15:08:01Z ERROR failed: b.enc -> b: Incorrect password, key file or identity
+//! 2026-10-14T15:08:01Z INFO  run failed with exit status 7: 1 file done, 1 failed in 211 ms
+//! ```
+//!
+//! Warnings, notices of what a long run is doing (such as the address a
+//! server listens on) and failures it carries on past go to standard error
+//! whatever the verbosity, and to the log file too; the error a run ends with
+//! is printed once, by `main`, and logged. Standard output is left to what the
+//! command itself prints.
+
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use file_encryptor::{date, i18n::t};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+struct Logger {
+    /// The number of `-v`s.
+    verbosity: u8,
+    file: Option<Mutex<fs::File>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Starts logging at `verbosity`, and to the end of the file at `path` if
+/// there is one. Until then nothing is logged and warnings are only printed.
+pub fn init(verbosity: u8, path: Option<&Path>) -> Result<()> {
+    let file = path
+        .map(|path| {
+            let mut options = fs::OpenOptions::new();
+            options.create(true).append(true);
+            // File names can say as much as the files.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            options
+                .open(path)
+                .with_context(|| t!("log-open-failed", path = path.display().to_string()))
+        })
+        .transpose()?;
+    let _ = LOGGER.set(Logger {
+        verbosity,
+        file: file.map(Mutex::new),
+    });
+    Ok(())
+}
+
+/// Logs that the run, or one file of it, failed; the message is printed
+/// elsewhere.
+pub fn error(message: &str) {
+    log(Level::Error, message, true);
+}
+
+/// Prints a failure the run carries on past on standard error, and logs it.
+pub fn failure(message: &str) {
+    eprintln!("{message}");
+    log(Level::Error, message, true);
+}
+
+/// Prints a warning on standard error, and logs it.
+pub fn warn(message: &str) {
+    eprintln!("{message}");
+    log(Level::Warn, message, true);
+}
+
+/// Prints what the run is doing on standard error, and logs it.
+pub fn notice(message: &str) {
+    eprintln!("{message}");
+    log(Level::Info, message, true);
+}
+
+/// What was done, such as a file started or finished.
+pub fn info(message: &str) {
+    log(Level::Info, message, false);
+}
+
+/// A step on the way, for `-vv`.
+pub fn debug(message: &str) {
+    log(Level::Debug, message, false);
+}
+
+/// Records `message`, and prints it for the verbosity unless it is `printed`
+/// on standard error already.
+fn log(level: Level, message: &str, printed: bool) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    let shown = match logger.verbosity {
+        0 => None,
+        1 => Some(Level::Info),
+        _ => Some(Level::Debug),
+    };
+    if !printed && shown.is_some_and(|shown| level <= shown) {
+        eprintln!("{}", message);
+    }
+    let recorded = shown.unwrap_or(Level::Info);
+    if let Some(file) = logger.file.as_ref().filter(|_| level <= recorded) {
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        // One write per line, so lines from threads don't interleave.
+        let line = format!(
+            "{} {} {message}\n",
+            timestamp(SystemTime::now()),
+            level.name()
+        );
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+/// `now` as `YYYY-MM-DDTHH:MM:SSZ`.
+fn timestamp(now: SystemTime) -> String {
+    date::format(now)
+}
@@ -1,184 +1,4126 @@
-use aes_gcm::{
-    aead::{Aead, KeyInit},
-    Aes256Gcm, Key, Nonce,
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use clap::{ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use file_encryptor::{
+    date, decrypt_bytes, encrypt_bytes, entropy, header,
+    i18n::{self, t},
+    key_file, recipient, recovery, shamir, stream, strip_footer, Cipher, Compression, DecryptError,
+    Encryptor, Kdf, KeySource, Metadata,
 };
-use aes_gcm::aead::generic_array::typenum::{U12}; // Only U12 is needed
-use anyhow::{anyhow, Context, Result};
-use bincode::{deserialize, serialize};
-use clap::{Parser, Subcommand};
-use hmac::Hmac;   // Only Hmac trait is directly used here
-use pbkdf2::pbkdf2;
-use rand::{rngs::OsRng, RngCore};
-use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use std::{
+    collections::HashMap,
     fs,
-    io::{Read, Write},
-    path::Path,
+    io::{self, Read, Seek, SeekFrom, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
+use zeroize::Zeroizing;
 
-const KEY_LENGTH: usize = 32; // 256 bits for AES
-const SALT_LENGTH: usize = 16;
-const PBKDF2_ITERATIONS: u32 = 100_000; // Adjust as needed
-const NONCE_LENGTH: usize = 12;
-
-#[derive(Serialize, Deserialize)]
-struct EncryptionMetadata {
-    nonce: [u8; NONCE_LENGTH],
-    salt: [u8; SALT_LENGTH],
-}
+mod age;
+mod algorithms;
+mod archive;
+mod armor;
+mod attempts;
+mod bench;
+mod completions;
+mod config;
+mod container;
+mod cron;
+mod doctor;
+mod mime;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod inspect;
+mod key;
+mod keyring;
+mod log;
+mod man;
+mod manifest;
+mod metadata;
+mod mmap;
+mod notes;
+mod openpgp;
+mod policy;
+mod priority;
+mod progress;
+mod rekey;
+mod remote;
+mod report;
+mod resume;
+mod rotate;
+mod saltpack;
+mod secretstream;
+mod self_test;
+mod service;
+mod shred;
+mod signed_manifest;
+mod split;
+mod staging;
+mod strength;
+mod text;
+mod throttle;
+mod timestamp;
+mod token;
+mod vault;
+mod verify;
+mod walk;
+mod watch;
 
+const DIRECT_IO_ALIGNMENT: usize = 4096; // Covers both 512-byte and 4K-sector devices
+const DIRECT_IO_CHUNK: usize = 1 << 20;
+/// Room left over on top of the result of `--in-place`, for its header and the file system.
+const IN_PLACE_SLACK: u64 = 64 << 10;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+    /// Write a manual page for the command and each subcommand to DIR, for packagers
+    #[arg(long, value_name = "DIR")]
+    generate_man: Option<std::path::PathBuf>,
+    /// Run at low CPU and I/O priority, so long runs don't slow down the rest of the machine
+    #[arg(long, global = true)]
+    background: bool,
+    /// Read inputs no faster than RATE across the whole run (e.g. 50MiB/s, 500K)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate, global = true)]
+    bwlimit: Option<u64>,
+    /// How encrypt, decrypt, verify, rekey, rotate and watch report on standard output; json
+    /// prints a line per file and one for the run, paths just the path of each file done
+    #[arg(long, value_enum, global = true, default_value_t)]
+    output_format: report::OutputFormat,
+    /// Print only the path of each file done, each ended by a NUL byte, for `xargs -0`; short for
+    /// --output-format paths0
+    #[arg(long, global = true, conflicts_with = "output_format")]
+    print0: bool,
+    /// Print each file on standard error as it is started and finished; -vv also the steps in
+    /// between
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Append a timestamped line for each file started and finished, and each warning, to FILE
+    #[arg(long, value_name = "FILE", global = true)]
+    log_file: Option<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Encrypt a file; prompts for a password if no key source is given
+    #[command(visible_alias = "e")]
+    #[command(group(
+        ArgGroup::new("key_source")
+            .args(["sealed_box", "pgp_recipient", "recipients"])
+    ))]
     Encrypt {
-        #[arg(short, long, value_name = "FILE")]
-        input_path: String,
-        #[arg(short, long, value_name = "FILE")]
-        output_path: String,
-        #[arg(short, long, value_name = "PASSWORD")]
-        password: String,
+        #[command(flatten)]
+        paths: Paths,
+        /// Password; repeat it, or add --recipient keys, so that any one of them decrypts the file
+        #[arg(short, long = "password", value_name = "PASSWORD", conflicts_with_all = ["sealed_box", "pgp_recipient", "password_source"])]
+        passwords: Vec<String>,
+        #[command(flatten)]
+        password_source: PasswordSource,
+        /// Use the password even if it breaks the administrator's password policy; the override is logged
+        #[arg(long)]
+        force_weak_password: bool,
+        /// Use the password even if it is easy to guess, with a warning
+        #[arg(long)]
+        allow_weak_password: bool,
+        /// Use the password saved in the platform keychain as NAME; if there is none, prompt for one
+        /// and save it once the file is encrypted
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["passwords", "password_source", "sealed_box", "pgp_recipient", "recipients"])]
+        use_keyring: Option<String>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+        /// Encrypt to this X25519 public key (hex or base64, see `keygen --x25519`) instead of using a
+        /// password; repeat for more recipients, any of whom can decrypt with --identity
+        #[arg(long = "recipient", value_name = "PUBLIC_KEY")]
+        recipients: Vec<String>,
+        /// Encrypt to the P-256 key on this hardware token, a PKCS#11 URI or yubikey:SLOT, so that
+        /// decrypting takes the device (`decrypt --token`); repeat for more, or add passwords
+        #[arg(long = "token", value_name = "TOKEN", conflicts_with_all = ["use_keyring", "key_file", "split_key", "sealed_box", "pgp_recipient"])]
+        tokens: Vec<String>,
+        /// Use this key file (see `keygen`) instead of a password, or as well as one given with --password
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["sealed_box", "pgp_recipient", "recipients"])]
+        key_file: Option<String>,
+        /// Encrypt with a new random key split into COUNT shares, written to OUTPUT.share-1, ...,
+        /// any THRESHOLD of which decrypt the file with `decrypt --share` (e.g. 3/5)
+        #[arg(long, value_name = "THRESHOLD/COUNT", value_parser = parse_split_key, conflicts_with_all = ["passwords", "password_source", "use_keyring", "recipients", "key_file", "sealed_box", "pgp_recipient"])]
+        split_key: Option<(u8, u8)>,
+        /// Print the --split-key shares instead of writing share files
+        #[arg(long, requires = "split_key")]
+        print_shares: bool,
+        /// Also wrap the file's key for a new random recovery key, printed once, which decrypts the
+        /// file with `decrypt --recovery-key` if the password is lost
+        #[arg(long, conflicts_with_all = ["key_file", "split_key", "sealed_box", "pgp_recipient", "deterministic"])]
+        recovery_key: bool,
+        /// Encrypt to this X25519 public key (hex or base64) as a libsodium sealed box instead of using a password
+        #[arg(long, value_name = "PUBLIC_KEY", conflicts_with = "password_source")]
+        sealed_box: Option<String>,
+        /// Encrypt to this OpenPGP public key file (armored or binary) so the recipient can decrypt with gpg
+        #[arg(long, value_name = "KEY_FILE", conflicts_with = "password_source")]
+        pgp_recipient: Option<String>,
+        /// Obtain an RFC 3161 timestamp for the ciphertext from this TSA, saved as <OUTPUT>.tsr
+        #[arg(long, value_name = "URL")]
+        timestamp_url: Option<String>,
+        /// Base64 line length for --format mime or --armor [default: 76 for mime, the most RFC 2045
+        /// allows, and 64 for --armor]
+        #[arg(long, value_name = "CHARS", value_parser = clap::value_parser!(u16).range(4..=mime::MAX_LINE_LENGTH as i64))]
+        line_length: Option<u16>,
+        /// Write the file as ASCII armor, base64 between BEGIN and END lines, to travel through
+        /// email, tickets and YAML; decrypt recognises it by itself
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "stream", "archive", "split_size", "direct_io"])]
+        armor: bool,
+        /// Encrypt in fixed-size chunks with bounded memory, for files too large to hold in memory
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "timestamp_url", "line_length", "direct_io"])]
+        stream: bool,
+        /// Write the output as parts OUTPUT.001, OUTPUT.002, ... of at most SIZE each (e.g. 1G,
+        /// 650M); decrypt reads them back in order by itself
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, conflicts_with_all = ["sealed_box", "pgp_recipient", "timestamp_url", "direct_io", "verify_after", "resume", "manifest", "in_place"])]
+        split_size: Option<u64>,
+        /// Cipher for the native format; decryption reads it from the file [default: aes256gcm,
+        /// or chacha20poly1305 on a CPU without AES instructions]
+        #[arg(long, value_enum, conflicts_with_all = ["sealed_box", "pgp_recipient"])]
+        cipher: Option<CipherName>,
+        /// Compress the plaintext before encrypting it; decryption decompresses it by itself
+        /// [default: none]
+        #[arg(long, value_enum, conflicts_with_all = ["sealed_box", "pgp_recipient"])]
+        compress: Option<CompressionName>,
+        /// Bind the file to TEXT, such as what it is for, as associated data: it is not stored in
+        /// the file, and decrypting needs the same --aad
+        #[arg(long, value_name = "TEXT", conflicts_with_all = ["sealed_box", "pgp_recipient"])]
+        aad: Option<String>,
+        /// Pack the directory INPUT, with its permissions and modification times, into one
+        /// streamed file; `decrypt --extract` restores it
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "timestamp_url", "line_length", "direct_io"])]
+        archive: bool,
+        /// With --archive, list the tree in path order with every modification time set to
+        /// SOURCE_DATE_EPOCH (or 0) and permissions reduced to 644 or 755, so the same tree packs
+        /// the same way wherever and whenever it is archived
+        #[arg(long, requires = "archive")]
+        reproducible: bool,
+        /// With a directory INPUT, also write OUTPUT.manifest listing every file of OUTPUT with
+        /// its BLAKE3 digest, signed with the Ed25519 key in KEY_FILE (from `keygen --ed25519`),
+        /// for recipients to check with `verify-manifest`
+        #[arg(long, value_name = "KEY_FILE")]
+        sign_manifest: Option<String>,
+        /// Once the output is written and flushed to disk, overwrite INPUT with random data and
+        /// delete it (see `shred` for where that falls short)
+        #[arg(long, conflicts_with = "in_place")]
+        remove_original: bool,
+        /// Record INPUT's name, permissions and modification time, encrypted, so `decrypt` can name
+        /// the output after it and `decrypt --restore-metadata` can restore the rest
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "archive"])]
+        keep_metadata: bool,
+        /// Give the files of a directory INPUT random names in one flat output directory, recording
+        /// each one's path within INPUT, encrypted, for `decrypt` to restore (implies --keep-metadata)
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "archive"])]
+        encrypt_names: bool,
+        /// Encrypt the same plaintext with the same password or key file to the same file, so
+        /// backups deduplicate; anyone can then tell equal files apart from different ones, and the
+        /// salt no longer slows down guessing one password for many files
+        #[arg(long, conflicts_with_all = ["sealed_box", "pgp_recipient", "recipients", "tokens", "split_key", "archive", "encrypt_names"])]
+        deterministic: bool,
+        /// Encrypt an INPUT file that is already encrypted by this tool, which is refused otherwise
+        #[arg(long)]
+        allow_double_encrypt: bool,
+        #[command(flatten)]
+        kdf: KdfOptions,
+        #[command(flatten)]
+        io: IoOptions,
+        #[command(flatten)]
+        limits: Limits,
     },
+    /// Decrypt a file; prompts for the password if no key source is given
+    #[command(visible_alias = "d")]
+    #[command(group(
+        ArgGroup::new("key_source")
+            .args(["password", "identity", "token"])
+    ))]
     Decrypt {
-        #[arg(short, long, value_name = "FILE")]
+        #[command(flatten)]
+        paths: Paths,
+        #[arg(short, long, value_name = "PASSWORD", conflicts_with = "password_source")]
+        password: Option<String>,
+        #[command(flatten)]
+        password_source: PasswordSource,
+        /// Use the password saved in the platform keychain as NAME by `encrypt --use-keyring`
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "password_source", "identity"])]
+        use_keyring: Option<String>,
+        /// The key file the input was encrypted with; the password is then only asked for if the file needs both
+        #[arg(long, value_name = "FILE", conflicts_with = "identity")]
+        key_file: Option<String>,
+        /// A share file (or the share itself) from `encrypt --split-key`; repeat it until there are
+        /// enough to decrypt
+        #[arg(long = "share", value_name = "FILE", conflicts_with_all = ["key_file", "identity", "use_keyring"])]
+        shares: Vec<String>,
+        /// The recovery key `encrypt --recovery-key` printed, instead of the password
+        #[arg(long, value_name = "CODE", conflicts_with_all = ["password", "password_source", "use_keyring", "key_file", "shares", "identity", "token"])]
+        recovery_key: Option<String>,
+        /// Input format; an age file is recognised without it
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+        /// File holding our X25519 secret key (hex or base64), for files encrypted with --recipient;
+        /// if it was encrypted with `encrypt`, its passphrase is prompted for
+        #[arg(long, value_name = "KEY_FILE", conflicts_with = "password_source")]
+        identity: Option<String>,
+        /// Decrypt with the hardware token the file was encrypted to with `encrypt --token`, which
+        /// is asked for its PIN
+        #[arg(long, conflicts_with_all = ["password_source", "use_keyring", "key_file", "shares"])]
+        token: bool,
+        /// Restore the directory an `encrypt --archive` file holds as OUTPUT
+        #[arg(long, conflicts_with_all = ["direct_io", "in_place"])]
+        extract: bool,
+        /// Give the output the permissions and modification time recorded by
+        /// `encrypt --keep-metadata`
+        #[arg(long, conflicts_with = "extract")]
+        restore_metadata: bool,
+        /// Recover what is intact of a damaged --stream file: the chunks that fail to authenticate
+        /// are listed and written as zeros, and the run still fails
+        #[arg(long, conflicts_with_all = ["extract", "manifest", "in_place", "resume", "verify_after"])]
+        best_effort: bool,
+        /// The associated data the file was bound to with `encrypt --aad`
+        #[arg(long, value_name = "TEXT")]
+        aad: Option<String>,
+        #[command(flatten)]
+        io: IoOptions,
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Encrypt a short text, given or from standard input, to an armored message on standard
+    /// output, for pasting into a chat or a config file
+    EncryptText {
+        #[command(flatten)]
+        options: text::EncryptTextOptions,
+    },
+    /// Decrypt an armored message from `encrypt-text` to standard output
+    DecryptText {
+        #[command(flatten)]
+        options: text::DecryptTextOptions,
+    },
+    /// Decrypt a file without writing the plaintext, to check that it is intact and the password
+    /// is right
+    Verify {
+        #[command(flatten)]
+        options: verify::VerifyOptions,
+    },
+    /// Check the signature on a manifest from `encrypt --sign-manifest`, and that the files
+    /// beside it are exactly those it lists, unchanged
+    VerifyManifest {
+        #[command(flatten)]
+        options: signed_manifest::VerifyManifestOptions,
+    },
+    /// Change a file's password in place; a file for recipients only gets a new header
+    Rekey {
+        #[command(flatten)]
+        options: rekey::RekeyOptions,
+    },
+    /// Encrypt the files that appear or change in a directory as they settle, until stopped
+    Watch {
+        #[command(flatten)]
+        options: watch::WatchOptions,
+    },
+    /// Run `watch` unattended as a systemd user unit, launchd agent or Windows service
+    Service {
+        #[command(subcommand)]
+        command: service::ServiceCommand,
+    },
+    /// Change the password of every encrypted file in a directory tree, reporting the files the
+    /// old password doesn't open
+    Rotate {
+        #[command(flatten)]
+        options: rotate::RotateOptions,
+    },
+    /// Overwrite files with random data, then delete them. SSDs, copy-on-write and journaling
+    /// filesystems, snapshots and backups can keep old copies that overwriting doesn't reach
+    Shred {
+        #[command(flatten)]
+        options: shred::ShredOptions,
+    },
+    /// Generate a random 256-bit key file for --key-file
+    Keygen {
+        /// File to write, or - for standard output; an existing file is never overwritten
+        #[arg(value_name = "OUTPUT")]
+        output_path: String,
+        /// Generate an X25519 key pair for --recipient and --identity instead: OUTPUT gets the
+        /// secret key, and the public key is printed
+        #[arg(long)]
+        x25519: bool,
+        /// Generate an Ed25519 signing key for `encrypt --sign-manifest` instead, and print the
+        /// public key for `verify-manifest --signer`
+        #[arg(long, conflicts_with = "x25519")]
+        ed25519: bool,
+        /// With --x25519, write the secret key unencrypted instead of under a passphrase, for
+        /// identities read without anyone there to type it
+        #[arg(long, requires = "x25519")]
+        no_passphrase: bool,
+        /// Record that the key expires after DURATION, such as 90d, 12w or 1y; using it after
+        /// that warns that it is due to be rotated
+        #[arg(long, value_name = "DURATION", value_parser = parse_expiry)]
+        expires_in: Option<Duration>,
+    },
+    /// Export an X25519 identity or public key as PKCS#8 or SubjectPublicKeyInfo PEM, or import
+    /// one
+    Key {
+        #[command(subcommand)]
+        command: key::KeyCommand,
+    },
+    /// Keep private notes in an encrypted container
+    Note {
+        #[command(subcommand)]
+        command: notes::NoteCommand,
+    },
+    /// Manage small secrets in an encrypted key-value vault, or files in a vault directory
+    Vault {
+        #[command(subcommand)]
+        command: vault::VaultCommand,
+    },
+    /// Run as a service for other programs
+    #[cfg(feature = "grpc")]
+    Serve {
+        /// Serve the streaming gRPC API from proto/file_encryptor.proto
+        #[arg(long, required = true)]
+        grpc: bool,
+        #[command(flatten)]
+        options: grpc::ServeOptions,
+    },
+    /// List the algorithms in use and whether this CPU accelerates them
+    Algorithms,
+    /// Check the environment (randomness, temp space, terminal, locale) and suggest fixes
+    Doctor,
+    /// Run known-answer tests of the ciphers, key derivation functions and hashes, and round
+    /// trips of the file formats, to check this build before trusting it
+    SelfTest,
+    /// Print a completion script for SHELL to standard output
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    /// Time key derivation, each cipher and end-to-end encryption at several chunk lengths on
+    /// this machine
+    Bench {
+        #[command(flatten)]
+        options: bench::BenchOptions,
+    },
+    /// Show what an encrypted file's header records, without the password unless asked
+    Inspect {
+        #[arg(value_name = "FILE")]
         input_path: String,
+        /// Prompt for the password to show the SHA-256 of the plaintext the file records
+        #[arg(long)]
+        with_password: bool,
+    },
+    /// Check an encrypted file for damage without decrypting it
+    Check {
         #[arg(short, long, value_name = "FILE")]
-        output_path: String,
-        #[arg(short, long, value_name = "PASSWORD")]
-        password: String,
+        input_path: String,
+        /// Validate the ciphertext checksum footer; needs no password
+        #[arg(long, required = true)]
+        transport: bool,
     },
 }
 
-fn read_file_bytes(path: &Path) -> Result<Vec<u8>> {
-    let mut file = fs::File::open(path).with_context(|| format!("Could not open file {path:?}"))?;
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// This tool's own password-based format
+    #[default]
+    Native,
+    /// Armored saltpack (v2) encryption to X25519 recipients
+    Saltpack,
+    /// The native format as a base64 MIME attachment, for email
+    Mime,
+    /// The age format (age-encryption.org/v1), to X25519 recipients or one password
+    Age,
+    /// libsodium's crypto_secretstream_xchacha20poly1305, with the raw key of a --key-file
+    Secretstream,
+}
+
+/// Input and output, given positionally (`encrypt secret.pdf`) or with
+/// `-i`/`-o` for scripts. A missing output is derived from the input name.
+/// `-` is standard input or output, and an `s3://` URL an object (see
+/// [`remote`]). A directory input is processed file by
+/// file into the output directory (see [`process_dir`]), and so are the
+/// files a `--manifest` lists (see [`manifest`]).
+#[derive(Args)]
+struct Paths {
+    /// File to read, - for standard input, or an s3://BUCKET/KEY URL
+    #[arg(value_name = "INPUT", required_unless_present_any = ["input_path", "manifest"])]
+    input: Option<String>,
+    /// File to write, - for standard output, or when encrypting an s3://BUCKET/KEY URL; derived
+    /// from INPUT if omitted
+    #[arg(value_name = "OUTPUT")]
+    output: Option<String>,
+    #[arg(short, long, value_name = "FILE", conflicts_with = "input")]
+    input_path: Option<String>,
+    #[arg(short, long, value_name = "FILE", conflicts_with = "output")]
+    output_path: Option<String>,
+    /// Overwrite the output if it already exists
+    #[arg(long)]
+    force: bool,
+    /// Replace the INPUT file with the result, which is written next to it, flushed to disk and
+    /// renamed over it, so INPUT stays whole until then
+    #[arg(long, conflicts_with_all = ["output", "output_path", "manifest"])]
+    in_place: bool,
+    /// Files to process at once when INPUT is a directory, or threads to encrypt or decrypt the
+    /// chunks of one --stream file with [default: the number of CPUs]
+    #[arg(long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// With a directory INPUT, only the files matching PATTERN, such as '*.rs' or 'src/**';
+    /// repeat for more
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+    /// With a directory INPUT, skip the files matching PATTERN, such as '*.tmp', and directories
+    /// such as 'node_modules/**' whole; repeat for more
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// With a directory INPUT, what to do with symbolic links [default: preserve with --archive,
+    /// else skip]; FIFOs, sockets and devices are always skipped, with a warning
+    #[arg(long, value_enum, value_name = "POLICY")]
+    symlinks: Option<walk::Symlinks>,
+    /// Process the files this TOML file lists, each one's [[file]] table giving its input and
+    /// optionally its output, in one run with one key derivation
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "output", "input_path", "output_path"])]
+    manifest: Option<String>,
+    /// With a directory INPUT or a --manifest, write every output under a temporary name and
+    /// rename them all into place only once every file succeeded, removing them otherwise
+    #[arg(long, conflicts_with = "keep_going")]
+    all_or_nothing: bool,
+    /// With a directory INPUT or a --manifest, carry on past the files that fail, list them at
+    /// the end and exit non-zero
+    #[arg(long)]
+    keep_going: bool,
+    /// The outputs of an --all-or-nothing run.
+    #[arg(skip)]
+    staging: staging::Staging,
+}
+
+impl Paths {
+    /// Returns the input and output paths, deriving the output with
+    /// `default_output` when none was given, in `output_dir` if there is
+    /// one. An existing output file is refused without `--force`. With
+    /// `--manifest` the input is the manifest, and the output is empty.
+    fn resolve(
+        &self,
+        default_output: fn(&str) -> Result<String>,
+        output_dir: Option<&Path>,
+    ) -> Result<(&str, String)> {
+        let for_directory = !self.filter().is_empty() || self.symlinks.is_some();
+        let directory_only = || t!("filter-needs-dir");
+        if let Some(manifest) = &self.manifest {
+            if for_directory {
+                bail!(directory_only());
+            }
+            return Ok((manifest, String::new()));
+        }
+        let input = self
+            .input
+            .as_deref()
+            .or(self.input_path.as_deref())
+            .expect("clap requires an input");
+        if for_directory && !Path::new(input).is_dir() {
+            bail!(directory_only());
+        }
+        if (self.all_or_nothing || self.keep_going) && !Path::new(input).is_dir() {
+            bail!(t!("batch-needs-files"));
+        }
+        if self.in_place {
+            let local = input != STDIO && !remote::is_remote(Path::new(input));
+            if !local || !Path::new(input).is_file() {
+                bail!(t!("in-place-not-file"));
+            }
+            check_room(Path::new(input))?;
+            return Ok((input, input.to_owned()));
+        }
+        let output = match self.output.as_ref().or(self.output_path.as_ref()) {
+            Some(output) => output.clone(),
+            None if input == STDIO => STDIO.to_owned(),
+            // Named after the object, in the current directory.
+            None if remote::is_remote(Path::new(input)) => {
+                derived_output(remote::file_name(input), default_output, output_dir)?
+            }
+            None => derived_output(input, default_output, output_dir)?,
+        };
+        // Devices such as /dev/null are written in place, not replaced.
+        let existing = output != STDIO && fs::metadata(&output).is_ok_and(|m| m.is_file());
+        if existing && !self.force {
+            return Err(anyhow!(t!("output-exists", path = output)));
+        }
+        check_distinct(input, &output)?;
+        Ok((input, output))
+    }
+
+    /// The files of a directory INPUT to take.
+    fn filter(&self) -> walk::Filter {
+        walk::Filter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+
+    /// How many files, or chunks of one streamed file, to work on at once.
+    fn jobs(&self) -> usize {
+        self.jobs.or(config::get().jobs).map_or_else(
+            || thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            NonZeroUsize::get,
+        )
+    }
+}
+
+/// Refuses an `output` that is the file `input` itself, by another name or a
+/// hard link, which `--force` would replace with what is made from it (that
+/// is what `--in-place` is for), or one inside the directory `input`, which
+/// would be taken as input in turn.
+fn check_distinct(input: &str, output: &str) -> Result<()> {
+    let local = |path: &str| path != STDIO && !remote::is_remote(Path::new(path));
+    if !local(input) || !local(output) {
+        return Ok(());
+    }
+    // A missing input is reported when it is opened.
+    let Ok(real_input) = fs::canonicalize(input) else {
+        return Ok(());
+    };
+    let real_output = resolve_path(Path::new(output));
+    if real_input.is_dir() {
+        if real_output.starts_with(&real_input) {
+            bail!(t!("output-inside-input", output = output, input = input));
+        }
+        return Ok(());
+    }
+    if real_output == real_input || is_same_file(Path::new(input), Path::new(output)) {
+        bail!(t!("output-is-input", path = output));
+    }
+    Ok(())
+}
+
+/// `path` made absolute, with the links in the part of it that exists
+/// resolved, for a file that may not exist yet.
+fn resolve_path(path: &Path) -> std::path::PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            return missing.iter().rev().fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_owned());
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+}
+
+/// Whether `a` and `b` are the same file, such as two hard links to it.
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Whether the file at `path` starts like one this tool encrypted, in either
+/// layout, or wrapped as armor or MIME, or as an age or saltpack file.
+fn is_encrypted(path: &Path) -> bool {
+    let mut head = Vec::new();
+    let read = fs::File::open(path).and_then(|file| file.take(HEADER_PEEK).read_to_end(&mut head));
+    if read.is_err() {
+        return false;
+    }
+    let magics: [&[u8]; 3] = [header::MAGIC, stream::MAGIC, age::INTRO];
+    if magics.iter().any(|magic| head.starts_with(magic)) {
+        return true;
+    }
+    // The text formats may be cut off mid-character at the end of `head`.
+    let text = String::from_utf8_lossy(&head);
+    armor::is_armored(text.as_bytes()) || mime::is_mime(&text) || saltpack::is_armored(&text)
+}
+
+/// Refuses to replace `input` in place when its file system hasn't room for
+/// the result beside it, taken to be a little larger than `input`. The
+/// original is only removed by the final rename, so a result that still
+/// runs out of space, such as a plaintext that was compressed, fails
+/// leaving `input` as it was.
+fn check_room(input: &Path) -> Result<()> {
+    let size = fs::metadata(input)
+        .with_context(|| t!("file-unreadable", path = input.display().to_string()))?
+        .len();
+    let needed = size + size / 1024 + IN_PLACE_SLACK;
+    let dir = match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match doctor::free_space(dir) {
+        Some(free) if free < needed => Err(anyhow!(t!(
+            "in-place-no-room",
+            path = input.display().to_string(),
+            needed = human_size(needed),
+            free = human_size(free)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The output `default_output` names for `input`, in `output_dir` if there
+/// is one.
+fn derived_output(
+    input: &str,
+    default_output: fn(&str) -> Result<String>,
+    output_dir: Option<&Path>,
+) -> Result<String> {
+    let output = default_output(input)?;
+    Ok(match (output_dir, Path::new(&output).file_name()) {
+        (Some(dir), Some(name)) => dir.join(name).to_string_lossy().into_owned(),
+        _ => output,
+    })
+}
+
+/// Runs `process` on every file under `input_dir` that `output_name` can name,
+/// writing to the same place under `output_dir` (see [`process_files`]).
+fn process_dir(
+    input_dir: &Path,
+    output_dir: &Path,
+    output_name: fn(&str) -> Result<String>,
+    paths: &Paths,
+    limits: &Limits,
+    process: impl Fn(&str, &str) -> Result<()> + Sync,
+) -> Result<()> {
+    if is_stdio(output_dir) {
+        bail!(t!("dir-needs-output-dir"));
+    }
+    let follow = match paths.symlinks {
+        Some(walk::Symlinks::Preserve) => {
+            bail!(t!("symlinks-preserve-needs-archive"))
+        }
+        symlinks => symlinks == Some(walk::Symlinks::Follow),
+    };
+    let listing = walk::walk_filtered(input_dir, &paths.filter(), follow)
+        .with_context(|| t!("dir-list-failed", path = input_dir.display().to_string()))?;
+    for (relative, skipped) in &listing.skipped {
+        log::warn(&skipped.warning(&input_dir.join(relative)));
+    }
+    let mut tasks = Vec::new();
+    for (relative, size) in listing.files {
+        let input = input_dir.join(&relative);
+        let relative = relative
+            .to_str()
+            .ok_or_else(|| anyhow!(t!("path-not-utf8", path = input.display().to_string())))?;
+        // Decrypting skips what isn't named like a ciphertext.
+        let Ok(output) = output_name(relative) else {
+            continue;
+        };
+        let input = input.to_string_lossy().into_owned();
+        let output = output_dir.join(output).to_string_lossy().into_owned();
+        tasks.push((input, output, size));
+    }
+    process_files(tasks, paths, limits, process)
+}
+
+/// Runs `process` on the `(input, output, input size)` of each of `tasks`,
+/// `paths.jobs` files at a time. Every output is checked for before any file
+/// is started. After a failure no more files are started, and the first
+/// error is returned once those in progress are done. With
+/// `--all-or-nothing`, `process` writes each output under the name
+/// [`staging::Staging::stage`] gives it, and the outputs are renamed into
+/// place only when every file succeeded. With `--keep-going`, every file is
+/// processed however many fail, and the error lists those that did.
+fn process_files(
+    tasks: Vec<(String, String, u64)>,
+    paths: &Paths,
+    limits: &Limits,
+    process: impl Fn(&str, &str) -> Result<()> + Sync,
+) -> Result<()> {
+    report::batch();
+    let sizes: Vec<_> = tasks
+        .iter()
+        .map(|(input, _, size)| (input.clone(), *size))
+        .collect();
+    limits.check(&sizes)?;
+    if !paths.force {
+        let existing = tasks.iter().find(|(_, output, _)| Path::new(output).exists());
+        if let Some((_, output, _)) = existing {
+            return Err(anyhow!(t!("output-exists", path = output.as_str())));
+        }
+    }
+    for (input, output, _) in &tasks {
+        check_distinct(input, output)?;
+        if paths.all_or_nothing && remote::is_remote(Path::new(output)) {
+            bail!(t!("all-or-nothing-remote", path = output.as_str()));
+        }
+    }
+
+    let jobs = paths.jobs();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let errors = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(tasks.len()) {
+            scope.spawn(|| {
+                while paths.keep_going || !failed.load(Ordering::Relaxed) {
+                    let Some((input, output, _)) = tasks.get(next.fetch_add(1, Ordering::Relaxed))
+                    else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    report::start(input, output);
+                    let target = match paths.all_or_nothing {
+                        true => paths.staging.stage(Path::new(output)),
+                        false => PathBuf::from(output),
+                    };
+                    let local = Some(target.as_path()).filter(|path| !remote::is_remote(path));
+                    let result = match local.and_then(Path::parent) {
+                        Some(parent) => fs::create_dir_all(parent).with_context(|| {
+                            t!("dir-create-failed", path = parent.display().to_string())
+                        }),
+                        None => Ok(()),
+                    }
+                    .and_then(|()| process(input, &target.to_string_lossy()));
+                    report::file(input, output, started, &result);
+                    if let Err(e) = result {
+                        failed.store(true, Ordering::Relaxed);
+                        let mut errors = errors.lock().unwrap_or_else(|e| e.into_inner());
+                        errors.push((input, e.context(t!("file-failed", path = input.as_str()))));
+                    }
+                }
+            });
+        }
+    });
+    let mut errors = errors.into_inner().unwrap_or_else(|e| e.into_inner());
+    if errors.is_empty() {
+        paths.staging.commit().map_err(|output| {
+            anyhow!(t!("staged-unmoved", path = output.display().to_string()))
+        })?;
+        if paths.all_or_nothing && !report::quiet() {
+            println!("{}", t!("batch-committed", total = tasks.len()));
+        }
+        return Ok(());
+    }
+    if paths.all_or_nothing {
+        paths.staging.rollback();
+        report::rolled_back();
+        log::warn(&t!("batch-rolled-back", total = tasks.len()));
+    }
+    if !paths.keep_going || errors.len() == 1 {
+        return Err(errors.swap_remove(0).1);
+    }
+    // In the order of the tasks, whichever worker got to each first.
+    errors.sort_by_key(|(input, _)| tasks.iter().position(|(task, _, _)| task == *input));
+    let mut summary = t!("batch-failed", failed = errors.len(), total = tasks.len());
+    for (_, e) in &errors {
+        summary.push_str(&format!("\n  {e:#}"));
+    }
+    Err(anyhow!(summary))
+}
+
+fn encrypted_name(input: &str) -> Result<String> {
+    Ok(format!("{input}.enc"))
+}
+
+/// For `--encrypt-names`, which leaves the real name to the file's metadata.
+fn random_name(_input: &str) -> Result<String> {
+    Ok(format!("{}.enc", hex::encode(entropy::bytes::<16>()?)))
+}
+
+fn decrypted_name(input: &str) -> Result<String> {
+    match split::set_name(input).strip_suffix(".enc") {
+        Some(stem) if !stem.is_empty() => Ok(stem.to_owned()),
+        _ => Err(anyhow!(t!("output-underivable", path = input))),
+    }
+}
+
+/// Asks for the password on the terminal, twice when `confirm` is set.
+fn prompt_password(confirm: bool) -> Result<Zeroizing<String>> {
+    prompt(t!("password-prompt"), confirm)
+}
+
+/// Asks for the password a file is changed to, twice.
+fn prompt_new_password() -> Result<Zeroizing<String>> {
+    prompt(t!("new-password-prompt"), true)
+}
+
+/// Prompts without echo. Whatever is typed is wiped from memory once dropped.
+fn prompt(prompt: String, confirm: bool) -> Result<Zeroizing<String>> {
+    let password = Zeroizing::new(
+        rpassword::prompt_password(prompt).context(t!("password-unavailable"))?,
+    );
+    if password.is_empty() {
+        return Err(anyhow!(t!("password-empty")));
+    }
+    if confirm {
+        let confirmation =
+            Zeroizing::new(rpassword::prompt_password(t!("password-confirm-prompt"))?);
+        if confirmation != password {
+            return Err(anyhow!(t!("password-mismatch")));
+        }
+    }
+    Ok(password)
+}
+
+/// Where a password comes from instead of `--password`, so that it stays out
+/// of the process list: what an orchestrator injects into a CI job or a
+/// provisioning run.
+#[derive(Args, Clone, Default)]
+#[group(id = "password_source", multiple = false)]
+struct PasswordSource {
+    /// Read the password from the environment variable VAR
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+    /// Read the password from the first line of the open file descriptor N (Unix)
+    #[arg(long, value_name = "N")]
+    password_fd: Option<i32>,
+    /// Read the password from the first line of FILE
+    #[arg(long, value_name = "FILE")]
+    password_file: Option<String>,
+}
+
+impl PasswordSource {
+    /// The password, if one of these options is given.
+    fn read(&self) -> Result<Option<Zeroizing<String>>> {
+        let contents = Zeroizing::new(if let Some(name) = &self.password_env {
+            std::env::var(name)
+                .with_context(|| t!("password-env-failed", name = name.as_str()))?
+        } else if let Some(fd) = self.password_fd {
+            read_fd(fd).with_context(|| t!("password-fd-failed", fd = fd))?
+        } else if let Some(path) = &self.password_file {
+            fs::read_to_string(path)
+                .with_context(|| t!("password-file-failed", path = path.as_str()))?
+        } else {
+            return Ok(None);
+        });
+        let password = Zeroizing::new(contents.lines().next().unwrap_or_default().to_owned());
+        if password.is_empty() {
+            return Err(anyhow!(t!("password-empty")));
+        }
+        Ok(Some(password))
+    }
+
+    /// `given` with `--password`, or else the password these options give.
+    fn or(&self, given: &Option<String>) -> Result<Option<Zeroizing<String>>> {
+        match given {
+            Some(password) => Ok(Some(Zeroizing::new(password.clone()))),
+            None => self.read(),
+        }
+    }
+}
+
+/// Reads the file descriptor `fd`, which a parent process opened for us, to
+/// the end, and closes it.
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<String> {
+    use std::os::fd::FromRawFd;
+
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor is open, and nothing else in this process uses it.
+    let mut file = unsafe { fs::File::from_raw_fd(fd) };
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        t!("password-fd-unsupported"),
+    ))
+}
+
+fn read_key_file(path: &str) -> Result<[u8; key_file::LENGTH]> {
+    let contents = Zeroizing::new(
+        fs::read(path).with_context(|| t!("key-file-unreadable", path = path))?,
+    );
+    let key =
+        key_file::parse(&contents).with_context(|| t!("key-file-unusable", path = path))?;
+    warn_if_expired(Path::new(path), &contents);
+    Ok(key)
+}
+
+/// Warns when the key or identity file at `path`, of `contents`, is past the
+/// expiry date it records.
+fn warn_if_expired(path: &Path, contents: &[u8]) {
+    let validity = key_file::Validity::read(contents);
+    if let Some(expires) = validity.expires.filter(|_| validity.is_expired(SystemTime::now())) {
+        log::warn(&t!(
+            "key-expired",
+            path = path.display().to_string(),
+            date = date::format(expires)
+        ));
+    }
+}
+
+/// Parses `--split-key THRESHOLD/COUNT`, such as `3/5`.
+fn parse_split_key(value: &str) -> Result<(u8, u8), String> {
+    value
+        .split_once('/')
+        .and_then(|(threshold, count)| {
+            Some((threshold.trim().parse().ok()?, count.trim().parse().ok()?))
+        })
+        .ok_or_else(|| t!("split-key-invalid", value = value))
+}
+
+/// A new random key for `encrypt --split-key`, once its shares are handed
+/// out: printed with `print`, else written to OUTPUT.share-1, OUTPUT.share-2,
+/// ... before anything is encrypted with it.
+fn split_key(
+    output_path: &str,
+    (threshold, count): (u8, u8),
+    print: bool,
+    force: bool,
+) -> Result<[u8; key_file::LENGTH]> {
+    let key = Zeroizing::new(entropy::bytes()?);
+    let shares = shamir::split(&key, threshold, count)?;
+    let message = t!(
+        "split-key-written",
+        count = count,
+        threshold = threshold,
+        path = output_path
+    );
+    if print {
+        println!("{message}");
+        for share in &shares {
+            println!("{}", *share.encode());
+        }
+        return Ok(*key);
+    }
+    let share_path = |share: &shamir::Share| format!("{output_path}.share-{}", share.index);
+    if let Some(path) = shares.iter().map(share_path).find(|path| Path::new(path).exists()) {
+        if !force {
+            return Err(anyhow!(t!("output-exists", path = path)));
+        }
+    }
+    for share in &shares {
+        let mut line = share.encode();
+        line.push('\n');
+        write_file_bytes(Path::new(&share_path(share)), line.as_bytes(), IoOptions::default())?;
+    }
+    if !report::quiet() {
+        println!("{message}");
+        for share in &shares {
+            println!("  {}", share_path(share));
+        }
+    }
+    Ok(*key)
+}
+
+/// The key the `decrypt --share` shares give back; each is a share file or
+/// the share's line itself.
+fn combine_shares(shares: &[String]) -> Result<[u8; key_file::LENGTH]> {
+    let shares = shares
+        .iter()
+        .map(|share| match share.trim_start().starts_with("feshare") {
+            true => shamir::Share::parse(share),
+            false => {
+                let contents = Zeroizing::new(
+                    fs::read_to_string(share)
+                        .with_context(|| t!("share-file-unreadable", path = share.as_str()))?,
+                );
+                shamir::Share::parse(&contents)
+                    .with_context(|| t!("share-file-unusable", path = share.as_str()))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(*shamir::combine(&shares)?)
+}
+
+/// The session for a password, a key file or both; at least one is given.
+fn encryptor(
+    password: Option<&str>,
+    key_file: Option<[u8; key_file::LENGTH]>,
+    kdf: Kdf,
+) -> Encryptor<'_> {
+    match (password, key_file) {
+        (Some(password), None) => Encryptor::with_kdf(password, kdf),
+        (Some(password), Some(key_file)) => {
+            Encryptor::with_kdf(password, kdf).with_key_file(key_file)
+        }
+        (None, Some(key_file)) => Encryptor::from_key_file(key_file),
+        (None, None) => unreachable!("no password or key file"),
+    }
+}
+
+/// The session to decrypt the file starting with `head`, taking the password
+/// from `prompted` if the file needs one that wasn't given. A missing or
+/// unwanted key file is refused before anything is prompted for.
+fn unlock<'a>(
+    head: &[u8],
+    password: Option<&'a str>,
+    key_file: Option<[u8; key_file::LENGTH]>,
+    prompted: &'a mut Zeroizing<String>,
+) -> Result<Encryptor<'a>> {
+    let source = header::key_source(head)?;
+    // A file for recipients opens with a password only if one was listed.
+    let password_slot = source == KeySource::Recipients
+        && header::Header::parse(head)?
+            .slots
+            .iter()
+            .any(|slot| matches!(slot, recipient::Slot::Password { .. }));
+    if (source == KeySource::Recipients && !password_slot)
+        || source.needs_key_file() != key_file.is_some()
+    {
+        return Err(source.mismatch());
+    }
+    let password = match password {
+        None if source.needs_password() || password_slot => {
+            *prompted = prompt_password(false)?;
+            Some(prompted.as_str())
+        }
+        password => password,
+    };
+    Ok(encryptor(password, key_file, Kdf::default()))
+}
+
+/// Refuses to decrypt the file starting with `head` with `keys` unless they
+/// are what it records as its key source.
+fn check_key_source(head: &[u8], keys: &Encryptor) -> Result<()> {
+    let source = header::key_source(head)?;
+    if !keys.accepts(source) {
+        return Err(source.mismatch());
+    }
+    Ok(())
+}
+
+/// `name` in our per-user state directory (`~/.local/state/file-encryptor` on
+/// Linux), for bookkeeping that should survive between runs.
+fn state_file(name: &str) -> Option<std::path::PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_dir)
+        .map(|dir| dir.join("file-encryptor").join(name))
+}
+
+/// How the input and output files are accessed.
+#[derive(Args, Clone, Copy, Default)]
+struct IoOptions {
+    /// Flush the output file and its directory to disk before reporting success
+    #[arg(long)]
+    fsync: bool,
+    /// Bypass the OS page cache (O_DIRECT / FILE_FLAG_NO_BUFFERING), for very large jobs
+    #[arg(long)]
+    direct_io: bool,
+    /// Re-read the output from the disk and check it before reporting success
+    #[arg(long)]
+    verify_after: bool,
+    /// Read large input files with read() instead of mapping them into memory
+    #[arg(long)]
+    no_mmap: bool,
+    /// Create outputs with the default permissions (umask, inherited ACLs) instead of owner-only
+    #[arg(long)]
+    honor_umask: bool,
+    /// Don't show the progress line that long --stream runs draw on a terminal
+    #[arg(short, long)]
+    quiet: bool,
+    /// Checkpoint --stream runs as they go, and carry on from an interrupted run's checkpoint
+    #[arg(long, conflicts_with = "direct_io")]
+    resume: bool,
+    /// Set from `encrypt --split-size`.
+    #[arg(skip)]
+    split_size: Option<u64>,
+}
+
+impl IoOptions {
+    /// Refuses options that need a real file where `-` stands for standard
+    /// input or output.
+    fn check_stdio(&self, input: &str, output: &str) -> Result<()> {
+        if self.direct_io && (input == STDIO || output == STDIO) {
+            return Err(anyhow!(t!("direct-io-stdio")));
+        }
+        if self.verify_after && output == STDIO {
+            return Err(anyhow!(t!("verify-after-stdout")));
+        }
+        if self.resume && (input == STDIO || output == STDIO) {
+            return Err(anyhow!(t!("resume-stdio")));
+        }
+        let remote = [input, output].map(|path| remote::is_remote(Path::new(path)));
+        if (self.direct_io || self.resume) && remote.contains(&true) {
+            return Err(anyhow!(t!("direct-io-remote")));
+        }
+        if self.verify_after && remote[1] {
+            return Err(anyhow!(t!("verify-after-remote")));
+        }
+        Ok(())
+    }
+}
+
+/// Refusal thresholds, so an automated caller can't feed a run far more
+/// data than intended.
+#[derive(Args, Clone, Copy, Default)]
+struct Limits {
+    /// Refuse any input file larger than this (e.g. 500M, 20G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+    /// Refuse a run whose inputs add up to more than this
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_total_bytes: Option<u64>,
+    /// Refuse a run over more than this many files
+    #[arg(long, value_name = "N")]
+    max_files: Option<u64>,
+}
+
+impl Limits {
+    /// Checks a run over `files`, given as the name to report and the size.
+    fn check(&self, files: &[(String, u64)]) -> Result<()> {
+        if let Some(limit) = self.max_files.filter(|&limit| files.len() as u64 > limit) {
+            return Err(anyhow!(t!(
+                "limit-files",
+                count = files.len(),
+                limit = limit
+            )));
+        }
+        if let Some(limit) = self.max_file_size {
+            if let Some((name, size)) = files.iter().find(|(_, size)| *size > limit) {
+                return Err(anyhow!(t!(
+                    "limit-file-size",
+                    path = name.as_str(),
+                    size = human_size(*size),
+                    limit = human_size(limit)
+                )));
+            }
+        }
+        let total: u64 = files.iter().map(|(_, size)| size).sum();
+        if let Some(limit) = self.max_total_bytes.filter(|&limit| total > limit) {
+            return Err(anyhow!(t!(
+                "limit-total-bytes",
+                size = human_size(total),
+                limit = human_size(limit)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a run over the single file at `path`. A file that can't be
+    /// examined is left for reading it to report.
+    fn check_input(&self, path: &str) -> Result<()> {
+        match fs::metadata(path) {
+            Ok(metadata) => self.check(&[(path.to_owned(), metadata.len())]),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CipherName {
+    /// AES-256-GCM, fastest on CPUs with AES instructions
+    #[value(name = "aes256gcm")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, faster in software, e.g. on ARM boards without AES instructions
+    #[value(name = "chacha20poly1305")]
+    ChaCha20Poly1305,
+    /// XChaCha20-Poly1305, ChaCha20-Poly1305 with a 192-bit nonce
+    #[value(name = "xchacha20poly1305")]
+    XChaCha20Poly1305,
+}
+
+impl CipherName {
+    /// The cipher when neither `--cipher` nor the config file names one:
+    /// AES-256-GCM where the CPU runs it on AES instructions, and
+    /// ChaCha20-Poly1305, much faster in software, where it doesn't.
+    fn for_this_cpu() -> CipherName {
+        match algorithms::aes_accelerated() {
+            true => CipherName::Aes256Gcm,
+            false => CipherName::ChaCha20Poly1305,
+        }
+    }
+}
+
+impl From<CipherName> for Cipher {
+    fn from(name: CipherName) -> Self {
+        match name {
+            CipherName::Aes256Gcm => Cipher::Aes256Gcm,
+            CipherName::ChaCha20Poly1305 => Cipher::ChaCha20Poly1305,
+            CipherName::XChaCha20Poly1305 => Cipher::XChaCha20Poly1305,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum CompressionName {
+    #[default]
+    None,
+    /// gzip (DEFLATE), for text, logs, databases and other compressible data
+    Gzip,
+    /// zstd, which compresses about as well as gzip several times faster
+    Zstd,
+}
+
+impl From<CompressionName> for Compression {
+    fn from(name: CompressionName) -> Self {
+        match name {
+            CompressionName::None => Compression::None,
+            CompressionName::Gzip => Compression::Gzip,
+            CompressionName::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum KdfName {
+    /// PBKDF2-HMAC-SHA256, 100,000 iterations
+    #[default]
+    Pbkdf2,
+    /// Argon2id, memory-hard; tune it with the --argon2-* options
+    Argon2id,
+}
+
+/// How the password becomes a key when encrypting. The choice is recorded in
+/// the file, so decrypting needs none of these.
+#[derive(Args, Clone, Copy, Default)]
+struct KdfOptions {
+    /// Key derivation function [default: pbkdf2]
+    #[arg(long = "kdf", value_enum)]
+    name: Option<KdfName>,
+    /// Memory for --kdf argon2id (e.g. 64M, 1G) [default: 64M]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    argon2_memory: Option<u64>,
+    /// Passes over the memory for --kdf argon2id [default: 3]
+    #[arg(long, value_name = "N")]
+    argon2_iterations: Option<u32>,
+    /// Lanes for --kdf argon2id [default: 1]
+    #[arg(long, value_name = "N")]
+    argon2_parallelism: Option<u32>,
+    /// Tune the KDF so deriving a key takes about this long on this machine (e.g. 500ms, 2s):
+    /// PBKDF2 iterations, at least the default, or Argon2id passes over --argon2-memory
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "argon2_iterations")]
+    kdf_time: Option<Duration>,
+}
+
+impl KdfOptions {
+    fn kdf(&self) -> Result<Kdf> {
+        // The config file's KDF keys only make sense together.
+        let config = &config::get().kdf;
+        if self.is_empty() && !config.is_empty() {
+            return config.kdf();
+        }
+        let tuned = self.argon2_memory.is_some()
+            || self.argon2_iterations.is_some()
+            || self.argon2_parallelism.is_some();
+        match self.name.unwrap_or_default() {
+            KdfName::Pbkdf2 if tuned => Err(anyhow!(t!("argon2-needs-kdf"))),
+            KdfName::Pbkdf2 => Ok(Kdf::default()),
+            KdfName::Argon2id => {
+                let mut kdf = Kdf::ARGON2ID;
+                if let Kdf::Argon2id {
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                } = &mut kdf
+                {
+                    if let Some(memory) = self.argon2_memory {
+                        *memory_kib = u32::try_from(memory >> 10)
+                            .map_err(|_| anyhow!(t!("argon2-memory-too-large")))?;
+                    }
+                    *iterations = self.argon2_iterations.unwrap_or(*iterations);
+                    *parallelism = self.argon2_parallelism.unwrap_or(*parallelism);
+                }
+                Ok(kdf)
+            }
+        }
+        .and_then(|kdf| match self.kdf_time {
+            Some(target) => {
+                let kdf = kdf.calibrated(target)?;
+                log::notice(&t!("kdf-calibrated", kdf = inspect::kdf_description(kdf)));
+                Ok(kdf)
+            }
+            None => Ok(kdf),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.argon2_memory.is_none()
+            && self.argon2_iterations.is_none()
+            && self.argon2_parallelism.is_none()
+            && self.kdf_time.is_none()
+    }
+}
+
+/// Parses a duration such as `500ms`, `2s` or `1.5s`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(number) => (number, 1e-3),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.0),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * scale).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| t!("duration-invalid", value = value))
+}
+
+/// Parses how long a key lasts, such as `36h`, `90d`, `12w` or `1y`, a year
+/// being 365 days.
+fn parse_expiry(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let unit = value.chars().last().unwrap_or_default();
+    let hours = match unit.to_ascii_lowercase() {
+        'h' => 1,
+        'd' => 24,
+        'w' => 7 * 24,
+        'y' => 365 * 24,
+        _ => return Err(t!("expiry-invalid", value = value)),
+    };
+    value[..value.len() - unit.len_utf8()]
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|&number| number > 0)
+        .and_then(|number| number.checked_mul(hours * 3600))
+        .map(Duration::from_secs)
+        .ok_or_else(|| t!("expiry-invalid", value = value))
+}
+
+/// Parses a size such as `4096`, `500M` or `20GiB`; units are binary.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let unit = unit.trim().to_ascii_uppercase();
+    let shift = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(t!("size-unit-unknown", unit = unit)),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| t!("size-invalid", value = value))
+}
+
+/// A `--bwlimit` rate: a size, optionally followed by `/s`.
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let size = value.strip_suffix("/s").unwrap_or(value);
+    match parse_size(size)? {
+        0 => Err(t!("rate-zero")),
+        rate => Ok(rate),
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// The path that stands for standard input or output.
+const STDIO: &str = "-";
+
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// Opens `path`, or standard input for `-`, or a [`remote`] URL, for reading
+/// front to back.
+fn open_input(path: &Path, options: IoOptions) -> Result<Box<dyn Read>> {
+    open_indexed_input(path, options).map(|(input, _)| input)
+}
+
+/// [`open_input`], with the index to authenticate when `path` is split into
+/// parts.
+fn open_indexed_input(
+    path: &Path,
+    options: IoOptions,
+) -> Result<(Box<dyn Read>, Option<split::Index>)> {
+    let mut index = None;
+    let input: Box<dyn Read> = if is_stdio(path) {
+        Box::new(io::stdin().lock())
+    } else if remote::is_remote(path) {
+        remote::open(path)?
+    } else if let Some(parts) = split::Reader::open(path)? {
+        index = parts.index();
+        Box::new(parts)
+    } else if let Some(map) = map_input(path, options) {
+        Box::new(io::Cursor::new(map))
+    } else {
+        let file = fs::File::open(path)
+            .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+        Box::new(io::BufReader::new(file))
+    };
+    if throttle::is_set() {
+        return Ok((Box::new(throttle::Reader(input)), index));
+    }
+    Ok((input, index))
+}
+
+/// Prints `message` about a finished `output_path`, except when the output
+/// went to standard output: a pipeline stays silent, like other filters. A
+/// staged output is reported with the others once they are in place.
+fn report_done(output_path: &Path, message: String) {
+    if !is_stdio(output_path) && !staging::is_staged(output_path) && !report::quiet() {
+        println!("{message}");
+    }
+}
+
+/// Sums up a `--manifest` run, whose files were each reported as they were
+/// done.
+fn report_manifest(manifest: &str, count: usize) {
+    if !report::quiet() {
+        println!("{}", t!("manifest-done", count = count, path = manifest));
+    }
+}
+
+/// Maps the local file at `path` into memory, if it is large enough and
+/// neither `--no-mmap` nor `--direct-io` is given. Under `--bwlimit` files
+/// are read, as a mapping's page faults can't be paced.
+fn map_input(path: &Path, options: IoOptions) -> Option<mmap::Map> {
+    if options.no_mmap || options.direct_io || is_stdio(path) || remote::is_remote(path) {
+        return None;
+    }
+    if throttle::is_set() {
+        return None;
+    }
+    mmap::open(path)
+}
+
+/// A whole input file, read into memory or mapped.
+enum Input {
+    Read(Zeroizing<Vec<u8>>),
+    Mapped(mmap::Map),
+}
+
+impl std::ops::Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Read(bytes) => bytes,
+            Input::Mapped(map) => map,
+        }
+    }
+}
+
+/// Like [`read_file_bytes`], but maps a large local file instead of copying
+/// it.
+fn read_input(path: &Path, options: IoOptions) -> Result<Input> {
+    match map_input(path, options) {
+        Some(map) => Ok(Input::Mapped(map)),
+        None => Ok(Input::Read(Zeroizing::new(read_file_bytes(path, options)?))),
+    }
+}
+
+fn read_file_bytes(path: &Path, options: IoOptions) -> Result<Vec<u8>> {
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)
-        .with_context(|| format!("Error reading file at path: {path:?}"))?;
+    if is_stdio(path) {
+        throttle::Reader(io::stdin().lock()).read_to_end(&mut buffer).map(drop)
+    } else if remote::is_remote(path) {
+        throttle::Reader(remote::open(path)?).read_to_end(&mut buffer).map(drop)
+    } else if options.direct_io {
+        let mut file = open_direct(fs::OpenOptions::new().read(true), path)
+            .with_context(|| t!("file-open-direct-failed", path = path.display().to_string()))?;
+        read_direct(&mut file, &mut buffer)
+    } else {
+        let mut file = fs::File::open(path)
+            .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+        throttle::Reader(&mut file).read_to_end(&mut buffer).map(drop)
+    }
+    .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
     Ok(buffer)
 }
 
-fn write_file_bytes(path: &Path, data: &[u8]) -> Result<()> {
-    let mut file = fs::File::create(path).with_context(|| format!("Error creating file {path:?}"))?;
-    file.write_all(data)
-        .with_context(|| format!("Error writing to file at path: {path:?}"))?;
+/// Writes `data` to `path`, or to standard output for `-`, or to a
+/// [`remote`] URL. `--split-size` parts are written by [`split::write`],
+/// which needs the key.
+fn write_file_bytes(path: &Path, data: &[u8], options: IoOptions) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = io::stdout().lock();
+        return stdout
+            .write_all(data)
+            .and_then(|()| stdout.flush())
+            .with_context(|| t!("stdout-write-failed"));
+    }
+    if remote::is_remote(path) {
+        let mut sink = remote::create(path)?;
+        sink.write_all(data)?;
+        return sink.finish();
+    }
+    let (mut file, output) = create_file(path, options)?;
+    preallocate(&file, data.len() as u64).with_context(|| {
+        t!(
+            "file-reserve-failed",
+            size = data.len(),
+            path = path.display().to_string()
+        )
+    })?;
+    if options.direct_io {
+        write_direct(&mut file, data)
+    } else {
+        file.write_all(data)
+    }
+    .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+    sync_file(path, &file, options)?;
+    if options.verify_after {
+        // Dirty pages can't be dropped, so flush before evicting them.
+        file.sync_data()
+            .with_context(|| t!("file-sync-failed", path = path.display().to_string()))?;
+        drop_cached_pages(&file);
+        drop(file);
+        if read_file_bytes(output.written_path(), options)? != data {
+            return Err(anyhow!(t!(
+                "verify-mismatch",
+                path = path.display().to_string()
+            )));
+        }
+    }
+    output.commit(options)
+}
+
+/// Creates the output for `path`: a new temporary file next to it, which
+/// [`PendingOutput::commit`] renames over `path` once it is complete. Until
+/// then any existing file at `path` is untouched. Anything other than a
+/// regular file, such as a device, is written in place instead.
+///
+/// A file this creates is readable only by its owner unless `--honor-umask`
+/// is given; one replacing an existing file takes over its permissions.
+fn create_file(path: &Path, options: IoOptions) -> Result<(fs::File, PendingOutput)> {
+    create_output(path, options, false)
+}
+
+/// Like [`create_file`], but for `--resume`: the temporary file is the
+/// `path`'s [`resume::partial_path`], opened as an interrupted run left it,
+/// and kept when the output is dropped uncommitted.
+fn create_partial(path: &Path, options: IoOptions) -> Result<(fs::File, PendingOutput)> {
+    create_output(path, options, true)
+}
+
+fn create_output(
+    path: &Path,
+    options: IoOptions,
+    partial: bool,
+) -> Result<(fs::File, PendingOutput)> {
+    let existing = fs::metadata(path).ok();
+    if existing.as_ref().is_some_and(|metadata| !metadata.is_file()) {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .with_context(|| t!("file-create-failed", path = path.display().to_string()))?;
+        let output = PendingOutput {
+            path: path.to_owned(),
+            temp: None,
+            keep: false,
+        };
+        return Ok((file, output));
+    }
+    // Replace what a symlink points to, not the link.
+    let path = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(path)
+            .with_context(|| t!("symlink-unresolved", path = path.display().to_string()))?,
+        _ => path.to_owned(),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = match partial {
+        true => resume::partial_path(&path),
+        false => path.with_file_name(format!(
+            ".{name}.{}.tmp",
+            hex::encode(entropy::bytes::<4>()?)
+        )),
+    };
+
+    let mut open_options = fs::OpenOptions::new();
+    match partial {
+        true => open_options.read(true).write(true).create(true),
+        false => open_options.write(true).create_new(true),
+    };
+    #[cfg(unix)]
+    if !options.honor_umask {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(0o600);
+    }
+    let file = if options.direct_io {
+        open_direct(&mut open_options, &temp)
+            .with_context(|| t!("file-create-direct-failed", path = temp.display().to_string()))?
+    } else {
+        open_options
+            .open(&temp)
+            .with_context(|| t!("file-create-failed", path = temp.display().to_string()))?
+    };
+    log::debug(&format!(
+        "writing {} through {}",
+        path.display(),
+        temp.display()
+    ));
+    let output = PendingOutput {
+        path,
+        temp: Some(temp),
+        keep: partial,
+    };
+    let temp = output.written_path();
+    match existing {
+        Some(metadata) => fs::set_permissions(temp, metadata.permissions()).with_context(|| {
+            t!("permissions-copy-failed", path = output.path.display().to_string())
+        })?,
+        #[cfg(windows)]
+        None if !options.honor_umask => restrict_to_owner(temp)
+            .with_context(|| t!("permissions-private-failed", path = temp.display().to_string()))?,
+        None => {}
+    }
+    Ok((file, output))
+}
+
+/// An output file that is still being written; see [`create_file`]. Dropping
+/// it uncommitted deletes the temporary file, so a failed run leaves nothing
+/// half-written behind.
+struct PendingOutput {
+    path: std::path::PathBuf,
+    /// `None` when writing to `path` in place.
+    temp: Option<std::path::PathBuf>,
+    /// Whether the temporary file outlives a failure, for `--resume`.
+    keep: bool,
+}
+
+impl PendingOutput {
+    /// Where the data is going until the commit, for reading it back.
+    fn written_path(&self) -> &Path {
+        self.temp.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Moves the finished file into place, and with `--fsync` makes the
+    /// rename durable.
+    fn commit(mut self, options: IoOptions) -> Result<()> {
+        if let Some(temp) = self.temp.take() {
+            if let Err(e) = fs::rename(&temp, &self.path) {
+                let _ = fs::remove_file(&temp);
+                return Err(e).with_context(|| {
+                    t!("file-create-failed", path = self.path.display().to_string())
+                });
+            }
+            log::debug(&format!(
+                "renamed {} to {}",
+                temp.display(),
+                self.path.display()
+            ));
+        }
+        if options.fsync {
+            sync_parent_dir(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PendingOutput {
+    fn drop(&mut self) {
+        if let Some(temp) = self.temp.as_ref().filter(|_| !self.keep) {
+            let _ = fs::remove_file(temp);
+            log::debug(&format!("removed the unfinished {}", temp.display()));
+        }
+    }
+}
+
+/// With `--fsync`, flushes the finished `file` to disk. Its directory entry
+/// follows in [`PendingOutput::commit`].
+fn sync_file(path: &Path, file: &fs::File, options: IoOptions) -> Result<()> {
+    if options.fsync {
+        file.sync_all()
+            .with_context(|| t!("file-sync-failed", path = path.display().to_string()))?;
+        log::debug(&format!("flushed {} to disk", path.display()));
+    }
+    Ok(())
+}
+
+/// Asks the OS to forget its cached copy of `file`, so reading it back comes
+/// from the device. Elsewhere `--direct-io` bypasses the cache instead.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn drop_cached_pages(file: &fs::File) {
+    use std::os::unix::io::AsRawFd;
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn drop_cached_pages(_file: &fs::File) {}
+
+/// Replaces the ACL inherited from the parent directory with one that gives
+/// only the file's owner access, the equivalent of mode 0600.
+#[cfg(windows)]
+fn restrict_to_owner(path: &Path) -> io::Result<()> {
+    use std::{ffi::c_void, os::windows::ffi::OsStrExt, ptr};
+
+    const SDDL_REVISION_1: u32 = 1;
+    const DACL_SECURITY_INFORMATION: u32 = 0x4;
+    // Protected (not inherited), full access for OWNER RIGHTS only.
+    const OWNER_ONLY: &str = "D:P(A;;FA;;;OW)";
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl: *const u16,
+            revision: u32,
+            descriptor: *mut *mut c_void,
+            size: *mut u32,
+        ) -> i32;
+        fn SetFileSecurityW(file: *const u16, information: u32, descriptor: *mut c_void) -> i32;
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LocalFree(memory: *mut c_void) -> *mut c_void;
+    }
+
+    let sddl: Vec<u16> = OWNER_ONLY.encode_utf16().chain(Some(0)).collect();
+    let file: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut descriptor = ptr::null_mut();
+    if unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl.as_ptr(),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            ptr::null_mut(),
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let result =
+        unsafe { SetFileSecurityW(file.as_ptr(), DACL_SECURITY_INFORMATION, descriptor) };
+    let error = io::Error::last_os_error();
+    unsafe { LocalFree(descriptor) };
+    if result == 0 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Makes the directory entry for `path` durable, so the file itself survives a
+/// crash and not just its contents.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)
+        .and_then(|dir_file| dir_file.sync_all())
+        .with_context(|| t!("dir-sync-failed", path = dir.display().to_string()))
+}
+
+/// Windows commits directory entries together with the file's metadata, and
+/// directories can't be opened as regular files there.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn derive_key(password: &str, salt: &[u8]) -> Key<Aes256Gcm> {
-    let mut key_bytes = [0u8; KEY_LENGTH];
-    pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key_bytes);
-    Key::<Aes256Gcm>::from_slice(&key_bytes).to_owned() // Explicit Key type
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn open_direct(options: &mut fs::OpenOptions, path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.custom_flags(libc::O_DIRECT).open(path)
 }
 
+#[cfg(target_os = "macos")]
+fn open_direct(options: &mut fs::OpenOptions, path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::io::AsRawFd;
+    let file = options.open(path)?;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
 
-fn generate_salt() -> [u8; SALT_LENGTH] {
-    let mut salt = [0u8; SALT_LENGTH];
-    OsRng.fill_bytes(&mut salt);
-    salt
+#[cfg(windows)]
+fn open_direct(options: &mut fs::OpenOptions, path: &Path) -> io::Result<fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+    options.custom_flags(FILE_FLAG_NO_BUFFERING).open(path)
 }
 
-fn generate_nonce() -> [u8; NONCE_LENGTH] {
-    let mut nonce_bytes = [0u8; NONCE_LENGTH];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    nonce_bytes
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    windows
+)))]
+fn open_direct(_options: &mut fs::OpenOptions, _path: &Path) -> io::Result<fs::File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        t!("direct-io-unsupported"),
+    ))
 }
 
-fn encrypt(key: &Key<Aes256Gcm>, nonce: &[u8; NONCE_LENGTH], data: &[u8]) -> Result<Vec<u8>> {
-    let cipher = Aes256Gcm::new(key);
-    let nonce_obj = Nonce::<U12>::from_slice(nonce);
-    cipher.encrypt(&nonce_obj, data).map_err(|e| anyhow!(e))  // Use anyhow!
+/// Returns a `len`-byte slice of `storage` whose address satisfies the
+/// alignment that unbuffered I/O requires.
+fn aligned_buffer(storage: &mut Vec<u8>, len: usize) -> &mut [u8] {
+    storage.resize(len + DIRECT_IO_ALIGNMENT, 0);
+    let offset = storage.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+    &mut storage[offset..offset + len]
 }
 
-fn decrypt(key: &Key<Aes256Gcm>, nonce: &[u8; NONCE_LENGTH], ciphertext: &[u8]) -> Result<Vec<u8>> {
-    let cipher = Aes256Gcm::new(key);
-    let nonce_obj = Nonce::<U12>::from_slice(nonce);
-    cipher.decrypt(&nonce_obj, ciphertext).map_err(|e| anyhow!(e))
+fn read_direct(file: &mut fs::File, out: &mut Vec<u8>) -> io::Result<()> {
+    let mut storage = Vec::new();
+    let buf = aligned_buffer(&mut storage, DIRECT_IO_CHUNK);
+    loop {
+        let n = match file.read(buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.extend_from_slice(&buf[..n]);
+        throttle::charge(n);
+        // A read that isn't a whole number of blocks can only happen at the end
+        // of the file, and the next offset would be misaligned anyway.
+        if n % DIRECT_IO_ALIGNMENT != 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn write_direct(file: &mut fs::File, data: &[u8]) -> io::Result<()> {
+    let mut storage = Vec::new();
+    let buf = aligned_buffer(&mut storage, DIRECT_IO_CHUNK);
+    for chunk in data.chunks(DIRECT_IO_CHUNK) {
+        let padded_len = chunk.len().next_multiple_of(DIRECT_IO_ALIGNMENT);
+        buf[..chunk.len()].copy_from_slice(chunk);
+        buf[chunk.len()..padded_len].fill(0);
+        file.write_all(&buf[..padded_len])?;
+    }
+    // Drop the zero padding the final block was written with.
+    file.set_len(data.len() as u64)
+}
+
+/// Reserves `len` bytes for `file` up front, so a full disk is reported before
+/// anything is written and the output isn't fragmented by incremental growth.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn preallocate(file: &fs::File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok(());
+    }
+    let len =
+        libc::off_t::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // posix_fallocate returns the error number instead of setting errno.
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+        0 => Ok(()),
+        libc::EOPNOTSUPP | libc::ENOSYS => Ok(()), // Filesystem can't preallocate; just write.
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+/// Reserves `len` bytes for `file` with `F_PREALLOCATE`, contiguous if the
+/// volume has room for that, and then sets the length as `posix_fallocate`
+/// does; the reservation alone doesn't change it.
+#[cfg(target_os = "macos")]
+fn preallocate(file: &fs::File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok(());
+    }
+    let length =
+        libc::off_t::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATECONTIG | libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: length,
+        fst_bytesalloc: 0,
+    };
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) } == -1 {
+        store.fst_flags = libc::F_ALLOCATEALL;
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) } == -1 {
+            let e = io::Error::last_os_error();
+            return match e.raw_os_error() {
+                Some(libc::ENOTSUP) => Ok(()), // Filesystem can't preallocate; just write.
+                _ => Err(e),
+            };
+        }
+    }
+    file.set_len(len)
 }
 
+/// Reserves `len` bytes for `file` by setting its allocation size, which
+/// leaves the length to grow as the file is written.
+#[cfg(windows)]
+fn preallocate(file: &fs::File, len: u64) -> io::Result<()> {
+    use std::{ffi::c_void, os::windows::io::AsRawHandle};
+
+    const FILE_ALLOCATION_INFO: i32 = 5;
+    #[repr(C)]
+    struct FileAllocationInfo {
+        allocation_size: i64,
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetFileInformationByHandle(
+            file: *mut c_void,
+            class: i32,
+            information: *const c_void,
+            size: u32,
+        ) -> i32;
+    }
+
+    if len == 0 {
+        return Ok(());
+    }
+    let info = FileAllocationInfo {
+        allocation_size: i64::try_from(len)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
+    };
+    let reserved = unsafe {
+        SetFileInformationByHandle(
+            file.as_raw_handle(),
+            FILE_ALLOCATION_INFO,
+            (&info as *const FileAllocationInfo).cast(),
+            std::mem::size_of::<FileAllocationInfo>() as u32,
+        )
+    };
+    match reserved {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Reserves nothing: these platforms have no call that allocates space
+/// without writing it, so a full disk shows up as a failed write instead.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    windows
+)))]
+fn preallocate(_file: &fs::File, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// How an encrypted file is written out as text, for transports that mangle
+/// binary.
+#[derive(Clone, Copy)]
+enum Wrapping {
+    /// A base64 MIME part with lines of this length.
+    Mime(usize),
+    /// ASCII armor with lines of this length.
+    Armor(usize),
+}
+
+/// Encrypts with the password `keys` holds; with `wrapping` the output is
+/// written as text, and with `stream` it is written in chunks (see
+/// [`stream_encrypt_file`]).
+fn encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    mut keys: Encryptor,
+    wrapping: Option<Wrapping>,
+    stream: bool,
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let input_path = Path::new(input_path);
+    if stream {
+        return stream_encrypt_file(input_path, Path::new(output_path), keys, io);
+    }
 
-fn encrypt_file(input_path: &str, output_path: &str, password: &str) -> Result<()> {
     // 1. Read input file
+    let plain_text_bytes = read_input(input_path, io)?;
+
+    // 2. Encrypt it
+    let mut full_encrypted_data = keys.encrypt(&plain_text_bytes)?;
+    if io.verify_after {
+        // Authenticate what will be written; the write itself then checks
+        // that the disk holds exactly these bytes.
+        keys.decrypt(&full_encrypted_data)
+            .with_context(|| t!("verify-failed", path = output_path))?;
+    }
+
+    // 3. Write to output file
+    let output_path = Path::new(output_path);
+    match wrapping {
+        Some(Wrapping::Mime(line_length)) => {
+            let file_name = output_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            full_encrypted_data =
+                mime::wrap(&full_encrypted_data, &file_name, line_length).into_bytes();
+        }
+        Some(Wrapping::Armor(line_length)) => {
+            full_encrypted_data = armor::armor(&full_encrypted_data, line_length).into_bytes();
+        }
+        None => {}
+    }
+    match io.split_size {
+        Some(size) => split::write(output_path, size, &full_encrypted_data, &mut keys, io)?,
+        None => write_file_bytes(output_path, &full_encrypted_data, io)?,
+    }
+    report_done(
+        output_path,
+        t!("encrypt-done", path = output_path.display().to_string()),
+    );
+
+    // 4. Optionally timestamp the ciphertext
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, &full_encrypted_data, tsa_url, io)?;
+    }
+    Ok(())
+}
+
+/// Encrypts one chunk at a time straight from the input file to the output,
+/// so memory use doesn't grow with the file. With `--resume` it carries on
+/// after the chunks an interrupted run checkpointed.
+fn stream_encrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    keys: Encryptor,
+    io: IoOptions,
+) -> Result<()> {
+    // Standard input has no length to reserve space for.
+    let length = match is_stdio(input_path) || remote::is_remote(input_path) {
+        true => None,
+        false => Some(
+            fs::metadata(input_path)
+                .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?
+                .len(),
+        ),
+    };
+    if !io.resume {
+        let keys = match length {
+            Some(_) => keys.with_content_checksum(Some(content_checksum(input_path, io)?)),
+            None => keys,
+        };
+        let input = open_input(input_path, io)?;
+        return stream_encrypt(input, length, input_path, output_path, keys, None, io);
+    }
+    // Compressed chunks don't line up with the input.
+    if keys.compression() != Compression::None {
+        return Err(anyhow!(t!("resume-unsupported")));
+    }
+    let (mut file, output) = create_partial(output_path, io)?;
+    let (progress, chunks) = resume::Progress::start(input_path, output_path)?;
+    let resumed = match chunks {
+        0 => None,
+        chunks => Some(
+            stream::Resumed::read(io::BufReader::new(&file), chunks).with_context(|| {
+                t!("resume-failed", path = progress.path().display().to_string())
+            })?,
+        ),
+    };
+    let (input_offset, output_offset) = resumed.as_ref().map_or((0, 0), |resumed| {
+        (resumed.plaintext_offset(), resumed.output_offset())
+    });
+    // A resumed file's header already holds it.
+    let keys = match resumed {
+        Some(_) => keys,
+        None => keys.with_content_checksum(Some(content_checksum(input_path, io)?)),
+    };
+    let mut input = fs::File::open(input_path)
+        .with_context(|| t!("file-open-failed", path = input_path.display().to_string()))?;
+    input
+        .seek(SeekFrom::Start(input_offset))
+        .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?;
+    file.set_len(output_offset)
+        .and_then(|()| file.seek(SeekFrom::Start(output_offset)))
+        .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    let resume = Resume {
+        file,
+        output,
+        progress,
+        resumed,
+    };
+    let input = io::BufReader::new(throttle::Reader(input));
+    stream_encrypt(input, length, input_path, output_path, keys, Some(resume), io)
+}
+
+/// The SHA-256 of the file at `input_path`, read through once ahead of
+/// encrypting it in chunks, as the header that records it comes first.
+fn content_checksum(input_path: &Path, io: IoOptions) -> Result<[u8; 32]> {
+    let mut input = open_input(input_path, io)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; stream::CHUNK_LENGTH as usize];
+    loop {
+        let n = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    t!("file-read-failed", path = input_path.display().to_string())
+                })
+            }
+        };
+        hasher.update(&buffer[..n]);
+    }
+    log::debug(&format!("hashed {}", input_path.display()));
+    Ok(hasher.finalize().into())
+}
+
+/// A `--resume` output: the partial file, cut back to the chunks an
+/// interrupted run left, if any, and its checkpoint.
+struct Resume {
+    file: fs::File,
+    output: PendingOutput,
+    progress: resume::Progress,
+    resumed: Option<stream::Resumed>,
+}
+
+/// Packs the directory `input_path`, or what of it the `paths` patterns take,
+/// with [`archive`] and encrypts it like a streamed file, `reproducible`ly
+/// if asked.
+fn archive_encrypt_dir(
+    input_path: &Path,
+    output_path: &Path,
+    paths: &Paths,
+    reproducible: bool,
+    keys: Encryptor,
+    io: IoOptions,
+) -> Result<()> {
+    let symlinks = paths.symlinks.unwrap_or(walk::Symlinks::Preserve);
+    let input = archive::Reader::new(input_path, &paths.filter(), symlinks, reproducible)?;
+    let length = input.length();
+    stream_encrypt(input, Some(length), input_path, output_path, keys, None, io)
+}
+
+/// Encrypts `input`, which holds `length` bytes if known, in chunks to
+/// `output_path`, or with `resume` resumes doing so from where `input` has
+/// been moved on to.
+fn stream_encrypt(
+    input: impl Read,
+    length: Option<u64>,
+    input_path: &Path,
+    output_path: &Path,
+    keys: Encryptor,
+    resume: Option<Resume>,
+    io: IoOptions,
+) -> Result<()> {
+    let resumed_at = resume
+        .as_ref()
+        .and_then(|resume| resume.resumed.as_ref())
+        .map_or(0, |resumed| resumed.plaintext_offset());
+    let remaining = length.map(|length| length.saturating_sub(resumed_at));
+    let input = progress::Reader::new(input, remaining, io.quiet);
+    // A resumed file keeps the chunk length its header records.
+    let mut keys = keys.with_chunk_length(stream::chunk_length_for(length));
+    if is_stdio(output_path) {
+        stream::encrypt(&mut keys, input, io::BufWriter::new(io::stdout().lock()))
+            .with_context(|| {
+                t!("encrypt-to-stdout-failed", path = input_path.display().to_string())
+            })?;
+        report_done(
+            output_path,
+            t!("encrypt-done", path = output_path.display().to_string()),
+        );
+        return Ok(());
+    }
+    if remote::is_remote(output_path) {
+        let mut sink = remote::create(output_path)?;
+        stream::encrypt(&mut keys, input, &mut sink).with_context(|| {
+            t!(
+                "encrypt-to-failed",
+                path = input_path.display().to_string(),
+                output = output_path.display().to_string()
+            )
+        })?;
+        sink.finish()?;
+        report_done(
+            output_path,
+            t!("encrypt-done", path = output_path.display().to_string()),
+        );
+        return Ok(());
+    }
+    if let Some(size) = io.split_size {
+        let mut parts = split::Writer::new(output_path, size, io)?;
+        stream::encrypt(&mut keys, input, &mut parts)
+            .with_context(|| {
+                t!(
+                    "encrypt-to-failed",
+                    path = input_path.display().to_string(),
+                    output = output_path.display().to_string()
+                )
+            })?;
+        parts.finish(&mut keys)?;
+        report_done(
+            output_path,
+            t!("encrypt-done", path = output_path.display().to_string()),
+        );
+        return Ok(());
+    }
+    let (file, output, progress, resumed) = match resume {
+        Some(resume) => (resume.file, resume.output, Some(resume.progress), resume.resumed),
+        None => {
+            let (file, output) = create_file(output_path, io)?;
+            (file, output, None, None)
+        }
+    };
+    let expected_length = length.and_then(|length| stream::encrypted_length(&keys, length));
+    if let Some(expected_length) = expected_length {
+        preallocate(&file, expected_length).with_context(|| {
+            t!(
+                "file-reserve-failed",
+                size = expected_length,
+                path = output_path.display().to_string()
+            )
+        })?;
+    }
+
+    let mut writer = io::BufWriter::new(file);
+    let written = match &progress {
+        Some(progress) => {
+            let checkpoint = |writer: &mut &mut io::BufWriter<fs::File>, chunks| {
+                progress.reached(writer, chunks)
+            };
+            stream::encrypt_from(&mut keys, resumed, input, &mut writer, checkpoint)
+        }
+        None => stream::encrypt(&mut keys, input, &mut writer),
+    }
+    .with_context(|| {
+        t!(
+            "encrypt-to-failed",
+            path = input_path.display().to_string(),
+            output = output_path.display().to_string()
+        )
+    })?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    // The input may have changed size since it was measured.
+    file.set_len(written)
+        .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    sync_file(output_path, &file, io)?;
+    if io.verify_after {
+        file.sync_data()
+            .with_context(|| t!("file-sync-failed", path = output_path.display().to_string()))?;
+        drop_cached_pages(&file);
+        drop(file);
+        let written = fs::File::open(output.written_path())
+            .with_context(|| t!("file-open-failed", path = output_path.display().to_string()))?;
+        stream::decrypt(&mut keys, io::BufReader::new(written), io::sink())
+            .with_context(|| t!("verify-failed", path = output_path.display().to_string()))?;
+    }
+    output.commit(io)?;
+    if let Some(progress) = progress {
+        progress.finish()?;
+    }
+    report_done(
+        output_path,
+        t!("encrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// Encrypts to an X25519 public key as a libsodium sealed box (crypto_box_seal),
+/// with no header or footer, so sodium-based services can open the output with
+/// crypto_box_seal_open.
+fn seal_file(
+    input_path: &str,
+    output_path: &str,
+    recipient: &str,
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let recipient = parse_public_key(recipient)?;
+    let plain_text_bytes = read_file_bytes(Path::new(input_path), io)?;
+
+    entropy::check()?;
+    let sealed = recipient
+        .seal(&mut entropy::Rng, &plain_text_bytes)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| t!("encrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &sealed, io)?;
+    report_done(
+        output_path,
+        t!("sealed-box-done", path = output_path.display().to_string()),
+    );
+
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, &sealed, tsa_url, io)?;
+    }
+    Ok(())
+}
+
+/// Encrypts to an OpenPGP certificate, producing a standard binary message
+/// that `gpg --decrypt` opens; none of our own header or footer is added.
+fn pgp_encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    key_path: &str,
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let cert_bytes = read_file_bytes(Path::new(key_path), IoOptions::default())?;
+    let cert = openpgp::read_certificate(&cert_bytes)?;
     let input_path = Path::new(input_path);
-    let plain_text_bytes = read_file_bytes(input_path)?;
+    let plain_text_bytes = read_file_bytes(input_path, io)?;
+    let file_name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
 
-    // 2. Generate salt and key
-    let salt = generate_salt();
-    let key = derive_key(password, &salt);
+    let message = openpgp::encrypt(&cert, &file_name, plain_text_bytes)?;
 
-    // 3. Generate nonce
-    let nonce = generate_nonce();
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &message, io)?;
+    report_done(
+        output_path,
+        t!("openpgp-done", path = output_path.display().to_string()),
+    );
 
-    // 4. Create and serialize metadata
-    let metadata = EncryptionMetadata { nonce, salt };
-    let metadata_bytes = serialize(&metadata)?;
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, &message, tsa_url, io)?;
+    }
+    Ok(())
+}
 
-    // 5. Encrypt data
-    let encrypted_data = encrypt(&key, &nonce, &plain_text_bytes)
-        .with_context(|| "Error during encryption")?;
+/// Encrypts to one or more X25519 recipients as an armored saltpack message.
+fn saltpack_encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    recipients: &[String],
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let recipients = recipients
+        .iter()
+        .map(|key| parse_public_key(key))
+        .collect::<Result<Vec<_>>>()?;
+    let plain_text_bytes = read_file_bytes(Path::new(input_path), io)?;
 
-    // 6. Combine metadata and encrypted data
-    let mut full_encrypted_data = metadata_bytes;
-    full_encrypted_data.extend_from_slice(&encrypted_data);
+    let armored = saltpack::encrypt(&recipients, &plain_text_bytes)
+        .with_context(|| t!("encrypt-failed"))?;
 
-    // 7. Write to output file
     let output_path = Path::new(output_path);
-    write_file_bytes(output_path, &full_encrypted_data)?;
+    write_file_bytes(output_path, armored.as_bytes(), io)?;
+    report_done(
+        output_path,
+        t!("saltpack-done", path = output_path.display().to_string()),
+    );
 
-    println!("Encryption complete: {}", output_path.display());
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, armored.as_bytes(), tsa_url, io)?;
+    }
     Ok(())
 }
 
-fn decrypt_file(input_path: &str, output_path: &str, password: &str) -> Result<()> {
+fn saltpack_decrypt_file(
+    input_path: &str,
+    output_path: &str,
+    identity_path: &str,
+    io: IoOptions,
+) -> Result<()> {
+    let identity = read_identity(Path::new(identity_path))?;
     let input_path = Path::new(input_path);
-    let encrypted_data = read_file_bytes(input_path)?;
+    let armored = String::from_utf8(read_file_bytes(input_path, io)?)
+        .map_err(|_| anyhow!(t!("saltpack-not-armored", path = input_path.display().to_string())))?;
+
+    let decrypted_data =
+        saltpack::decrypt(&identity, &armored).with_context(|| t!("decrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &decrypted_data, io)?;
+    report_done(
+        output_path,
+        t!("decrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// Encrypts to X25519 recipients, or with one password, as an age file.
+fn age_encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    recipients: &[String],
+    password: Option<&str>,
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let recipients = recipients
+        .iter()
+        .map(|key| parse_public_key(key))
+        .collect::<Result<Vec<_>>>()?;
+    let plain_text_bytes = read_file_bytes(Path::new(input_path), io)?;
+
+    let file = match password {
+        Some(password) => age::encrypt_with_password(password, &plain_text_bytes),
+        None => age::encrypt(&recipients, &plain_text_bytes),
+    }
+    .with_context(|| t!("encrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &file, io)?;
+    report_done(
+        output_path,
+        t!("age-done", path = output_path.display().to_string()),
+    );
+
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, &file, tsa_url, io)?;
+    }
+    Ok(())
+}
+
+fn age_decrypt_file(
+    input_path: &str,
+    output_path: &str,
+    key: age::Key,
+    io: IoOptions,
+) -> Result<()> {
+    let file = read_file_bytes(Path::new(input_path), io)?;
+
+    let decrypted_data = age::decrypt(&key, &file).with_context(|| t!("decrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &decrypted_data, io)?;
+    report_done(
+        output_path,
+        t!("decrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+fn secretstream_encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    key: &[u8; key_file::LENGTH],
+    timestamp_url: Option<&str>,
+    io: IoOptions,
+) -> Result<()> {
+    let plain_text_bytes = read_file_bytes(Path::new(input_path), io)?;
+    let file = secretstream::encrypt(key, &plain_text_bytes).with_context(|| t!("encrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &file, io)?;
+    report_done(
+        output_path,
+        t!("encrypt-done", path = output_path.display().to_string()),
+    );
+
+    if let Some(tsa_url) = timestamp_url {
+        timestamp_output(output_path, &file, tsa_url, io)?;
+    }
+    Ok(())
+}
+
+fn secretstream_decrypt_file(
+    input_path: &str,
+    output_path: &str,
+    key: &[u8; key_file::LENGTH],
+    io: IoOptions,
+) -> Result<()> {
+    let file = read_file_bytes(Path::new(input_path), io)?;
+
+    let decrypted_data = secretstream::decrypt(key, &file).with_context(|| t!("decrypt-failed"))?;
+
+    let output_path = Path::new(output_path);
+    write_file_bytes(output_path, &decrypted_data, io)?;
+    report_done(
+        output_path,
+        t!("decrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// Whether the local file at `path` starts as an age file does.
+fn is_age_file(path: &str) -> bool {
+    let mut intro = [0; age::INTRO.len()];
+    path != STDIO
+        && !remote::is_remote(Path::new(path))
+        && fs::File::open(path)
+            .and_then(|mut file| file.read_exact(&mut intro))
+            .is_ok()
+        && intro[..] == *age::INTRO
+}
 
-    let metadata_length = serialize(&EncryptionMetadata {
-        nonce: generate_nonce(),
-        salt: generate_salt(),
+/// Reads our secret key from `path`. A key file protected at rest, as
+/// `keygen --x25519` writes it or any key file run through `encrypt`, is
+/// recognised by its header; it is unlocked with a prompted passphrase,
+/// under the same wrong-password delays as any other ciphertext.
+fn read_identity(path: &Path) -> Result<crypto_box::SecretKey> {
+    let identity_bytes = Zeroizing::new(
+        fs::read(path)
+            .with_context(|| t!("identity-unreadable", path = path.display().to_string()))?,
+    );
+    open_identity(path, identity_bytes, || {
+        Ok(Zeroizing::new(
+            rpassword::prompt_password(t!(
+                "identity-passphrase-prompt",
+                path = path.display().to_string()
+            ))
+            .context(t!("password-unavailable"))?,
+        ))
+    })
+}
+
+/// The secret key in `identity_bytes`, read from `path`, unlocked with the
+/// passphrase `passphrase` gives if it is protected.
+fn open_identity(
+    path: &Path,
+    mut identity_bytes: Zeroizing<Vec<u8>>,
+    passphrase: impl FnOnce() -> Result<Zeroizing<String>>,
+) -> Result<crypto_box::SecretKey> {
+    if identity_bytes.starts_with(header::MAGIC) {
+        let passphrase = passphrase()?;
+        let attempt = attempts::begin(&identity_bytes);
+        identity_bytes = match decrypt_bytes(&passphrase, &identity_bytes) {
+            Ok(plain_bytes) => {
+                attempt.succeeded();
+                Zeroizing::new(plain_bytes)
+            }
+            Err(e) => {
+                attempt.failed(&e);
+                return Err(e.context(t!(
+                    "identity-unlock-failed",
+                    path = path.display().to_string()
+                )));
+            }
+        };
+    }
+    let identity_text = std::str::from_utf8(&identity_bytes)
+        .map_err(|_| anyhow!(t!("identity-not-key-file", path = path.display().to_string())))?;
+    warn_if_expired(path, &identity_bytes);
+    if let Some(identity) = age::parse_identity(identity_text)? {
+        return Ok(identity);
+    }
+    let identity_text = key_file::without_comments(identity_text);
+    Ok(crypto_box::SecretKey::from(parse_key_bytes(&identity_text, "secret")?))
+}
+
+/// Accepts a 32-byte public key as hex (sodium_bin2hex) or standard base64,
+/// or as an `age1...` recipient.
+fn parse_public_key(encoded: &str) -> Result<crypto_box::PublicKey> {
+    if encoded.trim().starts_with("age1") {
+        return age::parse_recipient(encoded);
+    }
+    parse_key_bytes(encoded, "public").map(crypto_box::PublicKey::from)
+}
+
+/// `kind` is `secret` or `public`, which the messages name.
+fn parse_key_bytes(encoded: &str, kind: &str) -> Result<[u8; crypto_box::KEY_SIZE]> {
+    let encoded = encoded.trim();
+    let bytes = Zeroizing::new(
+        hex::decode(encoded)
+            .or_else(|_| base64::engine::general_purpose::STANDARD.decode(encoded))
+            .map_err(|_| anyhow!(t!("key-not-encoded", kind = kind)))?,
+    );
+    bytes.as_slice().try_into().map_err(|_| {
+        anyhow!(t!(
+            "key-wrong-length",
+            kind = kind,
+            expected = crypto_box::KEY_SIZE,
+            length = bytes.len()
+        ))
     })
-    .unwrap()
-    .len();
-    let (metadata_bytes, encrypted_data) = encrypted_data.split_at(metadata_length);
+}
+
+/// Fetches an RFC 3161 timestamp over `data` and saves it as `<OUTPUT>.tsr`.
+fn timestamp_output(output_path: &Path, data: &[u8], tsa_url: &str, io: IoOptions) -> Result<()> {
+    let timestamp = timestamp::request_timestamp(tsa_url, data)?;
+    let mut token_path = output_path.as_os_str().to_owned();
+    token_path.push(".tsr");
+    let token_path = Path::new(&token_path);
+    write_file_bytes(token_path, &timestamp.response, io)?;
+    println!(
+        "{}",
+        t!(
+            "timestamp-done",
+            time = timestamp.gen_time.to_string(),
+            path = token_path.display().to_string()
+        )
+    );
+    Ok(())
+}
+
+/// Enough for any header, to tell a streamed file before reading it all.
+const HEADER_PEEK: u64 = 64 << 10;
+
+/// Decrypts with the session `keys` returns for the start of the file, which
+/// has the header.
+fn decrypt_file<'a>(
+    input_path: &str,
+    output_path: &str,
+    keys: impl FnOnce(&[u8]) -> Result<Encryptor<'a>>,
+    format: Format,
+    restore: metadata::Restore,
+    io: IoOptions,
+) -> Result<()> {
+    let input_path = Path::new(input_path);
+    let mut index = None;
+    let mut file_bytes = if format == Format::Native && !io.direct_io {
+        let length = match is_stdio(input_path) {
+            true => None,
+            false => fs::metadata(input_path).ok().map(|metadata| metadata.len()),
+        };
+        let (input, parts) = open_indexed_input(input_path, io)?;
+        index = parts;
+        let mut input = progress::Reader::new(input, length, io.quiet);
+        let mut head = Vec::new();
+        input
+            .by_ref()
+            .take(HEADER_PEEK)
+            .read_to_end(&mut head)
+            .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?;
+        if stream::is_stream(head.as_slice())? {
+            let mut keys = keys(&head)?;
+            if let Some(index) = &index {
+                index.authenticate(&mut keys, &head)?;
+            }
+            let output_path = Path::new(output_path);
+            return stream_decrypt_file(head, input, input_path, output_path, keys, restore, io);
+        }
+        input
+            .read_to_end(&mut head)
+            .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?;
+        head
+    } else {
+        read_file_bytes(input_path, io)?
+    };
+    if format == Format::Mime {
+        let text = String::from_utf8(file_bytes)
+            .map_err(|_| anyhow!(t!("mime-not-message", path = input_path.display().to_string())))?;
+        file_bytes = mime::unwrap(&text)?;
+    } else if armor::is_armored(&file_bytes) {
+        let text = String::from_utf8(file_bytes)
+            .map_err(|_| {
+                anyhow!(t!("armor-not-message", path = input_path.display().to_string()))
+            })?;
+        file_bytes = armor::dearmor(&text)?;
+    }
+
+    let mut keys = keys(&file_bytes)?;
+    let attempt = attempts::begin(&file_bytes);
+    let decrypted_data = match keys.decrypt(&file_bytes) {
+        Ok(data) => {
+            attempt.succeeded();
+            Zeroizing::new(data)
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            return Err(e);
+        }
+    };
+    if let Some(index) = &index {
+        index.authenticate(&mut keys, &file_bytes)?;
+    }
+
+    let metadata = keys.metadata(&file_bytes)?;
+    let output_path = &restore.output_path(input_path, Path::new(output_path), metadata.as_ref())?;
+    write_file_bytes(output_path, &decrypted_data, io)?;
+    restore.apply(output_path, metadata.as_ref())?;
+
+    report_done(
+        output_path,
+        t!("decrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// Decrypts a streamed file, which starts with `head` and continues with
+/// `rest`, chunk by chunk. The output only appears once every chunk has
+/// decrypted, so a wrong password or a damaged chunk leaves it alone.
+/// Standard output can't be taken back: there a later failure cuts the output
+/// short, after only authenticated chunks.
+fn stream_decrypt_file(
+    head: Vec<u8>,
+    rest: impl Read,
+    input_path: &Path,
+    output_path: &Path,
+    mut keys: Encryptor,
+    restore: metadata::Restore,
+    io: IoOptions,
+) -> Result<()> {
+    let header = head.clone();
+    let (first, mut decryptor) = open_stream(head, rest, &mut keys)?;
+    let metadata = keys.metadata(&header)?;
+    let output_path = &restore.output_path(input_path, output_path, metadata.as_ref())?;
+
+    if is_stdio(output_path) {
+        let mut output = io::BufWriter::new(io::stdout().lock());
+        let mut write = |plaintext: Vec<u8>| {
+            output
+                .write_all(&plaintext)
+                .with_context(|| t!("stdout-write-failed"))
+        };
+        if let Some(plaintext) = first {
+            write(plaintext)?;
+            decryptor.for_each_chunk(|plaintext, _| write(plaintext))?;
+        }
+        drop(decryptor);
+        output.flush().with_context(|| t!("stdout-write-failed"))?;
+        report_done(
+            output_path,
+            t!("decrypt-done", path = output_path.display().to_string()),
+        );
+        return Ok(());
+    }
+
+    let (mut file, output) = match io.resume {
+        true => create_partial(output_path, io)?,
+        false => create_file(output_path, io)?,
+    };
+    let mut digest = blake3::Hasher::new();
+    let mut chunk = first;
+    let (progress, kept) = match io.resume {
+        true => resume_decrypt(input_path, output_path, &mut decryptor, &file)?,
+        false => (None, 0),
+    };
+    if kept > 0 {
+        // The first chunk was the first of those kept.
+        chunk = decryptor.next_chunk()?;
+        if io.verify_after {
+            digest
+                .update_reader((&file).take(kept))
+                .with_context(|| t!("file-read-failed", path = output_path.display().to_string()))?;
+        }
+    }
+    if io.resume {
+        file.set_len(kept)
+            .and_then(|()| file.seek(SeekFrom::Start(kept)))
+            .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    }
+    let mut writer = io::BufWriter::new(file);
+    let mut write = |plaintext: Vec<u8>, position: Option<u64>| {
+        digest.update(&plaintext);
+        writer
+            .write_all(&plaintext)
+            .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+        if let (Some(progress), Some(chunks)) = (&progress, position) {
+            progress.reached(&mut writer, chunks)?;
+        }
+        Ok(())
+    };
+    if let Some(plaintext) = chunk {
+        write(plaintext, decryptor.position())?;
+        decryptor.for_each_chunk(&mut write)?;
+    }
+    // Clears the progress line.
+    drop(decryptor);
+    writer
+        .flush()
+        .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    sync_file(output_path, &file, io)?;
+    if io.verify_after {
+        file.sync_data()
+            .with_context(|| t!("file-sync-failed", path = output_path.display().to_string()))?;
+        drop_cached_pages(&file);
+        drop(file);
+        let mut reread = blake3::Hasher::new();
+        reread
+            .update_reader(
+                fs::File::open(output.written_path()).with_context(|| {
+                    t!("file-open-failed", path = output_path.display().to_string())
+                })?,
+            )
+            .with_context(|| t!("file-read-failed", path = output_path.display().to_string()))?;
+        if reread.finalize() != digest.finalize() {
+            return Err(anyhow!(t!(
+                "verify-mismatch",
+                path = output_path.display().to_string()
+            )));
+        }
+    }
+    output.commit(io)?;
+    if let Some(progress) = progress {
+        progress.finish()?;
+    }
+    restore.apply(output_path, metadata.as_ref())?;
+    report_done(
+        output_path,
+        t!("decrypt-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// `decrypt --best-effort`: decrypts a streamed file with the chunks that
+/// fail to authenticate written as zeros (left out of a compressed file), so
+/// the intact ones around a damaged region keep their offsets. Each damaged
+/// chunk is listed, and the output is kept but the run fails if there were
+/// any.
+fn salvage_file<'a>(
+    input_path: &str,
+    output_path: &str,
+    keys: impl FnOnce(&[u8]) -> Result<Encryptor<'a>>,
+    restore: metadata::Restore,
+    io: IoOptions,
+) -> Result<()> {
+    let input_path = Path::new(input_path);
+    let length = match is_stdio(input_path) {
+        true => None,
+        false => fs::metadata(input_path).ok().map(|metadata| metadata.len()),
+    };
+    let mut input = progress::Reader::new(open_input(input_path, io)?, length, io.quiet);
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(HEADER_PEEK)
+        .read_to_end(&mut head)
+        .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?;
+    if !stream::is_stream(head.as_slice())? {
+        bail!(t!("best-effort-single-message"));
+    }
+    let mut keys = keys(&head)?;
+    let metadata = keys.metadata(&head)?;
+    let mut decryptor = open_salvaging(head, input, &mut keys)?;
+    let output_path = &restore.output_path(input_path, Path::new(output_path), metadata.as_ref())?;
+
+    let (file, output) = create_file(output_path, io)?;
+    let mut writer = io::BufWriter::new(file);
+    let (mut chunks, mut damaged) = (0u64, 0u64);
+    while let Some(chunk) = decryptor.next_salvaged()? {
+        chunks += 1;
+        let plaintext = match chunk {
+            stream::Salvaged::Intact(plaintext) => Zeroizing::new(plaintext),
+            stream::Salvaged::Damaged(damage) => {
+                log::warn(&verify::describe(&damage));
+                damaged += 1;
+                let length = damage.plaintext.map_or(0, |(_, length)| length);
+                Zeroizing::new(vec![0; length as usize])
+            }
+        };
+        writer
+            .write_all(&plaintext)
+            .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    }
+    let footer_mismatch = decryptor.footer_mismatch();
+    // Clears the progress line.
+    drop(decryptor);
+    writer
+        .flush()
+        .with_context(|| t!("file-write-failed", path = output_path.display().to_string()))?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    sync_file(output_path, &file, io)?;
+    output.commit(io)?;
+    restore.apply(output_path, metadata.as_ref())?;
+
+    let path = output_path.display().to_string();
+    match damaged {
+        0 => {
+            if footer_mismatch {
+                log::warn(&t!("best-effort-footer", path = path.as_str()));
+            }
+            report_done(output_path, t!("decrypt-done", path = path));
+            Ok(())
+        }
+        damaged => Err(anyhow!(DecryptError::Corrupted).context(t!(
+            "best-effort-recovered",
+            path = path,
+            count = damaged,
+            total = chunks
+        ))),
+    }
+}
+
+/// Looks up the `--resume` checkpoint of decrypting `input_path` to
+/// `output_path` and skips the chunks it records, past the first, which
+/// `decryptor` has already decrypted. Returns the checkpoint and the length
+/// of the plaintext the `partial` output already holds, or no checkpoint for a
+/// compressed file, whose chunks don't line up with the plaintext.
+fn resume_decrypt<R: Read>(
+    input_path: &Path,
+    output_path: &Path,
+    decryptor: &mut StreamDecryptor<R>,
+    partial: &fs::File,
+) -> Result<(Option<resume::Progress>, u64)> {
+    let Some(chunk_length) = decryptor.chunk_length() else {
+        return Ok((None, 0));
+    };
+    let (progress, chunks) = resume::Progress::start(input_path, output_path)?;
+    let kept = chunks.saturating_mul(chunk_length);
+    if chunks > 0 {
+        let failed = || t!("resume-failed", path = progress.path().display().to_string());
+        let length = partial
+            .metadata()
+            .with_context(|| t!("file-read-failed", path = output_path.display().to_string()))?
+            .len();
+        if length < kept {
+            return Err(anyhow!(t!("resume-truncated"))).with_context(failed);
+        }
+        decryptor.skip(chunks - 1).with_context(failed)?;
+    }
+    Ok((Some(progress), kept))
+}
+
+type StreamDecryptor<R> = stream::Decryptor<io::Chain<io::Cursor<Vec<u8>>, R>>;
 
-    let metadata: EncryptionMetadata = deserialize(metadata_bytes)?;
-    let key = derive_key(password, &metadata.salt);
+/// Starts decrypting a streamed file, which starts with `head` and continues
+/// with `rest`, to salvage what it can. Only the header is counted against
+/// the file's attempts: a damaged first chunk says nothing about the key.
+fn open_salvaging<R: Read>(
+    head: Vec<u8>,
+    rest: R,
+    keys: &mut Encryptor,
+) -> Result<StreamDecryptor<R>> {
+    let attempt = attempts::begin(&head[..head.len().min(64)]);
+    match stream::Decryptor::new(keys, io::Cursor::new(head).chain(rest)) {
+        Ok(decryptor) => {
+            attempt.succeeded();
+            Ok(decryptor)
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            Err(e)
+        }
+    }
+}
+
+/// Starts decrypting a streamed file, which starts with `head` and continues
+/// with `rest`, returning its first chunk. The first chunk proves the key
+/// right or wrong, so that is what gets counted against the file's attempts.
+fn open_stream<R: Read>(
+    head: Vec<u8>,
+    rest: R,
+    keys: &mut Encryptor,
+) -> Result<(Option<Vec<u8>>, StreamDecryptor<R>)> {
+    // The header holds a random salt and nonce prefix, so it identifies the
+    // file as well as a digest of all of it would.
+    let attempt = attempts::begin(&head[..head.len().min(64)]);
 
+    let first = stream::Decryptor::new(keys, io::Cursor::new(head).chain(rest))
+        .and_then(|mut decryptor| Ok((decryptor.next_chunk()?, decryptor)));
+    match first {
+        Ok(first) => {
+            attempt.succeeded();
+            Ok(first)
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            Err(e)
+        }
+    }
+}
 
-    let decrypted_data = decrypt(&key, &metadata.nonce, encrypted_data)
-        .with_context(|| "Error during decryption")?;
+/// Decrypts an `encrypt --archive` file and restores the tree it holds as
+/// `output_path`. The tree is built in a temporary directory beside it and
+/// only renamed into place once every chunk has decrypted; with `force`, a
+/// directory already there is replaced only then.
+fn extract_file<'a>(
+    input_path: &str,
+    output_path: &str,
+    keys: impl FnOnce(&[u8]) -> Result<Encryptor<'a>>,
+    force: bool,
+    io: IoOptions,
+) -> Result<()> {
+    let (input_path, output_path) = (Path::new(input_path), Path::new(output_path));
+    if is_stdio(output_path) {
+        bail!(t!("extract-needs-output-dir"));
+    }
+    if !force && fs::symlink_metadata(output_path).is_ok() {
+        return Err(anyhow!(t!(
+            "output-exists",
+            path = output_path.display().to_string()
+        )));
+    }
+    let length = match is_stdio(input_path) {
+        true => None,
+        false => fs::metadata(input_path).ok().map(|metadata| metadata.len()),
+    };
+    let mut input = progress::Reader::new(open_input(input_path, io)?, length, io.quiet);
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(HEADER_PEEK)
+        .read_to_end(&mut head)
+        .with_context(|| t!("file-read-failed", path = input_path.display().to_string()))?;
+    if !stream::is_stream(head.as_slice())? {
+        bail!(t!("extract-not-archive"));
+    }
+    let mut keys = keys(&head)?;
+    let (first, decryptor) = open_stream(head, input, &mut keys)?;
+
+    let name = output_path
+        .file_name()
+        .ok_or_else(|| {
+            anyhow!(t!("extract-not-dir-name", path = output_path.display().to_string()))
+        })?;
+    let suffix = entropy::bytes::<4>()?;
+    let temp = output_path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        hex::encode(suffix)
+    ));
+    let pending = PendingDir(temp);
+    let mut plaintext = Plaintext {
+        decryptor,
+        chunk: first.unwrap_or_default(),
+        offset: 0,
+        error: None,
+    };
+    let files = archive::extract(io::BufReader::new(&mut plaintext), &pending.0, io.fsync);
+    // A decryption failure surfaces as a read error; report it as itself.
+    let files = match (files, plaintext.error.take()) {
+        (_, Some(e)) | (Err(e), None) => return Err(e),
+        (Ok(files), None) => files,
+    };
+    // Clears the progress line.
+    drop(plaintext);
 
+    if force {
+        match fs::symlink_metadata(output_path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(output_path),
+            Ok(_) => fs::remove_file(output_path),
+            Err(_) => Ok(()),
+        }
+        .with_context(|| t!("extract-replace-failed", path = output_path.display().to_string()))?;
+    }
+    fs::rename(&pending.0, output_path)
+        .with_context(|| t!("extract-move-failed", path = output_path.display().to_string()))?;
+    if io.fsync {
+        sync_parent_dir(output_path)?;
+    }
+    report_done(
+        output_path,
+        t!(
+            "extract-done",
+            count = files,
+            path = output_path.display().to_string()
+        ),
+    );
+    Ok(())
+}
+
+/// A half-extracted tree, removed when dropped unless it was moved into place.
+struct PendingDir(std::path::PathBuf);
+
+impl Drop for PendingDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Reads as the plaintext of a streamed file, whose first chunk is `chunk`.
+struct Plaintext<R> {
+    decryptor: StreamDecryptor<R>,
+    chunk: Vec<u8>,
+    offset: usize,
+    /// Why the last read failed, if decryption did.
+    error: Option<anyhow::Error>,
+}
+
+impl<R: Read> Read for Plaintext<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.chunk.len() {
+            match self.decryptor.next_chunk() {
+                Ok(Some(chunk)) => (self.chunk, self.offset) = (chunk, 0),
+                Ok(None) => return Ok(0),
+                Err(e) => {
+                    let message = e.to_string();
+                    self.error = Some(e);
+                    return Err(io::Error::other(message));
+                }
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.offset);
+        buf[..n].copy_from_slice(&self.chunk[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Writes a new key file, or with `x25519` a secret key whose public key is
+/// printed, refusing to replace one: that would lose access to everything
+/// the old key encrypted. The secret key is encrypted under a prompted
+/// passphrase unless `no_passphrase`. With `expires_in`, the file records
+/// when it is due to be rotated.
+fn keygen(
+    output_path: &str,
+    x25519: bool,
+    ed25519: bool,
+    no_passphrase: bool,
+    expires_in: Option<Duration>,
+) -> Result<()> {
     let output_path = Path::new(output_path);
-    write_file_bytes(output_path, &decrypted_data)?;
+    if !is_stdio(output_path) && output_path.exists() {
+        return Err(anyhow!(t!(
+            "key-file-exists",
+            path = output_path.display().to_string()
+        )));
+    }
+    let validity = key_file::Validity::starting_now(expires_in);
+    if x25519 {
+        let (identity, public_key) = recipient::generate(&validity)?;
+        let identity = match no_passphrase {
+            true => Zeroizing::new(identity.as_bytes().to_vec()),
+            false => {
+                let passphrase = prompt(t!("identity-new-passphrase-prompt"), true)?;
+                Zeroizing::new(protect_identity(identity.as_bytes(), &passphrase)?)
+            }
+        };
+        write_file_bytes(output_path, &identity, IoOptions::default())?;
+        let message = t!(
+            "keygen-identity-done",
+            path = output_path.display().to_string()
+        );
+        // Standard output holds the secret key itself.
+        match is_stdio(output_path) {
+            true => eprintln!("{message}\n{public_key}"),
+            false => println!("{message}\n{public_key}"),
+        }
+        return Ok(());
+    }
+    let key_file = key_file::generate(&validity)?;
+    write_file_bytes(output_path, key_file.as_bytes(), IoOptions::default())?;
+    if ed25519 {
+        let seed = Zeroizing::new(key_file::parse(key_file.as_bytes())?);
+        let message = t!(
+            "keygen-signing-done",
+            path = output_path.display().to_string()
+        );
+        let public_key = signed_manifest::public_key(&seed);
+        match is_stdio(output_path) {
+            true => eprintln!("{message}\n{public_key}"),
+            false => println!("{message}\n{public_key}"),
+        }
+        return Ok(());
+    }
+    report_done(
+        output_path,
+        t!("keygen-done", path = output_path.display().to_string()),
+    );
+    Ok(())
+}
+
+/// Encrypts the secret key file `identity` under `passphrase`, with Argon2id
+/// since a stolen copy can be attacked offline, as `encrypt` would.
+fn protect_identity(identity: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    Encryptor::with_kdf(passphrase, Kdf::ARGON2ID).encrypt(identity)
+}
 
+fn check_file(input_path: &str) -> Result<()> {
+    let input_path = Path::new(input_path);
+    let file_bytes = read_file_bytes(input_path, IoOptions::default())?;
+    if strip_footer(&file_bytes)?.is_none() {
+        return Err(anyhow!(t!(
+            "checksum-missing",
+            path = input_path.display().to_string()
+        )));
+    }
     println!(
-        "Decryption complete, decrypted file saved at: {}",
-        output_path.display()
+        "{}",
+        t!("checksum-ok", path = input_path.display().to_string())
     );
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    let started = Instant::now();
+    let result = run();
+    if let Err(e) = &result {
+        eprintln!("Error: {e:?}");
+    }
+    report::finish(started, &result)
+}
+
+fn run() -> Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    report::set_format(match cli.print0 {
+        true => report::OutputFormat::Paths0,
+        false => cli.output_format,
+    });
+    log::init(cli.verbose, cli.log_file.as_deref())?;
+    log::info(&format!(
+        "{} started",
+        matches.subcommand_name().unwrap_or_default()
+    ));
+    config::load()?;
+    if cli.background {
+        if let Err(e) = priority::lower() {
+            log::warn(&t!("background-failed", error = e.to_string()));
+        }
+    }
+    if let Some(rate) = cli.bwlimit {
+        throttle::set(rate);
+    }
 
-    match &cli.command {
+    if cli.generate_man.is_some() && cli.command.is_some() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                t!("generate-man-subcommand"),
+            )
+            .exit()
+    }
+    let Some(command) = &cli.command else {
+        let Some(dir) = &cli.generate_man else {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::MissingSubcommand,
+                    t!("subcommand-required"),
+                )
+                .exit()
+        };
+        let pages = man::write_pages(Cli::command(), dir)?;
+        println!("{}", t!("man-written", count = pages, path = dir.display().to_string()));
+        return Ok(());
+    };
+    match command {
         Commands::Encrypt {
-            input_path,
-            output_path,
-            password,
-        } => encrypt_file(input_path, output_path, password)?,
+            paths,
+            passwords,
+            password_source,
+            format,
+            recipients,
+            tokens,
+            sealed_box,
+            pgp_recipient,
+            key_file,
+            split_key: split,
+            print_shares,
+            recovery_key,
+            force_weak_password,
+            allow_weak_password,
+            use_keyring,
+            timestamp_url,
+            line_length,
+            armor,
+            stream,
+            split_size,
+            cipher,
+            compress,
+            aad,
+            archive,
+            reproducible,
+            sign_manifest,
+            remove_original,
+            keep_metadata,
+            encrypt_names,
+            deterministic,
+            allow_double_encrypt,
+            kdf,
+            io,
+            limits,
+        } => {
+            let kdf = kdf.kdf()?;
+            let output_dir = config::get().output_dir.as_deref();
+            let (input_path, output_path) = paths.resolve(encrypted_name, output_dir)?;
+            io.check_stdio(input_path, &output_path)?;
+            let manifest = paths
+                .manifest
+                .as_deref()
+                .map(|path| manifest::read(path, encrypted_name, output_dir))
+                .transpose()?;
+            match manifest {
+                Some(_) => report::batch(),
+                None => report::target(input_path, Some(&output_path))?,
+            }
+            if *remove_original && (input_path == STDIO || output_path == STDIO) {
+                return Err(anyhow!(t!("remove-original-stdio")));
+            }
+            // The plaintext only goes once the ciphertext is safely on disk.
+            let io = &IoOptions {
+                fsync: io.fsync || *remove_original || paths.in_place,
+                split_size: *split_size,
+                ..*io
+            };
+            if manifest.is_none() {
+                limits.check_input(input_path)?;
+                if !*allow_double_encrypt && is_encrypted(Path::new(input_path)) {
+                    return Err(anyhow!(t!("already-encrypted", path = input_path)));
+                }
+            }
+            let output_path = &output_path;
+            let timestamp_url = timestamp_url.as_deref();
+            if *armor && *format != Format::Native {
+                return Err(anyhow!(t!("armor-native-only")));
+            }
+            if line_length.is_some() && *format != Format::Mime && !*armor {
+                return Err(anyhow!(t!("line-length-needs-wrapping")));
+            }
+            if *stream && *format != Format::Native {
+                return Err(anyhow!(t!("stream-native-only")));
+            }
+            if io.resume && (!*stream || *archive) {
+                return Err(anyhow!(t!("resume-needs-stream")));
+            }
+            let remote_output = remote::is_remote(Path::new(output_path));
+            if timestamp_url.is_some() && (output_path == STDIO || remote_output) {
+                return Err(anyhow!(t!("timestamp-needs-output")));
+            }
+            let wrapping = match (*format, *armor) {
+                (Format::Mime, _) => Some(Wrapping::Mime(
+                    line_length.map_or(mime::MAX_LINE_LENGTH, usize::from),
+                )),
+                (_, true) => Some(Wrapping::Armor(
+                    line_length.map_or(armor::LINE_LENGTH, usize::from),
+                )),
+                _ => None,
+            };
+            let key_file = key_file.as_deref().map(read_key_file).transpose()?;
+            let is_dir = Path::new(input_path).is_dir();
+            let signing_key =
+                sign_manifest.as_deref().map(read_key_file).transpose()?.map(Zeroizing::new);
+            let write_manifest = || -> Result<()> {
+                if let Some(signing_key) = &signing_key {
+                    let output_path = Path::new(output_path);
+                    let manifest_path =
+                        signed_manifest::write(output_path, signing_key, paths.force)?;
+                    report_done(
+                        output_path,
+                        t!("manifest-signed", path = manifest_path.display().to_string()),
+                    );
+                }
+                Ok(())
+            };
+            let to_public_key =
+                *format == Format::Saltpack || sealed_box.is_some() || pgp_recipient.is_some();
+            if is_dir && to_public_key {
+                return Err(anyhow!(t!("dir-needs-password")));
+            }
+            if is_dir && *format == Format::Age {
+                return Err(anyhow!(t!("age-one-file")));
+            }
+            if is_dir && *format == Format::Secretstream {
+                return Err(anyhow!(t!("secretstream-one-file")));
+            }
+            // Formats of other tools, which have none of the native options.
+            let foreign = matches!(format, Format::Saltpack | Format::Age | Format::Secretstream);
+            if is_dir && !*archive && remote_output {
+                return Err(anyhow!(t!("dir-remote-needs-archive")));
+            }
+            if *archive && (!is_dir || *format != Format::Native) {
+                return Err(anyhow!(t!("archive-needs-dir")));
+            }
+            if split_size.is_some() {
+                let one_file = !is_dir || *archive;
+                let local = output_path != STDIO && !remote_output;
+                if !one_file || !local || *format != Format::Native || to_public_key {
+                    return Err(anyhow!(t!("split-size-one-file")));
+                }
+                split::check_output(Path::new(output_path), paths.force)?;
+            }
+            let compressed = compress.is_some_and(|c| c != CompressionName::None);
+            if compressed && foreign {
+                return Err(anyhow!(t!("compress-native-only")));
+            }
+            if aad.is_some() && foreign {
+                return Err(anyhow!(t!("aad-native-only")));
+            }
+            if *deterministic && foreign {
+                return Err(anyhow!(t!("deterministic-native-only")));
+            }
+            if *recovery_key && foreign {
+                return Err(anyhow!(t!("recovery-key-native-only")));
+            }
+            let aad = aad.as_ref().map(|aad| aad.as_bytes().to_vec());
+            let config = config::get();
+            let cipher = cipher.or(config.cipher).unwrap_or_else(CipherName::for_this_cpu);
+            let compress = compress.or(config.compress).unwrap_or_default();
+            let local_input = input_path != STDIO && !remote::is_remote(Path::new(input_path));
+            if *keep_metadata && (!local_input || foreign) {
+                return Err(anyhow!(t!("keep-metadata-needs-file")));
+            }
+            if *encrypt_names && (!is_dir || foreign) {
+                return Err(anyhow!(t!("encrypt-names-needs-dir")));
+            }
+            if sign_manifest.is_some()
+                && (!is_dir || foreign || to_public_key || output_path == STDIO || remote_output)
+            {
+                return Err(anyhow!(t!("manifest-needs-output")));
+            }
+            if manifest.is_some() && (to_public_key || foreign) {
+                return Err(anyhow!(t!("manifest-input-needs-password")));
+            }
+            let file_metadata = |input: &str, keep: bool| -> Result<Option<Metadata>> {
+                let input = Path::new(input);
+                let root = match is_dir {
+                    true => Path::new(input_path),
+                    false => input.parent().unwrap_or(Path::new("")),
+                };
+                match keep || *keep_metadata || *encrypt_names {
+                    true => metadata::read(input, root).map(Some),
+                    false => Ok(None),
+                }
+            };
+            let encrypt = |keys: Encryptor| -> Result<()> {
+                let mut keys = keys
+                    .with_cipher(cipher.into())
+                    .with_compression(compress.into())
+                    .with_aad(aad.clone())
+                    .with_deterministic(*deterministic);
+                if *archive {
+                    let (input_path, output_path) = (Path::new(input_path), Path::new(output_path));
+                    let keys = keys.with_threads(paths.jobs());
+                    archive_encrypt_dir(
+                        input_path,
+                        output_path,
+                        paths,
+                        *reproducible,
+                        keys,
+                        *io,
+                    )?;
+                    return write_manifest();
+                }
+                if let Some(entries) = &manifest {
+                    keys.derive()?;
+                    let io = IoOptions { quiet: true, ..*io };
+                    let entries_by_output: HashMap<_, _> = entries
+                        .iter()
+                        .map(|entry| (entry.output.as_str(), entry))
+                        .collect();
+                    process_files(manifest::tasks(entries)?, paths, limits, |input, output| {
+                        let entry = entries_by_output[output];
+                        let keys = keys
+                            .clone()
+                            .with_cipher(entry.cipher.unwrap_or(cipher).into())
+                            .with_compression(entry.compress.unwrap_or(compress).into());
+                        encrypt_file(
+                            input,
+                            output,
+                            keys.with_metadata(file_metadata(input, entry.keep_metadata)?),
+                            wrapping,
+                            *stream,
+                            timestamp_url,
+                            io,
+                        )
+                    })?;
+                    report_manifest(input_path, entries.len());
+                    return Ok(());
+                }
+                if !is_dir {
+                    // Only a streamed file has chunks to share out.
+                    return encrypt_file(
+                        input_path,
+                        output_path,
+                        keys.with_threads(paths.jobs())
+                            .with_metadata(file_metadata(input_path, false)?),
+                        wrapping,
+                        *stream,
+                        timestamp_url,
+                        *io,
+                    );
+                }
+                // One KDF run for the whole directory, shared by every worker.
+                keys.derive()?;
+                // Several files at once would fight over one progress line.
+                let io = IoOptions { quiet: true, ..*io };
+                process_dir(
+                    Path::new(input_path),
+                    Path::new(output_path),
+                    match *encrypt_names {
+                        true => random_name,
+                        false => encrypted_name,
+                    },
+                    paths,
+                    limits,
+                    |input, output| {
+                        encrypt_file(
+                            input,
+                            output,
+                            keys.clone().with_metadata(file_metadata(input, false)?),
+                            wrapping,
+                            *stream,
+                            timestamp_url,
+                            io,
+                        )
+                    },
+                )?;
+                write_manifest()
+            };
+            let key_file = match split {
+                Some(split) => {
+                    if foreign {
+                        return Err(anyhow!(t!("split-key-native-only")));
+                    }
+                    let local = output_path != STDIO && !remote_output && manifest.is_none();
+                    if !*print_shares && !local {
+                        return Err(anyhow!(t!("split-key-needs-output")));
+                    }
+                    if *print_shares && (output_path == STDIO || report::quiet()) {
+                        return Err(anyhow!(t!("print-shares-stdout")));
+                    }
+                    Some(split_key(output_path, *split, *print_shares, paths.force)?)
+                }
+                None => key_file,
+            };
+            let mut unsaved = None;
+            let sourced_passwords;
+            let passwords = match password_source.read()? {
+                Some(password) => {
+                    sourced_passwords = Zeroizing::new(vec![password.to_string()]);
+                    &*sourced_passwords
+                }
+                None => passwords,
+            };
+            let keyring_passwords;
+            let passwords = match use_keyring {
+                Some(name) => {
+                    let password = match keyring::get(name)? {
+                        Some(password) => password,
+                        None => {
+                            unsaved = Some(name);
+                            prompt_password(true)?
+                        }
+                    };
+                    keyring_passwords = Zeroizing::new(vec![password.to_string()]);
+                    &*keyring_passwords
+                }
+                None => passwords,
+            };
+            match (passwords.as_slice(), sealed_box, pgp_recipient) {
+                _ if *format == Format::Saltpack => {
+                    if recipients.is_empty() || !passwords.is_empty() || !tokens.is_empty() {
+                        return Err(anyhow!(t!("saltpack-needs-recipients")));
+                    }
+                    saltpack_encrypt_file(input_path, output_path, recipients, timestamp_url, *io)?
+                }
+                _ if *format == Format::Secretstream => {
+                    let key = match key_file {
+                        Some(key) if passwords.is_empty() && recipients.is_empty() => key,
+                        _ => return Err(anyhow!(t!("secretstream-key-file"))),
+                    };
+                    if !tokens.is_empty() {
+                        return Err(anyhow!(t!("secretstream-key-file")));
+                    }
+                    secretstream_encrypt_file(input_path, output_path, &key, timestamp_url, *io)?
+                }
+                _ if *format == Format::Age => {
+                    let one_password = match passwords.len() {
+                        0 => true,
+                        1 => recipients.is_empty(),
+                        _ => false,
+                    };
+                    if !one_password || !tokens.is_empty() || key_file.is_some() {
+                        return Err(anyhow!(t!("age-needs-recipients")));
+                    }
+                    let prompted;
+                    let password = match passwords.first() {
+                        Some(password) => Some(password),
+                        None if recipients.is_empty() => {
+                            prompted = prompt_password(true)?;
+                            Some(&*prompted)
+                        }
+                        None => None,
+                    };
+                    if let Some(password) = password {
+                        let policy = policy::Policy::load()?;
+                        policy.enforce(password, *force_weak_password, *allow_weak_password)?;
+                    }
+                    age_encrypt_file(
+                        input_path,
+                        output_path,
+                        recipients,
+                        password.map(String::as_str),
+                        timestamp_url,
+                        *io,
+                    )?
+                }
+                _ if !recipients.is_empty()
+                    || !tokens.is_empty()
+                    || passwords.len() > 1
+                    || *recovery_key =>
+                {
+                    if key_file.is_some() {
+                        return Err(anyhow!(t!("key-file-one-password")));
+                    }
+                    let prompted;
+                    let mut passwords: Vec<&str> = passwords.iter().map(String::as_str).collect();
+                    if passwords.is_empty() && recipients.is_empty() && tokens.is_empty() {
+                        prompted = prompt_password(true)?;
+                        passwords.push(&prompted);
+                    }
+                    let policy = policy::Policy::load()?;
+                    for password in &passwords {
+                        policy.enforce(password, *force_weak_password, *allow_weak_password)?;
+                    }
+                    let public_keys = recipients
+                        .iter()
+                        .map(|key| parse_public_key(key))
+                        .collect::<Result<Vec<_>>>()?;
+                    // Wrapped for like one more password, which the policy doesn't apply to.
+                    let recovery_key = recovery_key.then(recovery::generate).transpose()?;
+                    passwords.extend(recovery_key.as_deref().map(String::as_str));
+                    let mut keys = Encryptor::to_recipients(&public_keys, &passwords, kdf)?;
+                    for token in tokens {
+                        keys = keys.with_token(token, &token::public_key(token)?)?;
+                    }
+                    encrypt(keys)?;
+                    if let Some(code) = recovery_key {
+                        eprintln!("{}", t!("recovery-key-created", path = output_path.as_str()));
+                        eprintln!("  {}", *code);
+                    }
+                }
+                ([password], _, _) => {
+                    let policy = policy::Policy::load()?;
+                    policy.enforce(password, *force_weak_password, *allow_weak_password)?;
+                    encrypt(encryptor(Some(password), key_file, kdf))?
+                }
+                (_, Some(_), _) | (_, _, Some(_)) if *format == Format::Mime || *armor => {
+                    return Err(anyhow!(t!("mime-password-only")));
+                }
+                (_, Some(recipient), _) => {
+                    seal_file(input_path, output_path, recipient, timestamp_url, *io)?
+                }
+                (_, _, Some(key_path)) => {
+                    pgp_encrypt_file(input_path, output_path, key_path, timestamp_url, *io)?
+                }
+                (_, None, None) if key_file.is_some() => {
+                    encrypt(encryptor(None, key_file, kdf))?
+                }
+                (_, None, None) => {
+                    let policy = policy::Policy::load()?;
+                    let password = prompt_password(true)?;
+                    policy.enforce(&password, *force_weak_password, *allow_weak_password)?;
+                    encrypt(Encryptor::with_kdf(&password, kdf))?
+                }
+            }
+            if let Some(name) = unsaved {
+                keyring::set(name, &passwords[0])?;
+            }
+            if *remove_original {
+                match &manifest {
+                    Some(entries) => {
+                        for entry in entries {
+                            shred::remove(Path::new(&entry.input))?;
+                        }
+                    }
+                    None => shred::remove(Path::new(input_path))?,
+                }
+            }
+        }
         Commands::Decrypt {
-            input_path,
-            output_path,
+            paths,
             password,
-        } => decrypt_file(input_path, output_path, password)?,
+            password_source,
+            use_keyring,
+            key_file,
+            shares,
+            recovery_key,
+            format,
+            identity,
+            token,
+            extract,
+            restore_metadata,
+            best_effort,
+            aad,
+            io,
+            limits,
+        } => {
+            let (input_path, output_path) = paths.resolve(decrypted_name, None)?;
+            io.check_stdio(input_path, &output_path)?;
+            let manifest = paths
+                .manifest
+                .as_deref()
+                .map(|path| manifest::read(path, decrypted_name, None))
+                .transpose()?;
+            match &manifest {
+                Some(entries) if entries.iter().any(manifest::Entry::has_options) => {
+                    return Err(anyhow!(t!("job-encrypt-options")))
+                }
+                Some(_) => report::batch(),
+                None => {
+                    report::target(input_path, Some(&output_path))?;
+                    limits.check_input(input_path)?;
+                }
+            }
+            let output_path = &output_path;
+            let key_file = match shares.is_empty() {
+                true => key_file.as_deref().map(read_key_file).transpose()?,
+                false => Some(combine_shares(shares)?),
+            };
+            let batch = manifest.is_some() || Path::new(input_path).is_dir();
+            let format = match format {
+                Format::Native if !batch && is_age_file(input_path) => &Format::Age,
+                format => format,
+            };
+            if *extract && (*format != Format::Native || batch) {
+                return Err(anyhow!(t!("extract-one-archive")));
+            }
+            if *best_effort && (*format != Format::Native || batch || output_path == STDIO) {
+                return Err(anyhow!(t!("best-effort-one-file")));
+            }
+            let foreign = matches!(format, Format::Saltpack | Format::Age | Format::Secretstream);
+            if manifest.is_some() && foreign {
+                return Err(anyhow!(t!("manifest-input-native-only")));
+            }
+            if batch && *format == Format::Age {
+                return Err(anyhow!(t!("age-decrypts-one-file")));
+            }
+            if batch && *format == Format::Secretstream {
+                return Err(anyhow!(t!("secretstream-one-file")));
+            }
+            if *restore_metadata && output_path == STDIO {
+                return Err(anyhow!(t!("restore-metadata-needs-file")));
+            }
+            // The plaintext stays on this machine.
+            if remote::is_remote(Path::new(output_path)) {
+                return Err(anyhow!(t!("decrypt-remote-output")));
+            }
+            if aad.is_some() && foreign {
+                return Err(anyhow!(t!("aad-native-only")));
+            }
+            let aad = aad.as_ref().map(|aad| aad.as_bytes().to_vec());
+            let given = paths.output.is_some() || paths.output_path.is_some() || paths.in_place;
+            let remote_input = remote::is_remote(Path::new(input_path));
+            let restore = metadata::Restore {
+                naming: match given || input_path == STDIO || remote_input {
+                    true => metadata::Naming::Output,
+                    false => metadata::Naming::NextToInput,
+                },
+                force: paths.force,
+                metadata: *restore_metadata,
+                staging: None,
+            };
+            let sourced_password;
+            let password = match password_source.read()? {
+                Some(password) => {
+                    sourced_password = Zeroizing::new(Some(password.to_string()));
+                    &*sourced_password
+                }
+                None => password,
+            };
+            let keyring_password;
+            let password = match use_keyring {
+                Some(name) => {
+                    let missing = || anyhow!(t!("keyring-missing", name = name.as_str()));
+                    let stored = keyring::get(name)?.ok_or_else(missing)?;
+                    keyring_password = Zeroizing::new(Some(stored.to_string()));
+                    &*keyring_password
+                }
+                None => password,
+            };
+            let recovery_password;
+            let password = match recovery_key {
+                Some(code) => {
+                    recovery_password = Zeroizing::new(Some(recovery::parse(code)?.to_string()));
+                    &*recovery_password
+                }
+                None => password,
+            };
+            match (format, password, identity) {
+                (Format::Native | Format::Mime, password, identity) if batch => {
+                    // One password or identity for the whole directory or manifest, asked
+                    // for up front, and one session, so each distinct key is derived once.
+                    let prompted;
+                    let keys = match (password, identity) {
+                        _ if *token => Encryptor::from_token(token::agreement()),
+                        (_, Some(identity)) => {
+                            Encryptor::from_identity(read_identity(Path::new(identity))?)
+                        }
+                        (None, None) if key_file.is_none() => {
+                            prompted = prompt_password(false)?;
+                            encryptor(Some(prompted.as_str()), key_file, Kdf::default())
+                        }
+                        (password, None) => {
+                            encryptor(password.as_deref(), key_file, Kdf::default())
+                        }
+                    }
+                    .with_aad(aad);
+                    let io = IoOptions { quiet: true, ..*io };
+                    let process = |input: &str, output: &str| {
+                        let keys = |head: &[u8]| {
+                            check_key_source(head, &keys)?;
+                            Ok(keys.clone())
+                        };
+                        let restore = metadata::Restore {
+                            naming: match manifest {
+                                Some(_) => metadata::Naming::Output,
+                                None => metadata::Naming::Within(Path::new(output_path)),
+                            },
+                            staging: paths.all_or_nothing.then_some(&paths.staging),
+                            ..restore
+                        };
+                        decrypt_file(input, output, keys, *format, restore, io)
+                    };
+                    match &manifest {
+                        Some(entries) => {
+                            process_files(manifest::tasks(entries)?, paths, limits, process)?;
+                            report_manifest(input_path, entries.len());
+                        }
+                        None => process_dir(
+                            Path::new(input_path),
+                            Path::new(output_path),
+                            decrypted_name,
+                            paths,
+                            limits,
+                            process,
+                        )?,
+                    }
+                }
+                (Format::Native | Format::Mime, password, None) if !*token => {
+                    let mut prompted = Zeroizing::default();
+                    let password = password.as_deref();
+                    match *extract {
+                        true => extract_file(
+                            input_path,
+                            output_path,
+                            |head| {
+                                unlock(head, password, key_file, &mut prompted).map(|keys| {
+                                    keys.with_aad(aad.clone()).with_threads(paths.jobs())
+                                })
+                            },
+                            paths.force,
+                            *io,
+                        )?,
+                        false if *best_effort => salvage_file(
+                            input_path,
+                            output_path,
+                            |head| {
+                                unlock(head, password, key_file, &mut prompted)
+                                    .map(|keys| keys.with_aad(aad.clone()))
+                            },
+                            restore,
+                            *io,
+                        )?,
+                        false => decrypt_file(
+                            input_path,
+                            output_path,
+                            |head| {
+                                unlock(head, password, key_file, &mut prompted).map(|keys| {
+                                    keys.with_aad(aad.clone()).with_threads(paths.jobs())
+                                })
+                            },
+                            *format,
+                            restore,
+                            *io,
+                        )?,
+                    }
+                }
+                (Format::Native | Format::Mime, _, identity) => {
+                    let recipient = match identity {
+                        Some(identity) => {
+                            Encryptor::from_identity(read_identity(Path::new(identity))?)
+                        }
+                        None => Encryptor::from_token(token::agreement()),
+                    }
+                    .with_aad(aad)
+                    .with_threads(paths.jobs());
+                    let keys = |head: &[u8]| check_key_source(head, &recipient).map(|()| recipient);
+                    match *extract {
+                        true => extract_file(input_path, output_path, keys, paths.force, *io)?,
+                        false if *best_effort => {
+                            salvage_file(input_path, output_path, keys, restore, *io)?
+                        }
+                        false => {
+                            decrypt_file(input_path, output_path, keys, *format, restore, *io)?
+                        }
+                    }
+                }
+                (Format::Secretstream, None, None) if !*token => {
+                    let key = key_file.ok_or_else(|| anyhow!(t!("secretstream-key-file")))?;
+                    secretstream_decrypt_file(input_path, output_path, &key, *io)?
+                }
+                (Format::Secretstream, ..) => return Err(anyhow!(t!("secretstream-key-file"))),
+                (Format::Saltpack, _, Some(identity)) => {
+                    saltpack_decrypt_file(input_path, output_path, identity, *io)?
+                }
+                (Format::Saltpack, ..) => {
+                    return Err(anyhow!(t!("saltpack-needs-identity")))
+                }
+                _ if *token || key_file.is_some() => {
+                    return Err(anyhow!(t!("age-needs-identity")))
+                }
+                (Format::Age, _, Some(identity)) => {
+                    let identity = read_identity(Path::new(identity))?;
+                    age_decrypt_file(input_path, output_path, age::Key::Identity(&identity), *io)?
+                }
+                (Format::Age, password, None) => {
+                    let prompted;
+                    let password = match password.as_deref() {
+                        Some(password) => password,
+                        None => {
+                            prompted = prompt_password(false)?;
+                            prompted.as_str()
+                        }
+                    };
+                    age_decrypt_file(input_path, output_path, age::Key::Password(password), *io)?
+                }
+            }
+        }
+        Commands::Keygen {
+            output_path,
+            x25519,
+            ed25519,
+            no_passphrase,
+            expires_in,
+        } => keygen(output_path, *x25519, *ed25519, *no_passphrase, *expires_in)?,
+        Commands::EncryptText { options } => text::encrypt(options)?,
+        Commands::DecryptText { options } => text::decrypt(options)?,
+        Commands::Verify { options } => verify::run(options)?,
+        Commands::VerifyManifest { options } => signed_manifest::run(options)?,
+        Commands::Rekey { options } => rekey::run(options)?,
+        Commands::Rotate { options } => rotate::run(options)?,
+        Commands::Watch { options } => watch::run(options)?,
+        Commands::Service { command } => service::run(command)?,
+        Commands::Shred { options } => shred::run(options)?,
+        Commands::Key { command } => key::run(command)?,
+        Commands::Note { command } => notes::run(command)?,
+        Commands::Vault { command } => vault::run(command)?,
+        #[cfg(feature = "grpc")]
+        Commands::Serve { options, .. } => grpc::serve(options)?,
+        Commands::Algorithms => algorithms::print_report(),
+        Commands::Doctor => doctor::run()?,
+        Commands::SelfTest => self_test::run()?,
+        Commands::Completions { shell } => completions::print(*shell, &mut Cli::command()),
+        Commands::Bench { options } => bench::run(options)?,
+        Commands::Inspect {
+            input_path,
+            with_password,
+        } => inspect::run(input_path, *with_password)?,
+        Commands::Check { input_path, .. } => check_file(input_path)?,
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Parser)]
+    struct Batch {
+        #[command(flatten)]
+        paths: Paths,
+    }
+
+    fn paths(args: &[&str]) -> Paths {
+        Batch::parse_from(["file-encryptor"].iter().chain(args)).paths
+    }
+
+    /// The files `a`, `b` and `c` under `dir/in`, each to be written into a
+    /// directory under `dir/out` that doesn't exist yet.
+    fn tasks(dir: &Path) -> Vec<(String, String, u64)> {
+        fs::create_dir(dir.join("in")).unwrap();
+        ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                let input = dir.join("in").join(name);
+                fs::write(&input, name).unwrap();
+                let output = dir.join("out").join("sub").join(format!("{name}.enc"));
+                let [input, output] = [input, output].map(|path| path.display().to_string());
+                (input, output, 1)
+            })
+            .collect()
+    }
+
+    /// Writes the output, and then fails on `b`, as a file that breaks off
+    /// midway does.
+    fn failing_on_b(input: &str, output: &str) -> Result<()> {
+        fs::write(output, "partial")?;
+        match input.ends_with('b') {
+            true => bail!("read error"),
+            false => Ok(()),
+        }
+    }
+
+    #[test]
+    fn all_or_nothing_leaves_no_output_when_a_file_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = tasks(dir.path());
+        let input = dir.path().join("in").display().to_string();
+        let paths = paths(&[&input, "--all-or-nothing", "--jobs", "1"]);
+        let result = process_files(tasks, &paths, &Limits::default(), failing_on_b);
+        assert!(result.is_err());
+        assert!(!dir.path().join("out").exists());
+
+        let tasks: Vec<_> = fs::read_dir(dir.path().join("in"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path().display().to_string())
+            .filter(|input| !input.ends_with('b'))
+            .map(|input| (input.clone(), format!("{input}.enc"), 1))
+            .collect();
+        process_files(tasks, &paths, &Limits::default(), failing_on_b).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path().join("in"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a", "a.enc", "b", "c", "c.enc"]);
+    }
+
+    #[test]
+    fn keep_going_processes_the_rest_and_lists_the_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = tasks(dir.path());
+        // A directory can't be read as a file, even by root.
+        fs::remove_file(&tasks[1].0).unwrap();
+        fs::create_dir(&tasks[1].0).unwrap();
+        let input = dir.path().join("in").display().to_string();
+        let paths = paths(&[&input, "--keep-going", "--jobs", "1"]);
+        let copy = |input: &str, output: &str| {
+            let data = fs::read(input).with_context(|| format!("Could not read {input}"))?;
+            Ok(fs::write(output, data)?)
+        };
+        let result = process_files(tasks.clone(), &paths, &Limits::default(), copy);
+        for (_, output, _) in [&tasks[0], &tasks[2]] {
+            assert!(Path::new(output).is_file(), "{output}");
+        }
+        assert!(!Path::new(&tasks[1].1).exists());
+        let message = format!("{:#}", result.as_ref().unwrap_err());
+        assert!(message.contains(&tasks[1].0), "{message}");
+        // Some files done, and otherwise the read error's own status.
+        assert_eq!(report::exit_status(&result, true, 2), 7);
+        assert_eq!(report::exit_status(&result, true, 0), 6);
+    }
+
+    #[test]
+    fn protected_identity_opens_only_with_its_passphrase() {
+        let validity = key_file::Validity::starting_now(None);
+        let (identity, public_key) = recipient::generate(&validity).unwrap();
+        let protected = Zeroizing::new(protect_identity(identity.as_bytes(), "pass").unwrap());
+        assert!(protected.starts_with(header::MAGIC));
+        let path = Path::new("id.key");
+        let passphrase = |passphrase: &str| {
+            let passphrase = Zeroizing::new(passphrase.to_owned());
+            move || Ok(passphrase)
+        };
+
+        let secret = open_identity(path, protected.clone(), passphrase("pass")).unwrap();
+        let plain = Zeroizing::new(identity.into_bytes());
+        let unprotected = open_identity(path, plain, || panic!("not protected")).unwrap();
+        assert_eq!(secret.public_key(), unprotected.public_key());
+        assert!(public_key.contains(&hex::encode(secret.public_key().as_bytes())));
+
+        let wrong = open_identity(path, protected, passphrase("wrong")).unwrap_err();
+        assert!(matches!(
+            wrong.downcast_ref::<DecryptError>(),
+            Some(DecryptError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn sealed_boxes_open_with_crypto_box_seal_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let [input, output] = ["token.json", "token.sealed"].map(|name| dir.path().join(name));
+        fs::write(&input, "hello").unwrap();
+        let secret = crypto_box::SecretKey::from([1; crypto_box::KEY_SIZE]);
+        let public_key = base64::engine::general_purpose::STANDARD.encode(secret.public_key());
+        let [input, output] = [&input, &output].map(|path| path.display().to_string());
+        seal_file(&input, &output, &public_key, None, IoOptions::default()).unwrap();
+
+        let mut sealed = fs::read(&output).unwrap();
+        assert_eq!(sealed.len(), crypto_box::SEALBYTES + 5);
+        assert_eq!(secret.unseal(&sealed).unwrap(), b"hello");
+        let other = crypto_box::SecretKey::from([2; crypto_box::KEY_SIZE]);
+        assert!(other.unseal(&sealed).is_err());
+        // Sealed to the same key by libsodium's own crypto_box_seal.
+        let by_libsodium = hex::decode(concat!(
+            "9f18f248eb1b8a075d219376c514cdc692a3bd357932e131543317bf25b6c808",
+            "98080342de1f59d855ccc62215ce38ffccb83833f6",
+        ));
+        assert_eq!(secret.unseal(&by_libsodium.unwrap()).unwrap(), b"hello");
+        sealed[crypto_box::SEALBYTES] ^= 1;
+        assert!(secret.unseal(&sealed).is_err());
+
+        let hex = hex::encode(secret.public_key().as_bytes());
+        assert_eq!(parse_public_key(&hex).unwrap(), secret.public_key());
+        assert!(parse_public_key(&hex[2..]).is_err());
+    }
+}
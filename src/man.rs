@@ -0,0 +1,70 @@
+//! `--generate-man DIR`: manual pages in roff, one for the command and one
+//! for each subcommand at any depth, written by `clap_mangen` from the command
+//! line definition like the completion scripts so packagers can ship both
+//! from the binary:
+//!
+//! ```text
+//! DIR/file-encryptor.1
+//! DIR/file-encryptor-encrypt.1
+//! DIR/file-encryptor-vault-get.1
+//! ```
+
+use anyhow::{Context, Result};
+use clap::Command;
+use clap_mangen::Man;
+use std::{fs, path::Path};
+
+use file_encryptor::i18n::t;
+
+/// Writes the pages of `command` and its visible subcommands to `dir`,
+/// creating it, and returns how many there are.
+pub fn write_pages(command: Command, dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir)
+        .with_context(|| t!("dir-create-failed", path = dir.display().to_string()))?;
+    let mut command = command.disable_help_subcommand(true);
+    command.build();
+    let mut pending = vec![command];
+    let mut count = 0;
+    while let Some(command) = pending.pop() {
+        pending.extend(
+            command
+                .get_subcommands()
+                .filter(|sub| !sub.is_hide_set())
+                .cloned(),
+        );
+        Man::new(command)
+            .generate_to(dir)
+            .with_context(|| t!("file-unwritable", path = dir.display().to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn every_subcommand_has_a_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let count = write_pages(crate::Cli::command(), dir.path()).unwrap();
+        let names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names.len(), count);
+        for name in [
+            "file-encryptor.1",
+            "file-encryptor-encrypt.1",
+            "file-encryptor-vault-get.1",
+        ] {
+            assert!(names.iter().any(|page| page == name), "{name}");
+            let page = fs::read_to_string(dir.path().join(name)).unwrap();
+            for section in ["NAME", "SYNOPSIS"] {
+                assert!(page.contains(&format!("\n.SH {section}\n")), "{name}");
+            }
+        }
+        assert!(!names.iter().any(|page| page.ends_with("-help.1")));
+    }
+}
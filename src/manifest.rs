@@ -0,0 +1,142 @@
+//! `--manifest`: many files in one run, listed in a TOML file of `[[file]]`
+//! tables:
+//!
+//! ```toml
+//! [[file]]
+//! input = "reports/q1.pdf"
+//! output = "encrypted/q1.pdf.enc"
+//!
+//! [[file]]
+//! input = "db.sqlite"
+//! compress = "gzip"
+//! keep_metadata = true
+//! ```
+//!
+//! Paths are relative to the current directory, like those on the command
+//! line, and an output left out is derived from the input. When encrypting,
+//! `cipher`, `compress` and `keep_metadata` override the command line's for
+//! their file. Every file is encrypted or decrypted with one key source and
+//! one session, so the key derivation runs once for the whole list, and the
+//! files are processed like a directory's.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use std::{collections::HashSet, fs, path::Path};
+
+use file_encryptor::i18n::t;
+
+use crate::{config, derived_output, CipherName, CompressionName, STDIO};
+
+#[derive(Default)]
+pub struct Entry {
+    pub input: String,
+    pub output: String,
+    pub cipher: Option<CipherName>,
+    pub compress: Option<CompressionName>,
+    pub keep_metadata: bool,
+}
+
+impl Entry {
+    /// Whether the entry sets any of the options only encrypting has.
+    pub fn has_options(&self) -> bool {
+        self.cipher.is_some() || self.compress.is_some() || self.keep_metadata
+    }
+}
+
+/// The entries of the manifest at `path`, with the outputs left out derived
+/// with `default_output`, in `output_dir` if there is one.
+pub fn read(
+    path: &str,
+    default_output: fn(&str) -> Result<String>,
+    output_dir: Option<&Path>,
+) -> Result<Vec<Entry>> {
+    let text =
+        fs::read_to_string(path).with_context(|| t!("manifest-file-unreadable", path = path))?;
+    // The entries with their outputs, if given, and the lines they start on.
+    let mut entries: Vec<(Entry, Option<String>, usize)> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let at = |message: String| anyhow!("{path}:{}: {message}", number + 1);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            let table = line.split('#').next().unwrap_or_default().trim_end();
+            if table != "[[file]]" {
+                return Err(at(t!("manifest-unknown-table", table = table)));
+            }
+            entries.push((Entry::default(), None, number + 1));
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at(t!("config-expected-key-value")))?;
+        let key = key.trim();
+        let value = config::value_of(value.trim()).map_err(at)?;
+        let invalid = |e: String| at(format!("{key}: {e}"));
+        let Some((entry, output, _)) = entries.last_mut() else {
+            return Err(at(t!("manifest-key-outside-table", key = key)));
+        };
+        match key {
+            "input" => entry.input = value.to_owned(),
+            "output" => *output = Some(value.to_owned()),
+            "cipher" => entry.cipher = Some(CipherName::from_str(value, true).map_err(invalid)?),
+            "compress" => {
+                entry.compress = Some(CompressionName::from_str(value, true).map_err(invalid)?)
+            }
+            "keep_metadata" => {
+                entry.keep_metadata = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(invalid(t!("config-not-bool", value = value))),
+                }
+            }
+            _ => return Err(at(t!("config-unknown-key", key = key))),
+        }
+    }
+
+    if entries.is_empty() {
+        bail!(t!("manifest-empty", path = path));
+    }
+    let mut outputs = HashSet::new();
+    entries
+        .into_iter()
+        .map(|(mut entry, output, line)| {
+            let at = |message: String| anyhow!("{path}:{line}: {message}");
+            if entry.input.is_empty() {
+                return Err(at(t!("manifest-entry-no-input")));
+            }
+            if entry.input == STDIO || output.as_deref() == Some(STDIO) {
+                return Err(at(t!("manifest-entry-stdio")));
+            }
+            entry.output = match output {
+                Some(output) => output,
+                None => derived_output(&entry.input, default_output, output_dir)
+                    .map_err(|e| at(format!("{e:#}")))?,
+            };
+            if !outputs.insert(entry.output.clone()) {
+                return Err(at(t!(
+                    "manifest-output-twice",
+                    path = entry.output.as_str()
+                )));
+            }
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// The `(input, output, input size)` of each of `entries`, for
+/// [`process_files`](crate::process_files).
+pub fn tasks(entries: &[Entry]) -> Result<Vec<(String, String, u64)>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let metadata = fs::metadata(&entry.input)
+                .with_context(|| t!("file-read-failed", path = entry.input.as_str()))?;
+            if !metadata.is_file() {
+                bail!(t!("manifest-input-not-file", path = entry.input.as_str()));
+            }
+            Ok((entry.input.clone(), entry.output.clone(), metadata.len()))
+        })
+        .collect()
+}
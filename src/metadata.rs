@@ -0,0 +1,135 @@
+//! `encrypt --keep-metadata` and `decrypt --restore-metadata`: the name,
+//! permissions and modification time of a file, which its header records
+//! sealed under its key (see [`Metadata`]), so that decrypting can bring them
+//! back. The name of a file in a directory being encrypted is its path within
+//! the directory, which is how `encrypt --encrypt-names` can give outputs
+//! random names and leave `decrypt` to put each file back in its place.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    fs,
+    path::{self, Path, PathBuf},
+};
+
+use file_encryptor::{i18n::t, Metadata};
+
+use crate::{archive, log, report, staging::Staging};
+
+/// What encrypting the file at `path` records with `--keep-metadata`, naming
+/// it by its path within `root`: the directory being encrypted, or the
+/// file's own directory.
+pub fn read(path: &Path, root: &Path) -> Result<Metadata> {
+    let metadata = fs::metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    let name = path
+        .strip_prefix(root)
+        .ok()
+        .and_then(Path::to_str)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            anyhow!(t!(
+                "metadata-name-not-utf8",
+                path = path.display().to_string()
+            ))
+        })?;
+    Ok(Metadata {
+        name: name.replace(path::MAIN_SEPARATOR, "/"),
+        mode: archive::mode(&metadata),
+        modified: archive::modified_since_epoch(&metadata),
+    })
+}
+
+/// Where decrypting writes a file that recorded its name.
+#[derive(Clone, Copy, Default)]
+pub enum Naming<'a> {
+    /// Where the output was given, or for a file that records no name,
+    /// derived from the input's name.
+    #[default]
+    Output,
+    /// Next to the input, under the last part of the recorded name, as no
+    /// output was given.
+    NextToInput,
+    /// At the recorded path within this directory, which a directory is
+    /// decrypted into.
+    Within(&'a Path),
+}
+
+/// What decrypting does with the metadata a file recorded.
+#[derive(Clone, Copy, Default)]
+pub struct Restore<'a> {
+    pub naming: Naming<'a>,
+    /// Overwrite an existing file of that name.
+    pub force: bool,
+    /// Give the output the original permissions and modification time.
+    pub metadata: bool,
+    /// With [`Naming::Within`], where an `--all-or-nothing` run stages the
+    /// file under its recorded name.
+    pub staging: Option<&'a Staging>,
+}
+
+impl Restore<'_> {
+    /// Where the plaintext of `input_path` goes: `output_path`, or as
+    /// [`Restore::naming`] says, where the name `metadata` records puts it.
+    /// A name that would lead out of the directory is refused.
+    pub fn output_path(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        metadata: Option<&Metadata>,
+    ) -> Result<PathBuf> {
+        let Some(metadata) = metadata else {
+            return Ok(output_path.to_owned());
+        };
+        let path = match self.naming {
+            Naming::Output => return Ok(output_path.to_owned()),
+            Naming::NextToInput => {
+                let relative = archive::checked_path(&metadata.name)?;
+                let name = relative.file_name().expect("a checked path has a name");
+                input_path.with_file_name(name)
+            }
+            Naming::Within(dir) => dir.join(archive::checked_path(&metadata.name)?),
+        };
+        let existing = fs::metadata(&path).is_ok_and(|m| m.is_file());
+        if existing && !self.force {
+            bail!(t!("output-exists", path = path.display().to_string()));
+        }
+        let path = match (self.naming, self.staging) {
+            (Naming::Within(_), Some(staging)) => staging.stage(&path),
+            _ => path,
+        };
+        match self.naming {
+            Naming::Within(_) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        t!("dir-create-failed", path = parent.display().to_string())
+                    })?;
+                }
+            }
+            _ => report::target(&input_path.to_string_lossy(), Some(&path.to_string_lossy()))?,
+        }
+        Ok(path)
+    }
+
+    /// With [`Restore::metadata`], gives the file at `path` the permissions
+    /// and modification time `metadata` records.
+    pub fn apply(&self, path: &Path, metadata: Option<&Metadata>) -> Result<()> {
+        if !self.metadata {
+            return Ok(());
+        }
+        let Some(metadata) = metadata else {
+            log::warn(&t!("metadata-none", path = path.display().to_string()));
+            return Ok(());
+        };
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(archive::from_epoch(metadata.modified)))
+            .with_context(|| t!("time-restore-failed", path = path.display().to_string()))?;
+        fs::set_permissions(path, archive::permissions(path, metadata.mode)?).with_context(|| {
+            t!(
+                "permissions-restore-failed",
+                path = path.display().to_string()
+            )
+        })
+    }
+}
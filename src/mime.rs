@@ -0,0 +1,94 @@
+//! `--format mime`: the native format as a base64 MIME part, ready to paste
+//! into a message or attach, and the reverse.
+//!
+//! Decoding looks for our content type, so it accepts the bare part as
+//! written as well as a whole saved email (`.eml`) that carries it as one
+//! part of a multipart message.
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+
+use file_encryptor::i18n::t;
+
+pub const CONTENT_TYPE: &str = "application/x-file-encryptor";
+/// RFC 2045 caps base64 lines at 76 characters.
+pub const MAX_LINE_LENGTH: usize = 76;
+
+/// Wraps `data` as a MIME attachment named `file_name`, with base64 lines of
+/// at most `line_length` characters.
+pub fn wrap(data: &[u8], file_name: &str, line_length: usize) -> String {
+    let name = quoted(file_name);
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let mut out = format!(
+        "MIME-Version: 1.0\r\n\
+         Content-Type: {CONTENT_TYPE}; name={name}\r\n\
+         Content-Disposition: attachment; filename={name}\r\n\
+         Content-Transfer-Encoding: base64\r\n\r\n"
+    );
+    // base64 output is ASCII, so byte chunks are character chunks.
+    for line in encoded.as_bytes().chunks(line_length.max(1)) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Whether `text` has a part with our content type, as [`unwrap`] looks for.
+pub fn is_mime(text: &str) -> bool {
+    text.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .any(is_our_content_type)
+}
+
+fn is_our_content_type(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    line.strip_prefix("content-type:")
+        .is_some_and(|value| value.trim_start().starts_with(CONTENT_TYPE))
+}
+
+/// Extracts and decodes the first part with our content type.
+pub fn unwrap(text: &str) -> Result<Vec<u8>> {
+    let not_found = || anyhow!(t!("mime-no-part", content_type = CONTENT_TYPE));
+    let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+    lines
+        .by_ref()
+        .find(|line| is_our_content_type(line))
+        .ok_or_else(not_found)?;
+    // Skip the rest of the part's headers, then read up to the blank line or
+    // multipart boundary that ends the body.
+    lines
+        .by_ref()
+        .find(|line| line.is_empty())
+        .ok_or_else(not_found)?;
+    let encoded: String = lines
+        .take_while(|line| !line.is_empty() && !line.starts_with("--"))
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| {
+            anyhow!(t!(
+                "mime-not-base64",
+                content_type = CONTENT_TYPE,
+                error = e.to_string()
+            ))
+        })
+}
+
+/// Quotes a header parameter, replacing anything outside printable ASCII
+/// (which would need RFC 2231 encoding) with `_`.
+fn quoted(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => out.push('_'),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,260 @@
+//! Reading large local files by mapping them into memory instead of copying
+//! them through `read`. The chunked encryptor and decryptor then take their
+//! chunks straight from the page cache, and a whole-file encryption doesn't
+//! hold a second copy of the file in a growing buffer.
+//!
+//! Only regular files of at least [`MIN_LENGTH`] bytes on a local file
+//! system are mapped; anything else, or a mapping the system refuses, is
+//! read as before. A mapped file that another program truncates while it is
+//! being read can crash the process (SIGBUS), which `--no-mmap` avoids.
+
+use std::{fs, io, ops::Deref, path::Path};
+
+/// Below this, the cost of setting up a mapping outweighs the copy it saves.
+pub const MIN_LENGTH: u64 = 4 << 20;
+
+/// A read-only mapping of a whole file.
+pub struct Map {
+    ptr: *const u8,
+    len: usize,
+    #[cfg(windows)]
+    mapping: windows::Handle,
+}
+
+// The mapping is read-only and owned by the `Map`.
+unsafe impl Send for Map {}
+unsafe impl Sync for Map {}
+
+impl Deref for Map {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl AsRef<[u8]> for Map {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Maps the file at `path` if it is worth it and the system allows it, and
+/// returns `None` to have it read instead.
+pub fn open(path: &Path) -> Option<Map> {
+    let file = fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() < MIN_LENGTH || !is_local(path) {
+        return None;
+    }
+    let len = usize::try_from(metadata.len()).ok()?;
+    match map(&file, len) {
+        Ok(map) => {
+            crate::log::debug(&format!("mapped {} into memory", path.display()));
+            Some(map)
+        }
+        Err(e) => {
+            crate::log::debug(&format!(
+                "reading {}, as it can't be mapped: {e}",
+                path.display()
+            ));
+            None
+        }
+    }
+}
+
+/// Whether `path` is on a local file system. Files on network shares can
+/// change or vanish under a mapping, so those are read.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_local(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    const NETWORK: [u32; 7] = [
+        0x6969,      // NFS
+        0x517B,      // SMB
+        0xFE53_4D42, // SMB2
+        0xFF53_4D42, // CIFS
+        0x6573_5546, // FUSE, such as sshfs
+        0x0102_1997, // 9P
+        0x7346_1313, // AFS
+    ];
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    // The field's width differs between platforms; the magic numbers are 32-bit.
+    #[allow(clippy::unnecessary_cast)]
+    let kind = stat.f_type as u32;
+    !NETWORK.contains(&kind)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn is_local(_path: &Path) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn map(file: &fs::File, len: usize) -> io::Result<Map> {
+    use std::os::unix::io::AsRawFd;
+
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    // Chunks are read front to back; a failed hint changes nothing.
+    unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+    Ok(Map {
+        ptr: ptr.cast(),
+        len,
+    })
+}
+
+#[cfg(windows)]
+fn map(file: &fs::File, len: usize) -> io::Result<Map> {
+    use std::os::windows::io::AsRawHandle;
+
+    let mapping = unsafe {
+        windows::CreateFileMappingW(
+            file.as_raw_handle(),
+            std::ptr::null_mut(),
+            windows::PAGE_READONLY,
+            0,
+            0,
+            std::ptr::null(),
+        )
+    };
+    if mapping.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let ptr = unsafe { windows::MapViewOfFile(mapping, windows::FILE_MAP_READ, 0, 0, 0) };
+    if ptr.is_null() {
+        let error = io::Error::last_os_error();
+        unsafe { windows::CloseHandle(mapping) };
+        return Err(error);
+    }
+    Ok(Map {
+        ptr: ptr.cast(),
+        len,
+        mapping,
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn map(_file: &fs::File, _len: usize) -> io::Result<Map> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "memory mapping is not supported on this platform",
+    ))
+}
+
+impl Drop for Map {
+    #[cfg(unix)]
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut _, self.len) };
+    }
+
+    #[cfg(windows)]
+    fn drop(&mut self) {
+        unsafe {
+            windows::UnmapViewOfFile(self.ptr.cast());
+            windows::CloseHandle(self.mapping);
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn drop(&mut self) {}
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const PAGE_READONLY: u32 = 0x02;
+    pub const FILE_MAP_READ: u32 = 0x04;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn CreateFileMappingW(
+            file: Handle,
+            attributes: *mut c_void,
+            protect: u32,
+            size_high: u32,
+            size_low: u32,
+            name: *const u16,
+        ) -> Handle;
+        pub fn MapViewOfFile(
+            mapping: Handle,
+            access: u32,
+            offset_high: u32,
+            offset_low: u32,
+            length: usize,
+        ) -> *mut c_void;
+        pub fn UnmapViewOfFile(address: *const c_void) -> i32;
+        pub fn CloseHandle(handle: Handle) -> i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_encryptor::{DecryptError, Encryptor, Kdf};
+
+    #[test]
+    fn large_files_are_mapped_and_others_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large");
+        let contents: Vec<u8> = (0..MIN_LENGTH + 3).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &contents).unwrap();
+        assert!(open(&path).is_some_and(|map| *map == contents[..]));
+
+        let small = dir.path().join("small");
+        fs::write(&small, &contents[..MIN_LENGTH as usize - 1]).unwrap();
+        assert!(open(&small).is_none());
+        assert!(open(dir.path()).is_none());
+        assert!(open(&dir.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn mapped_input_encrypts_and_decrypts_as_read_input_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let [plain, encrypted] = ["plain", "plain.enc"].map(|name| dir.path().join(name));
+        let contents: Vec<u8> = (0..MIN_LENGTH + 3).map(|i| (i % 251) as u8).collect();
+        fs::write(&plain, &contents).unwrap();
+        let mut keys = Encryptor::with_kdf("pw", Kdf::Pbkdf2 { iterations: 1 });
+        let mut file = Vec::new();
+        keys.encrypt_reader_to_writer(&*open(&plain).unwrap(), &mut file)
+            .unwrap();
+        fs::write(&encrypted, &file).unwrap();
+
+        let mapped = open(&encrypted).unwrap();
+        let mut decrypted = Vec::new();
+        keys.decrypt_reader_to_writer(&*mapped, &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, contents);
+        let wrong = Encryptor::new("wrong")
+            .decrypt_reader_to_writer(&*mapped, io::sink())
+            .unwrap_err();
+        assert_eq!(wrong.downcast_ref(), Some(&DecryptError::WrongKey));
+
+        file[MIN_LENGTH as usize / 2] ^= 1;
+        fs::write(&encrypted, &file).unwrap();
+        let damaged = open(&encrypted).unwrap();
+        assert!(keys
+            .decrypt_reader_to_writer(&*damaged, io::sink())
+            .is_err());
+    }
+}
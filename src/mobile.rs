@@ -0,0 +1,136 @@
+//! The buffer and stream functions of [`crate::ffi`] for Kotlin and Swift,
+//! exported through uniffi. `uniffi-bindgen` generates the bindings from the
+//! built library, so they can't drift from what it exports; see
+//! `bindings/README.md`.
+//!
+//! Failures come out as a [`FileEncryptorError`], which the bindings raise as
+//! an exception (Kotlin) or throw as an error (Swift).
+
+use std::{fmt, sync::Mutex};
+
+use crate::{ffi::FeStream, i18n::t, DecryptError, Encryptor};
+
+/// Why a call failed, with the message the command line would print.
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum FileEncryptorError {
+    /// The password isn't the file's.
+    WrongKey(String),
+    /// The file was damaged after it was encrypted.
+    Corrupted(String),
+    /// Anything else, such as a stream used after it failed.
+    Failed(String),
+}
+
+impl From<anyhow::Error> for FileEncryptorError {
+    fn from(e: anyhow::Error) -> Self {
+        let message = format!("{e:#}");
+        match e.downcast_ref::<DecryptError>() {
+            Some(DecryptError::WrongKey) => FileEncryptorError::WrongKey(message),
+            Some(e) if e.is_damage() => FileEncryptorError::Corrupted(message),
+            _ => FileEncryptorError::Failed(message),
+        }
+    }
+}
+
+impl fmt::Display for FileEncryptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileEncryptorError::WrongKey(message)
+            | FileEncryptorError::Corrupted(message)
+            | FileEncryptorError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for FileEncryptorError {}
+
+/// Encrypts `plaintext` with `password` into a single-message file, as
+/// `encrypt` writes.
+#[uniffi::export]
+pub fn encrypt(password: String, plaintext: Vec<u8>) -> Result<Vec<u8>, FileEncryptorError> {
+    Ok(Encryptor::new(&password).encrypt(&plaintext)?)
+}
+
+/// Decrypts a file in either format with `password`.
+#[uniffi::export]
+pub fn decrypt(password: String, file: Vec<u8>) -> Result<Vec<u8>, FileEncryptorError> {
+    Ok(Encryptor::new(&password).decrypt(&file)?)
+}
+
+/// An encryption or decryption in the chunked format of `encrypt --stream`,
+/// for input too large to hold in memory. Push each piece with
+/// [`ChunkStream::update`] and end with [`ChunkStream::finish`].
+#[derive(uniffi::Object)]
+pub struct ChunkStream {
+    /// Gone once the stream is finished.
+    stream: Mutex<Option<FeStream>>,
+}
+
+#[uniffi::export]
+impl ChunkStream {
+    /// Starts encrypting with `password`.
+    #[uniffi::constructor]
+    pub fn encrypting(password: String) -> Self {
+        ChunkStream::new(FeStream::encrypting(&password))
+    }
+
+    /// Starts decrypting a chunked file with `password`. Single-message files
+    /// go to [`decrypt`].
+    #[uniffi::constructor]
+    pub fn decrypting(password: String) -> Self {
+        ChunkStream::new(FeStream::decrypting(&password))
+    }
+
+    /// Pushes the next piece of input, returning the output that is ready so
+    /// far, which may be less than was pushed or nothing.
+    pub fn update(&self, input: Vec<u8>) -> Result<Vec<u8>, FileEncryptorError> {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let stream = stream.as_mut().ok_or_else(finished)?;
+        Ok(stream.push(input)?)
+    }
+
+    /// Ends the input, returning the rest of the output. Decryption fails here
+    /// if the file was cut short or doesn't match its checksums.
+    pub fn finish(&self) -> Result<Vec<u8>, FileEncryptorError> {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stream = stream.take().ok_or_else(finished)?;
+        Ok(stream.end()?)
+    }
+}
+
+impl ChunkStream {
+    fn new(stream: FeStream) -> Self {
+        ChunkStream {
+            stream: Mutex::new(Some(stream)),
+        }
+    }
+}
+
+fn finished() -> FileEncryptorError {
+    FileEncryptorError::Failed(t!("ffi-stream-finished"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_and_streams_round_trip() {
+        let file = encrypt("pw".into(), b"hello".to_vec()).unwrap();
+        assert_eq!(decrypt("pw".into(), file.clone()).unwrap(), b"hello");
+        assert!(matches!(
+            decrypt("wrong".into(), file),
+            Err(FileEncryptorError::WrongKey(_))
+        ));
+
+        let encrypting = ChunkStream::encrypting("pw".into());
+        let mut chunked = encrypting.update(b"hello".to_vec()).unwrap();
+        chunked.extend(encrypting.finish().unwrap());
+        assert!(encrypting.finish().is_err());
+        let decrypting = ChunkStream::decrypting("pw".into());
+        let mut plaintext = decrypting.update(chunked).unwrap();
+        plaintext.extend(decrypting.finish().unwrap());
+        assert_eq!(plaintext, b"hello");
+    }
+}
@@ -0,0 +1,108 @@
+//! `note`: a private scratchpad kept in a single encrypted container.
+
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::io::{self, IsTerminal, Read};
+
+use crate::{
+    container::{self, ContainerArgs},
+    i18n::t,
+};
+
+const NOTES_FILE: &str = "notes.enc";
+
+#[derive(Subcommand)]
+pub enum NoteCommand {
+    /// Add a note; without --body, the text is read from standard input
+    Add {
+        title: String,
+        #[arg(long)]
+        body: Option<String>,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// Print a note, chosen by its number in `note list` or its title
+    Show {
+        note: String,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// List the titles of all notes
+    List {
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Notes {
+    notes: Vec<Note>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Note {
+    title: String,
+    body: String,
+}
+
+pub fn run(command: &NoteCommand) -> Result<()> {
+    match command {
+        NoteCommand::Add {
+            title,
+            body,
+            container,
+        } => {
+            let path = container.path(NOTES_FILE)?;
+            // Unlock before reading the body so a wrong password fails fast.
+            let password = container.password(&path)?;
+            let (mut notes, generation): (Notes, _) = container::open(&path, container, &password)?;
+            let body = match body {
+                Some(body) => body.clone(),
+                None => read_body()?,
+            };
+            notes.notes.push(Note {
+                title: title.clone(),
+                body,
+            });
+            container::save(&path, container, &password, &notes, generation)?;
+            println!(
+                "{}",
+                t!(
+                    "note-added",
+                    number = notes.notes.len(),
+                    title = title.as_str()
+                )
+            );
+        }
+        NoteCommand::Show { note, container } => {
+            let path = container.path(NOTES_FILE)?;
+            let notes: Notes = container::load(&path, container, &container.password(&path)?)?;
+            let found = note
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| notes.notes.get(number.checked_sub(1)?))
+                .or_else(|| notes.notes.iter().find(|n| n.title == *note))
+                .ok_or_else(|| anyhow!(t!("note-missing", note = format!("{note:?}"))))?;
+            println!("# {}\n\n{}", found.title, found.body.trim_end());
+        }
+        NoteCommand::List { container } => {
+            let path = container.path(NOTES_FILE)?;
+            let notes: Notes = container::load(&path, container, &container.password(&path)?)?;
+            for (index, note) in notes.notes.iter().enumerate() {
+                println!("{:>4}  {}", index + 1, note.title);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_body() -> Result<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        eprintln!("{}", t!("note-body-prompt"));
+    }
+    let mut body = String::new();
+    stdin.lock().read_to_string(&mut body)?;
+    Ok(body)
+}
@@ -0,0 +1,93 @@
+//! Encryption to OpenPGP certificates, so recipients who only run gpg can
+//! decrypt our output.
+
+use anyhow::{anyhow, Context, Result};
+use pgp::{
+    composed::{Deserializable, MessageBuilder, SignedPublicKey, SignedPublicSubKey},
+    crypto::sym::SymmetricKeyAlgorithm,
+    types::KeyDetails,
+};
+
+use file_encryptor::i18n::t;
+
+use crate::entropy;
+
+/// Loads an armored or binary OpenPGP certificate and checks its binding
+/// signatures.
+pub fn read_certificate(data: &[u8]) -> Result<SignedPublicKey> {
+    let (cert, _headers) =
+        SignedPublicKey::from_reader_single(data).context(t!("openpgp-key-invalid"))?;
+    cert.verify_bindings().context(t!("openpgp-key-bindings"))?;
+    Ok(cert)
+}
+
+/// Encrypts `data` to `cert` as a PKESK + SEIPD (v1) message with AES-256,
+/// which every maintained gpg release can read.
+pub fn encrypt(cert: &SignedPublicKey, file_name: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    entropy::check()?;
+    let mut builder = MessageBuilder::from_bytes(file_name.to_owned(), data)
+        .seipd_v1(entropy::Rng, SymmetricKeyAlgorithm::AES256);
+    match encryption_subkey(cert) {
+        Some(subkey) => builder.encrypt_to_key(entropy::Rng, subkey),
+        None if cert.primary_key.algorithm().can_encrypt() => {
+            builder.encrypt_to_key(entropy::Rng, cert)
+        }
+        None => return Err(anyhow!(t!("openpgp-key-cannot-encrypt"))),
+    }
+    .context(t!("openpgp-wrap-failed"))?;
+    builder
+        .to_vec(entropy::Rng)
+        .context(t!("openpgp-encrypt-failed"))
+}
+
+fn encryption_subkey(cert: &SignedPublicKey) -> Option<&SignedPublicSubKey> {
+    cert.public_subkeys.iter().find(|subkey| {
+        subkey.key.algorithm().can_encrypt()
+            && subkey.signatures.iter().any(|sig| {
+                let flags = sig.key_flags();
+                flags.encrypt_comms() || flags.encrypt_storage()
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgp::{
+        composed::{Message, SignedSecretKey},
+        types::Password,
+    };
+
+    /// Made with `gpg --quick-gen-key` and an added cv25519 encryption
+    /// subkey, unprotected.
+    const ALICE: &str = include_str!("openpgp/alice.pub.asc");
+    const ALICE_SECRET: &str = include_str!("openpgp/alice.sec.asc");
+    const BOB_SECRET: &str = include_str!("openpgp/bob.sec.asc");
+    const SIGNING_ONLY: &str = include_str!("openpgp/signing-only.pub.asc");
+
+    fn open(message: &[u8], secret: &str) -> Result<Vec<u8>> {
+        let (secret, _) = SignedSecretKey::from_string(secret)?;
+        let mut message = Message::from_bytes(message)?.decrypt(&Password::empty(), &secret)?;
+        Ok(message.as_data_vec()?)
+    }
+
+    #[test]
+    fn messages_open_with_the_recipients_secret_key_only() {
+        let cert = read_certificate(ALICE.as_bytes()).unwrap();
+        let message = encrypt(&cert, "hello.txt", b"hello".to_vec()).unwrap();
+        assert_eq!(open(&message, ALICE_SECRET).unwrap(), b"hello");
+        assert!(open(&message, BOB_SECRET).is_err());
+
+        let mut altered = message.clone();
+        let last = altered.len() - 1;
+        altered[last] ^= 1;
+        assert!(open(&altered, ALICE_SECRET).is_err());
+    }
+
+    #[test]
+    fn certificates_that_cannot_encrypt_are_refused() {
+        let cert = read_certificate(SIGNING_ONLY.as_bytes()).unwrap();
+        assert!(encrypt(&cert, "hello.txt", b"hello".to_vec()).is_err());
+        assert!(read_certificate(b"not a certificate").is_err());
+    }
+}
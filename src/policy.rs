@@ -0,0 +1,176 @@
+//! The administrator's password policy, enforced whenever a password is
+//! used to encrypt.
+//!
+//! The policy lives in a system-wide file (see [`policy_path`]) of
+//! `key = value` lines:
+//!
+//! ```text
+//! min_length = 12
+//! min_entropy_bits = 60
+//! # One forbidden password per line, compared case-insensitively.
+//! denylist = /etc/file-encryptor/common-passwords.txt
+//! ```
+//!
+//! Without the file nothing is enforced. A file that can't be read or parsed
+//! is an error rather than no policy, so a typo can't switch it off.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use crate::{i18n::t, log, strength};
+
+const POLICY_ENV: &str = "FILE_ENCRYPTOR_PASSWORD_POLICY";
+
+#[derive(Default)]
+pub struct Policy {
+    min_length: usize,
+    min_entropy_bits: f64,
+    denylist: HashSet<String>,
+}
+
+/// `FILE_ENCRYPTOR_PASSWORD_POLICY` if set, else `/etc/file-encryptor/password-policy`
+/// (`%ProgramData%\file-encryptor\password-policy` on Windows).
+fn policy_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(POLICY_ENV) {
+        return path.into();
+    }
+    let base = if cfg!(windows) {
+        std::env::var_os("ProgramData")
+            .map_or_else(|| PathBuf::from(r"C:\ProgramData"), PathBuf::from)
+    } else {
+        PathBuf::from("/etc")
+    };
+    base.join("file-encryptor").join("password-policy")
+}
+
+impl Policy {
+    pub fn load() -> Result<Policy> {
+        let path = policy_path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Policy::default()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| t!("policy-unreadable", path = path.display().to_string()))
+            }
+        };
+        let mut policy = Policy::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || anyhow!("{}:{}: expected `key = value`", path.display(), number + 1);
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "min_length" => policy.min_length = value.parse().map_err(|_| invalid())?,
+                "min_entropy_bits" => {
+                    policy.min_entropy_bits = value.parse().map_err(|_| invalid())?
+                }
+                "denylist" => {
+                    let list = fs::read_to_string(value)
+                        .with_context(|| t!("policy-denylist-unreadable", path = value))?;
+                    policy.denylist.extend(
+                        list.lines()
+                            .map(|entry| entry.trim().to_lowercase())
+                            .filter(|entry| !entry.is_empty()),
+                    );
+                }
+                key => bail!(t!(
+                    "policy-unknown-setting",
+                    path = path.display().to_string(),
+                    line = number + 1,
+                    key = key
+                )),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Why `password` breaks the policy, if it does.
+    pub fn violation(&self, password: &str) -> Option<String> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Some(t!("policy-too-short", min = self.min_length));
+        }
+        let bits = entropy_bits(password);
+        if bits < self.min_entropy_bits {
+            return Some(t!(
+                "policy-too-simple",
+                bits = bits.floor(),
+                min = self.min_entropy_bits
+            ));
+        }
+        if self.denylist.contains(&password.to_lowercase()) {
+            return Some(t!("policy-denylisted"));
+        }
+        None
+    }
+
+    /// Refuses `password` if it breaks the policy, unless `force` is set, in
+    /// which case the override is reported on standard error and, on Unix,
+    /// to syslog for the administrator. Past the policy, the password must
+    /// also pass the [`strength`] check, unless `allow_weak` or `force` is set.
+    pub fn enforce(&self, password: &str, force: bool, allow_weak: bool) -> Result<()> {
+        let Some(reason) = self.violation(password) else {
+            return strength::check(password, allow_weak || force);
+        };
+        if !force {
+            return Err(anyhow!(t!("policy-refused", reason = reason)));
+        }
+        log::warn(&t!("policy-forced", reason = reason.as_str()));
+        log_override(&reason);
+        Ok(())
+    }
+}
+
+/// A rough estimate: the bits per character of the character classes used,
+/// times the length not counting repeats and runs (`aaaa`, `1234`). It still
+/// overrates dictionary words, which is what the denylist is for.
+fn entropy_bits(password: &str) -> f64 {
+    let uses = |class: fn(&char) -> bool| password.chars().any(|c| class(&c));
+    let pool: u32 = [
+        (uses(char::is_ascii_lowercase), 26),
+        (uses(char::is_ascii_uppercase), 26),
+        (uses(char::is_ascii_digit), 10),
+        (uses(|c| c.is_ascii_punctuation() || *c == ' '), 33),
+        (uses(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|&&(used, _)| used)
+    .map(|&(_, size)| size)
+    .sum();
+    if pool == 0 {
+        return 0.0;
+    }
+    let mut effective_length = 0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        if previous.is_none_or(|p| (c as u32).abs_diff(p as u32) > 1) {
+            effective_length += 1;
+        }
+        previous = Some(c);
+    }
+    f64::from(effective_length) * f64::from(pool).log2()
+}
+
+#[cfg(unix)]
+fn log_override(reason: &str) {
+    use std::ffi::CString;
+
+    let user = std::env::var("USER").unwrap_or_default();
+    let message =
+        format!("password policy overridden with --force-weak-password by {user:?}: {reason}");
+    let (Ok(ident), Ok(message)) = (CString::new("file-encryptor"), CString::new(message)) else {
+        return;
+    };
+    unsafe {
+        libc::openlog(ident.as_ptr(), libc::LOG_PID, libc::LOG_AUTH);
+        libc::syslog(libc::LOG_WARNING, c"%s".as_ptr(), message.as_ptr());
+        libc::closelog();
+    }
+}
+
+#[cfg(not(unix))]
+fn log_override(_reason: &str) {}
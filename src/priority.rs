@@ -0,0 +1,77 @@
+//! `--background`: drops the whole run to low CPU and I/O priority, so
+//! encrypting a large dataset leaves the machine usable.
+//!
+//! This has to run before any threads are started: on Linux both the nice
+//! value and the I/O class are per thread and only inherited by threads
+//! created afterwards.
+
+use std::io;
+
+/// The lowest nice value unprivileged processes can drop to everywhere.
+#[cfg(all(unix, not(target_os = "macos")))]
+const LOWEST_NICE: libc::c_int = 19;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn lower() -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    nice()?;
+    // The idle class only gets disk time when nothing else wants it.
+    let class = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, class) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Darwin's background band throttles both CPU and disk access.
+#[cfg(target_os = "macos")]
+pub fn lower() -> io::Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, libc::PRIO_DARWIN_BG) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Other Unixes have no portable I/O priority, so only the CPU is lowered.
+#[cfg(all(
+    unix,
+    not(any(target_os = "linux", target_os = "android", target_os = "macos"))
+))]
+pub fn lower() -> io::Result<()> {
+    nice()
+}
+
+/// Background processing mode lowers CPU, I/O and memory priority together.
+#[cfg(windows)]
+pub fn lower() -> io::Result<()> {
+    const PROCESS_MODE_BACKGROUND_BEGIN: u32 = 0x0010_0000;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> isize;
+        fn SetPriorityClass(process: isize, priority_class: u32) -> i32;
+    }
+
+    if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn lower() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "priorities cannot be changed on this platform",
+    ))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn nice() -> io::Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, LOWEST_NICE) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! A progress line on standard error for long streamed runs: bytes done,
+//! throughput and, when the total is known, a bar and the time left.
+//!
+//! It only appears on a terminal, after the run has taken a moment, so
+//! scripts, pipelines and small files never see it.
+
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    time::{Duration, Instant},
+};
+
+use file_encryptor::i18n::t;
+
+use crate::human_size;
+
+/// How long a run goes before the line appears.
+const DELAY: Duration = Duration::from_millis(500);
+/// How often the line is redrawn.
+const INTERVAL: Duration = Duration::from_millis(200);
+const BAR_WIDTH: usize = 24;
+
+/// Counts the bytes read through it, drawing the line as they go; the line is
+/// erased when the reader is dropped.
+pub struct Reader<R> {
+    inner: R,
+    total: Option<u64>,
+    done: u64,
+    enabled: bool,
+    start: Instant,
+    drawn: Option<Instant>,
+}
+
+impl<R> Reader<R> {
+    /// Tracks `inner`, which holds `total` bytes if known; `quiet` or a
+    /// standard error that isn't a terminal disables the line.
+    pub fn new(inner: R, total: Option<u64>, quiet: bool) -> Self {
+        Reader {
+            inner,
+            total,
+            done: 0,
+            enabled: !quiet && io::stderr().is_terminal(),
+            start: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    fn draw(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.start;
+        if elapsed < DELAY || self.drawn.is_some_and(|drawn| now - drawn < INTERVAL) {
+            return;
+        }
+        self.drawn = Some(now);
+
+        let rate = self.done as f64 / elapsed.as_secs_f64();
+        let mut line = match self.total {
+            Some(total) if total > 0 => {
+                let fraction = (self.done as f64 / total as f64).min(1.0);
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                format!(
+                    "[{}{}] {:>3.0}% {} / {}",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    fraction * 100.0,
+                    human_size(self.done),
+                    human_size(total)
+                )
+            }
+            _ => human_size(self.done),
+        };
+        line += &format!("  {}/s", human_size(rate as u64));
+        if let Some(total) = self.total.filter(|_| rate > 0.0) {
+            let left = total.saturating_sub(self.done) as f64 / rate;
+            line += "  ";
+            line += &t!("progress-left", time = clock(left as u64));
+        }
+        // Erases the rest of the previous line, which may have been longer.
+        eprint!("\r{line}\x1b[K");
+        let _ = io::stderr().flush();
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.done += n as u64;
+        if self.enabled {
+            self.draw();
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for Reader<R> {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// `seconds` as m:ss, or h:mm:ss from an hour on.
+fn clock(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match hours {
+        0 => format!("{minutes}:{seconds:02}"),
+        _ => format!("{hours}:{minutes:02}:{seconds:02}"),
+    }
+}
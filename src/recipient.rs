@@ -0,0 +1,299 @@
+//! Recipients: files that any of several X25519 public keys and passwords
+//! open. Each file has a random key of its own, stored in the header once per
+//! recipient, in a [`Slot`] (see
+//! [`Encryptor::to_recipients`](crate::Encryptor::to_recipients)). A file
+//! for a password alone is one for recipients with just that password, so
+//! changing it only rewraps its slot. A public
+//! key needs no secret from the sender, so a server's operator can publish
+//! theirs and keep the secret key to themselves; passwords let a team share a
+//! file without sharing one password. A hardware token's P-256 key works like
+//! a public key, except that opening its slot takes the token itself, which
+//! does the key agreement without the secret key ever leaving it.
+
+use aes_gcm::{Aes256Gcm, Key};
+use anyhow::{anyhow, bail, Context, Result};
+use crypto_box::{PublicKey, SecretKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+use crate::{
+    entropy, header::TAG_LENGTH, i18n::t, key_file::Validity, Cipher, DecryptError, FileKey, Kdf,
+    KEY_LENGTH, SALT_LENGTH,
+};
+
+/// One file key sealed to one public key.
+pub const SEALED_KEY_LENGTH: usize = crypto_box::SEALBYTES + KEY_LENGTH;
+/// One file key encrypted under one password's key.
+const WRAPPED_KEY_LENGTH: usize = KEY_LENGTH + TAG_LENGTH;
+/// The nonce a file key is encrypted under.
+pub const WRAP_NONCE_LENGTH: usize = 12;
+/// A P-256 public key as a SEC1 uncompressed point.
+pub const TOKEN_PUBLIC_KEY_LENGTH: usize = 65;
+/// The longest token identifier a header can record.
+pub const MAX_TOKEN_LENGTH: usize = u8::MAX as usize;
+/// The most recipients a header can list.
+pub const MAX_RECIPIENTS: usize = u8::MAX as usize;
+
+/// One recipient's copy of the file key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    /// Sealed to an X25519 public key, as a libsodium sealed box.
+    PublicKey(Vec<u8>),
+    /// Encrypted with AES-256-GCM under the key `kdf` derives from the
+    /// password and `salt`, which one session shares between all the files
+    /// it encrypts, each under a random `nonce`.
+    Password {
+        kdf: Kdf,
+        salt: [u8; SALT_LENGTH],
+        nonce: [u8; WRAP_NONCE_LENGTH],
+        wrapped: Vec<u8>,
+    },
+    /// Encrypted with AES-256-GCM under a key from an ECDH key agreement
+    /// between the P-256 key held by the hardware token `token` (a PKCS#11
+    /// URI or the like, saying where to find it) and the `ephemeral` key
+    /// drawn for this slot alone, under a random `nonce`.
+    Token {
+        mechanism: TokenMechanism,
+        token: String,
+        ephemeral: Vec<u8>,
+        nonce: [u8; WRAP_NONCE_LENGTH],
+        wrapped: Vec<u8>,
+    },
+}
+
+/// How a [`Slot::Token`] is wrapped; the only one so far is ECDH over P-256.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenMechanism {
+    EcdhP256,
+}
+
+/// What opens a [`Slot::Token`]: the token named by its first argument doing
+/// the key agreement of its mechanism with the ephemeral public key, its
+/// second, and returning the shared secret.
+pub type TokenAgreement =
+    Arc<dyn Fn(&str, &[u8]) -> Result<Zeroizing<[u8; KEY_LENGTH]>> + Send + Sync>;
+
+impl Slot {
+    pub(crate) fn has_valid_length(&self) -> bool {
+        match self {
+            Slot::PublicKey(sealed) => sealed.len() == SEALED_KEY_LENGTH,
+            Slot::Password { wrapped, .. } => wrapped.len() == WRAPPED_KEY_LENGTH,
+            Slot::Token {
+                ephemeral, wrapped, ..
+            } => ephemeral.len() == TOKEN_PUBLIC_KEY_LENGTH && wrapped.len() == WRAPPED_KEY_LENGTH,
+        }
+    }
+}
+
+/// Someone the key of every new file is wrapped for.
+#[derive(Clone)]
+pub(crate) enum Recipient {
+    PublicKey(PublicKey),
+    /// A password, by the key `kdf` derived from it and `salt`.
+    Password {
+        kdf: Kdf,
+        salt: [u8; SALT_LENGTH],
+        key: Zeroizing<Key<Aes256Gcm>>,
+    },
+    /// A hardware token, by its identifier and its key's public half.
+    Token {
+        token: String,
+        public_key: p256::PublicKey,
+    },
+}
+
+impl Recipient {
+    /// `file_key` wrapped for this recipient.
+    pub(crate) fn slot(&self, file_key: &[u8; KEY_LENGTH]) -> Result<Slot> {
+        match self {
+            Recipient::PublicKey(public_key) => seal(file_key, public_key),
+            Recipient::Password { kdf, salt, key } => wrap(file_key, key, *kdf, *salt),
+            Recipient::Token { token, public_key } => wrap_for_token(file_key, token, public_key),
+        }
+    }
+
+    /// A slot as long as [`Recipient::slot`] makes, to measure a header by.
+    pub(crate) fn placeholder(&self) -> Slot {
+        match self {
+            Recipient::PublicKey(_) => Slot::PublicKey(vec![0; SEALED_KEY_LENGTH]),
+            Recipient::Password { kdf, salt, .. } => placeholder(*kdf, *salt),
+            Recipient::Token { token, .. } => Slot::Token {
+                mechanism: TokenMechanism::EcdhP256,
+                token: token.clone(),
+                ephemeral: vec![0; TOKEN_PUBLIC_KEY_LENGTH],
+                nonce: [0; WRAP_NONCE_LENGTH],
+                wrapped: vec![0; WRAPPED_KEY_LENGTH],
+            },
+        }
+    }
+}
+
+/// A password slot as long as [`wrap`] makes.
+pub(crate) fn placeholder(kdf: Kdf, salt: [u8; SALT_LENGTH]) -> Slot {
+    Slot::Password {
+        kdf,
+        salt,
+        nonce: [0; WRAP_NONCE_LENGTH],
+        wrapped: vec![0; WRAPPED_KEY_LENGTH],
+    }
+}
+
+/// A new identity file's contents, a line of hex like a key file's with the
+/// same comments for its `validity`, and the public key to give senders, in
+/// hex.
+pub fn generate(validity: &Validity) -> Result<(String, String)> {
+    let secret = SecretKey::from(entropy::bytes::<{ crypto_box::KEY_SIZE }>()?);
+    Ok((
+        format!(
+            "{}{}\n",
+            validity.comments(),
+            hex::encode(secret.to_bytes())
+        ),
+        hex::encode(secret.public_key().as_bytes()),
+    ))
+}
+
+/// Refuses more recipients than a header holds.
+pub(crate) fn check_count(count: usize) -> Result<()> {
+    if count > MAX_RECIPIENTS {
+        bail!(t!("recipients-too-many", max = MAX_RECIPIENTS));
+    }
+    Ok(())
+}
+
+/// Seals `file_key` to `recipient`.
+pub(crate) fn seal(file_key: &[u8; KEY_LENGTH], recipient: &PublicKey) -> Result<Slot> {
+    entropy::check()?;
+    let sealed = recipient
+        .seal(&mut entropy::Rng, file_key)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| t!("encrypt-failed"))?;
+    Ok(Slot::PublicKey(sealed))
+}
+
+/// Encrypts `file_key` under `key`, which `kdf` derived from a password and
+/// `salt`, and a random nonce.
+pub(crate) fn wrap(
+    file_key: &[u8; KEY_LENGTH],
+    key: &Key<Aes256Gcm>,
+    kdf: Kdf,
+    salt: [u8; SALT_LENGTH],
+) -> Result<Slot> {
+    let nonce = entropy::bytes()?;
+    let wrapped = crate::encrypt(Cipher::Aes256Gcm, key, &nonce, &[], file_key)
+        .with_context(|| t!("encrypt-failed"))?;
+    Ok(Slot::Password {
+        kdf,
+        salt,
+        nonce,
+        wrapped,
+    })
+}
+
+/// The hardware token `token` as a recipient, by the SEC1 encoding of its
+/// P-256 public key.
+pub(crate) fn token(token: &str, public_key: &[u8]) -> Result<Recipient> {
+    if token.len() > MAX_TOKEN_LENGTH {
+        bail!(t!("token-too-long", max = MAX_TOKEN_LENGTH));
+    }
+    let public_key = p256::PublicKey::from_sec1_bytes(public_key)
+        .map_err(|_| anyhow!(t!("token-not-p256", token = token)))?;
+    Ok(Recipient::Token {
+        token: token.to_owned(),
+        public_key,
+    })
+}
+
+/// The key a token slot's file key is wrapped under: the shared secret of
+/// the key agreement, bound to the slot's ephemeral key.
+fn token_wrap_key(shared: &[u8], ephemeral: &[u8]) -> Key<Aes256Gcm> {
+    let mut material = Zeroizing::new(shared.to_vec());
+    material.extend_from_slice(ephemeral);
+    blake3::derive_key("file-encryptor 2026 token slot", &material).into()
+}
+
+/// Wraps `file_key` for the token `token` with `public_key`, agreeing on the
+/// wrapping key with an ephemeral key of its own.
+fn wrap_for_token(
+    file_key: &[u8; KEY_LENGTH],
+    token: &str,
+    public_key: &p256::PublicKey,
+) -> Result<Slot> {
+    entropy::check()?;
+    let secret = p256::ecdh::EphemeralSecret::random(&mut entropy::Rng);
+    let ephemeral = secret
+        .public_key()
+        .to_encoded_point(false)
+        .as_bytes()
+        .to_vec();
+    let shared = secret.diffie_hellman(public_key);
+    let key = Zeroizing::new(token_wrap_key(shared.raw_secret_bytes(), &ephemeral));
+    let nonce = entropy::bytes()?;
+    let wrapped = crate::encrypt(Cipher::Aes256Gcm, &key, &nonce, &[], file_key)
+        .with_context(|| t!("encrypt-failed"))?;
+    Ok(Slot::Token {
+        mechanism: TokenMechanism::EcdhP256,
+        token: token.to_owned(),
+        ephemeral,
+        nonce,
+        wrapped,
+    })
+}
+
+/// The file key in whichever of `slots` a token `agree` reaches opens, or
+/// the error of the last one tried.
+pub(crate) fn open_token(agree: &TokenAgreement, slots: &[Slot]) -> Result<FileKey> {
+    let mut error = anyhow!(t!("token-not-listed"));
+    for slot in slots {
+        let Slot::Token {
+            mechanism: TokenMechanism::EcdhP256,
+            token,
+            ephemeral,
+            nonce,
+            wrapped,
+        } = slot
+        else {
+            continue;
+        };
+        let shared = match agree(token, ephemeral) {
+            Ok(shared) => shared,
+            Err(e) => {
+                error = e;
+                continue;
+            }
+        };
+        let key = Zeroizing::new(token_wrap_key(&*shared, ephemeral));
+        if let Some(file_key) = unwrap(&key, *nonce, wrapped) {
+            return Ok(file_key);
+        }
+        error = anyhow!(DecryptError::WrongKey);
+    }
+    Err(error)
+}
+
+/// The file key in a password slot, if `key` is the one it was wrapped under
+/// with `nonce`.
+pub(crate) fn unwrap(
+    key: &Key<Aes256Gcm>,
+    nonce: [u8; WRAP_NONCE_LENGTH],
+    wrapped: &[u8],
+) -> Option<FileKey> {
+    let file_key =
+        Zeroizing::new(crate::decrypt(Cipher::Aes256Gcm, key, &nonce, &[], wrapped).ok()?);
+    file_key.as_slice().try_into().ok().map(Zeroizing::new)
+}
+
+/// The file key in whichever of `slots` is sealed to `identity`.
+pub(crate) fn open(identity: &SecretKey, slots: &[Slot]) -> Result<FileKey> {
+    slots
+        .iter()
+        .find_map(|slot| match slot {
+            Slot::PublicKey(sealed) => {
+                let file_key = Zeroizing::new(identity.unseal(sealed).ok()?);
+                file_key.as_slice().try_into().ok().map(Zeroizing::new)
+            }
+            Slot::Password { .. } | Slot::Token { .. } => None,
+        })
+        .ok_or_else(|| anyhow!(t!("recipient-not-listed")))
+}
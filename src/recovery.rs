@@ -0,0 +1,163 @@
+//! Recovery keys: a random code, printed once when a file is encrypted, that
+//! opens it if the password is lost. The file key is wrapped for the code as
+//! for one more password (see [`recipient`](crate::recipient)), so the code
+//! opens the file on its own and changing the password leaves it working.
+//!
+//! A code is 16 random bytes and a 4-byte check, in Crockford's base32 and
+//! groups of four, to be typed back from paper:
+//!
+//! ```text
+//! TE7V-CT43-H8V2-YBEH-TFJ1-M7K9-NR2R-9FQ1
+//! ```
+//!
+//! Case, dashes and blank space don't matter when it is typed back, and the
+//! letters O and I or L read as 0 and 1. The check is the start of the
+//! BLAKE3 digest of the random bytes, so a mistyped code is refused rather
+//! than tried.
+
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+use crate::{entropy, i18n::t};
+
+const RANDOM_LENGTH: usize = 16;
+const CHECK_LENGTH: usize = 4;
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Characters between dashes.
+const GROUP_LENGTH: usize = 4;
+
+/// A new recovery key, as it is printed and as it is given to
+/// [`Encryptor::to_recipients`](crate::Encryptor::to_recipients).
+pub fn generate() -> Result<Zeroizing<String>> {
+    let random = Zeroizing::new(entropy::bytes::<RANDOM_LENGTH>()?);
+    Ok(encode(random.as_slice()))
+}
+
+/// The recovery key `text` holds, as [`generate`] wrote it.
+pub fn parse(text: &str) -> Result<Zeroizing<String>> {
+    let invalid = || anyhow!(t!("recovery-key-invalid"));
+    let mut bits = 0u16;
+    let mut count = 0;
+    let mut bytes = Zeroizing::new(Vec::with_capacity(RANDOM_LENGTH + CHECK_LENGTH));
+    for c in text.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let value = ALPHABET
+            .iter()
+            .position(|&a| char::from(a) == c)
+            .ok_or_else(invalid)?;
+        bits = bits << 5 | value as u16;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    if bytes.len() != RANDOM_LENGTH + CHECK_LENGTH || count != 0 {
+        return Err(invalid());
+    }
+    let (random, check_bytes) = bytes.split_at(RANDOM_LENGTH);
+    if check_bytes != check(random) {
+        return Err(invalid());
+    }
+    Ok(encode(random))
+}
+
+/// `random` and its check, in groups.
+fn encode(random: &[u8]) -> Zeroizing<String> {
+    let mut bytes = Zeroizing::new(random.to_vec());
+    bytes.extend_from_slice(&check(random));
+    let mut code = Zeroizing::new(String::new());
+    let (mut bits, mut count) = (0u16, 0);
+    let mut digits = 0;
+    let mut push = |value: u16, code: &mut String| {
+        if digits > 0 && digits % GROUP_LENGTH == 0 {
+            code.push('-');
+        }
+        code.push(char::from(ALPHABET[usize::from(value & 31)]));
+        digits += 1;
+    };
+    for &byte in bytes.iter() {
+        bits = bits << 8 | u16::from(byte);
+        count += 8;
+        while count >= 5 {
+            count -= 5;
+            push(bits >> count, &mut code);
+        }
+    }
+    code
+}
+
+/// The check bytes of the random bytes of a code.
+fn check(random: &[u8]) -> [u8; CHECK_LENGTH] {
+    let mut check = [0; CHECK_LENGTH];
+    check.copy_from_slice(&blake3::hash(random).as_bytes()[..CHECK_LENGTH]);
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header::{replace_slots, Header},
+        DecryptError, Encryptor, Kdf, FOOTER_LENGTH,
+    };
+
+    #[test]
+    fn codes_read_back_however_they_are_typed() {
+        let code = generate().unwrap();
+        assert_eq!(code.len(), 39);
+        assert_eq!(*parse(&code).unwrap(), *code);
+        let typed = code
+            .to_lowercase()
+            .replace('0', "o")
+            .replace('1', "l")
+            .replace('-', " ");
+        assert_eq!(*parse(&typed).unwrap(), *code);
+
+        let mistyped: String = code
+            .chars()
+            .enumerate()
+            .map(|(i, c)| match (i, c) {
+                (0, '2') => '3',
+                (0, _) => '2',
+                (_, c) => c,
+            })
+            .collect();
+        assert!(parse(&mistyped).is_err());
+        assert!(parse(&code[..code.len() - 1]).is_err());
+        assert!(parse(&format!("{}2", *code)).is_err());
+    }
+
+    #[test]
+    fn the_code_opens_the_file_as_the_password_does() {
+        let kdf = Kdf::Pbkdf2 { iterations: 1 };
+        let code = generate().unwrap();
+        let file = Encryptor::to_recipients(&[], &["pw", &code], kdf)
+            .unwrap()
+            .encrypt(b"hello")
+            .unwrap();
+        assert_eq!(Encryptor::new(&code).decrypt(&file).unwrap(), b"hello");
+        assert_eq!(Encryptor::new("pw").decrypt(&file).unwrap(), b"hello");
+        let other = generate().unwrap();
+        let wrong = Encryptor::new(&other).decrypt(&file).unwrap_err();
+        assert_eq!(wrong.downcast_ref(), Some(&DecryptError::WrongKey));
+
+        // Still, once the password is changed.
+        let header = Header::parse(&file).unwrap();
+        let length = Header::length(&file).unwrap();
+        let mut new = Encryptor::with_kdf("new", kdf);
+        let rekeyed = Encryptor::new("pw").rekeyed(&header, &mut new).unwrap();
+        let mut rekeyed_file = replace_slots(&file[..length], rekeyed.slots()).unwrap();
+        rekeyed_file.extend_from_slice(&file[length..file.len() - FOOTER_LENGTH]);
+        crate::append_footer(&mut rekeyed_file);
+        assert_eq!(
+            Encryptor::new(&code).decrypt(&rekeyed_file).unwrap(),
+            b"hello"
+        );
+        assert!(Encryptor::new("pw").decrypt(&rekeyed_file).is_err());
+    }
+}
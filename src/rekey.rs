@@ -0,0 +1,362 @@
+//! `rekey`: switches an encrypted file to a new password in place.
+//!
+//! A file for recipients whose slots are left out of its associated data
+//! (see [`header::slots_detached`]) keeps its payload byte for byte: only the
+//! slot the old password opens is rewrapped, so a file of any size is rekeyed
+//! in one pass of copying. Any other file is decrypted and re-encrypted with
+//! the same cipher, compression and layout. A password-only file without
+//! slots, from before the header or `--deterministic`, is re-encrypted for
+//! recipients, with the new password as its one slot, so that later changes
+//! are cheap; a file that also needs a key file keeps needing it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use std::{
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use file_encryptor::{
+    header::{self, Header},
+    i18n::t,
+    key_file,
+    recipient::Slot,
+    stream, DecryptError, Encryptor, Kdf, KeySource, FOOTER_LENGTH, FOOTER_MAGIC, SALT_LENGTH,
+};
+
+use crate::{
+    attempts, create_file, encryptor, open_stream, policy, progress, prompt_new_password,
+    read_key_file, report, sync_file, throttle, unlock, write_file_bytes, IoOptions, KdfOptions,
+    PasswordSource, Plaintext, HEADER_PEEK,
+};
+
+#[derive(Args)]
+pub struct RekeyOptions {
+    /// File to change
+    #[arg(value_name = "FILE")]
+    input_path: String,
+    /// The current password; prompted for if omitted
+    #[arg(
+        short,
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// The new password; prompted for, twice, if omitted
+    #[arg(long, value_name = "PASSWORD")]
+    new_password: Option<String>,
+    /// The key file a file encrypted with a password and --key-file also needs; it stays needed
+    #[arg(long, value_name = "FILE")]
+    key_file: Option<String>,
+    /// Use the new password even if it breaks the administrator's password policy; the override is logged
+    #[arg(long)]
+    force_weak_password: bool,
+    /// Use the new password even if it is easy to guess, with a warning
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// The associated data the file was bound to with `encrypt --aad`; it stays bound to it
+    #[arg(long, value_name = "TEXT")]
+    aad: Option<String>,
+    #[command(flatten)]
+    kdf: KdfOptions,
+    /// Flush the rewritten file and its directory to disk before reporting success
+    #[arg(long)]
+    fsync: bool,
+    /// Don't show the progress line
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+/// The password files are changed to. Its key is derived once, however many
+/// files are changed.
+pub struct NewPassword<'a> {
+    /// For files with a password alone or for recipients.
+    alone: Encryptor<'a>,
+    /// For files that need a key file as well, when one is given.
+    with_key_file: Option<Encryptor<'a>>,
+}
+
+impl<'a> NewPassword<'a> {
+    pub fn new(
+        password: &'a str,
+        key_file: Option<[u8; key_file::LENGTH]>,
+        kdf: Kdf,
+        aad: Option<Vec<u8>>,
+    ) -> Self {
+        NewPassword {
+            alone: Encryptor::with_kdf(password, kdf).with_aad(aad.clone()),
+            with_key_file: key_file
+                .map(|key_file| encryptor(Some(password), Some(key_file), kdf).with_aad(aad)),
+        }
+    }
+}
+
+pub fn run(options: &RekeyOptions) -> Result<()> {
+    report::target(&options.input_path, Some(&options.input_path))?;
+    let path = Path::new(&options.input_path);
+    let io = IoOptions {
+        fsync: options.fsync,
+        quiet: options.quiet,
+        ..IoOptions::default()
+    };
+    let (head, input) = open(path, io)?;
+    if header::key_source(&head)? == KeySource::KeyFile {
+        bail!(t!("rekey-key-file-only", path = path.display().to_string()));
+    }
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let kdf = options.kdf.kdf()?;
+    let aad = options.aad.as_ref().map(|aad| aad.as_bytes().to_vec());
+    let password = options.password_source.or(&options.password)?;
+    let mut prompted = Zeroizing::default();
+    let keys = unlock(
+        &head,
+        password.as_deref().map(String::as_str),
+        key_file,
+        &mut prompted,
+    )?
+    .with_aad(aad.clone());
+    let new_password = match &options.new_password {
+        Some(password) => Zeroizing::new(password.clone()),
+        None => prompt_new_password()?,
+    };
+    policy::Policy::load()?.enforce(
+        &new_password,
+        options.force_weak_password,
+        options.allow_weak_password,
+    )?;
+    let mut new = NewPassword::new(&new_password, key_file, kdf, aad);
+
+    let message = rekey_file(path, head, input, keys, &mut new, false, io)?;
+    if !report::quiet() {
+        println!("{message}");
+    }
+    Ok(())
+}
+
+/// Opens the encrypted file at `path`, returning its first bytes and the
+/// rest of it.
+pub fn open(path: &Path, io: IoOptions) -> Result<(Vec<u8>, impl Read)> {
+    let metadata = fs::metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    if !metadata.is_file() {
+        bail!(t!("rekey-not-file", path = path.display().to_string()));
+    }
+    let file = fs::File::open(path)
+        .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+    let mut input = progress::Reader::new(throttle::Reader(file), Some(metadata.len()), io.quiet);
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(HEADER_PEEK)
+        .read_to_end(&mut head)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    Ok((head, input))
+}
+
+/// Changes the file at `path`, which starts with `head` and continues with
+/// `input`, from the password `keys` holds to `new`, returning the message to
+/// report. With `dry_run` the file is only opened with the old password, by
+/// its slot or by decrypting it, and left as it is.
+pub fn rekey_file(
+    path: &Path,
+    head: Vec<u8>,
+    input: impl Read,
+    keys: Encryptor,
+    new: &mut NewPassword,
+    dry_run: bool,
+    io: IoOptions,
+) -> Result<String> {
+    let metadata = fs::metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    let name = path.display().to_string();
+    let message = if header::slots_detached(&head) {
+        let header = Header::parse(&head)?;
+        let attempt = attempts::begin(&head[..head.len().min(64)]);
+        let new_keys = match keys.rekeyed(&header, &mut new.alone) {
+            Ok(new_keys) => {
+                attempt.succeeded();
+                new_keys
+            }
+            Err(e) => {
+                attempt.failed(&e);
+                return Err(e);
+            }
+        };
+        if dry_run {
+            return Ok(t!("rotate-would-rewrap", path = name));
+        }
+        rewrite_header(path, head, input, metadata.len(), new_keys.slots(), io)?;
+        t!("rekey-done", path = name)
+    } else {
+        let new_keys = |header: &Header| -> Result<Encryptor> {
+            let new_keys = match header.key_source {
+                KeySource::Recipients => return keys.rekeyed(header, &mut new.alone),
+                KeySource::Password => new.alone.clone(),
+                source => new.with_key_file.clone().ok_or_else(|| source.mismatch())?,
+            };
+            Ok(new_keys
+                .with_cipher(header.cipher)
+                .with_compression(header.compression))
+        };
+        let source = header::key_source(&head)?;
+        reencrypt(path, head, input, keys.clone(), new_keys, dry_run, io)?;
+        match (source, dry_run) {
+            (_, true) => t!("rotate-would-reencrypt", path = name),
+            (KeySource::PasswordAndKeyFile, false) => t!("rekey-reencrypted", path = name),
+            (_, false) => t!("rekey-converted", path = name),
+        }
+    };
+    // The rewritten file starts out owner-only, like every output.
+    fs::set_permissions(path, metadata.permissions()).with_context(|| {
+        t!(
+            "permissions-restore-failed",
+            path = path.display().to_string()
+        )
+    })?;
+    Ok(message)
+}
+
+/// Replaces the slots in the header of the file at `path`, which starts with
+/// `head` and continues with `rest`, `length` bytes in all, with `slots`,
+/// copying the payload as it is and checking the old checksum footer on the
+/// way.
+fn rewrite_header(
+    path: &Path,
+    head: Vec<u8>,
+    rest: impl Read,
+    length: u64,
+    slots: &[Slot],
+    io: IoOptions,
+) -> Result<()> {
+    let header_length = Header::length(&head)?;
+    // In the version it was written in, which its payload is authenticated
+    // with.
+    let new_header = header::replace_slots(&head[..header_length], slots)?;
+    let payload_length = length
+        .checked_sub((header_length + FOOTER_LENGTH) as u64)
+        .ok_or_else(|| anyhow!(t!("header-invalid")))?;
+    let mut old_digest = blake3::Hasher::new();
+    old_digest.update(&head[..header_length]);
+    let mut input = io::Cursor::new(head[header_length..].to_vec()).chain(rest);
+
+    let (file, output) = create_file(path, io)?;
+    let mut writer = BufWriter::new(file);
+    let mut new_digest = blake3::Hasher::new();
+    new_digest.update(&new_header);
+    writer
+        .write_all(&new_header)
+        .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+    let mut payload = (&mut input).take(payload_length);
+    let mut buffer = vec![0; 64 << 10];
+    loop {
+        let n = payload
+            .read(&mut buffer)
+            .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+        if n == 0 {
+            break;
+        }
+        old_digest.update(&buffer[..n]);
+        new_digest.update(&buffer[..n]);
+        writer
+            .write_all(&buffer[..n])
+            .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+    }
+    let mut footer = [0; FOOTER_LENGTH];
+    input
+        .read_exact(&mut footer)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    let (digest, magic) = footer.split_at(blake3::OUT_LEN);
+    if magic != FOOTER_MAGIC || digest != old_digest.finalize().as_bytes() {
+        return Err(DecryptError::ChecksumMismatch.into());
+    }
+    writer
+        .write_all(new_digest.finalize().as_bytes())
+        .and_then(|()| writer.write_all(FOOTER_MAGIC))
+        .and_then(|()| writer.flush())
+        .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    sync_file(path, &file, io)?;
+    output.commit(io)
+}
+
+/// Decrypts the file at `path`, which starts with `head` and continues with
+/// `rest`, with `keys`, and encrypts it again in its own layout, with the
+/// same metadata, with the session `new_keys` makes for its header; with
+/// `dry_run`, only decrypts it.
+fn reencrypt<'a>(
+    path: &Path,
+    head: Vec<u8>,
+    mut rest: impl Read,
+    mut keys: Encryptor,
+    new_keys: impl FnOnce(&Header) -> Result<Encryptor<'a>>,
+    dry_run: bool,
+    io: IoOptions,
+) -> Result<()> {
+    if !stream::is_stream(head.as_slice())? {
+        let mut file_bytes = head;
+        rest.read_to_end(&mut file_bytes)
+            .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+        let header = match file_bytes.starts_with(header::MAGIC) {
+            true => Header::parse(&file_bytes)?,
+            false => Header::legacy([0; SALT_LENGTH], Vec::new()),
+        };
+        let attempt = attempts::begin(&file_bytes);
+        let plaintext = match keys.decrypt(&file_bytes) {
+            Ok(plaintext) => {
+                attempt.succeeded();
+                plaintext
+            }
+            Err(e) => {
+                attempt.failed(&e);
+                return Err(e);
+            }
+        };
+        if dry_run {
+            return Ok(());
+        }
+        let metadata = keys.metadata(&file_bytes)?;
+        let encrypted = new_keys(&header)?
+            .with_metadata(metadata)
+            .encrypt(&plaintext)?;
+        return write_file_bytes(path, &encrypted, io);
+    }
+
+    let (header, _) = stream::header(head.as_slice())?;
+    let header_bytes = head.clone();
+    let (first, mut decryptor) = open_stream(head, rest, &mut keys)?;
+    if dry_run {
+        let mut chunk = first;
+        while chunk.is_some() {
+            chunk = decryptor.next_chunk()?;
+        }
+        return Ok(());
+    }
+    let metadata = keys.metadata(&header_bytes)?;
+    // Checked against the plaintext again as it is re-encrypted.
+    let content_checksum = keys.content_checksum(&header_bytes)?;
+    let mut new_keys = new_keys(&header)?
+        .with_metadata(metadata)
+        .with_content_checksum(content_checksum);
+    let mut plaintext = Plaintext {
+        decryptor,
+        chunk: first.unwrap_or_default(),
+        offset: 0,
+        error: None,
+    };
+    let (file, output) = create_file(path, io)?;
+    let mut writer = BufWriter::new(file);
+    let written = stream::encrypt(&mut new_keys, &mut plaintext, &mut writer);
+    if let Some(e) = plaintext.error.take() {
+        return Err(e);
+    }
+    written.with_context(|| t!("file-encrypt-failed", path = path.display().to_string()))?;
+    drop(plaintext);
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    sync_file(path, &file, io)?;
+    output.commit(io)
+}
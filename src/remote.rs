@@ -0,0 +1,51 @@
+//! Inputs and outputs named by URL instead of path, such as
+//! `s3://BUCKET/KEY`, read and written as streams so nothing is staged on the
+//! local disk.
+//!
+//! Each scheme is a backend that opens a source to read and creates a
+//! [`Sink`] to write. Like a local output, what a sink writes only appears
+//! once it is finished; one dropped unfinished leaves nothing behind. Only
+//! `s3://` ([`s3`]) exists so far.
+
+use anyhow::{anyhow, Result};
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use file_encryptor::i18n::t;
+
+mod s3;
+
+/// Where written bytes go until [`Sink::finish`] puts them in place.
+pub trait Sink: Write {
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Whether `path` is a URL of a backend here rather than a local path.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with(s3::SCHEME))
+}
+
+/// The last part of the URL `path`, to name a local output after.
+pub fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn url(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!(t!("url-not-utf8", path = path.display().to_string())))
+}
+
+/// Opens the object at `path` for reading front to back.
+pub fn open(path: &Path) -> Result<Box<dyn Read>> {
+    let object = s3::Object::parse(url(path)?)?;
+    Ok(Box::new(object.open()?))
+}
+
+/// Starts writing the object at `path`, replacing any there once finished.
+pub fn create(path: &Path) -> Result<Box<dyn Sink>> {
+    let object = s3::Object::parse(url(path)?)?;
+    Ok(Box::new(object.create()))
+}
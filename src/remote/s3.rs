@@ -0,0 +1,367 @@
+//! `s3://BUCKET/KEY`: objects in Amazon S3 or a service speaking its API,
+//! such as MinIO, over HTTPS with Signature Version 4.
+//!
+//! The credentials, region and endpoint come from the usual environment
+//! variables: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, optionally
+//! `AWS_SESSION_TOKEN`, `AWS_REGION` or `AWS_DEFAULT_REGION` (`us-east-1` if
+//! neither is set), and `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL` for
+//! another service, which is then addressed by path rather than by bucket
+//! host name.
+//!
+//! Reading streams the object's body. Writing buffers [`PART_LENGTH`] bytes
+//! at a time: an object that fits in one part is sent with a single PUT, a
+//! larger one as a multipart upload that only becomes the object once it is
+//! completed, and that is aborted if the writer is dropped before.
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    mem,
+    time::SystemTime,
+};
+
+use file_encryptor::i18n::t;
+
+use super::Sink;
+
+pub const SCHEME: &str = "s3://";
+/// The size of each part of a multipart upload, which S3 allows 10,000 of.
+const PART_LENGTH: usize = 16 << 20;
+/// Error responses are short; anything longer is cut off.
+const MAX_ERROR_LENGTH: u64 = 64 << 10;
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+pub struct Object {
+    url: String,
+    /// `https://host[/bucket]`, without a trailing `/`.
+    base: String,
+    host: String,
+    /// The bucket's part of the path, when addressed by path.
+    bucket_path: String,
+    key: String,
+    region: String,
+    credentials: Credentials,
+}
+
+impl Object {
+    /// The object at `url`, an `s3://BUCKET/KEY` URL.
+    pub fn parse(url: &str) -> Result<Object> {
+        let (bucket, key) = url
+            .strip_prefix(SCHEME)
+            .and_then(|rest| rest.split_once('/'))
+            .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+            .ok_or_else(|| anyhow!(t!("s3-url-invalid", url = url)))?;
+        let variable = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let credentials = Credentials {
+            access_key_id: variable("AWS_ACCESS_KEY_ID")
+                .with_context(|| t!("s3-no-credentials"))?,
+            secret_access_key: variable("AWS_SECRET_ACCESS_KEY")
+                .with_context(|| t!("s3-no-credentials"))?,
+            session_token: variable("AWS_SESSION_TOKEN"),
+        };
+        let region = variable("AWS_REGION")
+            .or_else(|| variable("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_owned());
+        let (base, host, bucket_path) =
+            match variable("AWS_ENDPOINT_URL_S3").or_else(|| variable("AWS_ENDPOINT_URL")) {
+                Some(endpoint) => {
+                    let endpoint = endpoint.trim_end_matches('/');
+                    let host = endpoint
+                        .split_once("://")
+                        .map(|(_, rest)| rest.split('/').next().unwrap_or(rest))
+                        .filter(|host| !host.is_empty())
+                        .ok_or_else(|| anyhow!(t!("s3-endpoint-invalid", endpoint = endpoint)))?;
+                    let bucket_path = format!("/{}", uri_encode(bucket, false));
+                    (
+                        format!("{endpoint}{bucket_path}"),
+                        host.to_owned(),
+                        bucket_path,
+                    )
+                }
+                None => {
+                    let host = format!("{bucket}.s3.{region}.amazonaws.com");
+                    (format!("https://{host}"), host, String::new())
+                }
+            };
+        Ok(Object {
+            url: url.to_owned(),
+            base,
+            host,
+            bucket_path,
+            key: key.to_owned(),
+            region,
+            credentials,
+        })
+    }
+
+    /// The object's body.
+    pub fn open(&self) -> Result<impl Read> {
+        let response = self
+            .send("GET", &[], None)
+            .with_context(|| t!("remote-open-failed", url = self.url.as_str()))?;
+        Ok(response.into_reader())
+    }
+
+    pub fn create(self) -> Upload {
+        Upload {
+            object: self,
+            buffer: Vec::new(),
+            upload_id: None,
+            etags: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Sends a signed request about the object.
+    fn send(
+        &self,
+        method: &str,
+        query: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<ureq::Response> {
+        let path = format!("{}/{}", self.bucket_path, uri_encode(&self.key, true));
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let payload_hash = hex::encode(Sha256::digest(body.unwrap_or_default()));
+        let date = amz_date(SystemTime::now());
+
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let mut canonical = format!("{method}\n{path}\n{query}\n");
+        for (name, value) in &headers {
+            let _ = writeln!(canonical, "{name}:{value}");
+        }
+        let _ = write!(canonical, "\n{signed_headers}\n{payload_hash}");
+        let scope = format!("{}/{}/s3/aws4_request", &date[..8], self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical.as_bytes()))
+        );
+        let mut key = hmac(
+            format!("AWS4{}", self.credentials.secret_access_key).as_bytes(),
+            &date[..8],
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+            self.credentials.access_key_id,
+            hex::encode(hmac(&key, &string_to_sign))
+        );
+
+        let mut url = format!("{}/{}", self.base, uri_encode(&self.key, true));
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+        let mut request = ureq::request(method, &url).set("Authorization", &authorization);
+        // ureq sets Host itself.
+        for (name, value) in &headers[1..] {
+            request = request.set(name, value);
+        }
+        let result = match body {
+            Some(body) => request.send_bytes(body),
+            None => request.call(),
+        };
+        result.map_err(|e| match e {
+            ureq::Error::Status(status, response) => {
+                let mut body = String::new();
+                let _ = response
+                    .into_reader()
+                    .take(MAX_ERROR_LENGTH)
+                    .read_to_string(&mut body);
+                anyhow!(t!(
+                    "s3-error-status",
+                    status = status,
+                    error = error_message(&body)
+                ))
+            }
+            e => anyhow!(e),
+        })
+    }
+}
+
+/// The `<Code>` and `<Message>` of an S3 error response.
+fn error_message(body: &str) -> String {
+    match (element(body, "Code"), element(body, "Message")) {
+        (Some(code), Some(message)) => format!("{code}: {message}"),
+        (Some(code), None) => code.to_owned(),
+        _ => t!("s3-no-details"),
+    }
+}
+
+/// The text of the first `<name>` element in `xml`.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let length = xml[start..].find(&format!("</{name}>"))?;
+    Some(&xml[start..start + length])
+}
+
+/// Writes an object; see the module documentation.
+pub struct Upload {
+    object: Object,
+    buffer: Vec<u8>,
+    /// Set once the first part is sent.
+    upload_id: Option<String>,
+    etags: Vec<String>,
+    finished: bool,
+}
+
+impl Upload {
+    /// Sends the next `PART_LENGTH` bytes, or all that is left with `last`.
+    fn send_part(&mut self, last: bool) -> Result<()> {
+        let part = match last {
+            true => mem::take(&mut self.buffer),
+            false => {
+                let rest = self.buffer.split_off(PART_LENGTH);
+                mem::replace(&mut self.buffer, rest)
+            }
+        };
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let response = self.object.send("POST", &[("uploads", "")], None)?;
+                let body = response.into_string()?;
+                let upload_id = element(&body, "UploadId")
+                    .ok_or_else(|| anyhow!(t!("s3-no-upload-id")))?
+                    .to_owned();
+                self.upload_id.insert(upload_id).clone()
+            }
+        };
+        let number = (self.etags.len() + 1).to_string();
+        let response = self.object.send(
+            "PUT",
+            &[("partNumber", &number), ("uploadId", &upload_id)],
+            Some(&part),
+        )?;
+        let etag = response
+            .header("ETag")
+            .ok_or_else(|| anyhow!(t!("s3-no-etag", part = number.as_str())))?;
+        self.etags.push(etag.to_owned());
+        Ok(())
+    }
+
+    fn complete(&mut self) -> Result<()> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            // Small enough for a single request.
+            self.object.send("PUT", &[], Some(&self.buffer))?;
+            return Ok(());
+        };
+        if !self.buffer.is_empty() {
+            self.send_part(true)?;
+        }
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in self.etags.iter().enumerate() {
+            let _ = write!(
+                body,
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag.replace('&', "&amp;").replace('"', "&quot;")
+            );
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let response =
+            self.object
+                .send("POST", &[("uploadId", &upload_id)], Some(body.as_bytes()))?;
+        // Completing can fail after the 200 has been sent.
+        let body = response.into_string()?;
+        if body.contains("<Error>") {
+            return Err(anyhow!(t!("s3-error", error = error_message(&body))));
+        }
+        Ok(())
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= PART_LENGTH {
+            self.send_part(false).map_err(|e| {
+                io::Error::other(t!(
+                    "remote-write-failed-because",
+                    url = self.object.url.as_str(),
+                    error = format!("{e:#}")
+                ))
+            })?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink for Upload {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.complete()
+            .with_context(|| t!("remote-write-failed", url = self.object.url.as_str()))?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if let (Some(upload_id), false) = (&self.upload_id, self.finished) {
+            let _ = self.object.send("DELETE", &[("uploadId", upload_id)], None);
+        }
+    }
+}
+
+/// Percent-encodes `value` as SigV4 wants, keeping `/` when `keep_slash`.
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `now` as `YYYYMMDDTHHMMSSZ`.
+fn amz_date(now: SystemTime) -> String {
+    let [year, month, day, hour, minute, second] = file_encryptor::date::utc(now);
+    format!("{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}Z")
+}
@@ -0,0 +1,435 @@
+//! What a run tells its caller: an exit status scripts can rely on and, with
+//! `--output-format json`, one JSON object per line on standard output for
+//! each file processed, then one for the whole run:
+//!
+//! ```text
+//! {"type":"file","input":"a.txt","output":"a.txt.enc","status":"ok","input_bytes":12,"output_bytes":121,"duration_ms":104}
+//! {"type":"file","input":"b.enc","output":"b","status":"error","input_bytes":90,"output_bytes":null,"duration_ms":98,"error":{"kind":"wrong-key","message":"Incorrect password, key file or identity"}}
+//! {"type":"result","status":"error","files_ok":1,"files_failed":1,"duration_ms":211,"exit_status":7,"error":{"kind":"other","message":"..."},"failures":[{"input":"b.enc","error":{"kind":"wrong-key","message":"..."}}]}
+//! ```
+//!
+//! The run's record lists every file of a directory that failed under
+//! `failures`, which `--keep-going` runs use to sum up all the files it
+//! carried on past.
+//!
+//! The messages otherwise printed on success are left out, so standard
+//! output holds nothing but the records. The error is still printed on
+//! standard error.
+//!
+//! `--output-format paths` prints instead just the path of each file done,
+//! one per line, quoted for the shell where it holds anything but plain
+//! characters, and `--print0` (`--output-format paths0`) each raw path ended
+//! by a NUL byte, for `xargs -0`, so even names with spaces or newlines can
+//! be piped on safely. Files that failed are left out.
+
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::Instant,
+};
+
+use file_encryptor::{i18n::t, DecryptError};
+
+use crate::{human_size, is_stdio, log};
+
+/// For a directory that failed after some of its files were done.
+const EXIT_PARTIAL_BATCH: u8 = 7;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Messages for people
+    #[default]
+    Text,
+    /// A JSON object per line for scripts: one per file, then one for the run
+    Json,
+    /// The path of each file done, one per line, quoted for the shell where needed
+    Paths,
+    /// The path of each file done, ended by a NUL byte, for `xargs -0`; `--print0` for short
+    Paths0,
+}
+
+/// What went wrong, as scripts see it.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// Anything else: exit status 1.
+    Other,
+    /// The password, key file, identity or `--aad` doesn't open the file: 3.
+    WrongKey,
+    /// The file is damaged or was tampered with: 4.
+    Damaged,
+    /// The file is from a newer version, or uses an algorithm this one
+    /// doesn't know: 5.
+    BadFormat,
+    /// Reading or writing failed: 6.
+    Io,
+}
+
+impl ErrorKind {
+    pub fn of(e: &anyhow::Error) -> ErrorKind {
+        match e.downcast_ref::<DecryptError>() {
+            Some(DecryptError::WrongKey | DecryptError::WrongAad(_)) => return ErrorKind::WrongKey,
+            Some(DecryptError::Unsupported(_)) => return ErrorKind::BadFormat,
+            Some(e) if e.is_damage() => return ErrorKind::Damaged,
+            _ => {}
+        }
+        match e.chain().any(|cause| cause.is::<io::Error>()) {
+            true => ErrorKind::Io,
+            false => ErrorKind::Other,
+        }
+    }
+
+    fn exit_status(self) -> u8 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::WrongKey => 3,
+            ErrorKind::Damaged => 4,
+            ErrorKind::BadFormat => 5,
+            ErrorKind::Io => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize)]
+struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+/// A file of a directory that failed, for the run's record.
+#[derive(Serialize)]
+struct Failure {
+    input: String,
+    error: Error,
+}
+
+impl Error {
+    fn of(result: &Result<()>) -> Option<Error> {
+        let e = result.as_ref().err()?;
+        Some(Error {
+            kind: ErrorKind::of(e),
+            message: format!("{e:#}"),
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    File {
+        input: &'a str,
+        output: Option<&'a str>,
+        status: Status,
+        input_bytes: Option<u64>,
+        /// Only once the output is written.
+        output_bytes: Option<u64>,
+        duration_ms: u128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Error>,
+    },
+    Result {
+        status: Status,
+        files_ok: u64,
+        files_failed: u64,
+        duration_ms: u128,
+        exit_status: u8,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Error>,
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        failures: &'a [Failure],
+    },
+}
+
+struct Run {
+    /// The input and any output of a run on one file.
+    target: Option<(String, Option<String>)>,
+    /// Whether the run is on a directory, whose files are reported one by
+    /// one instead.
+    batch: bool,
+    files_ok: u64,
+    files_failed: u64,
+    failures: Vec<Failure>,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static RUN: Mutex<Run> = Mutex::new(Run {
+    target: None,
+    batch: false,
+    files_ok: 0,
+    files_failed: 0,
+    failures: Vec::new(),
+});
+
+fn run() -> MutexGuard<'static, Run> {
+    RUN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Whether standard output is the records', with the messages for people
+/// left out.
+pub fn quiet() -> bool {
+    FORMAT.get().is_some_and(|&format| format != OutputFormat::Text)
+}
+
+/// Records that the run reads `input` and writes `output`, if anything, so
+/// that [`finish`] can report the file. The records can't share standard
+/// output with the data.
+pub fn target(input: &str, output: Option<&str>) -> Result<()> {
+    if quiet() && output.is_some_and(|output| is_stdio(Path::new(output))) {
+        bail!(t!("report-stdout-taken"));
+    }
+    run().target = Some((input.to_owned(), output.map(str::to_owned)));
+    log::info(&format!("started: {}", files(input, output)));
+    Ok(())
+}
+
+/// Marks the run as one on a directory, each of whose files is reported with
+/// [`file`].
+pub fn batch() {
+    run().batch = true;
+}
+
+/// Logs that one file of a directory is started.
+pub fn start(input: &str, output: &str) {
+    log::info(&format!("started: {}", files(input, Some(output))));
+}
+
+/// Reports one file of a directory, started at `started`.
+pub fn file(input: &str, output: &str, started: Instant, result: &Result<()>) {
+    let mut run = run();
+    match Error::of(result) {
+        None => run.files_ok += 1,
+        Some(error) => {
+            run.files_failed += 1;
+            run.failures.push(Failure {
+                input: input.to_owned(),
+                error,
+            });
+        }
+    }
+    drop(run);
+    log_file(input, Some(output), started, result);
+    if json() {
+        print(&file_record(input, Some(output), started, result));
+    }
+    if result.is_ok() {
+        print_path(output);
+    }
+}
+
+/// Counts the files of a directory done so far as failed, as their outputs
+/// were removed again.
+pub fn rolled_back() {
+    let mut run = run();
+    run.files_failed += run.files_ok;
+    run.files_ok = 0;
+}
+
+/// Reports the run, started at `started`, and returns its exit status.
+pub fn finish(started: Instant, result: &Result<()>) -> ExitCode {
+    let mut run = run();
+    if let (Some((input, output)), false) = (run.target.take(), run.batch) {
+        match result {
+            Ok(()) => run.files_ok += 1,
+            Err(_) => run.files_failed += 1,
+        }
+        log_file(&input, output.as_deref(), started, result);
+        if json() {
+            print(&file_record(&input, output.as_deref(), started, result));
+        }
+        if result.is_ok() {
+            print_path(output.as_deref().unwrap_or(&input));
+        }
+    }
+    // Unless a file's failure said it already.
+    if let (Err(e), 0) = (result, run.files_failed) {
+        log::error(&format!("{e:#}"));
+    }
+    let exit_status = exit_status(result, run.batch, run.files_ok);
+    let outcome = match result {
+        Ok(()) => "finished".to_owned(),
+        Err(_) => format!("failed with exit status {exit_status}"),
+    };
+    let counts = match (run.files_ok, run.files_failed) {
+        (0, 0) => String::new(),
+        (ok, failed) => format!(": {} done, {failed} failed", plural(ok)),
+    };
+    log::info(&format!(
+        "run {outcome}{counts} in {} ms",
+        started.elapsed().as_millis()
+    ));
+    if json() {
+        print(&Record::Result {
+            status: status(result),
+            files_ok: run.files_ok,
+            files_failed: run.files_failed,
+            duration_ms: started.elapsed().as_millis(),
+            exit_status,
+            error: Error::of(result),
+            failures: &run.failures,
+        });
+    }
+    ExitCode::from(exit_status)
+}
+
+/// The exit status of a run that ended with `result`, which for a directory
+/// that got `files_ok` files done before it failed is [`EXIT_PARTIAL_BATCH`].
+pub fn exit_status(result: &Result<()>, batch: bool, files_ok: u64) -> u8 {
+    match result {
+        Ok(()) => 0,
+        Err(_) if batch && files_ok > 0 => EXIT_PARTIAL_BATCH,
+        Err(e) => ErrorKind::of(e).exit_status(),
+    }
+}
+
+fn file_record<'a>(
+    input: &'a str,
+    output: Option<&'a str>,
+    started: Instant,
+    result: &Result<()>,
+) -> Record<'a> {
+    Record::File {
+        input,
+        output,
+        status: status(result),
+        input_bytes: size(input),
+        output_bytes: output.filter(|_| result.is_ok()).and_then(size),
+        duration_ms: started.elapsed().as_millis(),
+        error: Error::of(result),
+    }
+}
+
+/// Logs how a file, started at `started`, went.
+fn log_file(input: &str, output: Option<&str>, started: Instant, result: &Result<()>) {
+    let files = files(input, output);
+    let milliseconds = started.elapsed().as_millis();
+    match result {
+        Ok(()) => {
+            let sizes = match (size(input), output.and_then(size)) {
+                (Some(input), Some(output)) => {
+                    format!(", {} -> {}", human_size(input), human_size(output))
+                }
+                (Some(size), None) | (None, Some(size)) => format!(", {}", human_size(size)),
+                (None, None) => String::new(),
+            };
+            log::info(&format!("finished: {files}{sizes} in {milliseconds} ms"));
+        }
+        Err(e) => log::error(&format!("failed: {files}: {e:#}")),
+    }
+}
+
+fn files(input: &str, output: Option<&str>) -> String {
+    match output {
+        Some(output) => format!("{input} -> {output}"),
+        None => input.to_owned(),
+    }
+}
+
+fn plural(files: u64) -> String {
+    match files {
+        1 => "1 file".to_owned(),
+        files => format!("{files} files"),
+    }
+}
+
+fn status(result: &Result<()>) -> Status {
+    match result {
+        Ok(()) => Status::Ok,
+        Err(_) => Status::Error,
+    }
+}
+
+/// The size of the file at `path`, unless it is standard input or output or
+/// not a file.
+fn size(path: &str) -> Option<u64> {
+    let path = Path::new(path);
+    if is_stdio(path) {
+        return None;
+    }
+    fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+/// Prints `path` for `--output-format paths` or `paths0`.
+fn print_path(path: &str) {
+    match FORMAT.get() {
+        Some(OutputFormat::Paths) => println!("{}", shell_quoted(path)),
+        Some(OutputFormat::Paths0) => {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout
+                .write_all(path.as_bytes())
+                .and_then(|()| stdout.write_all(b"\0"));
+        }
+        _ => {}
+    }
+}
+
+/// `path` as a shell word: as it is if it holds only characters no shell
+/// treats specially, in single quotes otherwise, and as a `$'...'` string,
+/// which bash, zsh and ksh read, if it holds control characters such as a
+/// newline, so each path stays on one line.
+fn shell_quoted(path: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:@%+=,".contains(c);
+    if !path.is_empty() && path.chars().all(plain) {
+        return Cow::Borrowed(path);
+    }
+    if !path.chars().any(char::is_control) {
+        return Cow::Owned(format!("'{}'", path.replace('\'', "'\\''")));
+    }
+    let mut quoted = String::from("$'");
+    for c in path.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\'' | '\\' => quoted.extend(['\\', c]),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+fn print(record: &Record) {
+    println!(
+        "{}",
+        serde_json::to_string(record).expect("records have only string keys")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_quoted_for_the_shell() {
+        assert_eq!(shell_quoted("out/a-1.txt.enc"), "out/a-1.txt.enc");
+        assert_eq!(shell_quoted("my file's.enc"), "'my file'\\''s.enc'");
+        assert_eq!(shell_quoted("a\nb\\c\u{7f}"), "$'a\\nb\\\\c\\x7f'");
+        assert_eq!(shell_quoted(""), "''");
+    }
+}
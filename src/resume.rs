@@ -0,0 +1,149 @@
+//! `--resume`: lets a long `--stream` encryption or decryption that was
+//! interrupted carry on from its last checkpoint instead of starting over.
+//!
+//! The output is written to `.NAME.partial` beside it, which a failure leaves
+//! in place, and every [`EVERY`] chunks the number written so far goes to
+//! `.NAME.checkpoint`, once they are on disk, along with the input's size and
+//! modification time. A later run with `--resume` carries on after those
+//! chunks; a checkpoint for an input that has changed since is discarded.
+//! Every chunk is authenticated on its own, so the chunks kept from the
+//! earlier run are checked like the rest when the file is decrypted.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use file_encryptor::i18n::t;
+
+use crate::log;
+
+/// Chunks between checkpoints: 64 MiB of plaintext.
+const EVERY: u64 = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct Input {
+    length: u64,
+    modified: (u64, u32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    input: Input,
+    chunks: u64,
+}
+
+/// The checkpoint of one output.
+pub struct Progress {
+    path: PathBuf,
+    input: Input,
+}
+
+/// `.NAME.suffix` beside `output`.
+fn beside(output: &Path, suffix: &str) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// Where the output is written until it is complete.
+pub fn partial_path(output: &Path) -> PathBuf {
+    beside(output, "partial")
+}
+
+fn describe(input: &Path) -> Result<Input> {
+    let metadata = fs::metadata(input)
+        .with_context(|| t!("file-read-failed", path = input.display().to_string()))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    Ok(Input {
+        length: metadata.len(),
+        modified: (modified.as_secs(), modified.subsec_nanos()),
+    })
+}
+
+impl Progress {
+    /// Looks up the checkpoint of `input` to `output`, returning the number of
+    /// chunks already written: 0 when there is nothing to resume.
+    pub fn start(input: &Path, output: &Path) -> Result<(Progress, u64)> {
+        let progress = Progress {
+            path: beside(output, "checkpoint"),
+            input: describe(input)?,
+        };
+        let checkpoint = fs::read(&progress.path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Checkpoint>(&data).ok());
+        let chunks = match checkpoint {
+            Some(checkpoint) if checkpoint.input == progress.input => {
+                log::notice(&t!(
+                    "resume-continuing",
+                    path = output.display().to_string()
+                ));
+                checkpoint.chunks
+            }
+            Some(_) => {
+                log::warn(&t!("resume-stale", path = input.display().to_string()));
+                0
+            }
+            None => 0,
+        };
+        Ok((progress, chunks))
+    }
+
+    /// The checkpoint file, to name in errors.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records that the first `chunks` chunks are on disk.
+    fn save(&self, chunks: u64) -> Result<()> {
+        let checkpoint = Checkpoint {
+            input: self.input,
+            chunks,
+        };
+        let data = serde_json::to_vec(&checkpoint)?;
+        // Written aside and renamed, so a kill mid-write keeps the last one.
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, data)
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .with_context(|| {
+                t!(
+                    "checkpoint-save-failed",
+                    path = self.path.display().to_string()
+                )
+            })
+    }
+
+    /// Saves a checkpoint every [`EVERY`] chunks, once the `chunks` written
+    /// through `writer` so far are flushed to disk.
+    pub fn reached(&self, writer: &mut BufWriter<fs::File>, chunks: u64) -> Result<()> {
+        if !chunks.is_multiple_of(EVERY) {
+            return Ok(());
+        }
+        writer
+            .flush()
+            .and_then(|()| writer.get_ref().sync_data())
+            .context(t!("output-sync-failed"))?;
+        self.save(chunks)
+    }
+
+    /// Forgets the checkpoint once the output is complete.
+    pub fn finish(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| {
+                t!(
+                    "checkpoint-delete-failed",
+                    path = self.path.display().to_string()
+                )
+            }),
+            _ => Ok(()),
+        }
+    }
+}
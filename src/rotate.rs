@@ -0,0 +1,163 @@
+//! `rotate`: changes the password of every encrypted file in a directory
+//! tree, as [`rekey`](crate::rekey) does for one. The new password's key is
+//! derived once for the whole tree. A file that fails doesn't stop the rest;
+//! the files the old password doesn't open are listed apart from those that
+//! failed for another reason once every file has been tried.
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use std::{path::Path, time::Instant};
+use zeroize::Zeroizing;
+
+use file_encryptor::{i18n::t, DecryptError, Kdf};
+
+use crate::{
+    check_key_source, decrypted_name, encryptor, policy, prompt_new_password, prompt_password,
+    read_key_file, rekey, report, walk, IoOptions, KdfOptions, PasswordSource,
+};
+
+#[derive(Args)]
+pub struct RotateOptions {
+    /// Directory whose .enc files, at any depth, are changed
+    #[arg(long, value_name = "DIR")]
+    dir: String,
+    /// The current password; prompted for if omitted
+    #[arg(
+        short = 'p',
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    old_password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// The new password; prompted for, twice, if omitted
+    #[arg(long, value_name = "PASSWORD")]
+    new_password: Option<String>,
+    /// The key file files encrypted with a password and --key-file also need; it stays needed
+    #[arg(long, value_name = "FILE")]
+    key_file: Option<String>,
+    /// Use the new password even if it breaks the administrator's password policy; the override is logged
+    #[arg(long)]
+    force_weak_password: bool,
+    /// Use the new password even if it is easy to guess, with a warning
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// The associated data the files were bound to with `encrypt --aad`; they stay bound to it
+    #[arg(long, value_name = "TEXT")]
+    aad: Option<String>,
+    /// Only check that the old password opens every file, and say what would be done to it,
+    /// changing nothing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    kdf: KdfOptions,
+    /// Flush each rewritten file and its directory to disk before moving on
+    #[arg(long)]
+    fsync: bool,
+}
+
+pub fn run(options: &RotateOptions) -> Result<()> {
+    report::batch();
+    let dir = Path::new(&options.dir);
+    let mut files = Vec::new();
+    for (relative, _) in
+        walk::walk(dir).with_context(|| t!("dir-list-failed", path = dir.display().to_string()))?
+    {
+        let name = relative.to_string_lossy();
+        if decrypted_name(&name).is_ok() {
+            files.push(dir.join(relative));
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!(t!("rotate-empty", path = options.dir.as_str())));
+    }
+    let io = IoOptions {
+        fsync: options.fsync,
+        // One file after another, each reported on a line of its own.
+        quiet: true,
+        ..IoOptions::default()
+    };
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let kdf = options.kdf.kdf()?;
+    let aad = options.aad.as_ref().map(|aad| aad.as_bytes().to_vec());
+    let old_password = match options.password_source.or(&options.old_password)? {
+        Some(password) => password,
+        None => prompt_password(false)?,
+    };
+    // One session, so files that share a salt derive the old key once.
+    let keys = encryptor(Some(&old_password), key_file, Kdf::default()).with_aad(aad.clone());
+    let new_password = match &options.new_password {
+        Some(password) => Zeroizing::new(password.clone()),
+        None => prompt_new_password()?,
+    };
+    if !options.dry_run {
+        policy::Policy::load()?.enforce(
+            &new_password,
+            options.force_weak_password,
+            options.allow_weak_password,
+        )?;
+    }
+    let mut new = rekey::NewPassword::new(&new_password, key_file, kdf, aad);
+
+    let mut wrong_password = Vec::new();
+    let mut failed = Vec::new();
+    for path in &files {
+        let started = Instant::now();
+        let name = path.display().to_string();
+        report::start(&name, &name);
+        let result = rekey::open(path, io).and_then(|(head, input)| {
+            check_key_source(&head, &keys)?;
+            rekey::rekey_file(
+                path,
+                head,
+                input,
+                keys.clone(),
+                &mut new,
+                options.dry_run,
+                io,
+            )
+        });
+        let (message, result) = match result {
+            Ok(message) => (Some(message), Ok(())),
+            Err(e) => (None, Err(e)),
+        };
+        report::file(&name, &name, started, &result);
+        match result {
+            Ok(()) if !report::quiet() => println!("{}", message.unwrap_or_default()),
+            Ok(()) => {}
+            Err(e) => match e.downcast_ref::<DecryptError>() {
+                Some(DecryptError::WrongKey) => wrong_password.push(name),
+                _ => failed.push((name, e)),
+            },
+        }
+    }
+
+    let changed = files.len() - wrong_password.len() - failed.len();
+    if !report::quiet() {
+        match options.dry_run {
+            true => println!("{}", t!("rotate-checked", count = changed)),
+            false => println!("{}", t!("rotate-done", count = changed)),
+        }
+    }
+    if !wrong_password.is_empty() {
+        eprintln!("{}", t!("rotate-wrong-password"));
+        for name in &wrong_password {
+            eprintln!("  {name}");
+        }
+    }
+    if !failed.is_empty() {
+        eprintln!("{}", t!("rotate-errors"));
+        for (name, e) in &failed {
+            eprintln!("  {name}: {e:#}");
+        }
+    }
+    match wrong_password.len() + failed.len() {
+        0 => Ok(()),
+        count => Err(anyhow!(t!(
+            "rotate-failed",
+            failed = count,
+            count = files.len()
+        ))),
+    }
+}
@@ -0,0 +1,457 @@
+//! The saltpack encryption format (version 2, armored), so messages can be
+//! exchanged with saltpack/Keybase tooling.
+//!
+//! Messages we write always have an anonymous sender: there is no long-term
+//! sender identity in this tool, so the ephemeral key stands in for it as the
+//! spec allows. Recipients are X25519 public keys and are listed visibly.
+
+use anyhow::{anyhow, bail, Context, Result};
+use crypto_box::{
+    aead::{Aead, KeyInit},
+    PublicKey, SalsaBox, SecretKey,
+};
+use crypto_secretbox::XSalsa20Poly1305;
+use hmac::{Hmac, Mac, NewMac};
+use rmpv::Value;
+use sha2::{Digest, Sha512};
+use subtle::ConstantTimeEq;
+
+use file_encryptor::i18n::t;
+
+use crate::entropy;
+
+const FORMAT_NAME: &str = "saltpack";
+const VERSION_MAJOR: u64 = 2;
+const MODE_ENCRYPTION: u64 = 0;
+const CHUNK_SIZE: usize = 1 << 20;
+
+const SENDER_KEY_NONCE: &[u8; 24] = b"saltpack_sender_key_sbox";
+const RECIPIENT_NONCE_PREFIX: &[u8; 16] = b"saltpack_recipsb";
+const PAYLOAD_NONCE_PREFIX: &[u8; 16] = b"saltpack_ploadsb";
+
+const ARMOR_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const ARMOR_BLOCK_BYTES: usize = 32;
+const ARMOR_WORD_CHARS: usize = 15;
+const ARMOR_LINE_WORDS: usize = 200;
+const ARMOR_MESSAGE_TYPE: &str = "ENCRYPTED MESSAGE";
+
+/// Encrypts `plaintext` to every key in `recipients`, returning the armored
+/// message.
+pub fn encrypt(recipients: &[PublicKey], plaintext: &[u8]) -> Result<String> {
+    if recipients.is_empty() {
+        bail!(t!("saltpack-no-recipients"));
+    }
+    let payload_key: [u8; 32] = entropy::bytes()?;
+    let ephemeral = SecretKey::from(entropy::bytes::<32>()?);
+    let ephemeral_public = ephemeral.public_key();
+    let payload_box = XSalsa20Poly1305::new(&payload_key.into());
+
+    // Anonymous sender: the sender key is the ephemeral key.
+    let sender_secretbox = payload_box
+        .encrypt(
+            SENDER_KEY_NONCE.into(),
+            ephemeral_public.as_bytes().as_slice(),
+        )
+        .map_err(|e| anyhow!(e))?;
+    let mut recipient_entries = Vec::with_capacity(recipients.len());
+    for (index, recipient) in recipients.iter().enumerate() {
+        let key_box = SalsaBox::new(recipient, &ephemeral)
+            .encrypt(
+                &counted_nonce(RECIPIENT_NONCE_PREFIX, index as u64).into(),
+                payload_key.as_slice(),
+            )
+            .map_err(|e| anyhow!(e))?;
+        recipient_entries.push(Value::Array(vec![
+            Value::Binary(recipient.as_bytes().to_vec()),
+            Value::Binary(key_box),
+        ]));
+    }
+    let header = Value::Array(vec![
+        Value::from(FORMAT_NAME),
+        Value::Array(vec![Value::from(VERSION_MAJOR), Value::from(0u64)]),
+        Value::from(MODE_ENCRYPTION),
+        Value::Binary(ephemeral_public.as_bytes().to_vec()),
+        Value::Binary(sender_secretbox),
+        Value::Array(recipient_entries),
+    ]);
+    let header_bytes = encode_value(&header);
+    let header_hash = Sha512::digest(&header_bytes);
+
+    let mac_keys: Vec<[u8; 32]> = recipients
+        .iter()
+        .enumerate()
+        .map(|(index, recipient)| {
+            let shared = SalsaBox::new(recipient, &ephemeral);
+            mac_key(&header_hash, index as u64, &shared, &shared)
+        })
+        .collect::<Result<_>>()?;
+
+    let mut message = encode_value(&Value::Binary(header_bytes));
+    let mut chunks = plaintext.chunks(CHUNK_SIZE).peekable();
+    let mut packet_number = 0u64;
+    loop {
+        let chunk = chunks.next().unwrap_or_default();
+        let is_final = chunks.peek().is_none();
+        let nonce = counted_nonce(PAYLOAD_NONCE_PREFIX, packet_number);
+        let secretbox = payload_box
+            .encrypt(&nonce.into(), chunk)
+            .map_err(|e| anyhow!(e))?;
+        let digest = payload_digest(&header_hash, &nonce, is_final, &secretbox);
+        let authenticators = mac_keys
+            .iter()
+            .map(|key| Value::Binary(authenticator(key, &digest).to_vec()))
+            .collect();
+        message.extend(encode_value(&Value::Array(vec![
+            Value::Boolean(is_final),
+            Value::Array(authenticators),
+            Value::Binary(secretbox),
+        ])));
+        packet_number += 1;
+        if is_final {
+            break;
+        }
+    }
+    Ok(armor(&message))
+}
+
+/// Decrypts an armored saltpack message addressed to `identity`.
+pub fn decrypt(identity: &SecretKey, armored: &str) -> Result<Vec<u8>> {
+    let message = dearmor(armored)?;
+    let mut reader = message.as_slice();
+
+    let header_bytes = match read_value(&mut reader)? {
+        Value::Binary(bytes) => bytes,
+        _ => bail!(t!("saltpack-header-not-binary")),
+    };
+    let header_hash = Sha512::digest(&header_bytes);
+    let header = array(read_value(&mut header_bytes.as_slice())?, "header")?;
+    if header.len() < 6 || header[0].as_str() != Some(FORMAT_NAME) {
+        bail!(t!("saltpack-not-message"));
+    }
+    let version = array(header[1].clone(), "version")?;
+    if version.first().and_then(Value::as_u64) != Some(VERSION_MAJOR) {
+        bail!(t!("saltpack-version"));
+    }
+    if header[2].as_u64() != Some(MODE_ENCRYPTION) {
+        bail!(t!("saltpack-not-encryption"));
+    }
+    let ephemeral_public = PublicKey::from_slice(binary(&header[3], "ephemeral-key")?)
+        .map_err(|_| anyhow!(t!("saltpack-ephemeral-length")))?;
+    let sender_secretbox = binary(&header[4], "sender-secretbox")?;
+    let recipient_entries = array(header[5].clone(), "recipients")?;
+
+    // Find our entry: a matching visible key, or any anonymous one that opens.
+    let our_public = identity.public_key();
+    let key_box = SalsaBox::new(&ephemeral_public, identity);
+    let (index, payload_key) = recipient_entries
+        .iter()
+        .enumerate()
+        .find_map(|(index, entry)| {
+            let entry = entry.as_array()?;
+            match entry.first()? {
+                Value::Binary(key) if key.as_slice() != our_public.as_bytes() => return None,
+                Value::Binary(_) | Value::Nil => {}
+                _ => return None,
+            }
+            let nonce = counted_nonce(RECIPIENT_NONCE_PREFIX, index as u64);
+            let payload_key = key_box
+                .decrypt(&nonce.into(), entry.get(1)?.as_slice()?)
+                .ok()?;
+            Some((index, <[u8; 32]>::try_from(payload_key).ok()?))
+        })
+        .ok_or_else(|| anyhow!(t!("saltpack-not-for-identity")))?;
+
+    let payload_box = XSalsa20Poly1305::new(&payload_key.into());
+    let sender_public = payload_box
+        .decrypt(SENDER_KEY_NONCE.into(), sender_secretbox)
+        .ok()
+        .and_then(|key| PublicKey::from_slice(&key).ok())
+        .ok_or_else(|| anyhow!(t!("saltpack-sender-corrupt")))?;
+    // The box operations are symmetric in the two key pairs, so we get the same
+    // MAC key the sender computed.
+    let mac_key = mac_key(
+        &header_hash,
+        index as u64,
+        &SalsaBox::new(&sender_public, identity),
+        &key_box,
+    )?;
+
+    let mut plaintext = Vec::new();
+    let mut packet_number = 0u64;
+    loop {
+        if reader.is_empty() {
+            bail!(t!("saltpack-truncated"));
+        }
+        let packet = array(read_value(&mut reader)?, "packet")?;
+        let (is_final, authenticators, secretbox) = match packet.as_slice() {
+            [Value::Boolean(is_final), Value::Array(authenticators), Value::Binary(secretbox)] => {
+                (*is_final, authenticators, secretbox)
+            }
+            _ => bail!(t!("saltpack-packet-malformed")),
+        };
+        let nonce = counted_nonce(PAYLOAD_NONCE_PREFIX, packet_number);
+        let expected = authenticator(
+            &mac_key,
+            &payload_digest(&header_hash, &nonce, is_final, secretbox),
+        );
+        let provided = authenticators
+            .get(index)
+            .and_then(Value::as_slice)
+            .ok_or_else(|| anyhow!(t!("saltpack-no-authenticator")))?;
+        if !bool::from(expected.as_slice().ct_eq(provided)) {
+            bail!(t!(
+                "saltpack-packet-unauthenticated",
+                packet = packet_number
+            ));
+        }
+        let chunk = payload_box
+            .decrypt(&nonce.into(), secretbox.as_slice())
+            .map_err(|_| anyhow!(t!("saltpack-packet-corrupt", packet = packet_number)))?;
+        plaintext.extend_from_slice(&chunk);
+        packet_number += 1;
+        if is_final {
+            break;
+        }
+    }
+    if !reader.is_empty() {
+        bail!(t!("saltpack-trailing"));
+    }
+    Ok(plaintext)
+}
+
+/// Derives a recipient's MAC key from two boxes of 32 zero bytes: one keyed
+/// between the sender's long-term key and the recipient, one between the
+/// ephemeral key and the recipient.
+fn mac_key(
+    header_hash: &[u8],
+    index: u64,
+    long_term_box: &SalsaBox,
+    ephemeral_box: &SalsaBox,
+) -> Result<[u8; 32]> {
+    let mut nonce = [0u8; 24];
+    nonce[..16].copy_from_slice(&header_hash[..16]);
+    nonce[16..].copy_from_slice(&index.to_be_bytes());
+    nonce[23] &= 0xfe;
+    let long_term = long_term_box
+        .encrypt(&nonce.into(), [0u8; 32].as_slice())
+        .map_err(|e| anyhow!(e))?;
+    nonce[23] |= 0x01;
+    let ephemeral = ephemeral_box
+        .encrypt(&nonce.into(), [0u8; 32].as_slice())
+        .map_err(|e| anyhow!(e))?;
+
+    let mut hasher = Sha512::new();
+    hasher.update(&long_term[long_term.len() - 32..]);
+    hasher.update(&ephemeral[ephemeral.len() - 32..]);
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hasher.finalize()[..32]);
+    Ok(key)
+}
+
+fn payload_digest(
+    header_hash: &[u8],
+    nonce: &[u8; 24],
+    is_final: bool,
+    secretbox: &[u8],
+) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(header_hash);
+    hasher.update(nonce);
+    hasher.update([is_final as u8]);
+    hasher.update(secretbox);
+    hasher.finalize().to_vec()
+}
+
+/// HMAC-SHA512 truncated to 32 bytes (crypto_auth).
+fn authenticator(mac_key: &[u8; 32], digest: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(mac_key).expect("HMAC accepts any key length");
+    mac.update(digest);
+    let mut tag = [0u8; 32];
+    tag.copy_from_slice(&mac.finalize().into_bytes()[..32]);
+    tag
+}
+
+fn counted_nonce(prefix: &[u8; 16], counter: u64) -> [u8; 24] {
+    let mut nonce = [0u8; 24];
+    nonce[..16].copy_from_slice(prefix);
+    nonce[16..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    rmpv::encode::write_value(&mut out, value).expect("writing to a Vec cannot fail");
+    out
+}
+
+fn read_value(reader: &mut &[u8]) -> Result<Value> {
+    rmpv::decode::read_value(reader).with_context(|| t!("saltpack-malformed"))
+}
+
+/// `what` names the part for the message: `header`, `version`,
+/// `recipients` or `packet`.
+fn array(value: Value, what: &str) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => bail!(t!("saltpack-not-list", what = what)),
+    }
+}
+
+/// `what` names the part for the message: `ephemeral-key` or
+/// `sender-secretbox`.
+fn binary<'a>(value: &'a Value, what: &str) -> Result<&'a [u8]> {
+    value
+        .as_slice()
+        .ok_or_else(|| anyhow!(t!("saltpack-not-binary", what = what)))
+}
+
+/// Base62 armor: 32-byte blocks become 43 characters, grouped into 15-character
+/// words and 200-word lines between BEGIN/END markers.
+fn armor(message: &[u8]) -> String {
+    let mut chars = Vec::new();
+    for block in message.chunks(ARMOR_BLOCK_BYTES) {
+        chars.extend(encode_block(block));
+    }
+    let words: Vec<&str> = chars
+        .chunks(ARMOR_WORD_CHARS)
+        .map(|word| std::str::from_utf8(word).expect("alphabet is ASCII"))
+        .collect();
+    let body = words
+        .chunks(ARMOR_LINE_WORDS)
+        .map(|line| line.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("BEGIN SALTPACK {ARMOR_MESSAGE_TYPE}. {body}. END SALTPACK {ARMOR_MESSAGE_TYPE}.\n")
+}
+
+/// Whether `text` starts as an armored saltpack encrypted message does.
+pub fn is_armored(text: &str) -> bool {
+    text.split('.')
+        .next()
+        .is_some_and(|begin| is_marker(begin, "BEGIN"))
+}
+
+/// Whether `line` is the `marker` line "BEGIN [BRAND] SALTPACK ENCRYPTED
+/// MESSAGE", where the brand is optional, or its END counterpart.
+fn is_marker(line: &str, marker: &str) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    words.first() == Some(&marker)
+        && words.ends_with(&["SALTPACK", "ENCRYPTED", "MESSAGE"])
+        && words.len() <= 5
+}
+
+fn dearmor(armored: &str) -> Result<Vec<u8>> {
+    let mut parts = armored.split('.');
+    let (Some(begin), Some(body), Some(end)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!(t!("saltpack-not-armored-text"));
+    };
+    if !is_marker(begin, "BEGIN") || !is_marker(end, "END") {
+        bail!(t!("saltpack-not-armored-encrypted"));
+    }
+
+    let chars: Vec<u8> = body.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let mut message =
+        Vec::with_capacity(chars.len() * ARMOR_BLOCK_BYTES / chars_for_bytes(ARMOR_BLOCK_BYTES));
+    for block in chars.chunks(chars_for_bytes(ARMOR_BLOCK_BYTES)) {
+        message.extend(decode_block(block)?);
+    }
+    Ok(message)
+}
+
+/// Smallest number of base62 characters that can hold `len` bytes.
+fn chars_for_bytes(len: usize) -> usize {
+    ((len * 8) as f64 / 62f64.log2()).ceil() as usize
+}
+
+fn encode_block(block: &[u8]) -> Vec<u8> {
+    // Repeated long division of the big-endian block by 62.
+    let mut number = block.to_vec();
+    let mut out = vec![ARMOR_ALPHABET[0]; chars_for_bytes(block.len())];
+    for slot in out.iter_mut().rev() {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / 62) as u8;
+            remainder = value % 62;
+        }
+        *slot = ARMOR_ALPHABET[remainder as usize];
+    }
+    out
+}
+
+fn decode_block(block: &[u8]) -> Result<Vec<u8>> {
+    let len = (1..=ARMOR_BLOCK_BYTES)
+        .rev()
+        .find(|&len| chars_for_bytes(len) == block.len())
+        .ok_or_else(|| anyhow!(t!("saltpack-armor-block-length")))?;
+    let mut number = vec![0u8; len];
+    for &c in block {
+        let digit = ARMOR_ALPHABET.iter().position(|&a| a == c).ok_or_else(|| {
+            anyhow!(t!(
+                "saltpack-armor-character",
+                character = format!("{:?}", c as char)
+            ))
+        })?;
+        let mut carry = digit as u32;
+        for byte in number.iter_mut().rev() {
+            let value = u32::from(*byte) * 62 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            bail!(t!("saltpack-armor-overflow"));
+        }
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(seed: u8) -> SecretKey {
+        SecretKey::from([seed; crypto_box::KEY_SIZE])
+    }
+
+    #[test]
+    fn every_recipient_opens_the_message() {
+        let recipients = [identity(1).public_key(), identity(2).public_key()];
+        // More than one payload chunk.
+        let plaintext: Vec<u8> = (0..CHUNK_SIZE + 7).map(|i| i as u8).collect();
+        let armored = encrypt(&recipients, &plaintext).unwrap();
+        assert!(is_armored(&armored));
+        assert!(armored.ends_with("END SALTPACK ENCRYPTED MESSAGE.\n"));
+        for seed in [1, 2] {
+            assert_eq!(decrypt(&identity(seed), &armored).unwrap(), plaintext);
+        }
+        assert!(decrypt(&identity(3), &armored).is_err());
+        assert_eq!(
+            decrypt(&identity(1), &encrypt(&recipients, b"").unwrap()).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    fn altered_and_truncated_messages_are_refused() {
+        let armored = encrypt(&[identity(1).public_key()], b"hello").unwrap();
+        let message = dearmor(&armored).unwrap();
+        for at in [message.len() / 2, message.len() - 1] {
+            let mut altered = message.clone();
+            altered[at] ^= 1;
+            assert!(decrypt(&identity(1), &armor(&altered)).is_err());
+        }
+        let truncated = armor(&message[..message.len() - 1]);
+        assert!(decrypt(&identity(1), &truncated).is_err());
+        assert!(decrypt(&identity(1), &armored.replace("BEGIN", "START")).is_err());
+    }
+
+    #[test]
+    fn armor_reads_back_for_any_length() {
+        for length in [0, 1, 31, 32, 33, 1000] {
+            let bytes: Vec<u8> = (0..length).map(|i| (i * 7) as u8).collect();
+            assert_eq!(dearmor(&armor(&bytes)).unwrap(), bytes);
+        }
+        assert_eq!(encode_block(&[0; ARMOR_BLOCK_BYTES]), [b'0'; 43]);
+        assert_eq!(encode_block(&[0xff]), b"47");
+    }
+}
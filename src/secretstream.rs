@@ -0,0 +1,271 @@
+//! libsodium's `crypto_secretstream_xchacha20poly1305`, as `--format
+//! secretstream`, so files can be exchanged with the many programs built on
+//! sodium.
+//!
+//! A file is the 24-byte stream header and then one message per chunk: the
+//! encrypted tag byte, the ciphertext and a 16-byte Poly1305 tag, under a
+//! key that HChaCha20 derives from the stream key and the header and that
+//! each message's MAC moves on. The last message is tagged `FINAL`, so a
+//! file cut short at a chunk boundary is noticed. The framing records no
+//! lengths, so programs agree on the chunk length out of band: this writes
+//! the 4096-byte chunks of libsodium's own example, and when decrypting
+//! tries every power of two from 256 bytes to 16 MiB until the first
+//! message authenticates. The key is a raw 32 bytes, which a key file is.
+
+use anyhow::{anyhow, Result};
+use chacha20::{
+    cipher::{consts::U10, KeyIvInit, StreamCipher, StreamCipherSeek},
+    hchacha, ChaCha20,
+};
+use poly1305::{universal_hash::KeyInit, Poly1305};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use file_encryptor::{i18n::t, DecryptError};
+
+use crate::entropy;
+
+pub const HEADER_LENGTH: usize = 24;
+/// What each message adds to its chunk: the tag byte and the MAC.
+const A_LENGTH: usize = 1 + MAC_LENGTH;
+const MAC_LENGTH: usize = 16;
+const TAG_MESSAGE: u8 = 0;
+const TAG_REKEY: u8 = 2;
+const TAG_FINAL: u8 = 3;
+/// The chunk length this writes.
+pub const CHUNK_LENGTH: usize = 4096;
+/// The chunk lengths tried when decrypting.
+const CHUNK_LENGTHS: std::ops::RangeInclusive<u32> = 8..=24;
+
+/// The key and nonce, which every message changes.
+#[derive(Clone)]
+struct State {
+    key: Zeroizing<[u8; 32]>,
+    /// A 32-bit little-endian counter, then the 8-byte inner nonce.
+    nonce: [u8; 12],
+}
+
+impl State {
+    fn new(key: &[u8; 32], header: &[u8; HEADER_LENGTH]) -> State {
+        let mut state = State {
+            key: Zeroizing::new(hchacha::<U10>(key.into(), header[..16].into()).into()),
+            nonce: [0; 12],
+        };
+        state.nonce[4..].copy_from_slice(&header[16..]);
+        state.reset_counter();
+        state
+    }
+
+    fn reset_counter(&mut self) {
+        self.nonce[..4].copy_from_slice(&1u32.to_le_bytes());
+    }
+
+    /// ChaCha20 under the current key and nonce, from block `counter`.
+    fn keystream(&self, counter: u64) -> ChaCha20 {
+        let mut cipher = ChaCha20::new(self.key.as_ref().into(), (&self.nonce).into());
+        cipher.seek(counter * 64);
+        cipher
+    }
+
+    /// The MAC over a message's tag block and ciphertext, with libsodium's
+    /// padding: it pads the ciphertext by its length modulo 16 rather than up
+    /// to a multiple of 16, which every implementation has to match.
+    fn mac(&self, block: &[u8; 64], ciphertext: &[u8]) -> [u8; MAC_LENGTH] {
+        let mut poly_key = Zeroizing::new([0; 32]);
+        self.keystream(0).apply_keystream(poly_key.as_mut());
+        let mut data = Vec::with_capacity(block.len() + ciphertext.len() + 32);
+        // No associated data, and so no padding for it.
+        data.extend_from_slice(block);
+        data.extend_from_slice(ciphertext);
+        data.resize(data.len() + ciphertext.len() % 16, 0);
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&((block.len() + ciphertext.len()) as u64).to_le_bytes());
+        Poly1305::new(poly_key.as_ref().into())
+            .compute_unpadded(&data)
+            .into()
+    }
+
+    /// Moves the nonce on after a message with `mac` and `tag`, and the key
+    /// too when the tag asks for it or the counter wraps.
+    fn advance(&mut self, mac: &[u8; MAC_LENGTH], tag: u8) {
+        for (nonce, mac) in self.nonce[4..].iter_mut().zip(mac) {
+            *nonce ^= mac;
+        }
+        let counter = u32::from_le_bytes(self.nonce[..4].try_into().unwrap()).wrapping_add(1);
+        self.nonce[..4].copy_from_slice(&counter.to_le_bytes());
+        if tag & TAG_REKEY != 0 || counter == 0 {
+            self.rekey();
+        }
+    }
+
+    fn rekey(&mut self) {
+        let mut next = Zeroizing::new([0; 40]);
+        next[..32].copy_from_slice(self.key.as_ref());
+        next[32..].copy_from_slice(&self.nonce[4..]);
+        self.keystream(0).apply_keystream(next.as_mut());
+        self.key.copy_from_slice(&next[..32]);
+        self.nonce[4..].copy_from_slice(&next[32..]);
+        self.reset_counter();
+    }
+
+    fn push(&mut self, plaintext: &[u8], tag: u8) -> Vec<u8> {
+        let mut block = [0; 64];
+        block[0] = tag;
+        self.keystream(1).apply_keystream(&mut block);
+        let mut message = Vec::with_capacity(plaintext.len() + A_LENGTH);
+        message.push(block[0]);
+        message.extend_from_slice(plaintext);
+        self.keystream(2).apply_keystream(&mut message[1..]);
+        let mac = self.mac(&block, &message[1..]);
+        message.extend_from_slice(&mac);
+        self.advance(&mac, tag);
+        message
+    }
+
+    /// The plaintext and tag of `message`, if it authenticates.
+    fn pull(&mut self, message: &[u8]) -> Option<(Zeroizing<Vec<u8>>, u8)> {
+        let (ciphertext, mac) = message.split_at_checked(message.len().checked_sub(MAC_LENGTH)?)?;
+        let (&encrypted_tag, ciphertext) = ciphertext.split_first()?;
+        let mut block = [0; 64];
+        block[0] = encrypted_tag;
+        self.keystream(1).apply_keystream(&mut block);
+        let tag = block[0];
+        block[0] = encrypted_tag;
+        let expected = self.mac(&block, ciphertext);
+        if !bool::from(expected.ct_eq(mac)) {
+            return None;
+        }
+        let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+        self.keystream(2).apply_keystream(&mut plaintext);
+        self.advance(&expected, tag);
+        Some((plaintext, tag))
+    }
+}
+
+/// Encrypts `plaintext` under `key` as one stream of [`CHUNK_LENGTH`] chunks.
+pub fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let header = entropy::bytes::<HEADER_LENGTH>()?;
+    Ok(seal(key, &header, plaintext))
+}
+
+fn seal(key: &[u8; 32], header: &[u8; HEADER_LENGTH], plaintext: &[u8]) -> Vec<u8> {
+    let mut state = State::new(key, header);
+    let chunks = plaintext.len().div_ceil(CHUNK_LENGTH).max(1);
+    let mut file = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + chunks * A_LENGTH);
+    file.extend_from_slice(header);
+    if plaintext.is_empty() {
+        file.extend_from_slice(&state.push(&[], TAG_FINAL));
+    }
+    for (i, chunk) in plaintext.chunks(CHUNK_LENGTH).enumerate() {
+        let tag = match i + 1 == chunks {
+            true => TAG_FINAL,
+            false => TAG_MESSAGE,
+        };
+        file.extend_from_slice(&state.push(chunk, tag));
+    }
+    file
+}
+
+/// Decrypts a stream under `key`, whatever chunk length it was written with
+/// of those tried.
+pub fn decrypt(key: &[u8; 32], file: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let (header, messages) = file
+        .split_first_chunk::<HEADER_LENGTH>()
+        .filter(|(_, messages)| messages.len() >= A_LENGTH)
+        .ok_or_else(|| {
+            anyhow!(t!("secretstream-truncated")).context(DecryptError::HeaderInvalid)
+        })?;
+    let mut state = State::new(key, header);
+    let mut chunk_length = None;
+    for length in CHUNK_LENGTHS.map(|shift| 1 << shift) {
+        let first = &messages[..messages.len().min(length + A_LENGTH)];
+        if state.clone().pull(first).is_some() {
+            chunk_length = Some(length);
+            break;
+        }
+        // Longer chunks would take the same first message.
+        if first.len() == messages.len() {
+            break;
+        }
+    }
+    // A key that opens no first message is as likely wrong as the chunk
+    // length unusual.
+    let chunk_length = chunk_length
+        .ok_or_else(|| anyhow!(t!("secretstream-unopened")).context(DecryptError::WrongKey))?;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(messages.len()));
+    let mut rest = messages;
+    while !rest.is_empty() {
+        let (message, next) = rest.split_at(rest.len().min(chunk_length + A_LENGTH));
+        let (chunk, tag) = state
+            .pull(message)
+            .ok_or_else(|| anyhow!(DecryptError::Corrupted))?;
+        plaintext.extend_from_slice(&chunk);
+        rest = next;
+        if tag & TAG_FINAL == TAG_FINAL {
+            return match rest.is_empty() {
+                true => Ok(plaintext),
+                false => Err(anyhow!(t!("secretstream-trailing")).context(DecryptError::Corrupted)),
+            };
+        }
+    }
+    Err(anyhow!(t!("secretstream-truncated")).context(DecryptError::Corrupted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plaintext() -> Vec<u8> {
+        (0..5000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Checked against libsodium 1.0.18's `crypto_secretstream_xchacha20poly1305_pull`.
+    #[test]
+    fn streams_match_libsodium() {
+        let file = seal(&[1; 32], &[2; HEADER_LENGTH], &plaintext());
+        assert_eq!(file.len(), HEADER_LENGTH + 5000 + 2 * A_LENGTH);
+        assert_eq!(
+            hex::encode(&file[HEADER_LENGTH..HEADER_LENGTH + 16]),
+            "9283df795f1888a7324691babe50f349"
+        );
+        assert_eq!(
+            hex::encode(&file[file.len() - MAC_LENGTH..]),
+            "a1deb615b562521c605794a1d28a33d7"
+        );
+        assert_eq!(*decrypt(&[1; 32], &file).unwrap(), plaintext());
+    }
+
+    #[test]
+    fn other_chunk_lengths_and_damage_are_found() {
+        let header = [3; HEADER_LENGTH];
+        let mut state = State::new(&[4; 32], &header);
+        let mut file = header.to_vec();
+        let chunks: Vec<_> = plaintext().chunks(1024).map(<[u8]>::to_vec).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let tag = match i {
+                1 => TAG_REKEY,
+                _ if i + 1 == chunks.len() => TAG_FINAL,
+                _ => TAG_MESSAGE,
+            };
+            file.extend_from_slice(&state.push(chunk, tag));
+        }
+        assert_eq!(*decrypt(&[4; 32], &file).unwrap(), plaintext());
+
+        let wrong_key = decrypt(&[5; 32], &file).unwrap_err();
+        assert!(matches!(
+            wrong_key.downcast_ref(),
+            Some(DecryptError::WrongKey)
+        ));
+        let mut damaged = file.clone();
+        damaged[HEADER_LENGTH + 3000] ^= 1;
+        assert!(matches!(
+            decrypt(&[4; 32], &damaged).unwrap_err().downcast_ref(),
+            Some(DecryptError::Corrupted)
+        ));
+        let cut = &file[..HEADER_LENGTH + 2 * (1024 + A_LENGTH)];
+        assert!(matches!(
+            decrypt(&[4; 32], cut).unwrap_err().downcast_ref(),
+            Some(DecryptError::Corrupted)
+        ));
+    }
+}
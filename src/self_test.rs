@@ -0,0 +1,397 @@
+//! `self-test`: known-answer tests of the ciphers, key derivation functions
+//! and hashes this binary was built with, and round trips of its file
+//! formats, to check a build (or a CPU's AES and SIMD code paths) before
+//! trusting it with data.
+//!
+//! The vectors are the published ones: the GCM specification's test case
+//! 16, RFC 8439 for ChaCha20-Poly1305, the XChaCha20 draft, RFC 7914 for
+//! PBKDF2-HMAC-SHA256 and for scrypt, which age files use, RFC 9106 and the
+//! reference implementation for Argon2id, and FIPS 180-2 and the BLAKE3 reference for the hashes. The
+//! round trips use cheap KDF settings, so the whole run takes a moment.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Nonce, Payload},
+    Aes256Gcm,
+};
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use hmac::Hmac;
+use sha2::{Digest, Sha256};
+
+use file_encryptor::{i18n::t, Cipher, Compression, Encryptor, Kdf};
+
+struct Test {
+    name: &'static str,
+    /// Where the vector comes from, or what the round trip covers.
+    detail: &'static str,
+    run: fn() -> Result<()>,
+}
+
+const TESTS: &[Test] = &[
+    Test {
+        name: "AES-256-GCM",
+        detail: "GCM specification, test case 16",
+        run: aes_256_gcm,
+    },
+    Test {
+        name: "ChaCha20-Poly1305",
+        detail: "RFC 8439, section 2.8.2",
+        run: chacha20_poly1305,
+    },
+    Test {
+        name: "XChaCha20-Poly1305",
+        detail: "draft-irtf-cfrg-xchacha, appendix A.3.1",
+        run: xchacha20_poly1305,
+    },
+    Test {
+        name: "PBKDF2-HMAC-SHA256",
+        detail: "RFC 7914, section 11",
+        run: pbkdf2_sha256,
+    },
+    Test {
+        name: "scrypt",
+        detail: "RFC 7914, section 12",
+        run: scrypt,
+    },
+    Test {
+        name: "Argon2id",
+        detail: "RFC 9106, section 5.3, and the reference implementation's tests",
+        run: argon2id,
+    },
+    Test {
+        name: "SHA-256",
+        detail: "FIPS 180-2, \"abc\"",
+        run: sha256,
+    },
+    Test {
+        name: "BLAKE3",
+        detail: "reference implementation, the empty input",
+        run: blake3,
+    },
+    Test {
+        name: "Container (password)",
+        detail: "encrypt and decrypt with PBKDF2 and with Argon2id, and refuse a changed byte",
+        run: container_password,
+    },
+    Test {
+        name: "Container (key file)",
+        detail: "each cipher, gzip compression, and a changed byte refused",
+        run: container_key_file,
+    },
+    Test {
+        name: "Stream",
+        detail: "chunked encryption of several chunks, sealed on one thread and on several",
+        run: stream,
+    },
+];
+
+pub fn run() -> Result<()> {
+    let mut failed = false;
+    for test in TESTS {
+        match (test.run)() {
+            Ok(()) => println!("[{:>4}] {}: {}", "ok", test.name, test.detail),
+            Err(e) => {
+                failed = true;
+                println!("[FAIL] {}: {}", test.name, test.detail);
+                println!("       {e:#}");
+            }
+        }
+    }
+    if failed {
+        bail!(t!("self-test-failed"));
+    }
+    Ok(())
+}
+
+/// Seals `plaintext` with `C` and checks the result against `sealed`, then
+/// that it opens again and that a changed tag is refused.
+fn aead_vector<C: Aead + KeyInit>(
+    key: &str,
+    nonce: &str,
+    aad: &str,
+    plaintext: &[u8],
+    sealed: &str,
+) -> Result<()> {
+    let cipher = C::new_from_slice(&hex::decode(key)?).map_err(|e| anyhow!(e))?;
+    let nonce = hex::decode(nonce)?;
+    let nonce = Nonce::<C>::from_slice(&nonce);
+    let aad = hex::decode(aad)?;
+    let expected = hex::decode(sealed)?;
+    let actual = cipher
+        .encrypt(
+            nonce,
+            Payload {
+                msg: plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|e| anyhow!(e))?;
+    if actual != expected {
+        bail!(t!("self-test-sealed", actual = hex::encode(actual)));
+    }
+    let opened = cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &expected,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| anyhow!(t!("self-test-unopened")))?;
+    if opened != plaintext {
+        bail!(t!("self-test-wrong-opening"));
+    }
+    let mut tampered = expected;
+    if let Some(last) = tampered.last_mut() {
+        *last ^= 1;
+    }
+    if cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &tampered,
+                aad: &aad,
+            },
+        )
+        .is_ok()
+    {
+        bail!(t!("self-test-tag-accepted"));
+    }
+    Ok(())
+}
+
+/// The plaintext of the ChaCha20-Poly1305 and XChaCha20-Poly1305 vectors.
+const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+    one tip for the future, sunscreen would be it.";
+
+fn aes_256_gcm() -> Result<()> {
+    let plaintext = hex::decode(
+        "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e24\
+         49a6b525b16aedf5aa0de657ba637b39",
+    )?;
+    aead_vector::<Aes256Gcm>(
+        "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+        "cafebabefacedbaddecaf888",
+        "feedfacedeadbeeffeedfacedeadbeefabaddad2",
+        &plaintext,
+        "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa8cb08e48590dbb3da7b08b10\
+         56828838c5f61e6393ba7a0abcc9f66276fc6ece0f4e1768cddf8853bb2d551b",
+    )
+}
+
+fn chacha20_poly1305() -> Result<()> {
+    aead_vector::<ChaCha20Poly1305>(
+        "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        "070000004041424344454647",
+        "50515253c0c1c2c3c4c5c6c7",
+        SUNSCREEN,
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69\
+         da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad67594\
+         5585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b61161ae10b594f09e26a7e902ecbd0600691",
+    )
+}
+
+fn xchacha20_poly1305() -> Result<()> {
+    aead_vector::<XChaCha20Poly1305>(
+        "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        "404142434445464748494a4b4c4d4e4f5051525354555657",
+        "50515253c0c1c2c3c4c5c6c7",
+        SUNSCREEN,
+        "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f\
+         4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f\
+         76b2383565d3fff921f9664c97637da9768812f615c68b13b52ec0875924c1c7987947deafd8780acf49",
+    )
+}
+
+/// Checks `actual` against the hex digits `expected`.
+fn check(actual: &[u8], expected: &str) -> Result<()> {
+    if hex::encode(actual) != expected {
+        bail!("got {}", hex::encode(actual));
+    }
+    Ok(())
+}
+
+fn pbkdf2_sha256() -> Result<()> {
+    let vectors = [
+        (
+            "passwd",
+            "salt",
+            1,
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664\
+             b39d77ef317c71b845b1e30bd509112041d3a19783",
+        ),
+        (
+            "Password",
+            "NaCl",
+            80_000,
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56a1d425a1225833549adb84\
+             1b51c9b3176a272bdebba1d078478f62b397f33c8d",
+        ),
+    ];
+    for (password, salt, iterations, expected) in vectors {
+        let mut key = [0; 64];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt.as_bytes(), iterations, &mut key);
+        check(&key, expected)?;
+    }
+    Ok(())
+}
+
+fn scrypt() -> Result<()> {
+    let vectors = [
+        (
+            "",
+            "",
+            4,
+            1,
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a75\
+             3a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906",
+        ),
+        (
+            "pleaseletmein",
+            "SodiumChloride",
+            14,
+            8,
+            "7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2d5432955613f0fcf62d497\
+             05242a9af9e61e85dc0d651e40dfcf017b45575887",
+        ),
+    ];
+    for (password, salt, log_n, r, expected) in vectors {
+        let mut key = [0; 64];
+        crate::age::scrypt_into(password.as_bytes(), salt.as_bytes(), log_n, r, &mut key);
+        check(&key, expected)?;
+    }
+    Ok(())
+}
+
+fn argon2id() -> Result<()> {
+    use argon2::{Algorithm, Argon2, ParamsBuilder, Version};
+
+    let invalid = |e: argon2::Error| anyhow!(e);
+    // RFC 9106's, which adds a secret and associated data on top of what files use.
+    let params = ParamsBuilder::new()
+        .m_cost(32)
+        .t_cost(3)
+        .p_cost(4)
+        .data(argon2::AssociatedData::new(&[4; 12]).map_err(invalid)?)
+        .output_len(32)
+        .build()
+        .map_err(invalid)?;
+    let mut key = [0; 32];
+    Argon2::new_with_secret(&[3; 8], Algorithm::Argon2id, Version::V0x13, params)
+        .map_err(invalid)?
+        .hash_password_into(&[1; 32], &[2; 16], &mut key)
+        .map_err(invalid)?;
+    check(
+        &key,
+        "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659",
+    )?;
+    // The reference implementation's, with a password and salt only, as files use it.
+    let params = argon2::Params::new(256, 2, 1, Some(32)).map_err(invalid)?;
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(b"password", b"somesalt", &mut key)
+        .map_err(invalid)?;
+    check(
+        &key,
+        "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe",
+    )
+}
+
+fn sha256() -> Result<()> {
+    check(
+        &Sha256::digest(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+    )
+}
+
+fn blake3() -> Result<()> {
+    check(
+        blake3::hash(b"").as_bytes(),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+    )
+}
+
+/// Plaintext for the round trips: a few chunks long, and not all one byte.
+fn plaintext() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+const PASSWORD: &str = "self-test password";
+
+/// Encrypts `plaintext` as a whole file with `encryptor`, decrypts it with
+/// `decryptor`, and checks that a changed byte is refused.
+fn round_trip(encryptor: &mut Encryptor, decryptor: impl Fn() -> Encryptor<'static>) -> Result<()> {
+    let plaintext = plaintext();
+    let mut sealed = encryptor.encrypt(&plaintext)?;
+    if decryptor().decrypt(&sealed)? != plaintext {
+        bail!(t!("self-test-wrong-plaintext"));
+    }
+    let middle = sealed.len() / 2;
+    sealed[middle] ^= 1;
+    if decryptor().decrypt(&sealed).is_ok() {
+        bail!(t!("self-test-file-accepted"));
+    }
+    Ok(())
+}
+
+fn container_password() -> Result<()> {
+    let kdfs = [
+        Kdf::Pbkdf2 { iterations: 1_000 },
+        Kdf::Argon2id {
+            memory_kib: 256,
+            iterations: 1,
+            parallelism: 1,
+        },
+    ];
+    for kdf in kdfs {
+        // The KDF's settings come from the header on the way back.
+        round_trip(&mut Encryptor::with_kdf(PASSWORD, kdf), || {
+            Encryptor::new(PASSWORD)
+        })?;
+    }
+    Ok(())
+}
+
+fn container_key_file() -> Result<()> {
+    let key = [7; 32];
+    for cipher in [
+        Cipher::Aes256Gcm,
+        Cipher::ChaCha20Poly1305,
+        Cipher::XChaCha20Poly1305,
+    ] {
+        round_trip(
+            &mut Encryptor::from_key_file(key).with_cipher(cipher),
+            || Encryptor::from_key_file(key),
+        )?;
+    }
+    round_trip(
+        &mut Encryptor::from_key_file(key).with_compression(Compression::Gzip),
+        || Encryptor::from_key_file(key),
+    )
+}
+
+fn stream() -> Result<()> {
+    let key = [7; 32];
+    let plaintext = plaintext();
+    let mut sealed = Vec::new();
+    for threads in [1, 4] {
+        let mut output = Vec::new();
+        Encryptor::from_key_file(key)
+            .with_threads(threads)
+            .encrypt_reader_to_writer(plaintext.as_slice(), &mut output)?;
+        let mut opened = Vec::new();
+        Encryptor::from_key_file(key).decrypt_reader_to_writer(output.as_slice(), &mut opened)?;
+        if opened != plaintext {
+            bail!(t!("self-test-wrong-plaintext"));
+        }
+        sealed = output;
+    }
+    let middle = sealed.len() / 2;
+    sealed[middle] ^= 1;
+    if Encryptor::from_key_file(key)
+        .decrypt_reader_to_writer(sealed.as_slice(), &mut Vec::new())
+        .is_ok()
+    {
+        bail!(t!("self-test-stream-accepted"));
+    }
+    Ok(())
+}
@@ -0,0 +1,785 @@
+//! `service install`, `uninstall` and `status`: registers `watch`, with the
+//! options given after `--`, with the platform's service manager, so an
+//! encrypted mirror keeps running unattended without hand-written unit
+//! files.
+//!
+//! On Linux and other Unixes the service is a systemd user unit,
+//! `file-encryptor-NAME.service`, that starts at login and restarts when it
+//! fails. It runs with `NoNewPrivileges`, a private `/tmp`, and
+//! `ProtectSystem=strict`, which leaves only the output directory and the
+//! `--log-file` writable. On macOS it is a launchd agent,
+//! `com.file-encryptor.NAME`, logging to `~/Library/Logs`. On Windows it is
+//! a service started at boot under the unprivileged `LocalService` account,
+//! which `service run` connects to the service control manager.
+//!
+//! A service has nobody to type a password, so the watch needs a key file,
+//! recipients or a password file; a password on its command line is refused,
+//! as the unit is readable by other users. Relative paths are taken from the
+//! directory `install` ran in.
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use file_encryptor::i18n::t;
+
+use crate::{Cli, Commands};
+
+const DEFAULT_NAME: &str = "watch";
+
+#[derive(Subcommand)]
+pub enum ServiceCommand {
+    /// Register `watch` with WATCH_OPTIONS as a service that starts at login (at boot on
+    /// Windows) and restarts when it fails, and start it
+    Install {
+        /// Tells several services apart
+        #[arg(long, default_value = DEFAULT_NAME)]
+        name: String,
+        /// The options of `watch`, after `--`, such as `-- --input-dir DIR --output-dir DIR
+        /// --key-file FILE`
+        #[arg(
+            value_name = "WATCH_OPTIONS",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        watch: Vec<String>,
+    },
+    /// Stop the service and remove it
+    Uninstall {
+        #[arg(long, default_value = DEFAULT_NAME)]
+        name: String,
+    },
+    /// Show what the service manager knows of the service; fails unless it is running
+    Status {
+        #[arg(long, default_value = DEFAULT_NAME)]
+        name: String,
+    },
+    /// Run the watch of a service `install` registered, for the service control manager
+    #[cfg(windows)]
+    #[command(hide = true)]
+    Run {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        dir: PathBuf,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        watch: Vec<String>,
+    },
+}
+
+/// What a service runs.
+struct Service {
+    name: String,
+    program: PathBuf,
+    /// `watch` and its options.
+    arguments: Vec<String>,
+    directory: PathBuf,
+    /// Where the watch writes, as absolute paths.
+    writable: Vec<PathBuf>,
+}
+
+pub fn run(command: &ServiceCommand) -> Result<()> {
+    match command {
+        ServiceCommand::Install { name, watch } => {
+            check_name(name)?;
+            let service = service(name, watch)?;
+            let path = platform::install(&service)?;
+            println!(
+                "{}",
+                t!("service-installed", name = name.as_str(), path = path)
+            );
+        }
+        ServiceCommand::Uninstall { name } => {
+            check_name(name)?;
+            platform::uninstall(name)?;
+            println!("{}", t!("service-uninstalled", name = name.as_str()));
+        }
+        ServiceCommand::Status { name } => {
+            check_name(name)?;
+            platform::status(name)?;
+        }
+        #[cfg(windows)]
+        ServiceCommand::Run { name, dir, watch } => platform::run(name, dir, watch)?,
+    }
+    Ok(())
+}
+
+/// Names go into file names and service labels, so only plain ones.
+fn check_name(name: &str) -> Result<()> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || !name.chars().all(plain) {
+        bail!(t!("service-name-invalid", name = name));
+    }
+    Ok(())
+}
+
+/// The service running `watch` with `options`, which are checked here as
+/// `watch` would check them.
+fn service(name: &str, options: &[String]) -> Result<Service> {
+    let arguments: Vec<String> = ["watch".to_owned()]
+        .into_iter()
+        .chain(options.iter().cloned())
+        .collect();
+    let cli = Cli::try_parse_from(
+        ["file-encryptor"]
+            .into_iter()
+            .chain(arguments.iter().map(String::as_str)),
+    )
+    .unwrap_or_else(|e| e.exit());
+    let Some(Commands::Watch { options }) = &cli.command else {
+        unreachable!("the arguments start with watch");
+    };
+    if options.has_password_argument() {
+        bail!(t!("service-password-argument"));
+    }
+    if !options.has_key_source() {
+        bail!(t!("service-needs-key"));
+    }
+    if let Some(argument) = arguments.iter().find(|a| a.chars().any(char::is_control)) {
+        bail!(t!("service-unquotable", argument = format!("{argument:?}")));
+    }
+
+    let directory = env::current_dir().context(t!("service-no-directory"))?;
+    let mut writable = vec![directory.join(options.output_dir())];
+    if let Some(log_dir) = cli.log_file.as_deref().and_then(Path::parent) {
+        writable.push(directory.join(log_dir));
+    }
+    // The sandbox can only leave directories that exist writable.
+    for dir in &writable {
+        fs::create_dir_all(dir)
+            .with_context(|| t!("dir-create-failed", path = dir.display().to_string()))?;
+    }
+    Ok(Service {
+        name: name.to_owned(),
+        program: env::current_exe().context(t!("service-no-program"))?,
+        arguments,
+        directory,
+        writable,
+    })
+}
+
+/// Runs a service manager's `command`, failing unless it succeeds.
+fn run_command(command: &mut Command) -> Result<()> {
+    let shown = format!("{command:?}");
+    let status = command
+        .status()
+        .with_context(|| t!("service-command-missing", command = shown.clone()))?;
+    if !status.success() {
+        bail!(t!(
+            "service-command-failed",
+            command = shown,
+            status = status.to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Writes a new service definition to `path`.
+#[cfg(unix)]
+fn write_definition(name: &str, path: &Path, contents: &str) -> Result<()> {
+    if path.exists() {
+        bail!(t!(
+            "service-exists",
+            name = name,
+            path = path.display().to_string()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| t!("dir-create-failed", path = parent.display().to_string()))?;
+    }
+    fs::write(path, contents)
+        .with_context(|| t!("file-unwritable", path = path.display().to_string()))
+}
+
+/// The definition at `path`, which must exist for the service `name` to.
+#[cfg(unix)]
+fn installed(name: &str, path: PathBuf) -> Result<PathBuf> {
+    if !path.exists() {
+        bail!(t!("service-not-installed", name = name));
+    }
+    Ok(path)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use anyhow::{anyhow, Context, Result};
+    use std::{fs, path::PathBuf, process::Command};
+
+    use file_encryptor::i18n::t;
+
+    use super::{installed, run_command, write_definition, Service};
+
+    fn unit_name(name: &str) -> String {
+        format!("file-encryptor-{name}.service")
+    }
+
+    fn unit_path(name: &str) -> Result<PathBuf> {
+        let config = dirs::config_dir().ok_or_else(|| anyhow!(t!("service-no-directory")))?;
+        Ok(config.join("systemd/user").join(unit_name(name)))
+    }
+
+    fn systemctl() -> Command {
+        let mut command = Command::new("systemctl");
+        command.arg("--user");
+        command
+    }
+
+    /// `%` starts a specifier in every setting used here.
+    fn escape(text: &str) -> String {
+        text.replace('%', "%%")
+    }
+
+    /// A word of a list or command line, in quotes; in `ExecStart`, `$`
+    /// also starts a variable.
+    fn quote(word: &str, command_line: bool) -> String {
+        let mut quoted = String::from('"');
+        for c in escape(word).chars() {
+            match c {
+                '\\' | '"' => quoted.extend(['\\', c]),
+                '$' if command_line => quoted.push_str("$$"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    pub(super) fn unit(service: &Service) -> String {
+        let command: Vec<String> = [service.program.to_string_lossy().into_owned()]
+            .iter()
+            .chain(&service.arguments)
+            .map(|word| quote(word, true))
+            .collect();
+        let writable: Vec<String> = service
+            .writable
+            .iter()
+            .map(|path| quote(&path.to_string_lossy(), false))
+            .collect();
+        format!(
+            "# Written by `file-encryptor service install`; remove it with \
+             `file-encryptor service uninstall --name {name}`.\n\
+             [Unit]\n\
+             Description=file-encryptor watch ({name})\n\
+             \n\
+             [Service]\n\
+             ExecStart={command}\n\
+             WorkingDirectory={directory}\n\
+             Restart=on-failure\n\
+             RestartSec=10\n\
+             NoNewPrivileges=yes\n\
+             PrivateTmp=yes\n\
+             ProtectSystem=strict\n\
+             ReadWritePaths={writable}\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            name = service.name,
+            command = command.join(" "),
+            directory = escape(&service.directory.to_string_lossy()),
+            writable = writable.join(" "),
+        )
+    }
+
+    pub fn install(service: &Service) -> Result<String> {
+        let path = unit_path(&service.name)?;
+        write_definition(&service.name, &path, &unit(service))?;
+        let started = run_command(systemctl().arg("daemon-reload")).and_then(|()| {
+            run_command(
+                systemctl()
+                    .args(["enable", "--now"])
+                    .arg(unit_name(&service.name)),
+            )
+        });
+        if let Err(e) = started {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(path.display().to_string())
+    }
+
+    pub fn uninstall(name: &str) -> Result<()> {
+        let path = installed(name, unit_path(name)?)?;
+        run_command(systemctl().args(["disable", "--now"]).arg(unit_name(name)))?;
+        fs::remove_file(&path)
+            .with_context(|| t!("file-delete-failed", path = path.display().to_string()))?;
+        run_command(systemctl().arg("daemon-reload"))
+    }
+
+    pub fn status(name: &str) -> Result<()> {
+        installed(name, unit_path(name)?)?;
+        run_command(
+            systemctl()
+                .args(["status", "--no-pager"])
+                .arg(unit_name(name)),
+        )
+        .map_err(|_| anyhow!(t!("service-not-running", name = name)))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::{anyhow, Context, Result};
+    use std::{fs, path::PathBuf, process::Command};
+
+    use file_encryptor::i18n::t;
+
+    use super::{installed, run_command, write_definition, Service};
+
+    fn label(name: &str) -> String {
+        format!("com.file-encryptor.{name}")
+    }
+
+    fn home() -> Result<PathBuf> {
+        dirs::home_dir().ok_or_else(|| anyhow!(t!("service-no-directory")))
+    }
+
+    fn plist_path(name: &str) -> Result<PathBuf> {
+        Ok(home()?
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label(name))))
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    pub(super) fn plist(service: &Service, log: &str) -> String {
+        let arguments: String = [service.program.to_string_lossy().into_owned()]
+            .iter()
+            .chain(&service.arguments)
+            .map(|word| format!("        <string>{}</string>\n", escape(word)))
+            .collect();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>{label}</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {arguments}\
+             \x20   </array>\n\
+             \x20   <key>WorkingDirectory</key>\n\
+             \x20   <string>{directory}</string>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             \x20   <key>KeepAlive</key>\n\
+             \x20   <dict>\n\
+             \x20       <key>SuccessfulExit</key>\n\
+             \x20       <false/>\n\
+             \x20   </dict>\n\
+             \x20   <key>ProcessType</key>\n\
+             \x20   <string>Background</string>\n\
+             \x20   <key>StandardOutPath</key>\n\
+             \x20   <string>{log}</string>\n\
+             \x20   <key>StandardErrorPath</key>\n\
+             \x20   <string>{log}</string>\n\
+             </dict>\n\
+             </plist>\n",
+            label = label(&service.name),
+            directory = escape(&service.directory.to_string_lossy()),
+            log = escape(log),
+        )
+    }
+
+    pub fn install(service: &Service) -> Result<String> {
+        let path = plist_path(&service.name)?;
+        let log = home()?
+            .join("Library/Logs")
+            .join(format!("file-encryptor-{}.log", service.name));
+        write_definition(
+            &service.name,
+            &path,
+            &plist(service, &log.to_string_lossy()),
+        )?;
+        if let Err(e) = run_command(Command::new("launchctl").args(["load", "-w"]).arg(&path)) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(path.display().to_string())
+    }
+
+    pub fn uninstall(name: &str) -> Result<()> {
+        let path = installed(name, plist_path(name)?)?;
+        run_command(Command::new("launchctl").args(["unload", "-w"]).arg(&path))?;
+        fs::remove_file(&path)
+            .with_context(|| t!("file-delete-failed", path = path.display().to_string()))
+    }
+
+    pub fn status(name: &str) -> Result<()> {
+        installed(name, plist_path(name)?)?;
+        run_command(Command::new("launchctl").arg("list").arg(label(name)))
+            .map_err(|_| anyhow!(t!("service-not-running", name = name)))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::{anyhow, bail, Result};
+    use std::{
+        ffi::c_void,
+        path::{Path, PathBuf},
+        process::{Child, Command, Stdio},
+        ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicIsize, Ordering},
+            Mutex, OnceLock,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use file_encryptor::i18n::t;
+
+    use super::{run_command, Service};
+
+    const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+    const SERVICE_STOPPED: u32 = 1;
+    const SERVICE_STOP_PENDING: u32 = 3;
+    const SERVICE_RUNNING: u32 = 4;
+    const SERVICE_ACCEPT_STOP: u32 = 1;
+    const SERVICE_ACCEPT_SHUTDOWN: u32 = 4;
+    const SERVICE_CONTROL_STOP: u32 = 1;
+    const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+    const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+    const NO_ERROR: u32 = 0;
+    const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+    const ERROR_SERVICE_SPECIFIC_ERROR: u32 = 1066;
+
+    type ServiceMain = unsafe extern "system" fn(u32, *mut *mut u16);
+    type Handler = unsafe extern "system" fn(u32, u32, *mut c_void, *mut c_void) -> u32;
+
+    #[repr(C)]
+    struct TableEntry {
+        name: *mut u16,
+        main: Option<ServiceMain>,
+    }
+
+    #[repr(C)]
+    struct ServiceStatus {
+        service_type: u32,
+        current_state: u32,
+        controls_accepted: u32,
+        win32_exit_code: u32,
+        service_specific_exit_code: u32,
+        check_point: u32,
+        wait_hint: u32,
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn StartServiceCtrlDispatcherW(table: *const TableEntry) -> i32;
+        fn RegisterServiceCtrlHandlerExW(
+            name: *const u16,
+            handler: Handler,
+            context: *mut c_void,
+        ) -> isize;
+        fn SetServiceStatus(handle: isize, status: *const ServiceStatus) -> i32;
+    }
+
+    /// The watch's program, directory and arguments, for [`service_main`].
+    static WATCH: OnceLock<(String, PathBuf, Vec<String>)> = OnceLock::new();
+    static HANDLE: AtomicIsize = AtomicIsize::new(0);
+    static CHILD: Mutex<Option<Child>> = Mutex::new(None);
+    static STOPPING: AtomicBool = AtomicBool::new(false);
+
+    fn service_name(name: &str) -> String {
+        format!("file-encryptor-{name}")
+    }
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// `words` as one command line that `CommandLineToArgvW` splits back
+    /// into them.
+    pub(super) fn command_line<'a>(words: impl IntoIterator<Item = &'a str>) -> String {
+        let mut line = Vec::new();
+        for word in words {
+            if !word.is_empty() && !word.contains([' ', '\t', '"']) {
+                line.push(word.to_owned());
+                continue;
+            }
+            let mut quoted = String::from('"');
+            let mut backslashes = 0;
+            for c in word.chars() {
+                match c {
+                    '\\' => backslashes += 1,
+                    '"' => {
+                        quoted.extend(std::iter::repeat('\\').take(2 * backslashes + 1));
+                        quoted.push('"');
+                        backslashes = 0;
+                    }
+                    c => {
+                        quoted.extend(std::iter::repeat('\\').take(backslashes));
+                        quoted.push(c);
+                        backslashes = 0;
+                    }
+                }
+            }
+            quoted.extend(std::iter::repeat('\\').take(2 * backslashes));
+            quoted.push('"');
+            line.push(quoted);
+        }
+        line.join(" ")
+    }
+
+    fn sc() -> Command {
+        Command::new("sc.exe")
+    }
+
+    pub fn install(service: &Service) -> Result<String> {
+        let name = service_name(&service.name);
+        let program = service.program.to_string_lossy();
+        let directory = service.directory.to_string_lossy();
+        let binary_path = command_line(
+            [
+                &*program,
+                "service",
+                "run",
+                "--name",
+                &service.name,
+                "--dir",
+                &*directory,
+                "--",
+            ]
+            .into_iter()
+            .chain(service.arguments.iter().map(String::as_str)),
+        );
+        run_command(sc().args([
+            "create",
+            &name,
+            "binPath=",
+            &binary_path,
+            "start=",
+            "auto",
+            "obj=",
+            "NT AUTHORITY\\LocalService",
+            "password=",
+            "",
+            "DisplayName=",
+            &format!("file-encryptor watch ({})", service.name),
+        ]))?;
+        run_command(sc().args([
+            "failure",
+            &name,
+            "reset=",
+            "86400",
+            "actions=",
+            "restart/10000",
+        ]))?;
+        run_command(sc().args(["start", &name]))?;
+        Ok(name)
+    }
+
+    pub fn uninstall(name: &str) -> Result<()> {
+        let name = service_name(name);
+        // A service that isn't running can't be stopped, but is deleted all
+        // the same.
+        let _ = sc().args(["stop", &name]).status();
+        run_command(sc().args(["delete", &name]))
+    }
+
+    pub fn status(name: &str) -> Result<()> {
+        let output = sc().args(["query", &service_name(name)]).output()?;
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        if !output.status.success() {
+            bail!(t!("service-not-installed", name = name));
+        }
+        match String::from_utf8_lossy(&output.stdout).contains("RUNNING") {
+            true => Ok(()),
+            false => Err(anyhow!(t!("service-not-running", name = name))),
+        }
+    }
+
+    fn set_status(state: u32, exit_code: u32) {
+        let status = ServiceStatus {
+            service_type: SERVICE_WIN32_OWN_PROCESS,
+            current_state: state,
+            controls_accepted: match state {
+                SERVICE_RUNNING => SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN,
+                _ => 0,
+            },
+            win32_exit_code: match exit_code {
+                0 => NO_ERROR,
+                _ => ERROR_SERVICE_SPECIFIC_ERROR,
+            },
+            service_specific_exit_code: exit_code,
+            check_point: 0,
+            wait_hint: 5000,
+        };
+        unsafe { SetServiceStatus(HANDLE.load(Ordering::Acquire), &status) };
+    }
+
+    unsafe extern "system" fn handler(control: u32, _: u32, _: *mut c_void, _: *mut c_void) -> u32 {
+        match control {
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                STOPPING.store(true, Ordering::Release);
+                set_status(SERVICE_STOP_PENDING, 0);
+                if let Some(child) = CHILD.lock().unwrap().as_mut() {
+                    let _ = child.kill();
+                }
+                NO_ERROR
+            }
+            SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+            _ => ERROR_CALL_NOT_IMPLEMENTED,
+        }
+    }
+
+    /// Runs the watch as a child until it ends or the service is stopped.
+    unsafe extern "system" fn service_main(_: u32, _: *mut *mut u16) {
+        let Some((name, directory, arguments)) = WATCH.get() else {
+            return;
+        };
+        let handle = RegisterServiceCtrlHandlerExW(wide(name).as_ptr(), handler, ptr::null_mut());
+        if handle == 0 {
+            return;
+        }
+        HANDLE.store(handle, Ordering::Release);
+        let child = std::env::current_exe().and_then(|program| {
+            Command::new(program)
+                .args(arguments)
+                .current_dir(directory)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+        });
+        let Ok(child) = child else {
+            set_status(SERVICE_STOPPED, 1);
+            return;
+        };
+        *CHILD.lock().unwrap() = Some(child);
+        set_status(SERVICE_RUNNING, 0);
+        let exit_code = loop {
+            let exited = CHILD.lock().unwrap().as_mut().map(Child::try_wait);
+            match exited {
+                Some(Ok(Some(status))) => break status.code().unwrap_or(1) as u32,
+                Some(Ok(None)) => thread::sleep(Duration::from_millis(500)),
+                _ => break 1,
+            }
+        };
+        match STOPPING.load(Ordering::Acquire) {
+            true => set_status(SERVICE_STOPPED, 0),
+            false => set_status(SERVICE_STOPPED, exit_code.max(1)),
+        }
+    }
+
+    pub fn run(name: &str, directory: &Path, arguments: &[String]) -> Result<()> {
+        let name = service_name(name);
+        let mut wide_name = wide(&name);
+        let _ = WATCH.set((name, directory.to_path_buf(), arguments.to_vec()));
+        let table = [
+            TableEntry {
+                name: wide_name.as_mut_ptr(),
+                main: Some(service_main),
+            },
+            TableEntry {
+                name: ptr::null_mut(),
+                main: None,
+            },
+        ];
+        if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use anyhow::{bail, Result};
+
+    use file_encryptor::i18n::t;
+
+    use super::Service;
+
+    pub fn install(_: &Service) -> Result<String> {
+        bail!(t!("service-unsupported"))
+    }
+
+    pub fn uninstall(_: &str) -> Result<()> {
+        bail!(t!("service-unsupported"))
+    }
+
+    pub fn status(_: &str) -> Result<()> {
+        bail!(t!("service-unsupported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Service {
+        Service {
+            name: "photos".to_owned(),
+            program: PathBuf::from("/usr/bin/file-encryptor"),
+            arguments: [
+                "watch",
+                "--input-dir",
+                "My Photos",
+                "--output-dir",
+                "/mnt/100%",
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+            directory: PathBuf::from("/home/sam"),
+            writable: vec![PathBuf::from("/mnt/100%")],
+        }
+    }
+
+    #[test]
+    fn names_are_plain() {
+        assert!(check_name("photos-2024_b").is_ok());
+        for name in ["", "a b", "../x", "a.b"] {
+            assert!(check_name(name).is_err(), "{name}");
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn units_are_sandboxed_and_quoted() {
+        let unit = platform::unit(&example());
+        assert!(unit.contains(
+            "ExecStart=\"/usr/bin/file-encryptor\" \"watch\" \"--input-dir\" \"My Photos\" \
+             \"--output-dir\" \"/mnt/100%%\"\n"
+        ));
+        assert!(unit.contains("ReadWritePaths=\"/mnt/100%%\"\n"));
+        assert!(unit.contains("WorkingDirectory=/home/sam\n"));
+        for line in [
+            "NoNewPrivileges=yes",
+            "PrivateTmp=yes",
+            "ProtectSystem=strict",
+        ] {
+            assert!(unit.contains(line), "{line}");
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn agents_list_their_arguments() {
+        let plist = platform::plist(&example(), "/tmp/a&b.log");
+        assert!(plist.contains("<string>My Photos</string>"));
+        assert!(plist.contains("<string>/tmp/a&amp;b.log</string>"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn command_lines_split_back() {
+        assert_eq!(
+            platform::command_line(["C:\\Program Files\\fe.exe", "a\"b", "C:\\dir\\", ""]),
+            "\"C:\\Program Files\\fe.exe\" \"a\\\"b\" \"C:\\dir\\\\\" \"\""
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Key shares: a [`key_file`](crate::key_file) key split with Shamir's secret
+//! sharing into `count` shares, any `threshold` of which give the key back
+//! and fewer of which say nothing about it. Each byte of the key is the
+//! constant term of its own random polynomial of degree `threshold - 1` over
+//! GF(2^8), and share `index` holds every polynomial's value at `index`.
+//!
+//! A share is one line of text, safe to print and write down:
+//!
+//! ```text
+//! feshare1-<threshold>-<index>-<set, 16 hex>-<values, 64 hex>-<check, 8 hex>
+//! ```
+//!
+//! where the set is random and the same for every share of one split, so
+//! shares of different splits aren't mixed, and the check is the start of
+//! the BLAKE3 digest of the rest, so a mistyped share is refused rather than
+//! giving a wrong key.
+
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+use crate::{entropy, i18n::t, KEY_LENGTH};
+
+const PREFIX: &str = "feshare1";
+const SET_LENGTH: usize = 8;
+const CHECK_LENGTH: usize = 4;
+
+pub struct Share {
+    pub threshold: u8,
+    pub index: u8,
+    set: [u8; SET_LENGTH],
+    values: Zeroizing<[u8; KEY_LENGTH]>,
+}
+
+impl Share {
+    /// The share as its line of text.
+    pub fn encode(&self) -> Zeroizing<String> {
+        let body = format!(
+            "{PREFIX}-{}-{}-{}-{}",
+            self.threshold,
+            self.index,
+            hex::encode(self.set),
+            hex::encode(self.values.as_slice())
+        );
+        Zeroizing::new(format!("{body}-{}", hex::encode(check(&body))))
+    }
+
+    /// The share in a line of text from [`Share::encode`].
+    pub fn parse(text: &str) -> Result<Share> {
+        let invalid = || anyhow!(t!("share-invalid"));
+        let text = text.trim();
+        let (body, check_digits) = text.rsplit_once('-').ok_or_else(invalid)?;
+        if hex::decode(check_digits).ok().as_deref() != Some(&check(body)[..]) {
+            return Err(invalid());
+        }
+        let parts: Vec<_> = body.split('-').collect();
+        let [PREFIX, threshold, index, set, values] = parts[..] else {
+            return Err(invalid());
+        };
+        let values = Zeroizing::new(hex::decode(values).map_err(|_| invalid())?);
+        // One share alone would be the key, which no split makes.
+        let threshold = threshold
+            .parse()
+            .ok()
+            .filter(|&threshold| threshold >= 2)
+            .ok_or_else(invalid)?;
+        Ok(Share {
+            threshold,
+            index: index.parse().map_err(|_| invalid())?,
+            set: hex::decode(set)
+                .ok()
+                .and_then(|set| set.try_into().ok())
+                .ok_or_else(invalid)?,
+            values: Zeroizing::new(values.as_slice().try_into().map_err(|_| invalid())?),
+        })
+    }
+}
+
+/// The check digits of a share's text before them.
+fn check(body: &str) -> [u8; CHECK_LENGTH] {
+    let mut check = [0; CHECK_LENGTH];
+    check.copy_from_slice(&blake3::hash(body.as_bytes()).as_bytes()[..CHECK_LENGTH]);
+    check
+}
+
+/// Splits `key` into `count` shares, any `threshold` of which, from 2 up to
+/// `count`, give it back.
+pub fn split(key: &[u8; KEY_LENGTH], threshold: u8, count: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > count {
+        return Err(anyhow!(t!("share-threshold")));
+    }
+    let set = entropy::bytes()?;
+    // Row i is the polynomial of key[i], constant term first.
+    let mut coefficients = Zeroizing::new(vec![0; KEY_LENGTH * usize::from(threshold)]);
+    entropy::fill(&mut coefficients)?;
+    for (row, &byte) in coefficients.chunks_mut(threshold.into()).zip(key) {
+        row[0] = byte;
+    }
+    Ok((1..=count)
+        .map(|index| {
+            let mut values = Zeroizing::new([0; KEY_LENGTH]);
+            for (value, row) in values.iter_mut().zip(coefficients.chunks(threshold.into())) {
+                // Horner's rule, from the highest coefficient down.
+                *value = row.iter().rev().fold(0, |sum, &c| mul(sum, index) ^ c);
+            }
+            Share {
+                threshold,
+                index,
+                set,
+                values,
+            }
+        })
+        .collect())
+}
+
+/// The key `shares` of one split give back, once there are enough of them.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<[u8; KEY_LENGTH]>> {
+    let Some(first) = shares.first() else {
+        return Err(anyhow!(t!("share-too-few", needed = 2, given = 0)));
+    };
+    if shares
+        .iter()
+        .any(|share| share.set != first.set || share.threshold != first.threshold)
+    {
+        return Err(anyhow!(t!("share-mismatch")));
+    }
+    let mut indexes: Vec<_> = shares.iter().map(|share| share.index).collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    if indexes.len() < shares.len() || indexes.contains(&0) {
+        return Err(anyhow!(t!("share-duplicate")));
+    }
+    if shares.len() < first.threshold.into() {
+        return Err(anyhow!(t!(
+            "share-too-few",
+            needed = first.threshold,
+            given = shares.len()
+        )));
+    }
+    // Lagrange interpolation at 0; any threshold of them will do.
+    let shares = &shares[..first.threshold.into()];
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+    for share in shares {
+        let basis = shares
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |basis, other| {
+                mul(basis, mul(other.index, inverse(other.index ^ share.index)))
+            });
+        for (byte, &value) in key.iter_mut().zip(share.values.iter()) {
+            *byte ^= mul(value, basis);
+        }
+    }
+    Ok(key)
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without
+/// branching on the operands.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        a = (a << 1) ^ (0x1b & (a >> 7).wrapping_neg());
+        b >>= 1;
+    }
+    product
+}
+
+/// The multiplicative inverse of a nonzero `a`: a^254.
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(result, power);
+        }
+        power = mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> [u8; KEY_LENGTH] {
+        std::array::from_fn(|i| i as u8 * 7 + 1)
+    }
+
+    #[test]
+    fn any_threshold_of_the_shares_give_the_key() {
+        for (threshold, count) in [(2, 2), (2, 3), (3, 5), (4, 6)] {
+            let shares = split(&key(), threshold, count).unwrap();
+            for subset in 1u32..1 << count {
+                let chosen: Vec<Share> = shares
+                    .iter()
+                    .filter(|share| subset & 1 << (share.index - 1) != 0)
+                    .map(|share| Share::parse(&share.encode()).unwrap())
+                    .collect();
+                let combined = combine(&chosen);
+                match chosen.len() >= threshold.into() {
+                    true => assert_eq!(*combined.unwrap(), key(), "{threshold}/{count} {subset:b}"),
+                    false => assert!(combined.is_err(), "{threshold}/{count} {subset:b}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_and_mixed_shares_are_refused() {
+        let shares = split(&key(), 2, 3).unwrap();
+        let line = shares[0].encode();
+        let mut typo = line.to_string();
+        typo.replace_range(20..21, if &typo[20..21] == "0" { "1" } else { "0" });
+        assert!(Share::parse(&typo).is_err());
+
+        let body = line.rsplit_once('-').unwrap().0.replacen("-2-", "-1-", 1);
+        let alone = format!("{body}-{}", hex::encode(check(&body)));
+        assert!(Share::parse(&alone).is_err());
+        assert!(split(&key(), 1, 3).is_err());
+        assert!(split(&key(), 4, 3).is_err());
+
+        let other = split(&key(), 2, 3).unwrap();
+        let mixed = [
+            Share::parse(&shares[0].encode()).unwrap(),
+            Share::parse(&other[1].encode()).unwrap(),
+        ];
+        assert!(combine(&mixed).is_err());
+        let twice = [
+            Share::parse(&shares[0].encode()).unwrap(),
+            Share::parse(&shares[0].encode()).unwrap(),
+        ];
+        assert!(combine(&twice).is_err());
+    }
+}
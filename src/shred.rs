@@ -0,0 +1,111 @@
+//! `shred` and `encrypt --remove-original`: overwriting files with random
+//! data before deleting them, so that the plaintext a file was encrypted from
+//! can't be read back from the disk.
+//!
+//! Overwriting only reaches the old data where a write lands on the blocks it
+//! replaces. SSDs and flash cards remap writes, copy-on-write filesystems
+//! (btrfs, ZFS, APFS) and filesystems journaling data write elsewhere first,
+//! and snapshots and backups keep copies of their own; there, the old blocks
+//! may survive until they happen to be reused. On those, full-disk encryption
+//! is what keeps deleted plaintext unreadable.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use std::{
+    fs,
+    io::{Seek, Write},
+    path::Path,
+};
+
+use file_encryptor::{entropy, i18n::t};
+
+use crate::{report, sync_parent_dir, walk};
+
+const BUFFER_LENGTH: usize = 64 << 10;
+
+#[derive(Args)]
+pub struct ShredOptions {
+    /// Files to overwrite and delete
+    #[arg(value_name = "FILE", required = true)]
+    paths: Vec<String>,
+    /// How many times to overwrite each file
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    passes: u8,
+}
+
+pub fn run(options: &ShredOptions) -> Result<()> {
+    for path in &options.paths {
+        shred(Path::new(path), options.passes)?;
+        println!("{}", t!("shred-done", path = path.as_str()));
+    }
+    Ok(())
+}
+
+/// Shreds the file at `path`, or every file under the directory at `path`
+/// and then the directories left empty.
+pub fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        let files = walk::walk(path)
+            .with_context(|| t!("dir-list-failed", path = path.display().to_string()))?;
+        for (relative, _) in files {
+            shred(&path.join(relative), 1)?;
+        }
+        remove_empty_dirs(path);
+    } else {
+        shred(path, 1)?;
+    }
+    if !report::quiet() {
+        println!("{}", t!("shred-done", path = path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Overwrites the file at `path` with random data `passes` times, flushing
+/// each pass to the disk, then deletes it.
+fn shred(path: &Path, passes: u8) -> Result<()> {
+    // Not through a symlink, which would shred what it points to.
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    if !metadata.is_file() {
+        bail!(t!("shred-not-file", path = path.display().to_string()));
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+    let mut buffer = vec![0; BUFFER_LENGTH];
+    // Seeded from the checked source, and fast enough for whole disks.
+    let mut rng = StdRng::from_seed(entropy::bytes()?);
+    for _ in 0..passes {
+        file.rewind()
+            .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+        let mut left = metadata.len();
+        while left > 0 {
+            let n = left.min(BUFFER_LENGTH as u64) as usize;
+            rng.fill_bytes(&mut buffer[..n]);
+            file.write_all(&buffer[..n])
+                .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+            left -= n as u64;
+        }
+        file.sync_data()
+            .with_context(|| t!("file-sync-failed", path = path.display().to_string()))?;
+    }
+    drop(file);
+    fs::remove_file(path)
+        .with_context(|| t!("file-delete-failed", path = path.display().to_string()))?;
+    sync_parent_dir(path)
+}
+
+/// Removes `dir` and the directories under it, keeping those that still
+/// hold something, such as a symlink, that wasn't encrypted.
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                remove_empty_dirs(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(dir);
+}
@@ -0,0 +1,350 @@
+//! Signed manifests: `encrypt --sign-manifest KEY_FILE` lists every file of a
+//! directory or `--archive` OUTPUT, with its length and BLAKE3 digest, in
+//! OUTPUT.manifest beside it, signed with the Ed25519 key in KEY_FILE (from
+//! `keygen --ed25519`). `verify-manifest` checks the signature, and that the
+//! files delivered are exactly those listed, none missing, changed or added,
+//! so recipients can confirm both that a file set is complete and who sent
+//! it.
+//!
+//! A manifest is text: an intro line, the signer's public key, the date, the
+//! name of OUTPUT, a `DIGEST LENGTH PATH` line per file with the path from
+//! the manifest's directory, and last the signature over all lines before
+//! it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use ed25519_dalek::{
+    Signature, SignatureError, Signer, SigningKey, Verifier, VerifyingKey, SIGNATURE_LENGTH,
+};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use file_encryptor::{date, i18n::t, DecryptError};
+
+use crate::{log, throttle, walk, write_file_bytes, IoOptions};
+
+const INTRO: &str = "file-encryptor signed manifest 1";
+const SIGNATURE_PREFIX: &str = "signature: ";
+
+#[derive(Args)]
+pub struct VerifyManifestOptions {
+    /// The OUTPUT.manifest that `encrypt --sign-manifest` wrote, beside the files it lists
+    #[arg(value_name = "MANIFEST")]
+    manifest_path: String,
+    /// The sender's public key, as `keygen --ed25519` printed it; without it the manifest
+    /// only proves itself unchanged since whoever signed it did so
+    #[arg(long, value_name = "PUBLIC_KEY")]
+    signer: Option<String>,
+}
+
+/// One file a manifest lists.
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    digest: [u8; 32],
+    length: u64,
+    /// From the manifest's directory, with `/` between components.
+    path: String,
+}
+
+#[derive(Debug)]
+struct Manifest {
+    signer: VerifyingKey,
+    /// The name of the output, the first component of every path.
+    root: String,
+    entries: Vec<Entry>,
+}
+
+/// Where the manifest of `output` goes.
+pub fn path_for(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_owned();
+    name.push(".manifest");
+    output.with_file_name(name)
+}
+
+/// The public key of the signing key `seed`, as `verify-manifest --signer`
+/// takes it.
+pub fn public_key(seed: &[u8; 32]) -> String {
+    hex::encode(SigningKey::from_bytes(seed).verifying_key().as_bytes())
+}
+
+/// Lists the files of `output`, a directory or one file, and writes their
+/// manifest signed with `seed` at [`path_for`]`(output)`, returning that.
+pub fn write(output: &Path, seed: &[u8; 32], force: bool) -> Result<PathBuf> {
+    let manifest_path = path_for(output);
+    if !force && manifest_path.exists() {
+        bail!(t!(
+            "output-exists",
+            path = manifest_path.display().to_string()
+        ));
+    }
+    let Some(root) = output.file_name() else {
+        bail!(t!(
+            "manifest-unlistable",
+            path = output.display().to_string()
+        ));
+    };
+    let root = name(Path::new(root))?;
+    let mut entries = Vec::new();
+    for relative in list(output)? {
+        let (path, file) = match relative.as_os_str().is_empty() {
+            true => (root.clone(), output.to_path_buf()),
+            false => (
+                format!("{root}/{}", name(&relative)?),
+                output.join(&relative),
+            ),
+        };
+        let (length, digest) = digest(&file)?;
+        entries.push(Entry {
+            digest,
+            length,
+            path,
+        });
+    }
+    let text = sign(
+        &SigningKey::from_bytes(seed),
+        &root,
+        &entries,
+        SystemTime::now(),
+    );
+    write_file_bytes(&manifest_path, text.as_bytes(), IoOptions::default())?;
+    Ok(manifest_path)
+}
+
+pub fn run(options: &VerifyManifestOptions) -> Result<()> {
+    let manifest_path = Path::new(&options.manifest_path);
+    let shown = manifest_path.display().to_string();
+    let text =
+        fs::read(manifest_path).with_context(|| t!("manifest-unreadable", path = shown.clone()))?;
+    let manifest = String::from_utf8(text)
+        .ok()
+        .and_then(|text| parse(&text))
+        .ok_or_else(|| anyhow!(t!("manifest-invalid", path = shown.clone())))?
+        .map_err(|_| {
+            anyhow!(t!("manifest-bad-signature", path = shown.clone()))
+                .context(DecryptError::Corrupted)
+        })?;
+    let signer = hex::encode(manifest.signer.as_bytes());
+    match &options.signer {
+        Some(expected) if !expected.trim().eq_ignore_ascii_case(&signer) => {
+            return Err(
+                anyhow!(t!("manifest-wrong-signer", path = shown, signer = signer))
+                    .context(DecryptError::WrongKey),
+            );
+        }
+        Some(_) => {}
+        None => log::warn(&t!(
+            "manifest-unverified-signer",
+            path = shown.clone(),
+            signer = signer.clone()
+        )),
+    }
+
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    let mut problems = 0;
+    let mut listed = HashSet::new();
+    for entry in &manifest.entries {
+        listed.insert(entry.path.as_str());
+        let path = base.join(&entry.path);
+        let message = match digest(&path) {
+            Ok(found) if found == (entry.length, entry.digest) => continue,
+            Ok(_) => t!("manifest-changed", path = entry.path.clone()),
+            Err(e) if not_found(&e) => t!("manifest-missing", path = entry.path.clone()),
+            Err(e) => return Err(e),
+        };
+        log::warn(&message);
+        problems += 1;
+    }
+    let root = base.join(&manifest.root);
+    if root.is_dir() {
+        for relative in list(&root)? {
+            let path = format!("{}/{}", manifest.root, name(&relative)?);
+            if !listed.contains(path.as_str()) {
+                log::warn(&t!("manifest-extra", path = path));
+                problems += 1;
+            }
+        }
+    }
+    if problems > 0 {
+        return Err(
+            anyhow!(t!("manifest-incomplete", path = shown, count = problems))
+                .context(DecryptError::Corrupted),
+        );
+    }
+    println!(
+        "{}",
+        t!(
+            "manifest-ok",
+            path = shown,
+            count = manifest.entries.len(),
+            signer = signer
+        )
+    );
+    Ok(())
+}
+
+/// The files of `output` relative to it in path order, or for a file just
+/// the empty path.
+fn list(output: &Path) -> Result<Vec<PathBuf>> {
+    match output.is_dir() {
+        true => Ok(walk::walk(output)
+            .with_context(|| t!("manifest-unreadable", path = output.display().to_string()))?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()),
+        false => Ok(vec![PathBuf::new()]),
+    }
+}
+
+/// `path` with `/` between its components, if it can go on a manifest line.
+fn name(path: &Path) -> Result<String> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component.as_os_str().to_str() {
+            Some(name) if !name.contains(['\n', '\r']) => names.push(name),
+            _ => bail!(t!("manifest-unlistable", path = path.display().to_string())),
+        }
+    }
+    Ok(names.join("/"))
+}
+
+/// The length and BLAKE3 digest of the file at `path`.
+fn digest(path: &Path) -> Result<(u64, [u8; 32])> {
+    let shown = || t!("manifest-unreadable", path = path.display().to_string());
+    let mut file = fs::File::open(path)
+        .map(throttle::Reader)
+        .with_context(shown)?;
+    let mut hasher = blake3::Hasher::new();
+    let length = io::copy(&mut file, &mut hasher).with_context(shown)?;
+    Ok((length, hasher.finalize().into()))
+}
+
+fn not_found(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
+/// The text of a manifest listing `entries` of `root`, signed with `key`.
+fn sign(key: &SigningKey, root: &str, entries: &[Entry], now: SystemTime) -> String {
+    let mut text = format!(
+        "{INTRO}\nsigner: {}\ncreated: {}\nroot: {root}\n",
+        hex::encode(key.verifying_key().as_bytes()),
+        date::format(now)
+    );
+    for entry in entries {
+        text.push_str(&format!(
+            "{} {} {}\n",
+            hex::encode(entry.digest),
+            entry.length,
+            entry.path
+        ));
+    }
+    let signature = key.sign(text.as_bytes());
+    text.push_str(&format!(
+        "{SIGNATURE_PREFIX}{}\n",
+        hex::encode(signature.to_bytes())
+    ));
+    text
+}
+
+/// Reads a manifest: `None` if it doesn't parse, and an error if it does
+/// but its signature doesn't match.
+fn parse(text: &str) -> Option<Result<Manifest, SignatureError>> {
+    let body_length = text.trim_end().rfind('\n')? + 1;
+    let (body, signature) = text.split_at(body_length);
+    let signature: [u8; SIGNATURE_LENGTH] =
+        hex::decode(signature.trim_end().strip_prefix(SIGNATURE_PREFIX)?)
+            .ok()?
+            .try_into()
+            .ok()?;
+    let mut lines = body.lines();
+    if lines.next()? != INTRO {
+        return None;
+    }
+    let mut field = |name: &str| {
+        lines
+            .next()?
+            .strip_prefix(name)?
+            .strip_prefix(": ")
+            .map(str::to_string)
+    };
+    let signer: [u8; 32] = hex::decode(field("signer")?).ok()?.try_into().ok()?;
+    let signer = VerifyingKey::from_bytes(&signer).ok()?;
+    date::parse(&field("created")?)?;
+    let root = field("root")?;
+    let mut entries = Vec::new();
+    for line in lines {
+        let mut fields = line.splitn(3, ' ');
+        let entry = Entry {
+            digest: hex::decode(fields.next()?).ok()?.try_into().ok()?,
+            length: fields.next()?.parse().ok()?,
+            path: fields.next()?.to_string(),
+        };
+        // Only paths within the output, so a manifest can't send the check
+        // elsewhere.
+        let mut components = Path::new(&entry.path).components();
+        if components.next()? != Component::Normal(root.as_ref())
+            || !components.all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        entries.push(entry);
+    }
+    let verified = signer.verify(body.as_bytes(), &Signature::from_bytes(&signature));
+    Some(verified.map(|()| Manifest {
+        signer,
+        root,
+        entries,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        ["out/a.txt.enc", "out/sub dir/b.txt.enc"]
+            .iter()
+            .enumerate()
+            .map(|(i, path)| Entry {
+                digest: [i as u8; 32],
+                length: 100 * i as u64,
+                path: path.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn manifests_read_back_only_as_signed() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let text = sign(&key, "out", &entries(), SystemTime::UNIX_EPOCH);
+        let manifest = parse(&text).unwrap().unwrap();
+        assert_eq!(manifest.signer, key.verifying_key());
+        assert_eq!(manifest.root, "out");
+        assert_eq!(manifest.entries, entries());
+        assert_eq!(
+            public_key(&[7; 32]),
+            hex::encode(key.verifying_key().as_bytes())
+        );
+
+        let changed = text.replace(" 100 ", " 101 ");
+        assert!(parse(&changed).unwrap().is_err());
+        let dropped = text.replace(&format!("{} 0 out/a.txt.enc\n", hex::encode([0; 32])), "");
+        assert!(parse(&dropped).unwrap().is_err());
+        let outside = sign(
+            &key,
+            "out",
+            &[Entry {
+                path: "out/../elsewhere".to_string(),
+                ..entries().remove(0)
+            }],
+            SystemTime::UNIX_EPOCH,
+        );
+        assert!(parse(&outside).is_none());
+        assert!(parse("file-encryptor signed manifest 1\n").is_none());
+    }
+}
@@ -0,0 +1,631 @@
+//! `encrypt --split-size`: the encrypted output cut into numbered parts,
+//! `NAME.001`, `NAME.002` and so on, none larger than the size given, for
+//! media and upload limits. Each part is
+//!
+//! ```text
+//! "FENCPART" | version (1) | set (16) | index (u32 LE) | last (1) | data
+//! ```
+//!
+//! where the set is random and the same in every part of one output, the
+//! index counts from 1, and the last flag is 1 only in the final part. The
+//! data of the parts, in order, is the encrypted file. `decrypt` reads the
+//! parts back in order by itself, refusing a set with a part missing, out of
+//! place or from another output; the data is then authenticated as usual.
+//!
+//! From version 2, `NAME.idx` lists the parts:
+//!
+//! ```text
+//! "FENCINDX" | version (1) | set (16) | count (u32 LE) | digests (32 each) | MAC (32)
+//! ```
+//!
+//! where each digest is the BLAKE3 of a part's data, in order, and the MAC
+//! is keyed BLAKE3 under a key derived from the file's key, so
+//! only someone holding that key could have written it. Joining the parts
+//! checks each one against its digest as it ends, which names a damaged
+//! part rather than the whole file, and the MAC once the key is known.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use subtle::ConstantTimeEq;
+
+use file_encryptor::{entropy, i18n::t, DecryptError, Encryptor};
+
+use crate::{create_file, report, sync_file, IoOptions, PendingOutput};
+
+const MAGIC: &[u8; 8] = b"FENCPART";
+const VERSION: u8 = 2;
+/// Parts written before the index.
+const UNINDEXED_VERSION: u8 = 1;
+const INDEX_MAGIC: &[u8; 8] = b"FENCINDX";
+const INDEX_SUFFIX: &str = ".idx";
+/// What the index key is derived for.
+const INDEX_CONTEXT: &str = "file-encryptor 2026 split index";
+/// How much of the output is kept for its header, the most a header holds.
+const HEAD_LENGTH: usize = u16::MAX as usize;
+const SET_LENGTH: usize = 16;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + SET_LENGTH + 4 + 1;
+/// Where the last flag is in a part.
+const LAST_OFFSET: u64 = HEADER_LENGTH as u64 - 1;
+const FIRST_SUFFIX: &str = ".001";
+
+/// Part `index` of the output `path`.
+fn part_path(path: &Path, index: u32) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(format!(".{index:03}"));
+    part.into()
+}
+
+/// The index of the parts of `path`.
+fn index_path(path: &Path) -> PathBuf {
+    let mut index = path.as_os_str().to_owned();
+    index.push(INDEX_SUFFIX);
+    index.into()
+}
+
+/// The MAC of an index, everything before it in `signed`.
+fn index_mac(key: &[u8; 32], signed: &[u8]) -> [u8; 32] {
+    *blake3::keyed_hash(key, signed).as_bytes()
+}
+
+/// The output a first part such as `backup.enc.001` belongs to, so that
+/// `decrypt` names its output after `backup.enc`.
+pub fn set_name(input: &str) -> &str {
+    input
+        .strip_suffix(FIRST_SUFFIX)
+        .filter(|stem| stem.ends_with(".enc"))
+        .unwrap_or(input)
+}
+
+/// Refuses to replace the first part of an earlier split output at `path`
+/// unless `force`; later parts are only replaced as they are written.
+pub fn check_output(path: &Path, force: bool) -> Result<()> {
+    let first = part_path(path, 1);
+    if !force && fs::symlink_metadata(&first).is_ok() {
+        return Err(anyhow!(t!(
+            "output-exists",
+            path = first.display().to_string()
+        )));
+    }
+    Ok(())
+}
+
+struct Part {
+    writer: BufWriter<fs::File>,
+    output: PendingOutput,
+    path: PathBuf,
+    written: u64,
+    digest: blake3::Hasher,
+}
+
+/// Writes an output as parts. Each part is created like any output, and all
+/// of them are moved into place by [`Writer::finish`]; until then an earlier
+/// output is untouched.
+pub struct Writer {
+    path: PathBuf,
+    /// Data bytes per part.
+    capacity: u64,
+    set: [u8; SET_LENGTH],
+    io: IoOptions,
+    done: Vec<PendingOutput>,
+    /// The digest of each part closed so far.
+    digests: Vec<[u8; 32]>,
+    /// The start of the output, for the key of the index.
+    head: Vec<u8>,
+    current: Option<Part>,
+}
+
+impl Writer {
+    /// Parts of `path`, each at most `size` bytes.
+    pub fn new(path: &Path, size: u64, io: IoOptions) -> Result<Writer> {
+        let capacity = size
+            .checked_sub(HEADER_LENGTH as u64)
+            .filter(|&capacity| capacity > 0)
+            .ok_or_else(|| anyhow!(t!("split-size-too-small", min = HEADER_LENGTH)))?;
+        Ok(Writer {
+            path: path.to_owned(),
+            capacity,
+            set: entropy::bytes()?,
+            io,
+            done: Vec::new(),
+            digests: Vec::new(),
+            head: Vec::new(),
+            current: None,
+        })
+    }
+
+    fn index(&self) -> u32 {
+        self.done.len() as u32 + u32::from(self.current.is_some())
+    }
+
+    /// Closes `part`, noting its digest.
+    fn close(&mut self, part: Part) -> Result<()> {
+        self.digests.push(*part.digest.finalize().as_bytes());
+        let file = part.writer.into_inner().map_err(|e| e.into_error())?;
+        sync_file(&part.path, &file, self.io)?;
+        self.done.push(part.output);
+        Ok(())
+    }
+
+    /// Closes the current part, if any, and starts the next.
+    fn next_part(&mut self) -> Result<()> {
+        if let Some(part) = self.current.take() {
+            self.close(part)?;
+        }
+        let index = self
+            .index()
+            .checked_add(1)
+            .ok_or_else(|| anyhow!(t!("split-too-many-parts")))?;
+        let path = part_path(&self.path, index);
+        let (file, output) = create_file(&path, self.io)?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(MAGIC)
+            .and_then(|()| writer.write_all(&[VERSION]))
+            .and_then(|()| writer.write_all(&self.set))
+            .and_then(|()| writer.write_all(&index.to_le_bytes()))
+            .and_then(|()| writer.write_all(&[0]))
+            .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+        self.current = Some(Part {
+            writer,
+            output,
+            path,
+            written: 0,
+            digest: blake3::Hasher::new(),
+        });
+        Ok(())
+    }
+
+    /// Marks the current part as the last, writes the index under the key
+    /// `keys` encrypted the output with, and moves every part into place.
+    pub fn finish(mut self, keys: &mut Encryptor) -> Result<()> {
+        if self.current.is_none() {
+            self.next_part()?;
+        }
+        let mut part = self.current.take().expect("started above");
+        part.writer
+            .flush()
+            .and_then(|()| part.writer.get_mut().seek(SeekFrom::Start(LAST_OFFSET)))
+            .and_then(|_| part.writer.get_mut().write_all(&[1]))
+            .with_context(|| t!("file-write-failed", path = part.path.display().to_string()))?;
+        self.close(part)?;
+        let count = self.done.len() as u32;
+        let key = keys.binding_key(&self.head, INDEX_CONTEXT)?;
+        self.write_index(count, &key)?;
+        for output in self.done.drain(..) {
+            output.commit(self.io)?;
+        }
+        if !report::quiet() {
+            println!(
+                "{}",
+                t!(
+                    "split-done",
+                    count = count,
+                    first = part_path(&self.path, 1).display().to_string(),
+                    last = part_path(&self.path, count).display().to_string()
+                )
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Writer {
+    fn write_index(&mut self, count: u32, key: &[u8; 32]) -> Result<()> {
+        let mut index = INDEX_MAGIC.to_vec();
+        index.push(VERSION);
+        index.extend_from_slice(&self.set);
+        index.extend_from_slice(&count.to_le_bytes());
+        for digest in &self.digests {
+            index.extend_from_slice(digest);
+        }
+        let mac = index_mac(key, &index);
+        index.extend_from_slice(&mac);
+        let path = index_path(&self.path);
+        let (mut file, output) = create_file(&path, self.io)?;
+        file.write_all(&index)
+            .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+        sync_file(&path, &file, self.io)?;
+        self.done.push(output);
+        Ok(())
+    }
+}
+
+/// Writes `data` to `path` as parts of at most `size` bytes, indexed under
+/// the key `keys` encrypted it with.
+pub fn write(
+    path: &Path,
+    size: u64,
+    data: &[u8],
+    keys: &mut Encryptor,
+    io: IoOptions,
+) -> Result<()> {
+    let mut parts = Writer::new(path, size, io)?;
+    parts
+        .write_all(data)
+        .with_context(|| t!("file-write-failed", path = path.display().to_string()))?;
+    parts.finish(keys)
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|part| part.written == self.capacity)
+        {
+            self.next_part()
+                .map_err(|e| io::Error::other(format!("{e:#}")))?;
+        }
+        let part = self.current.as_mut().expect("started above");
+        let room = usize::try_from(self.capacity - part.written).unwrap_or(usize::MAX);
+        let n = part.writer.write(&buf[..buf.len().min(room)])?;
+        part.written += n as u64;
+        part.digest.update(&buf[..n]);
+        let kept = HEAD_LENGTH.saturating_sub(self.head.len()).min(n);
+        self.head.extend_from_slice(&buf[..kept]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some(part) => part.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the data of a split output's parts in order, checking each part
+/// against the index as it ends.
+pub struct Reader {
+    path: PathBuf,
+    version: u8,
+    set: [u8; SET_LENGTH],
+    index: u32,
+    last: bool,
+    current: BufReader<fs::File>,
+    digest: blake3::Hasher,
+    /// What the index lists, unless the parts predate it.
+    listed: Option<Index>,
+}
+
+/// The index of a split output as read: the digest of each part's data, and
+/// the MAC over them.
+#[derive(Clone)]
+pub struct Index {
+    digests: Vec<[u8; 32]>,
+    signed: Vec<u8>,
+    mac: [u8; 32],
+}
+
+impl Index {
+    /// The index of the parts of `path` in `version` with `set`.
+    fn read(path: &Path, version: u8, set: &[u8; SET_LENGTH]) -> Result<Index> {
+        let path = index_path(path);
+        let invalid = || anyhow!(t!("split-index-invalid", path = path.display().to_string()));
+        let index = match fs::read(&path) {
+            Ok(index) => index,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(anyhow!(t!(
+                    "split-missing",
+                    path = path.display().to_string()
+                )));
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| t!("file-read-failed", path = path.display().to_string()))
+            }
+        };
+        let fixed = INDEX_MAGIC.len() + 1 + SET_LENGTH + 4;
+        let (signed, mac) = index
+            .split_last_chunk::<32>()
+            .filter(|(signed, _)| signed.len() >= fixed)
+            .ok_or_else(invalid)?;
+        let (head, digests) = signed.split_at(fixed);
+        let count = u32::from_le_bytes(head[fixed - 4..].try_into().expect("sized above"));
+        if &head[..INDEX_MAGIC.len()] != INDEX_MAGIC
+            || head[INDEX_MAGIC.len()] != version
+            || head[INDEX_MAGIC.len() + 1..][..SET_LENGTH] != set[..]
+            || count == 0
+            || digests.len() as u64 != u64::from(count) * 32
+        {
+            return Err(invalid());
+        }
+        Ok(Index {
+            digests: digests
+                .chunks_exact(32)
+                .map(|digest| digest.try_into().expect("sized above"))
+                .collect(),
+            signed: signed.to_vec(),
+            mac: *mac,
+        })
+    }
+
+    /// Checks the index's MAC under the key of the file that starts with
+    /// `file_bytes`, so that parts and index alike are known to come from
+    /// whoever encrypted it.
+    pub fn authenticate(&self, keys: &mut Encryptor, file_bytes: &[u8]) -> Result<()> {
+        let key = keys.binding_key(file_bytes, INDEX_CONTEXT)?;
+        if !bool::from(index_mac(&key, &self.signed).ct_eq(&self.mac)) {
+            return Err(anyhow!(t!("split-index-forged")).context(DecryptError::Corrupted));
+        }
+        Ok(())
+    }
+}
+
+impl Reader {
+    /// The parts `input` names, as its first part or as the output it was
+    /// split from, or `None` when it is an ordinary file.
+    pub fn open(input: &Path) -> Result<Option<Reader>> {
+        let first = match input.exists() {
+            false => part_path(input, 1),
+            true => input.to_owned(),
+        };
+        let Ok(mut file) = fs::File::open(&first) else {
+            return Ok(None);
+        };
+        let mut magic = [0; MAGIC.len()];
+        let is_part = file.read_exact(&mut magic).is_ok() && &magic == MAGIC;
+        let Some(name) = first
+            .to_str()
+            .and_then(|name| name.strip_suffix(FIRST_SUFFIX))
+        else {
+            return match is_part {
+                true => Err(anyhow!(t!(
+                    "split-first-part",
+                    path = first.display().to_string()
+                ))),
+                false => Ok(None),
+            };
+        };
+        if !is_part {
+            return Ok(None);
+        }
+        file.rewind()
+            .with_context(|| t!("file-read-failed", path = first.display().to_string()))?;
+        let mut current = BufReader::new(file);
+        let (version, set, index, last) = read_header(&mut current, &first)?;
+        if index != 1 {
+            return Err(anyhow!(t!(
+                "split-first-part",
+                path = first.display().to_string()
+            )));
+        }
+        let path = PathBuf::from(name);
+        let listed = match version {
+            UNINDEXED_VERSION => None,
+            _ => Some(Index::read(&path, version, &set)?),
+        };
+        Ok(Some(Reader {
+            path,
+            version,
+            set,
+            index,
+            last,
+            current,
+            digest: blake3::Hasher::new(),
+            listed,
+        }))
+    }
+
+    /// The index, to authenticate once the file's key is known, unless the
+    /// parts predate it.
+    pub fn index(&self) -> Option<Index> {
+        self.listed.clone()
+    }
+
+    /// Checks the part just read to its end against the index.
+    fn part_ended(&mut self) -> io::Result<()> {
+        let digest = std::mem::take(&mut self.digest).finalize();
+        let Some(listed) = &self.listed else {
+            return Ok(());
+        };
+        let path = part_path(&self.path, self.index);
+        let expected = listed.digests.get(self.index as usize - 1);
+        if !expected.is_some_and(|expected| bool::from(expected.ct_eq(digest.as_bytes()))) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                t!("split-part-damaged", path = path.display().to_string()),
+            ));
+        }
+        if self.last && listed.digests.len() != self.index as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                t!(
+                    "split-missing",
+                    path = part_path(&self.path, self.index + 1).display().to_string()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Moves on to the part after the current one.
+    fn next_part(&mut self) -> io::Result<()> {
+        self.part_ended()?;
+        let index = self.index + 1;
+        let path = part_path(&self.path, index);
+        let file = fs::File::open(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                e.kind(),
+                t!("split-missing", path = path.display().to_string()),
+            ),
+            _ => e,
+        })?;
+        let mut current = BufReader::new(file);
+        let (version, set, found, last) =
+            read_header(&mut current, &path).map_err(io::Error::other)?;
+        if version != self.version || set != self.set || found != index {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                t!("split-mismatch", path = path.display().to_string()),
+            ));
+        }
+        self.index = index;
+        self.last = last;
+        self.current = current;
+        Ok(())
+    }
+}
+
+/// Reads a part's header: its version, set, index and last flag.
+fn read_header(input: &mut impl Read, path: &Path) -> Result<(u8, [u8; SET_LENGTH], u32, bool)> {
+    let mut header = [0; HEADER_LENGTH];
+    input
+        .read_exact(&mut header)
+        .with_context(|| t!("split-mismatch", path = path.display().to_string()))?;
+    let (magic, rest) = header.split_at(MAGIC.len());
+    if magic != MAGIC || !(UNINDEXED_VERSION..=VERSION).contains(&rest[0]) {
+        return Err(anyhow!(t!(
+            "split-mismatch",
+            path = path.display().to_string()
+        )));
+    }
+    let set = rest[1..][..SET_LENGTH].try_into().expect("sized above");
+    let index = u32::from_le_bytes(rest[1 + SET_LENGTH..][..4].try_into().expect("sized above"));
+    let last = rest[HEADER_LENGTH - MAGIC.len() - 1] == 1;
+    Ok((rest[0], set, index, last))
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            self.digest.update(&buf[..n]);
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            if self.last {
+                // Checked on the first read past the end, and then done with.
+                if self.listed.is_some() {
+                    self.part_ended()?;
+                    self.listed = None;
+                }
+                return Ok(0);
+            }
+            self.next_part()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_encryptor::Kdf;
+
+    const PART_SIZE: u64 = 100;
+
+    fn keys(password: &str) -> Encryptor<'_> {
+        Encryptor::with_kdf(password, Kdf::Pbkdf2 { iterations: 1 })
+    }
+
+    /// Splits an encryption of 1000 bytes into `dir/out.enc`'s parts,
+    /// returning the encrypted file and its path.
+    fn split(dir: &Path) -> (Vec<u8>, PathBuf) {
+        let path = dir.join("out.enc");
+        let mut keys = keys("pw");
+        let encrypted = keys.encrypt(&[7; 1000]).unwrap();
+        write(
+            &path,
+            PART_SIZE,
+            &encrypted,
+            &mut keys,
+            IoOptions::default(),
+        )
+        .unwrap();
+        (encrypted, path)
+    }
+
+    fn join(path: &Path) -> io::Result<Vec<u8>> {
+        let mut joined = Vec::new();
+        Reader::open(path)
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut joined)?;
+        Ok(joined)
+    }
+
+    fn set(path: &Path, at: usize, change: impl FnOnce(&mut u8)) {
+        let mut bytes = fs::read(path).unwrap();
+        change(&mut bytes[at]);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn parts_join_back_into_the_encrypted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (encrypted, path) = split(dir.path());
+        let capacity = PART_SIZE as usize - HEADER_LENGTH;
+        let count = encrypted.len().div_ceil(capacity) as u32;
+        for index in 1..=count {
+            assert!(fs::metadata(part_path(&path, index)).unwrap().len() <= PART_SIZE);
+        }
+        assert!(!part_path(&path, count + 1).exists());
+        assert_eq!(join(&path).unwrap(), encrypted);
+        // By the first part's name too.
+        assert_eq!(join(&part_path(&path, 1)).unwrap(), encrypted);
+
+        let index = Reader::open(&path).unwrap().unwrap().index().unwrap();
+        index.authenticate(&mut keys("pw"), &encrypted).unwrap();
+        let wrong = index
+            .authenticate(&mut keys("wrong"), &encrypted)
+            .unwrap_err();
+        assert_eq!(wrong.downcast_ref(), Some(&DecryptError::WrongKey));
+        assert_eq!(keys("pw").decrypt(&encrypted).unwrap(), [7; 1000]);
+    }
+
+    #[test]
+    fn damaged_missing_and_forged_parts_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (encrypted, path) = split(dir.path());
+        set(&part_path(&path, 2), HEADER_LENGTH, |byte| *byte ^= 1);
+        let damaged = join(&path).unwrap_err().to_string();
+        assert!(damaged.contains(&part_path(&path, 2).display().to_string()));
+
+        let (_, path) = split(dir.path());
+        fs::remove_file(part_path(&path, 3)).unwrap();
+        assert!(join(&path).is_err());
+
+        // A part of another output under the same name.
+        let (_, path) = split(dir.path());
+        let other = dir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        let (_, other) = split(&other);
+        fs::copy(part_path(&other, 2), part_path(&path, 2)).unwrap();
+        assert!(join(&path).is_err());
+
+        let (encrypted_again, path) = split(dir.path());
+        assert_ne!(encrypted_again, encrypted);
+        let first_digest = INDEX_MAGIC.len() + 1 + SET_LENGTH + 4;
+        set(&index_path(&path), first_digest, |byte| *byte ^= 1);
+        let forged = Reader::open(&path).unwrap().unwrap().index().unwrap();
+        let refused = forged
+            .authenticate(&mut keys("pw"), &encrypted_again)
+            .unwrap_err();
+        assert_eq!(refused.downcast_ref(), Some(&DecryptError::Corrupted));
+    }
+
+    /// Parts from before the index have version 1 and no `.idx` file.
+    #[test]
+    fn unindexed_parts_still_join() {
+        let dir = tempfile::tempdir().unwrap();
+        let (encrypted, path) = split(dir.path());
+        fs::remove_file(index_path(&path)).unwrap();
+        assert!(Reader::open(&path).is_err());
+        for index in 1.. {
+            let part = part_path(&path, index);
+            if !part.exists() {
+                break;
+            }
+            set(&part, MAGIC.len(), |version| *version = UNINDEXED_VERSION);
+        }
+        assert!(Reader::open(&path).unwrap().unwrap().index().is_none());
+        assert_eq!(join(&path).unwrap(), encrypted);
+    }
+}
@@ -0,0 +1,102 @@
+//! Outputs written under temporary names and renamed into place together,
+//! for `--all-or-nothing` runs and jobs: each output is written as
+//! `<output>`[`SUFFIX`], and only once every file is done are they all
+//! renamed to their real names. Otherwise, or if the run panics, every
+//! staged file is removed again, with the directories the run created for
+//! them, so an interrupted run leaves the tree as it found it.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+pub const SUFFIX: &str = ".fe-staged";
+
+/// Whether `path` is a staged name, which isn't reported as an output.
+pub fn is_staged(path: &Path) -> bool {
+    path.as_os_str().to_string_lossy().ends_with(SUFFIX)
+}
+
+/// What a run has staged so far. Dropping it without a successful
+/// [`Staging::commit`] undoes everything.
+#[derive(Default)]
+pub struct Staging {
+    staged: Mutex<Staged>,
+}
+
+#[derive(Default)]
+struct Staged {
+    /// Each staged name and the output it becomes.
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Directories the run created, to remove again on rollback.
+    dirs: Vec<PathBuf>,
+}
+
+impl Staging {
+    fn staged(&self) -> MutexGuard<'_, Staged> {
+        self.staged.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the name to write `output` under, tracked before anything is
+    /// written so that a partial write is cleaned up too. Call it before
+    /// creating the output's directory, so that the directory is removed on
+    /// rollback.
+    pub fn stage(&self, output: &Path) -> PathBuf {
+        let mut staged_name = output.as_os_str().to_owned();
+        staged_name.push(SUFFIX);
+        let staged_name = PathBuf::from(staged_name);
+        let mut staged = self.staged();
+        let missing = output
+            .parent()
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .filter(|dir| !staged.dirs.iter().any(|known| known == dir))
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        staged.dirs.extend(missing);
+        staged.files.push((staged_name.clone(), output.to_owned()));
+        staged_name
+    }
+
+    /// Renames every staged file to its output, returning the output that
+    /// could not be renamed if one fails. A staged name nothing was written
+    /// to, as when decrypting put a file under the name it recorded
+    /// instead, is passed over. Outputs renamed before a failure are
+    /// complete and stay.
+    pub fn commit(&self) -> Result<(), PathBuf> {
+        let mut staged = self.staged();
+        while let Some((staged_name, output)) = staged.files.first().cloned() {
+            if fs::symlink_metadata(&staged_name).is_ok()
+                && fs::rename(&staged_name, &output).is_err()
+            {
+                return Err(output);
+            }
+            staged.files.remove(0);
+        }
+        staged.dirs.clear();
+        Ok(())
+    }
+
+    /// Removes every staged file and then any directory left empty.
+    pub fn rollback(&self) {
+        let mut staged = self.staged();
+        for (staged_name, _) in staged.files.drain(..) {
+            let _ = fs::remove_file(staged_name);
+        }
+        // Deepest first, so parents are empty by the time they are reached.
+        staged
+            .dirs
+            .sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for dir in staged.dirs.drain(..) {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        self.rollback();
+    }
+}
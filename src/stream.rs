@@ -0,0 +1,985 @@
+//! `encrypt --stream`: the password-based format cut into fixed-size chunks,
+//! so a file of any size is encrypted and decrypted in bounded memory.
+//!
+//! Chunks are sealed with the STREAM construction over the file's cipher:
+//! each nonce is a random prefix (7 bytes, or 19 for XChaCha20-Poly1305), a
+//! 32-bit chunk counter and a last-chunk flag. Every chunk's tag is checked
+//! before its plaintext is handed out, and reordered, dropped or truncated
+//! chunks fail to decrypt. An uncompressed file's chunks line up with its
+//! plaintext, so an interrupted run can be picked up at any chunk boundary
+//! ([`Resumed`], [`Decryptor::skip`]). The file is
+//!
+//! ```text
+//! header | chunks | footer
+//! ```
+//!
+//! where the [`header`](crate::header) carries the nonce prefix and a nonzero
+//! chunk length and is, less any recipient slots, the associated data of
+//! every chunk. Each chunk is
+//! `chunk length` bytes of ciphertext plus a 16-byte tag, except the last,
+//! which is shorter and may be empty. The footer is the same transport
+//! checksum the single-message format ends with.
+//!
+//! The header records the SHA-256 of the plaintext only if the caller knew
+//! it before the first chunk ([`Encryptor::with_content_checksum`]); the
+//! plaintext is hashed again as it is encrypted, and decrypted, to check it.
+//!
+//! Files from before the versioned header instead start with
+//!
+//! ```text
+//! "FENCSTR1" | salt (16) | nonce prefix (7) | chunk length (u32 LE)
+//! ```
+//!
+//! and have no associated data; they are still read.
+//!
+//! [`Encryptor::with_threads`] seals the chunks of one file on several
+//! threads at once, and [`Decryptor::for_each_chunk`] opens them so. With the `tokio` feature, [`encrypt_async`] and
+//! [`decrypt_async`] do the same as [`encrypt`] and [`decrypt`] over tokio's
+//! async readers and writers.
+
+use aes_gcm::{
+    aead::{
+        stream::{NewStream, StreamBE32, StreamPrimitive},
+        KeyInit, Payload,
+    },
+    Aes256Gcm, Key,
+};
+use anyhow::{anyhow, Context, Result};
+use bincode::deserialize;
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    compress, entropy,
+    header::{self, CONTENT_CHECKSUM_LENGTH, TAG_LENGTH},
+    i18n::t,
+    Cipher, Compression, DecryptError, Encryptor, FOOTER_LENGTH, FOOTER_MAGIC, SALT_LENGTH,
+};
+
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod pipeline;
+#[cfg(feature = "tokio")]
+pub use asynchronous::{decrypt_async, encrypt_async};
+
+/// The magic of the original streamed files.
+pub const MAGIC: &[u8; 8] = b"FENCSTR1";
+const LEGACY_HEADER_LENGTH: usize = MAGIC.len() + SALT_LENGTH + LEGACY_NONCE_PREFIX_LENGTH + 4;
+const LEGACY_NONCE_PREFIX_LENGTH: usize = 7;
+/// The chunk length files are written with unless the session sets another.
+pub const CHUNK_LENGTH: u32 = 64 << 10;
+/// Headers claiming longer chunks are refused rather than allocated for.
+pub const MAX_CHUNK_LENGTH: u32 = 16 << 20;
+
+/// The chunk length suited to a plaintext of `plaintext_length` bytes:
+/// short chunks for small files, where a chunk's tag is a larger share and a
+/// long chunk is mostly empty, and long ones for large files, where each
+/// chunk's setup and write cost less in all. A stream of unknown length,
+/// such as a pipe or a network input, keeps [`CHUNK_LENGTH`] so that no
+/// chunk waits long on a slow source.
+pub fn chunk_length_for(plaintext_length: Option<u64>) -> u32 {
+    match plaintext_length {
+        None => CHUNK_LENGTH,
+        Some(length) if length < 1 << 20 => 16 << 10,
+        Some(length) if length < 256 << 20 => CHUNK_LENGTH,
+        Some(length) if length < 4 << 30 => 1 << 20,
+        Some(_) => 4 << 20,
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyHeader {
+    salt: [u8; SALT_LENGTH],
+    nonce_prefix: [u8; LEGACY_NONCE_PREFIX_LENGTH],
+    chunk_length: u32,
+}
+
+/// The length of the encryption of `plaintext_length` bytes by `keys`,
+/// footer included, or `None` when compression makes it unknowable.
+pub fn encrypted_length(keys: &Encryptor, plaintext_length: u64) -> Option<u64> {
+    if keys.compression() != Compression::None {
+        return None;
+    }
+    let nonce = vec![0; keys.cipher().nonce_prefix_length()];
+    let metadata = keys.metadata.as_ref();
+    let header = header::Header {
+        key_check: Some([0; header::KEY_CHECK_LENGTH]),
+        aad_check: keys.aad.as_ref().map(|_| [0; header::AAD_CHECK_LENGTH]),
+        metadata: vec![0; metadata.map_or(0, |metadata| metadata.sealed_length(keys.cipher()))],
+        content_checksum: match keys.content_checksum {
+            Some(_) => vec![0; header::sealed_content_checksum_length(keys.cipher())],
+            None => Vec::new(),
+        },
+        ..keys.header([0; SALT_LENGTH], nonce, keys.chunk_length)
+    };
+    let header_length = header.to_bytes().ok()?.len();
+    let chunks = plaintext_length / u64::from(keys.chunk_length) + 1;
+    Some((header_length + FOOTER_LENGTH) as u64 + plaintext_length + chunks * TAG_LENGTH as u64)
+}
+
+/// Whether `input` starts like a chunked file, in either header.
+pub fn is_stream(mut input: impl Read) -> Result<bool> {
+    let mut magic = [0u8; MAGIC.len()];
+    if read_full(&mut input, &mut magic)? < magic.len() {
+        return Ok(false);
+    }
+    if &magic == MAGIC {
+        return Ok(true);
+    }
+    if &magic != header::MAGIC {
+        return Ok(false);
+    }
+    let (header, ..) = read_header(&mut io::Cursor::new(magic).chain(input))?;
+    Ok(header.chunk_length > 0)
+}
+
+/// The header `input` starts with, the legacy chunked one as the versioned
+/// kind, and its length in the file.
+pub fn header(mut input: impl Read) -> Result<(header::Header, usize)> {
+    let (header, _, length) = read_header(&mut input)?;
+    Ok((header, length))
+}
+
+/// Encrypts all of `input` to `output`, returning the number of bytes written.
+pub fn encrypt(keys: &mut Encryptor, input: impl Read, output: impl Write) -> Result<u64> {
+    encrypt_from(keys, None, input, output, |_, _| Ok(()))
+}
+
+/// [`encrypt`] in chunks of `chunk_length` bytes, from 1 to
+/// [`MAX_CHUNK_LENGTH`], instead of the session's. Decrypting
+/// reads the length from the header.
+pub fn encrypt_chunked(
+    keys: &mut Encryptor,
+    chunk_length: u32,
+    input: impl Read,
+    output: impl Write,
+) -> Result<u64> {
+    if !(1..=MAX_CHUNK_LENGTH).contains(&chunk_length) {
+        return Err(anyhow!(t!("chunk-length-invalid", max = MAX_CHUNK_LENGTH)));
+    }
+    seal(keys, None, chunk_length, input, output, |_, _| Ok(()))
+}
+
+/// Where an interrupted encryption got to, read back from the start of its
+/// output: the header and the chunks before a checkpoint.
+pub struct Resumed {
+    header: header::Header,
+    header_length: usize,
+    chunks: u64,
+    hasher: blake3::Hasher,
+}
+
+impl Resumed {
+    /// Reads the header and the first `chunks` chunks of `partial`, the
+    /// output of an interrupted [`encrypt_from`], hashing them for the footer.
+    pub fn read(mut partial: impl Read, chunks: u64) -> Result<Resumed> {
+        let mut partial = Hashing::new(&mut partial);
+        let (header, aad, header_length) = read_header(&mut partial)?;
+        // Compressed chunks don't line up with the plaintext.
+        if aad.is_empty() || header.compression != Compression::None || header.chunk_length == 0 {
+            return Err(anyhow!(t!("resume-unsupported")));
+        }
+        let sealed_length = u64::from(header.chunk_length) + TAG_LENGTH as u64;
+        let copied = io::copy(
+            &mut (&mut partial).take(chunks * sealed_length),
+            &mut io::sink(),
+        )?;
+        if copied < chunks * sealed_length {
+            return Err(anyhow!(t!("resume-truncated")));
+        }
+        Ok(Resumed {
+            header,
+            header_length,
+            chunks,
+            hasher: partial.hasher,
+        })
+    }
+
+    /// How much of the plaintext the chunks read back hold.
+    pub fn plaintext_offset(&self) -> u64 {
+        self.chunks * u64::from(self.header.chunk_length)
+    }
+
+    /// How much of the output they and the header take.
+    pub fn output_offset(&self) -> u64 {
+        let sealed_length = u64::from(self.header.chunk_length) + TAG_LENGTH as u64;
+        self.header_length as u64 + self.chunks * sealed_length
+    }
+}
+
+/// Encrypts `input` to `output`, returning the number of bytes written in
+/// all. With `resumed`, the file is the one it was read back from: `input`
+/// and `output` carry on from its offsets, and the key is the one `keys`
+/// derives for its header. After each chunk but the last, `checkpoint` is
+/// handed the output and the number of chunks written so far.
+pub fn encrypt_from<W: Write>(
+    keys: &mut Encryptor,
+    resumed: Option<Resumed>,
+    input: impl Read,
+    output: W,
+    checkpoint: impl FnMut(&mut W, u64) -> Result<()>,
+) -> Result<u64> {
+    let chunk_length = keys.chunk_length;
+    seal(keys, resumed, chunk_length, input, output, checkpoint)
+}
+
+/// [`encrypt_from`], with `chunk_length` for a file that isn't resumed. A
+/// file that isn't resumed takes the session's content checksum, which the
+/// input must match.
+fn seal<W: Write>(
+    keys: &mut Encryptor,
+    resumed: Option<Resumed>,
+    chunk_length: u32,
+    input: impl Read,
+    output: W,
+    mut checkpoint: impl FnMut(&mut W, u64) -> Result<()>,
+) -> Result<u64> {
+    let mut expected = None;
+    let (header, key, mut output, chunks) = match resumed {
+        None => {
+            let mut nonce = vec![0; keys.cipher().nonce_prefix_length()];
+            entropy::fill(&mut nonce)?;
+            expected = keys.content_checksum.take();
+            let (header, key) = keys.sealing_header(nonce, chunk_length, expected.as_ref())?;
+            let mut output = Hashing::new(output);
+            output.write_all(&header.to_bytes()?)?;
+            (header, key, output, 0)
+        }
+        Some(resumed) => {
+            let key = keys.key(&resumed.header)?;
+            let length = resumed.output_offset();
+            let output = Hashing {
+                inner: output,
+                hasher: resumed.hasher,
+                length,
+            };
+            (resumed.header, key, output, resumed.chunks)
+        }
+    };
+    let position = u32::try_from(chunks).map_err(|_| anyhow!(t!("resume-truncated")))?;
+    let mut content = Content {
+        inner: input,
+        hasher: expected.map(|_| Sha256::new()),
+    };
+    let mut input = compress::Reader::new(header.compression, &mut content)?;
+    let mut encryptor = Chunks::new(header.cipher, &key, &header.nonce, position);
+    let aad = keys.bind_aad(&header, &key, header.associated_data()?)?;
+
+    if keys.threads > 1 && !cfg!(target_arch = "wasm32") {
+        let pipeline = pipeline::Pipeline {
+            chunks: &encryptor,
+            aad: &aad,
+            chunk_length: header.chunk_length as usize,
+            threads: keys.threads,
+        };
+        pipeline.seal(&mut input, &mut output, checkpoint)?;
+    } else {
+        let mut chunk = vec![0; header.chunk_length as usize];
+        loop {
+            let length = read_full(&mut input, &mut chunk)?;
+            let last = length < chunk.len();
+            let payload = Payload {
+                msg: &chunk[..length],
+                aad: &aad,
+            };
+            let sealed = encryptor
+                .seal(payload, last)
+                .map_err(|e| anyhow!(e))
+                .with_context(|| t!("encrypt-failed"))?;
+            output.write_all(&sealed)?;
+            if last {
+                break;
+            }
+            checkpoint(&mut output.inner, u64::from(encryptor.position))?;
+        }
+    }
+    if let (Some(expected), Some(hasher)) = (expected, content.hasher) {
+        if !bool::from(hasher.finalize().ct_eq(&expected)) {
+            return Err(anyhow!(t!("input-changed")));
+        }
+    }
+
+    let digest = output.digest();
+    let written = output.length + FOOTER_LENGTH as u64;
+    let mut output = output.inner;
+    output.write_all(digest.as_bytes())?;
+    output.write_all(FOOTER_MAGIC)?;
+    output.flush()?;
+    Ok(written)
+}
+
+/// Decrypts all of `input` to `output`.
+pub fn decrypt(keys: &mut Encryptor, input: impl Read, mut output: impl Write) -> Result<()> {
+    let mut decryptor = Decryptor::new(keys, input)?;
+    decryptor.for_each_chunk(|chunk, _| Ok(output.write_all(&chunk)?))?;
+    output.flush()?;
+    Ok(())
+}
+
+/// A chunk that failed to authenticate, as [`Decryptor::next_salvaged`]
+/// finds it.
+#[derive(Clone, Copy, Debug)]
+pub struct Damage {
+    /// The chunk's number, from 0.
+    pub chunk: u64,
+    /// Where its ciphertext and tag start in the file.
+    pub offset: u64,
+    /// Their length, shorter than a tag if the file ends in the chunk.
+    pub length: u64,
+    /// Where its plaintext starts and how long it is, unless the file is
+    /// compressed and chunks don't line up with the plaintext.
+    pub plaintext: Option<(u64, u64)>,
+}
+
+impl Damage {
+    /// Whether the file ends before the chunk's tag, so nothing of it is
+    /// there to check.
+    pub fn is_missing(&self) -> bool {
+        self.length < TAG_LENGTH as u64
+    }
+}
+
+/// A chunk read by [`Decryptor::next_salvaged`].
+pub enum Salvaged {
+    /// Its plaintext. In a compressed file, chunks after a damaged one
+    /// authenticate but can't be decompressed, and come out empty.
+    Intact(Vec<u8>),
+    Damaged(Damage),
+}
+
+/// Decrypts a stream one chunk at a time, for callers that act on the first
+/// chunk (proof the password is right) before committing to an output. The
+/// input is read front to back only, so it may be a pipe.
+pub struct Decryptor<R> {
+    input: R,
+    header_length: u64,
+    /// Taken when the last chunk is decrypted.
+    decryptor: Option<Chunks>,
+    /// The versioned header, or nothing for the legacy one.
+    aad: Vec<u8>,
+    /// One sealed chunk plus a footer's worth of read-ahead, so the end of
+    /// the input, and with it the footer, is known before the last chunk is
+    /// decrypted.
+    buffer: Vec<u8>,
+    filled: usize,
+    sealed_length: usize,
+    /// Of everything before the footer consumed so far.
+    hasher: blake3::Hasher,
+    inflater: compress::Inflater,
+    compressed: bool,
+    /// Whether the key is known to be right, from the header's key check or
+    /// an authenticated chunk, so a chunk that fails was damaged.
+    key_proven: bool,
+    /// Whether salvaging passed over a damaged chunk.
+    damaged: bool,
+    /// Whether salvaging found the checksum footer wrong.
+    footer_mismatch: bool,
+    /// The header's content checksum and the hash of the plaintext so far,
+    /// unless the header has none or chunks were skipped.
+    content_checksum: Option<([u8; CONTENT_CHECKSUM_LENGTH], Sha256)>,
+    /// How many threads [`Decryptor::for_each_chunk`] opens chunks on.
+    threads: usize,
+}
+
+impl<R: Read> Decryptor<R> {
+    pub fn new(keys: &mut Encryptor, input: R) -> Result<Self> {
+        let mut input = Hashing::new(input);
+        let (header, aad, header_length) = read_header(&mut input)?;
+        if !(1..=MAX_CHUNK_LENGTH).contains(&header.chunk_length) {
+            return Err(anyhow!(t!("decrypt-failed")));
+        }
+        let key = keys.key(&header)?;
+        let aad = keys.bind_aad(&header, &key, aad)?;
+        let content_checksum = match header.content_checksum.is_empty() {
+            true => None,
+            false => Some((
+                header::open_content_checksum(&header.content_checksum, header.cipher, &key)?,
+                Sha256::new(),
+            )),
+        };
+        let decryptor = Chunks::new(header.cipher, &key, &header.nonce, 0);
+        let sealed_length = header.chunk_length as usize + TAG_LENGTH;
+        Ok(Decryptor {
+            input: input.inner,
+            header_length: header_length as u64,
+            decryptor: Some(decryptor),
+            aad,
+            buffer: vec![0; sealed_length + FOOTER_LENGTH],
+            filled: 0,
+            sealed_length,
+            hasher: input.hasher,
+            inflater: compress::Inflater::new(header.compression)?,
+            compressed: header.compression != Compression::None,
+            key_proven: header.key_check.is_some(),
+            damaged: false,
+            footer_mismatch: false,
+            content_checksum,
+            threads: keys.threads,
+        })
+    }
+
+    /// Hands the plaintext of each chunk still to come to `handle` in order,
+    /// with the number of chunks decrypted so far, or `None` after the last.
+    /// With [`Encryptor::with_threads`] the chunks are opened on several
+    /// threads while this one reads them and hands them out; either way the
+    /// run stops at the first chunk that fails, and nothing after it reaches
+    /// `handle`.
+    pub fn for_each_chunk(
+        &mut self,
+        mut handle: impl FnMut(Vec<u8>, Option<u64>) -> Result<()>,
+    ) -> Result<()> {
+        if self.threads == 1 || cfg!(target_arch = "wasm32") {
+            while let Some(chunk) = self.next_chunk()? {
+                handle(chunk, self.position())?;
+            }
+            return Ok(());
+        }
+        // The workers share the cipher and associated data while the
+        // pipeline has the rest of the decryptor.
+        let Some(chunks) = self.decryptor.take() else {
+            return Ok(());
+        };
+        let aad = std::mem::take(&mut self.aad);
+        let pipeline = pipeline::Pipeline {
+            chunks: &chunks,
+            aad: &aad,
+            chunk_length: self.sealed_length - TAG_LENGTH,
+            threads: self.threads,
+        };
+        pipeline.open(self, handle)
+    }
+
+    /// The next chunk of plaintext, or `None` after the last one. For a
+    /// compressed file it is what the chunk decompresses to, which may be
+    /// nothing yet or far more than a chunk.
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.advance(false)? {
+            Some(Salvaged::Intact(plaintext)) => Ok(Some(plaintext)),
+            Some(Salvaged::Damaged(_)) => unreachable!("only salvaging passes over damage"),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`next_chunk`](Self::next_chunk), but a chunk that fails to
+    /// authenticate once the key is proven right is reported and passed
+    /// over, so the chunks around a damaged region can still be recovered.
+    /// Chunks keep their places in the file, so damage only costs the chunks
+    /// it touches, unless the file was cut short. A wrong checksum footer is
+    /// left to [`footer_mismatch`](Self::footer_mismatch).
+    pub fn next_salvaged(&mut self) -> Result<Option<Salvaged>> {
+        self.advance(true)
+    }
+
+    /// Whether salvaging found the checksum footer wrong, which without a
+    /// damaged chunk means the damage is outside them, such as in a slot.
+    pub fn footer_mismatch(&self) -> bool {
+        self.footer_mismatch
+    }
+
+    fn advance(&mut self, salvage: bool) -> Result<Option<Salvaged>> {
+        let Some(decryptor) = self.decryptor.as_mut() else {
+            return Ok(None);
+        };
+        self.filled += read_full(&mut self.input, &mut self.buffer[self.filled..])?;
+        let mut rest = &self.buffer[..self.filled];
+        let mut footer = None;
+        // Only once the input has ended can what is left finish with the footer.
+        if self.filled < self.buffer.len()
+            && rest.len() >= FOOTER_LENGTH
+            && rest.ends_with(FOOTER_MAGIC)
+        {
+            let (body, trailer) = rest.split_at(rest.len() - FOOTER_LENGTH);
+            footer = Some(blake3::Hash::from_bytes(
+                trailer[..blake3::OUT_LEN].try_into()?,
+            ));
+            rest = body;
+        }
+        let chunk = u64::from(decryptor.position);
+        let damage = |length: usize| Damage {
+            chunk,
+            offset: self.header_length + chunk * self.sealed_length as u64,
+            length: length as u64,
+            plaintext: (!self.compressed).then(|| {
+                let chunk_length = (self.sealed_length - TAG_LENGTH) as u64;
+                let plaintext_length = length.saturating_sub(TAG_LENGTH) as u64;
+                (chunk * chunk_length, plaintext_length)
+            }),
+        };
+
+        if rest.len() >= self.sealed_length {
+            let sealed = &rest[..self.sealed_length];
+            self.hasher.update(sealed);
+            let payload = Payload {
+                msg: sealed,
+                aad: &self.aad,
+            };
+            let chunk = match decryptor.open(payload, false) {
+                Ok(plaintext) => Salvaged::Intact(plaintext),
+                Err(_) if salvage && self.key_proven => {
+                    decryptor.position = decryptor.position.saturating_add(1);
+                    Salvaged::Damaged(damage(self.sealed_length))
+                }
+                Err(e) => return Err(chunk_failure(anyhow!(e), self.key_proven)),
+            };
+            self.buffer.copy_within(self.sealed_length..self.filled, 0);
+            self.filled -= self.sealed_length;
+            return self.inflate(chunk, false).map(Some);
+        }
+
+        // A short chunk is the last one, and everything has now been read.
+        self.hasher.update(rest);
+        let damage = damage(rest.len());
+        let mut decryptor = self.decryptor.take().expect("checked above");
+        let payload = Payload {
+            msg: rest,
+            aad: &self.aad,
+        };
+        let chunk = match decryptor.open(payload, true) {
+            Ok(plaintext) => Salvaged::Intact(plaintext),
+            Err(_) if salvage && self.key_proven => Salvaged::Damaged(damage),
+            Err(e) => return Err(chunk_failure(anyhow!(e), self.key_proven)),
+        };
+        if footer.is_some_and(|footer| footer != self.hasher.finalize()) {
+            match salvage {
+                true => self.footer_mismatch = true,
+                false => return Err(DecryptError::ChecksumMismatch.into()),
+            }
+        }
+        self.inflate(chunk, true).map(Some)
+    }
+
+    /// Decompresses an intact chunk, the `last` one with what the
+    /// decompressor still holds, unless a damaged one came before it, and
+    /// after the last checks the plaintext against the content checksum.
+    fn inflate(&mut self, chunk: Salvaged, last: bool) -> Result<Salvaged> {
+        let plaintext = match chunk {
+            Salvaged::Intact(plaintext) => plaintext,
+            Salvaged::Damaged(damage) => {
+                self.damaged = true;
+                return Ok(Salvaged::Damaged(damage));
+            }
+        };
+        self.key_proven = true;
+        if self.compressed && self.damaged {
+            return Ok(Salvaged::Intact(Vec::new()));
+        }
+        let mut plaintext = self.inflater.push(plaintext)?;
+        if last {
+            plaintext.extend(self.inflater.finish()?);
+        }
+        if let Some((expected, hasher)) = self.content_checksum.as_mut() {
+            hasher.update(&plaintext);
+            // Salvaged zeros stand in for damaged chunks, and can't match.
+            if last && !self.damaged && !bool::from(hasher.finalize_reset().ct_eq(expected)) {
+                return Err(DecryptError::ContentMismatch.into());
+            }
+        }
+        Ok(Salvaged::Intact(plaintext))
+    }
+
+    /// The plaintext length of every chunk but the last, unless the file is
+    /// compressed, when chunks don't line up with the plaintext.
+    pub fn chunk_length(&self) -> Option<u64> {
+        match self.compressed {
+            true => None,
+            false => Some((self.sealed_length - TAG_LENGTH) as u64),
+        }
+    }
+
+    /// How many chunks have been decrypted, or skipped, until the last one
+    /// is.
+    pub fn position(&self) -> Option<u64> {
+        self.decryptor
+            .as_ref()
+            .map(|decryptor| u64::from(decryptor.position))
+    }
+
+    /// Passes over the next `chunks` chunks, which must not include the last,
+    /// without decrypting them, to resume decrypting after them. They are
+    /// still read for the checksum footer, but the content checksum can no
+    /// longer be checked.
+    pub fn skip(&mut self, chunks: u64) -> Result<()> {
+        if self.compressed {
+            return Err(anyhow!(t!("resume-unsupported")));
+        }
+        let decryptor = self
+            .decryptor
+            .as_mut()
+            .ok_or_else(|| anyhow!(t!("resume-truncated")))?;
+        if chunks > 0 {
+            self.content_checksum = None;
+        }
+        for _ in 0..chunks {
+            self.filled += read_full(&mut self.input, &mut self.buffer[self.filled..])?;
+            let ended = self.filled < self.buffer.len();
+            let mut available = self.filled;
+            if ended && self.buffer[..self.filled].ends_with(FOOTER_MAGIC) {
+                available = available.saturating_sub(FOOTER_LENGTH);
+            }
+            // The last chunk is the short one.
+            if available < self.sealed_length || decryptor.position == u32::MAX {
+                return Err(anyhow!(t!("resume-truncated")));
+            }
+            self.hasher.update(&self.buffer[..self.sealed_length]);
+            self.buffer.copy_within(self.sealed_length..self.filled, 0);
+            self.filled -= self.sealed_length;
+            decryptor.position += 1;
+        }
+        Ok(())
+    }
+}
+
+/// `e`, a chunk that failed to authenticate, as [`DecryptError::Corrupted`]
+/// once the key is `proven` right. Before then it may as well be the key.
+fn chunk_failure(e: anyhow::Error, proven: bool) -> anyhow::Error {
+    match proven {
+        true => e.context(DecryptError::Corrupted),
+        false => e.context(t!("decrypt-failed")),
+    }
+}
+
+/// Either header, as the versioned one, the associated data the chunks are
+/// sealed with, and the header's length.
+fn read_header(input: &mut impl Read) -> Result<(header::Header, Vec<u8>, usize)> {
+    let mut prefix = [0u8; header::PREFIX_LENGTH];
+    if read_full(input, &mut prefix)? < prefix.len() {
+        return Err(anyhow!(t!("decrypt-failed")));
+    }
+    if prefix.starts_with(MAGIC) {
+        let mut bytes = [0u8; LEGACY_HEADER_LENGTH];
+        bytes[..prefix.len()].copy_from_slice(&prefix);
+        if read_full(input, &mut bytes[prefix.len()..])? < LEGACY_HEADER_LENGTH - prefix.len() {
+            return Err(anyhow!(t!("decrypt-failed")));
+        }
+        let header: LegacyHeader = deserialize(&bytes[MAGIC.len()..])?;
+        let header = header::Header {
+            chunk_length: header.chunk_length,
+            ..header::Header::legacy(header.salt, header.nonce_prefix.to_vec())
+        };
+        return Ok((header, Vec::new(), LEGACY_HEADER_LENGTH));
+    }
+    let mut bytes = prefix.to_vec();
+    bytes.resize(header::Header::length(&prefix)?, 0);
+    if read_full(input, &mut bytes[prefix.len()..])? < bytes.len() - prefix.len() {
+        return Err(DecryptError::HeaderInvalid.into());
+    }
+    let header = header::Header::parse(&bytes)?;
+    let aad = header::associated_data(&bytes)?;
+    Ok((header, aad, bytes.len()))
+}
+
+/// The STREAM construction over whichever cipher the file uses, at a chunk
+/// counter that can start anywhere, so an interrupted file can be resumed.
+struct Chunks {
+    cipher: ChunkCipher,
+    /// The counter of the next chunk.
+    position: u32,
+}
+
+enum ChunkCipher {
+    /// Boxed, as AES's key schedule is far larger than ChaCha's key.
+    Aes256Gcm(Box<StreamBE32<Aes256Gcm>>),
+    ChaCha20Poly1305(StreamBE32<ChaCha20Poly1305>),
+    XChaCha20Poly1305(StreamBE32<XChaCha20Poly1305>),
+}
+
+impl Chunks {
+    /// `nonce_prefix` must be `cipher.nonce_prefix_length()` bytes. The first
+    /// chunk is chunk `position`.
+    fn new(cipher: Cipher, key: &Key<Aes256Gcm>, nonce_prefix: &[u8], position: u32) -> Self {
+        let cipher = match cipher {
+            Cipher::Aes256Gcm => ChunkCipher::Aes256Gcm(Box::new(StreamBE32::from_aead(
+                Aes256Gcm::new(key),
+                nonce_prefix.into(),
+            ))),
+            Cipher::ChaCha20Poly1305 => ChunkCipher::ChaCha20Poly1305(StreamBE32::from_aead(
+                ChaCha20Poly1305::new(key),
+                nonce_prefix.into(),
+            )),
+            Cipher::XChaCha20Poly1305 => ChunkCipher::XChaCha20Poly1305(StreamBE32::from_aead(
+                XChaCha20Poly1305::new(key),
+                nonce_prefix.into(),
+            )),
+        };
+        Chunks { cipher, position }
+    }
+
+    fn seal(&mut self, payload: Payload, last: bool) -> aes_gcm::aead::Result<Vec<u8>> {
+        self.apply(payload, last, true)
+    }
+
+    fn open(&mut self, payload: Payload, last: bool) -> aes_gcm::aead::Result<Vec<u8>> {
+        self.apply(payload, last, false)
+    }
+
+    fn apply(
+        &mut self,
+        payload: Payload,
+        last: bool,
+        seal: bool,
+    ) -> aes_gcm::aead::Result<Vec<u8>> {
+        let result = self.apply_at(self.position, payload, last, seal)?;
+        self.position += u32::from(!last);
+        Ok(result)
+    }
+
+    /// Seals chunk `position` without moving the counter, so chunks can be
+    /// sealed out of order and on several threads.
+    fn seal_at(
+        &self,
+        position: u32,
+        payload: Payload,
+        last: bool,
+    ) -> aes_gcm::aead::Result<Vec<u8>> {
+        self.apply_at(position, payload, last, true)
+    }
+
+    /// Opens chunk `position`, as [`Chunks::seal_at`] seals it.
+    fn open_at(
+        &self,
+        position: u32,
+        payload: Payload,
+        last: bool,
+    ) -> aes_gcm::aead::Result<Vec<u8>> {
+        self.apply_at(position, payload, last, false)
+    }
+
+    fn apply_at(
+        &self,
+        position: u32,
+        payload: Payload,
+        last: bool,
+        seal: bool,
+    ) -> aes_gcm::aead::Result<Vec<u8>> {
+        // The last counter is kept for the last chunk.
+        if !last && position == u32::MAX {
+            return Err(aes_gcm::aead::Error);
+        }
+        match (&self.cipher, seal) {
+            (ChunkCipher::Aes256Gcm(stream), true) => stream.encrypt(position, last, payload),
+            (ChunkCipher::Aes256Gcm(stream), false) => stream.decrypt(position, last, payload),
+            (ChunkCipher::ChaCha20Poly1305(stream), true) => {
+                stream.encrypt(position, last, payload)
+            }
+            (ChunkCipher::ChaCha20Poly1305(stream), false) => {
+                stream.decrypt(position, last, payload)
+            }
+            (ChunkCipher::XChaCha20Poly1305(stream), true) => {
+                stream.encrypt(position, last, payload)
+            }
+            (ChunkCipher::XChaCha20Poly1305(stream), false) => {
+                stream.decrypt(position, last, payload)
+            }
+        }
+    }
+}
+
+/// Passes reads or writes through, hashing the bytes for the footer.
+struct Hashing<T> {
+    inner: T,
+    hasher: blake3::Hasher,
+    length: u64,
+}
+
+impl<T> Hashing<T> {
+    fn new(inner: T) -> Self {
+        Hashing {
+            inner,
+            hasher: blake3::Hasher::new(),
+            length: 0,
+        }
+    }
+
+    fn digest(&self) -> blake3::Hash {
+        self.hasher.finalize()
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.length += n as u64;
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.length += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Passes reads through, hashing the plaintext for the content checksum if
+/// there is a `hasher`.
+struct Content<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+}
+
+impl<R: Read> Read for Content<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Reads until `buf` is full or the input ends, returning the length read.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: u32 = 4 << 10;
+    const SEALED: usize = CHUNK as usize + TAG_LENGTH;
+
+    fn keys(threads: usize) -> Encryptor<'static> {
+        Encryptor::from_key_file([7; 32]).with_threads(threads)
+    }
+
+    /// Enough chunks for several of the pipeline's runs, and a short last one.
+    fn plaintext() -> Vec<u8> {
+        (0..(3 << 20) + 5)
+            .map(|i: u32| (i * 31 % 251) as u8)
+            .collect()
+    }
+
+    fn encrypted(plaintext: &[u8]) -> (Vec<u8>, usize) {
+        let mut output = Vec::new();
+        encrypt_chunked(&mut keys(1), CHUNK, plaintext, &mut output).unwrap();
+        let (_, header_length) = header(&output[..]).unwrap();
+        (output, header_length)
+    }
+
+    /// `sealed` with its checksum footer recomputed, so only the chunks are
+    /// at fault.
+    fn with_footer(mut sealed: Vec<u8>) -> Vec<u8> {
+        let digest = blake3::hash(&sealed);
+        sealed.extend_from_slice(digest.as_bytes());
+        sealed.extend_from_slice(FOOTER_MAGIC);
+        sealed
+    }
+
+    fn decrypted(threads: usize, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        decrypt(&mut keys(threads), input, &mut output)?;
+        Ok(output)
+    }
+
+    fn is_corrupted(e: &anyhow::Error) -> bool {
+        e.downcast_ref::<DecryptError>() == Some(&DecryptError::Corrupted)
+    }
+
+    #[test]
+    fn truncation_at_a_chunk_boundary_is_rejected() {
+        let plaintext = plaintext();
+        let (sealed, header_length) = encrypted(&plaintext);
+        let cut = sealed[..header_length + 3 * SEALED].to_vec();
+        for threads in [1, 4] {
+            assert!(decrypted(threads, &cut).is_err());
+            let e = decrypted(threads, &with_footer(cut.clone())).unwrap_err();
+            assert!(is_corrupted(&e), "{e:#}");
+        }
+    }
+
+    #[test]
+    fn reordered_chunks_are_rejected() {
+        let plaintext = plaintext();
+        let (sealed, header_length) = encrypted(&plaintext);
+        let mut swapped = sealed[..sealed.len() - FOOTER_LENGTH].to_vec();
+        let (first, second) = (header_length + SEALED, header_length + 2 * SEALED);
+        let chunk = swapped[first..second].to_vec();
+        swapped.copy_within(second..second + SEALED, first);
+        swapped[second..second + SEALED].copy_from_slice(&chunk);
+        for threads in [1, 4] {
+            let e = decrypted(threads, &with_footer(swapped.clone())).unwrap_err();
+            assert!(is_corrupted(&e), "{e:#}");
+        }
+    }
+
+    #[test]
+    fn pipeline_output_matches_sequential_encryption() {
+        let plaintext = plaintext();
+        let (sealed, header_length) = encrypted(&plaintext);
+        for threads in [1, 4] {
+            // Resuming from no chunks seals again under the same header.
+            let resumed = Resumed::read(&sealed[..], 0).unwrap();
+            let mut output = sealed[..header_length].to_vec();
+            encrypt_from(
+                &mut keys(threads),
+                Some(resumed),
+                &plaintext[..],
+                &mut output,
+                |_, _| Ok(()),
+            )
+            .unwrap();
+            assert!(output == sealed, "{threads} threads");
+            assert!(decrypted(threads, &sealed).unwrap() == plaintext);
+        }
+    }
+
+    #[test]
+    fn resumed_picks_up_at_the_plaintext_offset() {
+        let plaintext = plaintext();
+        let (sealed, header_length) = encrypted(&plaintext);
+        let resumed = Resumed::read(&sealed[..], 3).unwrap();
+        assert_eq!(resumed.plaintext_offset(), 3 * u64::from(CHUNK));
+        assert_eq!(resumed.output_offset(), (header_length + 3 * SEALED) as u64);
+
+        let offset = resumed.output_offset() as usize;
+        let mut output = sealed[..offset].to_vec();
+        let rest = &plaintext[resumed.plaintext_offset() as usize..];
+        encrypt_from(
+            &mut keys(1),
+            Some(resumed),
+            rest,
+            &mut output,
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        assert!(output == sealed);
+
+        let short = &sealed[..header_length + 2 * SEALED];
+        assert!(Resumed::read(short, 3).is_err());
+    }
+
+    #[test]
+    fn the_session_chunk_length_is_recorded_and_read_back() {
+        let plaintext = plaintext();
+        let chunk_length = chunk_length_for(Some(plaintext.len() as u64));
+        assert_eq!(chunk_length, CHUNK_LENGTH);
+        for chunk_length in [chunk_length_for(Some(5)), chunk_length] {
+            let mut keys = keys(1).with_chunk_length(chunk_length);
+            let mut sealed = Vec::new();
+            let written = encrypt(&mut keys, &plaintext[..], &mut sealed).unwrap();
+            assert_eq!(Some(written), encrypted_length(&keys, plaintext.len() as u64));
+            assert_eq!(header(&sealed[..]).unwrap().0.chunk_length, chunk_length);
+            assert!(decrypted(1, &sealed).unwrap() == plaintext);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! The chunked format over tokio's [`AsyncRead`] and [`AsyncWrite`], for
+//! services that encrypt uploads and decrypt downloads as they pass through,
+//! without tying up a worker thread per transfer. The files are those of
+//! [`encrypt`](super::encrypt) and [`decrypt`](super::decrypt), and either
+//! side reads what the other writes.
+//!
+//! Only the sealing and opening of each chunk runs on the calling task, and a
+//! chunk at a time. The one key derivation a file costs does too: derive it
+//! beforehand on a blocking thread with [`Encryptor::derive`] when encrypting
+//! many files, or pick a cheaper KDF, if that matters.
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::{collections::VecDeque, io};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{Chunks, Decryptor, Hashing, CHUNK_LENGTH, LEGACY_HEADER_LENGTH, MAGIC};
+use crate::{
+    compress, entropy,
+    header::{self, Header},
+    i18n::t,
+    Encryptor, FOOTER_LENGTH, FOOTER_MAGIC,
+};
+
+/// How much of the input is read at once.
+const READ_LENGTH: usize = 16 << 10;
+
+/// Encrypts all of `input` to `output`, as [`super::encrypt`] does, returning
+/// the number of bytes written.
+pub async fn encrypt_async<R, W>(keys: &mut Encryptor<'_>, mut input: R, output: W) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut nonce = vec![0; keys.cipher().nonce_prefix_length()];
+    entropy::fill(&mut nonce)?;
+    let expected = keys.content_checksum.take();
+    let (header, key) = keys.sealing_header(nonce, CHUNK_LENGTH, expected.as_ref())?;
+    let mut output = Hashing::new(output);
+    output.put(&header.to_bytes()?).await?;
+    let mut content = expected.map(|_| Sha256::new());
+    let mut deflater = compress::Deflater::new(header.compression)?;
+    let mut encryptor = Chunks::new(header.cipher, &key, &header.nonce, 0);
+    let aad = keys.bind_aad(&header, &key, header.associated_data()?)?;
+
+    // Compressed plaintext waiting to fill a chunk.
+    let chunk_length = header.chunk_length as usize;
+    let mut pending = Vec::with_capacity(chunk_length + READ_LENGTH);
+    let mut piece = vec![0; READ_LENGTH];
+    loop {
+        let n = input.read(&mut piece).await?;
+        match n {
+            0 => deflater.finish(&mut pending)?,
+            n => {
+                if let Some(hasher) = &mut content {
+                    hasher.update(&piece[..n]);
+                }
+                deflater.push(&piece[..n], &mut pending)?;
+            }
+        }
+        // As in the blocking encryptor, a full chunk is never the last, so a
+        // plaintext of whole chunks ends with an empty one.
+        let mut start = 0;
+        while pending.len() - start >= chunk_length {
+            let chunk = &pending[start..start + chunk_length];
+            output
+                .put(&seal_chunk(&mut encryptor, chunk, &aad, false)?)
+                .await?;
+            start += chunk_length;
+        }
+        pending.drain(..start);
+        if n == 0 {
+            break;
+        }
+    }
+    output
+        .put(&seal_chunk(&mut encryptor, &pending, &aad, true)?)
+        .await?;
+    if let (Some(expected), Some(hasher)) = (expected, content) {
+        if !bool::from(hasher.finalize().ct_eq(&expected)) {
+            return Err(anyhow!(t!("input-changed")));
+        }
+    }
+
+    let digest = output.digest();
+    let written = output.length + FOOTER_LENGTH as u64;
+    let mut output = output.inner;
+    output.write_all(digest.as_bytes()).await?;
+    output.write_all(FOOTER_MAGIC).await?;
+    output.flush().await?;
+    Ok(written)
+}
+
+/// Decrypts all of `input` to `output`, as [`super::decrypt`] does,
+/// authenticating each chunk before writing it.
+pub async fn decrypt_async<R, W>(
+    keys: &mut Encryptor<'_>,
+    mut input: R,
+    mut output: W,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // The decryptor reads the whole header at once, and then never more than
+    // its read-ahead, so with that much fed to it ahead of time its reads
+    // only come up short at the real end of the input.
+    let mut feed = Feed::default();
+    feed.fill(&mut input, header::PREFIX_LENGTH).await?;
+    let prefix: Vec<u8> = feed.bytes.iter().copied().collect();
+    let length = match prefix.starts_with(MAGIC) {
+        true => Ok(LEGACY_HEADER_LENGTH),
+        false => Header::length(&prefix),
+    };
+    // A prefix that doesn't parse fails the same way in the decryptor.
+    if let Ok(length) = length {
+        feed.fill(&mut input, length).await?;
+    }
+    let mut decryptor = Decryptor::new(keys, feed)?;
+    let read_ahead = decryptor.buffer.len();
+    loop {
+        decryptor.input.fill(&mut input, read_ahead).await?;
+        match decryptor.next_chunk()? {
+            Some(chunk) => output.write_all(&chunk).await?,
+            None => break,
+        }
+    }
+    output.flush().await?;
+    Ok(())
+}
+
+fn seal_chunk(encryptor: &mut Chunks, chunk: &[u8], aad: &[u8], last: bool) -> Result<Vec<u8>> {
+    let payload = aes_gcm::aead::Payload { msg: chunk, aad };
+    encryptor
+        .seal(payload, last)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| t!("encrypt-failed"))
+}
+
+impl<W: AsyncWrite + Unpin> Hashing<W> {
+    async fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes).await?;
+        self.hasher.update(bytes);
+        self.length += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Input read ahead of the decryptor, which it reads as a blocking reader.
+#[derive(Default)]
+struct Feed {
+    bytes: VecDeque<u8>,
+}
+
+impl Feed {
+    /// Reads from `input` until at least `length` bytes are waiting, or the
+    /// input ends.
+    async fn fill(
+        &mut self,
+        input: &mut (impl AsyncRead + Unpin),
+        length: usize,
+    ) -> io::Result<()> {
+        let mut piece = [0; 8 << 10];
+        while self.bytes.len() < length {
+            let wanted = piece.len().min(length - self.bytes.len());
+            match input.read(&mut piece[..wanted]).await? {
+                0 => break,
+                n => self.bytes.extend(&piece[..n]),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for Feed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bytes.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stream, Cipher, Compression, DecryptError, Kdf, SALT_LENGTH};
+
+    fn keys(password: &str) -> Encryptor<'_> {
+        Encryptor::with_kdf(password, Kdf::Pbkdf2 { iterations: 1 })
+    }
+
+    /// Two whole chunks and a short one.
+    fn plaintext() -> Vec<u8> {
+        (0..2 * CHUNK_LENGTH as usize + 5)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    async fn decrypted(keys: &mut Encryptor<'_>, file: &[u8]) -> Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        decrypt_async(keys, file, &mut plaintext).await?;
+        Ok(plaintext)
+    }
+
+    #[tokio::test]
+    async fn either_side_reads_what_the_other_writes() {
+        let plaintext = plaintext();
+        let mut file = Vec::new();
+        let written = encrypt_async(&mut keys("pw"), plaintext.as_slice(), &mut file)
+            .await
+            .unwrap();
+        assert_eq!(written, file.len() as u64);
+        let mut blocking = Vec::new();
+        stream::decrypt(&mut keys("pw"), file.as_slice(), &mut blocking).unwrap();
+        assert_eq!(blocking, plaintext);
+
+        let mut file = Vec::new();
+        let mut compressing = keys("pw").with_compression(Compression::Zstd);
+        stream::encrypt(&mut compressing, plaintext.as_slice(), &mut file).unwrap();
+        assert_eq!(decrypted(&mut keys("pw"), &file).await.unwrap(), plaintext);
+    }
+
+    #[tokio::test]
+    async fn wrong_keys_damage_and_truncation_are_refused() {
+        let mut file = Vec::new();
+        encrypt_async(&mut keys("pw"), plaintext().as_slice(), &mut file)
+            .await
+            .unwrap();
+        let wrong = decrypted(&mut keys("wrong"), &file).await.unwrap_err();
+        assert_eq!(wrong.downcast_ref(), Some(&DecryptError::WrongKey));
+
+        let mut damaged = file[..file.len() - FOOTER_LENGTH].to_vec();
+        let middle = damaged.len() / 2;
+        damaged[middle] ^= 1;
+        crate::append_footer(&mut damaged);
+        let e = decrypted(&mut keys("pw"), &damaged).await.unwrap_err();
+        assert_eq!(e.downcast_ref(), Some(&DecryptError::Corrupted));
+
+        let mut cut = file[..file.len() - FOOTER_LENGTH - 1].to_vec();
+        crate::append_footer(&mut cut);
+        assert!(decrypted(&mut keys("pw"), &cut).await.is_err());
+    }
+
+    /// The chunked layout from before the header: the magic, the salt, a
+    /// 7-byte nonce prefix and the chunk length, then AES-256-GCM chunks under
+    /// PBKDF2 of the password.
+    #[tokio::test]
+    async fn streams_from_before_the_header_still_decrypt() {
+        let (salt, nonce_prefix) = ([2; SALT_LENGTH], [1; 7]);
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&salt);
+        file.extend_from_slice(&nonce_prefix);
+        file.extend_from_slice(&16u32.to_le_bytes());
+        let key = crate::derive_key("pw", Kdf::default(), &salt).unwrap();
+        let mut chunks = Chunks::new(Cipher::Aes256Gcm, &key, &nonce_prefix, 0);
+        file.extend(seal_chunk(&mut chunks, b"hello", &[], true).unwrap());
+        assert_eq!(file.len(), LEGACY_HEADER_LENGTH + 5 + header::TAG_LENGTH);
+
+        assert_eq!(decrypted(&mut keys("pw"), &file).await.unwrap(), b"hello");
+        assert!(decrypted(&mut keys("wrong"), &file).await.is_err());
+    }
+}
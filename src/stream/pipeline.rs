@@ -0,0 +1,379 @@
+//! Sealing or opening the chunks of one stream on several threads. The
+//! calling thread reads the input in runs of chunks, hands each run to
+//! whichever worker is free, and writes the finished runs back in order, so
+//! reading, the cipher and writing overlap and the output is the same as
+//! going through the chunks in turn. A bounded number of runs is in flight,
+//! so memory stays bounded too.
+
+use aes_gcm::aead::{self, Payload};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use super::{chunk_failure, read_full, Chunks, Decryptor, Hashing, Salvaged};
+use crate::{header::TAG_LENGTH, i18n::t, DecryptError, FOOTER_LENGTH, FOOTER_MAGIC};
+
+/// About how much plaintext one worker takes at a time, so that handing out
+/// work costs little next to the cipher's.
+const RUN_LENGTH: usize = 1 << 20;
+/// Runs in flight per worker, so none waits while the next one is read.
+const RUNS_PER_THREAD: usize = 2;
+
+pub(super) struct Pipeline<'a> {
+    pub chunks: &'a Chunks,
+    pub aad: &'a [u8],
+    pub chunk_length: usize,
+    pub threads: usize,
+}
+
+/// Whole chunks from chunk `position` on, of plaintext to seal or of
+/// ciphertext to open, and with `ends` the rest of the input, the last chunk
+/// among them.
+struct Run {
+    position: u64,
+    data: Vec<u8>,
+    ends: bool,
+}
+
+/// The chunks of `run`, cut `length` bytes apart. A full piece is never the
+/// last chunk, which may then be empty.
+fn pieces(run: &Run, length: usize) -> Vec<&[u8]> {
+    let mut pieces: Vec<&[u8]> = run.data.chunks(length).collect();
+    if run.ends && run.data.len().is_multiple_of(length) {
+        pieces.push(&[]);
+    }
+    pieces
+}
+
+/// The calling thread's side of a pipeline: where its runs come from, and
+/// where they go once the workers are done with them, in order.
+trait Stage {
+    type Done: Send;
+
+    /// The next run, or `None` once the input has all been read.
+    fn read(&mut self) -> Result<Option<Run>>;
+
+    fn write(&mut self, done: Self::Done) -> Result<()>;
+
+    /// The error for a worker gone before its run was done, which only a
+    /// panic does.
+    fn stopped(&self) -> anyhow::Error;
+}
+
+impl Pipeline<'_> {
+    /// Seals everything `input` holds to `output`, handing `checkpoint` the
+    /// output and the number of chunks written after each chunk but the last.
+    pub fn seal<W: Write>(
+        &self,
+        input: &mut impl Read,
+        output: &mut Hashing<W>,
+        checkpoint: impl FnMut(&mut W, u64) -> Result<()>,
+    ) -> Result<()> {
+        let position = u64::from(self.chunks.position);
+        let mut stage = Sealing {
+            input,
+            output,
+            checkpoint,
+            run_length: self.chunk_length * self.chunks_per_run(),
+            chunk_length: self.chunk_length,
+            position,
+            chunks_written: position,
+            ended: false,
+        };
+        self.drive(&mut stage, |run| self.seal_run(run))
+    }
+
+    /// Opens the chunks `decryptor` has yet to read from its input, from
+    /// chunk `self.chunks.position` on, handing `handle` each chunk's
+    /// plaintext as [`Decryptor::for_each_chunk`] does. The run stops at the
+    /// first chunk that fails to authenticate, after the chunks before it.
+    pub fn open<R: Read>(
+        &self,
+        decryptor: &mut Decryptor<R>,
+        handle: impl FnMut(Vec<u8>, Option<u64>) -> Result<()>,
+    ) -> Result<()> {
+        let position = u64::from(self.chunks.position);
+        let mut stage = Opening {
+            decryptor,
+            handle,
+            run_length: (self.chunk_length + TAG_LENGTH) * self.chunks_per_run(),
+            sealed_length: self.chunk_length + TAG_LENGTH,
+            position,
+            chunks_written: position,
+            ended: false,
+            footer: None,
+        };
+        self.drive(&mut stage, |run| self.open_run(run))
+    }
+
+    fn chunks_per_run(&self) -> usize {
+        (RUN_LENGTH / self.chunk_length).max(1)
+    }
+
+    /// How many runs may be read ahead of the output.
+    fn window(&self) -> usize {
+        self.threads * RUNS_PER_THREAD
+    }
+
+    /// Runs `stage` with `work` done to each of its runs on the workers.
+    fn drive<S: Stage>(&self, stage: &mut S, work: impl Fn(&Run) -> S::Done + Sync) -> Result<()> {
+        let (runs, queue) = mpsc::sync_channel::<(u64, Run)>(self.window());
+        let queue = Mutex::new(queue);
+        let (done, finished) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let (queue, done, work) = (&queue, done.clone(), &work);
+                scope.spawn(move || loop {
+                    // Ends once the runs are all handed out and the sender is gone.
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    let Ok((index, run)) = next else {
+                        break;
+                    };
+                    if done.send((index, work(&run))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(done);
+            // Returning drops the sender, which lets the workers go.
+            self.feed(stage, runs, finished)
+        })
+    }
+
+    /// Reads runs from `stage` into `runs`, at most [`Pipeline::window`]
+    /// ahead of the output, and writes what comes back `finished` in order.
+    fn feed<S: Stage>(
+        &self,
+        stage: &mut S,
+        runs: mpsc::SyncSender<(u64, Run)>,
+        finished: mpsc::Receiver<(u64, S::Done)>,
+    ) -> Result<()> {
+        let (mut read, mut written) = (0, 0);
+        let mut ended = false;
+        // Runs that came back before the ones ahead of them.
+        let mut waiting = BTreeMap::new();
+        loop {
+            while !ended && read - written < self.window() as u64 {
+                match stage.read()? {
+                    Some(run) => {
+                        runs.send((read, run)).map_err(|_| stage.stopped())?;
+                        read += 1;
+                    }
+                    None => ended = true,
+                }
+            }
+            if written == read {
+                return Ok(());
+            }
+            let (index, done) = finished.recv().map_err(|_| stage.stopped())?;
+            waiting.insert(index, done);
+            while let Some(done) = waiting.remove(&written) {
+                written += 1;
+                stage.write(done)?;
+            }
+        }
+    }
+
+    /// The sealed chunks of `run`, and whether the last is among them.
+    fn seal_run(&self, run: &Run) -> Result<(Vec<Vec<u8>>, bool)> {
+        let pieces = pieces(run, self.chunk_length);
+        let count = pieces.len();
+        let sealed = pieces
+            .into_iter()
+            .enumerate()
+            .map(|(i, msg)| {
+                let position = u32::try_from(run.position + i as u64).map_err(|_| aead::Error)?;
+                let payload = Payload { msg, aad: self.aad };
+                self.chunks
+                    .seal_at(position, payload, run.ends && i + 1 == count)
+            })
+            .collect::<aead::Result<_>>()
+            .map_err(|e| anyhow!(e))
+            .with_context(|| t!("encrypt-failed"))?;
+        Ok((sealed, run.ends))
+    }
+
+    /// The plaintext of the chunks of `run` up to the first that fails to
+    /// authenticate.
+    fn open_run(&self, run: &Run) -> Opened {
+        let pieces = pieces(run, self.chunk_length + TAG_LENGTH);
+        let count = pieces.len();
+        let mut opened = Opened {
+            plaintext: Vec::with_capacity(count),
+            ends: run.ends,
+            failed: None,
+        };
+        for (i, msg) in pieces.into_iter().enumerate() {
+            let payload = Payload { msg, aad: self.aad };
+            let plaintext = u32::try_from(run.position + i as u64)
+                .map_err(|_| aead::Error)
+                .and_then(|position| {
+                    self.chunks
+                        .open_at(position, payload, run.ends && i + 1 == count)
+                });
+            match plaintext {
+                Ok(plaintext) => opened.plaintext.push(plaintext),
+                Err(e) => {
+                    opened.failed = Some(e);
+                    break;
+                }
+            }
+        }
+        opened
+    }
+}
+
+struct Sealing<'a, R, W, C> {
+    input: &'a mut R,
+    output: &'a mut Hashing<W>,
+    checkpoint: C,
+    run_length: usize,
+    chunk_length: usize,
+    /// The first chunk of the next run.
+    position: u64,
+    chunks_written: u64,
+    ended: bool,
+}
+
+impl<R, W, C> Stage for Sealing<'_, R, W, C>
+where
+    R: Read,
+    W: Write,
+    C: FnMut(&mut W, u64) -> Result<()>,
+{
+    type Done = Result<(Vec<Vec<u8>>, bool)>;
+
+    fn read(&mut self) -> Result<Option<Run>> {
+        if self.ended {
+            return Ok(None);
+        }
+        let mut data = vec![0; self.run_length];
+        let length = read_full(self.input, &mut data)?;
+        data.truncate(length);
+        self.ended = length < self.run_length;
+        let run = Run {
+            position: self.position,
+            data,
+            ends: self.ended,
+        };
+        self.position += (length / self.chunk_length) as u64;
+        Ok(Some(run))
+    }
+
+    fn write(&mut self, done: Self::Done) -> Result<()> {
+        let (sealed, ends) = done?;
+        let count = sealed.len();
+        for (i, chunk) in sealed.into_iter().enumerate() {
+            self.output.write_all(&chunk)?;
+            if !(ends && i + 1 == count) {
+                self.chunks_written += 1;
+                (self.checkpoint)(&mut self.output.inner, self.chunks_written)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stopped(&self) -> anyhow::Error {
+        anyhow!(t!("encrypt-failed"))
+    }
+}
+
+/// What a worker made of a run of sealed chunks.
+struct Opened {
+    /// Of each chunk, up to a failed one.
+    plaintext: Vec<Vec<u8>>,
+    ends: bool,
+    failed: Option<aead::Error>,
+}
+
+struct Opening<'a, R, H> {
+    decryptor: &'a mut Decryptor<R>,
+    handle: H,
+    run_length: usize,
+    sealed_length: usize,
+    /// The first chunk of the next run.
+    position: u64,
+    chunks_written: u64,
+    ended: bool,
+    /// The checksum footer, once the input has ended in one.
+    footer: Option<blake3::Hash>,
+}
+
+impl<R, H> Stage for Opening<'_, R, H>
+where
+    R: Read,
+    H: FnMut(Vec<u8>, Option<u64>) -> Result<()>,
+{
+    type Done = Opened;
+
+    fn read(&mut self) -> Result<Option<Run>> {
+        if self.ended {
+            return Ok(None);
+        }
+        let decryptor = &mut *self.decryptor;
+        // What the decryptor had read ahead comes first, then a footer's
+        // worth more than the run, so the end is known before the last chunk.
+        let mut data = Vec::with_capacity(self.run_length + FOOTER_LENGTH);
+        data.extend_from_slice(&decryptor.buffer[..decryptor.filled]);
+        decryptor.filled = 0;
+        let start = data.len();
+        data.resize(self.run_length + FOOTER_LENGTH, 0);
+        let length = start + read_full(&mut decryptor.input, &mut data[start..])?;
+        data.truncate(length);
+        if length == self.run_length + FOOTER_LENGTH {
+            decryptor.buffer[..FOOTER_LENGTH].copy_from_slice(&data[self.run_length..]);
+            decryptor.filled = FOOTER_LENGTH;
+            data.truncate(self.run_length);
+        } else {
+            self.ended = true;
+            if data.len() >= FOOTER_LENGTH && data.ends_with(FOOTER_MAGIC) {
+                let body = data.len() - FOOTER_LENGTH;
+                let digest = data[body..body + blake3::OUT_LEN].try_into()?;
+                self.footer = Some(blake3::Hash::from_bytes(digest));
+                data.truncate(body);
+            }
+        }
+        decryptor.hasher.update(&data);
+        let run = Run {
+            position: self.position,
+            ends: self.ended,
+            data,
+        };
+        self.position += (run.data.len() / self.sealed_length) as u64;
+        Ok(Some(run))
+    }
+
+    fn write(&mut self, done: Self::Done) -> Result<()> {
+        let count = done.plaintext.len();
+        for (i, plaintext) in done.plaintext.into_iter().enumerate() {
+            let last = done.ends && done.failed.is_none() && i + 1 == count;
+            let decryptor = &mut *self.decryptor;
+            if last
+                && self
+                    .footer
+                    .is_some_and(|footer| footer != decryptor.hasher.finalize())
+            {
+                return Err(DecryptError::ChecksumMismatch.into());
+            }
+            let Salvaged::Intact(plaintext) =
+                decryptor.inflate(Salvaged::Intact(plaintext), last)?
+            else {
+                unreachable!("only damaged chunks pass through as damaged");
+            };
+            self.chunks_written += 1;
+            (self.handle)(plaintext, (!last).then_some(self.chunks_written))?;
+        }
+        match done.failed {
+            Some(e) => Err(chunk_failure(anyhow!(e), self.decryptor.key_proven)),
+            None => Ok(()),
+        }
+    }
+
+    fn stopped(&self) -> anyhow::Error {
+        anyhow!(t!("decrypt-failed"))
+    }
+}
@@ -0,0 +1,333 @@
+//! The built-in password strength check, run on every password a file is
+//! encrypted with whether or not there is a password policy: a zxcvbn-style
+//! estimate of how many guesses an attacker who knows how people choose
+//! passwords would need.
+//!
+//! The password is split into the pieces that are cheapest to guess:
+//! common passwords and words (also reversed, capitalised or with `@` for
+//! `a` and the like), runs such as `abc` or `9876`, repeats such as `aaa` or
+//! `abcabc`, rows of adjacent keys such as `qwerty`, years and dates, and
+//! anything else guessed character by character. The estimate is the
+//! product of the pieces' guesses, times the orders they could come in.
+//! Passwords that take fewer than [`MIN_GUESSES`] are refused unless
+//! `--allow-weak-password` is given.
+
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, sync::OnceLock};
+
+use file_encryptor::i18n::t;
+
+use crate::log;
+
+/// log10 of the fewest guesses a password may take.
+pub const MIN_GUESSES: f64 = 6.0;
+
+/// Only this many characters are looked at; more only add guesses.
+const MAX_LENGTH: usize = 64;
+
+/// log10 of the guesses added for each piece after the first, so that a
+/// password of many easy pieces still counts for something.
+const PIECE_GUESSES: f64 = 4.0;
+
+const REFERENCE_YEAR: i32 = 2026;
+
+const KEYBOARD: [&str; 4] = [
+    "1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+const SHIFTED: [&str; 4] = [
+    "!@#$%^&*()_+",
+    "QWERTYUIOP{}|",
+    "ASDFGHJKL:\"",
+    "ZXCVBNM<>?",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Word,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Date,
+    Other,
+}
+
+/// Refuses `password` if it is too easy to guess, unless `allow` is set, in
+/// which case it is only warned about on standard error.
+pub fn check(password: &str, allow: bool) -> Result<()> {
+    let (guesses, kind) = estimate(password);
+    if guesses >= MIN_GUESSES {
+        return Ok(());
+    }
+    let reason = match kind {
+        Kind::Word => t!("strength-word"),
+        Kind::Sequence => t!("strength-sequence"),
+        Kind::Repeat => t!("strength-repeat"),
+        Kind::Keyboard => t!("strength-keyboard"),
+        Kind::Date => t!("strength-date"),
+        Kind::Other => t!("strength-short"),
+    };
+    if !allow {
+        return Err(anyhow!(t!(
+            "strength-refused",
+            reason = reason,
+            guesses = guesses.floor()
+        )));
+    }
+    log::warn(&t!("strength-allowed", reason = reason));
+    Ok(())
+}
+
+/// log10 of the guesses `password` takes, and the kind of its largest
+/// easily guessed piece.
+fn estimate(password: &str) -> (f64, Kind) {
+    let chars: Vec<char> = password.chars().take(MAX_LENGTH).collect();
+    let n = chars.len();
+    if n == 0 {
+        return (0.0, Kind::Other);
+    }
+    // best[k][j]: the fewest guesses for chars[..j] in k pieces, and the
+    // piece ending at j.
+    let mut best = vec![vec![None::<(f64, usize, Kind)>; n + 1]; n + 1];
+    best[0][0] = Some((0.0, 0, Kind::Other));
+    for j in 1..=n {
+        for i in 0..j {
+            let (guesses, kind) = piece(&chars[i..j]);
+            for k in 0..j {
+                let Some((before, ..)) = best[k][i] else {
+                    continue;
+                };
+                let total = before + guesses;
+                if best[k + 1][j].is_none_or(|(old, ..)| total < old) {
+                    best[k + 1][j] = Some((total, i, kind));
+                }
+            }
+        }
+    }
+    let mut factorial = 0.0;
+    let (mut total, mut pieces) = (f64::INFINITY, 0);
+    for (k, ends) in best.iter().enumerate().skip(1) {
+        factorial += (k as f64).log10();
+        if let Some((guesses, ..)) = ends[n] {
+            // k! orders of the pieces, plus a floor for each piece past the first.
+            let guesses = add(guesses + factorial, PIECE_GUESSES * (k - 1) as f64);
+            if guesses < total {
+                (total, pieces) = (guesses, k);
+            }
+        }
+    }
+    // The kind of the longest piece that isn't guessed character by character.
+    let (mut end, mut kind, mut longest) = (n, Kind::Other, 0);
+    for k in (1..=pieces).rev() {
+        let (_, start, piece_kind) = best[k][end].expect("a piece on the best path");
+        if piece_kind != Kind::Other && end - start > longest {
+            (kind, longest) = (piece_kind, end - start);
+        }
+        end = start;
+    }
+    (total, kind)
+}
+
+/// log10(10^a + 10^b).
+fn add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high + (1.0 + 10f64.powf(low - high)).log10()
+}
+
+/// log10 of the guesses for `chars` as one piece, by its cheapest reading.
+fn piece(chars: &[char]) -> (f64, Kind) {
+    // Character by character, ten guesses each as zxcvbn counts them.
+    let mut cheapest = (chars.len() as f64, Kind::Other);
+    let readings = [
+        (word(chars), Kind::Word),
+        (sequence(chars), Kind::Sequence),
+        (repeat(chars), Kind::Repeat),
+        (keyboard(chars), Kind::Keyboard),
+        (date(chars), Kind::Date),
+    ];
+    for (guesses, kind) in readings {
+        if let Some(guesses) = guesses.filter(|&guesses| guesses < cheapest.0) {
+            cheapest = (guesses, kind);
+        }
+    }
+    cheapest
+}
+
+/// The common passwords and words, by rank.
+fn words() -> &'static HashMap<&'static str, usize> {
+    static WORDS: OnceLock<HashMap<&str, usize>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        include_str!("strength/words.txt")
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .enumerate()
+            .map(|(rank, word)| (word, rank + 1))
+            .collect()
+    })
+}
+
+fn word(chars: &[char]) -> Option<f64> {
+    if chars.len() < 3 {
+        return None;
+    }
+    // Upper case beyond a capital first letter, or all capitals, is rarer.
+    let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+    let case = match upper {
+        0 => 0.0,
+        _ if upper == chars.len() || (upper == 1 && chars[0].is_uppercase()) => 2f64.log10(),
+        _ => (chars.len() as f64).log2().max(1.0) * 2f64.log10() * 2.0,
+    };
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    let mut cheapest: Option<f64> = None;
+    for one in ['i', 'l'] {
+        let plain: String = lower
+            .iter()
+            .map(|&c| match c {
+                '4' | '@' => 'a',
+                '8' => 'b',
+                '(' => 'c',
+                '3' => 'e',
+                '1' | '!' | '|' => one,
+                '0' => 'o',
+                '$' | '5' => 's',
+                '7' | '+' => 't',
+                c => c,
+            })
+            .collect();
+        let substituted = plain.chars().ne(lower.iter().copied());
+        let reversed: String = plain.chars().rev().collect();
+        let readings = [(plain.as_str(), 0.0), (reversed.as_str(), 2f64.log10())];
+        for (candidate, extra) in readings {
+            let Some(&rank) = words().get(candidate) else {
+                continue;
+            };
+            let leet = if substituted { 2f64.log10() } else { 0.0 };
+            let guesses = (rank as f64).log10() + case + leet + extra;
+            cheapest = Some(cheapest.map_or(guesses, |c| c.min(guesses)));
+        }
+    }
+    cheapest
+}
+
+fn sequence(chars: &[char]) -> Option<f64> {
+    if chars.len() < 3 {
+        return None;
+    }
+    let delta = chars[1] as i64 - chars[0] as i64;
+    if !(1..=2).contains(&delta.abs())
+        || chars
+            .windows(2)
+            .any(|pair| pair[1] as i64 - pair[0] as i64 != delta)
+    {
+        return None;
+    }
+    let first = chars[0];
+    let start: f64 = if "aAzZ019".contains(first) {
+        4.0
+    } else if first.is_ascii_digit() {
+        10.0
+    } else {
+        26.0
+    };
+    let descending = if delta < 0 { 2.0 } else { 1.0 };
+    Some((start * chars.len() as f64 * descending).log10())
+}
+
+fn repeat(chars: &[char]) -> Option<f64> {
+    // The shortest unit the piece is two or more copies of.
+    let n = chars.len();
+    let unit = (1..=n / 2).find(|&unit| {
+        n.is_multiple_of(unit) && chars.chunks(unit).all(|chunk| chunk == &chars[..unit])
+    })?;
+    if unit == 1 && n < 3 {
+        return None;
+    }
+    let text: String = chars[..unit].iter().collect();
+    Some(estimate(&text).0 + ((n / unit) as f64).log10())
+}
+
+/// Where `c` is on the keyboard, with rows shifted half a key from each
+/// other, and whether it takes shift.
+fn key(c: char) -> Option<(f64, f64, bool)> {
+    for (layer, shifted) in [(KEYBOARD, false), (SHIFTED, true)] {
+        for (row, keys) in layer.iter().enumerate() {
+            if let Some(column) = keys.chars().position(|k| k == c) {
+                return Some((column as f64 + row as f64 * 0.5, row as f64, shifted));
+            }
+        }
+    }
+    None
+}
+
+fn keyboard(chars: &[char]) -> Option<f64> {
+    if chars.len() < 3 {
+        return None;
+    }
+    let keys = chars.iter().map(|&c| key(c)).collect::<Option<Vec<_>>>()?;
+    let mut turns = 0;
+    let mut direction = None;
+    for pair in keys.windows(2) {
+        let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+        let adjacent = (dy == 0.0 && dx.abs() == 1.0) || (dy.abs() == 1.0 && dx.abs() == 0.5);
+        if !adjacent {
+            return None;
+        }
+        if direction != Some((dx, dy)) {
+            turns += 1;
+            direction = Some((dx, dy));
+        }
+    }
+    // Any of 47 keys to start from, about four neighbours at each turn.
+    let shifts = keys.iter().filter(|key| key.2).count();
+    let shift = match shifts {
+        0 => 0.0,
+        _ if shifts == keys.len() => 2f64.log10(),
+        _ => (keys.len() as f64).log10(),
+    };
+    Some((47.0 * chars.len() as f64).log10() + f64::from(turns) * 4f64.log10() + shift)
+}
+
+fn date(chars: &[char]) -> Option<f64> {
+    if !chars.iter().all(char::is_ascii_digit) {
+        return None;
+    }
+    let text: String = chars.iter().collect();
+    let number = |range: std::ops::Range<usize>| text[range].parse::<i32>().ok();
+    let years = |year: i32| f64::from((year - REFERENCE_YEAR).abs().max(20)).log10();
+    let day_month = |a: i32, b: i32| (1..=31).contains(&a) && (1..=12).contains(&b);
+    match chars.len() {
+        4 => number(0..4)
+            .filter(|year| (1900..=2099).contains(year))
+            .map(years),
+        6 | 8 => {
+            let short = chars.len() == 6;
+            let year_length = if short { 2 } else { 4 };
+            let full = |year: i32| match short {
+                true if year > 50 => 1900 + year,
+                true => 2000 + year,
+                false => year,
+            };
+            // Day and month first, in either order, or the year first.
+            let (first, second) = (number(0..2)?, number(2..4)?);
+            let year_last = number(4..4 + year_length).map(full)?;
+            let year_first = number(0..year_length).map(full)?;
+            let rest = (
+                number(year_length..year_length + 2)?,
+                number(year_length + 2..year_length + 4)?,
+            );
+            let readings = [
+                (day_month(first, second) || day_month(second, first)).then_some(year_last),
+                day_month(rest.1, rest.0).then_some(year_first),
+            ];
+            readings
+                .into_iter()
+                .flatten()
+                .filter(|year| (1900..=2099).contains(year))
+                .map(|year| 365f64.log10() + years(year))
+                .reduce(f64::min)
+        }
+        _ => None,
+    }
+}
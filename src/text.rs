@@ -0,0 +1,144 @@
+//! `encrypt-text` and `decrypt-text`: a short text, given on the command line
+//! or on standard input, encrypted to an armored message on standard output
+//! and back, for pasting secrets into a chat or keeping them in a config
+//! file. The message is a whole native file in [`armor`], so it records its
+//! own cipher and KDF, and `decrypt` opens it too.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use std::io::{self, IsTerminal, Read, Write};
+use zeroize::Zeroizing;
+
+use file_encryptor::i18n::t;
+
+use crate::{
+    armor, attempts, encryptor, policy, prompt_password, read_key_file, unlock, CipherName,
+    KdfOptions, PasswordSource,
+};
+
+#[derive(Args)]
+pub struct EncryptTextOptions {
+    /// The text; read from standard input if omitted, which keeps it out of shell history
+    #[arg(value_name = "TEXT")]
+    text: Option<String>,
+    /// Password; prompted for, twice, if there is no other key source
+    #[arg(
+        short,
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// Key file to encrypt with, alone or with the password
+    #[arg(long, value_name = "FILE")]
+    key_file: Option<String>,
+    /// Use the password even if it breaks the administrator's password policy; the override is logged
+    #[arg(long)]
+    force_weak_password: bool,
+    /// Use the password even if it is easy to guess, with a warning
+    #[arg(long)]
+    allow_weak_password: bool,
+    #[command(flatten)]
+    kdf: KdfOptions,
+}
+
+#[derive(Args)]
+pub struct DecryptTextOptions {
+    /// The armored message; read from standard input if omitted
+    #[arg(value_name = "MESSAGE", allow_hyphen_values = true)]
+    message: Option<String>,
+    /// The password; prompted for if the message needs one and it is omitted
+    #[arg(
+        short,
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// The key file the message was encrypted with
+    #[arg(long, value_name = "FILE")]
+    key_file: Option<String>,
+}
+
+pub fn encrypt(options: &EncryptTextOptions) -> Result<()> {
+    let kdf = options.kdf.kdf()?;
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let password = match (options.password_source.or(&options.password)?, key_file) {
+        (Some(password), _) => Some(password),
+        (None, None) => Some(prompt_password(true)?),
+        (None, Some(_)) => None,
+    };
+    if let Some(password) = &password {
+        policy::Policy::load()?.enforce(
+            password,
+            options.force_weak_password,
+            options.allow_weak_password,
+        )?;
+    }
+    let text = match &options.text {
+        Some(text) => Zeroizing::new(text.clone().into_bytes()),
+        None => read_stdin()?,
+    };
+    let config = crate::config::get();
+    let sealed = encryptor(password.as_deref().map(String::as_str), key_file, kdf)
+        .with_cipher(
+            config
+                .cipher
+                .unwrap_or_else(CipherName::for_this_cpu)
+                .into(),
+        )
+        .encrypt(&text)?;
+    print!("{}", armor::armor(&sealed, armor::LINE_LENGTH));
+    Ok(())
+}
+
+pub fn decrypt(options: &DecryptTextOptions) -> Result<()> {
+    let message = match &options.message {
+        Some(message) => message.clone(),
+        None => String::from_utf8(read_stdin()?.to_vec()).context(t!("armor-not-text"))?,
+    };
+    let file_bytes = armor::dearmor(&message)?;
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let password = options.password_source.or(&options.password)?;
+    let mut prompted = Zeroizing::default();
+    let mut keys = unlock(
+        &file_bytes,
+        password.as_deref().map(String::as_str),
+        key_file,
+        &mut prompted,
+    )?;
+    let attempt = attempts::begin(&file_bytes);
+    let text = match keys.decrypt(&file_bytes) {
+        Ok(text) => {
+            attempt.succeeded();
+            Zeroizing::new(text)
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            return Err(e);
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&text)?;
+    // A prompt after the text shouldn't run into it.
+    if stdout.is_terminal() && !text.ends_with(b"\n") {
+        stdout.write_all(b"\n")?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// All of standard input, with a hint first if it is a terminal.
+fn read_stdin() -> Result<Zeroizing<Vec<u8>>> {
+    let mut stdin = io::stdin().lock();
+    if stdin.is_terminal() {
+        eprintln!("{}", t!("text-prompt"));
+    }
+    let mut text = Zeroizing::new(Vec::new());
+    stdin.read_to_end(&mut text)?;
+    Ok(text)
+}
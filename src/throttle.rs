@@ -0,0 +1,64 @@
+//! `--bwlimit`: caps how fast the run reads its inputs, so a long encryption
+//! leaves the disk and network to the rest of the machine. The limit covers
+//! the whole run, however many `--jobs` share it, and as every byte written
+//! follows from one read, the output keeps the same pace.
+//!
+//! Reads are paced rather than averaged: each one is charged against a
+//! schedule at the limit's rate, and waits for it when it runs ahead, so no
+//! burst saved up while the run was busy elsewhere goes over the limit.
+
+use std::{
+    io::{self, Read},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+static LIMIT: OnceLock<Limit> = OnceLock::new();
+
+struct Limit {
+    bytes_per_second: u64,
+    /// When the bytes read so far are due at the limit's rate.
+    due: Mutex<Option<Instant>>,
+}
+
+/// Limits reads to `bytes_per_second` for the rest of the run.
+pub fn set(bytes_per_second: u64) {
+    let _ = LIMIT.set(Limit {
+        bytes_per_second: bytes_per_second.max(1),
+        due: Mutex::new(None),
+    });
+}
+
+pub fn is_set() -> bool {
+    LIMIT.get().is_some()
+}
+
+/// Counts `bytes` just read against the limit, sleeping until they are due.
+pub fn charge(bytes: usize) {
+    let Some(limit) = LIMIT.get() else {
+        return;
+    };
+    let wait = {
+        let mut due = limit.due.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let start = due.map_or(now, |due| due.max(now));
+        let next = start + Duration::from_secs_f64(bytes as f64 / limit.bytes_per_second as f64);
+        *due = Some(next);
+        next.saturating_duration_since(now)
+    };
+    if !wait.is_zero() {
+        thread::sleep(wait);
+    }
+}
+
+/// Charges the reads made through it.
+pub struct Reader<R>(pub R);
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        charge(n);
+        Ok(n)
+    }
+}
@@ -0,0 +1,208 @@
+//! RFC 3161 trusted timestamps over the ciphertext digest.
+//!
+//! Only the request encoding and enough of the response to check that it
+//! answers our request are implemented here. Validating the TSA's signature and
+//! certificate chain is left to standard tooling (`openssl ts -verify`).
+
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+use file_encryptor::i18n::t;
+
+use crate::entropy;
+
+// 2.16.840.1.101.3.4.2.1
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+// 1.2.840.113549.1.9.16.1.4
+const TST_INFO_OID: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x01, 0x04,
+];
+const MAX_RESPONSE_LENGTH: u64 = 1 << 20;
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_EXPLICIT_0: u8 = 0xa0;
+
+/// A TSA's answer to a timestamp request, in the DER form `openssl ts` reads.
+pub struct Timestamp {
+    pub response: Vec<u8>,
+    pub gen_time: String,
+}
+
+/// Asks the TSA at `tsa_url` to timestamp the SHA-256 digest of `data`.
+pub fn request_timestamp(tsa_url: &str, data: &[u8]) -> Result<Timestamp> {
+    let digest = Sha256::digest(data);
+    let nonce: [u8; 8] = entropy::bytes()?;
+
+    let response = ureq::post(tsa_url)
+        .set("Content-Type", "application/timestamp-query")
+        .send_bytes(&encode_request(&digest, &nonce))
+        .with_context(|| t!("timestamp-request-failed", url = tsa_url))?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE_LENGTH)
+        .read_to_end(&mut body)
+        .with_context(|| t!("timestamp-response-unreadable"))?;
+
+    let gen_time = check_response(&body, &digest, &nonce)
+        .with_context(|| t!("timestamp-response-unusable"))?;
+    Ok(Timestamp {
+        response: body,
+        gen_time,
+    })
+}
+
+fn encode_request(digest: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let algorithm = der(
+        TAG_SEQUENCE,
+        &[der(TAG_OID, SHA256_OID), der(TAG_NULL, &[])].concat(),
+    );
+    let imprint = der(
+        TAG_SEQUENCE,
+        &[algorithm, der(TAG_OCTET_STRING, digest)].concat(),
+    );
+    let request = [
+        der(TAG_INTEGER, &[1]),
+        imprint,
+        der(TAG_INTEGER, &unsigned_integer(nonce)),
+        // Ask for the TSA certificate so the token can be verified on its own.
+        der(TAG_BOOLEAN, &[0xff]),
+    ];
+    der(TAG_SEQUENCE, &request.concat())
+}
+
+/// Checks the response status and that the token covers our digest and nonce,
+/// returning the timestamp's GeneralizedTime.
+fn check_response(response: &[u8], digest: &[u8], nonce: &[u8]) -> Result<String> {
+    let mut resp = Reader::new(Reader::new(response).expect(TAG_SEQUENCE)?);
+    let mut status_info = Reader::new(resp.expect(TAG_SEQUENCE)?);
+    let status = status_info.expect(TAG_INTEGER)?;
+    // granted (0) or grantedWithMods (1)
+    if status != [0] && status != [1] {
+        bail!(t!("timestamp-rejected", status = hex::encode(status)));
+    }
+
+    let mut content_info = Reader::new(resp.expect(TAG_SEQUENCE)?);
+    content_info.expect(TAG_OID)?;
+    let mut signed_data =
+        Reader::new(Reader::new(content_info.expect(TAG_EXPLICIT_0)?).expect(TAG_SEQUENCE)?);
+    signed_data.expect(TAG_INTEGER)?;
+    signed_data.read()?; // digestAlgorithms
+    let mut encap = Reader::new(signed_data.expect(TAG_SEQUENCE)?);
+    if encap.expect(TAG_OID)? != TST_INFO_OID {
+        bail!(t!("timestamp-no-tstinfo"));
+    }
+    let tst_info = Reader::new(encap.expect(TAG_EXPLICIT_0)?).expect(TAG_OCTET_STRING)?;
+
+    let mut tst = Reader::new(Reader::new(tst_info).expect(TAG_SEQUENCE)?);
+    tst.expect(TAG_INTEGER)?; // version
+    tst.expect(TAG_OID)?; // policy
+    let mut imprint = Reader::new(tst.expect(TAG_SEQUENCE)?);
+    let mut algorithm = Reader::new(imprint.expect(TAG_SEQUENCE)?);
+    if algorithm.expect(TAG_OID)? != SHA256_OID || imprint.expect(TAG_OCTET_STRING)? != digest {
+        bail!(t!("timestamp-wrong-digest"));
+    }
+    tst.expect(TAG_INTEGER)?; // serialNumber
+    let gen_time = std::str::from_utf8(tst.expect(TAG_GENERALIZED_TIME)?)?.to_owned();
+
+    // Optional accuracy and ordering fields precede the nonce.
+    while !tst.is_empty() {
+        let (tag, value) = tst.read()?;
+        if tag == TAG_INTEGER {
+            if trim_leading_zeros(value) != trim_leading_zeros(nonce) {
+                bail!(t!("timestamp-wrong-nonce"));
+            }
+            return Ok(gen_time);
+        }
+    }
+    Err(anyhow!(t!("timestamp-no-nonce")))
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        let len = content.len().to_be_bytes();
+        let skip = len.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (len.len() - skip) as u8);
+        out.extend_from_slice(&len[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// DER INTEGER content for a big-endian unsigned value.
+fn unsigned_integer(bytes: &[u8]) -> Vec<u8> {
+    let trimmed = trim_leading_zeros(bytes);
+    let mut out = Vec::with_capacity(trimmed.len() + 1);
+    if trimmed.first().is_none_or(|&b| b & 0x80 != 0) {
+        out.push(0);
+    }
+    out.extend_from_slice(trimmed);
+    out
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    &bytes[skip..]
+}
+
+/// Sequential reader over DER-encoded TLV elements.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn read(&mut self) -> Result<(u8, &'a [u8])> {
+        let truncated = || anyhow!(t!("der-truncated"));
+        let (&tag, rest) = self.data.split_first().ok_or_else(truncated)?;
+        let (&first, mut rest) = rest.split_first().ok_or_else(truncated)?;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > std::mem::size_of::<usize>() || rest.len() < count {
+                return Err(truncated());
+            }
+            let (len_bytes, after) = rest.split_at(count);
+            rest = after;
+            len_bytes
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize)
+        };
+        if rest.len() < len {
+            return Err(truncated());
+        }
+        let (value, after) = rest.split_at(len);
+        self.data = after;
+        Ok((tag, value))
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<&'a [u8]> {
+        let (tag, value) = self.read()?;
+        if tag != expected {
+            bail!(t!(
+                "der-unexpected-tag",
+                tag = format!("{tag:#04x}"),
+                expected = format!("{expected:#04x}")
+            ));
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,625 @@
+//! `encrypt --token` and `decrypt --token`: hardware tokens, such as smart
+//! cards, HSMs and YubiKeys, holding a P-256 key that the file key is wrapped
+//! for (a [`Slot::Token`](file_encryptor::recipient::Slot::Token)), so that
+//! decrypting needs the device itself. The token does the key agreement; its
+//! secret key never leaves it.
+//!
+//! Tokens are reached through their PKCS#11 module and named by a PKCS#11
+//! URI (RFC 7512), choosing the token by its `token` label or `serial` and
+//! the key by its `id` or `object` label:
+//!
+//! ```text
+//! pkcs11:token=Backup;id=%01?module-path=/usr/lib/softhsm/libsofthsm2.so
+//! ```
+//!
+//! or for the PIV applet of a YubiKey by `yubikey:SLOT`, such as
+//! `yubikey:9d`, through Yubico's `libykcs11`. The module is
+//! `FILE_ENCRYPTOR_PKCS11_MODULE` if set, else the URI's `module-path`, else
+//! OpenSC's. The header records the identifier the file was encrypted to, so
+//! `decrypt --token` finds the token by itself, and asks for its PIN once.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use file_encryptor::{i18n::t, TokenAgreement};
+
+const MODULE_ENV: &str = "FILE_ENCRYPTOR_PKCS11_MODULE";
+/// An ECDH P-256 shared secret, its x-coordinate.
+const SECRET_LENGTH: usize = 32;
+
+#[cfg(target_os = "macos")]
+const OPENSC_MODULE: &str = "/Library/OpenSC/lib/opensc-pkcs11.so";
+#[cfg(not(target_os = "macos"))]
+const OPENSC_MODULE: &str = "opensc-pkcs11.so";
+#[cfg(target_os = "macos")]
+const YKCS11_MODULE: &str = "libykcs11.dylib";
+#[cfg(not(target_os = "macos"))]
+const YKCS11_MODULE: &str = "libykcs11.so";
+
+/// A token and key named by a `--token` identifier.
+struct Uri {
+    module: String,
+    token: Option<String>,
+    serial: Option<String>,
+    id: Option<Vec<u8>>,
+    object: Option<String>,
+}
+
+impl Uri {
+    fn parse(text: &str) -> Result<Uri> {
+        let invalid = || anyhow!(t!("token-invalid", token = text));
+        let mut uri = Uri {
+            module: OPENSC_MODULE.to_owned(),
+            token: None,
+            serial: None,
+            id: None,
+            object: None,
+        };
+        if let Some(slot) = text.strip_prefix("yubikey:") {
+            uri.module = YKCS11_MODULE.to_owned();
+            uri.id = Some(vec![piv_id(slot).ok_or_else(invalid)?]);
+        } else {
+            let rest = text.strip_prefix("pkcs11:").ok_or_else(invalid)?;
+            let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let attributes = path.split(';').chain(query.split('&'));
+            for attribute in attributes.filter(|attribute| !attribute.is_empty()) {
+                let (name, value) = attribute.split_once('=').ok_or_else(invalid)?;
+                let value = percent_decode(value).ok_or_else(invalid)?;
+                let text = || String::from_utf8(value.clone()).map_err(|_| invalid());
+                match name {
+                    "token" => uri.token = Some(text()?),
+                    "serial" => uri.serial = Some(text()?),
+                    "id" => uri.id = Some(value),
+                    "object" => uri.object = Some(text()?),
+                    "module-path" => uri.module = text()?,
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+        if uri.id.is_none() && uri.object.is_none() {
+            return Err(invalid());
+        }
+        if let Some(module) = std::env::var_os(MODULE_ENV) {
+            uri.module = module.to_string_lossy().into_owned();
+        }
+        Ok(uri)
+    }
+}
+
+/// The PKCS#11 `id` of the key in the PIV slot `slot` (in hex, as `ykman`
+/// names them), as both libykcs11 and OpenSC number them.
+fn piv_id(slot: &str) -> Option<u8> {
+    match u8::from_str_radix(slot, 16).ok()? {
+        0x9a => Some(1),
+        0x9c => Some(2),
+        0x9d => Some(3),
+        0x9e => Some(4),
+        retired @ 0x82..=0x95 => Some(retired - 0x82 + 5),
+        _ => None,
+    }
+}
+
+fn percent_decode(value: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = match byte {
+            b'%' => {
+                let digits = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(digits, 16).ok()?);
+                &tail[2..]
+            }
+            byte => {
+                bytes.push(byte);
+                tail
+            }
+        };
+    }
+    Some(bytes)
+}
+
+/// The SEC1 encoding of the public key of the token `token`, to encrypt to.
+pub fn public_key(token: &str) -> Result<Vec<u8>> {
+    let uri = Uri::parse(token)?;
+    platform::Session::open(&uri)
+        .and_then(|session| session.public_key(&uri))
+        .with_context(|| t!("token-failed", token = token))
+}
+
+/// A token session logged in and holding the key a file was encrypted to.
+struct Unlocked {
+    session: platform::Session,
+    key: platform::Object,
+}
+
+/// What opens the token slots of files, asking each token for its PIN the
+/// first time it is needed.
+pub fn agreement() -> TokenAgreement {
+    let unlocked: Mutex<HashMap<String, Unlocked>> = Mutex::default();
+    Arc::new(move |token: &str, ephemeral: &[u8]| {
+        let mut unlocked = unlocked.lock().unwrap_or_else(|e| e.into_inner());
+        if !unlocked.contains_key(token) {
+            unlocked.insert(token.to_owned(), unlock(token)?);
+        }
+        let Unlocked { session, key } = &unlocked[token];
+        session
+            .agree(*key, ephemeral)
+            .with_context(|| t!("token-failed", token = token))
+    })
+}
+
+fn unlock(token: &str) -> Result<Unlocked> {
+    let uri = Uri::parse(token)?;
+    let failed = || t!("token-failed", token = token);
+    let session = platform::Session::open(&uri).with_context(failed)?;
+    let pin = match session.needs_pin() {
+        true => Some(crate::prompt(
+            t!("token-pin-prompt", token = session.label()),
+            false,
+        )?),
+        false => None,
+    };
+    session
+        .login(pin.as_deref().map(String::as_str))
+        .with_context(failed)?;
+    let key = session.private_key(&uri).with_context(failed)?;
+    Ok(Unlocked { session, key })
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{
+        ffi::{c_ulong, c_void, CString},
+        io, ptr,
+    };
+    use zeroize::Zeroizing;
+
+    use file_encryptor::i18n::t;
+
+    use super::{Uri, SECRET_LENGTH};
+
+    type Ulong = c_ulong;
+    type Rv = Ulong;
+    pub type Object = Ulong;
+
+    const CKR_OK: Rv = 0;
+    const CKR_DEVICE_REMOVED: Rv = 0x32;
+    const CKR_KEY_TYPE_INCONSISTENT: Rv = 0x63;
+    const CKR_MECHANISM_INVALID: Rv = 0x70;
+    const CKR_PIN_INCORRECT: Rv = 0xa0;
+    const CKR_PIN_LOCKED: Rv = 0xa4;
+    const CKR_TOKEN_NOT_PRESENT: Rv = 0xe0;
+    const CKR_USER_ALREADY_LOGGED_IN: Rv = 0x100;
+    const CKR_CRYPTOKI_ALREADY_INITIALIZED: Rv = 0x191;
+    const CKF_LOGIN_REQUIRED: Ulong = 0x4;
+    const CKF_PROTECTED_AUTHENTICATION_PATH: Ulong = 0x100;
+    const CKF_SERIAL_SESSION: Ulong = 0x4;
+    const CKU_USER: Ulong = 1;
+    const CKO_PUBLIC_KEY: Ulong = 2;
+    const CKO_PRIVATE_KEY: Ulong = 3;
+    const CKO_SECRET_KEY: Ulong = 4;
+    const CKK_GENERIC_SECRET: Ulong = 0x10;
+    const CKA_CLASS: Ulong = 0;
+    const CKA_TOKEN: Ulong = 1;
+    const CKA_LABEL: Ulong = 3;
+    const CKA_VALUE: Ulong = 0x11;
+    const CKA_KEY_TYPE: Ulong = 0x100;
+    const CKA_ID: Ulong = 0x102;
+    const CKA_SENSITIVE: Ulong = 0x103;
+    const CKA_VALUE_LEN: Ulong = 0x161;
+    const CKA_EXTRACTABLE: Ulong = 0x162;
+    const CKA_EC_POINT: Ulong = 0x181;
+    const CKM_ECDH1_DERIVE: Ulong = 0x1050;
+    const CKD_NULL: Ulong = 1;
+    const UNAVAILABLE: Ulong = !0;
+
+    #[repr(C)]
+    struct Attribute {
+        kind: Ulong,
+        value: *mut c_void,
+        length: Ulong,
+    }
+
+    impl Attribute {
+        fn new(kind: Ulong, value: &[u8]) -> Attribute {
+            Attribute {
+                kind,
+                value: value.as_ptr().cast_mut().cast(),
+                length: value.len() as Ulong,
+            }
+        }
+    }
+
+    #[repr(C)]
+    struct Mechanism {
+        mechanism: Ulong,
+        parameter: *mut c_void,
+        length: Ulong,
+    }
+
+    #[repr(C)]
+    struct Ecdh1DeriveParams {
+        kdf: Ulong,
+        shared_data_length: Ulong,
+        shared_data: *const u8,
+        public_data_length: Ulong,
+        public_data: *const u8,
+    }
+
+    #[repr(C)]
+    struct TokenInfo {
+        label: [u8; 32],
+        manufacturer: [u8; 32],
+        model: [u8; 16],
+        serial: [u8; 16],
+        flags: Ulong,
+        /// Session counts, PIN lengths and memory sizes.
+        counts: [Ulong; 10],
+        versions: [u8; 4],
+        time: [u8; 16],
+    }
+
+    /// `CK_FUNCTION_LIST`, with the functions we don't call left as
+    /// padding: the module's function pointers, in the order of the
+    /// specification.
+    #[repr(C)]
+    struct FunctionList {
+        version: [u8; 2],
+        initialize: unsafe extern "C" fn(*mut c_void) -> Rv,
+        _finalize_to_get_function_list: [usize; 3],
+        get_slot_list: unsafe extern "C" fn(u8, *mut Ulong, *mut Ulong) -> Rv,
+        _get_slot_info: usize,
+        get_token_info: unsafe extern "C" fn(Ulong, *mut TokenInfo) -> Rv,
+        _get_mechanism_list_to_set_pin: [usize; 5],
+        open_session:
+            unsafe extern "C" fn(Ulong, Ulong, *mut c_void, *mut c_void, *mut Ulong) -> Rv,
+        close_session: unsafe extern "C" fn(Ulong) -> Rv,
+        _close_all_sessions_to_set_operation_state: [usize; 4],
+        login: unsafe extern "C" fn(Ulong, Ulong, *const u8, Ulong) -> Rv,
+        _logout_to_get_object_size: [usize; 5],
+        get_attribute_value: unsafe extern "C" fn(Ulong, Ulong, *mut Attribute, Ulong) -> Rv,
+        _set_attribute_value: usize,
+        find_objects_init: unsafe extern "C" fn(Ulong, *mut Attribute, Ulong) -> Rv,
+        find_objects: unsafe extern "C" fn(Ulong, *mut Ulong, Ulong, *mut Ulong) -> Rv,
+        find_objects_final: unsafe extern "C" fn(Ulong) -> Rv,
+        _encrypt_init_to_unwrap_key: [usize; 33],
+        derive_key: unsafe extern "C" fn(
+            Ulong,
+            *mut Mechanism,
+            Ulong,
+            *mut Attribute,
+            Ulong,
+            *mut Ulong,
+        ) -> Rv,
+    }
+
+    fn check(rv: Rv, call: &str) -> io::Result<()> {
+        let reason = match rv {
+            CKR_OK => return Ok(()),
+            CKR_PIN_INCORRECT => t!("token-pin-incorrect"),
+            CKR_PIN_LOCKED => t!("token-pin-locked"),
+            CKR_TOKEN_NOT_PRESENT | CKR_DEVICE_REMOVED => t!("token-not-present"),
+            CKR_MECHANISM_INVALID | CKR_KEY_TYPE_INCONSISTENT => {
+                t!("token-no-ecdh")
+            }
+            rv => format!("CKR {rv:#x}"),
+        };
+        Err(io::Error::other(t!(
+            "token-call-failed",
+            call = call,
+            reason = reason
+        )))
+    }
+
+    fn not_found(what: String) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, what)
+    }
+
+    /// The module at `path`, loaded and initialized. It stays loaded until
+    /// the process exits.
+    fn load(path: &str) -> io::Result<&'static FunctionList> {
+        let name = CString::new(path).map_err(io::Error::other)?;
+        let library = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) };
+        if library.is_null() {
+            return Err(not_found(t!("token-module-unloadable", path = path)));
+        }
+        let symbol = unsafe { libc::dlsym(library, c"C_GetFunctionList".as_ptr()) };
+        if symbol.is_null() {
+            return Err(io::Error::other(t!("token-not-module", path = path)));
+        }
+        let get_function_list: unsafe extern "C" fn(*mut *const FunctionList) -> Rv =
+            unsafe { std::mem::transmute(symbol) };
+        let mut functions = ptr::null();
+        check(
+            unsafe { get_function_list(&mut functions) },
+            "C_GetFunctionList",
+        )?;
+        let functions = unsafe { &*functions };
+        match unsafe { (functions.initialize)(ptr::null_mut()) } {
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => {}
+            rv => check(rv, "C_Initialize")?,
+        }
+        Ok(functions)
+    }
+
+    /// Trailing spaces, with which PKCS#11 pads its fixed-size strings, cut.
+    fn padded(field: &[u8]) -> String {
+        String::from_utf8_lossy(field)
+            .trim_end_matches([' ', '\0'])
+            .to_owned()
+    }
+
+    pub struct Session {
+        functions: &'static FunctionList,
+        handle: Ulong,
+        label: String,
+        flags: Ulong,
+    }
+
+    impl Session {
+        /// A session with the first token present that `uri` matches.
+        pub fn open(uri: &Uri) -> io::Result<Session> {
+            let functions = load(&uri.module)?;
+            let mut count = 0;
+            check(
+                unsafe { (functions.get_slot_list)(1, ptr::null_mut(), &mut count) },
+                "C_GetSlotList",
+            )?;
+            let mut slots = vec![0; count as usize];
+            check(
+                unsafe { (functions.get_slot_list)(1, slots.as_mut_ptr(), &mut count) },
+                "C_GetSlotList",
+            )?;
+            slots.truncate(count as usize);
+            for slot in slots {
+                let mut info: TokenInfo = unsafe { std::mem::zeroed() };
+                check(
+                    unsafe { (functions.get_token_info)(slot, &mut info) },
+                    "C_GetTokenInfo",
+                )?;
+                let label = padded(&info.label);
+                if uri.token.as_ref().is_some_and(|token| *token != label)
+                    || uri
+                        .serial
+                        .as_ref()
+                        .is_some_and(|serial| *serial != padded(&info.serial))
+                {
+                    continue;
+                }
+                let mut handle = 0;
+                check(
+                    unsafe {
+                        (functions.open_session)(
+                            slot,
+                            CKF_SERIAL_SESSION,
+                            ptr::null_mut(),
+                            ptr::null_mut(),
+                            &mut handle,
+                        )
+                    },
+                    "C_OpenSession",
+                )?;
+                return Ok(Session {
+                    functions,
+                    handle,
+                    label,
+                    flags: info.flags,
+                });
+            }
+            Err(not_found(t!("token-none-matches")))
+        }
+
+        pub fn label(&self) -> &str {
+            &self.label
+        }
+
+        /// Whether logging in takes a PIN typed here, rather than none or
+        /// one entered on the reader's own keypad.
+        pub fn needs_pin(&self) -> bool {
+            self.flags & CKF_LOGIN_REQUIRED != 0
+                && self.flags & CKF_PROTECTED_AUTHENTICATION_PATH == 0
+        }
+
+        pub fn login(&self, pin: Option<&str>) -> io::Result<()> {
+            if self.flags & CKF_LOGIN_REQUIRED == 0 {
+                return Ok(());
+            }
+            let (pin, length) = pin.map_or((ptr::null(), 0), |pin| (pin.as_ptr(), pin.len()));
+            match unsafe { (self.functions.login)(self.handle, CKU_USER, pin, length as Ulong) } {
+                CKR_USER_ALREADY_LOGGED_IN => Ok(()),
+                rv => check(rv, "C_Login"),
+            }
+        }
+
+        /// The first object of `class` with the key's `id` and label.
+        fn find(&self, class: Ulong, uri: &Uri) -> io::Result<Object> {
+            let class = class.to_ne_bytes();
+            let mut template = vec![Attribute::new(CKA_CLASS, &class)];
+            if let Some(id) = &uri.id {
+                template.push(Attribute::new(CKA_ID, id));
+            }
+            if let Some(object) = &uri.object {
+                template.push(Attribute::new(CKA_LABEL, object.as_bytes()));
+            }
+            check(
+                unsafe {
+                    (self.functions.find_objects_init)(
+                        self.handle,
+                        template.as_mut_ptr(),
+                        template.len() as Ulong,
+                    )
+                },
+                "C_FindObjectsInit",
+            )?;
+            let (mut object, mut count) = (0, 0);
+            let found = check(
+                unsafe { (self.functions.find_objects)(self.handle, &mut object, 1, &mut count) },
+                "C_FindObjects",
+            );
+            unsafe { (self.functions.find_objects_final)(self.handle) };
+            found?;
+            match count {
+                0 => Err(not_found(t!("token-no-such-key"))),
+                _ => Ok(object),
+            }
+        }
+
+        fn attribute(&self, object: Object, kind: Ulong) -> io::Result<Zeroizing<Vec<u8>>> {
+            let mut attribute = Attribute {
+                kind,
+                value: ptr::null_mut(),
+                length: 0,
+            };
+            let get = |attribute: &mut Attribute| {
+                check(
+                    unsafe {
+                        (self.functions.get_attribute_value)(self.handle, object, attribute, 1)
+                    },
+                    "C_GetAttributeValue",
+                )
+            };
+            get(&mut attribute)?;
+            if attribute.length == UNAVAILABLE {
+                return Err(io::Error::other(t!("token-attribute-unavailable")));
+            }
+            let mut value = Zeroizing::new(vec![0; attribute.length as usize]);
+            attribute.value = value.as_mut_ptr().cast();
+            get(&mut attribute)?;
+            value.truncate(attribute.length as usize);
+            Ok(value)
+        }
+
+        /// The token key's public point, uncompressed.
+        pub fn public_key(&self, uri: &Uri) -> io::Result<Vec<u8>> {
+            let key = self.find(CKO_PUBLIC_KEY, uri)?;
+            let point = self.attribute(key, CKA_EC_POINT)?;
+            // Modules return it DER-encoded as an OCTET STRING, as the
+            // specification says, or some of them bare.
+            Ok(match point.as_slice() {
+                [0x04, length, rest @ ..] if usize::from(*length) == rest.len() => rest.to_vec(),
+                point => point.to_vec(),
+            })
+        }
+
+        pub fn private_key(&self, uri: &Uri) -> io::Result<Object> {
+            self.find(CKO_PRIVATE_KEY, uri)
+        }
+
+        /// The shared secret of ECDH between `key` and the public point
+        /// `ephemeral`.
+        pub fn agree(
+            &self,
+            key: Object,
+            ephemeral: &[u8],
+        ) -> io::Result<Zeroizing<[u8; SECRET_LENGTH]>> {
+            let mut params = Ecdh1DeriveParams {
+                kdf: CKD_NULL,
+                shared_data_length: 0,
+                shared_data: ptr::null(),
+                public_data_length: ephemeral.len() as Ulong,
+                public_data: ephemeral.as_ptr(),
+            };
+            let mut mechanism = Mechanism {
+                mechanism: CKM_ECDH1_DERIVE,
+                parameter: (&mut params as *mut Ecdh1DeriveParams).cast(),
+                length: std::mem::size_of::<Ecdh1DeriveParams>() as Ulong,
+            };
+            let (class, kind) = (
+                CKO_SECRET_KEY.to_ne_bytes(),
+                CKK_GENERIC_SECRET.to_ne_bytes(),
+            );
+            let length = (SECRET_LENGTH as Ulong).to_ne_bytes();
+            let (no, yes) = ([0u8], [1u8]);
+            // A session object, readable so that the secret comes back here.
+            let mut template = [
+                Attribute::new(CKA_CLASS, &class),
+                Attribute::new(CKA_KEY_TYPE, &kind),
+                Attribute::new(CKA_TOKEN, &no),
+                Attribute::new(CKA_SENSITIVE, &no),
+                Attribute::new(CKA_EXTRACTABLE, &yes),
+                Attribute::new(CKA_VALUE_LEN, &length),
+            ];
+            let mut secret = 0;
+            check(
+                unsafe {
+                    (self.functions.derive_key)(
+                        self.handle,
+                        &mut mechanism,
+                        key,
+                        template.as_mut_ptr(),
+                        template.len() as Ulong,
+                        &mut secret,
+                    )
+                },
+                "C_DeriveKey",
+            )?;
+            let value = self.attribute(secret, CKA_VALUE)?;
+            let shared = value
+                .as_slice()
+                .try_into()
+                .map_err(|_| io::Error::other(t!("token-secret-length")))?;
+            Ok(Zeroizing::new(shared))
+        }
+    }
+
+    impl Drop for Session {
+        fn drop(&mut self) {
+            unsafe { (self.functions.close_session)(self.handle) };
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::io;
+    use zeroize::Zeroizing;
+
+    use file_encryptor::i18n::t;
+
+    use super::{Uri, SECRET_LENGTH};
+
+    pub type Object = u64;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, t!("token-unsupported"))
+    }
+
+    pub struct Session;
+
+    impl Session {
+        pub fn open(_uri: &Uri) -> io::Result<Session> {
+            Err(unsupported())
+        }
+
+        pub fn label(&self) -> &str {
+            ""
+        }
+
+        pub fn needs_pin(&self) -> bool {
+            false
+        }
+
+        pub fn login(&self, _pin: Option<&str>) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        pub fn public_key(&self, _uri: &Uri) -> io::Result<Vec<u8>> {
+            Err(unsupported())
+        }
+
+        pub fn private_key(&self, _uri: &Uri) -> io::Result<Object> {
+            Err(unsupported())
+        }
+
+        pub fn agree(
+            &self,
+            _key: Object,
+            _ephemeral: &[u8],
+        ) -> io::Result<Zeroizing<[u8; SECRET_LENGTH]>> {
+            Err(unsupported())
+        }
+    }
+}
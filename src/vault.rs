@@ -0,0 +1,325 @@
+//! `vault`: small secrets such as API tokens, stored one record per key in an
+//! encrypted container, and with `--dir` whole files, kept as a unit in a
+//! directory of their own:
+//!
+//! ```text
+//! DIR/index.enc          the container: each name, its blob, key and size
+//! DIR/blobs/<id>.enc     one chunked file per name, under a key of its own
+//! ```
+//!
+//! The master password only opens the index. Each blob is encrypted with a
+//! random key the index holds, so opening one file costs a single key
+//! derivation, and the blobs' names and sizes are all an onlooker learns.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use file_encryptor::{entropy, Encryptor};
+
+use crate::{
+    container::{self, ContainerArgs},
+    create_file, human_size,
+    i18n::t,
+    is_stdio, open_input, IoOptions,
+};
+
+const VAULT_FILE: &str = "vault.enc";
+/// The index of a vault directory.
+const INDEX_FILE: &str = "index.enc";
+const BLOBS_DIR: &str = "blobs";
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Store a secret under KEY; the value is read from standard input so it
+    /// never appears in shell history
+    Set {
+        key: String,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// Print the secret stored under KEY, or with --dir write out the file
+    /// stored as KEY
+    Get {
+        key: String,
+        /// Vault directory of files (see `vault init`) instead of the secrets container
+        #[arg(long, value_name = "DIR", conflicts_with = "container")]
+        dir: Option<PathBuf>,
+        /// Write the file here instead of to standard output
+        #[arg(short, long, value_name = "FILE", requires = "dir")]
+        output: Option<PathBuf>,
+        /// Overwrite the output if it already exists
+        #[arg(long, requires = "output")]
+        force: bool,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// List the stored keys, or with --dir the stored files and their sizes
+    List {
+        /// Vault directory of files (see `vault init`) instead of the secrets container
+        #[arg(long, value_name = "DIR", conflicts_with = "container")]
+        dir: Option<PathBuf>,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// Delete the secret stored under KEY, or with --dir the file stored as KEY
+    Remove {
+        key: String,
+        /// Vault directory of files (see `vault init`) instead of the secrets container
+        #[arg(long, value_name = "DIR", conflicts_with = "container")]
+        dir: Option<PathBuf>,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// Create a vault directory of files, unlocked by the password
+    Init {
+        /// Directory to create, or an empty one to use
+        #[arg(long, value_name = "DIR", conflicts_with = "container")]
+        dir: PathBuf,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+    /// Encrypt FILE (`-` for standard input) into a vault directory as NAME,
+    /// replacing any file of that name
+    Add {
+        name: String,
+        file: PathBuf,
+        /// Vault directory of files (see `vault init`)
+        #[arg(long, value_name = "DIR", conflicts_with = "container")]
+        dir: PathBuf,
+        #[command(flatten)]
+        container: ContainerArgs,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Vault {
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    value: String,
+}
+
+/// What the index of a vault directory holds.
+#[derive(Serialize, Deserialize, Default)]
+struct Index {
+    files: BTreeMap<String, Blob>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Blob {
+    /// Its file name in the blobs directory.
+    file: String,
+    key: [u8; 32],
+    /// The plaintext's size.
+    length: u64,
+}
+
+pub fn run(command: &VaultCommand) -> Result<()> {
+    match command {
+        VaultCommand::Set { key, container } => {
+            let path = container.path(VAULT_FILE)?;
+            let password = container.password(&path)?;
+            let (mut vault, generation): (Vault, _) = container::open(&path, container, &password)?;
+            let value = read_value(key)?;
+            let replaced = vault.entries.insert(key.clone(), Entry { value }).is_some();
+            container::save(&path, container, &password, &vault, generation)?;
+            if replaced {
+                println!("{}", t!("vault-updated", key = key.as_str()));
+            } else {
+                println!("{}", t!("vault-stored", key = key.as_str()));
+            }
+        }
+        VaultCommand::Get { key, dir: Some(dir), output, force, container } => {
+            let path = index_path(dir)?;
+            let index: Index = container::load(&path, container, &container.password(&path)?)?;
+            let blob = index
+                .files
+                .get(key)
+                .ok_or_else(|| anyhow!(t!("vault-entry-missing", key = format!("{key:?}"))))?;
+            get_file(dir, blob, output.as_deref(), *force)?;
+        }
+        VaultCommand::Get { key, dir: None, container, .. } => {
+            let path = container.path(VAULT_FILE)?;
+            let vault: Vault = container::load(&path, container, &container.password(&path)?)?;
+            let entry = vault
+                .entries
+                .get(key)
+                .ok_or_else(|| anyhow!(t!("vault-entry-missing", key = format!("{key:?}"))))?;
+            println!("{}", entry.value);
+        }
+        VaultCommand::List { dir: Some(dir), container } => {
+            let path = index_path(dir)?;
+            let index: Index = container::load(&path, container, &container.password(&path)?)?;
+            for (name, blob) in &index.files {
+                println!("{name}\t{}", human_size(blob.length));
+            }
+        }
+        VaultCommand::List { dir: None, container } => {
+            let path = container.path(VAULT_FILE)?;
+            let vault: Vault = container::load(&path, container, &container.password(&path)?)?;
+            for key in vault.entries.keys() {
+                println!("{key}");
+            }
+        }
+        VaultCommand::Remove { key, dir: Some(dir), container } => {
+            let path = index_path(dir)?;
+            let password = container.password(&path)?;
+            let (mut index, generation): (Index, _) = container::open(&path, container, &password)?;
+            let blob = index
+                .files
+                .remove(key)
+                .ok_or_else(|| anyhow!(t!("vault-entry-missing", key = format!("{key:?}"))))?;
+            container::save(&path, container, &password, &index, generation)?;
+            remove_blob(dir, &blob)?;
+            println!("{}", t!("vault-removed", key = key.as_str()));
+        }
+        VaultCommand::Remove { key, dir: None, container } => {
+            let path = container.path(VAULT_FILE)?;
+            let password = container.password(&path)?;
+            let (mut vault, generation): (Vault, _) = container::open(&path, container, &password)?;
+            if vault.entries.remove(key).is_none() {
+                bail!(t!("vault-entry-missing", key = format!("{key:?}")));
+            }
+            container::save(&path, container, &password, &vault, generation)?;
+            println!("{}", t!("vault-removed", key = key.as_str()));
+        }
+        VaultCommand::Init { dir, container } => {
+            let path = dir.join(INDEX_FILE);
+            if path.exists() {
+                bail!(t!("vault-exists", path = dir.display().to_string()));
+            }
+            let password = container.password(&path)?;
+            let blobs = dir.join(BLOBS_DIR);
+            fs::create_dir_all(&blobs)
+                .with_context(|| t!("dir-create-failed", path = blobs.display().to_string()))?;
+            let (index, generation): (Index, _) = container::open(&path, container, &password)?;
+            container::save(&path, container, &password, &index, generation)?;
+            println!("{}", t!("vault-created", path = dir.display().to_string()));
+        }
+        VaultCommand::Add { name, file, dir, container } => {
+            let path = index_path(dir)?;
+            let password = container.password(&path)?;
+            let (mut index, generation): (Index, _) = container::open(&path, container, &password)?;
+            let blob = add_file(dir, file)?;
+            let length = blob.length;
+            let replaced = index.files.insert(name.clone(), blob);
+            if let Err(e) = container::save(&path, container, &password, &index, generation) {
+                let _ = remove_blob(dir, &index.files[name]);
+                return Err(e);
+            }
+            // The new blob is in the index before the old one goes.
+            let size = human_size(length);
+            match replaced {
+                Some(old) => {
+                    remove_blob(dir, &old)?;
+                    println!("{}", t!("vault-file-replaced", name = name.as_str(), size = size));
+                }
+                None => println!("{}", t!("vault-file-added", name = name.as_str(), size = size)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The index of the vault directory `dir`, which must have one.
+fn index_path(dir: &Path) -> Result<PathBuf> {
+    let path = dir.join(INDEX_FILE);
+    if !path.is_file() {
+        bail!(t!("vault-not-found", path = dir.display().to_string()));
+    }
+    Ok(path)
+}
+
+/// Encrypts `file` into a new blob of the vault directory `dir`.
+fn add_file(dir: &Path, file: &Path) -> Result<Blob> {
+    let key = entropy::bytes()?;
+    let name = format!("{}.enc", hex::encode(entropy::bytes::<16>()?));
+    let path = dir.join(BLOBS_DIR).join(&name);
+    let input = open_input(file, IoOptions::default())?;
+    let mut input = Counting { inner: input, length: 0 };
+    let (output, pending) = create_file(&path, IoOptions::default())?;
+    let mut writer = BufWriter::new(output);
+    Encryptor::from_key_file(key)
+        .encrypt_reader_to_writer(&mut input, &mut writer)
+        .with_context(|| t!("file-encrypt-failed", path = file.display().to_string()))?;
+    writer.into_inner().map_err(|e| e.into_error())?;
+    pending.commit(IoOptions::default())?;
+    Ok(Blob {
+        file: name,
+        key,
+        length: input.length,
+    })
+}
+
+/// Decrypts `blob` of the vault directory `dir` to `output`, or to standard
+/// output.
+fn get_file(dir: &Path, blob: &Blob, output: Option<&Path>, force: bool) -> Result<()> {
+    let path = dir.join(BLOBS_DIR).join(&blob.file);
+    let input = open_input(&path, IoOptions::default())?;
+    let mut keys = Encryptor::from_key_file(blob.key);
+    let Some(output) = output.filter(|output| !is_stdio(output)) else {
+        let mut stdout = io::stdout().lock();
+        keys.decrypt_reader_to_writer(input, &mut stdout)
+            .with_context(|| t!("file-decrypt-failed", path = path.display().to_string()))?;
+        return Ok(());
+    };
+    if output.exists() && !force {
+        bail!(t!("output-exists", path = output.display().to_string()));
+    }
+    let (file, pending) = create_file(output, IoOptions::default())?;
+    let mut writer = BufWriter::new(file);
+    keys.decrypt_reader_to_writer(input, &mut writer)
+        .with_context(|| t!("file-decrypt-failed", path = path.display().to_string()))?;
+    writer.flush()?;
+    drop(writer);
+    pending.commit(IoOptions::default())
+}
+
+/// Deletes the file of `blob`, which may already be gone.
+fn remove_blob(dir: &Path, blob: &Blob) -> Result<()> {
+    let path = dir.join(BLOBS_DIR).join(&blob.file);
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| t!("file-delete-failed", path = path.display().to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Passes reads through, counting the bytes.
+struct Counting<R> {
+    inner: R,
+    length: u64,
+}
+
+impl<R: io::Read> io::Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.length += n as u64;
+        Ok(n)
+    }
+}
+
+/// Reads a single-line secret from standard input.
+fn read_value(key: &str) -> Result<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        eprint!("{}", t!("vault-value-prompt", key = key));
+    }
+    let mut value = String::new();
+    stdin.lock().read_line(&mut value)?;
+    let value = value.trim_end_matches(['\r', '\n']).to_owned();
+    if value.is_empty() {
+        bail!(t!("vault-value-missing", key = key));
+    }
+    Ok(value)
+}
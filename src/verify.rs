@@ -0,0 +1,228 @@
+//! `verify`: decrypts a file all the way through and throws the plaintext
+//! away, proving a backup intact and its password right without writing the
+//! plaintext anywhere. When decryption fails, the key check in the header
+//! and the checksum footer, which needs no key, tell a damaged file from a
+//! wrong password. In a `--stream` file, every chunk is checked, and the
+//! ones that fail to authenticate are listed by where they are in the file
+//! and in the plaintext, for `decrypt --best-effort` to recover the rest.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgGroup, Args};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use file_encryptor::{i18n::t, stream, DecryptError, Encryptor};
+
+use crate::{
+    attempts, check_key_source, inspect, log, open_salvaging, progress, read_identity,
+    read_key_file, report, throttle, unlock, PasswordSource, HEADER_PEEK,
+};
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("key_source").args(["password", "identity"])))]
+pub struct VerifyOptions {
+    /// File to check, in the native format
+    #[arg(value_name = "FILE")]
+    input_path: String,
+    /// The password; prompted for if the file needs one and it is omitted
+    #[arg(
+        short,
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// The key file the file was encrypted with
+    #[arg(long, value_name = "FILE", conflicts_with = "identity")]
+    key_file: Option<String>,
+    /// File holding our X25519 secret key, for files encrypted with --recipient
+    #[arg(long, value_name = "KEY_FILE", conflicts_with = "password_source")]
+    identity: Option<String>,
+    /// The associated data the file was bound to with `encrypt --aad`
+    #[arg(long, value_name = "TEXT")]
+    aad: Option<String>,
+    /// Don't show the progress line
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+/// How far decryption got before it failed.
+enum Failure {
+    Decrypting(anyhow::Error),
+    /// Chunks of a streamed file that fail to authenticate, and how many
+    /// chunks it has.
+    Chunks(Vec<stream::Damage>, u64),
+    /// Before decryption began.
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        Failure::Other(e)
+    }
+}
+
+pub fn run(options: &VerifyOptions) -> Result<()> {
+    report::target(&options.input_path, None)?;
+    let path = Path::new(&options.input_path);
+    let metadata = fs::metadata(path)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    if !metadata.is_file() {
+        bail!(t!("verify-not-file", path = path.display().to_string()));
+    }
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let password = options.password_source.or(&options.password)?;
+    let identity = options
+        .identity
+        .as_deref()
+        .map(|identity| read_identity(Path::new(identity)))
+        .transpose()?
+        .map(Encryptor::from_identity);
+
+    let mut prompted = Zeroizing::default();
+    let aad = options.aad.as_ref().map(|aad| aad.as_bytes().to_vec());
+    let keys = |head: &[u8]| {
+        match identity {
+            Some(identity) => check_key_source(head, &identity).map(|()| identity),
+            None => unlock(
+                head,
+                password.as_deref().map(String::as_str),
+                key_file,
+                &mut prompted,
+            ),
+        }
+        .map(|keys| keys.with_aad(aad))
+    };
+    let name = path.display().to_string();
+    let failure = match decrypt(path, metadata.len(), keys, options.quiet) {
+        Ok(()) => {
+            if !report::quiet() {
+                println!("{}", t!("verify-ok", path = name));
+            }
+            return Ok(());
+        }
+        Err(failure) => failure,
+    };
+
+    let file = fs::File::open(path)
+        .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+    let checksum = inspect::checksum(io::BufReader::new(throttle::Reader(file)), metadata.len())
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    let e = match failure {
+        Failure::Chunks(damage, total) => {
+            for damage in &damage {
+                log::warn(&describe(damage));
+            }
+            return Err(anyhow!(DecryptError::Corrupted).context(t!(
+                "verify-chunks-damaged",
+                path = name,
+                count = damage.len(),
+                total = total
+            )));
+        }
+        Failure::Decrypting(e) => e,
+        Failure::Other(e) if checksum != Some(false) => return Err(e),
+        Failure::Other(e) => e,
+    };
+    // Files from before the key check only have the checksum to go by.
+    Err(match (e.downcast_ref::<DecryptError>(), checksum) {
+        (_, Some(false)) => {
+            anyhow!(DecryptError::ChecksumMismatch).context(t!("verify-damaged", path = name))
+        }
+        (Some(DecryptError::WrongAad(_)), _) => e,
+        (Some(DecryptError::WrongKey), _) => e.context(t!("verify-wrong-key", path = name)),
+        (None, Some(true)) => e
+            .context(DecryptError::WrongKey)
+            .context(t!("verify-wrong-key", path = name)),
+        (Some(_), _) => e.context(t!("verify-damaged", path = name)),
+        (None, None) => e.context(t!("verify-undecided", path = name)),
+    })
+}
+
+/// Decrypts the `length` bytes of the file at `path` with the session `keys`
+/// makes for its header, keeping none of the plaintext.
+fn decrypt<'a>(
+    path: &Path,
+    length: u64,
+    keys: impl FnOnce(&[u8]) -> Result<Encryptor<'a>>,
+    quiet: bool,
+) -> Result<(), Failure> {
+    let file = fs::File::open(path)
+        .with_context(|| t!("file-open-failed", path = path.display().to_string()))?;
+    let mut input = progress::Reader::new(throttle::Reader(file), Some(length), quiet);
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(HEADER_PEEK)
+        .read_to_end(&mut head)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+
+    if stream::is_stream(head.as_slice())? {
+        let mut keys = keys(&head)?;
+        let mut decryptor = open_salvaging(head, input, &mut keys).map_err(Failure::Decrypting)?;
+        let (mut damage, mut total) = (Vec::new(), 0);
+        while let Some(chunk) = decryptor.next_salvaged().map_err(Failure::Decrypting)? {
+            total += 1;
+            if let stream::Salvaged::Damaged(chunk) = chunk {
+                damage.push(chunk);
+            }
+        }
+        if !damage.is_empty() {
+            return Err(Failure::Chunks(damage, total));
+        }
+        if decryptor.footer_mismatch() {
+            return Err(Failure::Decrypting(DecryptError::ChecksumMismatch.into()));
+        }
+        return Ok(());
+    }
+
+    let mut file_bytes = head;
+    input
+        .read_to_end(&mut file_bytes)
+        .with_context(|| t!("file-read-failed", path = path.display().to_string()))?;
+    drop(input);
+    let mut keys = keys(&file_bytes)?;
+    let attempt = attempts::begin(&file_bytes);
+    match keys.decrypt(&file_bytes) {
+        Ok(plaintext) => {
+            attempt.succeeded();
+            drop(Zeroizing::new(plaintext));
+            Ok(())
+        }
+        Err(e) => {
+            attempt.failed(&e);
+            Err(Failure::Decrypting(e))
+        }
+    }
+}
+
+/// A line on where a damaged chunk is, in the file and in the plaintext.
+pub fn describe(damage: &stream::Damage) -> String {
+    let start = damage.offset;
+    if damage.is_missing() {
+        return t!("verify-chunk-missing", chunk = damage.chunk, start = start);
+    }
+    let end = start + damage.length - 1;
+    match damage.plaintext {
+        Some((plaintext_start, length)) => t!(
+            "verify-chunk-damaged",
+            chunk = damage.chunk,
+            start = start,
+            end = end,
+            plaintext_start = plaintext_start,
+            plaintext_end = (plaintext_start + length).saturating_sub(1)
+        ),
+        None => t!(
+            "verify-chunk-damaged-compressed",
+            chunk = damage.chunk,
+            start = start,
+            end = end
+        ),
+    }
+}
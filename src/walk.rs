@@ -0,0 +1,270 @@
+//! Listing the files of a directory tree, for commands that process a
+//! whole directory, and choosing among them with include and exclude
+//! patterns ([`Filter`]).
+
+use clap::ValueEnum;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+    thread,
+};
+
+use file_encryptor::i18n::t;
+
+/// Directory reads in flight while listing a tree.
+const MAX_WALK_WORKERS: usize = 16;
+
+/// Which files of a tree to take: those matching an `include` pattern, or
+/// every file when there are none, and no `exclude` pattern.
+///
+/// A pattern with a `/` is matched against the path below the top
+/// directory, one without against the file name. `*` matches within a name,
+/// `**` across directories and `?` one character. A directory that an
+/// `exclude` pattern matches, or matches everything in, such as
+/// `node_modules/**`, isn't even read.
+#[derive(Clone, Default)]
+pub struct Filter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether the file at `relative` is taken.
+    pub fn takes(&self, relative: &Path) -> bool {
+        let relative = slashed(relative);
+        (self.include.is_empty() || self.include.iter().any(|p| matches(p, &relative)))
+            && !self.exclude.iter().any(|p| matches(p, &relative))
+    }
+
+    /// Whether the directory at `relative` is skipped with all it holds.
+    pub fn prunes(&self, relative: &Path) -> bool {
+        let relative = slashed(relative);
+        let inside = format!("{relative}/");
+        self.exclude
+            .iter()
+            .any(|p| matches(p, &relative) || (p.contains('/') && matches(p, &inside)))
+    }
+}
+
+/// `relative` with `/` between its parts on every platform.
+fn slashed(relative: &Path) -> String {
+    relative
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+fn matches(pattern: &str, relative: &str) -> bool {
+    let text = match pattern.contains('/') {
+        true => relative,
+        false => relative.rsplit('/').next().unwrap_or(relative),
+    };
+    glob(pattern.as_bytes(), text.as_bytes())
+}
+
+/// Matches `text` against a pattern of `*`, `**` and `?`.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|skip| glob(rest, &text[skip..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != b'/')
+            .any(|skip| glob(rest, &text[skip..])),
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && glob(rest, tail)),
+        [c, rest @ ..] => matches!(text, [t, tail @ ..] if t == c && glob(rest, tail)),
+    }
+}
+
+/// What to do with the symbolic links of a directory INPUT.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Symlinks {
+    /// Leave them out, with a warning
+    Skip,
+    /// Take what they point to as if it were there
+    Follow,
+    /// Store the links themselves, pointing where they did (--archive only)
+    Preserve,
+}
+
+/// Why an entry of a tree was left out.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Skipped {
+    Symlink,
+    /// A FIFO, socket or device.
+    Special,
+    /// A followed link to nothing.
+    Broken,
+    /// A followed link to a directory it is in.
+    Loop,
+}
+
+impl Skipped {
+    /// The warning that `path` was left out.
+    pub fn warning(self, path: &Path) -> String {
+        let path = path.display().to_string();
+        match self {
+            Skipped::Symlink => t!("walk-skipped-symlink", path = path),
+            Skipped::Special => t!("walk-skipped-special", path = path),
+            Skipped::Broken => t!("walk-skipped-broken", path = path),
+            Skipped::Loop => t!("walk-skipped-loop", path = path),
+        }
+    }
+}
+
+/// The files of a tree, and what was left out of it.
+pub struct Listing {
+    pub files: Vec<(PathBuf, u64)>,
+    pub skipped: Vec<(PathBuf, Skipped)>,
+}
+
+/// Directories still to be read by [`walk`], and what it has found so far.
+#[derive(Default)]
+struct Walk {
+    /// With, when following links, the real paths of the directory and of
+    /// those it is in.
+    pending: Vec<(PathBuf, Vec<PathBuf>)>,
+    /// Workers reading a directory, which may add more to `pending`.
+    reading: usize,
+    files: Vec<(PathBuf, u64)>,
+    skipped: Vec<(PathBuf, Skipped)>,
+    error: Option<std::io::Error>,
+}
+
+/// Lists the regular files under `dir` with their sizes, relative to `dir`
+/// and in path order. Symbolic links and special files are left out.
+pub fn walk(dir: &Path) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    Ok(walk_filtered(dir, &Filter::default(), false)?.files)
+}
+
+/// [`walk`], listing only the files `filter` takes, and with `follow` what
+/// symbolic links point to. Trees of many small directories are bound by
+/// the latency of directory reads and stat calls rather than by CPU, so
+/// several workers take directories off a shared queue and read them
+/// concurrently.
+pub fn walk_filtered(dir: &Path, filter: &Filter, follow: bool) -> std::io::Result<Listing> {
+    let real = match follow {
+        true => vec![fs::canonicalize(dir)?],
+        false => Vec::new(),
+    };
+    let state = Mutex::new(Walk {
+        pending: vec![(PathBuf::new(), real)],
+        ..Walk::default()
+    });
+    let changed = Condvar::new();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get() * 2);
+    thread::scope(|scope| {
+        for _ in 0..workers.min(MAX_WALK_WORKERS) {
+            scope.spawn(|| walk_worker(dir, filter, follow, &state, &changed));
+        }
+    });
+    let walk = state.into_inner().unwrap_or_else(|e| e.into_inner());
+    if let Some(error) = walk.error {
+        return Err(error);
+    }
+    let (mut files, mut skipped) = (walk.files, walk.skipped);
+    files.sort_unstable();
+    skipped.sort_unstable();
+    Ok(Listing { files, skipped })
+}
+
+fn walk_worker(dir: &Path, filter: &Filter, follow: bool, state: &Mutex<Walk>, changed: &Condvar) {
+    let mut walk = state.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        if walk.error.is_some() {
+            return;
+        }
+        let Some((relative, ancestors)) = walk.pending.pop() else {
+            if walk.reading == 0 {
+                return;
+            }
+            walk = changed.wait(walk).unwrap_or_else(|e| e.into_inner());
+            continue;
+        };
+        walk.reading += 1;
+        drop(walk);
+        let listing = read_entries(&dir.join(&relative), follow);
+        walk = state.lock().unwrap_or_else(|e| e.into_inner());
+        walk.reading -= 1;
+        match listing {
+            Ok(entries) => {
+                for (name, found) in entries {
+                    let path = relative.join(name);
+                    match found {
+                        Found::File(size) if filter.takes(&path) => walk.files.push((path, size)),
+                        Found::Directory(None) if !filter.prunes(&path) => {
+                            walk.pending.push((path, Vec::new()))
+                        }
+                        Found::Directory(Some(real)) if !filter.prunes(&path) => {
+                            if ancestors.contains(&real) {
+                                walk.skipped.push((path, Skipped::Loop));
+                            } else {
+                                let mut ancestors = ancestors.clone();
+                                ancestors.push(real);
+                                walk.pending.push((path, ancestors));
+                            }
+                        }
+                        Found::Skipped(why) if filter.takes(&path) => {
+                            walk.skipped.push((path, why))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(e) => walk.error = Some(e),
+        }
+        changed.notify_all();
+    }
+}
+
+enum Found {
+    /// With its real path when links are followed.
+    Directory(Option<PathBuf>),
+    File(u64),
+    Skipped(Skipped),
+}
+
+/// What is directly in `dir`, with `follow` looking through symbolic links.
+fn read_entries(dir: &Path, follow: bool) -> std::io::Result<Vec<(OsString, Found)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let mut file_type = entry.file_type()?;
+        let mut length = None;
+        if file_type.is_symlink() {
+            let target = match follow {
+                true => fs::metadata(entry.path()),
+                false => {
+                    entries.push((entry.file_name(), Found::Skipped(Skipped::Symlink)));
+                    continue;
+                }
+            };
+            let Ok(target) = target else {
+                entries.push((entry.file_name(), Found::Skipped(Skipped::Broken)));
+                continue;
+            };
+            (file_type, length) = (target.file_type(), Some(target.len()));
+        }
+        let found = if file_type.is_dir() {
+            let real = follow.then(|| fs::canonicalize(entry.path())).transpose()?;
+            Found::Directory(real)
+        } else if file_type.is_file() {
+            let length = match length {
+                Some(length) => length,
+                None => entry.metadata()?.len(),
+            };
+            Found::File(length)
+        } else {
+            Found::Skipped(Skipped::Special)
+        };
+        entries.push((entry.file_name(), found));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,343 @@
+//! `watch`: a hot folder. Files that appear or change under the input
+//! directory are encrypted to the same place under the output directory,
+//! named like those of `encrypt` on a directory, until the command is
+//! stopped.
+//!
+//! The tree is polled, which needs nothing from the platform and sees files
+//! on network shares too. A file is only encrypted once its size and
+//! modification time have held still for the debounce time, so one that is
+//! still being copied in is left alone until it is whole. At startup, a file
+//! whose output is already newer than it counts as done. Which files are
+//! taken can be narrowed with a rules file of `include` and `exclude` lines:
+//!
+//! ```text
+//! # Office documents, but not the lock files editors leave next to them.
+//! include *.docx
+//! include reports/**
+//! exclude .~lock*
+//! exclude *.tmp
+//! ```
+//!
+//! The patterns are those of [`walk::Filter`], as for `encrypt --include`
+//! and `--exclude` on a directory.
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use file_encryptor::{i18n::t, Encryptor};
+
+use crate::{
+    encrypt_file, encryptor, log, parse_duration, parse_public_key, policy, prompt_password,
+    read_key_file, report, walk, CipherName, CompressionName, IoOptions, KdfOptions,
+    PasswordSource,
+};
+
+#[derive(Args)]
+pub struct WatchOptions {
+    /// Directory to watch, at any depth
+    #[arg(long, value_name = "DIR")]
+    input_dir: PathBuf,
+    /// Directory the encrypted files are written to, as FILE.enc at the same place
+    #[arg(long, value_name = "DIR")]
+    output_dir: PathBuf,
+    /// Password; prompted for, twice, if there is no other key source
+    #[arg(
+        short,
+        long,
+        value_name = "PASSWORD",
+        conflicts_with = "password_source"
+    )]
+    password: Option<String>,
+    #[command(flatten)]
+    password_source: PasswordSource,
+    /// Key file to encrypt with, alone or with the password
+    #[arg(long, value_name = "FILE", conflicts_with = "recipients")]
+    key_file: Option<String>,
+    /// Encrypt to this X25519 public key as well as or instead of the password; repeat for more
+    #[arg(long = "recipient", value_name = "PUBLIC_KEY")]
+    recipients: Vec<String>,
+    /// Use the password even if it breaks the administrator's password policy; the override is logged
+    #[arg(long)]
+    force_weak_password: bool,
+    /// Use the password even if it is easy to guess, with a warning
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// File of include and exclude patterns choosing the files to encrypt
+    #[arg(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+    /// How long a file's size and modification time must hold still before it is encrypted
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "2s")]
+    debounce: Duration,
+    /// How often the input directory is looked at
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1s")]
+    interval: Duration,
+    /// Encrypt in fixed-size chunks with bounded memory, for files too large to hold in memory
+    #[arg(long)]
+    stream: bool,
+    /// Cipher; decryption reads it from the file [default: aes256gcm, or chacha20poly1305 on a
+    /// CPU without AES instructions]
+    #[arg(long, value_enum)]
+    cipher: Option<CipherName>,
+    /// Compress the plaintext before encrypting it [default: none]
+    #[arg(long, value_enum)]
+    compress: Option<CompressionName>,
+    #[command(flatten)]
+    kdf: KdfOptions,
+    /// Flush each encrypted file and its directory to disk before reporting it
+    #[arg(long)]
+    fsync: bool,
+}
+
+impl WatchOptions {
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Whether the password was given on the command line itself.
+    pub fn has_password_argument(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// Whether the options give a key without prompting for a password.
+    pub fn has_key_source(&self) -> bool {
+        let source = &self.password_source;
+        self.password.is_some()
+            || self.key_file.is_some()
+            || !self.recipients.is_empty()
+            || source.password_env.is_some()
+            || source.password_fd.is_some()
+            || source.password_file.is_some()
+    }
+}
+
+/// The filter of a rules file.
+fn read_rules(path: &Path) -> Result<walk::Filter> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| t!("rules-unreadable", path = path.display().to_string()))?;
+    let mut filter = walk::Filter::default();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(char::is_whitespace) {
+            Some(("include", pattern)) => filter.include.push(pattern.trim().to_owned()),
+            Some(("exclude", pattern)) => filter.exclude.push(pattern.trim().to_owned()),
+            _ => {
+                return Err(anyhow!(t!(
+                    "rules-invalid",
+                    path = path.display().to_string(),
+                    line = number + 1
+                )))
+            }
+        }
+    }
+    Ok(filter)
+}
+
+/// What was last seen of a file.
+struct Seen {
+    size: u64,
+    modified: Option<SystemTime>,
+    /// When it was first seen with this size and time.
+    since: Instant,
+    /// Whether this version was encrypted or failed, so it isn't tried again.
+    handled: bool,
+}
+
+pub fn run(options: &WatchOptions) -> Result<()> {
+    if !options.input_dir.is_dir() {
+        return Err(anyhow!(t!(
+            "watch-not-dir",
+            path = options.input_dir.display().to_string()
+        )));
+    }
+    let filter = options
+        .rules
+        .as_deref()
+        .map(read_rules)
+        .transpose()?
+        .unwrap_or_default();
+    let kdf = options.kdf.kdf()?;
+    let key_file = options.key_file.as_deref().map(read_key_file).transpose()?;
+    let given = options.password_source.or(&options.password)?;
+    let password = match (given, key_file, options.recipients.is_empty()) {
+        (Some(password), ..) => Some(password),
+        (None, None, true) => Some(prompt_password(true)?),
+        (None, ..) => None,
+    };
+    if let Some(password) = &password {
+        policy::Policy::load()?.enforce(
+            password,
+            options.force_weak_password,
+            options.allow_weak_password,
+        )?;
+    }
+    fs::create_dir_all(&options.output_dir).with_context(|| {
+        t!(
+            "dir-create-failed",
+            path = options.output_dir.display().to_string()
+        )
+    })?;
+    // An output directory inside the input one isn't watched.
+    let skipped = fs::canonicalize(&options.output_dir)
+        .ok()
+        .zip(fs::canonicalize(&options.input_dir).ok())
+        .and_then(|(output, input)| output.strip_prefix(input).ok().map(Path::to_path_buf));
+    if skipped
+        .as_ref()
+        .is_some_and(|relative| relative.as_os_str().is_empty())
+    {
+        return Err(anyhow!(t!("watch-same-dir")));
+    }
+
+    let keys = match options.recipients.as_slice() {
+        [] => encryptor(password.as_deref().map(String::as_str), key_file, kdf),
+        recipients => {
+            let public_keys = recipients
+                .iter()
+                .map(|key| parse_public_key(key))
+                .collect::<Result<Vec<_>>>()?;
+            let passwords: Vec<&str> = password.iter().map(|p| p.as_str()).collect();
+            Encryptor::to_recipients(&public_keys, &passwords, kdf)?
+        }
+    };
+    let config = crate::config::get();
+    let mut keys = keys
+        .with_cipher(
+            options
+                .cipher
+                .or(config.cipher)
+                .unwrap_or_else(CipherName::for_this_cpu)
+                .into(),
+        )
+        .with_compression(
+            options
+                .compress
+                .or(config.compress)
+                .unwrap_or_default()
+                .into(),
+        );
+    // One KDF run for everything the watch encrypts.
+    keys.derive()?;
+    let io = IoOptions {
+        fsync: options.fsync,
+        quiet: true,
+        ..IoOptions::default()
+    };
+
+    report::batch();
+    if !report::quiet() {
+        log::notice(&t!(
+            "watch-started",
+            input = options.input_dir.display().to_string(),
+            output = options.output_dir.display().to_string()
+        ));
+    }
+    let mut seen: HashMap<PathBuf, Seen> = HashMap::new();
+    let mut first = true;
+    loop {
+        let files = match walk::walk_filtered(&options.input_dir, &filter, false) {
+            Ok(listing) => listing.files,
+            Err(e) => {
+                log::failure(&t!(
+                    "watch-list-failed",
+                    path = options.input_dir.display().to_string(),
+                    error = e.to_string()
+                ));
+                Vec::new()
+            }
+        };
+        let now = Instant::now();
+        let mut present = HashMap::with_capacity(files.len());
+        for (relative, size) in files {
+            if skipped
+                .as_ref()
+                .is_some_and(|dir| relative.starts_with(dir))
+            {
+                continue;
+            }
+            if relative.to_str().is_none() {
+                continue;
+            }
+            let input = options.input_dir.join(&relative);
+            let modified = fs::metadata(&input).and_then(|m| m.modified()).ok();
+            let output = output_path(&options.output_dir, &relative);
+            let entry = match seen.remove(&relative) {
+                Some(entry) if entry.size == size && entry.modified == modified => entry,
+                _ => Seen {
+                    size,
+                    modified,
+                    since: now,
+                    // Encrypted before this watch started, and unchanged since.
+                    handled: first && is_newer(&output, modified),
+                },
+            };
+            present.insert(relative, entry);
+        }
+        seen = present;
+        first = false;
+
+        for (relative, entry) in &mut seen {
+            if entry.handled || now.duration_since(entry.since) < options.debounce {
+                continue;
+            }
+            entry.handled = true;
+            let input = options.input_dir.join(relative);
+            let output = output_path(&options.output_dir, relative);
+            let (input, output) = (input.to_string_lossy(), output.to_string_lossy());
+            let started = Instant::now();
+            report::start(&input, &output);
+            let result = match Path::new(&*output).parent() {
+                Some(parent) => fs::create_dir_all(parent)
+                    .with_context(|| t!("dir-create-failed", path = parent.display().to_string())),
+                None => Ok(()),
+            }
+            .and_then(|()| {
+                encrypt_file(
+                    &input,
+                    &output,
+                    keys.clone(),
+                    None,
+                    options.stream,
+                    None,
+                    io,
+                )
+            });
+            report::file(&input, &output, started, &result);
+            // `report::file` has logged the failure; this is its message for
+            // the terminal.
+            if let Err(e) = result {
+                eprintln!(
+                    "{}",
+                    t!(
+                        "watch-failed",
+                        path = input.as_ref(),
+                        error = format!("{e:#}")
+                    )
+                );
+            }
+        }
+        thread::sleep(options.interval);
+    }
+}
+
+/// Where the file at `relative` is encrypted to.
+fn output_path(output_dir: &Path, relative: &Path) -> PathBuf {
+    let mut output = output_dir.join(relative).into_os_string();
+    output.push(".enc");
+    output.into()
+}
+
+/// Whether `output` exists and was modified after `modified`.
+fn is_newer(output: &Path, modified: Option<SystemTime>) -> bool {
+    let output = fs::metadata(output).and_then(|m| m.modified()).ok();
+    matches!((output, modified), (Some(output), Some(input)) if output >= input)
+}